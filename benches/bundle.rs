@@ -0,0 +1,88 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use kbdgen::{
+    cldr::{Keyboard, ToXml},
+    cli::{to_errormodel, to_m17n_mim, to_xkb},
+    Cache, Load, ProjectBundle,
+};
+use tempfile::tempdir;
+
+mod synthetic;
+
+fn load(c: &mut Criterion) {
+    let dir = tempdir().unwrap();
+    synthetic::write_bundle(dir.path(), &synthetic::LARGE).unwrap();
+
+    c.bench_function("load", |b| {
+        b.iter(|| ProjectBundle::load(dir.path()).unwrap())
+    });
+
+    let cache_dir = tempdir().unwrap();
+    let cache = Cache::new(cache_dir.path());
+    ProjectBundle::load_cached(dir.path(), &cache).unwrap();
+    c.bench_function("load cached", |b| {
+        b.iter(|| ProjectBundle::load_cached(dir.path(), &cache).unwrap())
+    });
+}
+
+fn cldr(c: &mut Criterion) {
+    let mut xml = vec![];
+    synthetic::cldr_keyboard(0).write_xml(&mut xml).unwrap();
+
+    c.bench_function("cldr parse", |b| {
+        b.iter(|| serde_xml_rs::from_reader::<_, Keyboard>(&xml[..]).unwrap())
+    });
+
+    let keyboard = synthetic::cldr_keyboard(0);
+    c.bench_function("cldr to modes", |b| b.iter(|| keyboard.to_mode()));
+}
+
+fn generators(c: &mut Criterion) {
+    let dir = tempdir().unwrap();
+    synthetic::write_bundle(dir.path(), &synthetic::LARGE).unwrap();
+    let bundle = ProjectBundle::load(dir.path()).unwrap();
+
+    c.bench_function("xkb", |b| {
+        b.iter_batched(
+            || tempdir().unwrap(),
+            |out| {
+                to_xkb::bundle_to_xkb(
+                    &bundle,
+                    out.path(),
+                    &to_xkb::Options {
+                        standalone: false,
+                        verify: false,
+                    },
+                )
+            },
+            BatchSize::PerIteration,
+        )
+    });
+
+    c.bench_function("m17n", |b| {
+        b.iter_batched(
+            || tempdir().unwrap(),
+            |out| to_m17n_mim::bundle_to_mim(&bundle, out.path()),
+            BatchSize::PerIteration,
+        )
+    });
+
+    // The error model generator only takes a bundle path; keep it off the
+    // user's bundle cache so it measures parsing too
+    std::env::set_var("KBDGEN_NO_CACHE", "1");
+    let options = to_errormodel::Options {
+        layout: "xx-0".to_string(),
+    };
+    c.bench_function("errormodel", |b| {
+        b.iter_batched(
+            || tempdir().unwrap(),
+            |out| {
+                to_errormodel::kbdgen_to_errormodel(dir.path(), &out.path().join("model"), &options)
+                    .unwrap()
+            },
+            BatchSize::PerIteration,
+        )
+    });
+}
+
+criterion_group!(benches, load, cldr, generators);
+criterion_main!(benches);
@@ -0,0 +1,197 @@
+//! Synthetic bundles for the benchmarks
+//!
+//! Real bundles are too small to show regressions, and the large ones are not
+//! ours to vendor. These are shaped like a large project: many layouts with
+//! display names in many locales, desktop and mobile modes, dead keys and
+//! transforms. The content is deterministic, so runs are comparable.
+
+use kbdgen::cldr::{KeyMap, Keyboard, Map, Name, Names, Version};
+use std::{fmt::Write as _, fs, io, path::Path};
+
+const LOWER: &[&str] = &[
+    "a", "á", "b", "c", "č", "d", "đ", "e", "f", "g", "h", "i", "j", "k", "l", "m", "n", "ŋ", "o",
+    "p", "r", "s", "š", "t", "ŧ", "u", "v", "z", "ž", "å", "æ", "ø", "ä", "ö",
+];
+const VOWELS: &[&str] = &["a", "e", "i", "o", "u"];
+const DEAD_KEY: &str = "´";
+
+/// Desktop rows, as the ISO keys are laid out in a mode string
+const DESKTOP_ROWS: &[usize] = &[13, 12, 12, 11];
+const MOBILE_ROWS: &[usize] = &[11, 11, 9];
+const DESKTOP_MODES: &[&str] = &["default", "shift", "caps", "alt", "alt+shift"];
+
+#[derive(Debug, Clone, Copy)]
+pub struct Shape {
+    pub layouts: usize,
+    pub locales: usize,
+}
+
+/// About the size of the largest bundles in use
+pub const LARGE: Shape = Shape {
+    layouts: 12,
+    locales: 48,
+};
+
+fn glyph(seed: usize, index: usize, upper: bool) -> String {
+    let glyph = LOWER[(seed * 7 + index * 13) % LOWER.len()];
+    if upper {
+        glyph.to_uppercase()
+    } else {
+        glyph.to_string()
+    }
+}
+
+fn mode_string(seed: usize, rows: &[usize], upper: bool, indent: &str) -> String {
+    let mut index = 0;
+    let mut out = String::new();
+    for (row, &len) in rows.iter().enumerate() {
+        let keys = (0..len)
+            .map(|_| {
+                index += 1;
+                // Put the dead key on the last key of the top row, like the
+                // Nordic layouts do
+                if row == 0 && index == len && !upper {
+                    DEAD_KEY.to_string()
+                } else {
+                    glyph(seed, index, upper)
+                }
+            })
+            .collect::<Vec<_>>();
+        let _ = writeln!(out, "{}{}", indent, keys.join(" "));
+    }
+    out
+}
+
+fn layout_yaml(seed: usize, shape: &Shape) -> String {
+    let mut out = String::from("displayNames:\n");
+    for locale in 0..shape.locales {
+        let _ = writeln!(
+            out,
+            "  l{}: Synthetic keyboard {} in locale {}",
+            locale, seed, locale
+        );
+    }
+
+    out.push_str("modes:\n");
+    for target in &["win", "mac", "x11", "desktop"] {
+        let _ = writeln!(out, "  {}:", target);
+        for (i, mode) in DESKTOP_MODES.iter().enumerate() {
+            let _ = writeln!(out, "    {}: |", mode);
+            out.push_str(&mode_string(
+                seed + i,
+                DESKTOP_ROWS,
+                mode.contains("shift"),
+                "      ",
+            ));
+        }
+    }
+    for target in &["ios", "android", "mobile"] {
+        let _ = writeln!(out, "  {}:", target);
+        for mode in &["default", "shift"] {
+            let _ = writeln!(out, "    {}: |", mode);
+            out.push_str(&mode_string(seed, MOBILE_ROWS, *mode == "shift", "      "));
+        }
+    }
+
+    out.push_str("deadKeys:\n");
+    for target in &["win", "mac", "x11", "desktop"] {
+        let _ = writeln!(out, "  {}:\n    default: ['{}']", target, DEAD_KEY);
+    }
+
+    let _ = writeln!(
+        out,
+        "transforms:\n  '{}':\n    ' ': '{}'",
+        DEAD_KEY, DEAD_KEY
+    );
+    for vowel in VOWELS {
+        let _ = writeln!(out, "    {}: {}\u{301}", vowel, vowel);
+        let _ = writeln!(
+            out,
+            "    {}: {}\u{301}",
+            vowel.to_uppercase(),
+            vowel.to_uppercase()
+        );
+    }
+
+    out
+}
+
+fn project_yaml(shape: &Shape) -> String {
+    let mut out = String::from("locales:\n");
+    for locale in 0..shape.locales {
+        let _ = writeln!(
+            out,
+            "  l{}:\n    name: Synthetic keyboards\n    description: Benchmark bundle in locale {}",
+            locale, locale
+        );
+    }
+    out.push_str(
+        "author: kbdgen\ncopyright: none\nemail: kbdgen@example.com\norganisation: kbdgen\n",
+    );
+    out
+}
+
+/// Writes a bundle of the given shape into `dir`
+pub fn write_bundle(dir: &Path, shape: &Shape) -> io::Result<()> {
+    fs::create_dir_all(dir.join("layouts"))?;
+    fs::create_dir_all(dir.join("targets"))?;
+    fs::write(dir.join("project.yaml"), project_yaml(shape))?;
+    for seed in 0..shape.layouts {
+        fs::write(
+            dir.join("layouts").join(format!("xx-{}.yaml", seed)),
+            layout_yaml(seed, shape),
+        )?;
+    }
+    Ok(())
+}
+
+/// A CLDR desktop keyboard with a key map per desktop mode
+pub fn cldr_keyboard(seed: usize) -> Keyboard {
+    /// Row letters and their first column
+    const ROWS: &[(char, usize)] = &[('E', 0), ('D', 1), ('C', 1), ('B', 0)];
+
+    let key_maps = DESKTOP_MODES
+        .iter()
+        .enumerate()
+        .map(|(i, mode)| {
+            let keys = DESKTOP_ROWS
+                .iter()
+                .zip(ROWS)
+                .flat_map(|(&len, &(row, first))| {
+                    (first..first + len).map(move |column| (row, column))
+                })
+                .enumerate()
+                .map(|(index, (row, column))| Map {
+                    iso: format!("{}{:02}", row, column),
+                    to: glyph(seed + i, index, mode.contains("shift")),
+                    ..Map::default()
+                })
+                .collect();
+            KeyMap {
+                keys,
+                modifiers: match *mode {
+                    "default" => None,
+                    "alt+shift" => Some("altR+shift".to_string()),
+                    "alt" => Some("altR".to_string()),
+                    other => Some(other.to_string()),
+                },
+            }
+        })
+        .collect();
+
+    Keyboard {
+        locale: format!("xx-t-k0-windows-{}", seed),
+        names: vec![Names {
+            values: vec![Name {
+                value: format!("Synthetic {}", seed),
+            }],
+        }],
+        version: Version {
+            platform: "10".to_string(),
+            number: "1".to_string(),
+        },
+        key_maps,
+        settings: None,
+        transforms: None,
+    }
+}
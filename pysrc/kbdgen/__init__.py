@@ -1,8 +1,10 @@
+import sys
+
 from .log import hijack_logging_getLogger
 
 try:
     hijack_logging_getLogger()
 except Exception as e:
-    print(e)
+    print(e, file=sys.stderr)
 
 __version__ = "2.0.0-alpha.16"
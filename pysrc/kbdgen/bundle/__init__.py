@@ -1,3 +1,5 @@
+import copy
+import json
 import os
 import os.path
 import shutil
@@ -65,7 +67,15 @@ ISO_KEYS = (
 )
 
 MOBILE_MODES = frozenset(
-    ("default", "shift", "alt", "alt+shift", "symbols-1", "symbols-2")
+    (
+        "default",
+        "shift",
+        "alt",
+        "alt+shift",
+        "symbols-1",
+        "symbols-2",
+        "mobile-6rows",
+    )
 )
 
 DESKTOP_MODES = frozenset(
@@ -289,9 +299,305 @@ def derive_transforms(layout, allow_glyphbombs=False):
             layout.transforms[d][ch] = normalised
 
 
-def decode_layout(tree):
+MOBILE_ROWS = (
+    ["D%02d" % i for i in range(1, 13)],
+    ["C%02d" % i for i in range(1, 12)] + ["D13"],
+    ["B%02d" % i for i in range(0, 11)],
+)
+
+DESKTOP_SOURCES = ("desktop", "win", "mac", "x11", "chrome")
+
+MOBILE_SOURCES = ("mobile", "android", "ios")
+
+# The layout `features` that generators know, see `Feature` in the Rust models
+KNOWN_FEATURES = ("no-autocorrect", "no-caps-lock", "spacebar-language-label")
+
+# The letter keys that the rows of a mobile layout are placed on
+DESKTOP_ROWS = (
+    ["D%02d" % i for i in range(1, 13)],
+    ["C%02d" % i for i in range(1, 12)] + ["D13"],
+    ["B%02d" % i for i in range(1, 11)],
+)
+
+BASE_LAYOUTS = {
+    "us": (
+        r"""` 1 2 3 4 5 6 7 8 9 0 - =
+              q w e r t y u i o p [ ]
+              a s d f g h j k l ; ' \
+            \ z x c v b n m , . /""",
+        r"""~ ! @ # $ % ^ & * ( ) _ +
+              Q W E R T Y U I O P { }
+              A S D F G H J K L : " |
+            | Z X C V B N M < > ?""",
+    ),
+    "nordic": (
+        r"""§ 1 2 3 4 5 6 7 8 9 0 + ´
+              q w e r t y u i o p å ¨
+              a s d f g h j k l ö ä '
+            < z x c v b n m , . -""",
+        r"""½ ! " # ¤ % & / ( ) = ? `
+              Q W E R T Y U I O P Å ^
+              A S D F G H J K L Ö Ä *
+            > Z X C V B N M ; : _""",
+    ),
+}
+
+
+def is_letter(value):
+    if not value:
+        return False
+    if not unicodedata.category(value[0]).startswith("L"):
+        return False
+    return all(unicodedata.category(c)[0] in "LM" for c in value[1:])
+
+
+def derive_mobile_modes(layout, options):
+    if options is True:
+        options = {}
+    elif not options:
+        return None
+
+    source = options.get("from", None)
+    if source is not None:
+        desktop = layout.modes.get(source, None)
+    else:
+        desktop = next(
+            (layout.modes[t] for t in DESKTOP_SOURCES if t in layout.modes), None
+        )
+    if desktop is None or "default" not in desktop:
+        return None
+
+    default = desktop["default"]
+    shift = desktop.get("shift", {})
+    keep = (options.get("keep", None) or "").split()
+    drop = (options.get("drop", None) or "").split()
+
+    default_rows = []
+    shift_rows = []
+    for row in MOBILE_ROWS:
+        default_row = []
+        shift_row = []
+        for key in row:
+            value = default.get(key, None)
+            if value is None:
+                continue
+            if not (is_letter(value) or value in keep) or value in drop:
+                continue
+            default_row.append(value)
+            shift_row.append(shift.get(key, None) or value.upper())
+        if len(default_row) > 0:
+            default_rows.append(default_row)
+            shift_rows.append(shift_row)
+
+    if len(default_rows) == 0:
+        return None
+    return MobileLayoutMode(default=default_rows, shift=shift_rows)
+
+
+def derive_desktop_modes(layout, options):
+    if options is True:
+        options = {}
+    elif not options:
+        return None
+
+    source = options.get("from", None)
+    if source is not None:
+        mobile = layout.modes.get(source, None)
+    else:
+        mobile = next(
+            (layout.modes[t] for t in MOBILE_SOURCES if t in layout.modes), None
+        )
+    if mobile is None or "default" not in mobile:
+        return None
+
+    base_default, base_shift = BASE_LAYOUTS[options.get("base", None) or "us"]
+    default = parse_desktop_layout(base_default)
+    shift = parse_desktop_layout(base_shift)
+    mobile_shift = mobile.get("shift", [])
+
+    for i, (row, keys) in enumerate(zip(mobile["default"], DESKTOP_ROWS)):
+        shift_row = mobile_shift[i] if i < len(mobile_shift) else []
+        letters = [
+            (column, value)
+            for (column, value) in enumerate(row)
+            if not value.startswith("\\s{")
+        ]
+        if len(letters) > len(keys):
+            logger.warning(
+                "Mobile row %d has more keys than the desktop row, the rest are left out"
+                % (i + 1)
+            )
+        for (column, value), key in zip(letters, keys):
+            shifted = shift_row[column] if column < len(shift_row) else None
+            if shifted is None or shifted.startswith("\\s{"):
+                shifted = value.upper()
+            default[key] = value
+            shift[key] = shifted
+
+    return DesktopLayoutMode(default=default, shift=shift)
+
+
+PLACEHOLDER_REGEX = re.compile(r"\{\{([a-z]+)\}\}")
+
+
+def expand_placeholders(value):
+    """Replaces `{{zwj}}` and other placeholders for invisible characters,
+    named after their abbreviation in lower case, see `placeholders.rs` in the
+    Rust models. Other `{{name}}`s are macros, see `expand_macros`."""
+    from ..gen.base import DISPLAY_ABBREVIATIONS
+
+    placeholders = dict((v.lower(), k) for (k, v) in DISPLAY_ABBREVIATIONS.items())
+
+    def expand(obj):
+        if isinstance(obj, str):
+            return PLACEHOLDER_REGEX.sub(
+                lambda m: placeholders.get(m.group(1), m.group(0)), obj
+            )
+        if isinstance(obj, dict):
+            return type(obj)((expand(k), expand(v)) for (k, v) in obj.items())
+        if isinstance(obj, list):
+            return [expand(x) for x in obj]
+        return obj
+
+    return expand(value)
+
+
+MACRO_REGEX = re.compile(r"\{\{([A-Za-z][A-Za-z0-9_]*)\}\}")
+
+
+def macro_values(name, tree):
+    """The values of the macros of a layout: its own `macros`, and for the
+    others the values from CLDR that kbdgen passes in `KBDGEN_MACROS`, see
+    `macros.rs` in the Rust CLI"""
+    values = json.loads(os.environ.get("KBDGEN_MACROS", "{}")).get(name, {})
+    values.update(tree.get("macros") or {})
+    return values
+
+
+def expand_macros(name, modes, values):
+    """Replaces `{{currency}}` and other macros in the modes of a layout with
+    their values, like the Rust side does before it generates a layout.
+    Placeholders have to be expanded first, as their names are not macros."""
+    unknown = set()
+
+    def replace(m):
+        if m.group(1) in values:
+            return values[m.group(1)]
+        unknown.add(m.group(1))
+        return m.group(0)
+
+    def expand(obj):
+        if isinstance(obj, str):
+            return MACRO_REGEX.sub(replace, obj)
+        if isinstance(obj, dict):
+            return type(obj)((k, expand(v)) for (k, v) in obj.items())
+        if isinstance(obj, list):
+            return [expand(x) for x in obj]
+        return obj
+
+    modes = expand(modes)
+    if unknown:
+        raise Exception(
+            "No value for macros %s in layout `%s`" % (", ".join(sorted(unknown)), name)
+        )
+    return modes
+
+
+def is_transform_pattern(value):
+    """Whether the input of a transform is a character class like `[aeiou]`,
+    see `transforms.rs` in the Rust models"""
+    return len(value) > 2 and value.startswith("[") and value.endswith("]")
+
+
+UNICODE_CLASS_REGEX = re.compile(r"\\([pP])(?:\{([^}]*)\}|([A-Z]))")
+
+# A noncharacter, for a class of categories that none of the characters has,
+# as `[]` is not a valid class
+NO_CHARACTER = "\uffff"
+
+
+def compile_transform_pattern(pattern, characters):
+    """Compiles a pattern to match whole inputs. Python's `re` has no
+    `\\p{Lu}` classes, so those are replaced by the characters of the layout
+    that are in the category; as only those are matched, the result is the
+    same as with the Rust regex crate."""
+
+    def replace(m):
+        category = m.group(2) if m.group(2) is not None else m.group(3)
+        if not 0 < len(category) <= 2 or not category.isalpha():
+            raise Exception(
+                "Unsupported class `%s` in transform pattern `%s`, only general categories "
+                "like `\\p{Lu}` are supported" % (m.group(0), pattern)
+            )
+        negated = m.group(1) == "P"
+        members = "".join(
+            re.escape(c)
+            for c in sorted(characters)
+            if unicodedata.category(c).startswith(category) != negated
+        )
+        inside_class = pattern[: m.start()].count("[") > pattern[: m.start()].count("]")
+        if inside_class:
+            return members or NO_CHARACTER
+        return "[%s]" % members if members else "(?!)"
+
+    return re.compile("^%s$" % UNICODE_CLASS_REGEX.sub(replace, pattern))
+
+
+def typeable_characters(modes):
+    """The single characters that the modes of a layout type"""
+    characters = set()
+    for target in modes.values():
+        for mode in target.values():
+            keys = mode.values() if isinstance(mode, dict) else itertools.chain(*mode)
+            characters.update(k for k in keys if k is not None and len(k) == 1)
+    return characters
+
+
+def expand_transform_patterns(layout):
+    """Replaces the patterns in all transforms with the explicit pairs they
+    match, like `expand_transform_patterns` in the Rust models does as it
+    loads a layout. Pairs listed explicitly take precedence, and of patterns
+    that match the same character the one that sorts first."""
+    if not any(
+        is_transform_pattern(input)
+        for transform in (layout.transforms or {}).values()
+        for input in transform
+    ):
+        return
+    characters = typeable_characters(layout.modes)
+
+    for dead_key, transform in layout.transforms.items():
+        expanded = OrderedDict()
+        for pattern in sorted(filter(is_transform_pattern, transform)):
+            try:
+                regex = compile_transform_pattern(pattern, characters)
+            except re.error as e:
+                raise Exception(
+                    "Invalid pattern `%s` in transforms for `%s`: %s"
+                    % (pattern, dead_key, e)
+                )
+            for c in sorted(filter(regex.match, characters)):
+                output = transform[pattern].replace("$0", c)
+                expanded.setdefault(c, unicodedata.normalize("NFC", output))
+        for input, output in transform.items():
+            if not is_transform_pattern(input):
+                expanded[input] = output
+        layout.transforms[dead_key] = OrderedDict(sorted(expanded.items()))
+
+
+def decode_layout(name, tree):
     layout = Layout.decode(tree)
-    layout.modes = parse_modes(layout.modes)
+    # After splitting modes and long-press keys, as placeholders can be spaces
+    layout.modes = expand_placeholders(parse_modes(layout.modes))
+    layout.modes = expand_macros(name, layout.modes, macro_values(name, tree))
+    layout.transforms = expand_placeholders(layout.transforms)
+
+    for feature in layout.features or []:
+        if feature not in KNOWN_FEATURES:
+            raise Exception(
+                "Unknown feature `%s`, expected one of: %s"
+                % (feature, ", ".join(KNOWN_FEATURES))
+            )
 
     if layout.longpress is None:
         layout.longpress = OrderedDict()
@@ -299,19 +605,74 @@ def decode_layout(tree):
     lp = OrderedDict()
     for longpress, strings in layout.longpress.items():
         lp[longpress] = re.split(r"\s+", strings.strip())
-    layout.longpress = lp
+    layout.longpress = expand_placeholders(lp)
+
+    transforms_derive = layout.derive is not None and layout.derive.transforms
+    if layout.derive is not None and "mobile" not in layout.modes:
+        mobile = derive_mobile_modes(layout, layout.derive.mobile)
+        if mobile is not None:
+            logger.trace("Derived mobile modes: %r" % mobile)
+            layout.modes["mobile"] = mobile
+
+    has_desktop_modes = any(t in layout.modes for t in DESKTOP_SOURCES)
+    if layout.derive is not None and not has_desktop_modes:
+        desktop = derive_desktop_modes(layout, layout.derive.desktop)
+        if desktop is not None:
+            logger.trace("Derived desktop modes: %r" % desktop)
+            layout.modes["desktop"] = desktop
 
-    transforms_derive = layout.derive is not None and layout.derive.get(
-        "transforms", False
-    )
     if transforms_derive is True:
         derive_transforms(
             layout, False
         )  # TODO: allow strange, non-standard interactions
 
+    expand_transform_patterns(layout)
     return layout
 
 
+WINDOWS_RESERVED = (
+    ["CON", "PRN", "AUX", "NUL"]
+    + ["COM%d" % n for n in range(1, 10)]
+    + ["LPT%d" % n for n in range(1, 10)]
+)
+
+
+def check_layout_name(name):
+    """Raises ValueError if a layout name cannot be a file name on every
+    system, like the Rust side does as it loads a bundle."""
+    if name == "":
+        raise ValueError("Layout name is empty")
+    if "/" in name or "\\" in name:
+        raise ValueError("Layout name %r is a path, not a name" % name)
+    if name.startswith("."):
+        raise ValueError("Layout name %r starts with a dot" % name)
+    if name.endswith(".") or name.endswith(" "):
+        raise ValueError("Layout name %r ends with a dot or a space" % name)
+    for c in name:
+        if c in '<>:"|?*' or unicodedata.category(c) == "Cc":
+            raise ValueError("Layout name %r has %r, which is not allowed" % (name, c))
+    if name.split(".")[0].upper() in WINDOWS_RESERVED:
+        raise ValueError("Layout name %r is a device name on Windows" % name)
+
+
+def expand_variants(name, tree):
+    """Yields the layout and the layouts defined by its `variants`, which share
+    everything with it except what they set themselves."""
+    variants = tree.pop("variants", None) or {}
+    check_layout_name(name)
+    yield (name, tree)
+
+    for variant_name, variant in variants.items():
+        check_layout_name("%s-%s" % (name, variant_name))
+        variant_tree = copy.deepcopy(tree)
+        modes = variant_tree["modes"]
+        modes.update(variant.get("modes", {}))
+        for key in ["displayNames", "deadKeys", "longpress", "transforms"]:
+            if key in variant:
+                variant_tree[key] = variant[key]
+        yield ("%s-%s" % (name, variant_name), variant_tree)
+
+
 def normalized_yaml_load(f):
     data = unicodedata.normalize("NFC", f.read())
     try:
@@ -340,15 +701,14 @@ class ProjectBundle:
         logger.trace("Loading layouts")
         layouts = dict(
             [
-                (
+                (name, decode_layout(name, tree))
+                for x in os.listdir(layouts_path)
+                for (name, tree) in expand_variants(
                     os.path.splitext(x)[0],
-                    decode_layout(
-                        normalized_yaml_load(
-                            open(os.path.join(layouts_path, x), encoding="utf-8")
-                        )
+                    normalized_yaml_load(
+                        open(os.path.join(layouts_path, x), encoding="utf-8")
                     ),
                 )
-                for x in os.listdir(layouts_path)
             ]
         )
 
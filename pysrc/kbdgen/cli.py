@@ -1,9 +1,11 @@
 import argparse
 import yaml
 import sys
+import os
 import os.path
 import platform
 import logging
+from contextlib import contextmanager
 
 from . import __version__, gen
 from .base import KbdgenException, Parser, get_logger, UserException
@@ -92,6 +94,11 @@ def parse_args(args):
     p.add_argument("--github-token", help="GitHub token for source getting")
     p.add_argument("-c", "--command", help="Command to run for a given generators")
     p.add_argument("--ci", action="store_true", help="Continuous integration build")
+    p.add_argument(
+        "--ui-locale",
+        default="en",
+        help="Language that language pickers are sorted for (default: en)",
+    )
 
     return p.parse_args(args)
 
@@ -123,7 +130,30 @@ def print_diagnostics():
         logger.debug("  %s = %r" % (k, v))
 
 
+@contextmanager
+def stdout_to_stderr():
+    """Sends what is written to stdout, by Python and by the processes it
+    runs, to stderr, which kbdgen asks for in `KBDGEN_STDOUT_TO_STDERR` when
+    its own stdout is for `--json` output"""
+    sys.stdout.flush()
+    saved = os.dup(1)
+    os.dup2(2, 1)
+    try:
+        yield
+    finally:
+        sys.stdout.flush()
+        os.dup2(saved, 1)
+        os.close(saved)
+
+
 def run_cli(cli_args):
+    if os.environ.get("KBDGEN_STDOUT_TO_STDERR"):
+        with stdout_to_stderr():
+            return _run_cli(cli_args)
+    return _run_cli(cli_args)
+
+
+def _run_cli(cli_args):
     args = parse_args(cli_args)
     # logger.setLevel(args.logging)
 
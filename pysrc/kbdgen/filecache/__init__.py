@@ -13,7 +13,9 @@ from ..base import get_logger
 
 logger = get_logger(__name__)
 
-if sys.platform.startswith("win"):
+if os.getenv("KBDGEN_CACHE_DIR"):
+    default_cache_dir = Path(os.getenv("KBDGEN_CACHE_DIR"))
+elif sys.platform.startswith("win"):
     default_cache_dir = Path(os.getenv("LOCALAPPDATA")) / "kbdgen" / "cache"
 elif sys.platform.startswith("darwin"):
     default_cache_dir = Path(os.getenv("HOME")) / "Library" / "Caches" / "kbdgen"
@@ -16,7 +16,31 @@ from xml.etree.ElementTree import Element, SubElement
 import tarfile
 import tempfile
 
-from .base import Generator, run_process, MobileLayoutView, get_bin_resource
+from .base import (
+    Generator,
+    run_process,
+    MobileLayoutView,
+    has_modes,
+    get_bin_resource,
+    accessibility_labels,
+    display_labels,
+    dead_key_previews,
+    sort_layouts,
+    spacebar_config,
+    key_feedback,
+    analytics_events,
+    mobile_theme,
+    target_fonts,
+    missing_fonts,
+    stamp_metadata,
+    layout_license,
+    layout_attributions,
+    display_name,
+    project_urls,
+    missing_store_urls,
+    transform_partial,
+    symmetric_transforms,
+)
 from ..filecache import FileCache
 from ..base import get_logger
 from .. import boolmap
@@ -33,6 +57,32 @@ for api in (21, 23):
         ANDROID_GLYPHS[api] = boolmap.BoolMap(f.read())
 
 
+KEYBOARD_LAYOUTS_RECEIVER = """package no.divvun.kbdgen;
+
+import android.content.BroadcastReceiver;
+import android.content.Context;
+import android.content.Intent;
+
+public class KeyboardLayoutsReceiver extends BroadcastReceiver {
+    @Override
+    public void onReceive(Context context, Intent intent) {
+    }
+}
+"""
+
+KEYBOARD_LAYOUTS_MANIFEST = """    <receiver
+            android:name="no.divvun.kbdgen.KeyboardLayoutsReceiver"
+            android:exported="true">
+            <intent-filter>
+                <action android:name="android.hardware.input.action.QUERY_KEYBOARD_LAYOUTS" />
+            </intent-filter>
+            <meta-data
+                android:name="android.hardware.input.metadata.KEYBOARD_LAYOUTS"
+                android:resource="@xml/keyboard_layouts" />
+        </receiver>
+    """
+
+
 class AndroidGenerator(Generator):
     REPO = "giella-ime"
     ANDROID_NS = "http://schemas.android.com/apk/res/android"
@@ -78,6 +128,10 @@ class AndroidGenerator(Generator):
     def android_target(self):
         return self._bundle.targets.get("android", {})
 
+    @property
+    def fonts(self):
+        return target_fonts(self._bundle, self.android_target)
+
     @property
     def _version(self):
         return self.android_target.version
@@ -99,9 +153,10 @@ class AndroidGenerator(Generator):
     def supported_layouts(self):
         o = OrderedDict()
         for k, v in self._bundle.layouts.items():
-            if "android" in v.modes or "mobile" in v.modes:
+            if has_modes(v, "android"):
                 o[k] = v
-        return o
+        # The order of the language picker
+        return sort_layouts(o, self.picker_locale, self._bundle.project)
 
     def generate(self, base="."):
         if not self.satisfies_requirements():
@@ -182,8 +237,13 @@ class AndroidGenerator(Generator):
         else:
             self.inject_speller_xml(self.supported_layouts, base)
         self.add_layout_json(self.supported_layouts, base)
+        self.add_fonts(base)
+        self.add_autocorrect(base)
 
+        self.add_kcm_files(base)
+        self.add_app_strings(base)
         self.update_localisation(base)
+        self.update_key_feedback(base)
         self.generate_icons(base)
         self.build(base, tree_id, self.is_release)
 
@@ -238,11 +298,22 @@ class AndroidGenerator(Generator):
                 logger.error("STORE_PW and KEY_PW must be set for a release build.")
                 sane = False
 
+            missing = missing_store_urls(self._bundle.project)
+            if missing:
+                logger.error(
+                    "Google Play releases need %s in project.yaml."
+                    % " and ".join("`%s`" % name for name in missing)
+                )
+                sane = False
+
         pid = self.android_target.package_id
         if pid is None:
             sane = False
             logger.error("No package ID provided for Android target.")
 
+        if missing_fonts(self.fonts):
+            sane = False
+
         for name, kbd in self.supported_layouts.items():
             for dn_locale in kbd.display_names:
                 if dn_locale in ["zz", name]:
@@ -260,6 +331,10 @@ class AndroidGenerator(Generator):
                             )
                             % (name, n + 1, mode, len(row))
                         )
+            # The system font is only a fallback then, and `kbdgen lint`
+            # checks the embedded fonts instead
+            if self.fonts:
+                continue
             for api_v in [21, 23]:
                 if not self.detect_unavailable_glyphs(name, kbd, api_v):
                     sane = False
@@ -277,10 +352,42 @@ class AndroidGenerator(Generator):
             logger.info("Adding layout configuration JSON for '%s'…" % locale)
             o = {}
 
-            if layout.transforms is not None:
-                o["transforms"] = layout.transforms
-            else:
-                o["transforms"] = {}
+            if layout.rtl:
+                o["rtl"] = True
+
+            o["transforms"] = symmetric_transforms(layout)
+            o["transformPartial"] = transform_partial(layout, "android")
+
+            if layout.flicks is not None:
+                o["flicks"] = layout.flicks
+
+            if layout.reorders is not None:
+                o["reorders"] = layout.reorders
+
+            if layout.backspaces is not None:
+                o["backspaces"] = layout.backspaces
+
+            if layout.features:
+                o["features"] = layout.features
+
+            modes = MobileLayoutView(layout, "android").modes()
+            o["accessibilityLabels"] = accessibility_labels(layout, modes)
+            o["displayLabels"] = display_labels(layout, modes)
+            o["deadKeyPreviews"] = dead_key_previews(
+                layout, MobileLayoutView(layout, "android").dead_keys()
+            )
+
+            o["spacebar"] = spacebar_config(layout, locale, self.android_target)
+            o["theme"] = mobile_theme(layout, "android")
+            analytics = analytics_events(
+                self.android_target, locale, layout, "android"
+            )
+            if analytics is not None:
+                o["analytics"] = analytics
+            if self.fonts:
+                o["fonts"] = [
+                    "fonts/%s" % os.path.basename(path) for path in self.fonts
+                ]
 
             pahkat_key = self.layout_target(layout).get("spellerPackageKey", None)
             speller_path = self.layout_target(layout).get("spellerPath", None)
@@ -290,10 +397,52 @@ class AndroidGenerator(Generator):
                     "packageUrl": pahkat_key
                 }
 
+            stamp = stamp_metadata(locale)
+            if stamp is not None:
+                o["stamp"] = stamp
+
+            # For the About screen
+            license = layout_license(self._bundle.project, layout)
+            if license is not None:
+                o["license"] = license
+            attributions = layout_attributions(self._bundle.project, layout)
+            if attributions:
+                o["attributions"] = attributions
+            o.update(project_urls(self._bundle.project))
+
             o = json.dumps(o, indent=2, ensure_ascii=False)
             with open(os.path.join(json_path, "%s.json" % locale), 'w', encoding="utf-8") as f:
                 f.write(o)
 
+    def add_fonts(self, build_dir):
+        nm = "app/src/main/assets/fonts"
+        path = os.path.join(build_dir, "deps", self.REPO, nm)
+        if os.path.exists(path):
+            shutil.rmtree(path)
+        if not self.fonts:
+            return
+        os.makedirs(path, exist_ok=True)
+
+        for fn in self.fonts:
+            bfn = os.path.basename(fn)
+            logger.info("Adding font '%s'…" % bfn)
+            shutil.copyfile(fn, os.path.join(path, bfn))
+
+    def add_autocorrect(self, base):
+        # Written by `kbdgen_to_autocorrect` from the bundle's wordlists
+        src = os.path.join(base, "autocorrect")
+        nm = "app/src/main/assets/autocorrect"
+        path = os.path.join(base, "deps", self.REPO, nm)
+        if os.path.exists(path):
+            shutil.rmtree(path)
+        if not os.path.isdir(src):
+            return
+        os.makedirs(path, exist_ok=True)
+
+        for fn in sorted(os.listdir(src)):
+            logger.info("Adding autocorrect dictionary '%s'…" % fn)
+            shutil.copyfile(os.path.join(src, fn), os.path.join(path, fn))
+
     def add_bhfst_files(self, build_dir):
         nm = "app/src/main/assets"
         dict_path = os.path.join(build_dir, "deps", self.REPO, nm)
@@ -385,6 +534,52 @@ class AndroidGenerator(Generator):
         with open(fn, "w", encoding="utf-8") as f:
             f.write(self._tostring(tree))
 
+    def add_kcm_files(self, base):
+        kcm_dir = os.path.join(base, "kcm", "res")
+        if not os.path.isdir(kcm_dir):
+            return
+
+        logger.info("Adding hardware keyboard layouts…")
+        app_dir = os.path.join(base, "deps", self.REPO, "app/src/main")
+        for subdir in ("raw", "xml"):
+            src = os.path.join(kcm_dir, subdir)
+            dst = os.path.join(app_dir, "res", subdir)
+            os.makedirs(dst, exist_ok=True)
+            for fn in os.listdir(src):
+                shutil.copyfile(os.path.join(src, fn), os.path.join(dst, fn))
+
+        # Android only reads the layouts' metadata, but the receiver has to exist
+        java_dir = os.path.join(app_dir, "java", "no", "divvun", "kbdgen")
+        os.makedirs(java_dir, exist_ok=True)
+        with open(
+            os.path.join(java_dir, "KeyboardLayoutsReceiver.java"), "w", encoding="utf-8"
+        ) as f:
+            f.write(KEYBOARD_LAYOUTS_RECEIVER)
+
+        manifest_fn = os.path.join(app_dir, "AndroidManifest.xml")
+        with open(manifest_fn, encoding="utf-8") as f:
+            manifest = f.read()
+        if "QUERY_KEYBOARD_LAYOUTS" not in manifest:
+            manifest = manifest.replace(
+                "</application>", KEYBOARD_LAYOUTS_MANIFEST + "</application>", 1
+            )
+            with open(manifest_fn, "w", encoding="utf-8") as f:
+                f.write(manifest)
+
+    def add_app_strings(self, base):
+        # Written by `kbdgen_to_mobile_strings`, laid out like the app
+        src = os.path.join(base, "strings")
+        if not os.path.isdir(src):
+            return
+
+        logger.info("Adding app strings…")
+        dst = os.path.join(base, "deps", self.REPO)
+        for root, _, filenames in os.walk(src):
+            target = os.path.join(dst, os.path.relpath(root, src))
+            os.makedirs(target, exist_ok=True)
+            for fn in filenames:
+                shutil.copyfile(os.path.join(root, fn), os.path.join(target, fn))
+
     def update_localisation(self, base):
         res_dir = os.path.join(base, "deps", self.REPO, "app/src/main/res")
 
@@ -399,6 +594,24 @@ class AndroidGenerator(Generator):
             if os.path.isdir(d):
                 self._update_locale(d, values)
 
+    def update_key_feedback(self, base):
+        fn = os.path.join(
+            base, "deps", self.REPO, "app/src/main/res/values/kbdgen_feedback.xml"
+        )
+        feedback = key_feedback(self.android_target)
+        logger.info("Setting key feedback defaults…")
+
+        root = etree.XML("<resources/>")
+        for name, key in (
+            ("kbdgen_default_sound", "sound"),
+            ("kbdgen_default_haptics", "haptics"),
+            ("kbdgen_feedback_per_locale", "perLocale"),
+        ):
+            SubElement(root, "bool", name=name).text = str(feedback[key]).lower()
+
+        with open(fn, "w", encoding="utf-8") as f:
+            f.write(self._tostring(root))
+
     @property
     def android_resources(self):
         return self._bundle.resources("android")
@@ -527,7 +740,7 @@ class AndroidGenerator(Generator):
 
         SubElement(
             tree.getroot(), "string", name="subtype_in_root_locale_%s" % clean_name
-        ).text = kbd.display_names[name]
+        ).text = display_name(kbd, name, self._bundle.project)
 
         with open(fn, "w", encoding="utf-8") as f:
             f.write(self._tostring(tree.getroot()))
@@ -663,8 +876,11 @@ class AndroidGenerator(Generator):
         branch = self._args["kbd_branch"]
 
         deps_dir = Path(os.path.join(base, "deps"))
+        template_dir = os.environ.get("KBDGEN_TEMPLATE_DIR", None)
 
-        if is_local:
+        if template_dir is not None:
+            self._get_local_source_tree(deps_dir, template_dir)
+        elif is_local:
             self._get_local_source_tree(deps_dir, repo)
         else:
             logger.info("Getting source files from %s %s branch…" % (repo, branch))
@@ -875,10 +1091,7 @@ ext.app = [
                 self.add_button_type(key, action, row, tree, is_start)
 
     def _is_dead_key(self, kbd, mode, key):
-        if kbd.dead_keys is None:
-            return False
-        dead_keys_android = kbd.dead_keys.get("android", {})
-        dead_keys = dead_keys_android.get(mode, [])
+        dead_keys = MobileLayoutView(kbd, "android").dead_keys().get(mode, [])
         return key in dead_keys
 
     def add_rows(self, kbd, n, values, style, out, mode):
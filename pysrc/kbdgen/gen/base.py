@@ -6,6 +6,8 @@ import subprocess
 import sys
 import re
 import io
+import json
+import unicodedata
 
 from functools import lru_cache
 from collections import OrderedDict
@@ -13,6 +15,11 @@ from collections import OrderedDict
 from . import bin as resources
 from ..base import ISO_KEYS, KbdgenException
 
+try:
+    import icu
+except ImportError:
+    icu = None
+
 logger = logging.getLogger(__name__)
 
 # Parses "\s{foo:42.12}", "\s{foo}" and "\{foo:42}"
@@ -58,34 +65,550 @@ def interpret_special_keys(rows):
                 row[n] = {"id": id_, "width": width}
 
 
+def stamp_lines(layout_name=None):
+    """The provenance stamp kbdgen passes on in `KBDGEN_STAMP`, one line per
+    field, or no lines if stamping is turned off"""
+    stamp = stamp_metadata(layout_name)
+    if stamp is None:
+        return []
+    lines = ["Generated by kbdgen %s" % stamp["version"]]
+    if stamp.get("commit") is not None:
+        lines.append("Bundle commit: %s" % stamp["commit"])
+    lines.append("Build date: %s" % stamp["date"])
+    if stamp.get("layout") is not None:
+        lines.append("Layout: %s" % stamp["layout"])
+    return lines
+
+
+def stamp_comment(prefix, layout_name=None):
+    """The stamp as line comments starting with `prefix`, like `//` or `;`"""
+    return "".join("%s %s\n" % (prefix, line) for line in stamp_lines(layout_name))
+
+
+def stamp_metadata(layout_name=None):
+    """The stamp as a dict for JSON files, or None if stamping is turned off"""
+    data = os.environ.get("KBDGEN_STAMP", None)
+    if data is None:
+        return None
+    stamp = json.loads(data)
+    if layout_name is not None:
+        stamp["layout"] = layout_name
+    return stamp
+
+
+def layout_file_path(target, layout_name, extension, default, part=None):
+    """Where a file generated for a layout goes, relative to the output
+    directory, under the layout in `KBDGEN_OUTPUT_LAYOUT`, see
+    `output_layout.rs`, or else `default`, the target's own convention"""
+    output_layout = os.environ.get("KBDGEN_OUTPUT_LAYOUT", None)
+    first = layout_name if output_layout != "per-locale" else target
+    name = "%s.%s" % (first if part is None else "%s-%s" % (first, part), extension)
+    if output_layout == "per-target":
+        return os.path.join(target, name)
+    if output_layout == "per-locale":
+        return os.path.join(layout_name, name)
+    if output_layout == "flat":
+        return name
+    return default
+
+
+DEFAULT_LOCALE = "en"
+
+
+def _locale_truncations(tag):
+    """The tag and its truncations, like `smj-se` and `smj`, dropping a
+    singleton like `x` along with the subtag after it"""
+    subtags = [s for s in tag.strip().replace("_", "-").lower().split("-") if s]
+    tags = []
+    while subtags:
+        tags.append("-".join(subtags))
+        subtags.pop()
+        if subtags and len(subtags[-1]) == 1:
+            subtags.pop()
+    return tags
+
+
+def locale_fallback_chain(locale, project=None):
+    """The locales to look a display name up in for `locale`, in order: the
+    locale and its BCP 47 truncations, each followed by the project's
+    `localeFallbacks` for it, then the project's `defaultLocale`, then `en`.
+    The same chain as the Rust generators use."""
+    fallbacks = {}
+    for tag, locales in (getattr(project, "locale_fallbacks", None) or {}).items():
+        fallbacks.setdefault(tag.replace("_", "-").lower(), []).extend(locales)
+    chain = []
+
+    def push(tags):
+        for tag in tags:
+            if tag not in chain:
+                chain.append(tag)
+
+    for tag in _locale_truncations(locale):
+        push([tag])
+        for fallback in fallbacks.get(tag, []):
+            push(_locale_truncations(fallback))
+    default_locale = getattr(project, "default_locale", None)
+    for default in [default_locale, DEFAULT_LOCALE]:
+        if default is not None:
+            push(_locale_truncations(default))
+    return chain
+
+
+def display_name(layout, locale, project=None):
+    """The display name of the layout for a UI locale, along the locale's
+    fallback chain, or else its first display name"""
+    names = layout.display_names or {}
+    normalized = OrderedDict()
+    for tag, name in sorted(names.items()):
+        normalized.setdefault(tag.replace("_", "-").lower(), name)
+    for tag in locale_fallback_chain(locale, project):
+        if tag in normalized:
+            return normalized[tag]
+    return next(iter(normalized.values()), None)
+
+
+def _fallback_collation_key(name):
+    # Letters first, then accents, then case, like the root collation
+    decomposed = unicodedata.normalize("NFD", name)
+    base = "".join(c for c in decomposed if not unicodedata.combining(c))
+    return (base.casefold(), decomposed.casefold(), decomposed)
+
+
+def sort_layouts(layouts, ui_locale="en", project=None):
+    """Orders a dict of layouts by their display names in `ui_locale`, for
+    language pickers. Names are collated for that language with ICU when it
+    is available, and by letter, then accent, then case otherwise."""
+
+    def name_of(item):
+        name, layout = item
+        return display_name(layout, ui_locale, project) or name
+
+    if icu is not None:
+        collator = icu.Collator.createInstance(icu.Locale(ui_locale.replace("-", "_")))
+        key = lambda item: collator.getSortKey(name_of(item))  # noqa: E731
+    else:
+        key = lambda item: _fallback_collation_key(name_of(item))  # noqa: E731
+
+    return OrderedDict(sorted(layouts.items(), key=key))
+
+
+def spacebar_config(layout, locale, target):
+    """The `spacebar` options of a mobile target for a layout: whether
+    long-pressing space opens the language switcher, and the label drawn on
+    the spacebar, `None` for no label"""
+    spacebar = getattr(target, "spacebar", None)
+    language_switch = getattr(spacebar, "language_switch", None)
+    label = getattr(spacebar, "label", None)
+    if label is None:
+        if "spacebar-language-label" in (layout.features or []):
+            label = "displayName"
+        else:
+            label = "space"
+
+    if label == "space":
+        text = layout.strings.space if layout.strings is not None else None
+    elif label == "displayName":
+        text = layout.display_names.get(locale)
+    else:
+        text = None
+
+    return OrderedDict(
+        [
+            ("languageSwitch", language_switch is not False),
+            ("label", text),
+        ]
+    )
+
+
+def key_feedback(target):
+    """The `feedback` of a mobile target, with the defaults for what it
+    leaves out: no sound, haptics, and one setting for all keyboards"""
+    feedback = getattr(target, "feedback", None)
+    sound = getattr(feedback, "sound", None)
+    haptics = getattr(feedback, "haptics", None)
+    per_locale = getattr(feedback, "per_locale", None)
+    return OrderedDict(
+        [
+            ("sound", sound is True),
+            ("haptics", haptics is not False),
+            ("perLocale", per_locale is True),
+        ]
+    )
+
+
+def _event_word(text):
+    """`symbols_1` for `symbols-1`: lowercase ASCII letters and digits, with
+    underscores for anything else"""
+    return re.sub(r"[^a-z0-9]+", "_", text.lower()).strip("_")
+
+
+def _event_chars(text):
+    """`a_u00e1` for `aá`: lowercase ASCII letters and digits as they are,
+    and every other character as `u` and its code point, so that `A` and `a`
+    stay apart"""
+    return "_".join(
+        c if c in "abcdefghijklmnopqrstuvwxyz0123456789" else "u%04x" % ord(c)
+        for c in text
+    )
+
+
+def analytics_events(target, locale, layout, view_target):
+    """The names of the usage events of a layout, from the `analytics` of a
+    mobile target, or `None` if it has none. The app counts the event of the
+    layout when its keyboard is opened, of a layer when it is switched to, and
+    of a long-press alternative when it is chosen. The names are only made of
+    ASCII letters, digits and underscores, as analytics services expect."""
+    analytics = getattr(target, "analytics", None)
+    if analytics is None:
+        return None
+
+    prefix = _event_word(analytics.prefix or "keyboard")
+    base = "%s_%s" % (prefix, _event_word(locale))
+    out = OrderedDict([("layout", base)])
+
+    if analytics.layers is not False:
+        modes = MobileLayoutView(layout, view_target).modes()
+        out["layers"] = OrderedDict(
+            (mode, "%s_layer_%s" % (base, _event_word(mode))) for mode in modes
+        )
+
+    if analytics.longpress is not False:
+        out["longpress"] = OrderedDict(
+            (
+                key,
+                OrderedDict(
+                    (
+                        alt,
+                        "%s_longpress_%s_%s"
+                        % (base, _event_chars(key), _event_chars(alt)),
+                    )
+                    for alt in alts
+                ),
+            )
+            for key, alts in (layout.longpress or {}).items()
+        )
+
+    return out
+
+
+# What a mobile `theme` leaves out, the same for the Android and iOS apps.
+# Keep in sync with the table in docs/targets/android.adoc.
+THEME_DEFAULTS = {
+    "colors": {
+        "background": "#d1d5db",
+        "key": "#ffffff",
+        "specialKey": "#abb0ba",
+        "keyPressed": "#e5e7eb",
+        "text": "#000000",
+        "secondaryText": "#6b7280",
+    },
+    "darkColors": {
+        "background": "#1f1f1f",
+        "key": "#6b6b6b",
+        "specialKey": "#464646",
+        "keyPressed": "#8a8a8a",
+        "text": "#ffffff",
+        "secondaryText": "#b0b0b0",
+    },
+    "keyCornerRadius": 5,
+    "font": None,
+}
+
+
+def _theme_color(color):
+    """A color as lowercase `#rrggbb` or `#rrggbbaa`, as kbdgen keeps them"""
+    color = color.lower()
+    if len(color) == 4:
+        color = "#" + "".join(c * 2 for c in color[1:])
+    return color
+
+
+RENDER_DEFAULTS = {
+    "fontFamily": "sans-serif",
+    "colors": {
+        "background": None,
+        "key": "#ffffff",
+        "functionKey": "#eeeeee",
+        "border": "#000000",
+        "isoLabel": "#777777",
+        "default": "#000000",
+        "alt": "#ff0000",
+        "caps": "#0000ff",
+        "capsAlt": "#800080",
+        "deadKey": None,
+    },
+    # The colors of dead keys for each group of modes, unless `deadKey` is set
+    "deadKeyColors": {
+        "default": "#008000",
+        "alt": "#ffa500",
+        "caps": "#ffc0cb",
+        "capsAlt": "#008000",
+    },
+    "deadKeyMarker": "bold",
+    "keyLabels": "both",
+    "layoutOrder": [],
+}
+
+
+def render_theme(project):
+    """The project's `render` section, with the defaults for everything it
+    leaves out. It is validated when the bundle is loaded."""
+    render = getattr(project, "render", None) or {}
+    given = render.get("colors") or {}
+    out = OrderedDict()
+    out["fontFamily"] = render.get("fontFamily", RENDER_DEFAULTS["fontFamily"])
+    out["colors"] = OrderedDict(
+        (name, _theme_color(given[name]) if given.get(name) else default)
+        for name, default in RENDER_DEFAULTS["colors"].items()
+    )
+    out["deadKeyColors"] = OrderedDict(
+        (group, out["colors"]["deadKey"] or default)
+        for group, default in RENDER_DEFAULTS["deadKeyColors"].items()
+    )
+    for name in ("deadKeyMarker", "keyLabels", "layoutOrder"):
+        out[name] = render.get(name, RENDER_DEFAULTS[name])
+    return out
+
+
+def mobile_theme(layout, target):
+    """The `theme` of a mobile target for a layout, with the defaults for
+    everything it leaves out. Themes are validated when the bundle is loaded."""
+    theme = ((layout.targets or {}).get(target) or {}).get("theme") or {}
+    out = OrderedDict()
+    for colors in ("colors", "darkColors"):
+        given = theme.get(colors) or {}
+        out[colors] = OrderedDict(
+            (name, _theme_color(given.get(name, default)))
+            for name, default in THEME_DEFAULTS[colors].items()
+        )
+    out["keyCornerRadius"] = theme.get(
+        "keyCornerRadius", THEME_DEFAULTS["keyCornerRadius"]
+    )
+    out["font"] = theme.get("font", THEME_DEFAULTS["font"])
+    return out
+
+
+def target_fonts(bundle, target):
+    """The paths of the `fonts` of a mobile target, in the order key labels
+    use them. `kbdgen lint` reports the characters that none of them have."""
+    return [bundle.relpath(path) for path in getattr(target, "fonts", None) or []]
+
+
+def missing_fonts(fonts):
+    """Whether any of the fonts cannot be found, logging each that cannot"""
+    missing = False
+    for path in fonts:
+        if not os.path.isfile(path):
+            logger.error("Font '%s' not found." % path)
+            missing = True
+    return missing
+
+
+def layout_license(project, layout=None):
+    """The layout's `license`, or else the project's"""
+    if layout is not None and layout.license is not None:
+        return layout.license
+    return project.license
+
+
+def layout_attributions(project, layout=None):
+    """The project's `attributions` followed by the layout's"""
+    attributions = list(project.attributions or [])
+    if layout is not None:
+        for attribution in layout.attributions or []:
+            if attribution not in attributions:
+                attributions.append(attribution)
+    return attributions
+
+
+def license_notice(project, layout=None):
+    """The license and attributions of the layout, or of the project if no
+    layout is given, as lines for About screens and file headers"""
+    license = layout_license(project, layout)
+    lines = [] if license is None else ["License: %s" % license]
+    return lines + layout_attributions(project, layout)
+
+
+def project_urls(project):
+    """The project's URLs that are set, by their name in `project.yaml`, for
+    About screens"""
+    urls = [
+        ("homepage", project.homepage),
+        ("supportUrl", project.support_url),
+        ("privacyPolicyUrl", project.privacy_policy_url),
+    ]
+    return OrderedDict((name, url) for name, url in urls if url)
+
+
+def missing_store_urls(project):
+    """The URLs that app stores need and the project does not set"""
+    urls = project_urls(project)
+    return [name for name in ["supportUrl", "privacyPolicyUrl"] if name not in urls]
+
+
+def license_comment(prefix, project, layout=None):
+    """The license notice as line comments starting with `prefix`"""
+    return "".join("%s %s\n" % (prefix, line) for line in license_notice(project, layout))
+
+
+def accessibility_label(layout, key):
+    """What screen readers say for the key, see `accessibilityLabels`"""
+    labels = layout.accessibility_labels or {}
+    if key in labels:
+        return labels[key]
+    if key == "":
+        return None
+    try:
+        return " ".join(unicodedata.name(c).lower() for c in key)
+    except ValueError:
+        return None
+
+
+def _mode_keys(layout, *modes):
+    """Every key of the modes that types something, and their long-press
+    keys"""
+    keys = []
+    for mode in modes:
+        for rows in mode.values():
+            for row in rows:
+                # Special keys have been turned into dicts if the mode was
+                # viewed before
+                keys.extend(
+                    key
+                    for key in row
+                    if isinstance(key, str) and RE_SPECIAL_KEY.match(key) is None
+                )
+    longpress = layout.longpress or {}
+    for key in list(keys):
+        keys.extend(longpress.get(key, []))
+    return keys
+
+
+def accessibility_labels(layout, *modes):
+    """The labels of every key of the modes, and of their long-press keys"""
+    labels = OrderedDict()
+    for key in _mode_keys(layout, *modes):
+        if key in labels:
+            continue
+        label = accessibility_label(layout, key)
+        if label is not None:
+            labels[key] = label
+    return labels
+
+
+# The base that combining marks are shown on
+DOTTED_CIRCLE = "\u25cc"
+
+# Abbreviations of invisible characters, see `display.rs` in the Rust models
+DISPLAY_ABBREVIATIONS = {
+    "\u00a0": "NBSP",
+    "\u00ad": "SHY",
+    "\u034f": "CGJ",
+    "\u061c": "ALM",
+    "\u180e": "MVS",
+    "\u2002": "ENSP",
+    "\u2003": "EMSP",
+    "\u2009": "THSP",
+    "\u200a": "HSP",
+    "\u200b": "ZWSP",
+    "\u200c": "ZWNJ",
+    "\u200d": "ZWJ",
+    "\u200e": "LRM",
+    "\u200f": "RLM",
+    "\u202f": "NNBSP",
+    "\u2060": "WJ",
+    "\ufeff": "ZWNBSP",
+}
+
+
+def _is_invisible(c):
+    return c != " " and (c.isspace() or unicodedata.category(c) in ("Cc", "Cf"))
+
+
+def default_display_label(key):
+    """The label of a key typing `key`, if it cannot be drawn as it is:
+    combining marks on a dotted circle, and invisible characters as their
+    abbreviations"""
+    if key == "":
+        return None
+    if unicodedata.category(key[0]).startswith("M"):
+        return DOTTED_CIRCLE + key
+    if all(_is_invisible(c) for c in key):
+        return " ".join(
+            DISPLAY_ABBREVIATIONS.get(c, "U+%04X" % ord(c)) for c in key
+        )
+    return None
+
+
+def display_label(layout, key):
+    """What to draw on the key instead of what it types, see
+    `displayOverrides`, or `None` to draw what it types"""
+    overrides = layout.display_overrides or {}
+    if key in overrides:
+        return overrides[key]
+    return default_display_label(key)
+
+
+def display_labels(layout, *modes):
+    """The labels of the keys of the modes, and of their long-press keys,
+    that are not drawn as what they type"""
+    labels = OrderedDict()
+    for key in _mode_keys(layout, *modes):
+        if key in labels:
+            continue
+        label = display_label(layout, key)
+        if label is not None:
+            labels[key] = label
+    return labels
+
+
+# The target whose modes are used when a layout defines none for a target,
+# see `Target::fallback` in the Rust models
+TARGET_FALLBACKS = {
+    "win": "desktop",
+    "mac": "desktop",
+    "chrome": "desktop",
+    "x11": "desktop",
+    "ios": "mobile",
+    "android": "mobile",
+}
+
+
+def resolve_modes(layout, target):
+    """The target the layout's modes for `target` come from: `target` if the
+    layout has modes for it, otherwise its fallback, and None if neither.
+    The modes of a target replace those of its fallback as a whole, as in
+    `Modes::resolve` of the Rust models."""
+    for candidate in (target, TARGET_FALLBACKS.get(target)):
+        if candidate is not None and candidate in layout.modes:
+            return candidate
+    return None
+
+
+def has_modes(layout, target):
+    return resolve_modes(layout, target) is not None
+
+
 class MobileLayoutView:
     def __init__(self, layout, target):
         self._layout = layout
         self._target = target
 
     def mode(self, mode):
-        o = {}
-        o.update(self._layout.modes.get("mobile", {}))
-        o.update(self._layout.modes.get(self._target, {}))
-        rows = o.get(mode, None)
+        rows = self.modes().get(mode, None)
         if rows is None:
             return None
         interpret_special_keys(rows)
         return rows
 
     def modes(self):
-        o = {}
-        o.update(self._layout.modes.get("mobile", {}))
-        o.update(self._layout.modes.get(self._target, {}))
-        return o
+        source = resolve_modes(self._layout, self._target)
+        return dict(self._layout.modes.get(source, {}))
 
     def dead_keys(self):
-        o = {}
-        if self._layout.dead_keys is None:
-            return o
-        o.update(self._layout.dead_keys.get("mobile", {}))
-        o.update(self._layout.dead_keys.get(self._target, {}))
-        return o
+        """The dead keys of the target the modes come from"""
+        source = resolve_modes(self._layout, self._target)
+        return dict((self._layout.dead_keys or {}).get(source, {}))
 
 
 class TabletLayoutView:
@@ -117,18 +640,13 @@ class DesktopLayoutView:
         return self.modes().get(mode, None)
 
     def modes(self):
-        o = {}
-        o.update(self._layout.modes.get("desktop", {}))
-        o.update(self._layout.modes.get(self._target, {}))
-        return o
+        source = resolve_modes(self._layout, self._target)
+        return dict(self._layout.modes.get(source, {}))
 
     def dead_keys(self):
-        o = {}
-        if self._layout.dead_keys is None:
-            return o
-        o.update(self._layout.dead_keys.get("desktop", {}))
-        o.update(self._layout.dead_keys.get(self._target, {}))
-        return o
+        """The dead keys of the target the modes come from"""
+        source = resolve_modes(self._layout, self._target)
+        return dict((self._layout.dead_keys or {}).get(source, {}))
 
     def space(self):
         o = {}
@@ -138,6 +656,203 @@ class DesktopLayoutView:
         o.update(self._layout.space.get(self._target, {}))
         return o
 
+    def modifier_remaps(self):
+        o = {}
+        if self._layout.modifier_remaps is None:
+            return o
+        o.update(self._layout.modifier_remaps.get("desktop", {}))
+        o.update(self._layout.modifier_remaps.get(self._target, {}))
+        return o
+
+
+def dead_key_terminator(layout, dead_key):
+    """What the dead key types on its own, see `deadKeyTerminators`"""
+    terminators = layout.dead_key_terminators or {}
+    if dead_key in terminators:
+        return terminators[dead_key]
+    return (layout.transforms or {}).get(dead_key, {}).get(" ", dead_key)
+
+
+def dead_key_preview(layout, dead_key):
+    """The glyph to show for a pending dead key, see `deadKeyOptions`"""
+    options = (layout.dead_key_options or {}).get(dead_key) or {}
+    return options.get("preview", None) or dead_key
+
+
+def dead_key_previews(layout, *dead_keys):
+    """The `preview` of each dead key in the `{mode: [key]}` maps of
+    `dead_keys` that has one, for the popups of mobile keyboards"""
+    previews = OrderedDict()
+    for modes in dead_keys:
+        for keys in modes.values():
+            for key in keys:
+                preview = dead_key_preview(layout, key)
+                if preview != key:
+                    previews[key] = preview
+    return previews
+
+
+def all_dead_keys(layout):
+    """The dead keys of every target and mode"""
+    return set(
+        key
+        for modes in (layout.dead_keys or {}).values()
+        for keys in modes.values()
+        for key in keys
+    )
+
+
+def dead_key_chain(layout, dead_keys, all_keys=None):
+    """The dead keys typed in turn to reach the transforms for `dead_keys`,
+    if it is a chain of several like `^´`, or None"""
+    if all_keys is None:
+        all_keys = all_dead_keys(layout)
+    if dead_keys in all_keys:
+        return None
+
+    def split(rest):
+        if rest == "":
+            return []
+        for key in sorted(
+            (k for k in all_keys if k and rest.startswith(k)), key=len, reverse=True
+        ):
+            tail = split(rest[len(key):])
+            if tail is not None:
+                return [key] + tail
+        return None
+
+    chain = split(dead_keys)
+    if chain is None or len(chain) < 2:
+        return None
+    return chain
+
+
+def _is_marks(s):
+    return len(s) > 0 and all(unicodedata.category(c).startswith("M") for c in s)
+
+
+def symmetric_transforms(layout):
+    """The transforms of the layout, with each transform of a combining mark
+    and a base character also the other way round if the layout sets
+    `symmetricTransforms`. Only swapped transforms that start with a dead key
+    are added, and transforms written out win over them."""
+    dead_keys = all_dead_keys(layout)
+    transforms = OrderedDict(
+        (first, dict(transform)) for first, transform in (layout.transforms or {}).items()
+    )
+    if not layout.symmetric_transforms:
+        return transforms
+
+    for first, transform in (layout.transforms or {}).items():
+        for second, output in transform.items():
+            if not isinstance(output, str) or second == " ":
+                continue
+            if _is_marks(first) == _is_marks(second):
+                continue
+            if second not in dead_keys:
+                continue
+            transforms.setdefault(second, {}).setdefault(first, output)
+    return transforms
+
+
+def nested_transforms(layout):
+    """The transforms of the layout with each dead key chain nested under
+    the dead keys it is typed with, so that `^´` becomes `´` of `^`"""
+    all_keys = all_dead_keys(layout)
+    nested = OrderedDict()
+    chains = []
+    for dead_keys, transform in symmetric_transforms(layout).items():
+        chain = dead_key_chain(layout, dead_keys, all_keys)
+        if chain is None:
+            nested[dead_keys] = dict(transform)
+        else:
+            chains.append((chain, transform))
+
+    for chain, transform in sorted(chains, key=lambda c: len(c[0])):
+        node = nested
+        for key in chain:
+            if not isinstance(node.get(key), dict):
+                node[key] = {}
+            node = node[key]
+        node.update(transform)
+    return nested
+
+
+# Losses are logged as JSON on their own logger, which kbdgen collects into
+# the build report. See `kbdgen::loss`.
+loss_logger = logging.getLogger("kbdgen::loss")
+
+
+def report_loss(name, target, item, reason, altered=False):
+    """Reports a key or rule of a layout that the target cannot represent,
+    and so leaves out or, if `altered`, changes."""
+    loss_logger.warning(
+        json.dumps(
+            {
+                "target": target,
+                "layout": name,
+                "item": item,
+                "kind": "altered" if altered else "dropped",
+                "reason": reason,
+            },
+            ensure_ascii=False,
+        )
+    )
+
+
+def warn_unsupported_dead_key_chains(name, layout, target):
+    for dead_keys in layout.transforms or {}:
+        if dead_key_chain(layout, dead_keys) is not None:
+            report_loss(
+                name,
+                target,
+                "dead key chain `%s`" % dead_keys,
+                "dead key chains cannot be expressed for %s" % target,
+            )
+
+
+# What each target does with a transform in progress when the layout does not
+# set `transformPartial`, and whether it can do otherwise
+TRANSFORM_PARTIAL_DEFAULTS = {
+    "mac": ("show", False),
+    "win": ("hide", False),
+    "x11": ("hide", False),
+    "chrome": ("hide", True),
+    "android": ("show", True),
+    "ios": ("show", True),
+}
+
+
+def transform_partial(layout, target):
+    """Whether `target` shows a transform in progress, like a pending dead
+    key, for the layout, as `hide` or `show`"""
+    default, configurable = TRANSFORM_PARTIAL_DEFAULTS[target]
+    if configurable and layout.transform_partial is not None:
+        return layout.transform_partial
+    return default
+
+
+def warn_ignored_transform_partial(name, layout, target):
+    partial = layout.transform_partial
+    if partial is not None and transform_partial(layout, target) != partial:
+        report_loss(
+            name,
+            target,
+            "transformPartial `%s`" % partial,
+            "%s always does '%s'" % (target, transform_partial(layout, target)),
+            altered=True,
+        )
+
+
+def warn_unsupported_reordering(name, layout, target):
+    if layout.reorders or layout.backspaces:
+        report_loss(
+            name,
+            target,
+            "reorders and backspaces",
+            "%s cannot express them" % target,
+        )
+
 
 class Generator:
     def __init__(self, bundle, args=None):
@@ -164,6 +879,10 @@ class Generator:
     def output_dir(self):
         return self._args.get("output", ".")
 
+    @property
+    def picker_locale(self):
+        return self._args.get("ui_locale") or "en"
+
     def satisfies_requirements(self) -> bool:
         # if len(self.supported_layouts) == 0:
         #     logger.error("This project defines no supported layouts for this target.")
@@ -181,8 +900,6 @@ class PhysicalGenerator(Generator):
         mode_keys = set(view.modes().keys())
         deadkey_keys = set(view.dead_keys().keys())
 
-        print(mode_keys)
-
         undefined_modes = deadkey_keys - mode_keys
 
         if len(undefined_modes) > 0:
@@ -1,6 +1,7 @@
 class Keyboard {
   static install(descriptor) {
     let contextId = -1
+    let composing = false
     const kbd = new Keyboard(descriptor)
 
     chrome.input.ime.onFocus.addListener((context) => {
@@ -16,6 +17,23 @@ class Keyboard {
       if (keyData.type === "keydown") {
         const result = kbd.parseInput(keyboardId, keyData)
         console.log(result)
+
+        // Show the transform in progress, if the layout wants it shown
+        const partial = kbd.partial(keyboardId)
+        if (partial != null) {
+          chrome.input.ime.setComposition({
+            contextID: contextId,
+            text: partial,
+            cursor: partial.length
+          })
+          composing = true
+          return true
+        }
+
+        if (composing) {
+          chrome.input.ime.clearComposition({ contextID: contextId })
+          composing = false
+        }
         
         if (result == null) {
           // Pass through the default value
@@ -64,6 +82,15 @@ class Keyboard {
   constructor(descriptor) {
     this.descriptor = descriptor
     this.transformRef = null
+    this.pending = ""
+  }
+
+  // What has been typed of the transform in progress, if the layout shows it
+  partial(keyboardId) {
+    if (this.transformRef == null || this.descriptor[keyboardId].transformPartial !== "show") {
+      return null
+    }
+    return this.pending
   }
 
   *deriveFallbackLayers(layer) {
@@ -150,6 +177,7 @@ class Keyboard {
       const deadKeyRef = this.deadKey(keyboardId, layer[code], layerName)
       if (deadKeyRef != null) {
         this.transformRef = deadKeyRef
+        this.pending = layer[code]
         return null
       }
 
@@ -178,11 +206,13 @@ class Keyboard {
         // If it's a string, we're at the end of the line
         if (typeof t === "string") {
           this.transformRef = null
+          this.pending = ""
           return t
         }
 
         // Otherwise, buckle up for more transforms!
         this.transformRef = t
+        this.pending = t == null ? "" : this.pending + value
         return null
       }
 
@@ -2,18 +2,20 @@ from collections import OrderedDict
 import json
 import os
 import os.path
-import shutil
-import tempfile
-import urllib
-import urllib.request
 
 from ..base import get_logger
 from .base import (
     PhysicalGenerator,
     run_process,
     DesktopLayoutView,
+    has_modes,
     bind_iso_keys,
     get_bin_resource,
+    warn_unsupported_dead_key_chains,
+    warn_unsupported_reordering,
+    sort_layouts,
+    transform_partial,
+    symmetric_transforms,
 )
 
 logger = get_logger(__name__)
@@ -117,9 +119,10 @@ class ChromeOSGenerator(PhysicalGenerator):
     def supported_layouts(self):
         o = OrderedDict()
         for k, v in self._bundle.layouts.items():
-            if "chrome" in v.modes or "desktop" in v.modes:
+            if has_modes(v, "chrome"):
                 o[k] = v
-        return o
+        # The order of the language picker
+        return sort_layouts(o, self.picker_locale, self._bundle.project)
 
     @property
     def chrome_target(self):
@@ -197,6 +200,8 @@ class ChromeOSGenerator(PhysicalGenerator):
             "default_locale": "en",
             "icons": ICONS,
         }
+        if self._bundle.project.homepage:
+            manifest["homepage_url"] = self._bundle.project.homepage
 
         return {"manifest": manifest, "messages": messages}
 
@@ -275,46 +280,6 @@ class ChromeOSGenerator(PhysicalGenerator):
 
         return super().satisfies_requirements()
 
-    def upload_package(self, build_dir):
-        data = {
-            "grant_type": "refresh_token",
-            "client_id": os.environ["CHROME_CLIENT_ID"],
-            "client_secret": os.environ["CHROME_CLIENT_SECRET"],
-            "refresh_token": os.environ["CHROME_REFRESH_TOKEN"],
-        }
-
-        request = urllib.request.Request(
-            "https://www.googleapis.com/oauth2/v4/token", data=data, method="POST"
-        )
-        response = urllib.request.urlopen(request)
-        oauth_response = json.loads(response.read().decode("utf-8"))
-
-        logger.info("Generating .zip for upload…")
-        with tempfile.TemporaryDirectory() as tmpdir:
-            p = "%s/upload-%s.zip" % (tmpdir, self.app_id)
-            logger.trace("p: %r, b: %r" % (p, build_dir))
-            run_process(["zip", "-r9", p, "."], cwd=build_dir, show_output=True)
-
-            url = (
-                "https://www.googleapis.com/upload/chromewebstore/v1.1/items/%s"
-                % self.app_id
-            )
-            headers = {
-                "Authorization": "Bearer %s" % oauth_response["access_token"],
-                "x-goog-api-version": "2",
-            }
-            logger.info("Uploading…")
-            with open(p, "rb") as f:
-                request = urllib.request.Request(
-                    url, headers=headers, data=f.read(), method="PUT"
-                )
-            result = urllib.request.urlopen(request)
-            logger.debug("%r" % result)
-
-            shutil.copyfile(
-                p, os.path.join(build_dir, "..", "chrome-%s.zip" % self.app_id)
-            )
-
     def generate(self, base="."):
         if not self.satisfies_requirements():
             return
@@ -345,6 +310,8 @@ class ChromeOSGenerator(PhysicalGenerator):
         layouts = {}
         for locale, layout in self.supported_layouts.items():
             logger.info("Generating layout for locale '%s'…" % locale)
+            warn_unsupported_reordering(locale, layout, "chrome")
+            warn_unsupported_dead_key_chains(locale, layout, "chrome")
             layout_view = DesktopLayoutView(layout, "chrome")
 
             modes = {}
@@ -353,7 +320,8 @@ class ChromeOSGenerator(PhysicalGenerator):
 
             layout_descriptor = {
                 "deadKeys": layout_view.dead_keys(),
-                "transforms": layout.transforms,
+                "transforms": symmetric_transforms(layout),
+                "transformPartial": transform_partial(layout, "chrome"),
                 "layers": modes,
                 "space": layout_view.space(),
             }
@@ -379,6 +347,6 @@ class ChromeOSGenerator(PhysicalGenerator):
         logger.info("Generating icons…")
         self.generate_icons(deps_dir)
 
-        if self.is_release:
-            self.upload_package(deps_dir)
+        # The zip for the web store is made, and uploaded for releases, by
+        # kbdgen once this returns, see `package_chrome`
         logger.info("Done!")
@@ -3,7 +3,7 @@ import os.path
 from io import StringIO
 from collections import namedtuple, OrderedDict
 from math import sqrt
-from .base import Generator, MobileLayoutView
+from .base import Generator, MobileLayoutView, has_modes
 from ..base import get_logger
 
 logger = get_logger(__name__)
@@ -102,7 +102,7 @@ class ErrorModelGenerator(Generator):
     def supported_layouts(self):
         o = OrderedDict()
         for k, v in self._bundle.layouts.items():
-            if "ios" in v.modes or "mobile" in v.modes:
+            if has_modes(v, "ios"):
                 o[k] = v
         return o
 
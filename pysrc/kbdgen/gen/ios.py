@@ -16,14 +16,54 @@ from collections import OrderedDict
 from pathlib import Path
 
 from ..base import get_logger
+from ..cldr import decode_u
 from ..filecache import FileCache
-from .base import Generator, run_process, MobileLayoutView, TabletLayoutView
+from .base import (
+    Generator,
+    run_process,
+    MobileLayoutView,
+    TabletLayoutView,
+    DesktopLayoutView,
+    has_modes,
+    accessibility_labels,
+    display_labels,
+    dead_key_previews,
+    sort_layouts,
+    spacebar_config,
+    key_feedback,
+    analytics_events,
+    mobile_theme,
+    target_fonts,
+    missing_fonts,
+    stamp_metadata,
+    license_notice,
+    layout_license,
+    layout_attributions,
+    display_name,
+    project_urls,
+    missing_store_urls,
+    transform_partial,
+    symmetric_transforms,
+)
 from .osxutil import Pbxproj
 
 logger = get_logger(__name__)
 
 VERSION_RE = re.compile(r"Xcode (\d+)\.(\d+)")
 
+# The macOS modes an attached hardware keyboard can reach on iPadOS. Command
+# and control are left to the system for shortcuts.
+HARDWARE_MODES = (
+    "default",
+    "shift",
+    "caps",
+    "caps+shift",
+    "alt",
+    "alt+shift",
+    "caps+alt",
+    "caps+alt+shift",
+)
+
 
 class AppleiOSGenerator(Generator):
     @property
@@ -68,6 +108,14 @@ class AppleiOSGenerator(Generator):
         deps_dir = Path(os.path.join(base, "ios-build"))
         shutil.rmtree(str(deps_dir), ignore_errors=True)
 
+        template_dir = os.environ.get("KBDGEN_TEMPLATE_DIR", None)
+        if template_dir is not None:
+            logger.info("Copying source files from %s…" % template_dir)
+            shutil.copytree(
+                template_dir, deps_dir, ignore=shutil.ignore_patterns(".git", ".svn")
+            )
+            return
+
         logger.trace("Github username: %r" % self.github_username)
 
         repo = self._args["kbd_repo"]
@@ -82,6 +130,10 @@ class AppleiOSGenerator(Generator):
     def ios_target(self):
         return self._bundle.targets.get("ios", {})
 
+    @property
+    def fonts(self):
+        return target_fonts(self._bundle, self.ios_target)
+
     @property
     def pkg_id(self):
         return self.ios_target.package_id.replace("_", "-")
@@ -173,9 +225,10 @@ class AppleiOSGenerator(Generator):
     def supported_layouts(self):
         o = OrderedDict()
         for k, v in self._bundle.layouts.items():
-            if "ios" in v.modes or "mobile" in v.modes:
+            if has_modes(v, "ios"):
                 o[k] = v
-        return o
+        # The order of the language picker
+        return sort_layouts(o, self.picker_locale, self._bundle.project)
 
     def satisfies_requirements(self):
         if not super().satisfies_requirements():
@@ -189,10 +242,21 @@ class AppleiOSGenerator(Generator):
             logger.error("CODE_SIGN_ID must be set.")
             return False
 
+        missing = missing_store_urls(self._bundle.project)
+        if self.is_release and missing:
+            logger.error(
+                "App Store releases need %s in project.yaml."
+                % " and ".join("`%s`" % name for name in missing)
+            )
+            return False
+
         if shutil.which("convert") is None:
             logger.error("`convert` not found. Is imagemagick installed?")
             return False
 
+        if missing_fonts(self.fonts):
+            return False
+
         return True
 
     def generate(self, base="."):
@@ -242,6 +306,11 @@ class AppleiOSGenerator(Generator):
         kbd_plist_path = os.path.join(deps_dir, "Keyboard", "Info.plist")
         dev_team = self.team_id
 
+        # Before the keyboard target is copied for each layout, so that the
+        # copies have them too
+        self.add_fonts(pbxproj, deps_dir)
+        self.add_autocorrect(pbxproj, base, deps_dir)
+
         with open(kbd_plist_path, "rb") as f:
             kbd_plist = plistlib.load(f, dict_type=OrderedDict)
             # Workaround for plist having Divvun fields already
@@ -250,7 +319,7 @@ class AppleiOSGenerator(Generator):
                 del kbd_plist[k]
 
         for n, (locale, layout) in enumerate(self.supported_layouts.items()):
-            native_name = layout.display_names[locale]
+            native_name = display_name(layout, locale, self._bundle.project)
             kbd_pkg_id = self.kbd_pkg_id(locale)
             suffix = kbd_pkg_id.split(".")[-1]
 
@@ -287,6 +356,7 @@ class AppleiOSGenerator(Generator):
 
         # Create locale strings
         self.create_locales(pbxproj, deps_dir)
+        self.add_app_strings(pbxproj, base, deps_dir)
 
         # Update pbxproj with locales
         with open(path, "w", encoding="utf-8") as f:
@@ -601,6 +671,60 @@ class AppleiOSGenerator(Generator):
             logger.info("Adding '%s' to '%s'…" % (bfn, nm))
             shutil.copyfile(fn, os.path.join(path, bfn))
 
+    def add_fonts(self, pbxproj, build_dir):
+        if not self.fonts:
+            return
+        path = os.path.join(build_dir, "Keyboard", "Fonts")
+        os.makedirs(path, exist_ok=True)
+
+        for fn in self.fonts:
+            bfn = os.path.basename(fn)
+            logger.info("Adding font '%s'…" % bfn)
+            shutil.copyfile(fn, os.path.join(path, bfn))
+            ref = pbxproj.create_font_file(bfn)
+            pbxproj.add_path(["Keyboard", "Fonts"])
+            pbxproj.add_ref_to_group(ref, ["Keyboard", "Fonts"])
+            pbxproj.add_resource_ref_to_build_phase(ref, "Keyboard")
+
+    def add_autocorrect(self, pbxproj, base, build_dir):
+        # Written by `kbdgen_to_autocorrect` from the bundle's wordlists
+        src = os.path.join(base, "autocorrect")
+        if not os.path.isdir(src):
+            return
+        path = os.path.join(build_dir, "Keyboard", "Autocorrect")
+        os.makedirs(path, exist_ok=True)
+
+        for fn in sorted(os.listdir(src)):
+            logger.info("Adding autocorrect dictionary '%s'…" % fn)
+            shutil.copyfile(os.path.join(src, fn), os.path.join(path, fn))
+            ref = pbxproj.create_json_file(fn)
+            pbxproj.add_path(["Keyboard", "Autocorrect"])
+            pbxproj.add_ref_to_group(ref, ["Keyboard", "Autocorrect"])
+            pbxproj.add_resource_ref_to_build_phase(ref, "Keyboard")
+
+    def add_app_strings(self, pbxproj, base, build_dir):
+        # Written by `kbdgen_to_mobile_strings`, laid out like the app
+        src = os.path.join(base, "strings", "HostingApp")
+        if not os.path.isdir(src):
+            return
+
+        logger.info("Adding app strings…")
+        refs = []
+        for lproj in sorted(os.listdir(src)):
+            path = os.path.join(build_dir, "HostingApp", lproj)
+            os.makedirs(path, exist_ok=True)
+            shutil.copyfile(
+                os.path.join(src, lproj, "Localizable.strings"),
+                os.path.join(path, "Localizable.strings"),
+            )
+            locale = os.path.splitext(lproj)[0]
+            refs.append(pbxproj.create_plist_string_file(locale, "Localizable.strings"))
+
+        ref = pbxproj.add_variant_group_to_build_phase(
+            refs, "Localizable.strings", "HostingApp"
+        )
+        pbxproj.add_ref_to_group(ref, ["HostingApp"])
+
     @property
     def ios_resources(self):
         return self._bundle.resources("ios")
@@ -701,14 +825,22 @@ class AppleiOSGenerator(Generator):
                 self.write_l10n_str(f, "CFBundleName", attrs.name)
                 self.write_l10n_str(f, "CFBundleDisplayName", attrs.name)
 
-            # Add About.txt to the lproj if exists
+            # Add About.txt to the lproj if exists, followed by the license
+            notice = "\n".join(license_notice(self._bundle.project))
+            about_fn = os.path.join(lproj, "About.txt")
             if locale in about_locales:
                 about_file = os.path.join(about_dir, "%s.txt" % locale)
-                shutil.copyfile(about_file, os.path.join(lproj, "About.txt"))
+                shutil.copyfile(about_file, about_fn)
+                if notice:
+                    with open(about_fn, "a", encoding="utf-8") as f:
+                        f.write("\n\n%s\n" % notice)
 
                 if lproj_dir != "Base":
                     file_ref = pbxproj.create_text_file(locale, "About.txt")
                     pbxproj.add_file_ref_to_variant_group(file_ref, "About.txt")
+            elif lproj_dir == "Base" and notice:
+                with open(about_fn, "w", encoding="utf-8") as f:
+                    f.write("%s\n\n%s\n" % (attrs.description, notice))
 
     def get_layout_locales(self, name, layout):
         locales = set(layout.display_names.keys())
@@ -768,6 +900,8 @@ class AppleiOSGenerator(Generator):
         plist["LSApplicationQueriesSchemes"][0] = pkg_id
         plist["NSExtension"]["NSExtensionAttributes"]["PrimaryLanguage"] = locale
         plist["DivvunKeyboardIndex"] = n
+        if self.fonts:
+            plist["UIAppFonts"] = [os.path.basename(path) for path in self.fonts]
 
         pahkat_key = self.layout_target(layout).get("spellerPackageKey", None)
         speller_path = self.layout_target(layout).get("spellerPath", None)
@@ -782,8 +916,16 @@ class AppleiOSGenerator(Generator):
         if dsn is not None:
             plist["SentryDSN"] = dsn
 
+        self._set_key_feedback(plist)
         plistlib.dump(plist, f)
 
+    def _set_key_feedback(self, plist):
+        # The defaults of the settings, for the app and each keyboard
+        feedback = key_feedback(self.ios_target)
+        plist["DivvunKeySound"] = feedback["sound"]
+        plist["DivvunHaptics"] = feedback["haptics"]
+        plist["DivvunFeedbackPerLocale"] = feedback["perLocale"]
+
     def update_plist(self, plist, f):
         pkg_id = self.pkg_id
 
@@ -798,6 +940,7 @@ class AppleiOSGenerator(Generator):
         plist["CFBundleURLTypes"][0]["CFBundleURLSchemes"][0] = pkg_id
         plist["LSApplicationQueriesSchemes"][0] = pkg_id
 
+        self._set_key_feedback(plist)
         plistlib.dump(plist, f)
 
     def generate_json_layout(self, name, layout):
@@ -811,18 +954,32 @@ class AppleiOSGenerator(Generator):
 
         all_dead_keys = layout.dead_keys or {}
         dead_keys = {
-            "iphone": all_dead_keys.get("ios", {}),
+            "iphone": MobileLayoutView(layout, "ios").dead_keys(),
             "ipad-9in": all_dead_keys.get("ipad-9in", {}),
             "ipad-12in": all_dead_keys.get("ipad-12in", {}),
         }
 
         out["name"] = local_name
         out["locale"] = name
+        out["rtl"] = bool(layout.rtl)
         out["return"] = layout.strings._return
-        out["space"] = layout.strings.space
+        spacebar = spacebar_config(layout, name, self.ios_target)
+        out["space"] = spacebar["label"] or ""
+        out["spacebar"] = spacebar
+        out["theme"] = mobile_theme(layout, "ios")
+        analytics = analytics_events(self.ios_target, name, layout, "ios")
+        if analytics is not None:
+            out["analytics"] = analytics
+        out["fonts"] = [os.path.basename(path) for path in self.fonts]
+        out["features"] = layout.features or []
         out["longPress"] = layout.longpress
+        out["flicks"] = layout.flicks or {}
         out["deadKeys"] = dead_keys
-        out["transforms"] = layout.transforms
+        out["deadKeyPreviews"] = dead_key_previews(layout, *dead_keys.values())
+        out["transforms"] = symmetric_transforms(layout)
+        out["transformPartial"] = transform_partial(layout, "ios")
+        out["reorders"] = layout.reorders or []
+        out["backspaces"] = layout.backspaces or {}
 
         iphone = out["iphone"] = {}
         ipad_9in = out["ipad-9in"] = {}
@@ -850,4 +1007,54 @@ class AppleiOSGenerator(Generator):
         ipad_12in["symbols-1"] = view.mode("symbols-1")
         ipad_12in["symbols-2"] = view.mode("symbols-2")
 
+        modes = [
+            MobileLayoutView(layout, "ios").modes(),
+            TabletLayoutView(layout, "ipad-9in").modes(),
+            TabletLayoutView(layout, "ipad-12in").modes(),
+        ]
+        out["accessibilityLabels"] = accessibility_labels(layout, *modes)
+        out["displayLabels"] = display_labels(layout, *modes)
+
+        stamp = stamp_metadata(name)
+        if stamp is not None:
+            out["stamp"] = stamp
+
+        # For the About screen
+        license = layout_license(self._bundle.project, layout)
+        if license is not None:
+            out["license"] = license
+        attributions = layout_attributions(self._bundle.project, layout)
+        if attributions:
+            out["attributions"] = attributions
+        out.update(project_urls(self._bundle.project))
+
+        hardware = self.generate_hardware_layout(layout)
+        if hardware is not None:
+            out["hardware"] = hardware
+
         return out
+
+    def generate_hardware_layout(self, layout):
+        # Attached keyboards get the desktop layout, as the macOS keyboard
+        # would have it. Keys are ISO positions, which the extension maps to
+        # the UIKeyCommand inputs of the physical keys.
+        view = DesktopLayoutView(layout, "mac")
+
+        modes = OrderedDict()
+        for mode_name in HARDWARE_MODES:
+            mode = view.mode(mode_name)
+            if mode is None:
+                continue
+            modes[mode_name] = OrderedDict(
+                (iso, decode_u(key)) for iso, key in mode.items() if key is not None
+            )
+
+        if not modes:
+            return None
+
+        dead_keys = OrderedDict()
+        for mode_name, keys in view.dead_keys().items():
+            if mode_name in modes:
+                dead_keys[mode_name] = [decode_u(key) for key in keys]
+
+        return OrderedDict((("modes", modes), ("deadKeys", dead_keys)))
@@ -3,7 +3,7 @@ import shutil
 
 from collections import OrderedDict
 
-from .base import Generator, run_process, MobileLayoutView
+from .base import Generator, run_process, MobileLayoutView, layout_file_path
 from ..base import get_logger
 from .ios import AppleiOSGenerator
 import json
@@ -57,7 +57,10 @@ class QRGenerator(AppleiOSGenerator):
         compressed = brotli.compress(data.encode("utf-8"))
         logger.debug(["%02x" % x for x in compressed])
         logger.debug("%s %s" % (len(data), len(compressed)))
-        fn_path = os.path.abspath(os.path.join(base, "%s.png" % name))
+        fn_path = os.path.abspath(
+            os.path.join(base, layout_file_path("qr", name, "png", "%s.png" % name))
+        )
+        os.makedirs(os.path.dirname(fn_path), exist_ok=True)
 
         run_process(["qrencode", "-8o", fn_path], shell=False, pipe=compressed)
         logger.info("QR code generated at: %s" % fn_path)
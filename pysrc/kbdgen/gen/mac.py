@@ -5,6 +5,11 @@ import tempfile
 import sys
 import re
 import binascii
+import json
+import shlex
+import subprocess
+import time
+import urllib.request
 import xml.etree.ElementTree as etree
 
 from xml.etree.ElementTree import SubElement
@@ -12,12 +17,97 @@ from collections import defaultdict, OrderedDict
 from textwrap import indent, dedent
 
 from ..base import get_logger
-from .base import PhysicalGenerator, run_process, DictWalker, DesktopLayoutView
+from .base import (
+    PhysicalGenerator,
+    run_process,
+    DictWalker,
+    DesktopLayoutView,
+    has_modes,
+    dead_key_preview,
+    dead_key_terminator,
+    nested_transforms,
+    report_loss,
+    warn_ignored_transform_partial,
+    warn_unsupported_reordering,
+    stamp_lines,
+    license_notice,
+)
 from .osxutil import OSXKeyLayout, OSX_HARDCODED, OSX_KEYMAP
 
 logger = get_logger(__name__)
 
 INVERTED_ID_RE = re.compile(r"[^A-Za-z0-9]")
+BUNDLE_FILENAME_RE = re.compile(r'[/\\:*?"<>|]')
+
+# Seconds between asking the notary service whether it is done
+NOTARY_POLL_INTERVAL = 30
+
+# HID usages of the modifier keys that `modifierRemaps` can remap, and of the
+# keys whose role they take. Macs have no menu key, and `hidutil` cannot
+# turn a key off.
+MAC_MODIFIER_USAGES = {
+    "caps": 0x700000039,
+    "left-ctrl": 0x7000000E0,
+    "left-alt": 0x7000000E2,
+    "right-ctrl": 0x7000000E4,
+    "right-alt": 0x7000000E6,
+}
+
+MAC_ROLE_USAGES = {
+    "altgr": 0x7000000E6,
+    "level3": 0x7000000E6,
+    "alt": 0x7000000E2,
+    "ctrl": 0x7000000E0,
+    "shift": 0x7000000E1,
+    "caps": 0x700000039,
+}
+
+# The remaps are a package of their own, which the installer only installs
+# if the user picks it, as they apply to every keyboard layout. The script
+# that removes them again is left in `Application Support`.
+MODIFIER_POSTINSTALL = """\
+#!/bin/sh
+# Remaps modifier keys for every user at login, and for this session now
+mkdir -p "/Library/Application Support/{label}"
+cat > "/Library/Application Support/{label}/remove-modifier-remaps" <<'EOF'
+#!/bin/sh
+# Removes the modifier remaps of {label}, run with sudo
+rm -f "/Library/LaunchAgents/{label}.plist"
+/usr/bin/hidutil property --set '{{"UserKeyMapping":[]}}'
+rm -rf "/Library/Application Support/{label}"
+/usr/sbin/pkgutil --forget "{label}" > /dev/null 2>&1 || true
+EOF
+chmod 755 "/Library/Application Support/{label}/remove-modifier-remaps"
+cat > "/Library/LaunchAgents/{label}.plist" <<'EOF'
+<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{label}</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>/usr/bin/hidutil</string>
+        <string>property</string>
+        <string>--set</string>
+        <string>{mapping}</string>
+    </array>
+    <key>RunAtLoad</key>
+    <true/>
+</dict>
+</plist>
+EOF
+/usr/bin/hidutil property --set '{mapping}' || true
+"""
+
+# The layouts were once installed as a bundle named by its identifier, which
+# macOS would keep listing next to the layouts of the bundle named by
+# `bundleName` that replaces it
+BUNDLE_PREINSTALL = """\
+#!/bin/sh
+rm -rf "$3/Library/Keyboard Layouts/"{old_bundle}
+exit 0
+"""
 
 
 class MacGenerator(PhysicalGenerator):
@@ -38,7 +128,7 @@ class MacGenerator(PhysicalGenerator):
     def supported_layouts(self):
         o = OrderedDict()
         for k, v in self._bundle.layouts.items():
-            if "mac" in v.modes or "desktop" in v.modes:
+            if has_modes(v, "mac"):
                 o[k] = v
         return o
 
@@ -54,10 +144,76 @@ class MacGenerator(PhysicalGenerator):
     def developer_account(self):
         return os.environ.get("DEVELOPER_ACCOUNT")
 
+    @property
+    def notary_settings(self):
+        """The notarization settings of the target, with the environment filling
+        in what it leaves out, or None if installers are not notarized"""
+        n = self.mac_target.notarization
+
+        def get(attr):
+            return getattr(n, attr, None) if n is not None else None
+
+        settings = {
+            "tool": get("tool") or "notarytool",
+            "keychain_profile": get("keychain_profile")
+            or os.environ.get("NOTARY_KEYCHAIN_PROFILE"),
+            "apple_id": get("apple_id") or self.developer_account,
+            "password_chain_item": get("password_chain_item")
+            or self.developer_password_chain_item,
+            "team_id": self.mac_target.team_id or os.environ.get("DEVELOPER_TEAM_ID"),
+            "staple": get("staple") is not False,
+            "timeout": get("timeout") or 30,
+        }
+        if (
+            n is None
+            and settings["keychain_profile"] is None
+            and settings["apple_id"] is None
+        ):
+            return None
+        return settings
+
+    def _notary_settings_error(self, notary):
+        """What is missing to notarize with `notary`, if anything"""
+        tool = notary["tool"]
+        if tool not in ("notarytool", "altool"):
+            return "Unknown notarization tool '%s'; use `notarytool` or `altool`." % tool
+        if tool == "notarytool":
+            # notarytool only takes a password on its command line, where
+            # other processes can see it, so only stored credentials are used
+            if notary["keychain_profile"] is not None:
+                return None
+            return (
+                "Notarizing with notarytool needs a keychainProfile (NOTARY_KEYCHAIN_PROFILE) "
+                "in `targets.mac` or the environment. Store one with `xcrun notarytool "
+                "store-credentials <name> --apple-id %s --team-id %s`, which asks for the "
+                "app-specific password."
+                % (notary["apple_id"] or "<appleId>", notary["team_id"] or "<teamId>")
+            )
+        missing = [
+            name
+            for name, key in (
+                ("appleId (DEVELOPER_ACCOUNT)", "apple_id"),
+                ("passwordChainItem (DEVELOPER_PASSWORD_CHAIN_ITEM)", "password_chain_item"),
+            )
+            if notary[key] is None
+        ]
+        if not missing:
+            return None
+        return "Notarizing with altool needs %s in `targets.mac` or the environment." % (
+            ", ".join(missing)
+        )
+
     def satisfies_requirements(self):
         if super().satisfies_requirements() is False:
             return False
 
+        if self.mac_target.bundle_name is not None:
+            try:
+                self._bundle_filename()
+            except Exception as e:
+                logger.error(str(e))
+                return False
+
         if shutil.which("pkgbuild") is None:
             logger.error("'pkgbuild' not found on PATH; are you running on macOS?")
             return False
@@ -66,10 +222,6 @@ class MacGenerator(PhysicalGenerator):
             logger.error("'productbuild' not found on PATH; are you running on macOS?")
             return False
 
-        if shutil.which("convert") is None:
-            logger.error("`convert` not found. Is imagemagick installed?")
-            return False
-
         if self.is_release:
             if self.sign_id is None:
                 logger.error("No signing identify found, release build not possible.")
@@ -78,12 +230,22 @@ class MacGenerator(PhysicalGenerator):
                 )
                 return False
 
-            if self.developer_account is None or self.developer_password_chain_item is None:
-                logger.error("No notarization developer account details found")
-                logger.error(
-                    "Set the DEVELOPER_ACCOUNT environment variable to the Apple developer account name, and DEVELOPER_PASSWORD_CHAIN_ITEM to the name of the keychain item with the password."
+            notary = self.notary_settings
+            if notary is None:
+                logger.warn(
+                    "No notarization settings found; the installer will not be notarized."
                 )
-                return False
+                logger.warn(
+                    "Add `notarization` to the mac target yaml, or set NOTARY_KEYCHAIN_PROFILE or DEVELOPER_ACCOUNT in the environment."
+                )
+            else:
+                error = self._notary_settings_error(notary)
+                if error is not None:
+                    logger.error(error)
+                    return False
+                if shutil.which("xcrun") is None:
+                    logger.error("'xcrun' not found on PATH; are the Xcode command line tools installed?")
+                    return False
 
         fail = False
         ids = []
@@ -122,6 +284,8 @@ class MacGenerator(PhysicalGenerator):
                 logger.error("[%s] Error while validating layout:\n%s" % (name, e))
                 raise e
                 return
+            warn_unsupported_reordering(name, layout, "mac")
+            warn_ignored_transform_partial(name, layout, "mac")
 
             logger.info("Generating '%s'…" % name)
             o[name] = self.generate_xml(name, layout)
@@ -145,7 +309,7 @@ class MacGenerator(PhysicalGenerator):
 
             logger.debug("%s.keylayout -> bundle" % fn)
             with open(os.path.join(res_path, "%s.keylayout" % fn), "w", encoding="utf-8") as f:
-                f.write(data)
+                f.write(self.keylayout_file(name, layout, data))
 
             self.write_icon(res_path, name, layout)
 
@@ -160,57 +324,22 @@ class MacGenerator(PhysicalGenerator):
         else:
             logger.info("Installer generated at '%s'." % pkg_path)
 
-    def generate_iconset(self, icon, output_fn):
-        cmd_tmpl = (
-            "convert -resize {d}x{d} -background transparent "
-            + "-gravity center -extent {d}x{d}"
-        )
-
-        files = (
-            ("icon_16x16", 16),
-            ("icon_16x16@2x", 32),
-            ("icon_32x32", 32),
-            ("icon_32x32@2x", 64),
-        )
-
-        iconset = tempfile.TemporaryDirectory(suffix=".iconset")
-
-        for name, dimen in files:
-            fn = "%s.png" % name
-            cmd = cmd_tmpl.format(d=dimen).split(" ") + [
-                icon,
-                os.path.join(iconset.name, fn),
-            ]
-            logger.info("Creating '%s.png' at size %dx%d" % (name, dimen, dimen))
-            run_process(cmd)
-
-        cmd = ["iconutil", "--convert", "icns", "--output", output_fn, iconset.name]
-        run_process(cmd)
-
-        iconset.cleanup()
-
     def layout_target(self, layout):
         if layout.targets is not None:
             return layout.targets.get("mac", {})
         return {}
 
     def write_icon(self, res_path, name, layout):
-        try:
-            for x in os.listdir(self.mac_resources):
-                if x.startswith("icon.%s." % name):
-                    icon = x
-                    break
-            else:
-                logger.warning("no icon for layout '%s'." % name)
-                return
-        except Exception:
-            logger.debug("No resources directory.")
+        """Copies the layout's `.icns` into the bundle, which kbdgen writes
+        into the output directory from the layout's `icon`, see
+        `to_macos_resources.rs`"""
+        icon = os.path.join(self.build_dir, "%s.icns" % name)
+        if not os.path.isfile(icon):
+            logger.warning("no icon for layout '%s'." % name)
             return
 
-        iconpath = os.path.join(self.mac_resources, icon)
-
         fn = os.path.join(res_path, "%s.icns" % self._layout_name(name, layout))
-        self.generate_iconset(iconpath, fn)
+        shutil.copyfile(icon, fn)
 
     def write_localisations(self, res_path, translations):
         for locale, o in translations.items():
@@ -224,21 +353,45 @@ class MacGenerator(PhysicalGenerator):
     def _layout_name(self, locale, layout):
         return INVERTED_ID_RE.sub("", locale)
 
-    def create_bundle(self, path):
+    def _bundle_filename(self):
+        filename = BUNDLE_FILENAME_RE.sub("", self.mac_target.bundle_name).strip()
+        if filename in ("", ".", ".."):
+            raise Exception(
+                "Target 'mac' has a 'bundleName' of %r, which leaves no file name "
+                "for the bundle. Please fix your project YAML file."
+                % self.mac_target.bundle_name
+            )
+        return filename
+
+    @property
+    def bundle_id(self):
         # Bundle ID must contain be in format *.keyboardlayout.<name>
         # Failure to do so and the bundle will not be detected as a keyboard bundle
-        bundle_id = "%s.keyboardlayout.%s" % (
+        return "%s.keyboardlayout.%s" % (
             self.mac_target.package_id,
             self._bundle.name,
         )
+
+    def write_bundle_preinstall(self, scripts):
+        """Writes the `preinstall` script that removes the bundle of older
+        installers, which was named `<bundle id>.bundle`"""
+        os.makedirs(scripts, exist_ok=True)
+        path = os.path.join(scripts, "preinstall")
+        with open(path, "w") as f:
+            f.write(
+                BUNDLE_PREINSTALL.format(
+                    old_bundle=shlex.quote("%s.bundle" % self.bundle_id)
+                )
+            )
+        os.chmod(path, 0o755)
+
+    def create_bundle(self, path):
+        bundle_id = self.bundle_id
         logger.debug(
             "target.package_id: %r, _bundle.name: %r"
             % (self.mac_target.package_id, self._bundle.name)
         )
 
-        bundle_path = os.path.join(path, "%s.bundle" % bundle_id)
-        if os.path.exists(bundle_path):
-            shutil.rmtree(bundle_path)
         bundle_name = self.mac_target.bundle_name
 
         if bundle_name is None:
@@ -247,6 +400,13 @@ class MacGenerator(PhysicalGenerator):
                 "Please fix your project YAML file."
             )
 
+        # Every layout goes into the one bundle, which is what users see in
+        # /Library/Keyboard Layouts, so it is named for people rather than
+        # by its identifier.
+        bundle_path = os.path.join(path, "%s.bundle" % self._bundle_filename())
+        if os.path.exists(bundle_path):
+            shutil.rmtree(bundle_path)
+
         os.makedirs(os.path.join(bundle_path, "Contents", "Resources"), exist_ok=True)
 
         target_tmpl = indent(
@@ -266,7 +426,11 @@ class MacGenerator(PhysicalGenerator):
         targets = []
         for name, layout in self.supported_layouts.items():
             layout_name = self._layout_name(name, layout)
-            targets.append(target_tmpl % (layout_name, bundle_id, layout_name, name))
+            # The language Input Sources lists the layout under
+            language = self.layout_target(layout).get("language", name)
+            targets.append(
+                target_tmpl % (layout_name, bundle_id, layout_name, language)
+            )
 
         with open(os.path.join(bundle_path, "Contents", "Info.plist"), "w", encoding="utf-8") as f:
             f.write(
@@ -300,7 +464,7 @@ class MacGenerator(PhysicalGenerator):
 
         return bundle_path
 
-    def generate_distribution_xml(self, component_fn, working_dir):
+    def generate_distribution_xml(self, component_fn, modifier_fn, working_dir):
         dist_fn = os.path.join(working_dir.name, "distribution.xml")
         bundle_name = self.mac_target.bundle_name
         # Root "bundle id" is used as a unique key only in the pkg xml
@@ -309,7 +473,8 @@ class MacGenerator(PhysicalGenerator):
         root = etree.fromstring("""<installer-gui-script minSpecVersion="2" />""")
 
         SubElement(root, "title").text = bundle_name
-        SubElement(root, "options", customize="never", rootVolumeOnly="true")
+        customize = "never" if modifier_fn is None else "allow"
+        SubElement(root, "options", customize=customize, rootVolumeOnly="true")
 
         choices_outline = SubElement(root, "choices-outline")
         line = SubElement(choices_outline, "line", choice="default")
@@ -328,6 +493,25 @@ class MacGenerator(PhysicalGenerator):
             onConclusion="RequireRestart",
         ).text = os.path.basename(component_fn)
 
+        if modifier_fn is not None:
+            modifier_id = "%s.modifiers" % bundle_id
+            SubElement(line, "line", choice=modifier_id)
+            choice = SubElement(
+                root,
+                "choice",
+                id=modifier_id,
+                title="Remap modifier keys",
+                description="Gives modifier keys, like Caps Lock, the roles the "
+                "layouts need. This applies to every keyboard layout, not only "
+                "these. Run \"/Library/Application Support/%s/remove-modifier-remaps\" "
+                "with sudo to undo it." % modifier_id,
+                start_selected="false",
+            )
+            SubElement(choice, "pkg-ref", id=modifier_id)
+            SubElement(
+                root, "pkg-ref", id=modifier_id, version="0", auth="root"
+            ).text = os.path.basename(modifier_fn)
+
         bg = self.resource_with_prefix("background")
         if bg is not None:
             SubElement(root, "background", file=bg, alignment="bottomleft")
@@ -359,14 +543,84 @@ class MacGenerator(PhysicalGenerator):
                 return os.path.join(self.mac_resources, fn)
         return None
 
+    def _modifier_remaps(self):
+        """The modifier remaps of all layouts, which macOS can only apply to
+        every keyboard layout at once. The first layout to remap a key wins."""
+        remaps = {}
+        for name, layout in self.supported_layouts.items():
+            for key, role in DesktopLayoutView(layout, "mac").modifier_remaps().items():
+                if key not in MAC_MODIFIER_USAGES or role not in MAC_ROLE_USAGES:
+                    report_loss(
+                        name,
+                        "mac",
+                        "modifier remap of `%s`" % key,
+                        "macOS cannot make it %s" % role,
+                    )
+                elif key not in remaps:
+                    remaps[key] = (role, name)
+                elif remaps[key][0] != role:
+                    report_loss(
+                        name,
+                        "mac",
+                        "modifier remap of `%s`" % key,
+                        "remaps apply to every layout, and `%s` remaps it to %s"
+                        % (remaps[key][1], remaps[key][0]),
+                    )
+        return {key: role for key, (role, _) in remaps.items()}
+
+    def create_modifier_pkg(self, version, working_dir):
+        """Builds the package without a payload whose script applies the
+        modifier remaps, returning its path, or `None` if there are none"""
+        remaps = self._modifier_remaps()
+        if not remaps:
+            return None
+
+        mapping = json.dumps(
+            {
+                "UserKeyMapping": [
+                    {
+                        "HIDKeyboardModifierMappingSrc": MAC_MODIFIER_USAGES[key],
+                        "HIDKeyboardModifierMappingDst": MAC_ROLE_USAGES[role],
+                    }
+                    for key, role in sorted(remaps.items())
+                ]
+            }
+        )
+        label = "%s.modifiers" % self.mac_target.package_id
+        scripts = os.path.join(working_dir.name, "scripts")
+        os.makedirs(scripts, exist_ok=True)
+        path = os.path.join(scripts, "postinstall")
+        with open(path, "w") as f:
+            f.write(MODIFIER_POSTINSTALL.format(label=label, mapping=mapping))
+        os.chmod(path, 0o755)
+
+        pkg_path = os.path.join(working_dir.name, "%s.pkg" % label)
+        cmd = [
+            "pkgbuild",
+            "--nopayload",
+            "--scripts",
+            scripts,
+            "--identifier",
+            label,
+            "--version",
+            version,
+            pkg_path,
+        ]
+        run_process(cmd, self.build_dir)
+        return pkg_path
+
     def create_component_pkg(self, bundle, version, working_dir):
         pkg_name = "%s.pkg" % self.mac_target.package_id
         pkg_path = os.path.join(working_dir.name, pkg_name)
+        scripts = os.path.join(working_dir.name, "bundle-scripts")
+        self.write_bundle_preinstall(scripts)
 
         cmd = [
             "pkgbuild",
             "--component",
             os.path.join(working_dir.name, bundle),
+            "--scripts",
+            scripts,
             "--ownership",
             "recommended",
             "--install-location",
@@ -389,12 +643,15 @@ class MacGenerator(PhysicalGenerator):
             version = "0.0.0"
 
         component_pkg_path = self.create_component_pkg(bundle, version, working_dir)
+        modifier_pkg_path = self.create_modifier_pkg(version, working_dir)
 
         resources = self.mac_resources
         if resources is not None:
             resources = self._bundle.relpath(resources)
 
-        dist_xml_path = self.generate_distribution_xml(component_pkg_path, working_dir)
+        dist_xml_path = self.generate_distribution_xml(
+            component_pkg_path, modifier_pkg_path, working_dir
+        )
 
         bundle_name = "keyboard-%s" % self._bundle.name
         pkg_name = "%s_%s.unsigned.pkg" % (bundle_name, version)
@@ -439,15 +696,146 @@ class MacGenerator(PhysicalGenerator):
         out, err = run_process(cmd, self.build_dir)
         logger.info(out.decode().strip())
 
-        logger.info("Notarizing installer…")
-        cmd = ["xcnotary", "notarize", signed_path, "--override-path-type", "pkg",
-               "-d", self.developer_account, "-k", self.developer_password_chain_item]
-        out, err = run_process(cmd, self.build_dir, retries=3)
-        logger.info(out.decode().strip())
+        notary = self.notary_settings
+        if notary is not None:
+            self.notarize_installer(signed_path, notary)
+
         logger.info(
             "Installer generated at '%s'." % os.path.join(self.build_dir, signed_path)
         )
 
+    def _xcrun_json(self, args, what):
+        """Runs `xcrun` with `args` and returns its JSON output, or None after
+        logging why it failed"""
+        process = subprocess.run(
+            ["xcrun"] + args,
+            cwd=self.build_dir,
+            stdout=subprocess.PIPE,
+            stderr=subprocess.PIPE,
+        )
+        try:
+            data = json.loads(process.stdout.decode())
+        except ValueError:
+            data = None
+        if process.returncode != 0 or data is None:
+            logger.error("Could not %s (exit code %s):" % (what, process.returncode))
+            output = process.stderr.decode().strip() or process.stdout.decode().strip()
+            logger.error(output)
+            return None
+        return data
+
+    def _notary_credentials(self, notary):
+        if notary["tool"] == "altool":
+            return [
+                "--username", notary["apple_id"],
+                "--password", "@keychain:%s" % notary["password_chain_item"],
+            ] + (["--asc-provider", notary["team_id"]] if notary["team_id"] else [])
+        return ["--keychain-profile", notary["keychain_profile"]]
+
+    def _submit_for_notarization(self, pkg_path, notary, credentials):
+        """Uploads the installer, returning the id of the submission"""
+        if notary["tool"] == "altool":
+            args = [
+                "altool", "--notarize-app",
+                "--primary-bundle-id", self.mac_target.package_id,
+                "--file", pkg_path,
+                "--output-format", "json",
+            ]
+            data = self._xcrun_json(args + credentials, "upload the installer")
+            if data is None:
+                return None
+            return data.get("notarization-upload", {}).get("RequestUUID")
+
+        args = ["notarytool", "submit", pkg_path, "--output-format", "json"]
+        data = self._xcrun_json(args + credentials, "upload the installer")
+        if data is None:
+            return None
+        return data.get("id")
+
+    def _notarization_status(self, request_id, notary, credentials):
+        """The status of the submission as `accepted`, `in progress` or
+        `invalid`, and where its log is for altool, or None if it could not
+        be asked for"""
+        if notary["tool"] == "altool":
+            args = ["altool", "--notarization-info", request_id, "--output-format", "json"]
+            data = self._xcrun_json(args + credentials, "get the notarization status")
+            if data is None:
+                return None, None
+            info = data.get("notarization-info", {})
+            status = info.get("Status", "in progress").lower()
+            status = "accepted" if status == "success" else status
+            return status, info.get("LogFileURL")
+
+        args = ["notarytool", "info", request_id, "--output-format", "json"]
+        data = self._xcrun_json(args + credentials, "get the notarization status")
+        if data is None:
+            return None, None
+        return data.get("status", "In Progress").lower(), None
+
+    def _notarization_log(self, request_id, notary, credentials, log_url):
+        if notary["tool"] == "altool":
+            if log_url is None:
+                return None
+            try:
+                with urllib.request.urlopen(log_url) as f:
+                    return json.loads(f.read().decode())
+            except Exception as e:
+                logger.error("Could not download the notarization log: %s" % e)
+                return None
+
+        args = ["notarytool", "log", request_id]
+        return self._xcrun_json(args + credentials, "get the notarization log")
+
+    def notarize_installer(self, pkg_path, notary):
+        """Submits the installer to Apple's notary service, waits for the
+        verdict and staples the ticket to it, exiting with the issues Apple
+        found if it is rejected"""
+        logger.info("Notarizing installer with %s…" % notary["tool"])
+        credentials = self._notary_credentials(notary)
+
+        request_id = self._submit_for_notarization(pkg_path, notary, credentials)
+        if request_id is None:
+            logger.error("The installer was not submitted for notarization.")
+            sys.exit(1)
+        logger.info("Submitted for notarization as %s." % request_id)
+
+        deadline = time.monotonic() + notary["timeout"] * 60
+        while True:
+            status, log_url = self._notarization_status(request_id, notary, credentials)
+            if status is not None and status != "in progress":
+                break
+            if time.monotonic() > deadline:
+                logger.error(
+                    "Notarization of %s did not finish within %s minutes; check on it "
+                    "with `xcrun %s` later." % (request_id, notary["timeout"], notary["tool"])
+                )
+                sys.exit(1)
+            logger.debug("Notarization in progress, asking again in %ss…" % NOTARY_POLL_INTERVAL)
+            time.sleep(NOTARY_POLL_INTERVAL)
+
+        if status != "accepted":
+            logger.error("Notarization of %s failed with status '%s'." % (request_id, status))
+            log = self._notarization_log(request_id, notary, credentials, log_url)
+            if log is not None:
+                if log.get("statusSummary"):
+                    logger.error(log["statusSummary"])
+                for issue in log.get("issues") or []:
+                    logger.error(
+                        "%s: %s: %s"
+                        % (
+                            issue.get("severity", "error"),
+                            issue.get("path", pkg_path),
+                            issue.get("message", ""),
+                        )
+                    )
+            sys.exit(1)
+        logger.info("Notarization of %s accepted." % request_id)
+
+        if notary["staple"]:
+            logger.info("Stapling notarization ticket…")
+            run_process(["xcrun", "stapler", "staple", pkg_path], self.build_dir, retries=3)
+            run_process(["xcrun", "stapler", "validate", pkg_path], self.build_dir)
+
     def _layout_id(self, name) -> str:
         return str(-min(max(binascii.crc_hqx(name.encode("utf-8"), 0) // 2, 1), 32768,))
 
@@ -471,11 +859,25 @@ class MacGenerator(PhysicalGenerator):
             (92, "9"),
         )
 
+    def keylayout_file(self, name, layout, data):
+        """The `.keylayout` file of the XML of `generate_xml`, with the stamp
+        and license notice as comments"""
+        # Comments cannot come before the XML declaration
+        decl, rest = data.split("\n", 1)
+        lines = stamp_lines(name) + license_notice(self._bundle.project, layout)
+        comments = "".join(
+            "<!-- %s -->\n" % line.replace("--", "- -") for line in lines
+        )
+        return decl + "\n" + comments + rest
+
     def generate_xml(self, name, layout):
         name = self._layout_name(name, layout)
         out = OSXKeyLayout(name, self._layout_id(name))
 
         layout_view = DesktopLayoutView(layout, "mac")
+        # Dead key chains like `^´` become a state reached from the state of
+        # `^` by typing `´`
+        transforms = nested_transforms(layout)
 
         # Create list to ignore false negatives for different targets
         dead_keys = layout.dead_keys or {}
@@ -486,12 +888,22 @@ class MacGenerator(PhysicalGenerator):
             )
         )
 
-        dead_keys = set(itertools.chain.from_iterable(layout_view.dead_keys().values()))
+        mode_dead_keys = layout_view.dead_keys()
+        dead_keys = set(itertools.chain.from_iterable(mode_dead_keys.values()))
         action_keys = set()
-        for x in DictWalker(layout.transforms or {}):
+        for x in DictWalker(transforms):
             for i in x[0] + (x[1],):
                 action_keys.add(str(i))
 
+        def terminator(key):
+            """The terminator of a dead key's state, which macOS also shows
+            while the dead key is pending, so its `preview` if it has one and
+            no `deadKeyTerminators` entry"""
+            preview = dead_key_preview(layout, key)
+            if preview != key and key not in (layout.dead_key_terminators or {}):
+                return preview
+            return dead_key_terminator(layout, key)
+
         # Naively add all keys
         for mode_name in OSXKeyLayout.modes:
             logger.trace("BEGINNING MODE: %r" % mode_name)
@@ -510,7 +922,7 @@ class MacGenerator(PhysicalGenerator):
 
             logger.trace(
                 "Dead keys - mode:%r keys:%r"
-                % (mode_name, (layout.dead_keys or {}).get(mode_name, []))
+                % (mode_name, mode_dead_keys.get(mode_name, []))
             )
 
             for iso, key in mode.items():
@@ -523,23 +935,22 @@ class MacGenerator(PhysicalGenerator):
                     out.set_key(mode_name, key, key_id)
                     continue
 
-                dead_keys = layout.dead_keys or {}
-                if key in dead_keys.get(mode_name, []):
+                if key in mode_dead_keys.get(mode_name, []):
                     logger.trace("Dead key found - mode:%r key:%r" % (mode_name, key))
 
-                    transforms = layout.transforms or {}
                     if key in transforms:
                         logger.trace(
                             "Set deadkey - mode:%r key:%r id:%r"
                             % (mode_name, key, key_id)
                         )
-                        out.set_deadkey(
-                            mode_name, key, key_id, transforms[key].get(" ", key)
-                        )
+                        out.set_deadkey(mode_name, key, key_id, terminator(key))
                     else:
-                        logger.warning(
-                            "Dead key '%s' not found in mode '%s'; "
-                            "build will continue, but please fix." % (key, mode_name)
+                        report_loss(
+                            name,
+                            "mac",
+                            "dead key `%s` in `%s`" % (key, mode_name),
+                            "it has no transforms, so it will be typed as it is",
+                            altered=True,
                         )
                         out.set_key(mode_name, key, key_id)
                 else:
@@ -559,7 +970,7 @@ class MacGenerator(PhysicalGenerator):
                 sp = " "
 
             out.set_key(mode_name, sp, "49")
-            if not self.disable_transforms and len(layout.transforms or {}) > 0:
+            if not self.disable_transforms and len(transforms) > 0:
                 out.set_transform_key(mode_name, sp, "49")
 
             # Add hardcoded keyboard bits
@@ -571,10 +982,28 @@ class MacGenerator(PhysicalGenerator):
             for key_id, key in self._numpad(decimal):
                 out.set_key(mode_name, str(key), str(key_id))
 
+        def chain_terminator(chain):
+            """What the dead keys of `chain` type when followed by a key that
+            does not continue it"""
+            node = transforms
+            for key in chain:
+                node = node.get(key, {})
+            if isinstance(node.get(" "), str):
+                return node[" "]
+            return "".join(terminator(key) for key in chain)
+
         class TransformWalker(DictWalker):
             def on_branch(self, base, branch):
                 logger.debug("BRANCH: %r" % branch)
-                if branch not in dead_keys or not out.actions.has(branch):
+                if base != () and not out.actions.has(branch):
+                    logger.error(
+                        "Dead key chain %r not supported; is a deadkey missing?"
+                        % "".join(base + (branch,))
+                    )
+                    return False
+                if base == () and (
+                    branch not in dead_keys or not out.actions.has(branch)
+                ):
                     if branch in all_dead_keys:
                         logger.debug(
                             "Transform %r not supported by current target." % branch
@@ -598,7 +1027,9 @@ class MacGenerator(PhysicalGenerator):
                     when_state = out.states.get(
                         "".join(base)
                     )  # "State %s" % "".join(base)
-                    next_state = "%s%s" % (when_state, branch)
+                    chain = base + (branch,)
+                    next_state = out.states.get("".join(chain))
+                    out.set_chain_terminator(next_state, chain_terminator(chain))
 
                 logger.trace(
                     "Branch: action:%r when:%r next:%r"
@@ -637,6 +1068,6 @@ class MacGenerator(PhysicalGenerator):
                     )
 
         if not self.disable_transforms:
-            TransformWalker(layout.transforms or {})()
+            TransformWalker(transforms)()
 
         return bytes(out).decode("utf-8")
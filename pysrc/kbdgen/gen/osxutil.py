@@ -188,11 +188,11 @@ class Pbxproj:
 
         return None
 
-    def create_plist_string_variant(self, variants):
+    def create_plist_string_variant(self, variants, name="InfoPlist.strings"):
         o = {
             "isa": "PBXVariantGroup",
             "children": variants,
-            "name": "InfoPlist.strings",
+            "name": name,
             "sourceTree": "<group>",
         }
 
@@ -216,6 +216,18 @@ class Pbxproj:
         phase["files"].append(ref)
         return var_ref
 
+    def add_variant_group_to_build_phase(self, variants, name, target_name):
+        """Adds the localisations `variants` of the file `name` to the
+        resources of the target, returning the key of their variant group"""
+        var_key = Pbxproj.gen_key()
+        self.objects[var_key] = self.create_plist_string_variant(variants, name)
+
+        key = Pbxproj.gen_key()
+        self.objects[key] = {"isa": "PBXBuildFile", "fileRef": var_key}
+        self.find_resource_build_phase(target_name)["files"].append(key)
+
+        return var_key
+
     def find_variant_group(self, target):
         for o in self.objects.values():
             if (
@@ -324,6 +336,34 @@ class Pbxproj:
         self.objects[k] = o
         return k
 
+    def create_font_file(self, font_path):
+        logger.trace("create_font_file: %r" % font_path)
+        o = {
+            "isa": "PBXFileReference",
+            "lastKnownFileType": "file",
+            "name": pathlib.Path(font_path).name,
+            "path": font_path,
+            "sourceTree": "<group>",
+        }
+
+        k = Pbxproj.gen_key()
+        self.objects[k] = o
+        return k
+
+    def create_json_file(self, json_path):
+        logger.trace("create_json_file: %r" % json_path)
+        o = {
+            "isa": "PBXFileReference",
+            "lastKnownFileType": "text.json",
+            "name": pathlib.Path(json_path).name,
+            "path": json_path,
+            "sourceTree": "<group>",
+        }
+
+        k = Pbxproj.gen_key()
+        self.objects[k] = o
+        return k
+
     def create_plist_string_file(self, locale, name="InfoPlist.strings"):
         return self.create_file_reference("text.plist.strings", locale, name)
 
@@ -591,6 +631,17 @@ class Pbxproj:
 
         o["files"].append(nref)
 
+    def add_resource_ref_to_build_phase(self, ref, target):
+        logger.trace("add_resource_ref_to_build_phase: %r %r" % (ref, target))
+        phase = self.find_resource_build_phase(target)
+        if phase is None:
+            raise Exception("No src found.")
+
+        nref = Pbxproj.gen_key()
+        self.objects[nref] = {"isa": "PBXBuildFile", "fileRef": ref}
+
+        phase["files"].append(nref)
+
     def remove_target(self, target):
         logger.trace("remove_target: %r" % target)
         for ref, o in self.objects.items():
@@ -938,6 +989,11 @@ class OSXKeyLayout:
         self._set_default_action(key)
         self._set_terminator(pressed_id, output)
 
+    def set_chain_terminator(self, state, output):
+        """output is the output when the dead key chain in state is followed
+        by an invalid"""
+        self._set_terminator(state, output)
+
     def set_transform_key(self, mode, key, key_id):
         action_id = self.actions.get(key)  # "Key %s" % key
 
@@ -8,25 +8,105 @@ from textwrap import dedent
 from collections import OrderedDict
 
 from ..base import get_logger
-from .base import Generator, mode_dict, ISO_KEYS, get_bin_resource
+from .base import (
+    Generator,
+    mode_dict,
+    has_modes,
+    ISO_KEYS,
+    get_bin_resource,
+    display_name,
+    display_label,
+    dead_key_preview,
+    DesktopLayoutView,
+    layout_file_path,
+    render_theme,
+)
 from ..cldr import decode_u
 
 logger = get_logger(__name__)
 
 NS = "{http://www.w3.org/2000/svg}"
 
+# The modes of each group of labels on a key, by the name of their colors
+GROUPS = (
+    ("key-group-1", "default", "Default"),
+    ("key-group-2", "alt", "AltGr/Option"),
+    ("key-group-3", "caps", "Caps Lock (Mode Switch)"),
+    ("key-group-4", "capsAlt", "Caps Lock + AltGr/Option"),
+)
+
+
+# How the legend shows dead keys, by `deadKeyMarker`
+DEAD_KEY_STYLES = {
+    "bold": "; font-weight: bold",
+    "underline": "; text-decoration: underline",
+    "none": "",
+}
+
+
+def dead_key_color(theme, name):
+    """The color of dead keys in the group of modes `name`"""
+    if theme["deadKeyMarker"] == "bold" or theme["colors"]["deadKey"]:
+        return theme["deadKeyColors"][name]
+    return theme["colors"][name]
+
+
+def theme_css(theme):
+    """CSS for the `render` theme, overriding the template's own"""
+    colors = theme["colors"]
+    rules = [
+        "g { font-family: %s; }" % theme["fontFamily"],
+        ".key rect { fill: %s; stroke: %s; }" % (colors["key"], colors["border"]),
+        ".key-function rect { fill: %s; }" % colors["functionKey"],
+        ".iso { fill: %s; }" % colors["isoLabel"],
+    ]
+    marker = theme["deadKeyMarker"]
+    for cls, name, _ in GROUPS:
+        color = colors[name]
+        dead = dead_key_color(theme, name)
+        rules.append(".%s { fill: %s; stroke: %s; }" % (cls, color, color))
+        rules.append(".%s .key-dead { fill: %s; stroke: %s; }" % (cls, dead, dead))
+    if marker == "underline":
+        rules.append(".key-dead { font-weight: normal; text-decoration: underline; }")
+    elif marker == "none":
+        rules.append(".key-dead { font-weight: normal; }")
+    if theme["keyLabels"] == "outputs":
+        rules.append(".iso { display: none; }")
+    elif theme["keyLabels"] == "iso":
+        rules.append(".key-group { display: none; }")
+        rules.append(".iso { font-size: 14pt; }")
+    return "\n".join("        " + rule for rule in rules) + "\n"
+
+
+def apply_theme(root, theme):
+    """Themes the keyboard template `root` in place"""
+    for style in root.iter(NS + "style"):
+        style.text = (style.text or "") + theme_css(theme)
+        break
+    background = theme["colors"]["background"]
+    if background is not None:
+        rect = Element(
+            NS + "rect", **{"width": "100%", "height": "100%", "fill": background}
+        )
+        root.insert(0, rect)
+
+
+def print_order(names, order):
+    """`names` in the `layoutOrder` of the theme, then by name"""
+    for name in order:
+        if name not in names:
+            logger.warning("layoutOrder has '%s', which is not a layout" % name)
+    position = {name: i for i, name in enumerate(order)}
+    return sorted(names, key=lambda name: (position.get(name, len(order)), name))
+
 
 class SVGGenerator(Generator):
     @property
     def supported_layouts(self):
         o = OrderedDict()
         for k, v in self._bundle.layouts.items():
-            if (
-                "desktop" in v.modes
-                or "mac" in v.modes
-                or "win" in v.modes
-                or "chrome" in v.modes
-            ):
+            # The keyboards are drawn from the `win` modes
+            if has_modes(v, "win"):
                 o[k] = v
         return o
 
@@ -34,23 +114,25 @@ class SVGGenerator(Generator):
         with get_bin_resource("keyboard-iso.svg", text=True) as f:
             tree = etree.parse(f)
         root = tree.getroot()
+        theme = render_theme(self._bundle.project)
+        apply_theme(root, theme)
 
-        files = []
+        files = OrderedDict()
 
         for name, layout in self.supported_layouts.items():
-            files.append(
-                (
-                    "%s.svg" % name,
-                    layout.display_names.get(name, name),
-                    self.generate_svg(name, layout, copy.deepcopy(root)),
-                )
+            files[name] = (
+                layout_file_path("svg", name, "svg", "%s.svg" % name),
+                display_name(layout, name, self._bundle.project) or name,
+                self.generate_svg(name, layout, copy.deepcopy(root)),
             )
 
         out_dir = os.path.abspath(base)
         os.makedirs(out_dir, exist_ok=True)
 
-        for fn, _, data in files:
-            with open(os.path.join(out_dir, fn), "w", encoding="utf-8") as f:
+        for fn, _, data in files.values():
+            path = os.path.join(out_dir, fn)
+            os.makedirs(os.path.dirname(path), exist_ok=True)
+            with open(path, "w", encoding="utf-8") as f:
                 f.write(data)
 
         # Get English name, or fallback to internal name
@@ -65,6 +147,7 @@ class SVGGenerator(Generator):
             <head>
                 <meta charset='utf-8'>
                 <title>Layout(s) for the %s</title>
+                <style>body { font-family: %s; }</style>
             </head>
             <body>
                 <p><strong>Legend:</strong></p>
@@ -77,37 +160,32 @@ class SVGGenerator(Generator):
                     </tr>
                   </thead>
                   <tbody>
-                    <tr>
-                        <th>Default</th>
-                        <td>black</td>
-                        <td>green</td>
-                    </tr>
-                    <tr>
-                        <th>AltGr/Option</th>
-                        <td>red</td>
-                        <td>orange</td>
-                    </tr>
-                    <tr>
-                        <th>Caps Lock (Mode Switch)</th>
-                        <td>blue</td>
-                        <td>pink</td>
-                    </tr>
-                    <tr>
-                        <th>Caps Lock + AltGr/Option:</th>
-                        <td>purple</td>
-                        <td>green</td>
-                    </tr>
-                  </tbody>
-                </table>
             """
                 )
-                % kbd_name
+                % (kbd_name, theme["fontFamily"])
             )
+            marker = theme["deadKeyMarker"]
+            for _, color_name, mode in GROUPS:
+                color = theme["colors"][color_name]
+                dead = dead_key_color(theme, color_name)
+                f.write("        <tr>\n")
+                f.write("            <th>%s</th>\n" % mode)
+                f.write('            <td style="color: %s">%s</td>\n' % (color, color))
+                f.write(
+                    '            <td style="color: %s%s">%s</td>\n'
+                    % (dead, DEAD_KEY_STYLES[marker], dead)
+                )
+                f.write("        </tr>\n")
+            f.write("      </tbody>\n    </table>\n")
 
-            for fn, name, _ in sorted(files):
+            for layout_name in print_order(list(files), theme["layoutOrder"]):
+                fn, name, _ = files[layout_name]
                 f.write("    <h2>%s</h2>\n" % name)
                 f.write('    <object type="image/svg+xml"')
-                f.write('data="%s" width="960" height="320"></object><br>\n' % fn)
+                f.write(
+                    'data="%s" width="960" height="320"></object><br>\n'
+                    % fn.replace(os.sep, "/")
+                )
                 # f.write('    <iframe style="border:none" ')
                 # f.write('src="%s" width="960" height="320"></iframe><br>\n' % fn)
 
@@ -132,20 +210,28 @@ class SVGGenerator(Generator):
                 )
             )
 
-    def _make_key_group(self, primary, secondary, cls=None):
+    def _key_label(self, layout, key, dead):
+        """What to draw for a key, the `preview` of dead keys that have one"""
+        if dead and dead_key_preview(layout, key) != key:
+            return dead_key_preview(layout, key)
+        return display_label(layout, key) or key
+
+    def _make_key_group(
+        self,
+        layout,
+        primary,
+        secondary,
+        cls=None,
+        primary_dead=False,
+        secondary_dead=False,
+    ):
         if cls is None:
             cls = ""
 
-        trans = {
-            "\u00A0": "NBSP",
-            "\u200B": "ZWSP",
-            "\u200C": "ZWNJ",
-            "\u200D": "ZWJ",
-            "\u2060": "WJ",
-        }
-
-        primary = trans.get(primary, primary)
-        secondary = trans.get(secondary, secondary)
+        if primary is not None:
+            primary = self._key_label(layout, primary, primary_dead)
+        if secondary is not None:
+            secondary = self._key_label(layout, secondary, secondary_dead)
 
         g = Element(NS + "g", **{"class": ("key-group %s" % cls).strip()})
         p = SubElement(
@@ -182,42 +268,42 @@ class SVGGenerator(Generator):
         alt_caps = mode_dict(locale, layout, "caps+alt", "win")
         alt_caps_shift = mode_dict(locale, layout, "caps+alt+shift", "win")
 
+        dead_keys = DesktopLayoutView(layout, "win").dead_keys()
+
         for k in itertools.chain(ISO_KEYS, ("A03",)):
             logger.trace("%s" % k)
             groups = []
 
             dk = decode_u(default.get(k, "")) or None
-            dk_dead = dk is not None and default[k] in layout.dead_keys.get(
-                "default", {}
-            )
+            dk_dead = dk is not None and default[k] in dead_keys.get("default", [])
 
             sk = decode_u(shift.get(k, "")) or None
-            sk_dead = sk is not None and shift[k] in layout.dead_keys.get("shift", {})
+            sk_dead = sk is not None and shift[k] in dead_keys.get("shift", [])
 
             ack = decode_u(alt_caps.get(k, "")) or None
-            ack_dead = ack is not None and alt_caps[k] in layout.dead_keys.get(
-                "caps+alt", {}
+            ack_dead = ack is not None and alt_caps[k] in dead_keys.get(
+                "caps+alt", []
             )
 
             acsk = decode_u(alt_caps_shift.get(k, "")) or None
-            acsk_dead = acsk is not None and alt_caps_shift[k] in layout.dead_keys.get(
-                "caps+alt+shift", {}
+            acsk_dead = acsk is not None and alt_caps_shift[k] in dead_keys.get(
+                "caps+alt+shift", []
             )
 
             ak = decode_u(alts.get(k, "")) or None
-            ak_dead = ak is not None and alts[k] in layout.dead_keys.get("alt", {})
+            ak_dead = ak is not None and alts[k] in dead_keys.get("alt", [])
 
             ask = decode_u(alts_shift.get(k, "")) or None
-            ask_dead = ask is not None and alts_shift[k] in layout.dead_keys.get(
-                "alt+shift", {}
+            ask_dead = ask is not None and alts_shift[k] in dead_keys.get(
+                "alt+shift", []
             )
 
             ck = decode_u(caps.get(k, "")) or None
-            ck_dead = ck is not None and caps[k] in layout.dead_keys.get("caps", {})
+            ck_dead = ck is not None and caps[k] in dead_keys.get("caps", [])
 
             csk = decode_u(caps_shift.get(k, "")) or None
-            csk_dead = csk is not None and caps_shift[k] in layout.dead_keys.get(
-                "caps+shift", {}
+            csk_dead = csk is not None and caps_shift[k] in dead_keys.get(
+                "caps+shift", []
             )
 
             for g in root.iter():
@@ -230,7 +316,9 @@ class SVGGenerator(Generator):
             logger.trace("Element: %r %r %r" % (g, g.tag, g.attrib))
 
             if True:  # has_group1:
-                group1, p1, s1 = self._make_key_group(dk, sk, "key-group-1")
+                group1, p1, s1 = self._make_key_group(
+                    layout, dk, sk, "key-group-1", dk_dead, sk_dead
+                )
                 if dk_dead:
                     p1.attrib["class"] += " key-dead"
                 if sk_dead:
@@ -240,7 +328,9 @@ class SVGGenerator(Generator):
                 groups.append(group1)
 
             if True:  # has_group2:
-                group2, p2, s2 = self._make_key_group(ak, ask, "key-group-2")
+                group2, p2, s2 = self._make_key_group(
+                    layout, ak, ask, "key-group-2", ak_dead, ask_dead
+                )
                 if ak_dead:
                     p2.attrib["class"] += " key-dead"
                 if ask_dead:
@@ -250,7 +340,9 @@ class SVGGenerator(Generator):
                 groups.append(group2)
 
             if True:  # has_group3:
-                group3, p3, s3 = self._make_key_group(ck, csk, "key-group-3")
+                group3, p3, s3 = self._make_key_group(
+                    layout, ck, csk, "key-group-3", ck_dead, csk_dead
+                )
                 if ck_dead:
                     p3.attrib["class"] += " key-dead"
                 if csk_dead:
@@ -260,7 +352,9 @@ class SVGGenerator(Generator):
                 groups.append(group3)
 
             if True:  # has_group4:
-                group4, p4, s4 = self._make_key_group(ack, acsk, "key-group-4")
+                group4, p4, s4 = self._make_key_group(
+                    layout, ack, acsk, "key-group-4", ack_dead, acsk_dead
+                )
                 if ack_dead:
                     p4.attrib["class"] += " key-dead"
                 if acsk_dead:
@@ -1,3 +1,4 @@
+import copy
 import io
 import os
 import os.path
@@ -15,6 +16,7 @@ import language_tags
 from collections import OrderedDict
 from distutils.dir_util import copy_tree
 from textwrap import dedent
+from xml.sax.saxutils import quoteattr, escape
 
 from ..base import get_logger
 from .base import (
@@ -23,7 +25,21 @@ from .base import (
     run_process,
     mode_iter,
     DesktopLayoutView,
+    has_modes,
     get_bin_resource,
+    dead_key_chain,
+    dead_key_terminator,
+    report_loss,
+    symmetric_transforms,
+    warn_ignored_transform_partial,
+    warn_unsupported_dead_key_chains,
+    warn_unsupported_reordering,
+    stamp_comment,
+    stamp_lines,
+    license_comment,
+    license_notice,
+    display_name,
+    layout_file_path,
 )
 from ..cldr import decode_u
 
@@ -164,6 +180,203 @@ WIN_KEYMAP = bind_iso_keys(
     )
 )
 
+# Scancodes of the modifier keys that `modifierRemaps` can remap, and of the
+# keys whose role they take. Extended scancodes have E0 as their high byte.
+WIN_MODIFIER_SCANCODES = {
+    "caps": 0x003A,
+    "left-alt": 0x0038,
+    "right-alt": 0xE038,
+    "left-ctrl": 0x001D,
+    "right-ctrl": 0xE01D,
+    "menu": 0xE05D,
+}
+
+WIN_ROLE_SCANCODES = {
+    "altgr": 0xE038,
+    "level3": 0xE038,
+    "alt": 0x0038,
+    "ctrl": 0x001D,
+    "shift": 0x002A,
+    "caps": 0x003A,
+    "none": 0x0000,
+}
+
+
+def scancode_map_entries(remaps):
+    """The entries of a `Scancode Map` for the remaps, four bytes each: the
+    scancode to type, then that of the key typing it, as a Pascal string of
+    character codes for the installer's code"""
+    data = bytearray()
+    entries = sorted(
+        (WIN_MODIFIER_SCANCODES[key], WIN_ROLE_SCANCODES[role])
+        for key, role in remaps.items()
+    )
+    for key, role in entries:
+        data += role.to_bytes(2, "little") + key.to_bytes(2, "little")
+    return "".join("#$%02X" % b for b in data)
+
+
+# The `Scancode Map` applies to every layout on the system, and may have
+# entries of its own already. Installing merges the remaps into it, keeping
+# the entries they replace, and uninstalling takes them out again and puts
+# those back, unless the entry was changed in the meantime.
+SCANCODE_MAP_CODE = """\
+[Code]
+const
+  KeyboardLayoutKey = 'SYSTEM\\CurrentControlSet\\Control\\Keyboard Layout';
+  ProductKey = '%(product_key)s';
+
+function RemapEntries: AnsiString;
+begin
+  Result := %(entries)s;
+end;
+
+function CurrentEntries: AnsiString;
+var
+  Map: AnsiString;
+  Count: Integer;
+begin
+  Result := '';
+  if RegQueryBinaryValue(HKLM, KeyboardLayoutKey, 'Scancode Map', Map) and (Length(Map) >= 16) then
+  begin
+    Count := Ord(Map[9]) + Ord(Map[10]) * 256;
+    Result := Copy(Map, 13, (Count - 1) * 4);
+  end;
+end;
+
+procedure WriteEntries(Entries: AnsiString);
+var
+  Count: Integer;
+begin
+  if Entries = '' then
+    RegDeleteValue(HKLM, KeyboardLayoutKey, 'Scancode Map')
+  else
+  begin
+    Count := Length(Entries) div 4 + 1;
+    RegWriteBinaryValue(HKLM, KeyboardLayoutKey, 'Scancode Map',
+      #0#0#0#0#0#0#0#0 + Chr(Count mod 256) + Chr(Count div 256) + #0#0 + Entries + #0#0#0#0);
+  end;
+end;
+
+{ The position of the entry for the same key as Entry, or 0 }
+function EntryForKey(Entries, Entry: AnsiString): Integer;
+var
+  I: Integer;
+begin
+  Result := 0;
+  I := 1;
+  while I < Length(Entries) do
+  begin
+    if Copy(Entries, I + 2, 2) = Copy(Entry, 3, 2) then
+    begin
+      Result := I;
+      Exit;
+    end;
+    I := I + 4;
+  end;
+end;
+
+function WithoutEntry(Entries: AnsiString; Index: Integer): AnsiString;
+begin
+  Result := Copy(Entries, 1, Index - 1) + Copy(Entries, Index + 4, Length(Entries));
+end;
+
+procedure ApplyRemaps;
+var
+  Entries, Ours, Replaced, Entry: AnsiString;
+  I, Index: Integer;
+begin
+  Entries := CurrentEntries;
+  Ours := RemapEntries;
+  if not RegQueryBinaryValue(HKLM, ProductKey, 'ReplacedScancodes', Replaced) then
+    Replaced := '';
+  I := 1;
+  while I < Length(Ours) do
+  begin
+    Entry := Copy(Ours, I, 4);
+    Index := EntryForKey(Entries, Entry);
+    if Index > 0 then
+    begin
+      if (Copy(Entries, Index, 4) <> Entry) and (EntryForKey(Replaced, Entry) = 0) then
+        Replaced := Replaced + Copy(Entries, Index, 4);
+      Entries := WithoutEntry(Entries, Index);
+    end;
+    Entries := Entries + Entry;
+    I := I + 4;
+  end;
+  RegWriteBinaryValue(HKLM, ProductKey, 'ReplacedScancodes', Replaced);
+  WriteEntries(Entries);
+end;
+
+procedure RemoveRemaps;
+var
+  Entries, Ours, Replaced, Entry: AnsiString;
+  I, Index: Integer;
+begin
+  Entries := CurrentEntries;
+  Ours := RemapEntries;
+  if not RegQueryBinaryValue(HKLM, ProductKey, 'ReplacedScancodes', Replaced) then
+    Replaced := '';
+  I := 1;
+  while I < Length(Ours) do
+  begin
+    Entry := Copy(Ours, I, 4);
+    Index := EntryForKey(Entries, Entry);
+    if (Index > 0) and (Copy(Entries, Index, 4) = Entry) then
+    begin
+      Entries := WithoutEntry(Entries, Index);
+      Index := EntryForKey(Replaced, Entry);
+      if Index > 0 then
+        Entries := Entries + Copy(Replaced, Index, 4);
+    end;
+    I := I + 4;
+  end;
+  RegDeleteValue(HKLM, ProductKey, 'ReplacedScancodes');
+  WriteEntries(Entries);
+end;
+
+procedure CurStepChanged(CurStep: TSetupStep);
+begin
+  if CurStep = ssPostInstall then
+    ApplyRemaps;
+end;
+
+procedure CurUninstallStepChanged(CurUninstallStep: TUninstallStep);
+begin
+  if CurUninstallStep = usUninstall then
+    RemoveRemaps;
+end;
+"""
+
+# kbdi picks the KLID a layout is registered under, so its key is found by
+# the DLL kbdi records as its `Layout File`
+LAYOUT_LABEL_CODE = """\
+[Code]
+const
+  KeyboardLayoutsKey = 'SYSTEM\\CurrentControlSet\\Control\\Keyboard Layouts';
+
+procedure SetLayoutLabel(DllName, LayoutLabel: String);
+var
+  Klids: TArrayOfString;
+  Key, LayoutFile: String;
+  I: Integer;
+begin
+  if not RegGetSubkeyNames(HKLM, KeyboardLayoutsKey, Klids) then
+    Exit;
+  for I := 0 to GetArrayLength(Klids) - 1 do
+  begin
+    Key := KeyboardLayoutsKey + '\\' + Klids[I];
+    if RegQueryStringValue(HKLM, Key, 'Layout File', LayoutFile)
+      and (CompareText(LayoutFile, DllName) = 0) then
+    begin
+      RegWriteStringValue(HKLM, Key, 'Layout Text', LayoutLabel);
+      RegWriteStringValue(HKLM, Key, 'Layout Display Name', LayoutLabel);
+    end;
+  end;
+end;
+"""
+
+
 DEFAULT_KEYNAMES = """\
 KEYNAME
 
@@ -284,6 +497,45 @@ def win_glyphbomb(v):
 
 inno_langs = {"en": "English", "fi": "Finnish", "nb": "Norwegian"}
 
+# The logos an MSIX package needs, from the `msix` directory of the resources
+msix_assets = ["StoreLogo.png", "Square150x150Logo.png", "Square44x44Logo.png"]
+
+# The layout DLLs each architecture in `architectures` installs, as the
+# directory they are built into, where Inno Setup installs them and the
+# directory of the Windows directory the install scripts of MSIX packages copy
+# them to. 64-bit Windows, x64 or ARM64, runs 32-bit applications with the
+# DLLs in SysWOW64.
+win_architectures = OrderedDict(
+    [
+        ("x86", [("i386", "{sys}", "System32")]),
+        ("x64", [("amd64", "{sys}", "System32"), ("wow64", "{syswow64}", "SysWOW64")]),
+        ("arm64", [("arm64", "{sys}", "System32"), ("wow64", "{syswow64}", "SysWOW64")]),
+    ]
+)
+
+
+def cmd_quote(value):
+    """`value` as one argument in a batch file, where `%` starts a variable
+    and a quote cannot be escaped"""
+    if '"' in value:
+        raise Exception("Cannot pass %r in a batch file, as it has a quote" % value)
+    return '"%s"' % value.replace("%", "%%")
+
+
+def pascal_quote(value):
+    """`value` as a string in the Pascal of an Inno Setup script"""
+    return "'%s'" % value.replace("'", "''")
+
+# The LLVM tools that build the ARM64 DLLs, as MSKLC cannot
+arm64_tools = ["clang-cl", "llvm-rc", "lld-link"]
+
+# What Inno Setup checks to install the DLLs of each architecture
+inno_architecture_checks = {
+    "x86": "not Is64BitInstallMode",
+    "x64": "IsX64",
+    "arm64": "IsARM64",
+}
+
 custom_msgs = {"Enable": {"en": "Enable %1", "fi": "Aktivoi %1", "nb": "Aktiver %1"}}
 
 
@@ -304,10 +556,95 @@ class WindowsGenerator(Generator):
     def supported_layouts(self):
         o = OrderedDict()
         for k, v in self._bundle.layouts.items():
-            if "win" in v.modes or "desktop" in v.modes:
+            if has_modes(v, "win"):
                 o[k] = v
         return o
 
+    @property
+    def generated_layouts(self):
+        """The supported layouts as (locale, layout) pairs, each followed by its
+        compatibility layout if the target has a `compatMap`."""
+        o = []
+        compat_map = self.win_target.compat_map
+        for locale, layout in self.supported_layouts.items():
+            o.append((locale, layout))
+            if compat_map:
+                o.append((locale, self.compat_layout(locale, layout, compat_map)))
+        return o
+
+    def compat_layout(self, locale, layout, compat_map):
+        """A copy of the layout typing the replacements in `compat_map`, with
+        an id and display names of its own. The dead keys, and the keys typed
+        after them, are replaced as well, so that their transforms still
+        apply."""
+
+        # All replacements are made in one pass, so that the replacement of
+        # one character is not replaced again by another entry
+        originals = sorted((x for x in compat_map if x), key=len, reverse=True)
+        pattern = re.compile("|".join(re.escape(x) for x in originals))
+
+        def replace(value):
+            if value is None:
+                return None
+            return pattern.sub(lambda m: compat_map[m.group(0)], value)
+
+        def replace_keys(name, mapping):
+            o = OrderedDict()
+            for key, value in mapping.items():
+                new_key = replace(key)
+                if new_key in o:
+                    raise Exception(
+                        "The `compatMap` of the Windows target turns both `%s` and "
+                        "another key of %s of `%s` into `%s`"
+                        % (key, name, locale, new_key)
+                    )
+                o[new_key] = value
+            return o
+
+        def replace_transform(name, transform):
+            if not isinstance(transform, dict):
+                return replace(transform)
+            return OrderedDict(
+                (key, replace_transform(name, value))
+                for key, value in replace_keys(name, transform).items()
+            )
+
+        compat = copy.deepcopy(layout)
+        for target in ("win", "desktop"):
+            for mode in compat.modes.get(target, {}).values():
+                for key, value in mode.items():
+                    mode[key] = replace(value)
+        for target, modes in (compat.dead_keys or {}).items():
+            for mode, keys in modes.items():
+                modes[mode] = [replace(key) for key in keys]
+        if compat.transforms is not None:
+            compat.transforms = replace_transform("the transforms", compat.transforms)
+        if compat.dead_key_terminators is not None:
+            compat.dead_key_terminators = OrderedDict(
+                (key, replace(value))
+                for key, value in replace_keys(
+                    "the dead key terminators", compat.dead_key_terminators
+                ).items()
+            )
+        if compat.dead_key_options is not None:
+            compat.dead_key_options = replace_keys(
+                "the dead key options", compat.dead_key_options
+            )
+        compat.display_names = OrderedDict(
+            (k, "%s (legacy)" % v) for (k, v) in layout.display_names.items()
+        )
+
+        # Allocated by kbdgen around the ids of all other layouts and those of
+        # Windows, see `compat_win_ids` in the Rust models
+        compat_ids = json.loads(os.environ.get("KBDGEN_WIN_COMPAT_IDS", "{}"))
+        if locale not in compat_ids:
+            raise Exception("No id for the legacy layout of `%s`" % locale)
+        if compat.targets is None:
+            compat.targets = {}
+        compat.targets["win"] = dict(compat.targets.get("win", {}))
+        compat.targets["win"]["id"] = compat_ids[locale]
+        return compat
+
     def generate(self, base="."):
         outputs = OrderedDict()
 
@@ -323,9 +660,13 @@ class WindowsGenerator(Generator):
                 raise e
                 return
 
-        for locale, layout in self.supported_layouts.items():
-            outputs[self._klc_get_name(locale, layout, False)] = self.generate_klc(
-                locale, layout
+        for locale, layout in self.generated_layouts:
+            warn_unsupported_reordering(locale, layout, "win")
+            warn_unsupported_dead_key_chains(locale, layout, "win")
+            warn_ignored_transform_partial(locale, layout, "win")
+            outputs[self._klc_get_name(locale, layout, False)] = (
+                locale,
+                self.generate_klc(locale, layout),
             )
 
         if self.dry_run:
@@ -340,26 +681,22 @@ class WindowsGenerator(Generator):
         try:
             futures = []
 
-            for name, data in outputs.items():
-                klc_path = os.path.join(build_dir, "%s.klc" % name)
+            for name, (locale, data) in outputs.items():
+                # Like `klc_path` in generator.rs
+                klc_path = os.path.join(
+                    build_dir,
+                    layout_file_path("win", locale, "klc", "%s.klc" % name, part=name),
+                )
+                os.makedirs(os.path.dirname(klc_path), exist_ok=True)
                 self.write_klc_file(klc_path, data)
 
                 if self.is_release:
-                    futures.append(
-                        executor.submit(
-                            self.build_dll, name, "i386", klc_path, build_dir
+                    for arch in self.dll_builds:
+                        futures.append(
+                            executor.submit(
+                                self.build_dll, name, arch, klc_path, build_dir
+                            )
                         )
-                    )
-                    futures.append(
-                        executor.submit(
-                            self.build_dll, name, "amd64", klc_path, build_dir
-                        )
-                    )
-                    futures.append(
-                        executor.submit(
-                            self.build_dll, name, "wow64", klc_path, build_dir
-                        )
-                    )
 
             for future in futures:
                 future.result()
@@ -369,6 +706,10 @@ class WindowsGenerator(Generator):
         if self.is_release:
             self.copy_nlp_files(build_dir)
 
+            if self.packager == "msix":
+                self.build_msix(kbdi, build_dir)
+                return
+
             oses = [("Windows 8.1/10", kbdi)]
             if self.is_legacy:
                 oses.append(("legacy Windows", kbdi_legacy))
@@ -381,6 +722,31 @@ class WindowsGenerator(Generator):
     @property
     def is_legacy(self):
         return self._args.get("legacy", False)
+
+    @property
+    def packager(self):
+        return self.win_target.packager or "inno"
+
+    @property
+    def architectures(self):
+        """The architectures to build for, by default x86 and x64 for the
+        installer and x64 for MSIX packages"""
+        if self.win_target.architectures:
+            return [x for x in win_architectures if x in self.win_target.architectures]
+        if self.packager == "msix":
+            return ["x64"]
+        return ["x86", "x64"]
+
+    @property
+    def dll_builds(self):
+        """The builds of the layout DLLs the architectures need, like `amd64`
+        and `wow64` for x64"""
+        builds = []
+        for arch in self.architectures:
+            for build, _, _ in win_architectures[arch]:
+                if build not in builds:
+                    builds.append(build)
+        return builds
     
     def copy_nlp_files(self, build_dir):
         target = self.win_target
@@ -412,6 +778,23 @@ class WindowsGenerator(Generator):
             if os.path.isdir(p):
                 return p
 
+    def get_makeappx(self):
+        path = os.environ.get("MAKEAPPX_PATH", None)
+        if path is not None:
+            return path if os.path.isfile(path) else None
+        if not is_windows:
+            return None
+        sdk = "C:\\Program Files (x86)\\Windows Kits\\10\\bin"
+        try:
+            versions = sorted(os.listdir(sdk), reverse=True)
+        except OSError:
+            return shutil.which("makeappx.exe")
+        for version in versions:
+            p = os.path.join(sdk, version, "x64", "makeappx.exe")
+            if os.path.isfile(p):
+                return p
+        return shutil.which("makeappx.exe")
+
     def get_msklc_dir(self):
         possibles = [os.environ.get("MSKLC_PATH", None)]
         if is_windows:
@@ -470,7 +853,7 @@ class WindowsGenerator(Generator):
         if guid is None:
             logger.error(
                 "Property 'targets.win.uuid' must be defined in the project "
-                + "for this target."
+                + "for this target; run `kbdgen target win init` to generate one."
             )
             return False
         try:
@@ -479,7 +862,25 @@ class WindowsGenerator(Generator):
             logger.error("Property 'targets.win.uuid' is not a valid UUID.")
             return False
 
+        unknown = [
+            x for x in self.win_target.architectures or [] if x not in win_architectures
+        ]
+        if unknown:
+            logger.error(
+                "Property 'targets.win.architectures' has unknown architectures %s; "
+                "use %s." % (", ".join(unknown), ", ".join(win_architectures))
+            )
+            return False
+
         for locale, layout in self.supported_layouts.items():
+            label = self.layout_target(layout).get("label", None)
+            if label is not None and (not label.strip() or '"' in label):
+                logger.error(
+                    "The label of layout '%s' must not be empty or contain quotes."
+                    % locale
+                )
+                return False
+
             lcid = lcid_get(locale)
             if lcid is None and self.layout_target(layout).get("locale", None) is None:
                 logger.error(
@@ -515,13 +916,14 @@ class WindowsGenerator(Generator):
 
         fail = False
         ids = []
-        for locale, layout in self.supported_layouts.items():
+        for locale, layout in self.generated_layouts:
             id_ = self._klc_get_name(locale, layout)
             if id_ in ids:
                 fail = True
                 msg = (
                     "Duplicate id found for '%s': '%s'; "
-                    + "set targets.win.id to override."
+                    + "set targets.win.id to override, or run "
+                    + "`kbdgen target win allocate-ids`."
                 )
                 logger.error(msg, locale, id_)
             else:
@@ -550,8 +952,27 @@ class WindowsGenerator(Generator):
                     + "2.10; here be dragons."
                 )
 
+        if self.packager == "msix":
+            if self.get_makeappx() is None:
+                logger.error(
+                    "The Windows 10 SDK must be installed or MAKEAPPX_PATH environment "
+                    + "variable must point to makeappx.exe."
+                )
+                return False
+
+            missing = [
+                x
+                for x in msix_assets
+                if not os.path.isfile(os.path.join(self.win_resources, "msix", x))
+            ]
+            if missing:
+                logger.error(
+                    "MSIX packages need the logos %s in the `msix` directory of the "
+                    "Windows resources." % ", ".join(missing)
+                )
+                return False
         # Check for INNO_PATH
-        if self.get_inno_setup_dir() is None:
+        elif self.get_inno_setup_dir() is None:
             logger.error(
                 "Inno Setup 6 must be installed or INNO_PATH environment variable must "
                 + "point to the Inno Setup 6 directory."
@@ -566,6 +987,23 @@ class WindowsGenerator(Generator):
             )
             return False
 
+        if "arm64" in self.dll_builds:
+            missing = [x for x in arm64_tools if shutil.which(x) is None]
+            if missing:
+                logger.error(
+                    "Building layouts for ARM64 needs %s on your PATH."
+                    % ", ".join("`%s`" % x for x in missing)
+                )
+                return False
+            include = os.environ.get("INCLUDE", "").split(os.pathsep)
+            if not any(os.path.isfile(os.path.join(x, "kbd.h")) for x in include if x):
+                logger.error(
+                    "Building layouts for ARM64 needs the INCLUDE environment variable "
+                    + "to list the include directories of the Windows SDK and of the "
+                    + "WDK, which has `kbd.h`."
+                )
+                return False
+
         return True
 
     def _wine_path(self, thing):
@@ -582,17 +1020,20 @@ class WindowsGenerator(Generator):
             return "%s/bin/i386/kbdutool.exe" % self.get_msklc_dir()
 
     def build_dll(self, name, arch, klc_path, build_dir):
-        # x86, x64, wow64
-        flags = {"i386": "-x", "amd64": "-m", "wow64": "-o"}
-
-        flag = flags[arch]
-
         out_path = os.path.join(build_dir, arch)
         os.makedirs(out_path, exist_ok=True)
 
         logger.info("Building '%s' for %s…" % (name, arch))
-        cmd = [self._kbdutool, "-n", flag, "-u", self._wine_path(klc_path)]
-        run_process(cmd, cwd=out_path)
+        if arch == "arm64":
+            self.build_arm64_dll(name, klc_path, build_dir)
+        else:
+            # x86, x64, wow64
+            flags = {"i386": "-x", "amd64": "-m", "wow64": "-o"}
+
+            flag = flags[arch]
+
+            cmd = [self._kbdutool, "-n", flag, "-u", self._wine_path(klc_path)]
+            run_process(cmd, cwd=out_path)
 
         pfx = self.codesign_pfx
         if pfx is None:
@@ -614,6 +1055,51 @@ class WindowsGenerator(Generator):
         ]
         run_process(cmd, cwd=out_path)
 
+    def build_arm64_dll(self, name, klc_path, build_dir):
+        """MSKLC cannot build for ARM64, so kbdutool only writes the layout as
+        C sources to `arm64-src`, which clang-cl builds into the DLL against
+        `kbd.h` of the WDK."""
+        src_path = os.path.join(build_dir, "arm64-src", name)
+        os.makedirs(src_path, exist_ok=True)
+        cmd = [self._kbdutool, "-n", "-u", "-s", self._wine_path(klc_path)]
+        run_process(cmd, cwd=src_path)
+
+        # kbdutool names the files in upper case, like `KBDFOO.C`
+        sources = {
+            os.path.splitext(x)[1].lower(): x
+            for x in os.listdir(src_path)
+            if os.path.splitext(x)[0].lower() == name.lower()
+        }
+        missing = [x for x in (".c", ".rc", ".def") if x not in sources]
+        if missing:
+            raise Exception(
+                "kbdutool did not write %s for '%s'" % (", ".join(missing), name)
+            )
+
+        obj = "%s.obj" % name
+        res = "%s.res" % name
+        dll = os.path.join(build_dir, "arm64", "%s.dll" % name)
+        run_process(
+            [
+                "clang-cl", "--target=aarch64-pc-windows-msvc", "/nologo",
+                "/c", "/O2", "/GS-", "/Zl", "/Fo%s" % obj, sources[".c"],
+            ],
+            cwd=src_path,
+        )
+        run_process(["llvm-rc", "/FO", res, sources[".rc"]], cwd=src_path)
+        # Keyboard layouts are data only, loaded by the kernel without an
+        # entry point or runtime
+        run_process(
+            [
+                "lld-link", "/nologo", "/DLL", "/NOENTRY", "/NODEFAULTLIB",
+                "/MACHINE:ARM64", "/SUBSYSTEM:NATIVE",
+                "/MERGE:.rdata=.data", "/MERGE:.edata=.data", "/MERGE:.bss=.data",
+                "/SECTION:.data,RW",
+                "/DEF:%s" % sources[".def"], "/OUT:%s" % dll, obj, res,
+            ],
+            cwd=src_path,
+        )
+
     @property
     def win_resources_list(self):
         try:
@@ -621,7 +1107,7 @@ class WindowsGenerator(Generator):
         except Exception:
             return []
 
-    def _generate_inno_languages(self):
+    def _generate_inno_languages(self, app_license_path):
         out = []
 
         license_locales = [
@@ -635,6 +1121,8 @@ class WindowsGenerator(Generator):
             en_license = self._wine_path(
                 os.path.join(self.win_resources, "license.txt")
             )
+        elif app_license_path is not None:
+            en_license = self._wine_path(app_license_path)
 
         readme_locales = [
             os.path.splitext(x)[0].split(".").pop()
@@ -725,15 +1213,72 @@ class WindowsGenerator(Generator):
         else:
             return "MinVersion=0,6.3.9200"
 
+    def _generate_inno_architectures(self):
+        """Only lets the installer run on the architectures it has layouts
+        for, and in 64-bit mode on the 64-bit ones"""
+        bits64 = [x for x in self.architectures if x != "x86"]
+        return "ArchitecturesAllowed=%s\nArchitecturesInstallIn64BitMode=%s" % (
+            " ".join(self.architectures),
+            " ".join(bits64),
+        )
+
+    def _generate_inno_dll_files(self):
+        lines = []
+        for arch in self.architectures:
+            for build, dest_dir, _ in win_architectures[arch]:
+                lines.append(
+                    'Source: "{#BuildDir}\\%s\\*"; DestDir: "%s"; Check: %s; '
+                    "Flags: restartreplace uninsrestartdelete ignoreversion"
+                    % (build, dest_dir, inno_architecture_checks[arch])
+                )
+        return "\n".join(lines)
+
+    def _write_license_notice(self, build_dir):
+        """Writes the license and attributions of the project and its layouts
+        for the installer's license page, if there are any"""
+        project = self._bundle.project
+        lines = license_notice(project)
+        for locale, layout in self.supported_layouts.items():
+            extra = [x for x in license_notice(project, layout) if x not in lines]
+            if extra:
+                name = display_name(layout, "en", project) or locale
+                lines += ["", "%s:" % name] + extra
+
+        if not lines:
+            return None
+
+        path = os.path.join(build_dir, "license.txt")
+        with open(path, "w", encoding="utf-8-sig", newline="\r\n") as f:
+            f.write("\n".join(lines) + "\n")
+        return path
+
+    def _modifier_remaps(self):
+        """The modifier remaps of all layouts, which Windows can only apply to
+        the whole system. The first layout to remap a key wins."""
+        remaps = {}
+        for locale, layout in self.generated_layouts:
+            for key, role in DesktopLayoutView(layout, "win").modifier_remaps().items():
+                if key not in remaps:
+                    remaps[key] = (role, locale)
+                elif remaps[key][0] != role:
+                    report_loss(
+                        locale,
+                        "win",
+                        "modifier remap of `%s`" % key,
+                        "remaps apply to every layout, and `%s` remaps it to %s"
+                        % (remaps[key][1], remaps[key][0]),
+                    )
+        return {key: role for key, (role, _) in remaps.items()}
+
     def generate_inno_script(self, os_, build_dir):
         logger.info("Generating Inno Setup script for %s…" % os_)
         target = self.win_target
         try:
             app_version = target.version
+            app_name = target.app_name
             app_publisher = self._bundle.project.organisation
-            app_url = target.url
-            if app_url is None:
-                app_url = ""
+            app_url = target.url or self._bundle.project.homepage or ""
+            app_support_url = self._bundle.project.support_url or app_url
             app_uuid = target.uuid
             if app_uuid.startswith("{"):
                 app_uuid = app_uuid[1:]
@@ -746,15 +1291,19 @@ class WindowsGenerator(Generator):
         app_license_path = target.license_path
         if app_license_path is not None:
             app_license_path = self._bundle.relpath(app_license_path)
+        else:
+            app_license_path = self._write_license_notice(build_dir)
 
         app_readme_path = target.readme_path
         if app_readme_path is not None:
             app_readme_path = self._bundle.relpath(app_readme_path)
 
         script = """\
+#define MyAppName "%s"
 #define MyAppVersion "%s"
 #define MyAppPublisher "%s"
 #define MyAppURL "%s"
+#define MyAppSupportURL "%s"
 #define MyAppUUID "{{%s}"
 #define BuildDir "%s"
 
@@ -764,15 +1313,15 @@ AppName={cm:AppName}
 AppVersion={#MyAppVersion}
 AppPublisher={#MyAppPublisher}
 AppPublisherURL={#MyAppURL}
-AppSupportURL={#MyAppURL}
+AppSupportURL={#MyAppSupportURL}
 AppUpdatesURL={#MyAppURL}
-DefaultDirName={pf}\\{cm:AppName}
+DefaultDirName={pf}\\{#MyAppName}
 DisableDirPage=no
 DefaultGroupName={cm:AppName}
 OutputBaseFilename=install
 Compression=lzma
 SolidCompression=yes
-ArchitecturesInstallIn64BitMode=x64
+%s
 AlwaysRestart=yes
 AllowCancelDuringInstall=no
 UninstallRestartComputer=yes
@@ -787,20 +1336,39 @@ UninstallDisplayName={cm:AppName}
 
 [Files]
 Source: "{#BuildDir}\\kbdi.exe"; DestDir: "{app}"; Flags: restartreplace uninsrestartdelete ignoreversion
-Source: "{#BuildDir}\\i386\\*"; DestDir: "{sys}"; Check: not Is64BitInstallMode; Flags: restartreplace uninsrestartdelete ignoreversion
-Source: "{#BuildDir}\\amd64\\*"; DestDir: "{sys}"; Check: Is64BitInstallMode; Flags: restartreplace uninsrestartdelete ignoreversion
-Source: "{#BuildDir}\\wow64\\*"; DestDir: "{syswow64}"; Check: Is64BitInstallMode; Flags: restartreplace uninsrestartdelete ignoreversion
+%s
         """.strip() % (  # noqa: E501
+            app_name,
             app_version,
             app_publisher,
             app_url,
+            app_support_url,
             app_uuid,
             self._wine_path(build_dir),
+            self._generate_inno_architectures(),
             self._generate_inno_setup(app_url, os_),
-            self._generate_inno_languages(),
+            self._generate_inno_languages(app_license_path),
             self._generate_inno_custom_messages(),
+            self._generate_inno_dll_files(),
         )
 
+        # Every layout is recorded under the product, so that the layouts an
+        # installation put on the system can be found again, and the product
+        # key goes away once the last of them is uninstalled.
+        product_key = "SOFTWARE\\kbdgen\\%s" % app_uuid.upper()
+        reg = [
+            (
+                'Root: HKLM; Subkey: "%s"; ValueType: string; ValueName: "AppName"; '
+                'ValueData: "{#MyAppName}"; Flags: uninsdeletekeyifempty'
+            )
+            % product_key,
+            (
+                'Root: HKLM; Subkey: "%s"; ValueType: string; ValueName: "Version"; '
+                'ValueData: "{#MyAppVersion}"; Flags: uninsdeletekeyifempty'
+            )
+            % product_key,
+        ]
+
         # TODO: add an actual thing for this
         custom_locales = getattr(target, "customLocales", None)
 
@@ -811,7 +1379,6 @@ Source: "{#BuildDir}\\wow64\\*"; DestDir: "{syswow64}"; Check: Is64BitInstallMod
                 for x in os.listdir(custom_locales_path)
                 if x.endswith(".nlp")
             ]
-            reg = []
 
             for l in locales:
                 o = (
@@ -831,10 +1398,6 @@ Source: "{#BuildDir}\\wow64\\*"; DestDir: "{syswow64}"; Check: Is64BitInstallMod
             script += """DestDir: "{win}\\Globalization"; """
             script += """Flags: restartreplace uninsrestartdelete\n"""
 
-            script += "\n[Registry]\n"
-            script += "\n".join(reg)
-            script += "\n"
-
         # Add Run section
         run_scr = io.StringIO()
         run_scr.write("[Run]\n")
@@ -842,12 +1405,12 @@ Source: "{#BuildDir}\\wow64\\*"; DestDir: "{syswow64}"; Check: Is64BitInstallMod
         uninst_scr.write("[UninstallRun]\n")
         icons_scr = io.StringIO()
         icons_scr.write("[Icons]\n")
+        labels = False
 
-        for locale, layout in self.supported_layouts.items():
-            kbd_id = self._klc_get_name(locale, layout)
-            dll_name = "%s.dll" % kbd_id
-            language_code = self.layout_target(layout).get("locale", locale)
-            language_name = self.layout_target(layout).get("languageName", None)
+        for locale, layout in self.generated_layouts:
+            kbd_id, dll_name, language_code, language_name, guid_str = (
+                self._layout_registration(locale, layout)
+            )
             if language_name is not None:
                 logger.info(
                     "Using language name '%s' for layout '%s'."
@@ -862,7 +1425,17 @@ Source: "{#BuildDir}\\wow64\\*"; DestDir: "{syswow64}"; Check: Is64BitInstallMod
                     )
                     % locale
                 )
-            guid_str = "{%s}" % str(guid(kbd_id)).upper()
+
+            layout_key = "%s\\Layouts\\%s" % (product_key, kbd_id)
+            for name, value in (
+                ("Guid", "{%s" % guid_str),
+                ("Locale", language_code),
+                ("Dll", dll_name),
+            ):
+                reg.append(
+                    'Root: HKLM; Subkey: "%s"; ValueType: string; ValueName: "%s"; '
+                    'ValueData: "%s"; Flags: uninsdeletekey' % (layout_key, name, value)
+                )
 
             # Install script
             run_scr.write('Filename: "{app}\\kbdi.exe"; Parameters: "keyboard_install')
@@ -872,14 +1445,23 @@ Source: "{#BuildDir}\\wow64\\*"; DestDir: "{syswow64}"; Check: Is64BitInstallMod
             run_scr.write(' -g ""{%s""' % guid_str)  # Product code
             run_scr.write(" -d %s" % dll_name)  # Layout DLL
             run_scr.write(
-                ' -n ""%s""' % layout.display_names[locale]
+                ' -n ""%s""' % display_name(layout, locale, self._bundle.project)
             )  # Layout native display name
             run_scr.write(" -e")  # Enable layout after installing it
-            run_scr.write('"; Flags: runhidden waituntilterminated\n')
+            run_scr.write('"; Flags: runhidden waituntilterminated')
+            label = self.layout_target(layout).get("label", None)
+            if label is not None:
+                labels = True
+                run_scr.write(
+                    '; AfterInstall: "SetLayoutLabel(%s, %s)"'
+                    % (pascal_quote(dll_name), pascal_quote(label).replace('"', '""'))
+                )
+            run_scr.write("\n")
 
             # Enablement icon
             icons_scr.write(
-                'Name: "{group}\\{cm:Enable,%s}"; ' % layout.display_names[locale]
+                'Name: "{group}\\{cm:Enable,%s}"; '
+                % display_name(layout, locale, self._bundle.project)
             )
             icons_scr.write('Filename: "{app}\\kbdi.exe"; ')
             icons_scr.write(
@@ -898,10 +1480,26 @@ Source: "{#BuildDir}\\wow64\\*"; DestDir: "{syswow64}"; Check: Is64BitInstallMod
                 ' ""{%s"""; Flags: runhidden waituntilterminated\n' % guid_str
             )
 
+        script += "\n[Registry]\n"
+        script += "\n".join(reg)
+        script += "\n"
+
         script = "\n\n".join(
             (script, run_scr.getvalue(), uninst_scr.getvalue(), icons_scr.getvalue())
         )
 
+        if labels:
+            script += "\n\n" + LAYOUT_LABEL_CODE
+
+        # Remapping scancodes only takes effect after a restart, which the
+        # installer asks for anyway
+        remaps = self._modifier_remaps()
+        if remaps:
+            script += "\n\n" + SCANCODE_MAP_CODE % {
+                "product_key": product_key,
+                "entries": scancode_map_entries(remaps),
+            }
+
         fn_os = "all" if os_ != "Windows 7" else "win7"
         with open(
             os.path.join(build_dir, "install.%s.iss" % fn_os),
@@ -909,6 +1507,8 @@ Source: "{#BuildDir}\\wow64\\*"; DestDir: "{syswow64}"; Check: Is64BitInstallMod
             encoding="utf-8-sig",
             newline="\r\n",
         ) as f:
+            f.write(stamp_comment(";"))
+            f.write(license_comment(";", self._bundle.project))
             f.write(script)
 
     def build_installer(self, os_, build_dir):
@@ -939,6 +1539,294 @@ Source: "{#BuildDir}\\wow64\\*"; DestDir: "{syswow64}"; Check: Is64BitInstallMod
 
         logger.info("Installer generated at '%s'." % os.path.join(build_dir, fn))
 
+    def _layout_registration(self, locale, layout):
+        """The id, DLL, language tag, optional language name and product GUID
+        kbdi registers a layout with"""
+        kbd_id = self._klc_get_name(locale, layout)
+        target = self.layout_target(layout)
+        return (
+            kbd_id,
+            "%s.dll" % kbd_id,
+            target.get("locale", locale),
+            target.get("languageName", None),
+            "{%s}" % str(guid(kbd_id)).upper(),
+        )
+
+    def _msix_version(self):
+        """The target's version as the four numbers MSIX requires, with any
+        pre-release part dropped"""
+        version = self.win_target.version.split("-")[0].split("+")[0]
+        chunks = [re.sub(r"[^0-9]", "", x) or "0" for x in version.split(".")]
+        return ".".join((chunks + ["0"] * 4)[:4])
+
+    def _msix_fn(self, ext="msix"):
+        return "keyboard-%s_%s_windows.%s" % (
+            self._bundle.name,
+            self.win_target.version,
+            ext,
+        )
+
+    def generate_appx_manifest(self, package_dir, arch):
+        logger.info("Generating AppX manifest for %s…" % arch)
+        target = self.win_target
+        project = self._bundle.project
+        publisher = target.publisher or "CN=%s" % project.organisation
+        name = re.sub(r"[^A-Za-z0-9.-]", "", "keyboard-%s" % self._bundle.name)
+
+        resources = "\n".join(
+            "    <Resource Language=%s />" % quoteattr(tag)
+            for tag in project.locales.keys()
+        )
+
+        manifest = """\
+<Package
+  xmlns="http://schemas.microsoft.com/appx/manifest/foundation/windows10"
+  xmlns:uap="http://schemas.microsoft.com/appx/manifest/uap/windows10"
+  xmlns:rescap="http://schemas.microsoft.com/appx/manifest/foundation/windows10/restrictedcapabilities"
+  xmlns:desktop6="http://schemas.microsoft.com/appx/manifest/desktop/windows10/6"
+  IgnorableNamespaces="uap rescap desktop6">
+  <Identity Name=%s Publisher=%s Version=%s ProcessorArchitecture=%s />
+  <Properties>
+    <DisplayName>%s</DisplayName>
+    <PublisherDisplayName>%s</PublisherDisplayName>
+    <Logo>Assets\\StoreLogo.png</Logo>
+  </Properties>
+  <Dependencies>
+    <TargetDeviceFamily Name="Windows.Desktop" MinVersion="10.0.19041.0" MaxVersionTested="10.0.19041.0" />
+  </Dependencies>
+  <Resources>
+%s
+  </Resources>
+  <Applications>
+    <Application Id="kbdi" Executable="CustomInstall\\kbdi.exe" EntryPoint="Windows.FullTrustApplication">
+      <uap:VisualElements DisplayName=%s Description=%s BackgroundColor="transparent"
+        Square150x150Logo="Assets\\Square150x150Logo.png" Square44x44Logo="Assets\\Square44x44Logo.png"
+        AppListEntry="none" />
+      <Extensions>
+        <desktop6:Extension Category="windows.customInstall">
+          <desktop6:CustomInstall Folder="CustomInstall">
+            <desktop6:InstallActions>
+              <desktop6:InstallAction File="install.cmd" Name="Install" />
+            </desktop6:InstallActions>
+            <desktop6:RepairActions>
+              <desktop6:RepairAction File="install.cmd" Name="Repair" />
+            </desktop6:RepairActions>
+            <desktop6:UninstallActions>
+              <desktop6:UninstallAction File="uninstall.cmd" Name="Uninstall" />
+            </desktop6:UninstallActions>
+          </desktop6:CustomInstall>
+        </desktop6:Extension>
+      </Extensions>
+    </Application>
+  </Applications>
+  <Capabilities>
+    <rescap:Capability Name="runFullTrust" />
+    <rescap:Capability Name="customInstallActions" />
+  </Capabilities>
+</Package>
+""" % (  # noqa: E501
+            quoteattr(name),
+            quoteattr(publisher),
+            quoteattr(self._msix_version()),
+            quoteattr(arch),
+            escape(target.app_name),
+            escape(project.organisation),
+            resources,
+            quoteattr(target.app_name),
+            quoteattr(target.app_name),
+        )
+
+        lines = stamp_lines() + license_notice(project)
+        path = os.path.join(package_dir, "AppxManifest.xml")
+        with open(path, "w", encoding="utf-8", newline="\r\n") as f:
+            f.write('<?xml version="1.0" encoding="utf-8"?>\n')
+            f.write(
+                "".join("<!-- %s -->\n" % line.replace("--", "- -") for line in lines)
+            )
+            f.write(manifest)
+        return path
+
+    def generate_msix_scripts(self, custom_dir, arch):
+        """Writes the scripts the custom install actions of an MSIX package
+        run, elevated, when it is installed, repaired and uninstalled.
+
+        Files of a package are only visible to the package's own processes,
+        so the layout DLLs are copied to System32 and SysWOW64 like the
+        installer does, and registered with kbdi. The scripts are written in
+        UTF-8, which `chcp` switches the console to for the display names."""
+        logger.info("Generating MSIX install scripts for %s…" % arch)
+        target = self.win_target
+        product_key = "HKLM\\SOFTWARE\\kbdgen\\%s" % target.uuid.upper()
+        comments = [
+            "rem %s" % line
+            for line in stamp_lines() + license_notice(self._bundle.project)
+        ]
+        header = ["@echo off"] + comments + ["chcp 65001 >nul", "setlocal"]
+
+        def reg_add(key, name, value):
+            return "reg add %s /v %s /t REG_SZ /d %s /f >nul || exit /b 1" % (
+                cmd_quote(key),
+                name,
+                cmd_quote(value),
+            )
+
+        install = list(header)
+        uninstall = list(header)
+        for build, _, system_dir in win_architectures[arch]:
+            install.append(
+                'copy /y "%%~dp0%s\\*.dll" "%%SystemRoot%%\\%s\\" >nul || exit /b 1'
+                % (build, system_dir)
+            )
+
+        if target.custom_locales is not None:
+            nls_key = "HKLM\\SYSTEM\\CurrentControlSet\\Control\\Nls\\CustomLocale"
+            install.append(
+                'copy /y "%~dp0nlp\\*.nlp" "%SystemRoot%\\Globalization\\" >nul || exit /b 1'
+            )
+            for fn in sorted(os.listdir(self._bundle.relpath(target.custom_locales))):
+                if fn.endswith(".nlp"):
+                    tag = os.path.splitext(fn)[0]
+                    install.append(reg_add(nls_key, tag, tag))
+
+        install.append(reg_add(product_key, "AppName", target.app_name))
+        install.append(reg_add(product_key, "Version", target.version))
+        for locale, layout in self.generated_layouts:
+            kbd_id, dll_name, language_code, language_name, guid_str = (
+                self._layout_registration(locale, layout)
+            )
+            layout_key = "%s\\Layouts\\%s" % (product_key, kbd_id)
+            install.append(reg_add(layout_key, "Guid", guid_str))
+            install.append(reg_add(layout_key, "Locale", language_code))
+            install.append(reg_add(layout_key, "Dll", dll_name))
+
+            args = ["keyboard_install", "-t", cmd_quote(language_code)]
+            if language_name:
+                args += ["-l", cmd_quote(language_name)]
+            args += [
+                "-g", cmd_quote(guid_str),
+                "-d", dll_name,
+                "-n", cmd_quote(display_name(layout, locale, self._bundle.project)),
+                "-e",
+            ]
+            install.append('"%%~dp0kbdi.exe" %s || exit /b 1' % " ".join(args))
+            label = self.layout_target(layout).get("label", None)
+            if label is not None:
+                install.append(
+                    'for /f "delims=" %%%%k in (\'reg query "HKLM\\SYSTEM\\CurrentControlSet'
+                    '\\Control\\Keyboard Layouts" /s /f %s /d /e ^| findstr /b HKEY_\') do ('
+                    % dll_name
+                )
+                for name in ("Layout Text", "Layout Display Name"):
+                    install.append(
+                        '    reg add "%%%%k" /v "%s" /t REG_SZ /d %s /f >nul || exit /b 1'
+                        % (name, cmd_quote(label))
+                    )
+                install.append(")")
+
+            # A DLL that is still loaded goes away with the next restart
+            uninstall.append('"%%~dp0kbdi.exe" keyboard_uninstall %s' % cmd_quote(guid_str))
+            for _, _, system_dir in win_architectures[arch]:
+                uninstall.append(
+                    'del /f /q "%%SystemRoot%%\\%s\\%s" 2>nul' % (system_dir, dll_name)
+                )
+        uninstall.append("reg delete %s /f >nul 2>nul" % cmd_quote(product_key))
+        install.append("exit /b 0")
+        uninstall.append("exit /b 0")
+
+        for fn, lines in (("install.cmd", install), ("uninstall.cmd", uninstall)):
+            with open(
+                os.path.join(custom_dir, fn), "w", encoding="utf-8", newline="\r\n"
+            ) as f:
+                f.write("\n".join(lines) + "\n")
+
+    def build_msix(self, kbdi, build_dir):
+        """Packages kbdi and the layout DLLs as an MSIX package for each
+        architecture, bundled into an MSIX bundle if there are several. The
+        package's custom install actions install the DLLs into System32 and
+        SysWOW64 and register them, see `generate_msix_scripts`."""
+        logger.info("Building MSIX package…")
+        if self._modifier_remaps():
+            logger.warn(
+                "MSIX packages do not remap modifiers, which the installer does with "
+                "its Scancode Map; use the Inno Setup installer for these layouts."
+            )
+        msix_dir = os.path.join(build_dir, "msix")
+        if os.path.isdir(msix_dir):
+            shutil.rmtree(msix_dir)
+        os.makedirs(msix_dir)
+
+        packages = []
+        for arch in self.architectures:
+            package_dir = os.path.join(msix_dir, arch)
+            custom_dir = os.path.join(package_dir, "CustomInstall")
+            os.makedirs(custom_dir)
+            shutil.copyfile(kbdi, os.path.join(custom_dir, "kbdi.exe"))
+            for build, _, _ in win_architectures[arch]:
+                copy_tree(os.path.join(build_dir, build), os.path.join(custom_dir, build))
+            if os.path.isdir(os.path.join(build_dir, "nlp")):
+                copy_tree(os.path.join(build_dir, "nlp"), os.path.join(custom_dir, "nlp"))
+            self.generate_msix_scripts(custom_dir, arch)
+            assets_dir = os.path.join(package_dir, "Assets")
+            os.makedirs(assets_dir)
+            for asset in msix_assets:
+                shutil.copyfile(
+                    os.path.join(self.win_resources, "msix", asset),
+                    os.path.join(assets_dir, asset),
+                )
+            self.generate_appx_manifest(package_dir, arch)
+            packages.append((arch, package_dir))
+
+        if len(packages) == 1:
+            fn = self._msix_fn()
+            msix_path = os.path.join(build_dir, fn)
+            self._makeappx_pack(packages[0][1], msix_path, build_dir)
+        else:
+            # The packages of a bundle are signed with it
+            bundle_dir = os.path.join(msix_dir, "bundle")
+            os.makedirs(bundle_dir)
+            for arch, package_dir in packages:
+                self._makeappx_pack(
+                    package_dir,
+                    os.path.join(bundle_dir, "%s_%s.msix" % (self._bundle.name, arch)),
+                    build_dir,
+                )
+            fn = self._msix_fn("msixbundle")
+            msix_path = os.path.join(build_dir, fn)
+            cmd = [
+                self.get_makeappx(),
+                "bundle", "/o",
+                "/bv", self._msix_version(),
+                "/d", self._wine_path(bundle_dir),
+                "/p", self._wine_path(msix_path),
+            ]
+            run_process(cmd, cwd=build_dir, show_output=True)
+
+        pfx = self.codesign_pfx
+        if pfx is None:
+            logger.warn("'%s' was not code signed due to no codeSignPfx property." % fn)
+        else:
+            logger.info("Signing '%s'…" % fn)
+            cmd = [
+                "signtool.exe",
+                "sign", "/fd", "SHA256",
+                "/t", "http://timestamp.verisign.com/scripts/timstamp.dll",
+                "/f", self._wine_path(self._bundle.relpath(pfx)),
+                "/p", self.codesign_pw,
+                self._wine_path(msix_path)
+            ]
+            run_process(cmd, cwd=build_dir)
+
+        logger.info("MSIX package generated at '%s'." % msix_path)
+
+    def _makeappx_pack(self, package_dir, msix_path, build_dir):
+        cmd = [
+            self.get_makeappx(),
+            "pack", "/o",
+            "/d", self._wine_path(package_dir),
+            "/p", self._wine_path(msix_path),
+        ]
+        run_process(cmd, cwd=build_dir, show_output=True)
+
     def _klc_get_name(self, locale, layout, show_errors=True):
         id_ = self.layout_target(layout).get("id", None)
         if id_ is not None:
@@ -972,7 +1860,10 @@ Source: "{#BuildDir}\\wow64\\*"; DestDir: "{syswow64}"; Check: Is64BitInstallMod
     def _klc_write_headers(self, locale, layout, buf):
         buf.write(
             'KBD\t%s\t"%s"\n\n'
-            % (self._klc_get_name(locale, layout, False), layout.display_names[locale])
+            % (
+                self._klc_get_name(locale, layout, False),
+                display_name(layout, locale, self._bundle.project),
+            )
         )
 
         copyright_ = self._bundle.project.copyright or r"¯\_(ツ)_/¯"
@@ -1008,6 +1899,8 @@ Source: "{#BuildDir}\\wow64\\*"; DestDir: "{syswow64}"; Check: Is64BitInstallMod
         caps = mode_iter(locale, layout, "caps", "win")
         caps_shift = mode_iter(locale, layout, "caps+shift", "win")
 
+        no_caps_lock = "no-caps-lock" in (layout.features or [])
+
         # Hold all the glyphbombs
         glyphbombs = []
 
@@ -1025,7 +1918,9 @@ Source: "{#BuildDir}\\wow64\\*"; DestDir: "{syswow64}"; Check: Is64BitInstallMod
         ):
 
             cap_mode = 0
-            if cap is not None and c0 != cap and c1 != cap:
+            if no_caps_lock:
+                pass
+            elif cap is not None and c0 != cap and c1 != cap:
                 cap_mode = "SGCap"
             elif cap is None:
                 cap_mode += 1 if c0 != c1 else 0
@@ -1118,11 +2013,16 @@ Source: "{#BuildDir}\\wow64\\*"; DestDir: "{syswow64}"; Check: Is64BitInstallMod
             buf.write("\n")
 
         # Deadkeys!
-        transforms = layout.transforms or {}
+        transforms = symmetric_transforms(layout)
         for basekey, o in transforms.items():
+            if dead_key_chain(layout, basekey) is not None:
+                continue
             if len(basekey) != 1:
-                logger.warning(
-                    ("Base key '%s' invalid for Windows deadkeys; skipping.") % basekey
+                report_loss(
+                    locale,
+                    "win",
+                    "dead key `%s`" % basekey,
+                    "Windows dead keys must be a single character",
                 )
                 continue
 
@@ -1135,9 +2035,11 @@ Source: "{#BuildDir}\\wow64\\*"; DestDir: "{syswow64}"; Check: Is64BitInstallMod
                 output = str(output)
 
                 if len(key) != 1 or len(output) != 1:
-                    logger.debug(
-                        ("%s%s -> %s is invalid for Windows " + "deadkeys; skipping.")
-                        % (basekey, key, output)
+                    report_loss(
+                        locale,
+                        "win",
+                        "transform `%s%s` -> `%s`" % (basekey, key, output),
+                        "Windows dead keys only take and type a single character",
                     )
                     continue
                 buf.write(
@@ -1145,20 +2047,19 @@ Source: "{#BuildDir}\\wow64\\*"; DestDir: "{syswow64}"; Check: Is64BitInstallMod
                     % (win_filter(key, output, force=True) + (key, output))
                 )
 
-            # Create fallback key from space, or the basekey.
-            output = o.get(" ", basekey)
+            # Create fallback key from the terminator, space, or the basekey.
+            output = dead_key_terminator(layout, basekey)
             buf.write("0020\t%s\t//   -> %s\n\n" % (win_filter(output)[0], output))
 
     def _klc_write_deadkey_names(self, layout, buf):
         buf.write("KEYNAME_DEAD\n\n")
 
-        transforms = layout.transforms or {}
+        transforms = symmetric_transforms(layout)
         for basekey, o in transforms.items():
+            if dead_key_chain(layout, basekey) is not None:
+                continue
             if len(basekey) != 1:
-                logger.warning(
-                    ("Base key '%s' invalid for Windows " + "deadkeys; skipping.")
-                    % basekey
-                )
+                # Reported as a loss with the dead keys themselves
                 continue
 
             buf.write(
@@ -1168,7 +2069,7 @@ Source: "{#BuildDir}\\wow64\\*"; DestDir: "{syswow64}"; Check: Is64BitInstallMod
     def _klc_write_footer(self, locale, layout, buf):
         language_name = self.layout_target(layout).get("languageName", "Undefined")
         lcid = lcid_get(locale) or 0x0C00
-        layout_name = layout.display_names[locale]
+        layout_name = display_name(layout, locale, self._bundle.project)
 
         buf.write("\nDESCRIPTIONS\n\n")
         buf.write("%04x\t%s\n" % (lcid, layout_name))
@@ -1181,6 +2082,8 @@ Source: "{#BuildDir}\\wow64\\*"; DestDir: "{syswow64}"; Check: Is64BitInstallMod
     def generate_klc(self, locale, layout):
         buf = io.StringIO()
 
+        buf.write(stamp_comment("//", locale))
+        buf.write(license_comment("//", self._bundle.project, layout))
         self._klc_write_headers(locale, layout, buf)
         self._klc_write_keys(locale, layout, buf)
         buf.write(DEFAULT_KEYNAMES)
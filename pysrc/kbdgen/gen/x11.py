@@ -2,7 +2,18 @@ import os
 from collections import OrderedDict
 
 from ..base import get_logger
-from .base import Generator, filepath, mode_iter, ISO_KEYS, get_bin_resource
+from .base import (
+    Generator,
+    filepath,
+    mode_iter,
+    has_modes,
+    ISO_KEYS,
+    get_bin_resource,
+    warn_ignored_transform_partial,
+    warn_unsupported_dead_key_chains,
+    warn_unsupported_reordering,
+    display_name,
+)
 from ..cldr import CP_REGEX
 
 logger = get_logger(__name__)
@@ -31,8 +42,7 @@ class XKBGenerator(Generator):
     def supported_layouts(self):
         o = OrderedDict()
         for k, v in self._bundle.layouts.items():
-            # TODO: we might need an X11 target
-            if "win" in v.modes or "desktop" in v.modes:
+            if has_modes(v, "x11"):
                 o[k] = v
         return o
 
@@ -56,6 +66,9 @@ class XKBGenerator(Generator):
         self.xcompose = open(xcompose_fn, "w", encoding="utf-8")
 
         for name, layout in self.supported_layouts.items():
+            warn_unsupported_reordering(name, layout, "x11")
+            warn_unsupported_dead_key_chains(name, layout, "x11")
+            warn_ignored_transform_partial(name, layout, "x11")
             self.write_nonsense(name, layout)
 
         self.xkb.close()
@@ -86,12 +99,15 @@ class XKBGenerator(Generator):
         buf.write("default partial alphanumeric_keys\n")
         buf.write('xkb_symbols "basic" {\n\n')
         buf.write('    include "latin"\n')
-        buf.write('    name[Group1] = "%s";\n\n' % layout.display_names[name])
-
-        col0 = mode_iter(name, layout, "default", "win", required=True)
-        col1 = mode_iter(name, layout, "shift", "win")
-        col2 = mode_iter(name, layout, "alt", "win")
-        col3 = mode_iter(name, layout, "alt+shift", "win")
+        buf.write(
+            '    name[Group1] = "%s";\n\n'
+            % display_name(layout, name, self._bundle.project)
+        )
+
+        col0 = mode_iter(name, layout, "default", "x11", required=True)
+        col1 = mode_iter(name, layout, "shift", "x11")
+        col2 = mode_iter(name, layout, "alt", "x11")
+        col3 = mode_iter(name, layout, "alt+shift", "x11")
 
         def xkb_filter(self, *args):
             out = [self.filter_xkb_keysyms(i) for i in args]
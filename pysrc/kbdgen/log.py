@@ -15,7 +15,7 @@ class RustLogger:
         try:
             mod, lno = self._find_caller()
         except Exception as e:
-            print(e)
+            print(e, file=sys.stderr)
             mod, lno = None, None
 
         if not isinstance(msg, str):
@@ -45,12 +45,35 @@ class ProjectDesc:
 
 
 class Project:
-    def __init__(self, locales, author, email, copyright, organisation):
+    def __init__(
+        self,
+        locales,
+        author,
+        email,
+        copyright,
+        organisation,
+        license,
+        attributions,
+        default_locale,
+        locale_fallbacks,
+        homepage,
+        support_url,
+        privacy_policy_url,
+        render,
+    ):
         self.locales = locales
         self.author = author
         self.email = email
         self.copyright = copyright
         self.organisation = organisation
+        self.license = license
+        self.attributions = attributions
+        self.default_locale = default_locale
+        self.locale_fallbacks = locale_fallbacks
+        self.homepage = homepage
+        self.support_url = support_url
+        self.privacy_policy_url = privacy_policy_url
+        self.render = render
 
     def get_locales(self):
         return self.locales
@@ -67,6 +90,54 @@ class Project:
     def get_organisation(self):
         return self.organisation
 
+    def get_license(self):
+        """
+    The license of the layouts, as an SPDX identifier or a short text.
+    """
+        return self.license
+
+    def get_attributions(self):
+        """
+    Credits for work the layouts are based on.
+    """
+        return self.attributions
+
+    def get_default_locale(self):
+        """
+    The locale to take display names from before falling back to `en`.
+    """
+        return self.default_locale
+
+    def get_locale_fallbacks(self):
+        """
+    More locales to try for a locale, after it and before its truncations.
+    """
+        return self.locale_fallbacks
+
+    def get_homepage(self):
+        """
+    The website of the project.
+    """
+        return self.homepage
+
+    def get_support_url(self):
+        """
+    Where users get help. App store releases need it.
+    """
+        return self.support_url
+
+    def get_privacy_policy_url(self):
+        """
+    The privacy policy. App store releases need it.
+    """
+        return self.privacy_policy_url
+
+    def get_render(self):
+        """
+    How the keyboards of the svg target look.
+    """
+        return self.render
+
     @staticmethod
     def decode(data):
         f_locales = data["locales"]
@@ -104,7 +175,118 @@ class Project:
         if not isinstance(f_organisation, str):
             raise Exception("not a string")
 
-        return Project(f_locales, f_author, f_email, f_copyright, f_organisation)
+        f_license = None
+
+        if "license" in data:
+            f_license = data["license"]
+
+            if f_license is not None:
+                if not isinstance(f_license, str):
+                    raise Exception("not a string")
+
+        f_attributions = None
+
+        if "attributions" in data:
+            f_attributions = data["attributions"]
+
+            if f_attributions is not None:
+                if not isinstance(f_attributions, list):
+                    raise Exception("not an array")
+
+                _l0 = []
+
+                for _v0 in f_attributions:
+                    if not isinstance(_v0, str):
+                        raise Exception("not a string")
+                    _l0.append(_v0)
+
+                f_attributions = _l0
+
+        f_default_locale = None
+
+        if "defaultLocale" in data:
+            f_default_locale = data["defaultLocale"]
+
+            if f_default_locale is not None:
+                if not isinstance(f_default_locale, str):
+                    raise Exception("not a string")
+
+        f_locale_fallbacks = None
+
+        if "localeFallbacks" in data:
+            f_locale_fallbacks = data["localeFallbacks"]
+
+            if f_locale_fallbacks is not None:
+                if not isinstance(f_locale_fallbacks, dict):
+                    raise Exception("not an object")
+
+                _o0 = {}
+
+                for _k0, _v0 in f_locale_fallbacks.items():
+                    if not isinstance(_k0, str):
+                        raise Exception("not a string")
+
+                    if not isinstance(_v0, list):
+                        raise Exception("not an array")
+
+                    _l1 = []
+
+                    for _v1 in _v0:
+                        if not isinstance(_v1, str):
+                            raise Exception("not a string")
+                        _l1.append(_v1)
+
+                    _o0[_k0] = _l1
+
+                f_locale_fallbacks = _o0
+
+        f_homepage = None
+
+        if "homepage" in data:
+            f_homepage = data["homepage"]
+
+            if f_homepage is not None:
+                if not isinstance(f_homepage, str):
+                    raise Exception("not a string")
+
+        f_support_url = None
+
+        if "supportUrl" in data:
+            f_support_url = data["supportUrl"]
+
+            if f_support_url is not None:
+                if not isinstance(f_support_url, str):
+                    raise Exception("not a string")
+
+        f_privacy_policy_url = None
+
+        if "privacyPolicyUrl" in data:
+            f_privacy_policy_url = data["privacyPolicyUrl"]
+
+            if f_privacy_policy_url is not None:
+                if not isinstance(f_privacy_policy_url, str):
+                    raise Exception("not a string")
+
+        f_render = None
+
+        if "render" in data:
+            f_render = data["render"]
+
+        return Project(
+            f_locales,
+            f_author,
+            f_email,
+            f_copyright,
+            f_organisation,
+            f_license,
+            f_attributions,
+            f_default_locale,
+            f_locale_fallbacks,
+            f_homepage,
+            f_support_url,
+            f_privacy_policy_url,
+            f_render,
+        )
 
     def encode(self):
         data = dict()
@@ -134,11 +316,47 @@ class Project:
 
         data["organisation"] = self.organisation
 
+        if self.license is not None:
+            data["license"] = self.license
+
+        if self.attributions is not None:
+            data["attributions"] = self.attributions
+
+        if self.default_locale is not None:
+            data["defaultLocale"] = self.default_locale
+
+        if self.locale_fallbacks is not None:
+            data["localeFallbacks"] = self.locale_fallbacks
+
+        if self.homepage is not None:
+            data["homepage"] = self.homepage
+
+        if self.support_url is not None:
+            data["supportUrl"] = self.support_url
+
+        if self.privacy_policy_url is not None:
+            data["privacyPolicyUrl"] = self.privacy_policy_url
+
+        if self.render is not None:
+            data["render"] = self.render
+
         return data
 
     def __repr__(self):
-        return "<Project locales:{!r}, author:{!r}, email:{!r}, copyright:{!r}, organisation:{!r}>".format(
-            self.locales, self.author, self.email, self.copyright, self.organisation
+        return "<Project locales:{!r}, author:{!r}, email:{!r}, copyright:{!r}, organisation:{!r}, license:{!r}, attributions:{!r}, default_locale:{!r}, locale_fallbacks:{!r}, homepage:{!r}, support_url:{!r}, privacy_policy_url:{!r}, render:{!r}>".format(
+            self.locales,
+            self.author,
+            self.email,
+            self.copyright,
+            self.organisation,
+            self.license,
+            self.attributions,
+            self.default_locale,
+            self.locale_fallbacks,
+            self.homepage,
+            self.support_url,
+            self.privacy_policy_url,
+            self.render,
         )
 
 
@@ -189,12 +407,26 @@ class LayoutStrings:
 
 
 class DeriveOptions:
-    def __init__(self, transforms):
+    def __init__(self, transforms, mobile, desktop):
         self.transforms = transforms
+        self.mobile = mobile
+        self.desktop = desktop
 
     def get_transforms(self):
         return self.transforms
 
+    def get_mobile(self):
+        """
+    Derive mobile modes from the desktop default and shift modes, either true or a map of options.
+    """
+        return self.mobile
+
+    def get_desktop(self):
+        """
+    Derive desktop modes from the mobile default and shift modes, either true or a map of options.
+    """
+        return self.desktop
+
     @staticmethod
     def decode(data):
         f_transforms = None
@@ -206,7 +438,17 @@ class DeriveOptions:
                 if not isinstance(f_transforms, bool):
                     raise Exception("not a boolean")
 
-        return DeriveOptions(f_transforms)
+        f_mobile = None
+
+        if "mobile" in data:
+            f_mobile = data["mobile"]
+
+        f_desktop = None
+
+        if "desktop" in data:
+            f_desktop = data["desktop"]
+
+        return DeriveOptions(f_transforms, f_mobile, f_desktop)
 
     def encode(self):
         data = dict()
@@ -214,35 +456,71 @@ class DeriveOptions:
         if self.transforms is not None:
             data["transforms"] = self.transforms
 
+        if self.mobile is not None:
+            data["mobile"] = self.mobile
+
+        if self.desktop is not None:
+            data["desktop"] = self.desktop
+
         return data
 
     def __repr__(self):
-        return "<DeriveOptions transforms:{!r}>".format(self.transforms)
+        return "<DeriveOptions transforms:{!r}, mobile:{!r}, desktop:{!r}>".format(
+            self.transforms, self.mobile, self.desktop
+        )
 
 
 class Layout:
     def __init__(
         self,
         display_names,
+        license,
+        attributions,
         modes,
         decimal,
         space,
         dead_keys,
+        dead_key_options,
+        dead_key_terminators,
         longpress,
+        flicks,
+        accessibility_labels,
+        display_overrides,
         transforms,
+        transform_partial,
+        symmetric_transforms,
+        reorders,
+        backspaces,
         strings,
         derive,
+        features,
+        rtl,
+        modifier_remaps,
         targets,
     ):
         self.display_names = display_names
+        self.license = license
+        self.attributions = attributions
         self.modes = modes
         self.decimal = decimal
         self.space = space
         self.dead_keys = dead_keys
+        self.dead_key_options = dead_key_options
+        self.dead_key_terminators = dead_key_terminators
         self.longpress = longpress
+        self.flicks = flicks
+        self.accessibility_labels = accessibility_labels
+        self.display_overrides = display_overrides
         self.transforms = transforms
+        self.transform_partial = transform_partial
+        self.symmetric_transforms = symmetric_transforms
+        self.reorders = reorders
+        self.backspaces = backspaces
         self.strings = strings
         self.derive = derive
+        self.features = features
+        self.rtl = rtl
+        self.modifier_remaps = modifier_remaps
         self.targets = targets
 
     def get_display_names(self):
@@ -251,6 +529,18 @@ class Layout:
     """
         return self.display_names
 
+    def get_license(self):
+        """
+    The license of this layout, instead of the project's.
+    """
+        return self.license
+
+    def get_attributions(self):
+        """
+    Credits for work this layout is based on, in addition to the project's.
+    """
+        return self.attributions
+
     def get_modes(self):
         """
     The different modes.
@@ -275,18 +565,72 @@ class Layout:
     """
         return self.dead_keys
 
+    def get_dead_key_options(self):
+        """
+    Options for individual dead keys, keyed by the dead key.
+    """
+        return self.dead_key_options
+
+    def get_dead_key_terminators(self):
+        """
+    What each dead key types on its own, keyed by the dead key.
+    """
+        return self.dead_key_terminators
+
     def get_longpress(self):
         """
     The items to be shown when a key is long-pressed. Values are space separated in one string.
     """
         return self.longpress
 
+    def get_flicks(self):
+        """
+    The items typed by flicking a key up, down, left or right, keyed by the key's tap output.
+    """
+        return self.flicks
+
+    def get_accessibility_labels(self):
+        """
+    What screen readers say for a key, keyed by the key's output.
+    """
+        return self.accessibility_labels
+
+    def get_display_overrides(self):
+        """
+    What to draw on a key instead of its output, keyed by the output.
+    """
+        return self.display_overrides
+
     def get_transforms(self):
         """
     The chain of inputs necessary to provide an output after a deadkey is pressed. Keyed by each individual input.
     """
         return self.transforms
 
+    def get_transform_partial(self):
+        """
+    Whether a transform in progress is shown before it is finished, hide or show, as in CLDR.
+    """
+        return self.transform_partial
+
+    def get_symmetric_transforms(self):
+        """
+    Whether transforms of a combining mark and a base character can also be typed the other way round.
+    """
+        return self.symmetric_transforms
+
+    def get_reorders(self):
+        """
+    Rules for putting characters typed out of order into their stored order, as in CLDR.
+    """
+        return self.reorders
+
+    def get_backspaces(self):
+        """
+    Text before the cursor that a single backspace deletes or replaces, keyed by the text to match.
+    """
+        return self.backspaces
+
     def get_strings(self):
         """
     Strings to be shown on some OSes
@@ -299,6 +643,24 @@ class Layout:
     """
         return self.derive
 
+    def get_features(self):
+        """
+    Flags that change how generators treat the layout, like no-autocorrect.
+    """
+        return self.features
+
+    def get_rtl(self):
+        """
+    Whether the layout is for a right-to-left script.
+    """
+        return self.rtl
+
+    def get_modifier_remaps(self):
+        """
+    Other roles for modifier keys, like caps: altgr, keyed by target and then by the key.
+    """
+        return self.modifier_remaps
+
     def get_targets(self):
         """
     Targets...
@@ -323,6 +685,33 @@ class Layout:
 
         f_display_names = _o0
 
+        f_license = None
+
+        if "license" in data:
+            f_license = data["license"]
+
+            if f_license is not None:
+                if not isinstance(f_license, str):
+                    raise Exception("not a string")
+
+        f_attributions = None
+
+        if "attributions" in data:
+            f_attributions = data["attributions"]
+
+            if f_attributions is not None:
+                if not isinstance(f_attributions, list):
+                    raise Exception("not an array")
+
+                _l0 = []
+
+                for _v0 in f_attributions:
+                    if not isinstance(_v0, str):
+                        raise Exception("not a string")
+                    _l0.append(_v0)
+
+                f_attributions = _l0
+
         f_modes = data["modes"]
 
         if not isinstance(f_modes, dict):
@@ -382,6 +771,44 @@ class Layout:
 
                 f_dead_keys = _o0
 
+        f_dead_key_options = None
+
+        if "deadKeyOptions" in data:
+            f_dead_key_options = data["deadKeyOptions"]
+
+            if f_dead_key_options is not None:
+                if not isinstance(f_dead_key_options, dict):
+                    raise Exception("not an object")
+
+                _o0 = {}
+
+                for _k0, _v0 in f_dead_key_options.items():
+                    if not isinstance(_k0, str):
+                        raise Exception("not a string")
+                    _o0[_k0] = _v0
+
+                f_dead_key_options = _o0
+
+        f_dead_key_terminators = None
+
+        if "deadKeyTerminators" in data:
+            f_dead_key_terminators = data["deadKeyTerminators"]
+
+            if f_dead_key_terminators is not None:
+                if not isinstance(f_dead_key_terminators, dict):
+                    raise Exception("not an object")
+
+                _o0 = {}
+
+                for _k0, _v0 in f_dead_key_terminators.items():
+                    if not isinstance(_k0, str):
+                        raise Exception("not a string")
+                    if not isinstance(_v0, str):
+                        raise Exception("not a string")
+                    _o0[_k0] = _v0
+
+                f_dead_key_terminators = _o0
+
         f_longpress = None
 
         if "longpress" in data:
@@ -402,6 +829,64 @@ class Layout:
 
                 f_longpress = _o0
 
+        f_flicks = None
+
+        if "flicks" in data:
+            f_flicks = data["flicks"]
+
+            if f_flicks is not None:
+                if not isinstance(f_flicks, dict):
+                    raise Exception("not an object")
+
+                _o0 = {}
+
+                for _k0, _v0 in f_flicks.items():
+                    if not isinstance(_k0, str):
+                        raise Exception("not a string")
+                    _o0[_k0] = _v0
+
+                f_flicks = _o0
+
+        f_accessibility_labels = None
+
+        if "accessibilityLabels" in data:
+            f_accessibility_labels = data["accessibilityLabels"]
+
+            if f_accessibility_labels is not None:
+                if not isinstance(f_accessibility_labels, dict):
+                    raise Exception("not an object")
+
+                _o0 = {}
+
+                for _k0, _v0 in f_accessibility_labels.items():
+                    if not isinstance(_k0, str):
+                        raise Exception("not a string")
+                    if not isinstance(_v0, str):
+                        raise Exception("not a string")
+                    _o0[_k0] = _v0
+
+                f_accessibility_labels = _o0
+
+        f_display_overrides = None
+
+        if "displayOverrides" in data:
+            f_display_overrides = data["displayOverrides"]
+
+            if f_display_overrides is not None:
+                if not isinstance(f_display_overrides, dict):
+                    raise Exception("not an object")
+
+                _o0 = {}
+
+                for _k0, _v0 in f_display_overrides.items():
+                    if not isinstance(_k0, str):
+                        raise Exception("not a string")
+                    if not isinstance(_v0, str):
+                        raise Exception("not a string")
+                    _o0[_k0] = _v0
+
+                f_display_overrides = _o0
+
         f_transforms = None
 
         if "transforms" in data:
@@ -420,6 +905,60 @@ class Layout:
 
                 f_transforms = _o0
 
+        f_transform_partial = None
+
+        if "transformPartial" in data:
+            f_transform_partial = data["transformPartial"]
+
+            if f_transform_partial is not None:
+                if not isinstance(f_transform_partial, str):
+                    raise Exception("not a string")
+
+        f_symmetric_transforms = None
+
+        if "symmetricTransforms" in data:
+            f_symmetric_transforms = data["symmetricTransforms"]
+
+            if f_symmetric_transforms is not None:
+                if not isinstance(f_symmetric_transforms, bool):
+                    raise Exception("not a boolean")
+
+        f_reorders = None
+
+        if "reorders" in data:
+            f_reorders = data["reorders"]
+
+            if f_reorders is not None:
+                if not isinstance(f_reorders, list):
+                    raise Exception("not an array")
+
+                _l0 = []
+
+                for _v0 in f_reorders:
+                    _l0.append(_v0)
+
+                f_reorders = _l0
+
+        f_backspaces = None
+
+        if "backspaces" in data:
+            f_backspaces = data["backspaces"]
+
+            if f_backspaces is not None:
+                if not isinstance(f_backspaces, dict):
+                    raise Exception("not an object")
+
+                _o0 = {}
+
+                for _k0, _v0 in f_backspaces.items():
+                    if not isinstance(_k0, str):
+                        raise Exception("not a string")
+                    if not isinstance(_v0, str):
+                        raise Exception("not a string")
+                    _o0[_k0] = _v0
+
+                f_backspaces = _o0
+
         f_strings = None
 
         if "strings" in data:
@@ -436,6 +975,51 @@ class Layout:
             if f_derive is not None:
                 f_derive = DeriveOptions.decode(f_derive)
 
+        f_features = None
+
+        if "features" in data:
+            f_features = data["features"]
+
+            if f_features is not None:
+                if not isinstance(f_features, list):
+                    raise Exception("not an array")
+
+                _l0 = []
+
+                for _v0 in f_features:
+                    if not isinstance(_v0, str):
+                        raise Exception("not a string")
+                    _l0.append(_v0)
+
+                f_features = _l0
+
+        f_rtl = None
+
+        if "rtl" in data:
+            f_rtl = data["rtl"]
+
+            if f_rtl is not None:
+                if not isinstance(f_rtl, bool):
+                    raise Exception("not a boolean")
+
+        f_modifier_remaps = None
+
+        if "modifierRemaps" in data:
+            f_modifier_remaps = data["modifierRemaps"]
+
+            if f_modifier_remaps is not None:
+                if not isinstance(f_modifier_remaps, dict):
+                    raise Exception("not an object")
+
+                _o0 = {}
+
+                for _k0, _v0 in f_modifier_remaps.items():
+                    if not isinstance(_k0, str):
+                        raise Exception("not a string")
+                    _o0[_k0] = _v0
+
+                f_modifier_remaps = _o0
+
         f_targets = None
 
         if "targets" in data:
@@ -456,14 +1040,28 @@ class Layout:
 
         return Layout(
             f_display_names,
+            f_license,
+            f_attributions,
             f_modes,
             f_decimal,
             f_space,
             f_dead_keys,
+            f_dead_key_options,
+            f_dead_key_terminators,
             f_longpress,
+            f_flicks,
+            f_accessibility_labels,
+            f_display_overrides,
             f_transforms,
+            f_transform_partial,
+            f_symmetric_transforms,
+            f_reorders,
+            f_backspaces,
             f_strings,
             f_derive,
+            f_features,
+            f_rtl,
+            f_modifier_remaps,
             f_targets,
         )
 
@@ -475,6 +1073,12 @@ class Layout:
 
         data["displayNames"] = self.display_names
 
+        if self.license is not None:
+            data["license"] = self.license
+
+        if self.attributions is not None:
+            data["attributions"] = self.attributions
+
         if self.modes is None:
             raise Exception("modes: is a required field")
 
@@ -489,152 +1093,384 @@ class Layout:
         if self.dead_keys is not None:
             data["deadKeys"] = self.dead_keys
 
+        if self.dead_key_options is not None:
+            data["deadKeyOptions"] = self.dead_key_options
+
+        if self.dead_key_terminators is not None:
+            data["deadKeyTerminators"] = self.dead_key_terminators
+
         if self.longpress is not None:
             data["longpress"] = self.longpress
 
+        if self.flicks is not None:
+            data["flicks"] = self.flicks
+
+        if self.accessibility_labels is not None:
+            data["accessibilityLabels"] = self.accessibility_labels
+
+        if self.display_overrides is not None:
+            data["displayOverrides"] = self.display_overrides
+
         if self.transforms is not None:
             data["transforms"] = self.transforms
 
+        if self.transform_partial is not None:
+            data["transformPartial"] = self.transform_partial
+
+        if self.symmetric_transforms is not None:
+            data["symmetricTransforms"] = self.symmetric_transforms
+
+        if self.reorders is not None:
+            data["reorders"] = self.reorders
+
+        if self.backspaces is not None:
+            data["backspaces"] = self.backspaces
+
         if self.strings is not None:
             data["strings"] = self.strings.encode()
 
-        if self.derive is not None:
-            data["derive"] = self.derive.encode()
+        if self.derive is not None:
+            data["derive"] = self.derive.encode()
+
+        if self.features is not None:
+            data["features"] = self.features
+
+        if self.rtl is not None:
+            data["rtl"] = self.rtl
+
+        if self.modifier_remaps is not None:
+            data["modifierRemaps"] = self.modifier_remaps
+
+        if self.targets is not None:
+            data["targets"] = self.targets
+
+        return data
+
+    def __repr__(self):
+        return "<Layout display_names:{!r}, license:{!r}, attributions:{!r}, modes:{!r}, decimal:{!r}, space:{!r}, dead_keys:{!r}, dead_key_options:{!r}, dead_key_terminators:{!r}, longpress:{!r}, flicks:{!r}, accessibility_labels:{!r}, display_overrides:{!r}, transforms:{!r}, transform_partial:{!r}, symmetric_transforms:{!r}, reorders:{!r}, backspaces:{!r}, strings:{!r}, derive:{!r}, features:{!r}, rtl:{!r}, modifier_remaps:{!r}, targets:{!r}>".format(
+            self.display_names,
+            self.license,
+            self.attributions,
+            self.modes,
+            self.decimal,
+            self.space,
+            self.dead_keys,
+            self.dead_key_options,
+            self.dead_key_terminators,
+            self.longpress,
+            self.flicks,
+            self.accessibility_labels,
+            self.display_overrides,
+            self.transforms,
+            self.transform_partial,
+            self.symmetric_transforms,
+            self.reorders,
+            self.backspaces,
+            self.strings,
+            self.derive,
+            self.features,
+            self.rtl,
+            self.modifier_remaps,
+            self.targets,
+        )
+
+
+class LayoutTargetWindows:
+    def __init__(self, locale, language_name):
+        self.locale = locale
+        self.language_name = language_name
+
+    def get_locale(self):
+        """
+    The actual locale within Windows, as per their broken ISO 639-3 scheme or secret hardcoded lists.
+    """
+        return self.locale
+
+    def get_language_name(self):
+        """
+    The language name to be cached, in order to try to mask the ugly ISO code name that often shows.
+    """
+        return self.language_name
+
+    @staticmethod
+    def decode(data):
+        f_locale = data["locale"]
+
+        if not isinstance(f_locale, str):
+            raise Exception("not a string")
+
+        f_language_name = data["languageName"]
+
+        if not isinstance(f_language_name, str):
+            raise Exception("not a string")
+
+        return LayoutTargetWindows(f_locale, f_language_name)
+
+    def encode(self):
+        data = dict()
+
+        if self.locale is None:
+            raise Exception("locale: is a required field")
+
+        data["locale"] = self.locale
+
+        if self.language_name is None:
+            raise Exception("languageName: is a required field")
+
+        data["languageName"] = self.language_name
+
+        return data
+
+    def __repr__(self):
+        return "<LayoutTargetWindows locale:{!r}, language_name:{!r}>".format(
+            self.locale, self.language_name
+        )
+
+
+class LayoutTargetAndroid:
+    def __init__(self, minimum_sdk, theme):
+        self.minimum_sdk = minimum_sdk
+        self.theme = theme
+
+    def get_minimum_sdk(self):
+        """
+    Minimum SDK can be specified for a specific layout
+    """
+        return self.minimum_sdk
+
+    def get_theme(self):
+        """
+    How the keys look: their colors in light and dark mode, how round their corners are and the font of their labels.
+    """
+        return self.theme
+
+    @staticmethod
+    def decode(data):
+        f_minimum_sdk = None
+
+        if "minimumSdk" in data:
+            f_minimum_sdk = data["minimumSdk"]
+
+            if f_minimum_sdk is not None:
+                if not isinstance(f_minimum_sdk, int):
+                    raise Exception("not an integer")
+
+        f_theme = None
+
+        if "theme" in data:
+            f_theme = data["theme"]
+
+            if f_theme is not None:
+                if not isinstance(f_theme, dict):
+                    raise Exception("not an object")
+
+                _o0 = {}
+
+                for _k0, _v0 in f_theme.items():
+                    if not isinstance(_k0, str):
+                        raise Exception("not a string")
+                    _o0[_k0] = _v0
+
+                f_theme = _o0
+
+        return LayoutTargetAndroid(f_minimum_sdk, f_theme)
+
+    def encode(self):
+        data = dict()
+
+        if self.minimum_sdk is not None:
+            data["minimumSdk"] = self.minimum_sdk
+
+        if self.theme is not None:
+            data["theme"] = self.theme
+
+        return data
+
+    def __repr__(self):
+        return "<LayoutTargetAndroid minimum_sdk:{!r}, theme:{!r}>".format(
+            self.minimum_sdk, self.theme
+        )
+
+
+class Spacebar:
+    def __init__(self, language_switch, label):
+        self.language_switch = language_switch
+        self.label = label
+
+    def get_language_switch(self):
+        return self.language_switch
+
+    def get_label(self):
+        return self.label
+
+    @staticmethod
+    def decode(data):
+        f_language_switch = None
+
+        if "languageSwitch" in data:
+            f_language_switch = data["languageSwitch"]
+
+            if f_language_switch is not None:
+                if not isinstance(f_language_switch, bool):
+                    raise Exception("not a boolean")
+
+        f_label = None
+
+        if "label" in data:
+            f_label = data["label"]
+
+            if f_label is not None:
+                if not isinstance(f_label, str):
+                    raise Exception("not a string")
+
+        return Spacebar(f_language_switch, f_label)
+
+    def encode(self):
+        data = dict()
+
+        if self.language_switch is not None:
+            data["languageSwitch"] = self.language_switch
 
-        if self.targets is not None:
-            data["targets"] = self.targets
+        if self.label is not None:
+            data["label"] = self.label
 
         return data
 
     def __repr__(self):
-        return "<Layout display_names:{!r}, modes:{!r}, decimal:{!r}, space:{!r}, dead_keys:{!r}, longpress:{!r}, transforms:{!r}, strings:{!r}, derive:{!r}, targets:{!r}>".format(
-            self.display_names,
-            self.modes,
-            self.decimal,
-            self.space,
-            self.dead_keys,
-            self.longpress,
-            self.transforms,
-            self.strings,
-            self.derive,
-            self.targets,
+        return "<Spacebar language_switch:{!r}, label:{!r}>".format(
+            self.language_switch, self.label
         )
 
 
-class LayoutTargetWindows:
-    def __init__(self, locale, language_name):
-        self.locale = locale
-        self.language_name = language_name
+class KeyFeedback:
+    def __init__(self, sound, haptics, per_locale):
+        self.sound = sound
+        self.haptics = haptics
+        self.per_locale = per_locale
 
-    def get_locale(self):
-        """
-    The actual locale within Windows, as per their broken ISO 639-3 scheme or secret hardcoded lists.
-    """
-        return self.locale
+    def get_sound(self):
+        return self.sound
 
-    def get_language_name(self):
-        """
-    The language name to be cached, in order to try to mask the ugly ISO code name that often shows.
-    """
-        return self.language_name
+    def get_haptics(self):
+        return self.haptics
+
+    def get_per_locale(self):
+        return self.per_locale
 
     @staticmethod
     def decode(data):
-        f_locale = data["locale"]
+        f_sound = None
 
-        if not isinstance(f_locale, str):
-            raise Exception("not a string")
+        if "sound" in data:
+            f_sound = data["sound"]
 
-        f_language_name = data["languageName"]
+            if f_sound is not None:
+                if not isinstance(f_sound, bool):
+                    raise Exception("not a boolean")
 
-        if not isinstance(f_language_name, str):
-            raise Exception("not a string")
+        f_haptics = None
 
-        return LayoutTargetWindows(f_locale, f_language_name)
+        if "haptics" in data:
+            f_haptics = data["haptics"]
+
+            if f_haptics is not None:
+                if not isinstance(f_haptics, bool):
+                    raise Exception("not a boolean")
+
+        f_per_locale = None
+
+        if "perLocale" in data:
+            f_per_locale = data["perLocale"]
+
+            if f_per_locale is not None:
+                if not isinstance(f_per_locale, bool):
+                    raise Exception("not a boolean")
+
+        return KeyFeedback(f_sound, f_haptics, f_per_locale)
 
     def encode(self):
         data = dict()
 
-        if self.locale is None:
-            raise Exception("locale: is a required field")
-
-        data["locale"] = self.locale
+        if self.sound is not None:
+            data["sound"] = self.sound
 
-        if self.language_name is None:
-            raise Exception("languageName: is a required field")
+        if self.haptics is not None:
+            data["haptics"] = self.haptics
 
-        data["languageName"] = self.language_name
+        if self.per_locale is not None:
+            data["perLocale"] = self.per_locale
 
         return data
 
     def __repr__(self):
-        return "<LayoutTargetWindows locale:{!r}, language_name:{!r}>".format(
-            self.locale, self.language_name
+        return "<KeyFeedback sound:{!r}, haptics:{!r}, per_locale:{!r}>".format(
+            self.sound, self.haptics, self.per_locale
         )
 
 
-class LayoutTargetAndroid:
-    def __init__(self, minimum_sdk, style):
-        self.minimum_sdk = minimum_sdk
-        self.style = style
+class Analytics:
+    def __init__(self, prefix, layers, longpress):
+        self.prefix = prefix
+        self.layers = layers
+        self.longpress = longpress
 
-    def get_minimum_sdk(self):
-        """
-    Minimum SDK can be specified for a specific layout
-    """
-        return self.minimum_sdk
+    def get_prefix(self):
+        return self.prefix
 
-    def get_style(self):
-        """
-    Styles
-    """
-        return self.style
+    def get_layers(self):
+        return self.layers
+
+    def get_longpress(self):
+        return self.longpress
 
     @staticmethod
     def decode(data):
-        f_minimum_sdk = None
+        f_prefix = None
 
-        if "minimumSdk" in data:
-            f_minimum_sdk = data["minimumSdk"]
+        if "prefix" in data:
+            f_prefix = data["prefix"]
 
-            if f_minimum_sdk is not None:
-                if not isinstance(f_minimum_sdk, int):
-                    raise Exception("not an integer")
+            if f_prefix is not None:
+                if not isinstance(f_prefix, str):
+                    raise Exception("not a string")
 
-        f_style = None
+        f_layers = None
 
-        if "style" in data:
-            f_style = data["style"]
+        if "layers" in data:
+            f_layers = data["layers"]
 
-            if f_style is not None:
-                if not isinstance(f_style, dict):
-                    raise Exception("not an object")
+            if f_layers is not None:
+                if not isinstance(f_layers, bool):
+                    raise Exception("not a boolean")
 
-                _o0 = {}
+        f_longpress = None
 
-                for _k0, _v0 in f_style.items():
-                    if not isinstance(_k0, str):
-                        raise Exception("not a string")
-                    _o0[_k0] = _v0
+        if "longpress" in data:
+            f_longpress = data["longpress"]
 
-                f_style = _o0
+            if f_longpress is not None:
+                if not isinstance(f_longpress, bool):
+                    raise Exception("not a boolean")
 
-        return LayoutTargetAndroid(f_minimum_sdk, f_style)
+        return Analytics(f_prefix, f_layers, f_longpress)
 
     def encode(self):
         data = dict()
 
-        if self.minimum_sdk is not None:
-            data["minimumSdk"] = self.minimum_sdk
+        if self.prefix is not None:
+            data["prefix"] = self.prefix
 
-        if self.style is not None:
-            data["style"] = self.style
+        if self.layers is not None:
+            data["layers"] = self.layers
+
+        if self.longpress is not None:
+            data["longpress"] = self.longpress
 
         return data
 
     def __repr__(self):
-        return "<LayoutTargetAndroid minimum_sdk:{!r}, style:{!r}>".format(
-            self.minimum_sdk, self.style
+        return "<Analytics prefix:{!r}, layers:{!r}, longpress:{!r}>".format(
+            self.prefix, self.layers, self.longpress
         )
 
 
@@ -651,6 +1487,10 @@ class TargetAndroid:
         bhfst,
         key_store,
         key_alias,
+        spacebar,
+        feedback,
+        analytics,
+        fonts,
     ):
         self.version = version
         self.build = build
@@ -662,6 +1502,10 @@ class TargetAndroid:
         self.bhfst = bhfst
         self.key_store = key_store
         self.key_alias = key_alias
+        self.spacebar = spacebar
+        self.feedback = feedback
+        self.analytics = analytics
+        self.fonts = fonts
 
     def get_version(self):
         return self.version
@@ -693,6 +1537,18 @@ class TargetAndroid:
     def get_key_alias(self):
         return self.key_alias
 
+    def get_spacebar(self):
+        return self.spacebar
+
+    def get_feedback(self):
+        return self.feedback
+
+    def get_analytics(self):
+        return self.analytics
+
+    def get_fonts(self):
+        return self.fonts
+
     @staticmethod
     def decode(data):
         f_version = data["version"]
@@ -773,6 +1629,48 @@ class TargetAndroid:
                 if not isinstance(f_key_alias, str):
                     raise Exception("not a string")
 
+        f_spacebar = None
+
+        if "spacebar" in data:
+            f_spacebar = data["spacebar"]
+
+            if f_spacebar is not None:
+                f_spacebar = Spacebar.decode(f_spacebar)
+
+        f_feedback = None
+
+        if "feedback" in data:
+            f_feedback = data["feedback"]
+
+            if f_feedback is not None:
+                f_feedback = KeyFeedback.decode(f_feedback)
+
+        f_analytics = None
+
+        if "analytics" in data:
+            f_analytics = data["analytics"]
+
+            if f_analytics is not None:
+                f_analytics = Analytics.decode(f_analytics)
+
+        f_fonts = None
+
+        if "fonts" in data:
+            f_fonts = data["fonts"]
+
+            if f_fonts is not None:
+                if not isinstance(f_fonts, list):
+                    raise Exception("not an array")
+
+                _l0 = []
+
+                for _v0 in f_fonts:
+                    if not isinstance(_v0, str):
+                        raise Exception("not a string")
+                    _l0.append(_v0)
+
+                f_fonts = _l0
+
         return TargetAndroid(
             f_version,
             f_build,
@@ -784,6 +1682,10 @@ class TargetAndroid:
             f_bhfst,
             f_key_store,
             f_key_alias,
+            f_spacebar,
+            f_feedback,
+            f_analytics,
+            f_fonts,
         )
 
     def encode(self):
@@ -825,10 +1727,22 @@ class TargetAndroid:
         if self.key_alias is not None:
             data["keyAlias"] = self.key_alias
 
+        if self.spacebar is not None:
+            data["spacebar"] = self.spacebar.encode()
+
+        if self.feedback is not None:
+            data["feedback"] = self.feedback.encode()
+
+        if self.analytics is not None:
+            data["analytics"] = self.analytics.encode()
+
+        if self.fonts is not None:
+            data["fonts"] = self.fonts
+
         return data
 
     def __repr__(self):
-        return "<TargetAndroid version:{!r}, build:{!r}, package_id:{!r}, icon:{!r}, sentry_dsn:{!r}, show_number_hints:{!r}, minimum_sdk:{!r}, bhfst:{!r}, key_store:{!r}, key_alias:{!r}>".format(
+        return "<TargetAndroid version:{!r}, build:{!r}, package_id:{!r}, icon:{!r}, sentry_dsn:{!r}, show_number_hints:{!r}, minimum_sdk:{!r}, bhfst:{!r}, key_store:{!r}, key_alias:{!r}, spacebar:{!r}, feedback:{!r}, analytics:{!r}, fonts:{!r}>".format(
             self.version,
             self.build,
             self.package_id,
@@ -839,6 +1753,10 @@ class TargetAndroid:
             self.bhfst,
             self.key_store,
             self.key_alias,
+            self.spacebar,
+            self.feedback,
+            self.analytics,
+            self.fonts,
         )
 
 
@@ -855,6 +1773,10 @@ class TargetIOS:
         sentry_dsn,
         about_dir,
         bhfst,
+        spacebar,
+        feedback,
+        analytics,
+        fonts,
     ):
         self.version = version
         self.build = build
@@ -866,6 +1788,10 @@ class TargetIOS:
         self.sentry_dsn = sentry_dsn
         self.about_dir = about_dir
         self.bhfst = bhfst
+        self.spacebar = spacebar
+        self.feedback = feedback
+        self.analytics = analytics
+        self.fonts = fonts
 
     def get_version(self):
         return self.version
@@ -897,6 +1823,18 @@ class TargetIOS:
     def get_bhfst(self):
         return self.bhfst
 
+    def get_spacebar(self):
+        return self.spacebar
+
+    def get_feedback(self):
+        return self.feedback
+
+    def get_analytics(self):
+        return self.analytics
+
+    def get_fonts(self):
+        return self.fonts
+
     @staticmethod
     def decode(data):
         f_version = data["version"]
@@ -973,6 +1911,48 @@ class TargetIOS:
                 if not isinstance(f_bhfst, bool):
                     raise Exception("not a boolean")
 
+        f_spacebar = None
+
+        if "spacebar" in data:
+            f_spacebar = data["spacebar"]
+
+            if f_spacebar is not None:
+                f_spacebar = Spacebar.decode(f_spacebar)
+
+        f_feedback = None
+
+        if "feedback" in data:
+            f_feedback = data["feedback"]
+
+            if f_feedback is not None:
+                f_feedback = KeyFeedback.decode(f_feedback)
+
+        f_analytics = None
+
+        if "analytics" in data:
+            f_analytics = data["analytics"]
+
+            if f_analytics is not None:
+                f_analytics = Analytics.decode(f_analytics)
+
+        f_fonts = None
+
+        if "fonts" in data:
+            f_fonts = data["fonts"]
+
+            if f_fonts is not None:
+                if not isinstance(f_fonts, list):
+                    raise Exception("not an array")
+
+                _l0 = []
+
+                for _v0 in f_fonts:
+                    if not isinstance(_v0, str):
+                        raise Exception("not a string")
+                    _l0.append(_v0)
+
+                f_fonts = _l0
+
         return TargetIOS(
             f_version,
             f_build,
@@ -984,6 +1964,10 @@ class TargetIOS:
             f_sentry_dsn,
             f_about_dir,
             f_bhfst,
+            f_spacebar,
+            f_feedback,
+            f_analytics,
+            f_fonts,
         )
 
     def encode(self):
@@ -1027,10 +2011,22 @@ class TargetIOS:
         if self.bhfst is not None:
             data["bhfst"] = self.bhfst
 
+        if self.spacebar is not None:
+            data["spacebar"] = self.spacebar.encode()
+
+        if self.feedback is not None:
+            data["feedback"] = self.feedback.encode()
+
+        if self.analytics is not None:
+            data["analytics"] = self.analytics.encode()
+
+        if self.fonts is not None:
+            data["fonts"] = self.fonts
+
         return data
 
     def __repr__(self):
-        return "<TargetIOS version:{!r}, build:{!r}, package_id:{!r}, icon:{!r}, bundle_name:{!r}, team_id:{!r}, code_sign_id:{!r}, sentry_dsn:{!r}, about_dir:{!r}, bhfst:{!r}>".format(
+        return "<TargetIOS version:{!r}, build:{!r}, package_id:{!r}, icon:{!r}, bundle_name:{!r}, team_id:{!r}, code_sign_id:{!r}, sentry_dsn:{!r}, about_dir:{!r}, bhfst:{!r}, spacebar:{!r}, feedback:{!r}, analytics:{!r}, fonts:{!r}>".format(
             self.version,
             self.build,
             self.package_id,
@@ -1041,6 +2037,10 @@ class TargetIOS:
             self.sentry_dsn,
             self.about_dir,
             self.bhfst,
+            self.spacebar,
+            self.feedback,
+            self.analytics,
+            self.fonts,
         )
 
 
@@ -1055,6 +2055,10 @@ class TargetWindows:
         custom_locales,
         license_path,
         readme_path,
+        compat_map,
+        packager,
+        publisher,
+        architectures,
     ):
         self.version = version
         self.app_name = app_name
@@ -1064,6 +2068,10 @@ class TargetWindows:
         self.custom_locales = custom_locales
         self.license_path = license_path
         self.readme_path = readme_path
+        self.compat_map = compat_map
+        self.packager = packager
+        self.publisher = publisher
+        self.architectures = architectures
 
     def get_version(self):
         return self.version
@@ -1089,6 +2097,30 @@ class TargetWindows:
     def get_readme_path(self):
         return self.readme_path
 
+    def get_compat_map(self):
+        """
+    Characters to replace in an additional layout for legacy applications, keyed by the character to replace.
+    """
+        return self.compat_map
+
+    def get_packager(self):
+        """
+    How the layouts are packaged, `inno` (the default) or `msix`.
+    """
+        return self.packager
+
+    def get_publisher(self):
+        """
+    The subject of the code signing certificate, which MSIX packages name as their publisher.
+    """
+        return self.publisher
+
+    def get_architectures(self):
+        """
+    The processor architectures to build layouts for, of `x86`, `x64` and `arm64`.
+    """
+        return self.architectures
+
     @staticmethod
     def decode(data):
         f_version = data["version"]
@@ -1147,6 +2179,62 @@ class TargetWindows:
                 if not isinstance(f_readme_path, str):
                     raise Exception("not a string")
 
+        f_compat_map = None
+
+        if "compatMap" in data:
+            f_compat_map = data["compatMap"]
+
+            if f_compat_map is not None:
+                if not isinstance(f_compat_map, dict):
+                    raise Exception("not an object")
+
+                _o0 = {}
+
+                for _k0, _v0 in f_compat_map.items():
+                    if not isinstance(_k0, str):
+                        raise Exception("not a string")
+                    if not isinstance(_v0, str):
+                        raise Exception("not a string")
+                    _o0[_k0] = _v0
+
+                f_compat_map = _o0
+
+        f_packager = None
+
+        if "packager" in data:
+            f_packager = data["packager"]
+
+            if f_packager is not None:
+                if not isinstance(f_packager, str):
+                    raise Exception("not a string")
+
+        f_publisher = None
+
+        if "publisher" in data:
+            f_publisher = data["publisher"]
+
+            if f_publisher is not None:
+                if not isinstance(f_publisher, str):
+                    raise Exception("not a string")
+
+        f_architectures = None
+
+        if "architectures" in data:
+            f_architectures = data["architectures"]
+
+            if f_architectures is not None:
+                if not isinstance(f_architectures, list):
+                    raise Exception("not an array")
+
+                _l0 = []
+
+                for _v0 in f_architectures:
+                    if not isinstance(_v0, str):
+                        raise Exception("not a string")
+                    _l0.append(_v0)
+
+                f_architectures = _l0
+
         return TargetWindows(
             f_version,
             f_app_name,
@@ -1156,6 +2244,10 @@ class TargetWindows:
             f_custom_locales,
             f_license_path,
             f_readme_path,
+            f_compat_map,
+            f_packager,
+            f_publisher,
+            f_architectures,
         )
 
     def encode(self):
@@ -1193,10 +2285,22 @@ class TargetWindows:
         if self.readme_path is not None:
             data["readmePath"] = self.readme_path
 
+        if self.compat_map is not None:
+            data["compatMap"] = self.compat_map
+
+        if self.packager is not None:
+            data["packager"] = self.packager
+
+        if self.publisher is not None:
+            data["publisher"] = self.publisher
+
+        if self.architectures is not None:
+            data["architectures"] = self.architectures
+
         return data
 
     def __repr__(self):
-        return "<TargetWindows version:{!r}, app_name:{!r}, url:{!r}, uuid:{!r}, code_sign_pfx:{!r}, custom_locales:{!r}, license_path:{!r}, readme_path:{!r}>".format(
+        return "<TargetWindows version:{!r}, app_name:{!r}, url:{!r}, uuid:{!r}, code_sign_pfx:{!r}, custom_locales:{!r}, license_path:{!r}, readme_path:{!r}, compat_map:{!r}, packager:{!r}, publisher:{!r}, architectures:{!r}>".format(
             self.version,
             self.app_name,
             self.url,
@@ -1205,12 +2309,161 @@ class TargetWindows:
             self.custom_locales,
             self.license_path,
             self.readme_path,
+            self.compat_map,
+            self.packager,
+            self.publisher,
+            self.architectures,
+        )
+
+
+class Notarization:
+    def __init__(
+        self, tool, keychain_profile, apple_id, password_chain_item, staple, timeout
+    ):
+        self.tool = tool
+        self.keychain_profile = keychain_profile
+        self.apple_id = apple_id
+        self.password_chain_item = password_chain_item
+        self.staple = staple
+        self.timeout = timeout
+
+    def get_tool(self):
+        """
+    `notarytool`, the default, or `altool` for Xcode before 13.
+    """
+        return self.tool
+
+    def get_keychain_profile(self):
+        return self.keychain_profile
+
+    def get_apple_id(self):
+        return self.apple_id
+
+    def get_password_chain_item(self):
+        return self.password_chain_item
+
+    def get_staple(self):
+        """
+    Whether the ticket is stapled to the installer, by default true.
+    """
+        return self.staple
+
+    def get_timeout(self):
+        """
+    How long to wait for the notary service, in minutes, by default 30.
+    """
+        return self.timeout
+
+    @staticmethod
+    def decode(data):
+        f_tool = None
+
+        if "tool" in data:
+            f_tool = data["tool"]
+
+            if f_tool is not None:
+                if not isinstance(f_tool, str):
+                    raise Exception("not a string")
+
+        f_keychain_profile = None
+
+        if "keychainProfile" in data:
+            f_keychain_profile = data["keychainProfile"]
+
+            if f_keychain_profile is not None:
+                if not isinstance(f_keychain_profile, str):
+                    raise Exception("not a string")
+
+        f_apple_id = None
+
+        if "appleId" in data:
+            f_apple_id = data["appleId"]
+
+            if f_apple_id is not None:
+                if not isinstance(f_apple_id, str):
+                    raise Exception("not a string")
+
+        f_password_chain_item = None
+
+        if "passwordChainItem" in data:
+            f_password_chain_item = data["passwordChainItem"]
+
+            if f_password_chain_item is not None:
+                if not isinstance(f_password_chain_item, str):
+                    raise Exception("not a string")
+
+        f_staple = None
+
+        if "staple" in data:
+            f_staple = data["staple"]
+
+            if f_staple is not None:
+                if not isinstance(f_staple, bool):
+                    raise Exception("not a boolean")
+
+        f_timeout = None
+
+        if "timeout" in data:
+            f_timeout = data["timeout"]
+
+            if f_timeout is not None:
+                if not isinstance(f_timeout, int):
+                    raise Exception("not an integer")
+
+        return Notarization(
+            f_tool,
+            f_keychain_profile,
+            f_apple_id,
+            f_password_chain_item,
+            f_staple,
+            f_timeout,
+        )
+
+    def encode(self):
+        data = dict()
+
+        if self.tool is not None:
+            data["tool"] = self.tool
+
+        if self.keychain_profile is not None:
+            data["keychainProfile"] = self.keychain_profile
+
+        if self.apple_id is not None:
+            data["appleId"] = self.apple_id
+
+        if self.password_chain_item is not None:
+            data["passwordChainItem"] = self.password_chain_item
+
+        if self.staple is not None:
+            data["staple"] = self.staple
+
+        if self.timeout is not None:
+            data["timeout"] = self.timeout
+
+        return data
+
+    def __repr__(self):
+        return "<Notarization tool:{!r}, keychain_profile:{!r}, apple_id:{!r}, password_chain_item:{!r}, staple:{!r}, timeout:{!r}>".format(
+            self.tool,
+            self.keychain_profile,
+            self.apple_id,
+            self.password_chain_item,
+            self.staple,
+            self.timeout,
         )
 
 
 class TargetMacOS:
     def __init__(
-        self, version, build, package_id, icon, bundle_name, team_id, code_sign_id
+        self,
+        version,
+        build,
+        package_id,
+        icon,
+        bundle_name,
+        team_id,
+        code_sign_id,
+        notarization,
     ):
         self.version = version
         self.build = build
@@ -1219,6 +2472,7 @@ class TargetMacOS:
         self.bundle_name = bundle_name
         self.team_id = team_id
         self.code_sign_id = code_sign_id
+        self.notarization = notarization
 
     def get_version(self):
         return self.version
@@ -1241,6 +2495,9 @@ class TargetMacOS:
     def get_code_sign_id(self):
         return self.code_sign_id
 
+    def get_notarization(self):
+        return self.notarization
+
     @staticmethod
     def decode(data):
         f_version = data["version"]
@@ -1290,6 +2547,14 @@ class TargetMacOS:
                 if not isinstance(f_code_sign_id, str):
                     raise Exception("not a string")
 
+        f_notarization = None
+
+        if "notarization" in data:
+            f_notarization = data["notarization"]
+
+            if f_notarization is not None:
+                f_notarization = Notarization.decode(f_notarization)
+
         return TargetMacOS(
             f_version,
             f_build,
@@ -1298,6 +2563,7 @@ class TargetMacOS:
             f_bundle_name,
             f_team_id,
             f_code_sign_id,
+            f_notarization,
         )
 
     def encode(self):
@@ -1332,10 +2598,13 @@ class TargetMacOS:
         if self.code_sign_id is not None:
             data["codeSignId"] = self.code_sign_id
 
+        if self.notarization is not None:
+            data["notarization"] = self.notarization.encode()
+
         return data
 
     def __repr__(self):
-        return "<TargetMacOS version:{!r}, build:{!r}, package_id:{!r}, icon:{!r}, bundle_name:{!r}, team_id:{!r}, code_sign_id:{!r}>".format(
+        return "<TargetMacOS version:{!r}, build:{!r}, package_id:{!r}, icon:{!r}, bundle_name:{!r}, team_id:{!r}, code_sign_id:{!r}, notarization:{!r}>".format(
             self.version,
             self.build,
             self.package_id,
@@ -1343,4 +2612,5 @@ class TargetMacOS:
             self.bundle_name,
             self.team_id,
             self.code_sign_id,
+            self.notarization,
         )
@@ -0,0 +1,91 @@
+"""Tests for the `preview` of dead keys on macOS and in the SVG keyboards.
+
+Run from pysrc with `python3 -m unittest discover -s tests`. The
+`rust_logger` and `reqwest` modules are provided by the kbdgen binary, so
+they are stubbed out here.
+"""
+import html
+import os
+import re
+import sys
+import types
+import unittest
+import xml.etree.ElementTree as etree
+
+
+class _Logger:
+    def __init__(self, target):
+        pass
+
+    def log(self, *args):
+        pass
+
+
+sys.modules.setdefault("rust_logger", types.SimpleNamespace(Logger=_Logger))
+sys.modules.setdefault("reqwest", types.ModuleType("reqwest"))
+
+from kbdgen.bundle import ProjectBundle  # noqa: E402
+from kbdgen.gen.base import get_bin_resource  # noqa: E402
+from kbdgen.gen.mac import MacGenerator  # noqa: E402
+from kbdgen.gen.svgkbd import SVGGenerator  # noqa: E402
+
+EXAMPLE = os.path.join(
+    os.path.dirname(__file__), "..", "..", "examples", "sme.kbdgen"
+)
+
+
+class DeadKeyPreviewTests(unittest.TestCase):
+    def setUp(self):
+        self.bundle = ProjectBundle.load(EXAMPLE)
+        self.layout = self.bundle.layouts["se-FI"]
+        self.layout.dead_key_options = {
+            "´": {"preview": "◌́"},
+            "¨": {"preview": "◌̈"},
+        }
+
+    def terminators(self):
+        """What the states of the dead keys output when they are ended"""
+        xml = MacGenerator(self.bundle, {"flags": []}).generate_xml(
+            "se-FI", self.layout
+        )
+        terminators = re.search("<terminators>(.*)</terminators>", xml).group(1)
+        outputs = re.findall(r'<when state="[^"]+" output="([^"]*)" />', terminators)
+        return [html.unescape(output) for output in outputs]
+
+    def svg_labels(self):
+        with get_bin_resource("keyboard-iso.svg", text=True) as f:
+            root = etree.parse(f).getroot()
+        svg = SVGGenerator(self.bundle).generate_svg("se-FI", self.layout, root)
+        root = etree.fromstring(svg.split("\n", 1)[1])
+        return [
+            (text.get("class"), text.text)
+            for text in root.iter("{http://www.w3.org/2000/svg}text")
+            if "key-dead" in (text.get("class") or "")
+        ]
+
+    def test_mac_terminators_are_the_previews(self):
+        terminators = self.terminators()
+        self.assertIn("◌́", terminators)
+        self.assertIn("◌̈", terminators)
+        self.assertNotIn("´", terminators)
+        self.assertNotIn("¨", terminators)
+        self.assertIn("`", terminators)
+
+    def test_mac_terminators_set_explicitly_are_kept(self):
+        self.layout.dead_key_terminators = {"´": "'"}
+
+        terminators = self.terminators()
+        self.assertIn("'", terminators)
+        self.assertNotIn("◌́", terminators)
+        self.assertIn("◌̈", terminators)
+
+    def test_svg_draws_dead_keys_as_their_previews(self):
+        labels = self.svg_labels()
+        self.assertIn(("key-text-primary key-dead", "◌́"), labels)
+        self.assertIn(("key-text-primary key-dead", "◌̈"), labels)
+        self.assertIn(("key-text-secondary key-dead", "`"), labels)
+        self.assertNotIn(("key-text-primary key-dead", "´"), labels)
+
+
+if __name__ == "__main__":
+    unittest.main()
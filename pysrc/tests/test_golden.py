@@ -0,0 +1,100 @@
+"""Python generator output compared with known-good files in `tests/golden`.
+
+Run from pysrc with `python3 -m unittest discover -s tests`. The files are
+what the generators make of the `se-NO` layout of the example bundle, without
+a stamp. When a change to a generator is meant to change its output, run the
+tests with `KBDGEN_UPDATE_GOLDEN=1` to rewrite the files, and review their
+diff. `tests/golden.rs` compares the same files with the output of a full
+build, where one is possible. The `rust_logger`, `reqwest` and
+`language_tags` modules are provided by the kbdgen binary, so they are
+stubbed out here.
+"""
+import os
+import sys
+import tempfile
+import types
+import unittest
+
+
+class _Logger:
+    def __init__(self, target):
+        pass
+
+    def log(self, *args):
+        pass
+
+
+class _LanguageTag:
+    """A tag that already has a script and a region, which `override_locale`
+    in win.py then keeps as it is"""
+
+    def __init__(self, tag):
+        self._subtags = tag.split("-")
+
+    def script(self):
+        return next((x for x in self._subtags[1:] if len(x) == 4), None)
+
+    def region(self):
+        return next((x for x in self._subtags[1:] if len(x) in (2, 3)), None)
+
+    def __str__(self):
+        return "-".join(self._subtags)
+
+
+sys.modules.setdefault("rust_logger", types.SimpleNamespace(Logger=_Logger))
+sys.modules.setdefault("reqwest", types.ModuleType("reqwest"))
+sys.modules.setdefault("language_tags", types.ModuleType("language_tags"))
+sys.modules["language_tags"].LanguageTag = _LanguageTag
+
+from kbdgen.bundle import ProjectBundle  # noqa: E402
+from kbdgen.gen.mac import MacGenerator  # noqa: E402
+from kbdgen.gen.win import WindowsGenerator  # noqa: E402
+
+ROOT = os.path.join(os.path.dirname(__file__), "..", "..")
+EXAMPLE = os.path.join(ROOT, "examples", "sme.kbdgen")
+GOLDEN = os.path.join(ROOT, "tests", "golden")
+
+
+class GoldenTests(unittest.TestCase):
+    def setUp(self):
+        self._stamp = os.environ.pop("KBDGEN_STAMP", None)
+        self.bundle = ProjectBundle.load(EXAMPLE)
+        self.layout = self.bundle.layouts["se-NO"]
+
+    def tearDown(self):
+        if self._stamp is not None:
+            os.environ["KBDGEN_STAMP"] = self._stamp
+
+    def assert_golden(self, name, actual):
+        path = os.path.join(GOLDEN, name)
+        if os.environ.get("KBDGEN_UPDATE_GOLDEN") is not None:
+            with open(path, "wb") as f:
+                f.write(actual)
+            return
+        with open(path, "rb") as f:
+            expected = f.read()
+        self.assertEqual(
+            actual,
+            expected,
+            "`%s` differs from its golden file; run with KBDGEN_UPDATE_GOLDEN=1 "
+            "to update it if that is intended" % name,
+        )
+
+    def test_klc_matches_golden(self):
+        generator = WindowsGenerator(self.bundle)
+        data = generator.generate_klc("se-NO", self.layout)
+        with tempfile.TemporaryDirectory() as tmp:
+            path = os.path.join(tmp, "kbdse-NO.klc")
+            generator.write_klc_file(path, data)
+            with open(path, "rb") as f:
+                self.assert_golden("kbdse-NO.klc", f.read())
+
+    def test_keylayout_matches_golden(self):
+        generator = MacGenerator(self.bundle, {"flags": []})
+        data = generator.generate_xml("se-NO", self.layout)
+        data = generator.keylayout_file("se-NO", self.layout, data)
+        self.assert_golden("seNO.keylayout", data.encode("utf-8"))
+
+
+if __name__ == "__main__":
+    unittest.main()
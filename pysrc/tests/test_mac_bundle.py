@@ -0,0 +1,66 @@
+"""Tests for the keyboard layout bundle of the macOS target.
+
+Run from pysrc with `python3 -m unittest discover -s tests`. The
+`rust_logger` and `reqwest` modules are provided by the kbdgen binary, so
+they are stubbed out here.
+"""
+import os
+import sys
+import tempfile
+import types
+import unittest
+
+
+class _Logger:
+    def __init__(self, target):
+        pass
+
+    def log(self, *args):
+        pass
+
+
+sys.modules.setdefault("rust_logger", types.SimpleNamespace(Logger=_Logger))
+sys.modules.setdefault("reqwest", types.ModuleType("reqwest"))
+
+from kbdgen.bundle import ProjectBundle  # noqa: E402
+from kbdgen.gen.mac import MacGenerator  # noqa: E402
+
+EXAMPLE = os.path.join(
+    os.path.dirname(__file__), "..", "..", "examples", "sme.kbdgen"
+)
+
+
+class BundleTests(unittest.TestCase):
+    def setUp(self):
+        self.bundle = ProjectBundle.load(EXAMPLE)
+        self.generator = MacGenerator(self.bundle, {"flags": []})
+
+    def test_bundle_is_named_after_bundle_name(self):
+        self.generator.mac_target.bundle_name = 'Sámi: "Nordic"/Keyboards '
+        self.assertEqual(self.generator._bundle_filename(), "Sámi NordicKeyboards")
+
+    def test_bundle_names_without_a_file_name_are_refused(self):
+        for name in ("", " ", '/:*?"<>|', ".."):
+            self.generator.mac_target.bundle_name = name
+            with self.assertRaises(Exception):
+                self.generator._bundle_filename()
+
+    def test_preinstall_removes_the_bundle_named_by_identifier(self):
+        with tempfile.TemporaryDirectory() as scripts:
+            self.generator.write_bundle_preinstall(scripts)
+            path = os.path.join(scripts, "preinstall")
+            self.assertTrue(os.access(path, os.X_OK))
+            with open(path) as f:
+                script = f.read().splitlines()
+
+        self.assertEqual(script[0], "#!/bin/sh")
+        self.assertIn(
+            'rm -rf "$3/Library/Keyboard Layouts/"%s.bundle'
+            % self.generator.bundle_id,
+            script,
+        )
+        self.assertIn(".keyboardlayout.", self.generator.bundle_id)
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,88 @@
+"""Tests for the layout JSON in the Android and iOS keyboards.
+
+Run from pysrc with `python3 -m unittest discover -s tests`. The
+`rust_logger` and `reqwest` modules are provided by the kbdgen binary, so
+they are stubbed out here.
+"""
+import json
+import os
+import sys
+import tempfile
+import types
+import unittest
+
+
+class _Logger:
+    def __init__(self, target):
+        pass
+
+    def log(self, *args):
+        pass
+
+
+sys.modules.setdefault("rust_logger", types.SimpleNamespace(Logger=_Logger))
+sys.modules.setdefault("reqwest", types.ModuleType("reqwest"))
+
+from kbdgen.bundle import ProjectBundle  # noqa: E402
+from kbdgen.gen.android import AndroidGenerator  # noqa: E402
+from kbdgen.gen.ios import AppleiOSGenerator  # noqa: E402
+
+EXAMPLE = os.path.join(
+    os.path.dirname(__file__), "..", "..", "examples", "sme.kbdgen"
+)
+
+
+class LayoutFileTests(unittest.TestCase):
+    def setUp(self):
+        self.bundle = ProjectBundle.load(EXAMPLE)
+        self.layout = self.bundle.layouts["se"]
+
+    def android_json(self):
+        with tempfile.TemporaryDirectory() as build_dir:
+            AndroidGenerator(self.bundle).add_layout_json(
+                {"se": self.layout}, build_dir
+            )
+            path = os.path.join(
+                build_dir,
+                "deps",
+                AndroidGenerator.REPO,
+                "app/src/main/assets/layouts/se.json",
+            )
+            with open(path, encoding="utf-8") as f:
+                return json.load(f)
+
+    def ios_json(self):
+        return AppleiOSGenerator(self.bundle).generate_json_layout("se", self.layout)
+
+
+class DirectionTests(LayoutFileTests):
+    def test_left_to_right_by_default(self):
+        self.assertNotIn("rtl", self.android_json())
+        self.assertIs(self.ios_json()["rtl"], False)
+
+    def test_right_to_left(self):
+        self.layout.rtl = True
+
+        self.assertIs(self.android_json()["rtl"], True)
+        self.assertIs(self.ios_json()["rtl"], True)
+
+
+class DeadKeyPreviewTests(LayoutFileTests):
+    def setUp(self):
+        super().setUp()
+        self.layout.dead_keys = {"mobile": {"default": ["´", "¨"]}}
+        self.layout.dead_key_options = {"´": {"preview": "◌́"}}
+
+    def test_popups_show_the_preview(self):
+        self.assertEqual(self.android_json()["deadKeyPreviews"], {"´": "◌́"})
+        self.assertEqual(self.ios_json()["deadKeyPreviews"], {"´": "◌́"})
+
+    def test_no_previews_without_options(self):
+        self.layout.dead_key_options = None
+
+        self.assertEqual(self.android_json()["deadKeyPreviews"], {})
+        self.assertEqual(self.ios_json()["deadKeyPreviews"], {})
+
+
+if __name__ == "__main__":
+    unittest.main()
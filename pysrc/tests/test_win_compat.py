@@ -0,0 +1,68 @@
+"""Tests for the legacy layouts the `compatMap` of the Windows target adds.
+
+Run from pysrc with `python3 -m unittest discover -s tests`. The
+`rust_logger` and `reqwest` modules are provided by the kbdgen binary, and
+`language_tags` is only needed for locales Windows does not know, so they
+are stubbed out here.
+"""
+import os
+import sys
+import types
+import unittest
+from unittest import mock
+
+
+class _Logger:
+    def __init__(self, target):
+        pass
+
+    def log(self, *args):
+        pass
+
+
+sys.modules.setdefault("rust_logger", types.SimpleNamespace(Logger=_Logger))
+sys.modules.setdefault("reqwest", types.ModuleType("reqwest"))
+sys.modules.setdefault("language_tags", types.ModuleType("language_tags"))
+
+from kbdgen.bundle import ProjectBundle  # noqa: E402
+from kbdgen.gen.win import WindowsGenerator  # noqa: E402
+
+EXAMPLE = os.path.join(
+    os.path.dirname(__file__), "..", "..", "examples", "sme.kbdgen"
+)
+
+
+@mock.patch.dict(os.environ, {"KBDGEN_WIN_COMPAT_IDS": '{"se-FI": "0c3b"}'})
+class CompatLayoutTests(unittest.TestCase):
+    def setUp(self):
+        self.bundle = ProjectBundle.load(EXAMPLE)
+        self.layout = self.bundle.layouts["se-FI"]
+        self.generator = WindowsGenerator(self.bundle)
+
+    def compat(self, compat_map):
+        return self.generator.compat_layout("se-FI", self.layout, compat_map)
+
+    def test_each_character_is_replaced_once(self):
+        compat = self.compat({"š": "s", "s": "ſ"})
+        self.assertEqual(compat.modes["win"]["default"]["D02"], "s")
+        self.assertEqual(compat.modes["win"]["default"]["C02"], "ſ")
+        self.assertEqual(self.layout.modes["win"]["default"]["C02"], "s")
+
+    def test_dead_keys_and_their_transforms_are_replaced(self):
+        compat = self.compat({"´": "ʹ", "ʹ": "'", "s": "ſ"})
+        self.assertEqual(compat.modes["win"]["default"]["E12"], "ʹ")
+        self.assertEqual(compat.dead_keys["win"]["default"], ["ʹ"])
+        self.assertEqual(compat.dead_keys["win"]["caps"], ["ʹ"])
+        self.assertNotIn("´", compat.transforms)
+        self.assertEqual(compat.transforms["ʹ"][" "], "ʹ")
+        self.assertEqual(compat.transforms["ʹ"]["ſ"], "ś")
+        self.assertNotIn("s", compat.transforms["ʹ"])
+        self.assertEqual(self.layout.dead_keys["win"]["default"], ["´"])
+
+    def test_replacements_that_merge_keys_are_refused(self):
+        with self.assertRaises(Exception):
+            self.compat({"ʼ": "´"})
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,94 @@
+"""Tests for the layout labels of the Windows target.
+
+Run from pysrc with `python3 -m unittest discover -s tests`. The
+`rust_logger` and `reqwest` modules are provided by the kbdgen binary, and
+`language_tags` is only needed for locales Windows does not know, so they
+are stubbed out here.
+"""
+import os
+import sys
+import tempfile
+import types
+import unittest
+
+
+class _Logger:
+    def __init__(self, target):
+        pass
+
+    def log(self, *args):
+        pass
+
+
+sys.modules.setdefault("rust_logger", types.SimpleNamespace(Logger=_Logger))
+sys.modules.setdefault("reqwest", types.ModuleType("reqwest"))
+sys.modules.setdefault("language_tags", types.ModuleType("language_tags"))
+
+from kbdgen.bundle import ProjectBundle  # noqa: E402
+from kbdgen.gen.win import WindowsGenerator  # noqa: E402
+
+EXAMPLE = os.path.join(
+    os.path.dirname(__file__), "..", "..", "examples", "sme.kbdgen"
+)
+
+
+class LabelTests(unittest.TestCase):
+    def setUp(self):
+        self.bundle = ProjectBundle.load(EXAMPLE)
+        self.bundle.layouts["se-NO"].targets["win"]["label"] = "Sámi 'Norga'"
+        self.generator = WindowsGenerator(self.bundle)
+
+    def test_installer_writes_the_label_after_kbdi(self):
+        with tempfile.TemporaryDirectory() as build_dir:
+            self.generator.generate_inno_script("Windows 8.1/10", build_dir)
+            path = os.path.join(build_dir, "install.all.iss")
+            with open(path, encoding="utf-8-sig") as f:
+                script = f.read().splitlines()
+
+        runs = [line for line in script if "keyboard_install -t" in line]
+        labelled = [line for line in runs if "AfterInstall" in line]
+        self.assertEqual(len(labelled), 1)
+        self.assertIn(" -d kbdse-NO.dll ", labelled[0])
+        self.assertTrue(
+            labelled[0].endswith(
+                "; AfterInstall: \"SetLayoutLabel('kbdse-NO.dll', 'Sámi ''Norga''')\""
+            )
+        )
+        self.assertIn("procedure SetLayoutLabel(DllName, LayoutLabel: String);", script)
+        self.assertIn(
+            "      RegWriteStringValue(HKLM, Key, 'Layout Display Name', LayoutLabel);",
+            script,
+        )
+
+    def test_msix_writes_the_label_after_kbdi(self):
+        with tempfile.TemporaryDirectory() as custom_dir:
+            self.generator.generate_msix_scripts(custom_dir, "x64")
+            with open(os.path.join(custom_dir, "install.cmd"), encoding="utf-8") as f:
+                install = f.read().splitlines()
+
+        start = install.index(
+            "for /f \"delims=\" %%k in ('reg query "
+            '"HKLM\\SYSTEM\\CurrentControlSet\\Control\\Keyboard Layouts" '
+            "/s /f kbdse-NO.dll /d /e ^| findstr /b HKEY_') do ("
+        )
+        self.assertIn(" -d kbdse-NO.dll ", install[start - 1])
+        self.assertEqual(
+            install[start + 1 : start + 4],
+            [
+                '    reg add "%%k" /v "Layout Text" /t REG_SZ '
+                "/d \"Sámi 'Norga'\" /f >nul || exit /b 1",
+                '    reg add "%%k" /v "Layout Display Name" /t REG_SZ '
+                "/d \"Sámi 'Norga'\" /f >nul || exit /b 1",
+                ")",
+            ],
+        )
+        self.assertEqual(sum(line.startswith("for /f") for line in install), 1)
+
+    def test_labels_with_quotes_are_refused(self):
+        self.assertTrue(self.generator.satisfies_requirements())
+        self.bundle.layouts["se-NO"].targets["win"]["label"] = 'Sámi "Norga"'
+        self.assertFalse(self.generator.satisfies_requirements())
+
+
+if __name__ == "__main__":
+    unittest.main()
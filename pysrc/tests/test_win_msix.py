@@ -0,0 +1,94 @@
+"""Tests for the MSIX packages of the Windows target.
+
+Run from pysrc with `python3 -m unittest discover -s tests`. The
+`rust_logger` and `reqwest` modules are provided by the kbdgen binary, and
+`language_tags` is only needed for locales Windows does not know, so they
+are stubbed out here.
+"""
+import os
+import sys
+import tempfile
+import types
+import unittest
+
+
+class _Logger:
+    def __init__(self, target):
+        pass
+
+    def log(self, *args):
+        pass
+
+
+sys.modules.setdefault("rust_logger", types.SimpleNamespace(Logger=_Logger))
+sys.modules.setdefault("reqwest", types.ModuleType("reqwest"))
+sys.modules.setdefault("language_tags", types.ModuleType("language_tags"))
+
+from kbdgen.bundle import ProjectBundle  # noqa: E402
+from kbdgen.gen.win import WindowsGenerator, cmd_quote  # noqa: E402
+
+EXAMPLE = os.path.join(
+    os.path.dirname(__file__), "..", "..", "examples", "sme.kbdgen"
+)
+
+
+class MsixTests(unittest.TestCase):
+    def setUp(self):
+        self.bundle = ProjectBundle.load(EXAMPLE)
+        self.generator = WindowsGenerator(self.bundle)
+
+    def scripts(self, arch):
+        with tempfile.TemporaryDirectory() as custom_dir:
+            self.generator.generate_msix_scripts(custom_dir, arch)
+            scripts = []
+            for fn in ("install.cmd", "uninstall.cmd"):
+                with open(os.path.join(custom_dir, fn), encoding="utf-8") as f:
+                    scripts.append(f.read().splitlines())
+            return scripts
+
+    def test_layouts_are_installed_and_registered(self):
+        install, uninstall = self.scripts("x64")
+        self.assertIn(
+            'copy /y "%~dp0amd64\\*.dll" "%SystemRoot%\\System32\\" >nul || exit /b 1',
+            install,
+        )
+        self.assertIn(
+            'copy /y "%~dp0wow64\\*.dll" "%SystemRoot%\\SysWOW64\\" >nul || exit /b 1',
+            install,
+        )
+
+        layouts = list(self.generator.supported_layouts)
+        kbdi = [line for line in install if line.startswith('"%~dp0kbdi.exe"')]
+        self.assertEqual(len(kbdi), len(layouts))
+        for line in kbdi:
+            self.assertIn(" keyboard_install -t ", line)
+            self.assertTrue(line.endswith(" -e || exit /b 1"))
+        self.assertTrue(
+            any(
+                line.startswith('reg add "HKLM\\SOFTWARE\\kbdgen\\')
+                and "\\Layouts\\" in line
+                and " /v Dll " in line
+                for line in install
+            )
+        )
+
+        removed = [line for line in uninstall if " keyboard_uninstall " in line]
+        self.assertEqual(len(removed), len(layouts))
+        self.assertTrue(any("SysWOW64" in line for line in uninstall))
+
+    def test_x86_packages_only_install_into_system32(self):
+        install, _ = self.scripts("x86")
+        copies = [line for line in install if line.startswith("copy ")]
+        self.assertEqual(
+            copies,
+            ['copy /y "%~dp0i386\\*.dll" "%SystemRoot%\\System32\\" >nul || exit /b 1'],
+        )
+
+    def test_batch_arguments_are_quoted(self):
+        self.assertEqual(cmd_quote("Sámi 100%"), '"Sámi 100%%"')
+        with self.assertRaises(Exception):
+            cmd_quote('say "hi"')
+
+
+if __name__ == "__main__":
+    unittest.main()
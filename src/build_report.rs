@@ -0,0 +1,323 @@
+//! Local build reports, for auditing what a build generated
+//!
+//! With `kbdgen build --report`, each build writes what it generated, the
+//! warnings it logged and how long it took to `kbdgen-report.json` in the
+//! output directory. Builds of other targets into the same directory are
+//! added to the same report. The report is only ever written to disk.
+//!
+//! Warnings are collected by wrapping the logger in [`WarningLog`], which
+//! sees the warnings of the Python generators too. So are the keys and rules
+//! that generators could not represent, see [`crate::loss`].
+
+use crate::loss::{self, Loss, LossReport};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Instant, SystemTime},
+};
+
+/// The name of the report in the output directory
+pub const REPORT_FILE: &str = "kbdgen-report.json";
+
+lazy_static::lazy_static! {
+    static ref WARNINGS: Mutex<Vec<Warning>> = Mutex::new(vec![]);
+    static ref LOSSES: Mutex<Vec<Loss>> = Mutex::new(vec![]);
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Warning {
+    /// The module that logged the warning
+    pub target: String,
+    pub message: String,
+}
+
+/// A logger that keeps the warnings, errors and losses it logs for the report
+///
+/// Losses are logged as what they are, rather than as their JSON.
+pub struct WarningLog<L> {
+    inner: L,
+}
+
+impl<L: log::Log> WarningLog<L> {
+    pub fn new(inner: L) -> WarningLog<L> {
+        WarningLog { inner }
+    }
+}
+
+impl<L: log::Log> log::Log for WarningLog<L> {
+    fn enabled(&self, metadata: &log::Metadata<'_>) -> bool {
+        metadata.level() <= log::Level::Warn || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        if record.target() == loss::LOG_TARGET {
+            if let Some(loss) = Loss::from_message(&record.args().to_string()) {
+                let message = loss.to_string();
+                if let Ok(mut losses) = LOSSES.lock() {
+                    losses.push(loss);
+                }
+                return self.inner.log(
+                    &log::Record::builder()
+                        .metadata(record.metadata().clone())
+                        .args(format_args!("{}", message))
+                        .build(),
+                );
+            }
+        }
+        if record.level() <= log::Level::Warn {
+            if let Ok(mut warnings) = WARNINGS.lock() {
+                warnings.push(Warning {
+                    target: record.target().to_string(),
+                    message: record.args().to_string(),
+                });
+            }
+        }
+        self.inner.log(record)
+    }
+
+    fn flush(&self) {
+        self.inner.flush()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Artifact {
+    /// The path relative to the output directory, separated by `/`
+    pub path: String,
+    /// The size in bytes
+    pub size: u64,
+}
+
+/// What the build of one target did
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TargetReport {
+    pub kbdgen_version: String,
+    pub project_path: PathBuf,
+    /// When the build started, in RFC 3339
+    pub started: String,
+    pub duration_seconds: f64,
+    pub succeeded: bool,
+    pub warnings: Vec<Warning>,
+    /// The keys and rules the build could not represent exactly
+    #[serde(default)]
+    pub losses: LossReport,
+    /// The files the build wrote, in order of path
+    pub artifacts: Vec<Artifact>,
+    /// The size of all artifacts in bytes
+    pub total_size: u64,
+}
+
+/// The report in an output directory, by target
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BuildReport {
+    pub targets: BTreeMap<String, TargetReport>,
+}
+
+impl BuildReport {
+    /// Reads the report in the output directory, or an empty one if there is
+    /// none or it cannot be read
+    pub fn read(output_path: &Path) -> BuildReport {
+        fs::read_to_string(output_path.join(REPORT_FILE))
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn write(&self, output_path: &Path) -> Result<(), Error> {
+        let path = output_path.join(REPORT_FILE);
+        let json = serde_json::to_string_pretty(self).expect("reports serialize");
+        fs::create_dir_all(output_path)
+            .and_then(|_| fs::write(&path, json + "\n"))
+            .map_err(|source| Error::CannotWrite { path, source })
+    }
+}
+
+/// The files under `dir` modified since `since`, leaving out the report
+fn list_artifacts(dir: &Path, prefix: &str, since: SystemTime, artifacts: &mut Vec<Artifact>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let relative = format!("{}{}", prefix, name);
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        if metadata.is_dir() {
+            list_artifacts(&entry.path(), &format!("{}/", relative), since, artifacts);
+        } else if relative != REPORT_FILE
+            && matches!(metadata.modified(), Ok(modified) if modified >= since)
+        {
+            artifacts.push(Artifact {
+                path: relative,
+                size: metadata.len(),
+            });
+        }
+    }
+}
+
+/// Records the build of a target from when it is started, adding it to the
+/// report when it is finished
+///
+/// A recorder that is dropped without being finished, like when the build
+/// panics, records the build as failed.
+pub struct Recorder {
+    target: String,
+    project_path: PathBuf,
+    output_path: PathBuf,
+    started: Instant,
+    started_at: SystemTime,
+    warnings_before: usize,
+    losses_before: usize,
+    write: bool,
+    finished: bool,
+}
+
+impl Recorder {
+    pub fn start(target: &str, project_path: &Path, output_path: &Path) -> Recorder {
+        Recorder {
+            target: target.to_string(),
+            project_path: project_path.to_path_buf(),
+            output_path: output_path.to_path_buf(),
+            started: Instant::now(),
+            started_at: SystemTime::now(),
+            warnings_before: WARNINGS.lock().map(|w| w.len()).unwrap_or(0),
+            losses_before: LOSSES.lock().map(|l| l.len()).unwrap_or(0),
+            write: true,
+            finished: false,
+        }
+    }
+
+    /// Whether to add the build to the report in the output directory, or
+    /// only return what it did
+    pub fn write_report(mut self, write: bool) -> Recorder {
+        self.write = write;
+        self
+    }
+
+    pub fn finish(mut self, succeeded: bool) -> Result<TargetReport, Error> {
+        self.finished = true;
+        self.record(succeeded)
+    }
+
+    fn record(&self, succeeded: bool) -> Result<TargetReport, Error> {
+        let warnings = WARNINGS
+            .lock()
+            .map(|warnings| warnings[self.warnings_before.min(warnings.len())..].to_vec())
+            .unwrap_or_default();
+        let losses = LOSSES
+            .lock()
+            .map(|losses| losses[self.losses_before.min(losses.len())..].to_vec())
+            .unwrap_or_default();
+        // File systems with coarse timestamps can date files written right
+        // after the start before it
+        let since = self.started_at - std::time::Duration::from_secs(1);
+        let mut files = vec![];
+        list_artifacts(&self.output_path, "", since, &mut files);
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let target = TargetReport {
+            kbdgen_version: env!("CARGO_PKG_VERSION").to_string(),
+            project_path: self.project_path.clone(),
+            started: chrono::DateTime::<chrono::Utc>::from(self.started_at).to_rfc3339(),
+            duration_seconds: self.started.elapsed().as_secs_f64(),
+            succeeded,
+            warnings,
+            losses: LossReport(losses),
+            total_size: files.iter().map(|file| file.size).sum(),
+            artifacts: files,
+        };
+
+        if self.write {
+            let mut report = BuildReport::read(&self.output_path);
+            report.targets.insert(self.target.clone(), target.clone());
+            report.write(&self.output_path)?;
+        }
+        Ok(target)
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if !self.finished {
+            if let Err(e) = self.record(false) {
+                log::error!("{}", e);
+            }
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not write `{}`", path.display())]
+    CannotWrite { path: PathBuf, source: io::Error },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_are_added_to_the_report() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("output");
+
+        let recorder = Recorder::start("x11", Path::new("sme.kbdgen"), &output);
+        fs::create_dir_all(output.join("symbols")).unwrap();
+        fs::write(output.join("symbols/se"), "xkb_symbols").unwrap();
+        let x11 = recorder.finish(true).unwrap();
+        assert_eq!(
+            x11.artifacts,
+            vec![Artifact {
+                path: "symbols/se".into(),
+                size: 11
+            }]
+        );
+        assert_eq!(x11.total_size, 11);
+
+        drop(Recorder::start("m17n", Path::new("sme.kbdgen"), &output));
+
+        let report = BuildReport::read(&output);
+        assert!(report.targets["x11"].succeeded);
+        assert!(!report.targets["m17n"].succeeded);
+    }
+
+    struct Discard;
+
+    impl log::Log for Discard {
+        fn enabled(&self, _: &log::Metadata<'_>) -> bool {
+            true
+        }
+        fn log(&self, _: &log::Record<'_>) {}
+        fn flush(&self) {}
+    }
+
+    #[test]
+    fn losses_are_added_to_the_report() {
+        use log::Log;
+
+        let dir = tempfile::tempdir().unwrap();
+        let recorder = Recorder::start("x11", Path::new("sme.kbdgen"), dir.path());
+
+        let loss = Loss::dropped("x11", "se", "reorders and backspaces")
+            .because("XKB cannot express them");
+        let message = serde_json::to_string(&loss).unwrap();
+        WarningLog::new(Discard).log(
+            &log::Record::builder()
+                .level(log::Level::Warn)
+                .target(loss::LOG_TARGET)
+                .args(format_args!("{}", message))
+                .build(),
+        );
+
+        let x11 = recorder.write_report(false).finish(true).unwrap();
+        assert_eq!(x11.losses, LossReport(vec![loss]));
+        assert!(x11.warnings.iter().all(|w| w.target != loss::LOG_TARGET));
+    }
+}
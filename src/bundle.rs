@@ -6,13 +6,82 @@ pub mod models;
 
 pub mod key_map;
 pub use key_map::{DesktopKeyMap, Error as KeyMapError, MobileKeyMap};
+mod macros;
+pub use macros::Error as MacroError;
 mod modes;
-pub use modes::{Desktop, Mobile};
+pub use modes::{
+    allowed_modes, check_mobile_mode, parse_mode_name, Desktop, Mobile, MobileMode,
+    MobileModeError, ModeNameError,
+};
+mod modifiers;
+pub use modifiers::{Error as ModifierError, ModeName, Modifier, ModifierSet};
+mod target;
+pub use target::Target;
 
+mod aliases;
+pub use aliases::AliasNote;
+mod cache;
+pub use cache::Cache;
 mod loading;
-pub use loading::{Error as LoadError, Load};
+pub(crate) use loading::windows_target_path;
+pub use loading::{Error as LoadError, Load, PartialBundle};
+mod lazy;
+pub use lazy::LazyLayout;
 mod saving;
 pub use saving::{Error as SaveError, Save};
+mod simulate;
+pub use simulate::{KeyPress, KeyPressError};
+mod locale;
+pub use locale::{LocaleFallbacks, DEFAULT_LOCALE};
+mod layout_tests;
+pub use layout_tests::{LayoutTests, TestCase, TestFailure, TestOutcome};
+mod which_key;
+pub use which_key::Way;
+mod score;
+pub use score::Score;
+pub mod signature;
+pub use signature::{Error as SignatureError, SigningKey, VerifyingKey};
+mod transforms;
+pub use transforms::{Error as TransformError, TransformConflict};
+pub mod adjacency;
+mod autocorrect;
+pub use adjacency::AdjacencyGraph;
+pub use autocorrect::{parse_wordlist, TapDictionary, TapKey, TapWord};
+mod confusables;
+mod features;
+pub use features::Feature;
+pub mod fonts;
+pub mod modifier_remaps;
+mod theme;
+pub use fonts::{Error as FontError, Font};
+pub use modifier_remaps::{ModifierKey, ModifierRole};
+pub use theme::{Color, MobileTheme, ThemeColors};
+mod render;
+pub use render::{DeadKeyMarker, KeyLabels, Render, RenderColors};
+mod derive;
+pub use confusables::{inconsistent_confusables, InconsistentConfusables};
+pub mod lint;
+mod variants;
+pub use variants::variant_name;
+mod display;
+pub use display::default_display_label;
+mod placeholders;
+pub use placeholders::{expand_placeholders, placeholder, raw_invisible_characters, RawInvisible};
+mod resolve;
+pub use resolve::ResolvedModes;
+mod names;
+pub use names::{check_layout_name, identifier, layout_file, NameError};
+mod guid;
+pub use guid::{is_guid, new_guid};
+mod win_ids;
+pub use win_ids::{
+    compat_win_id, default_win_id, WinId, WinIdAllocation, WinIdCollision, MAX_ID_LENGTH,
+    SYSTEM_IDS,
+};
+mod remap;
+pub use remap::ErgonomicBase;
+mod shortcuts;
+pub use shortcuts::ShortcutConflict;
 
 pub(crate) mod keys;
 pub use keys::KeyValue;
@@ -0,0 +1,194 @@
+//! Where the keys of a mobile layer are, and which of them border each other
+//!
+//! Keys are laid out in rows one unit high, each key as wide as its `width`
+//! or one unit, and rows shorter than the longest are centred. Rows are
+//! named like `Layout::key` does, from `D01` for the first key of the first
+//! row. Predictive text and spelling correction engines use the adjacency
+//! graph to weigh a tap on one key as a miss of its neighbours, see
+//! `kbdgen adjacency`.
+
+use crate::{
+    models::{IsoKey, Layout, ISO_ROWS},
+    KeyValue, MobileKeyMap, MobileMode, Target,
+};
+use bigdecimal::ToPrimitive;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Keys whose centres are at most this far apart border each other
+pub const MAX_DISTANCE: f32 = 1.5;
+
+/// A key that types something, with the centre of the key
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyPlace {
+    pub key: IsoKey,
+    pub label: String,
+    pub x: f32,
+    pub y: f32,
+}
+
+impl KeyPlace {
+    pub fn distance(&self, other: &KeyPlace) -> f32 {
+        ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+    }
+}
+
+fn key_width(key: &KeyValue) -> f32 {
+    match key {
+        KeyValue::Special { width, .. } => width.to_f32().unwrap_or(1.0),
+        _ => 1.0,
+    }
+}
+
+/// The keys of the layer that type something. Special keys like shift take
+/// up room but are left out.
+pub fn key_places(key_map: &MobileKeyMap) -> Vec<KeyPlace> {
+    let row_width = |row: &[KeyValue]| row.iter().map(key_width).sum::<f32>();
+    let longest = key_map
+        .0
+        .iter()
+        .map(|row| row_width(row))
+        .fold(0.0, f32::max);
+
+    let mut places = vec![];
+    for (y, row) in key_map.0.iter().enumerate() {
+        let letter = match ISO_ROWS.get(y + 1) {
+            Some(letter) => letter,
+            None => break,
+        };
+        let mut x = (longest - row_width(row)) / 2.0;
+        for (column, value) in row.iter().enumerate() {
+            let width = key_width(value);
+            let key = format!("{}{:02}", letter, column + 1).parse::<IsoKey>();
+            if let (KeyValue::Symbol(label), Ok(key)) = (value, key) {
+                places.push(KeyPlace {
+                    key,
+                    label: label.clone(),
+                    x: x + width / 2.0,
+                    y: y as f32,
+                });
+            }
+            x += width;
+        }
+    }
+    places
+}
+
+/// A key bordering another
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Neighbour {
+    pub key: IsoKey,
+    /// Between the centres of the keys, in key widths
+    pub distance: f32,
+}
+
+/// The keys bordering `place`, nearest first
+pub fn neighbours(places: &[KeyPlace], place: &KeyPlace) -> Vec<Neighbour> {
+    let mut near = places
+        .iter()
+        .filter(|other| other.key != place.key)
+        .map(|other| Neighbour {
+            key: other.key,
+            distance: place.distance(other),
+        })
+        .filter(|neighbour| neighbour.distance <= MAX_DISTANCE)
+        .collect::<Vec<_>>();
+    near.sort_by(|a, b| {
+        (a.distance, a.key)
+            .partial_cmp(&(b.distance, b.key))
+            .expect("distances are numbers")
+    });
+    near
+}
+
+/// A key of an adjacency graph
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AdjacentKey {
+    pub label: String,
+    /// The centre of the key, in key widths from the left and rows from the
+    /// top
+    pub x: f32,
+    pub y: f32,
+    /// The keys bordering this one, nearest first
+    pub neighbours: Vec<Neighbour>,
+}
+
+/// Which keys of a mobile layer border which
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AdjacencyGraph {
+    /// The distance up to which keys border each other
+    #[serde(rename = "maxDistance")]
+    pub max_distance: f32,
+    pub keys: BTreeMap<IsoKey, AdjacentKey>,
+}
+
+impl AdjacencyGraph {
+    /// The graph of the keys of a layer that type something
+    pub fn of(key_map: &MobileKeyMap) -> AdjacencyGraph {
+        let places = key_places(key_map);
+        let keys = places
+            .iter()
+            .map(|place| {
+                let key = AdjacentKey {
+                    label: place.label.clone(),
+                    x: place.x,
+                    y: place.y,
+                    neighbours: neighbours(&places, place),
+                };
+                (place.key, key)
+            })
+            .collect();
+        AdjacencyGraph {
+            max_distance: MAX_DISTANCE,
+            keys,
+        }
+    }
+}
+
+impl Layout {
+    /// The adjacency graph of a mobile mode of `target`, or of the target it
+    /// falls back to. `None` if there are no mobile modes for `target` or
+    /// they have no `mode`.
+    pub fn adjacency_graph(&self, target: Target, mode: MobileMode) -> Option<AdjacencyGraph> {
+        let (_, modes) = self.modes.resolve_mobile(target)?;
+        Some(AdjacencyGraph::of(modes.get(&mode)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn neighbours_are_nearest_first() {
+        let layout: Layout = serde_yaml::from_str(
+            "displayNames:\n  en: Test\nmodes:\n  mobile:\n    default: |\n      q w e\n      a s\n      \\s{shift:1.5} z\n",
+        )
+        .unwrap();
+        let graph = layout
+            .adjacency_graph(Target::Ios, MobileMode::Default)
+            .unwrap();
+        assert!(layout
+            .adjacency_graph(Target::Ios, MobileMode::Symbols1)
+            .is_none());
+
+        let key = |s: &str| s.parse::<IsoKey>().unwrap();
+        let s = &graph.keys[&key("C02")];
+        assert_eq!((s.label.as_str(), s.x, s.y), ("s", 2.0, 1.0));
+        let neighbours = s
+            .neighbours
+            .iter()
+            .map(|n| (n.key, n.distance))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            neighbours,
+            vec![
+                (key("C01"), 1.0),
+                (key("B02"), 0.25f32.hypot(1.0)),
+                (key("D02"), 0.5f32.hypot(1.0)),
+                (key("D03"), 0.5f32.hypot(1.0)),
+            ]
+        );
+        assert!(!graph.keys.contains_key(&key("B01")));
+    }
+}
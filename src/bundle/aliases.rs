@@ -0,0 +1,242 @@
+//! Where YAML aliases were expanded
+//!
+//! Aliases (`*name`) are expanded into copies of the node with that anchor
+//! (`&name`) when a file is read, so rows can be shared between modes. An
+//! error in the copy is reported where the anchor is, though, and a typo
+//! seems to turn up in places that never had it. The notes here point from
+//! the anchored node to the aliases that used it.
+
+use std::fmt;
+
+/// The aliases an error may have been expanded from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AliasNote {
+    pub alias: String,
+    /// One-based lines of the aliases
+    pub lines: Vec<usize>,
+}
+
+impl fmt::Display for AliasNote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lines = self
+            .lines
+            .iter()
+            .map(|line| line.to_string())
+            .collect::<Vec<_>>();
+        let plural = if lines.len() == 1 { "" } else { "s" };
+        write!(
+            f,
+            "expanded from alias `*{}` on line{} {}",
+            self.alias,
+            plural,
+            lines.join(", ")
+        )
+    }
+}
+
+/// `, expanded from ...` for error messages, if there is a note
+pub(crate) fn describe(note: &Option<AliasNote>) -> String {
+    match note {
+        Some(note) => format!(", {}", note),
+        None => String::new(),
+    }
+}
+
+#[derive(Debug)]
+struct Anchor {
+    name: String,
+    first_line: usize,
+    last_line: usize,
+}
+
+/// The note for an error on `line` (one-based), if it is in an anchored node
+/// that aliases refer to. The innermost such node wins.
+pub(crate) fn note_for_line(text: &str, line: usize) -> Option<AliasNote> {
+    let (anchors, aliases) = scan(text);
+    anchors
+        .iter()
+        .filter(|anchor| anchor.first_line <= line && line <= anchor.last_line)
+        .filter_map(|anchor| {
+            let lines = aliases
+                .iter()
+                .filter(|(name, _)| *name == anchor.name)
+                .map(|(_, line)| *line)
+                .collect::<Vec<_>>();
+            if lines.is_empty() {
+                return None;
+            }
+            Some((
+                anchor.first_line,
+                AliasNote {
+                    alias: anchor.name.clone(),
+                    lines,
+                },
+            ))
+        })
+        .max_by_key(|(first_line, _)| *first_line)
+        .map(|(_, note)| note)
+}
+
+/// The note for an error about the mapping key `key`, like a mode name
+pub(crate) fn note_for_key(text: &str, key: &str) -> Option<AliasNote> {
+    let plain = format!("{}:", key);
+    let quoted = format!("\"{}\":", key);
+    let (lines, _) = content_lines(text);
+    lines
+        .into_iter()
+        .find(|(_, line)| {
+            let line = line.trim_start();
+            line.starts_with(&plain) || line.starts_with(&quoted)
+        })
+        .and_then(|(number, _)| note_for_line(text, number))
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn is_blank(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.is_empty() || trimmed.starts_with('#')
+}
+
+/// Whether the line's value is a block scalar, like `default: |`
+fn starts_block_scalar(line: &str) -> bool {
+    let value = line.split(" #").next().unwrap_or(line).trim_end();
+    let last = value.rsplit(' ').next().unwrap_or(value);
+    last.starts_with('|') || last.starts_with('>')
+}
+
+/// The lines that are YAML structure, numbered from one, leaving out the
+/// content of block scalars, where `&` and `*` are just characters. Also
+/// returns every line's indentation, with blank lines counting as `None`.
+fn content_lines(text: &str) -> (Vec<(usize, &str)>, Vec<Option<usize>>) {
+    let mut lines = vec![];
+    let mut indents = vec![];
+    let mut scalar_indent = None;
+    for (n, line) in text.lines().enumerate() {
+        if is_blank(line) {
+            indents.push(None);
+            continue;
+        }
+        let indent = indent(line);
+        indents.push(Some(indent));
+        match scalar_indent {
+            Some(parent) if indent > parent => continue,
+            _ => scalar_indent = None,
+        }
+        if starts_block_scalar(line) {
+            scalar_indent = Some(indent);
+        }
+        lines.push((n + 1, line));
+    }
+    (lines, indents)
+}
+
+/// The anchors with the lines their nodes span, and the aliases with their
+/// lines
+fn scan(text: &str) -> (Vec<Anchor>, Vec<(String, usize)>) {
+    let (lines, indents) = content_lines(text);
+    let mut anchors = vec![];
+    let mut aliases = vec![];
+
+    for (number, line) in lines {
+        for (sigil, name) in names(line) {
+            if sigil == '*' {
+                aliases.push((name, number));
+                continue;
+            }
+            // The node lasts until the next line that is indented no more
+            // than the anchor's own
+            let own = indents[number - 1].unwrap_or(0);
+            let last_line = indents[number..]
+                .iter()
+                .position(|indent| matches!(indent, Some(i) if *i <= own))
+                .map(|offset| number + offset)
+                .unwrap_or_else(|| indents.len());
+            anchors.push(Anchor {
+                name,
+                first_line: number,
+                last_line: last_line.max(number),
+            });
+        }
+    }
+
+    (anchors, aliases)
+}
+
+/// The anchors and aliases on a line, outside of quotes and comments
+fn names(line: &str) -> Vec<(char, String)> {
+    let mut names = vec![];
+    let mut quote = None;
+    let mut previous = ' ';
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"') | (None, '\'') if previous == ' ' || previous == ':' => quote = Some(c),
+            (None, '#') if previous == ' ' => break,
+            (None, '&') | (None, '*') if matches!(previous, ' ' | '[' | '{' | ',') => {
+                let mut name = String::new();
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || matches!(next, ',' | '[' | ']' | '{' | '}') {
+                        break;
+                    }
+                    name.push(next);
+                    chars.next();
+                }
+                if !name.is_empty() {
+                    names.push((c, name));
+                }
+            }
+            _ => {}
+        }
+        previous = c;
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const YAML: &str = "\
+modes:
+  desktop: &base
+    default: |
+      1 2 3 & *x
+    shfit: |
+      ! \" #
+  mac: *base
+  win: *base
+transforms: {}
+";
+
+    #[test]
+    fn errors_in_anchored_nodes_point_to_aliases() {
+        let note = note_for_line(YAML, 5).unwrap();
+        assert_eq!(note.alias, "base");
+        assert_eq!(note.lines, vec![7, 8]);
+        assert_eq!(
+            note.to_string(),
+            "expanded from alias `*base` on lines 7, 8"
+        );
+        assert_eq!(note_for_key(YAML, "shfit"), Some(note));
+
+        assert_eq!(note_for_line(YAML, 1), None);
+        assert_eq!(note_for_line(YAML, 7), None);
+        assert_eq!(note_for_key(YAML, "transforms"), None);
+    }
+
+    #[test]
+    fn block_scalars_and_quotes_have_no_anchors() {
+        let yaml = "a: |\n  &x *x\nb: \"&y\"\nc: &z 1\nd: *z # *w\n";
+        let (anchors, aliases) = scan(yaml);
+        assert_eq!(
+            anchors.iter().map(|a| a.name.as_str()).collect::<Vec<_>>(),
+            vec!["z"]
+        );
+        assert_eq!(aliases, vec![("z".to_string(), 5)]);
+    }
+}
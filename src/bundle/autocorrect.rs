@@ -0,0 +1,189 @@
+//! Wordlists for correcting mistyped taps on mobile keyboards
+//!
+//! A bundle can have a wordlist for a layout in
+//! `resources/wordlists/<layout>.txt`, one word per line with an optional
+//! frequency after a tab. Each word is turned into the keys tapped to type
+//! it, going through the layout's dead keys, transforms and long-press keys
+//! as typing does (see `Layout::ways`). Along with where each key is and
+//! which keys border it, this is what the apps' autocorrect needs to tell
+//! which word a sequence of taps that missed their keys was meant to be.
+
+use crate::{
+    models::{IsoKey, Layout},
+    MobileMode, Target, Way,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use unicode_normalization::UnicodeNormalization;
+
+/// Outputs longer than this are not looked for when splitting up a word
+const MAX_OUTPUT_LEN: usize = 4;
+
+/// A key of the layer that words are tapped on
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TapKey {
+    /// What the key types in the `default` mode
+    pub label: String,
+    /// The centre of the key, in key widths from the left and rows from the
+    /// top
+    pub x: f32,
+    pub y: f32,
+    /// The keys bordering this one, nearest first
+    pub neighbours: Vec<IsoKey>,
+}
+
+/// A word, as the keys tapped to type it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TapWord {
+    pub word: String,
+    pub taps: Vec<IsoKey>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency: Option<u64>,
+}
+
+/// The words of a wordlist, as taps on the keys of a layout
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TapDictionary {
+    pub keys: BTreeMap<IsoKey, TapKey>,
+    pub words: Vec<TapWord>,
+    /// Words that cannot be typed with the layout, which are left out
+    #[serde(skip)]
+    pub untypeable: Vec<String>,
+}
+
+/// The words of a wordlist file, with their frequencies if it has them.
+/// Empty lines and lines starting with `#` are skipped.
+pub fn parse_wordlist(text: &str) -> Vec<(String, Option<u64>)> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let mut fields = line.split('\t');
+            let word = fields.next().unwrap_or_default().trim().to_string();
+            let frequency = fields.next().and_then(|f| f.trim().parse().ok());
+            (word, frequency)
+        })
+        .collect()
+}
+
+impl Layout {
+    /// The words as taps on the `default` layer of `target`, with the keys
+    /// of that layer. `None` if the layout has no mobile modes for `target`.
+    pub fn tap_dictionary(
+        &self,
+        target: Target,
+        words: impl IntoIterator<Item = (String, Option<u64>)>,
+    ) -> Option<TapDictionary> {
+        let graph = self.adjacency_graph(target, MobileMode::Default)?;
+        let keys = graph
+            .keys
+            .into_iter()
+            .map(|(key, adjacent)| {
+                let tap_key = TapKey {
+                    label: adjacent.label,
+                    x: adjacent.x,
+                    y: adjacent.y,
+                    neighbours: adjacent.neighbours.iter().map(|n| n.key).collect(),
+                };
+                (key, tap_key)
+            })
+            .collect();
+
+        let ways = self.ways(target);
+        let mut dictionary = TapDictionary {
+            keys,
+            words: vec![],
+            untypeable: vec![],
+        };
+        for (word, frequency) in words {
+            let word = word.nfc().collect::<String>();
+            match taps(&ways, &word) {
+                Some(taps) => dictionary.words.push(TapWord {
+                    word,
+                    taps,
+                    frequency,
+                }),
+                None => dictionary.untypeable.push(word),
+            }
+        }
+        Some(dictionary)
+    }
+}
+
+/// The keys tapped to type `word`, taking the longest output that can be
+/// typed at each point. Long-pressed keys are tapped once.
+fn taps(ways: &BTreeMap<String, Way>, word: &str) -> Option<Vec<IsoKey>> {
+    let chars = word.chars().collect::<Vec<_>>();
+    let mut taps = vec![];
+    let mut start = 0;
+    while start < chars.len() {
+        let longest = MAX_OUTPUT_LEN.min(chars.len() - start);
+        let (len, way) = (1..=longest).rev().find_map(|len| {
+            let output = chars[start..start + len].iter().collect::<String>();
+            Some((len, ways.get(&output)?))
+        })?;
+        match way {
+            Way::Keys(presses) => taps.extend(presses.iter().map(|press| press.key)),
+            Way::Longpress(press) => taps.push(press.key),
+        }
+        start += len;
+    }
+    Some(taps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn words_are_typed_through_the_layout() {
+        let layout: Layout = serde_yaml::from_str(
+            r#"
+displayNames:
+  en: Test
+modes:
+  mobile:
+    default: |
+      q w e r t y u i o p
+      a s d f g h j k l
+      \s{shift:1.5} z x c v b n m \s{backspace:1.5}
+    shift: |
+      Q W E R T Y U I O P
+      A S D F G H J K L
+      \s{shift:1.5} Z X C V B N M \s{backspace:1.5}
+longpress:
+  a: á
+"#,
+        )
+        .unwrap();
+        let words = parse_wordlist("# Words\na\u{301}s\t12\nsa\n\nsa1\n");
+        let dictionary = layout.tap_dictionary(Target::Android, words).unwrap();
+
+        let key = |s: &str| s.parse::<IsoKey>().unwrap();
+        assert_eq!(
+            dictionary.words,
+            vec![
+                TapWord {
+                    word: "ás".into(),
+                    taps: vec![key("C01"), key("C02")],
+                    frequency: Some(12),
+                },
+                TapWord {
+                    word: "sa".into(),
+                    taps: vec![key("C02"), key("C01")],
+                    frequency: None,
+                },
+            ]
+        );
+        assert_eq!(dictionary.untypeable, vec!["sa1".to_string()]);
+
+        let a = &dictionary.keys[&key("C01")];
+        assert_eq!((a.label.as_str(), a.x, a.y), ("a", 1.0, 1.0));
+        assert_eq!(
+            a.neighbours,
+            vec![key("C02"), key("D01"), key("D02"), key("B02")]
+        );
+        assert!(!dictionary.keys.contains_key(&key("B01")));
+        assert_eq!(dictionary.keys[&key("B02")].label, "z");
+    }
+}
@@ -0,0 +1,123 @@
+//! On-disk cache of parsed bundle files
+//!
+//! Parsing YAML is most of the time spent loading a large bundle. The parsed
+//! form of each file is stored as JSON under a hash of the file's contents,
+//! so editing one layout only re-parses that layout. Entries are kept apart
+//! per kbdgen version, as the models change between releases.
+
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>) -> Cache {
+        Cache { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Removes all entries, for every kbdgen version
+    pub fn clear(&self) -> std::io::Result<()> {
+        if self.dir.exists() {
+            fs::remove_dir_all(&self.dir)?;
+        }
+        Ok(())
+    }
+
+    fn entry_path<T>(&self, contents: &[u8]) -> PathBuf {
+        let mut hasher = Sha256::new();
+        hasher.update(std::any::type_name::<T>().as_bytes());
+        hasher.update([0]);
+        hasher.update(contents);
+        let hash = hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+
+        self.dir
+            .join(env!("CARGO_PKG_VERSION"))
+            .join(&hash[..2])
+            .join(&hash[2..])
+            .with_extension("json")
+    }
+
+    /// The cached parse of a file with these contents, if there is one
+    pub(crate) fn get<T: DeserializeOwned>(&self, contents: &[u8]) -> Option<T> {
+        let path = self.entry_path::<T>(contents);
+        let data = fs::read(&path).ok()?;
+        match serde_json::from_slice(&data) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                log::debug!(
+                    "Ignoring unreadable cache entry `{}`: {}",
+                    path.display(),
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    /// Stores the parse of a file with these contents. Failing to write the
+    /// cache is never an error, the file will just be parsed again next time.
+    pub(crate) fn put<T: Serialize>(&self, contents: &[u8], value: &T) {
+        let path = self.entry_path::<T>(contents);
+        let result = serde_json::to_vec(value)
+            .map_err(|e| e.to_string())
+            .and_then(|data| {
+                fs::create_dir_all(path.parent().expect("has parent"))
+                    .and_then(|_| {
+                        // Write to a temporary file first, so a concurrent
+                        // reader never sees a partial entry
+                        let tmp = path.with_extension("json.tmp");
+                        fs::write(&tmp, data)?;
+                        fs::rename(&tmp, &path)
+                    })
+                    .map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            log::debug!("Could not write cache entry `{}`: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Load, ProjectBundle};
+
+    #[test]
+    fn cached_bundle_is_identical() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path());
+
+        let uncached = ProjectBundle::load("examples/sme.kbdgen").unwrap();
+        let first = ProjectBundle::load_cached("examples/sme.kbdgen", &cache).unwrap();
+        let second = ProjectBundle::load_cached("examples/sme.kbdgen", &cache).unwrap();
+        assert_eq!(first, uncached);
+        assert_eq!(second, uncached);
+
+        cache.clear().unwrap();
+        assert!(!dir.path().exists());
+    }
+
+    #[test]
+    fn entries_differ_by_type() {
+        let cache = Cache::new("/cache");
+        assert_ne!(
+            cache.entry_path::<String>(b"a: b"),
+            cache.entry_path::<u32>(b"a: b")
+        );
+    }
+}
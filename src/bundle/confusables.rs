@@ -0,0 +1,272 @@
+//! Characters that look alike and serve the same purpose
+//!
+//! The groups are generated from Unicode's `confusables.txt` (UTS #39) into
+//! `confusable_groups.rs`, keeping only punctuation, modifier letters and
+//! symbols, which orthographies use for the same thing, like an apostrophe.
+//! Letters that look alike are left out, since a bundle with layouts for
+//! several scripts uses them on purpose, except for the Latin click letters
+//! that stand in for punctuation. Groups of `confusables.txt` are split
+//! where orthographies tell the characters apart, like the turned comma
+//! U+02BB of Hawaiian from the apostrophe.
+//!
+//! To regenerate the groups for a new version of Unicode, download
+//! <https://www.unicode.org/Public/security/latest/confusables.txt> and run
+//!
+//! ```sh
+//! KBDGEN_CONFUSABLES_TXT=confusables.txt KBDGEN_UPDATE_CONFUSABLES=1 \
+//!     cargo test groups_match_confusables_txt -- --ignored
+//! ```
+//!
+//! and review the diff. Without `KBDGEN_UPDATE_CONFUSABLES` the test checks
+//! that the groups are what the file gives.
+
+use crate::{models::Layout, KeyValue};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+// Groups of confusable characters, by what they look like. No character is
+// in more than one group.
+include!("confusable_groups.rs");
+
+/// A group of confusables of which layouts use different characters
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InconsistentConfusables {
+    pub group: &'static str,
+    /// The layouts using each character of the group
+    pub uses: BTreeMap<char, Vec<String>>,
+}
+
+impl Layout {
+    /// Every character the layout can type, from its modes, long-press keys
+    /// and transforms
+    pub fn used_characters(&self) -> BTreeSet<char> {
+        let mut characters = BTreeSet::new();
+        self.for_each_key(|key| {
+            if let KeyValue::Symbol(value) = key {
+                characters.extend(value.chars());
+            }
+        });
+        for values in self.longpress.iter().flat_map(|l| l.values()) {
+            characters.extend(values.chars().filter(|c| !c.is_whitespace()));
+        }
+        for transform in self.transforms.iter().flat_map(|t| t.values()) {
+            characters.extend(transform.values().flat_map(|output| output.chars()));
+        }
+        characters
+    }
+}
+
+/// Groups of confusables for which some layouts use one character and others
+/// another
+///
+/// Layouts that use several characters of a group tell them apart on purpose
+/// and are left out.
+pub fn inconsistent_confusables<S: std::hash::BuildHasher>(
+    layouts: &HashMap<String, Layout, S>,
+) -> Vec<InconsistentConfusables> {
+    let used = layouts
+        .iter()
+        .map(|(name, layout)| (name, layout.used_characters()))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut found = vec![];
+    for (group, characters) in GROUPS {
+        let mut uses: BTreeMap<char, Vec<String>> = BTreeMap::new();
+        for (name, used) in &used {
+            let mut in_group = characters.iter().filter(|c| used.contains(c));
+            if let (Some(&c), None) = (in_group.next(), in_group.next()) {
+                uses.entry(c).or_default().push(name.to_string());
+            }
+        }
+        if uses.len() > 1 {
+            found.push(InconsistentConfusables { group, uses });
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use unic_ucd_category::GeneralCategory;
+
+    /// The Latin click letters, which orthographies write with the
+    /// punctuation they look like
+    const ALSO_KEPT: &[char] = &['\u{01C0}', '\u{01C1}', '\u{01C2}', '\u{01C3}'];
+
+    /// Characters that `confusables.txt` groups with others but orthographies
+    /// tell apart, which get groups of their own
+    const SPLITS: &[&[char]] = &[&['\u{2018}', '\u{02BB}', '\u{02BD}']];
+
+    fn kept(c: char) -> bool {
+        let category = GeneralCategory::of(c);
+        category.is_punctuation()
+            || matches!(
+                category,
+                GeneralCategory::ModifierLetter
+                    | GeneralCategory::ModifierSymbol
+                    | GeneralCategory::MathSymbol
+            )
+            || ALSO_KEPT.contains(&c)
+    }
+
+    /// The contents of `confusable_groups.rs` for a `confusables.txt`
+    fn generate_groups(confusables: &str) -> String {
+        let mut version = None;
+        let mut by_prototype: BTreeMap<Vec<char>, BTreeSet<char>> = BTreeMap::new();
+        for line in confusables.trim_start_matches('\u{FEFF}').lines() {
+            if let Some(found) = line.strip_prefix("# Version:") {
+                version = Some(found.trim().to_string());
+            }
+            let mut fields = line.split('#').next().unwrap().split(';');
+            let (source, prototype) = match (fields.next(), fields.next()) {
+                (Some(source), Some(prototype)) => (source, prototype),
+                _ => continue,
+            };
+            let chars = |field: &str| {
+                field
+                    .split_whitespace()
+                    .map(|hex| {
+                        u32::from_str_radix(hex, 16)
+                            .ok()
+                            .and_then(std::char::from_u32)
+                            .unwrap_or_else(|| panic!("`{}` is not a code point", hex))
+                    })
+                    .collect::<Vec<_>>()
+            };
+            let prototype = chars(prototype);
+            let group = by_prototype.entry(prototype.clone()).or_default();
+            group.extend(chars(source));
+            if let [c] = prototype[..] {
+                group.insert(c);
+            }
+        }
+
+        let mut groups = by_prototype
+            .into_values()
+            .map(|group| {
+                group
+                    .into_iter()
+                    .filter(|&c| kept(c) && !SPLITS.iter().any(|split| split.contains(&c)))
+                    .collect::<Vec<_>>()
+            })
+            .chain(SPLITS.iter().map(|split| {
+                let mut split = split.to_vec();
+                split.sort_unstable();
+                split
+            }))
+            .filter(|group| group.len() > 1)
+            .collect::<Vec<_>>();
+        groups.sort();
+
+        let mut out = String::new();
+        out.push_str(
+            "// Groups of confusable characters, written by `groups_match_confusables_txt`\n\
+             // in `confusables.rs` from Unicode's `confusables.txt`; do not edit by hand.\n",
+        );
+        out.push_str(&format!(
+            "// Version: {}\n",
+            version.expect("confusables.txt has no `# Version:` line")
+        ));
+        out.push_str("#[rustfmt::skip]\nconst GROUPS: &[(&str, &[char])] = &[\n");
+        for group in groups {
+            let name = unic_ucd_name::Name::of(group[0])
+                .map(|name| name.to_string().to_lowercase())
+                .unwrap_or_else(|| format!("U+{:04X}", group[0] as u32));
+            let chars = group
+                .iter()
+                .map(|c| format!("'\\u{{{:04X}}}'", *c as u32))
+                .collect::<Vec<_>>();
+            out.push_str(&format!("    ({:?}, &[{}]),\n", name, chars.join(", ")));
+        }
+        out.push_str("];\n");
+        out
+    }
+
+    fn layout(default: &str) -> Layout {
+        serde_yaml::from_str(&format!(
+            "displayNames:\n  en: Test\nmodes:\n  mobile:\n    default: |\n      {}\n",
+            default
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn different_apostrophes_are_reported() {
+        let mut layouts = HashMap::new();
+        layouts.insert("a".to_string(), layout("q w \u{2BC}"));
+        layouts.insert("b".to_string(), layout("q w \u{2019}"));
+        layouts.insert("c".to_string(), layout("q w \u{2BC}"));
+        // Uses both on purpose
+        layouts.insert("d".to_string(), layout("' \u{2019}"));
+
+        let found = inconsistent_confusables(&layouts);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].group, "apostrophe");
+        assert_eq!(found[0].uses[&'\u{2BC}'], vec!["a", "c"]);
+        assert_eq!(found[0].uses[&'\u{2019}'], vec!["b"]);
+    }
+
+    #[test]
+    fn groups_are_generated_from_confusables_txt() {
+        let confusables = "\u{FEFF}# confusables.txt\n\
+            # Version: 15.1.0\n\
+            \n\
+            2019 ;\t0027 ;\tMA\t# ( ’ → ' ) RIGHT SINGLE QUOTATION MARK → APOSTROPHE\t# \n\
+            02BC ;\t0027 ;\tMA\t# ( ʼ → ' ) MODIFIER LETTER APOSTROPHE → APOSTROPHE\t# \n\
+            02BB ;\t0027 ;\tMA\t# ( ʻ → ' ) MODIFIER LETTER TURNED COMMA → APOSTROPHE\t# \n\
+            2018 ;\t0027 ;\tMA\t# ( ‘ → ' ) LEFT SINGLE QUOTATION MARK → APOSTROPHE\t# \n\
+            201D ;\t0027 0027 ;\tMA\t# ( ” → '' ) RIGHT DOUBLE QUOTATION MARK → APOSTROPHE, APOSTROPHE\t# \n\
+            0022 ;\t0027 0027 ;\tMA\t# ( \" → '' ) QUOTATION MARK → APOSTROPHE, APOSTROPHE\t# \n\
+            007C ;\t006C ;\tMA\t# ( | → l ) VERTICAL LINE → LATIN SMALL LETTER L\t# \n\
+            01C0 ;\t006C ;\tMA\t# ( ǀ → l ) LATIN LETTER DENTAL CLICK → LATIN SMALL LETTER L\t# \n\
+            0031 ;\t006C ;\tMA\t# ( 1 → l ) DIGIT ONE → LATIN SMALL LETTER L\t# \n\
+            0430 ;\t0061 ;\tMA\t# ( а → a ) CYRILLIC SMALL LETTER A → LATIN SMALL LETTER A\t# \n";
+
+        assert_eq!(
+            generate_groups(confusables),
+            "// Groups of confusable characters, written by `groups_match_confusables_txt`\n\
+             // in `confusables.rs` from Unicode's `confusables.txt`; do not edit by hand.\n\
+             // Version: 15.1.0\n\
+             #[rustfmt::skip]\n\
+             const GROUPS: &[(&str, &[char])] = &[\n    \
+             (\"quotation mark\", &['\\u{0022}', '\\u{201D}']),\n    \
+             (\"apostrophe\", &['\\u{0027}', '\\u{02BC}', '\\u{2019}']),\n    \
+             (\"vertical line\", &['\\u{007C}', '\\u{01C0}']),\n    \
+             (\"modifier letter turned comma\", &['\\u{02BB}', '\\u{02BD}', '\\u{2018}']),\n\
+             ];\n"
+        );
+    }
+
+    /// Regenerates the groups from the `confusables.txt` at
+    /// `KBDGEN_CONFUSABLES_TXT`, see the module docs
+    #[test]
+    #[ignore = "needs Unicode's confusables.txt, see the module docs"]
+    fn groups_match_confusables_txt() {
+        let confusables = std::env::var_os("KBDGEN_CONFUSABLES_TXT")
+            .expect("KBDGEN_CONFUSABLES_TXT is not set to the path of confusables.txt");
+        let confusables = std::fs::read_to_string(confusables).unwrap();
+        let generated = generate_groups(&confusables);
+
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/bundle/confusable_groups.rs");
+        if std::env::var_os("KBDGEN_UPDATE_CONFUSABLES").is_some() {
+            std::fs::write(&path, generated).unwrap();
+            return;
+        }
+        assert!(
+            std::fs::read_to_string(&path).unwrap() == generated,
+            "The groups differ from confusables.txt, run with KBDGEN_UPDATE_CONFUSABLES=1 \
+             to update them"
+        );
+    }
+
+    #[test]
+    fn groups_do_not_overlap() {
+        let mut seen = BTreeSet::new();
+        for (group, characters) in GROUPS {
+            for c in *characters {
+                assert!(seen.insert(c), "{:?} of {} is in another group", c, group);
+            }
+        }
+    }
+}
@@ -0,0 +1,390 @@
+//! Modes derived from the modes for another kind of target
+//!
+//! New languages often start out with a desktop or a mobile layout only.
+//! Rather than writing out the same letters again, `derive` can fill in the
+//! modes of the other kind of target when the bundle is loaded. The targets
+//! that got derived modes are recorded in `Layout::derived_modes`.
+
+use crate::{
+    models::{
+        BaseLayout, DeriveDesktop, DeriveDesktopOptions, DeriveMobile, DeriveMobileOptions,
+        DesktopModes, IsoKey, Layout, MobileModes,
+    },
+    DesktopKeyMap, KeyValue, MobileKeyMap, MobileMode, Target,
+};
+use unic_ucd_category::GeneralCategory;
+
+/// The keys of the desktop rows that become the rows of a mobile layout
+const MOBILE_ROWS: [&[IsoKey]; 3] = {
+    use IsoKey::*;
+    [
+        &[D01, D02, D03, D04, D05, D06, D07, D08, D09, D10, D11, D12],
+        &[C01, C02, C03, C04, C05, C06, C07, C08, C09, C10, C11, C12],
+        &[B00, B01, B02, B03, B04, B05, B06, B07, B08, B09, B10],
+    ]
+};
+
+/// Desktop targets to derive from when no target is given, in order of
+/// preference
+const DESKTOP_SOURCES: &[Target] = &[
+    Target::Desktop,
+    Target::Win,
+    Target::Mac,
+    Target::X11,
+    Target::Chrome,
+];
+
+/// Mobile targets to derive from when no target is given, in order of
+/// preference
+const MOBILE_SOURCES: &[Target] = &[Target::Mobile, Target::Android, Target::Ios];
+
+/// The letter keys of each desktop row that the rows of a mobile layout are
+/// placed on
+const DESKTOP_ROWS: [&[IsoKey]; 3] = {
+    use IsoKey::*;
+    [
+        &[D01, D02, D03, D04, D05, D06, D07, D08, D09, D10, D11, D12],
+        &[C01, C02, C03, C04, C05, C06, C07, C08, C09, C10, C11, C12],
+        &[B01, B02, B03, B04, B05, B06, B07, B08, B09, B10],
+    ]
+};
+
+impl BaseLayout {
+    /// The `default` and `shift` modes of the layout
+    fn modes(self) -> (&'static str, &'static str) {
+        match self {
+            BaseLayout::Us => (
+                r#"` 1 2 3 4 5 6 7 8 9 0 - =
+                     q w e r t y u i o p [ ]
+                     a s d f g h j k l ; ' \
+                   \ z x c v b n m , . /"#,
+                r#"~ ! @ # $ % ^ & * ( ) _ +
+                     Q W E R T Y U I O P { }
+                     A S D F G H J K L : " |
+                   | Z X C V B N M < > ?"#,
+            ),
+            BaseLayout::Nordic => (
+                r#"§ 1 2 3 4 5 6 7 8 9 0 + ´
+                     q w e r t y u i o p å ¨
+                     a s d f g h j k l ö ä '
+                   < z x c v b n m , . -"#,
+                r#"½ ! " # ¤ % & / ( ) = ? `
+                     Q W E R T Y U I O P Å ^
+                     A S D F G H J K L Ö Ä *
+                   > Z X C V B N M ; : _"#,
+            ),
+        }
+    }
+}
+
+fn is_letter(value: &str) -> bool {
+    let mut chars = value.chars();
+    match chars.next() {
+        Some(c) => {
+            GeneralCategory::of(c).is_letter()
+                && chars.all(|c| {
+                    let category = GeneralCategory::of(c);
+                    category.is_letter() || category.is_mark()
+                })
+        }
+        None => false,
+    }
+}
+
+fn words(value: &Option<String>) -> Vec<&str> {
+    value
+        .as_deref()
+        .map(|v| v.split_whitespace().collect())
+        .unwrap_or_default()
+}
+
+impl Layout {
+    /// Adds the modes `derive` asks for that the layout does not have
+    pub fn apply_derives(&mut self) {
+        if self.modes.mobile.is_none() {
+            if let Some(modes) = self.derived_mobile_modes() {
+                log::info!("Derived mobile modes from the desktop modes");
+                self.modes.mobile = Some(modes);
+                self.derived_modes.insert(Target::Mobile);
+            }
+        }
+
+        let has_desktop_modes = DESKTOP_SOURCES
+            .iter()
+            .any(|&target| self.modes.desktop_for(target).is_some());
+        if !has_desktop_modes {
+            if let Some(modes) = self.derived_desktop_modes() {
+                log::info!("Derived desktop modes from the mobile modes");
+                self.modes.desktop = Some(modes);
+                self.derived_modes.insert(Target::Desktop);
+            }
+        }
+    }
+
+    /// The layout as written in its file, without derived modes
+    pub fn without_derived_modes(&self) -> Layout {
+        let mut layout = self.clone();
+        for target in std::mem::take(&mut layout.derived_modes) {
+            match target {
+                Target::Mobile => layout.modes.mobile = None,
+                Target::Desktop => layout.modes.desktop = None,
+                _ => {}
+            }
+        }
+        layout
+    }
+
+    /// Desktop `default` and `shift` modes built from the mobile modes and
+    /// a base layout, if `derive.desktop` is set
+    pub fn derived_desktop_modes(&self) -> Option<DesktopModes> {
+        let options = match self.derive.as_ref()?.desktop.as_ref()? {
+            DeriveDesktop::Enabled(false) => return None,
+            DeriveDesktop::Enabled(true) => DeriveDesktopOptions::default(),
+            DeriveDesktop::Options(options) => options.clone(),
+        };
+
+        let mobile = match options.from {
+            Some(target) => self.modes.mobile_for(target)?,
+            None => MOBILE_SOURCES
+                .iter()
+                .find_map(|&target| self.modes.mobile_for(target))?,
+        };
+        let default = mobile.get(&MobileMode::Default)?;
+        let shift = mobile.get(&MobileMode::Shift);
+
+        let (base_default, base_shift) = options.base.modes();
+        let mut desktop_default: DesktopKeyMap = base_default.parse().ok()?;
+        let mut desktop_shift: DesktopKeyMap = base_shift.parse().ok()?;
+
+        for (index, (row, keys)) in default.0.iter().zip(DESKTOP_ROWS.iter()).enumerate() {
+            let shift_row = shift.and_then(|shift| shift.0.get(index));
+            let letters = row
+                .iter()
+                .enumerate()
+                .filter_map(|(column, key)| match key {
+                    KeyValue::Symbol(value) => Some((column, value)),
+                    _ => None,
+                });
+
+            let mut placed = 0;
+            for ((column, value), &key) in letters.zip(keys.iter()) {
+                let shifted = match shift_row.and_then(|row| row.get(column)) {
+                    Some(KeyValue::Symbol(shifted)) => shifted.clone(),
+                    _ => value.to_uppercase(),
+                };
+                desktop_default
+                    .0
+                    .insert(key, KeyValue::Symbol(value.clone()));
+                desktop_shift.0.insert(key, KeyValue::Symbol(shifted));
+                placed += 1;
+            }
+
+            let symbols = row.iter().filter(|key| matches!(key, KeyValue::Symbol(_)));
+            if symbols.count() > placed {
+                log::warn!(
+                    "Mobile row {} has more keys than the desktop row, the rest are left out",
+                    index + 1
+                );
+            }
+        }
+
+        let mut modes = DesktopModes::default();
+        modes.0.insert("default".to_string(), desktop_default);
+        modes.0.insert("shift".to_string(), desktop_shift);
+        Some(modes)
+    }
+
+    /// Mobile `default` and `shift` modes built from the desktop modes, if
+    /// `derive.mobile` is set
+    pub fn derived_mobile_modes(&self) -> Option<MobileModes> {
+        let options = match self.derive.as_ref()?.mobile.as_ref()? {
+            DeriveMobile::Enabled(false) => return None,
+            DeriveMobile::Enabled(true) => DeriveMobileOptions::default(),
+            DeriveMobile::Options(options) => options.clone(),
+        };
+
+        let desktop = match options.from {
+            Some(target) => self.modes.desktop_for(target)?,
+            None => DESKTOP_SOURCES
+                .iter()
+                .find_map(|&target| self.modes.desktop_for(target))?,
+        };
+        let default = desktop.get("default")?;
+        let shift = desktop.get("shift");
+
+        let keep = words(&options.keep);
+        let drop = words(&options.drop);
+        let symbol = |key_map: &DesktopKeyMap, key| match key_map.0.get(&key) {
+            Some(KeyValue::Symbol(value)) => Some(value.clone()),
+            _ => None,
+        };
+
+        let mut default_rows = vec![];
+        let mut shift_rows = vec![];
+        for row in MOBILE_ROWS.iter() {
+            let keys = row.iter().filter_map(|&key| {
+                let value = symbol(default, key)?;
+                let wanted = (is_letter(&value) || keep.contains(&value.as_str()))
+                    && !drop.contains(&value.as_str());
+                if !wanted {
+                    return None;
+                }
+                let shifted = shift
+                    .and_then(|shift| symbol(shift, key))
+                    .unwrap_or_else(|| value.to_uppercase());
+                Some((KeyValue::Symbol(value), KeyValue::Symbol(shifted)))
+            });
+            let (default_row, shift_row): (Vec<_>, Vec<_>) = keys.unzip();
+            if !default_row.is_empty() {
+                default_rows.push(default_row);
+                shift_rows.push(shift_row);
+            }
+        }
+
+        if default_rows.is_empty() {
+            return None;
+        }
+        let mut modes = MobileModes::default();
+        modes
+            .0
+            .insert(MobileMode::Default, MobileKeyMap(default_rows));
+        modes.0.insert(MobileMode::Shift, MobileKeyMap(shift_rows));
+        Some(modes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAYOUT: &str = r#"
+displayNames:
+  en: Test
+modes:
+  desktop:
+    default: |
+      § 1 2 3 4 5 6 7 8 9 0 + ´
+        á š e r t y u i o p å ŋ
+        a s d f g h j k l ö ä đ
+      ž z č c v b n m , . -
+    shift: |
+      ½ ! " # ¤ % & / ( ) = ? `
+        Á Š E R T Y U I O P Å Ŋ
+        A S D F G H J K L Ö Ä Đ
+      Ž Z Č C V B N M ; : _
+"#;
+
+    fn rows(modes: &MobileModes, mode: &str) -> Vec<String> {
+        modes.0[&mode.parse::<MobileMode>().unwrap()]
+            .0
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|key| key.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn letter_rows_become_mobile_rows() {
+        let mut layout: Layout =
+            serde_yaml::from_str(&format!("{}derive:\n  mobile: true\n", LAYOUT)).unwrap();
+        layout.apply_derives();
+        let modes = layout.modes.mobile.as_ref().unwrap();
+
+        assert_eq!(
+            rows(modes, "default"),
+            vec![
+                "á š e r t y u i o p å ŋ",
+                "a s d f g h j k l ö ä đ",
+                "ž z č c v b n m"
+            ]
+        );
+        assert_eq!(rows(modes, "shift")[2], "Ž Z Č C V B N M");
+    }
+
+    #[test]
+    fn options_keep_and_drop_keys() {
+        let layout: Layout = serde_yaml::from_str(&format!(
+            "{}derive:\n  mobile:\n    keep: \"-\"\n    drop: ŋ đ\n",
+            LAYOUT
+        ))
+        .unwrap();
+        let modes = layout.derived_mobile_modes().unwrap();
+
+        assert_eq!(rows(&modes, "default")[0], "á š e r t y u i o p å");
+        assert_eq!(rows(&modes, "default")[2], "ž z č c v b n m -");
+        assert_eq!(rows(&modes, "shift")[2], "Ž Z Č C V B N M _");
+    }
+
+    #[test]
+    fn own_mobile_modes_win() {
+        let mut layout: Layout = serde_yaml::from_str(&format!(
+            "{}  mobile:\n    default: |\n      a b c\n    shift: |\n      A B C\nderive:\n  mobile: true\n",
+            LAYOUT
+        ))
+        .unwrap();
+        let before = layout.modes.mobile.clone();
+        layout.apply_derives();
+        assert_eq!(layout.modes.mobile, before);
+    }
+
+    const MOBILE_LAYOUT: &str = r#"
+displayNames:
+  en: Test
+modes:
+  mobile:
+    default: |
+      á š e r t y u i o p å ŋ
+      a s d f g h j k l ö ä đ
+      \s{shift} ž z č c v b n m \s{backspace}
+    shift: |
+      Á Š E R T Y U I O P Å Ŋ
+      A S D F G H J K L Ö Ä Đ
+      \s{shift} Ž Z Č C V B N M \s{backspace}
+"#;
+
+    #[test]
+    fn mobile_rows_are_placed_on_the_base_layout() {
+        let mut layout: Layout = serde_yaml::from_str(&format!(
+            "{}derive:\n  desktop:\n    base: nordic\n",
+            MOBILE_LAYOUT
+        ))
+        .unwrap();
+        layout.apply_derives();
+        let modes = layout.modes.desktop.as_ref().unwrap();
+        let default = modes.get("default").unwrap();
+        let shift = modes.get("shift").unwrap();
+
+        assert_eq!(default.get_string(IsoKey::D01).as_deref(), Some("á"));
+        assert_eq!(default.get_string(IsoKey::C12).as_deref(), Some("đ"));
+        assert_eq!(default.get_string(IsoKey::B01).as_deref(), Some("ž"));
+        assert_eq!(shift.get_string(IsoKey::B08).as_deref(), Some("M"));
+        // The other keys come from the base layout
+        assert_eq!(default.get_string(IsoKey::E00).as_deref(), Some("§"));
+        assert_eq!(default.get_string(IsoKey::B09).as_deref(), Some("."));
+        assert_eq!(shift.get_string(IsoKey::B10).as_deref(), Some("_"));
+
+        assert!(layout.derived_modes.contains(&Target::Desktop));
+        assert_eq!(layout.without_derived_modes().modes.desktop, None);
+    }
+
+    #[test]
+    fn base_layouts_are_complete() {
+        for base in &[BaseLayout::Us, BaseLayout::Nordic] {
+            let (default, shift) = base.modes();
+            assert!(default.parse::<DesktopKeyMap>().is_ok(), "{:?}", base);
+            assert!(shift.parse::<DesktopKeyMap>().is_ok(), "{:?}", base);
+        }
+    }
+
+    #[test]
+    fn desktop_modes_are_not_derived_over_existing_ones() {
+        let mut layout: Layout =
+            serde_yaml::from_str(&format!("{}derive:\n  desktop: true\n", LAYOUT)).unwrap();
+        layout.apply_derives();
+        assert!(layout.derived_modes.is_empty());
+    }
+}
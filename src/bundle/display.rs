@@ -0,0 +1,128 @@
+//! Labels for keys whose output cannot be seen
+//!
+//! A key typing a combining mark, a joiner or an unusual space would be
+//! drawn as an empty or broken key. Like CLDR's `displayMap`, such keys are
+//! labelled instead: combining marks on a dotted circle, and invisible
+//! characters with a short abbreviation, like `ZWJ`. `displayOverrides` of a
+//! layout sets labels of its own.
+
+use crate::models::Layout;
+use unic_ucd_category::GeneralCategory;
+
+/// The base that combining marks are shown on
+pub const DOTTED_CIRCLE: char = '\u{25CC}';
+
+/// Abbreviations of invisible characters, as commonly written in keyboard
+/// and font tools
+pub(super) const ABBREVIATIONS: &[(char, &str)] = &[
+    ('\u{00A0}', "NBSP"),
+    ('\u{00AD}', "SHY"),
+    ('\u{034F}', "CGJ"),
+    ('\u{061C}', "ALM"),
+    ('\u{180E}', "MVS"),
+    ('\u{2002}', "ENSP"),
+    ('\u{2003}', "EMSP"),
+    ('\u{2009}', "THSP"),
+    ('\u{200A}', "HSP"),
+    ('\u{200B}', "ZWSP"),
+    ('\u{200C}', "ZWNJ"),
+    ('\u{200D}', "ZWJ"),
+    ('\u{200E}', "LRM"),
+    ('\u{200F}', "RLM"),
+    ('\u{202F}', "NNBSP"),
+    ('\u{2060}', "WJ"),
+    ('\u{FEFF}', "ZWNBSP"),
+];
+
+/// Whether `c` draws nothing of its own, other than the ordinary space
+pub(super) fn is_invisible(c: char) -> bool {
+    c != ' '
+        && (c.is_whitespace()
+            || c.is_control()
+            || GeneralCategory::of(c) == GeneralCategory::Format)
+}
+
+/// Like `ZWJ`, or `U+2061` for invisible characters without a common
+/// abbreviation
+fn abbreviation(c: char) -> String {
+    ABBREVIATIONS
+        .iter()
+        .find(|(other, _)| *other == c)
+        .map(|(_, abbreviation)| abbreviation.to_string())
+        .unwrap_or_else(|| format!("U+{:04X}", c as u32))
+}
+
+/// The label of a key typing `output`, if it cannot be drawn as it is:
+/// output starting with a combining mark on a dotted circle, and output of
+/// only invisible characters as their abbreviations
+pub fn default_display_label(output: &str) -> Option<String> {
+    let first = output.chars().next()?;
+    if GeneralCategory::of(first).is_mark() {
+        return Some(format!("{}{}", DOTTED_CIRCLE, output));
+    }
+    if output.chars().all(is_invisible) {
+        let abbreviations = output.chars().map(abbreviation).collect::<Vec<_>>();
+        return Some(abbreviations.join(" "));
+    }
+    None
+}
+
+impl Layout {
+    /// What to draw on a key typing `output` instead of the output itself,
+    /// from `displayOverrides` or else `default_display_label`
+    pub fn display_label(&self, output: &str) -> Option<String> {
+        self.display_overrides
+            .as_ref()
+            .and_then(|overrides| overrides.get(output))
+            .cloned()
+            .or_else(|| default_display_label(output))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invisible_outputs_get_labels() {
+        assert_eq!(
+            default_display_label("\u{301}").as_deref(),
+            Some("\u{25CC}\u{301}")
+        );
+        assert_eq!(
+            default_display_label("\u{301}\u{323}").as_deref(),
+            Some("\u{25CC}\u{301}\u{323}")
+        );
+        assert_eq!(default_display_label("\u{200D}").as_deref(), Some("ZWJ"));
+        assert_eq!(
+            default_display_label("\u{200C}\u{200D}").as_deref(),
+            Some("ZWNJ ZWJ")
+        );
+        assert_eq!(default_display_label("\u{2061}").as_deref(), Some("U+2061"));
+        for visible in &["a", "á", "ŋ", " ", "", "\u{200D}a", "a\u{301}"] {
+            assert_eq!(default_display_label(visible), None, "{:?}", visible);
+        }
+    }
+
+    #[test]
+    fn overrides_take_precedence() {
+        let layout: Layout = serde_yaml::from_str(
+            r#"
+displayNames:
+  en: Test
+modes:
+  mobile:
+    default: |
+      a \u{301} \u{200D} \u{200C}
+displayOverrides:
+  "\u0301": ´
+  "\u200C": ZW
+"#,
+        )
+        .unwrap();
+        assert_eq!(layout.display_label("\u{301}").as_deref(), Some("´"));
+        assert_eq!(layout.display_label("\u{200C}").as_deref(), Some("ZW"));
+        assert_eq!(layout.display_label("\u{200D}").as_deref(), Some("ZWJ"));
+        assert_eq!(layout.display_label("a"), None);
+    }
+}
@@ -0,0 +1,86 @@
+//! Flags that change how generators treat a layout
+//!
+//! A layout lists its `features` by name. Unknown names are an error when the
+//! layout is loaded, so a typo does not silently leave a generator's default
+//! behaviour in place.
+
+use crate::{models::Layout, Target};
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumIter, EnumString};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Serialize, Deserialize, Display, EnumString, EnumIter)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum Feature {
+    /// Turn off autocorrection and suggestions, for languages without a
+    /// speller
+    NoAutocorrect,
+    /// Keep caps lock from changing what the keys type
+    NoCapsLock,
+    /// Show the layout's name on the spacebar
+    SpacebarLanguageLabel,
+}
+
+impl Feature {
+    /// The targets whose generators honour the feature
+    pub fn targets(self) -> &'static [Target] {
+        match self {
+            Feature::NoAutocorrect => &[Target::Android, Target::Ios],
+            Feature::NoCapsLock => &[Target::Android, Target::Ios, Target::Win, Target::X11],
+            Feature::SpacebarLanguageLabel => &[Target::Android, Target::Ios],
+        }
+    }
+}
+
+impl Layout {
+    pub fn has_feature(&self, feature: Feature) -> bool {
+        self.features.iter().flatten().any(|&f| f == feature)
+    }
+
+    /// Features that no target the layout has modes for honours
+    pub fn unused_features(&self) -> Vec<Feature> {
+        self.features
+            .iter()
+            .flatten()
+            .copied()
+            .filter(|feature| {
+                !feature
+                    .targets()
+                    .iter()
+                    .any(|&target| self.modes.resolve(target).is_some())
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_features_are_rejected() {
+        let layout = |features: &str| {
+            serde_yaml::from_str::<Layout>(&format!(
+                "displayNames:\n  en: Test\nmodes: {{}}\nfeatures: [{}]\n",
+                features
+            ))
+        };
+
+        let known = layout("no-caps-lock, spacebar-language-label").unwrap();
+        assert!(known.has_feature(Feature::NoCapsLock));
+        assert!(!known.has_feature(Feature::NoAutocorrect));
+
+        let desktop_only = serde_yaml::from_str::<Layout>(
+            "displayNames:\n  en: Test\nmodes:\n  mac:\n    default: |\n      a\nfeatures: [no-caps-lock, no-autocorrect]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            desktop_only.unused_features(),
+            vec![Feature::NoCapsLock, Feature::NoAutocorrect]
+        );
+
+        let error = layout("no-capslock").unwrap_err().to_string();
+        assert!(error.contains("no-caps-lock"), "{}", error);
+    }
+}
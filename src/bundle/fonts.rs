@@ -0,0 +1,296 @@
+//! Fonts embedded into the mobile apps
+//!
+//! The system fonts of phones have no glyphs for many minority scripts, so
+//! their keys would show as boxes. `targets/android.yaml` and
+//! `targets/ios.yaml` can list font files in the bundle as `fonts`, which the
+//! generators copy into the apps. Key labels are drawn with the first of them
+//! that has a glyph for a character, and with the system font otherwise.
+//! `kbdgen lint` reports the characters of a layout that none of the fonts of
+//! a target have, and with `--font`, those that a given font lacks for any
+//! target.
+
+use crate::{models::Layout, KeyValue, ProjectBundle, Target};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+use strum::IntoEnumIterator;
+use unic_ucd_category::GeneralCategory;
+
+/// A font file, read to look up which characters it has glyphs for
+#[derive(Debug, Clone)]
+pub struct Font {
+    pub path: PathBuf,
+    data: Vec<u8>,
+}
+
+impl Font {
+    /// Reads a TrueType or OpenType font, or the first font of a collection
+    pub fn load(path: impl AsRef<Path>) -> Result<Font, Error> {
+        let path = path.as_ref();
+        let data = std::fs::read(path).map_err(|source| Error::CannotRead {
+            path: path.into(),
+            source,
+        })?;
+        ttf_parser::Face::from_slice(&data, 0).map_err(|source| Error::Invalid {
+            path: path.into(),
+            source,
+        })?;
+        Ok(Font {
+            path: path.into(),
+            data,
+        })
+    }
+
+    fn face(&self) -> ttf_parser::Face<'_> {
+        ttf_parser::Face::from_slice(&self.data, 0).expect("fonts are parsed when loaded")
+    }
+
+    /// Whether the font has a glyph for `c`
+    pub fn has_glyph(&self, c: char) -> bool {
+        self.face().glyph_index(c).is_some()
+    }
+}
+
+/// The characters that none of `fonts` have a glyph for
+pub fn missing_glyphs(fonts: &[Font], characters: &BTreeSet<char>) -> BTreeSet<char> {
+    let faces = fonts.iter().map(Font::face).collect::<Vec<_>>();
+    characters
+        .iter()
+        .copied()
+        .filter(|&c| faces.iter().all(|face| face.glyph_index(c).is_none()))
+        .collect()
+}
+
+/// Characters that are not drawn, and so need no glyph: spaces, controls and
+/// format characters like ZWJ
+fn is_drawn(c: char) -> bool {
+    !c.is_whitespace() && !c.is_control() && GeneralCategory::of(c) != GeneralCategory::Format
+}
+
+fn drawn<'a>(values: impl Iterator<Item = &'a String>) -> BTreeSet<char> {
+    values
+        .flat_map(|v| v.chars())
+        .filter(|&c| is_drawn(c))
+        .collect()
+}
+
+fn key_characters<'a>(keys: impl Iterator<Item = &'a KeyValue>) -> BTreeSet<char> {
+    drawn(keys.filter_map(|key| match key {
+        KeyValue::Symbol(value) => Some(value),
+        _ => None,
+    }))
+}
+
+impl Layout {
+    /// The characters on the keys of the layout for a mobile target, by
+    /// mode like `mobile shift`, and `longpress` and `flicks`
+    pub fn label_characters_by_mode(&self, target: Target) -> BTreeMap<String, BTreeSet<char>> {
+        let mut found = BTreeMap::new();
+        if let Some((target, modes)) = self.modes.resolve_mobile(target) {
+            for (mode, key_map) in &modes.0 {
+                let name = format!("{} {}", target, mode);
+                found.insert(name, key_characters(key_map.iter()));
+            }
+        }
+        self.insert_popup_characters(&mut found);
+        found.retain(|_, characters| !characters.is_empty());
+        found
+    }
+
+    /// The characters on the keys of the layout for a mobile target
+    pub fn label_characters(&self, target: Target) -> BTreeSet<char> {
+        self.label_characters_by_mode(target)
+            .values()
+            .flatten()
+            .copied()
+            .collect()
+    }
+
+    /// Every character the layout types, by mode like `win alt` for each
+    /// target it has modes for, and `longpress`, `flicks` and `transforms`
+    pub fn output_characters_by_mode(&self) -> BTreeMap<String, BTreeSet<char>> {
+        let mut found = BTreeMap::new();
+        for target in Target::iter() {
+            for (mode, key_map) in self.modes.desktop_for(target).iter().flat_map(|m| &m.0) {
+                let name = format!("{} {}", target, mode);
+                found.insert(name, key_characters(key_map.0.values()));
+            }
+            for (mode, key_map) in self.modes.mobile_for(target).iter().flat_map(|m| &m.0) {
+                let name = format!("{} {}", target, mode);
+                found.insert(name, key_characters(key_map.iter()));
+            }
+        }
+        self.insert_popup_characters(&mut found);
+        let transforms = self.transforms.iter().flat_map(|t| t.values());
+        found.insert(
+            "transforms".to_string(),
+            drawn(transforms.flat_map(|outputs| outputs.values())),
+        );
+        found.retain(|_, characters| !characters.is_empty());
+        found
+    }
+
+    fn insert_popup_characters(&self, found: &mut BTreeMap<String, BTreeSet<char>>) {
+        let longpress = self.longpress.iter().flat_map(|l| l.values());
+        found.insert("longpress".to_string(), drawn(longpress));
+        let flicks = self
+            .flicks
+            .iter()
+            .flat_map(|f| f.values())
+            .flat_map(|f| vec![&f.up, &f.down, &f.left, &f.right])
+            .flatten();
+        found.insert("flicks".to_string(), drawn(flicks));
+    }
+}
+
+/// The characters of a mode of a layout that none of a set of fonts have
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingGlyphs {
+    /// The mobile target the fonts are the `fonts` of, if they are
+    pub target: Option<Target>,
+    pub layout: String,
+    /// Like `mobile default`, or `longpress`, see
+    /// [`Layout::output_characters_by_mode`]
+    pub mode: String,
+    pub characters: BTreeSet<char>,
+}
+
+fn find_missing_glyphs(
+    fonts: &[Font],
+    target: Option<Target>,
+    layout: &str,
+    by_mode: BTreeMap<String, BTreeSet<char>>,
+) -> Vec<MissingGlyphs> {
+    by_mode
+        .into_iter()
+        .map(|(mode, characters)| MissingGlyphs {
+            target,
+            layout: layout.to_string(),
+            mode,
+            characters: missing_glyphs(fonts, &characters),
+        })
+        .filter(|missing| !missing.characters.is_empty())
+        .collect()
+}
+
+impl ProjectBundle {
+    /// The `fonts` of a mobile target, relative to the bundle
+    pub fn font_paths(&self, target: Target) -> Vec<PathBuf> {
+        let fonts = match target {
+            Target::Android => self.targets.android.as_ref().and_then(|t| t.fonts.as_ref()),
+            Target::Ios => self.targets.i_os.as_ref().and_then(|t| t.fonts.as_ref()),
+            _ => None,
+        };
+        let base = self.path.as_deref().unwrap_or_else(|| Path::new(""));
+        fonts
+            .into_iter()
+            .flatten()
+            .map(|path| base.join(path))
+            .collect()
+    }
+
+    /// The fonts of a mobile target, or why each could not be read
+    pub fn fonts(&self, target: Target) -> Vec<Result<Font, Error>> {
+        self.font_paths(target).iter().map(Font::load).collect()
+    }
+
+    fn sorted_layouts(&self) -> Vec<(&String, &Layout)> {
+        let mut layouts = self.layouts.iter().collect::<Vec<_>>();
+        layouts.sort_by_key(|(name, _)| name.as_str());
+        layouts
+    }
+
+    /// For each mobile target with `fonts`, the characters of each mode of
+    /// its layouts that none of the fonts have. Fonts that cannot be read are
+    /// left out, see [`ProjectBundle::fonts`].
+    pub fn missing_glyphs(&self) -> Vec<MissingGlyphs> {
+        let mut found = vec![];
+        for &target in &[Target::Android, Target::Ios] {
+            let fonts = self
+                .fonts(target)
+                .into_iter()
+                .filter_map(Result::ok)
+                .collect::<Vec<_>>();
+            if fonts.is_empty() {
+                continue;
+            }
+            for (name, layout) in self.sorted_layouts() {
+                let by_mode = layout.label_characters_by_mode(target);
+                found.extend(find_missing_glyphs(&fonts, Some(target), name, by_mode));
+            }
+        }
+        found
+    }
+
+    /// The characters of each mode of every layout that none of `fonts` have
+    pub fn missing_glyphs_in(&self, fonts: &[Font]) -> Vec<MissingGlyphs> {
+        self.sorted_layouts()
+            .into_iter()
+            .flat_map(|(name, layout)| {
+                let by_mode = layout.output_characters_by_mode();
+                find_missing_glyphs(fonts, None, name, by_mode)
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not read font `{}`", path.display())]
+    CannotRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("`{}` is not a TrueType or OpenType font", path.display())]
+    Invalid {
+        path: PathBuf,
+        source: ttf_parser::FaceParsingError,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Has a glyph for `A` only
+    const FONT: &str = "tests/fonts/demo.ttf";
+
+    #[test]
+    fn missing_glyphs_are_found() {
+        let layout: Layout = serde_yaml::from_str(
+            "displayNames:\n  en: Test\nmodes:\n  mobile:\n    default: |\n      A B \\u{200D}\n  mac:\n    default: |\n      C\nlongpress:\n  A: Á\n",
+        )
+        .unwrap();
+        let characters = layout.label_characters(Target::Android);
+        assert_eq!(characters, "ABÁ".chars().collect());
+
+        let font = Font::load(FONT).unwrap();
+        assert!(font.has_glyph('A'));
+        assert_eq!(
+            missing_glyphs(std::slice::from_ref(&font), &characters),
+            "BÁ".chars().collect()
+        );
+        assert!(matches!(
+            Font::load("Cargo.toml"),
+            Err(Error::Invalid { .. })
+        ));
+
+        let mut bundle = ProjectBundle::default();
+        bundle.layouts.insert("test".to_string(), layout);
+        let missing = bundle
+            .missing_glyphs_in(&[font])
+            .into_iter()
+            .map(|m| (m.mode, m.characters.into_iter().collect::<String>()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            missing,
+            vec![
+                ("longpress".to_string(), "Á".to_string()),
+                ("mac default".to_string(), "C".to_string()),
+                ("mobile default".to_string(), "B".to_string()),
+            ]
+        );
+    }
+}
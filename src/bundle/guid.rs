@@ -0,0 +1,63 @@
+//! The GUID of the Windows installer, `uuid` of the Windows target
+//!
+//! The installer registers its layouts under the GUID and finds them again
+//! by it to update or uninstall them, so it has to be set once and never
+//! change. A missing or mistyped one used to only show up when packaging.
+
+use rand::RngCore;
+
+/// Whether `uuid` is a GUID, like `0D18406F-1209-43EF-B18F-58961BC8E2E3`,
+/// with or without braces and hyphens
+pub fn is_guid(uuid: &str) -> bool {
+    let uuid = uuid
+        .strip_prefix('{')
+        .and_then(|uuid| uuid.strip_suffix('}'))
+        .unwrap_or(uuid);
+    let hex = |s: &str| s.chars().all(|c| c.is_ascii_hexdigit());
+    match uuid.len() {
+        32 => hex(uuid),
+        36 => uuid.split('-').map(str::len).eq([8, 4, 4, 4, 12]) && hex(&uuid.replace('-', "")),
+        _ => false,
+    }
+}
+
+/// A random (version 4) GUID, in upper case with hyphens
+pub fn new_guid() -> String {
+    let mut bytes = [0u8; 16];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = bytes
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<String>();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guids_are_checked() {
+        assert!(is_guid("108880AA-3489-4E88-9BA3-8A14B159B13B"));
+        assert!(is_guid("{108880aa-3489-4e88-9ba3-8a14b159b13b}"));
+        assert!(is_guid("108880AA34894E889BA38A14B159B13B"));
+        assert!(!is_guid(""));
+        assert!(!is_guid("108880AA-3489-4E88-9BA3-8A14B159B13"));
+        assert!(!is_guid("108880AA-34894-E88-9BA3-8A14B159B13B"));
+        assert!(!is_guid("108880AG-3489-4E88-9BA3-8A14B159B13B"));
+
+        let guid = new_guid();
+        assert!(is_guid(&guid), "{}", guid);
+        assert_eq!(&guid[14..15], "4");
+        assert_ne!(guid, new_guid());
+    }
+}
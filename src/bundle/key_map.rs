@@ -1,5 +1,8 @@
 pub use crate::bundle::keys::{Error as KeyValueError, KeyValue};
-use crate::{bundle::keys, models::IsoKey};
+use crate::{
+    bundle::keys,
+    models::{IsoKey, ISO_ROWS},
+};
 use derive_collect_docs::CollectDocs;
 use serde::{
     de::{self, Deserializer},
@@ -109,42 +112,65 @@ impl FromStr for DesktopKeyMap {
 }
 
 impl fmt::Display for DesktopKeyMap {
+    /// Writes the key map as a grid of four rows, with every key padded to
+    /// the width of its column so that keys on the same ISO position line up
+    /// (the `D` and `C` rows start one column in, as on a physical keyboard).
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let keys: Vec<String> = self.0.values().map(|v| keys::serialize(&v)).collect();
-        let width = keys
+        use strum::IntoEnumIterator;
+
+        let mut rows: Vec<Vec<(usize, String)>> = vec![Vec::new(); ISO_ROWS.len()];
+        for key in IsoKey::iter() {
+            let (row, column) = key.grid_position();
+            let value = self
+                .0
+                .get(&key)
+                .map(keys::serialize)
+                .unwrap_or_else(|| keys::serialize(&KeyValue::None));
+            rows[row].push((column, value));
+        }
+
+        let column_count = rows
             .iter()
-            .map(|x| unic_segment::Graphemes::new(x).count())
+            .flat_map(|row| row.iter().map(|(column, _)| column + 1))
             .max()
-            .unwrap_or(1);
-        let lines: Vec<&[String]> = [
-            &keys.get(0..13),
-            &keys.get(13..25),
-            &keys.get(25..37),
-            &keys.get(37..48),
-            &keys.get(48..),
-        ]
-        .iter()
-        .filter_map(|x| x.filter(|x| !x.is_empty()))
-        .collect();
-
-        for (idx, line) in lines.into_iter().enumerate() {
-            use std::fmt::Write;
-            let mut l = String::new();
-
-            if idx == 1 || idx == 2 {
-                write!(&mut l, "{key:width$} ", key = " ", width = width)?;
-            }
-            for key in line {
-                write!(&mut l, "{key:width$} ", key = key, width = width)?;
+            .unwrap_or(0);
+        let mut widths = vec![1; column_count];
+        for (column, value) in rows.iter().flatten() {
+            widths[*column] = widths[*column].max(display_width(value));
+        }
+
+        for row in &rows {
+            let mut line = String::new();
+            let mut next_column = 0;
+            for (column, value) in row {
+                for width in &widths[next_column..*column] {
+                    pad(&mut line, "", *width);
+                }
+                pad(&mut line, value, widths[*column]);
+                next_column = column + 1;
             }
 
-            writeln!(f, "{}", l.trim_end())?;
+            writeln!(f, "{}", line.trim_end())?;
         }
 
         Ok(())
     }
 }
 
+/// Number of columns `s` takes up in a monospaced font
+fn display_width(s: &str) -> usize {
+    unicode_width::UnicodeWidthStr::width(s)
+}
+
+/// Append `value` to `line`, followed by enough spaces to fill `width`
+/// columns and one space as separator
+fn pad(line: &mut String, value: &str, width: usize) {
+    line.push_str(value);
+    for _ in display_width(value)..=width {
+        line.push(' ');
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("{description}")]
@@ -210,14 +236,59 @@ impl Serialize for MobileKeyMap {
                     .join(" ")
             })
             .collect();
-        let max_len = lines.iter().map(|x| x.trim().len()).max().unwrap_or(0);
+        let max_width = lines.iter().map(|x| display_width(x)).max().unwrap_or(0);
 
         let mut res = String::new();
         for line in &lines {
-            res.push_str(&format!("{line:^width$}", line = line, width = max_len));
-            res.push_str("\n");
+            let indent = (max_width - display_width(line)) / 2;
+            res.push_str(&" ".repeat(indent));
+            res.push_str(line);
+            res.push('\n');
         }
 
         serializer.serialize_str(&res)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{DesktopKeyMap, KeyValue};
+    use crate::models::IsoKey;
+
+    const LAYER: &str = "' 1 2 3 4 5 6 7 8 9 0 + ´
+  á š e r t y u i o p å ŋ
+  a s d f g h j k l ö ä đ
+ž z č c v b n m , . -
+";
+
+    #[test]
+    fn roundtrips_aligned_layer() {
+        let map: DesktopKeyMap = LAYER.parse().unwrap();
+        assert_eq!(map.to_string(), LAYER);
+    }
+
+    #[test]
+    fn pads_columns_to_widest_key() {
+        let mut map: DesktopKeyMap = LAYER.parse().unwrap();
+        map.0.insert(IsoKey::D01, KeyValue::Symbol("漢".into()));
+        map.0.insert(IsoKey::B03, KeyValue::Symbol("ch".into()));
+
+        let expected = "' 1  2 3  4 5 6 7 8 9 0 + ´
+  漢 š e  r t y u i o p å ŋ
+  a  s d  f g h j k l ö ä đ
+ž z  č ch v b n m , . -
+";
+        assert_eq!(map.to_string(), expected);
+        assert_eq!(expected.parse::<DesktopKeyMap>().unwrap(), map);
+    }
+
+    #[test]
+    fn keeps_positions_of_missing_keys() {
+        let mut map: DesktopKeyMap = LAYER.parse().unwrap();
+        map.0.remove(&IsoKey::C05);
+
+        let reparsed: DesktopKeyMap = map.to_string().parse().unwrap();
+        assert_eq!(reparsed.0[&IsoKey::C05], KeyValue::None);
+        assert_eq!(reparsed.0[&IsoKey::C06], map.0[&IsoKey::C06]);
+    }
+}
@@ -0,0 +1,195 @@
+//! Expected behaviour of layouts
+//!
+//! A bundle can say what its layouts type in `tests/*.yaml`, so that a change
+//! to a layout that breaks something it used to do is caught before release:
+//!
+//! ```yaml
+//! layout: se-NO
+//! cases:
+//!   - keys: shift+E05
+//!     expect: "%"
+//!   - name: acute on a
+//!     keys: E12 C01
+//!     expect: á
+//! ```
+//!
+//! The keys are typed with `Layout::simulate`, so dead keys go through the
+//! layout's transforms as they would on the target.
+
+use crate::{models::Layout, KeyPress, KeyPressError, Target};
+use serde::{Deserialize, Serialize};
+
+/// The targets cases are typed on unless they say otherwise
+const DESKTOP_TARGETS: [Target; 4] = [Target::Win, Target::Mac, Target::Chrome, Target::X11];
+
+/// The contents of a file in the `tests` directory of a bundle
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LayoutTests {
+    /// The layout to test, by default the one named like the file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layout: Option<String>,
+
+    /// The targets to type the cases on, by default every desktop target the
+    /// layout has modes for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub targets: Option<Vec<Target>>,
+
+    pub cases: Vec<TestCase>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TestCase {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// The key presses, see `KeyPress::parse_sequence`
+    pub keys: String,
+
+    /// The text typed by the keys
+    pub expect: String,
+
+    /// The targets to type this case on, instead of those of the file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub targets: Option<Vec<Target>>,
+}
+
+/// A case that typed something else than expected on a target
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestFailure {
+    pub name: Option<String>,
+    pub keys: String,
+    pub target: Target,
+    pub expected: String,
+    pub actual: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TestOutcome {
+    /// The number of cases that typed what was expected, counted once per
+    /// target
+    pub passed: usize,
+    pub failures: Vec<TestFailure>,
+}
+
+impl LayoutTests {
+    /// Types every case on its targets. Targets the layout has no modes for,
+    /// even through fallbacks, are skipped.
+    pub fn run(&self, layout: &Layout) -> Result<TestOutcome, KeyPressError> {
+        let mut outcome = TestOutcome::default();
+
+        for case in &self.cases {
+            let presses = KeyPress::parse_sequence(&case.keys)?;
+
+            for &target in self.targets_of(case) {
+                if layout.modes.resolve(target).is_none() {
+                    continue;
+                }
+                let actual = layout.simulate(target, &presses);
+                if actual == case.expect {
+                    outcome.passed += 1;
+                } else {
+                    outcome.failures.push(TestFailure {
+                        name: case.name.clone(),
+                        keys: case.keys.clone(),
+                        target,
+                        expected: case.expect.clone(),
+                        actual,
+                    });
+                }
+            }
+        }
+
+        Ok(outcome)
+    }
+
+    /// The targets `case` is typed on, whether or not the layout has modes
+    /// for them
+    fn targets_of<'a>(&'a self, case: &'a TestCase) -> &'a [Target] {
+        case.targets
+            .as_deref()
+            .or(self.targets.as_deref())
+            .unwrap_or(&DESKTOP_TARGETS)
+    }
+
+    /// The cases that `run` types on `target`, for typing them on something
+    /// else than `Layout::simulate`, like the symbols generated for it
+    pub fn cases_on<'a>(
+        &'a self,
+        layout: &Layout,
+        target: Target,
+    ) -> impl Iterator<Item = &'a TestCase> + 'a {
+        let has_modes = layout.modes.resolve(target).is_some();
+        self.cases
+            .iter()
+            .filter(move |case| has_modes && self.targets_of(case).contains(&target))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cases_are_typed_on_each_target_with_modes() {
+        let layout: Layout = serde_yaml::from_str(
+            r#"
+displayNames:
+  en: Test
+modes:
+  desktop:
+    default: |
+      ´ 1 2 3 4 5 6 7 8 9 0 + '
+        q w e r t y u i o p å ¨
+        a s d f g h j k l ö ä '
+      < z x c v b n m , . -
+    shift: |
+      ` ! " # $ % & / ( ) = ? *
+        Q W E R T Y U I O P Å ^
+        A S D F G H J K L Ö Ä *
+      > Z X C V B N M ; : _
+  mac:
+    default: |
+      ´ 1 2 3 4 5 6 7 8 9 0 + '
+        q w e r t y u i o p å ¨
+        a s d f g h j k l ö ä '
+      < z x c v b n m , . -
+deadKeys:
+  desktop:
+    default: ["´"]
+transforms:
+  ´:
+    a: á
+    " ": ´
+"#,
+        )
+        .unwrap();
+        let tests: LayoutTests = serde_yaml::from_str(
+            r#"
+cases:
+  - keys: shift+E05
+    expect: "%"
+  - keys: E00 C01
+    expect: á
+"#,
+        )
+        .unwrap();
+
+        let outcome = tests.run(&layout).unwrap();
+        // The mac modes have neither shift nor dead keys
+        assert_eq!(outcome.passed, 6);
+        assert_eq!(
+            outcome
+                .failures
+                .iter()
+                .map(|f| (f.target, f.actual.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(Target::Mac, ""), (Target::Mac, "´a")]
+        );
+
+        let tests = LayoutTests {
+            targets: Some(vec![Target::Android]),
+            ..tests
+        };
+        assert_eq!(tests.run(&layout).unwrap(), TestOutcome::default());
+    }
+}
@@ -0,0 +1,133 @@
+//! Loading only the parts of a layout that a query needs
+//!
+//! Most of a layout file is its modes, for up to eight targets. Commands that
+//! answer a question about a single target, like `which-key`, read the file
+//! into a raw YAML document and only turn the sections they ask for into
+//! models. The modes of other targets are never deserialized.
+
+use super::loading::{prepare_layout, Error};
+use crate::{models::Layout, Target};
+use serde::de::DeserializeOwned;
+use serde_yaml::{Mapping, Value};
+use std::path::{Path, PathBuf};
+
+/// A layout file that has been parsed as YAML, but not into a `Layout`
+#[derive(Debug, Clone)]
+pub struct LazyLayout {
+    path: PathBuf,
+    document: Mapping,
+}
+
+impl LazyLayout {
+    pub fn load(path: impl AsRef<Path>) -> Result<LazyLayout, Error> {
+        let path = path.as_ref();
+        let document = match super::loading::read_yml::<Value>(path, None)? {
+            Value::Mapping(document) => document,
+            Value::Null => Mapping::new(),
+            other => {
+                return Err(parse_error(
+                    path,
+                    serde_yaml::from_value::<Mapping>(other).unwrap_err(),
+                ))
+            }
+        };
+        Ok(LazyLayout {
+            path: path.to_path_buf(),
+            document,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// A top-level section of the layout, like `displayNames`, as a model
+    pub fn section<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, Error> {
+        self.document
+            .get(&Value::from(key))
+            .map(|value| serde_yaml::from_value(value.clone()))
+            .transpose()
+            .map_err(|source| parse_error(&self.path, source))
+    }
+
+    /// The targets the file has modes for, without parsing the modes
+    pub fn targets(&self) -> Vec<Target> {
+        use strum::IntoEnumIterator;
+
+        let modes = self.modes();
+        Target::iter()
+            .filter(|target| matches!(modes, Some(modes) if modes.contains_key(&Value::from(target.to_string()))))
+            .collect()
+    }
+
+    /// The layout with only the modes that `target` falls back to
+    ///
+    /// When the file has none of them, every mode is kept, as the modes of
+    /// `target` may still be derived from them.
+    pub fn for_target(&self, target: Target) -> Result<Layout, Error> {
+        let modes = self.modes();
+        let kept = target
+            .fallback_chain()
+            .map(|target| Value::from(target.to_string()))
+            .filter_map(|key| {
+                let value = modes?.get(&key)?.clone();
+                Some((key, value))
+            })
+            .collect::<Mapping>();
+
+        let mut document = self.document.clone();
+        if !kept.is_empty() {
+            document.insert(Value::from("modes"), Value::Mapping(kept));
+        }
+        let mut layout: Layout = serde_yaml::from_value(Value::Mapping(document))
+            .map_err(|source| parse_error(&self.path, source))?;
+        prepare_layout(&self.path, &mut layout)?;
+        Ok(layout)
+    }
+
+    fn modes(&self) -> Option<&Mapping> {
+        match self.document.get(&Value::from("modes")) {
+            Some(Value::Mapping(modes)) => Some(modes),
+            _ => None,
+        }
+    }
+}
+
+fn parse_error(path: &Path, source: serde_yaml::Error) -> Error {
+    Error::ParseFile {
+        path: path.into(),
+        source,
+        alias: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Load;
+    use std::collections::BTreeMap;
+
+    const LAYOUT: &str = "examples/sme.kbdgen/layouts/se-NO.yaml";
+
+    #[test]
+    fn only_the_modes_of_the_target_are_materialized() {
+        let lazy = LazyLayout::load(LAYOUT).unwrap();
+        let full = Layout::load(LAYOUT).unwrap();
+        assert_eq!(lazy.targets(), full.modes.available_modes());
+
+        let win = lazy.for_target(Target::Win).unwrap();
+        assert!(win.modes.win.is_some());
+        assert!(win.modes.mac.is_none());
+        assert_eq!(
+            win.which_key(Target::Win, "ŧ", &[]),
+            full.which_key(Target::Win, "ŧ", &[])
+        );
+
+        let names: BTreeMap<String, String> = lazy.section("displayNames").unwrap().unwrap();
+        assert_eq!(names, full.display_names);
+        assert!(lazy
+            .section::<Vec<String>>("noSuchSection")
+            .unwrap()
+            .is_none());
+    }
+}
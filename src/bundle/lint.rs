@@ -0,0 +1,475 @@
+//! Checks for mistakes that do not stop a bundle from loading
+//!
+//! Each warning names the rule that found it, so that tools can tell the
+//! kinds of warnings apart. How seriously each rule is taken can be set per
+//! bundle and per layout in `.kbdgen-lint.yaml`, see `Config`.
+
+use crate::{models::TransformPartial, Font, ProjectBundle, Target, WinIdCollision};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    path::{Path, PathBuf},
+};
+use strum_macros::{Display, EnumIter, EnumString};
+
+/// The lint configuration of a bundle, in its root
+pub const CONFIG_FILE: &str = ".kbdgen-lint.yaml";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Display, EnumString, EnumIter, Deserialize)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum Rule {
+    /// `flicks` for a key that no mobile mode has
+    UnknownFlickKeys,
+    /// `reorders` or `backspaces` in a layout with desktop modes
+    UnsupportedReordering,
+    /// Transforms for chains of dead keys in a layout with desktop modes for
+    /// targets other than macOS
+    UnsupportedDeadKeyChains,
+    /// A `transformPartial` that targets the layout has modes for cannot
+    /// honour
+    IgnoredTransformPartial,
+    /// A transform written out for a combining mark and a base character
+    /// typed in one order that `symmetricTransforms` would generate with a
+    /// different output
+    SymmetricTransformConflicts,
+    /// Brackets in left-to-right order in a right-to-left layout
+    UnmirroredBrackets,
+    /// Lookalike characters used for the same purpose by different layouts
+    InconsistentConfusables,
+    /// `features` that no target of the layout honours
+    UnusedFeatures,
+    /// `modifierRemaps` that a target cannot do, or for a target that
+    /// ignores them
+    IgnoredModifierRemaps,
+    /// A `cmd` or `ctrl` mode that moves or drops the letter of a common
+    /// shortcut, see the targets' `shortcuts`
+    ShortcutConflicts,
+    /// An `android` or `ios` target in a project without the URLs that app
+    /// stores need
+    MissingStoreUrls,
+    /// `fonts` of a mobile target that cannot be read
+    UnreadableFonts,
+    /// Characters on the keys of a layout that none of the `fonts` of a
+    /// mobile target have, or that a font given to `kbdgen lint --font`
+    /// lacks
+    MissingGlyphs,
+    /// Layouts built into the same Windows keyboard DLL as each other or as
+    /// a layout Windows comes with, see `kbdgen target win allocate-ids`
+    WinIdCollisions,
+    /// Invisible characters written as they are in a layout file rather than
+    /// as placeholders like `{{zwj}}` or escapes
+    RawInvisibleCharacters,
+    /// A `style` in the Android target of a layout, which is no longer read
+    /// since `theme` replaced it
+    AndroidStyle,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    pub rule: Rule,
+    /// The layout the warning is about, if it is about a single one
+    pub layout: Option<String>,
+    pub message: String,
+}
+
+/// How seriously the warnings of a rule are taken
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    /// Not reported
+    Allow,
+    /// Reported, the default
+    #[default]
+    Warn,
+    /// Reported as errors, which fail `kbdgen lint` and `validate`
+    Deny,
+}
+
+/// The levels of rules in a bundle, read from `.kbdgen-lint.yaml`:
+///
+/// ```yaml
+/// rules:
+///   missing-glyphs: deny
+///   inconsistent-confusables: allow
+/// layouts:
+///   se-NO:
+///     missing-glyphs: warn
+/// ```
+///
+/// The level for a layout takes precedence over the level for the whole
+/// bundle, and rules not given a level warn.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub rules: BTreeMap<Rule, Level>,
+    #[serde(default)]
+    pub layouts: BTreeMap<String, BTreeMap<Rule, Level>>,
+}
+
+impl Config {
+    /// The configuration in the root of the bundle at `bundle_path`, or the
+    /// default one if it has none
+    pub fn load(bundle_path: &Path) -> Result<Config, Error> {
+        let path = bundle_path.join(CONFIG_FILE);
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(source) => return Err(Error::CannotRead { path, source }),
+        };
+        if text.trim().is_empty() {
+            return Ok(Config::default());
+        }
+        serde_yaml::from_str(&text).map_err(|source| Error::CannotParse { path, source })
+    }
+
+    pub fn level(&self, warning: &Warning) -> Level {
+        warning
+            .layout
+            .as_ref()
+            .and_then(|layout| self.layouts.get(layout)?.get(&warning.rule))
+            .or_else(|| self.rules.get(&warning.rule))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Splits `warnings` into those denied and those that only warn, leaving
+    /// out those allowed
+    pub fn apply(&self, warnings: Vec<Warning>) -> (Vec<Warning>, Vec<Warning>) {
+        let mut denied = vec![];
+        let mut warned = vec![];
+        for warning in warnings {
+            match self.level(&warning) {
+                Level::Allow => {}
+                Level::Warn => warned.push(warning),
+                Level::Deny => denied.push(warning),
+            }
+        }
+        (denied, warned)
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.layout {
+            Some(layout) => write!(f, "{}: {}", layout, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// All warnings for the bundle, sorted by layout
+pub fn lint(bundle: &ProjectBundle) -> Vec<Warning> {
+    let mut layouts = bundle.layouts.iter().collect::<Vec<_>>();
+    layouts.sort_by_key(|(name, _)| name.as_str());
+
+    let mut warnings = vec![];
+    for (name, layout) in layouts {
+        let mut warn = |rule, message| {
+            warnings.push(Warning {
+                rule,
+                layout: Some(name.clone()),
+                message,
+            })
+        };
+
+        for key in layout.unknown_flick_keys() {
+            warn(
+                Rule::UnknownFlickKeys,
+                format!("flicks are defined for `{}`, which no mobile mode has", key),
+            );
+        }
+        for target in layout.targets_without_reordering() {
+            warn(
+                Rule::UnsupportedReordering,
+                format!("reorders and backspaces are ignored for {}", target),
+            );
+        }
+        for target in layout.targets_without_dead_key_chains() {
+            let chains = layout
+                .dead_key_chains()
+                .iter()
+                .map(|chain| format!("`{}`", chain))
+                .collect::<Vec<_>>();
+            warn(
+                Rule::UnsupportedDeadKeyChains,
+                format!(
+                    "dead key chains {} are ignored for {}",
+                    chains.join(", "),
+                    target
+                ),
+            );
+        }
+        for target in layout.targets_ignoring_transform_partial() {
+            let always = match layout.transform_partial(target) {
+                TransformPartial::Show => "shows",
+                TransformPartial::Hide => "hides",
+            };
+            warn(
+                Rule::IgnoredTransformPartial,
+                format!(
+                    "transformPartial is ignored for {}, which always {} transforms in progress",
+                    target, always
+                ),
+            );
+        }
+        for feature in layout.unused_features() {
+            warn(
+                Rule::UnusedFeatures,
+                format!(
+                    "feature `{}` is only honoured for {}",
+                    feature,
+                    feature
+                        .targets()
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            );
+        }
+        for (target, key, role) in layout.ignored_modifier_remaps() {
+            warn(
+                Rule::IgnoredModifierRemaps,
+                format!("remapping `{}` to {} is ignored for {}", key, role, target),
+            );
+        }
+        for conflict in layout.symmetric_transform_conflicts() {
+            warn(
+                Rule::SymmetricTransformConflicts,
+                format!("{} when typed in either order", conflict),
+            );
+        }
+        for conflict in layout.shortcut_conflicts(&bundle.targets) {
+            warn(Rule::ShortcutConflicts, conflict.to_string());
+        }
+        let file = bundle
+            .path
+            .as_ref()
+            .map(|path| path.join("layouts").join(format!("{}.yaml", name)));
+        if let Some(text) = file.and_then(|file| std::fs::read_to_string(file).ok()) {
+            for raw in super::raw_invisible_characters(&text) {
+                warn(Rule::RawInvisibleCharacters, raw.to_string());
+            }
+            if has_android_style(&text) {
+                warn(
+                    Rule::AndroidStyle,
+                    "`targets.android.style` is ignored, write it as `theme`".to_string(),
+                );
+            }
+        }
+        if layout.is_rtl() {
+            for pair in layout.unmirrored_pairs() {
+                warn(
+                    Rule::UnmirroredBrackets,
+                    format!(
+                        "{} mode `{}` has brackets on {:?} and {:?} in left-to-right order",
+                        pair.target, pair.mode, pair.open, pair.close
+                    ),
+                );
+            }
+        }
+    }
+
+    let store_targets: Vec<_> = vec![
+        (Target::Android, bundle.targets.android.is_some()),
+        (Target::Ios, bundle.targets.i_os.is_some()),
+    ]
+    .into_iter()
+    .filter(|(_, configured)| *configured)
+    .map(|(target, _)| target.to_string())
+    .collect();
+    let missing = bundle.project.missing_store_urls();
+    if !store_targets.is_empty() && !missing.is_empty() {
+        warnings.push(Warning {
+            rule: Rule::MissingStoreUrls,
+            layout: None,
+            message: format!(
+                "app store releases for {} need {} in project.yaml",
+                store_targets.join(" and "),
+                missing
+                    .iter()
+                    .map(|name| format!("`{}`", name))
+                    .collect::<Vec<_>>()
+                    .join(" and ")
+            ),
+        });
+    }
+
+    for target in &[Target::Android, Target::Ios] {
+        for error in bundle.fonts(*target).into_iter().filter_map(Result::err) {
+            warnings.push(Warning {
+                rule: Rule::UnreadableFonts,
+                layout: None,
+                message: format!("{} font ignored: {}", target, error),
+            });
+        }
+    }
+    for missing in bundle.missing_glyphs() {
+        warnings.push(Warning {
+            rule: Rule::MissingGlyphs,
+            message: format!(
+                "no {} font has {} in `{}`, the system font is used for them",
+                missing.target.map(|t| t.to_string()).unwrap_or_default(),
+                describe_characters(&missing.characters),
+                missing.mode
+            ),
+            layout: Some(missing.layout),
+        });
+    }
+
+    for collision in bundle.win_id_collisions() {
+        let layout = match &collision {
+            WinIdCollision::System(id) => Some(id.layout.clone()),
+            WinIdCollision::Layouts { .. } => None,
+        };
+        warnings.push(Warning {
+            rule: Rule::WinIdCollisions,
+            layout,
+            message: format!("{}, set `targets.win.id` to another id", collision),
+        });
+    }
+
+    for found in super::confusables::inconsistent_confusables(&bundle.layouts) {
+        let uses = found
+            .uses
+            .iter()
+            .map(|(c, layouts)| format!("U+{:04X} in {}", *c as u32, layouts.join(", ")))
+            .collect::<Vec<_>>();
+        warnings.push(Warning {
+            rule: Rule::InconsistentConfusables,
+            layout: None,
+            message: format!(
+                "layouts use different characters for the {}: {}",
+                found.group,
+                uses.join("; ")
+            ),
+        });
+    }
+
+    warnings
+}
+
+/// Warnings for the characters of each layout that none of `fonts` have,
+/// sorted by layout
+pub fn lint_fonts(bundle: &ProjectBundle, fonts: &[Font]) -> Vec<Warning> {
+    let names = fonts
+        .iter()
+        .map(|font| match font.path.file_name() {
+            Some(name) => format!("`{}`", name.to_string_lossy()),
+            None => format!("`{}`", font.path.display()),
+        })
+        .collect::<Vec<_>>();
+    bundle
+        .missing_glyphs_in(fonts)
+        .into_iter()
+        .map(|missing| Warning {
+            rule: Rule::MissingGlyphs,
+            message: format!(
+                "{} in `{}` missing from {}",
+                describe_characters(&missing.characters),
+                missing.mode,
+                names.join(", ")
+            ),
+            layout: Some(missing.layout),
+        })
+        .collect()
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not read `{}`", path.display())]
+    CannotRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Could not parse `{}`", path.display())]
+    CannotParse {
+        path: PathBuf,
+        source: serde_yaml::Error,
+    },
+}
+
+/// Like `ꞌ (U+A78C), ŋ (U+014B)`
+fn describe_characters(characters: &BTreeSet<char>) -> String {
+    characters
+        .iter()
+        .map(|c| format!("{} (U+{:04X})", c, *c as u32))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Whether `text`, the YAML of a layout, has a `style` in its Android target
+fn has_android_style(text: &str) -> bool {
+    serde_yaml::from_str::<serde_yaml::Value>(text)
+        .ok()
+        .and_then(|layout| layout.get("targets")?.get("android")?.get("style").cloned())
+        .is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn warning(rule: Rule, layout: Option<&str>) -> Warning {
+        Warning {
+            rule,
+            layout: layout.map(str::to_string),
+            message: String::new(),
+        }
+    }
+
+    #[test]
+    fn levels_for_layouts_take_precedence() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+rules:
+  missing-glyphs: deny
+  inconsistent-confusables: allow
+layouts:
+  se-NO:
+    missing-glyphs: warn
+  sma-NO:
+    unused-features: allow
+"#,
+        )
+        .unwrap();
+
+        let warnings = vec![
+            warning(Rule::MissingGlyphs, Some("se-NO")),
+            warning(Rule::MissingGlyphs, Some("sma-NO")),
+            warning(Rule::InconsistentConfusables, None),
+            warning(Rule::UnusedFeatures, Some("se-NO")),
+            warning(Rule::UnusedFeatures, Some("sma-NO")),
+        ];
+        let (denied, warned) = config.apply(warnings.clone());
+        assert_eq!(denied, vec![warnings[1].clone()]);
+        assert_eq!(warned, vec![warnings[0].clone(), warnings[3].clone()]);
+
+        let (denied, warned) = Config::default().apply(warnings.clone());
+        assert!(denied.is_empty());
+        assert_eq!(warned, warnings);
+    }
+
+    #[test]
+    fn android_styles_are_found() {
+        assert!(has_android_style(
+            "targets:\n  android:\n    style:\n      keyColor: \"#fff\"\n"
+        ));
+        assert!(!has_android_style(
+            "targets:\n  android:\n    theme:\n      keyCornerRadius: 8\n"
+        ));
+        assert!(!has_android_style("style: {}\nmodes: {}\n"));
+    }
+
+    #[test]
+    fn unknown_rules_and_levels_are_errors() {
+        assert!(serde_yaml::from_str::<Config>("rules:\n  missing-glyph: deny\n").is_err());
+        assert!(serde_yaml::from_str::<Config>("rules:\n  missing-glyphs: error\n").is_err());
+    }
+}
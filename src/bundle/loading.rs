@@ -1,9 +1,10 @@
+use super::aliases::{self, AliasNote};
 use crate::{
-    models::{Layout, Project},
-    ProjectBundle, Targets,
+    models::{Layout, Project, TargetWindows},
+    Cache, ProjectBundle, Targets,
 };
 use log::trace;
-use serde::de::DeserializeOwned;
+use serde::{de::DeserializeOwned, Serialize};
 use std::{
     collections::HashMap,
     default::Default,
@@ -11,6 +12,7 @@ use std::{
     fs::{canonicalize, read_dir},
     hash::BuildHasher,
     path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
 };
 use thiserror::Error;
 
@@ -21,93 +23,366 @@ pub trait Load: Sized {
 
 impl Load for ProjectBundle {
     fn load(bundle_path: impl AsRef<Path>) -> Result<Self, Error> {
-        let bundle_path: &Path = bundle_path.as_ref();
-        trace!("Loading {:?}", bundle_path);
+        load_bundle(bundle_path.as_ref(), None, &mut Err)
+    }
+}
 
-        Ok(ProjectBundle {
-            path: Some(
-                canonicalize(&bundle_path).map_err(|source| Error::ReadFile {
-                    path: bundle_path.into(),
-                    source,
-                })?,
-            ),
-            project: Load::load(&bundle_path.join("project.yaml"))?,
-            layouts: Load::load(&bundle_path.join("layouts"))?,
-            targets: Load::load(&bundle_path.join("targets"))?,
-        })
+/// A bundle with the layouts and targets that could be loaded, and the errors
+/// for the files that could not
+#[derive(Debug)]
+pub struct PartialBundle {
+    pub bundle: ProjectBundle,
+    pub errors: Vec<Error>,
+}
+
+impl ProjectBundle {
+    /// Like `Load::load`, but reuses the parsed form of files that have not
+    /// changed since they were last loaded with the same cache
+    pub fn load_cached(bundle_path: impl AsRef<Path>, cache: &Cache) -> Result<Self, Error> {
+        load_bundle(bundle_path.as_ref(), Some(cache), &mut Err)
+    }
+
+    /// Like `Load::load`, but leaves out the layouts and targets that cannot
+    /// be loaded instead of stopping at the first one
+    ///
+    /// A bundle without a valid `project.yaml`, or whose directories cannot
+    /// be read, is still an error.
+    pub fn load_partial(
+        bundle_path: impl AsRef<Path>,
+        cache: Option<&Cache>,
+    ) -> Result<PartialBundle, Error> {
+        let mut errors = vec![];
+        let bundle = load_bundle(bundle_path.as_ref(), cache, &mut |e| {
+            errors.push(e);
+            Ok(())
+        })?;
+        Ok(PartialBundle { bundle, errors })
     }
 }
 
+/// What to do with the error of a single layout or target file: fail by
+/// returning it, or skip the file
+type OnError<'a> = dyn FnMut(Error) -> Result<(), Error> + 'a;
+
+fn load_bundle(
+    bundle_path: &Path,
+    cache: Option<&Cache>,
+    on_error: &mut OnError,
+) -> Result<ProjectBundle, Error> {
+    trace!("Loading {:?}", bundle_path);
+
+    Ok(ProjectBundle {
+        path: Some(canonicalize(bundle_path).map_err(|source| Error::ReadFile {
+            path: bundle_path.into(),
+            source,
+        })?),
+        project: load_project(&bundle_path.join("project.yaml"), cache)?,
+        layouts: load_layouts(&bundle_path.join("layouts"), cache, on_error)?,
+        targets: load_targets(&bundle_path.join("targets"), cache, on_error)?,
+    })
+}
+
 impl Load for Project {
     fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
-        let path: &Path = path.as_ref();
-        Ok(read_yml(path)?)
+        load_project(path.as_ref(), None)
+    }
+}
+
+fn load_project(path: &Path, cache: Option<&Cache>) -> Result<Project, Error> {
+    let project: Project = read_yml(path, cache)?;
+    let errors = project.render.as_ref().map(|render| render.errors());
+    if let Some(message) = errors.into_iter().flatten().next() {
+        return Err(Error::InvalidRender {
+            path: path.into(),
+            message,
+        });
+    }
+    Ok(project)
+}
+
+impl Load for Layout {
+    fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let mut layout: Layout = read_yml(path.as_ref(), None)?;
+        prepare_layout(path.as_ref(), &mut layout)?;
+        Ok(layout)
+    }
+}
+
+/// What every layout goes through once it is read, however it is loaded:
+/// placeholders and derived modes are expanded, transform patterns become
+/// explicit pairs, and the result is checked
+pub(super) fn prepare_layout(path: &Path, layout: &mut Layout) -> Result<(), Error> {
+    layout.expand_placeholders();
+    layout.apply_derives();
+    expand_transform_patterns(path, layout)?;
+    check_layout(path, layout)
+}
+
+/// Patterns are expanded as the layout is loaded, so that every target,
+/// including the Python generators, sees only explicit pairs
+fn expand_transform_patterns(path: &Path, layout: &mut Layout) -> Result<(), Error> {
+    layout
+        .expand_transform_patterns()
+        .map_err(|source| Error::InvalidTransformPattern {
+            path: path.into(),
+            source,
+        })
+}
+
+/// Mode names are free strings in YAML, so a typo would otherwise silently
+/// become a mode of its own. The same goes for dead keys that only have a
+/// terminator.
+pub(super) fn check_layout(path: &Path, layout: &Layout) -> Result<(), Error> {
+    if let Some(source) = layout.mode_name_errors().into_iter().next() {
+        let alias = std::fs::read_to_string(path)
+            .ok()
+            .and_then(|text| aliases::note_for_key(&text, source.mode()));
+        return Err(Error::InvalidModeName {
+            path: path.into(),
+            source,
+            alias,
+        });
     }
+    if let Some(dead_key) = layout.unknown_dead_key_terminators().into_iter().next() {
+        return Err(Error::UnknownDeadKeyTerminator {
+            path: path.into(),
+            dead_key: dead_key.to_string(),
+        });
+    }
+    if let Some((target, message)) = layout.theme_errors().into_iter().next() {
+        return Err(Error::InvalidTheme {
+            path: path.into(),
+            target,
+            message,
+        });
+    }
+    Ok(())
 }
 
 impl<S: BuildHasher + Default> Load for HashMap<String, Layout, S> {
     fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
-        let path: &Path = path.as_ref();
-        let yml_files = read_dir(path)
-            .map_err(|source| Error::ReadFile {
-                path: path.into(),
-                source,
-            })?
-            .filter_map(Result::ok)
-            .map(|f| f.path())
-            .filter(|p| p.is_file())
-            .filter(|p| p.extension() == Some(OsStr::new("yaml")));
-
-        yml_files
-            .map(|path| {
-                let name = path
-                    .file_stem()
-                    .ok_or_else(|| Error::MalformedFilename { path: path.clone() })?
-                    .to_string_lossy()
-                    .to_string();
-                let data = read_yml(&path)?;
-                Ok((name, data))
-            })
-            .collect()
+        load_layouts(path.as_ref(), None, &mut Err)
+    }
+}
+
+fn load_layouts<S: BuildHasher + Default>(
+    path: &Path,
+    cache: Option<&Cache>,
+    on_error: &mut OnError,
+) -> Result<HashMap<String, Layout, S>, Error> {
+    let mut yml_files = read_dir(path)
+        .map_err(|source| Error::ReadFile {
+            path: path.into(),
+            source,
+        })?
+        .filter_map(Result::ok)
+        .map(|f| f.path())
+        .filter(|p| p.is_file())
+        .filter(|p| p.extension() == Some(OsStr::new("yaml")))
+        .collect::<Vec<_>>();
+    // Files are handled in order of path, so which of two duplicates is
+    // reported does not depend on the order of the directory
+    yml_files.sort();
+    let parsed = read_layouts_parallel(&yml_files, cache);
+
+    let mut layouts = HashMap::default();
+    let mut files = HashMap::<String, PathBuf>::new();
+    for (path, layout) in yml_files.into_iter().zip(parsed) {
+        let name = match path.file_stem() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => {
+                on_error(Error::MalformedFilename { path: path.clone() })?;
+                continue;
+            }
+        };
+        let layout: Layout = match layout {
+            Ok(layout) => layout,
+            Err(e) => {
+                on_error(e)?;
+                continue;
+            }
+        };
+
+        let variants = layout
+            .variant_layouts()
+            .into_iter()
+            .map(|(variant, layout)| (crate::variant_name(&name, &variant), layout))
+            .collect::<Vec<_>>();
+        for (name, mut layout) in std::iter::once((name, layout)).chain(variants) {
+            if let Err(source) = crate::check_layout_name(&name) {
+                on_error(Error::InvalidLayoutName {
+                    path: path.clone(),
+                    source,
+                })?;
+                continue;
+            }
+            if let Err(e) = prepare_layout(&path, &mut layout) {
+                on_error(e)?;
+                continue;
+            }
+            if let Some(other) = files.get(&name) {
+                on_error(Error::DuplicateLayout {
+                    name,
+                    paths: vec![other.clone(), path.clone()],
+                })?;
+                continue;
+            }
+            files.insert(name.clone(), path.clone());
+            layouts.insert(name, layout);
+        }
     }
+
+    Ok(layouts)
+}
+
+/// Reads and parses the layout files on as many threads as there are CPUs,
+/// returning the results in the order of `paths`
+///
+/// Loading a large bundle is mostly reading and parsing many small files,
+/// which do not depend on each other.
+fn read_layouts_parallel(paths: &[PathBuf], cache: Option<&Cache>) -> Vec<Result<Layout, Error>> {
+    let threads = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(paths.len());
+    if threads <= 1 {
+        return paths.iter().map(|path| read_yml(path, cache)).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let mut results = std::thread::scope(|scope| {
+        let workers = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = vec![];
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        match paths.get(index) {
+                            Some(path) => results.push((index, read_yml(path, cache))),
+                            None => return results,
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("layout parsing does not panic"))
+            .collect::<Vec<_>>()
+    });
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
 }
 
 impl Load for Targets {
     fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
-        let path: &Path = path.as_ref();
-        Ok(Targets {
-            android: read_yml_if_exists(&path.join("android.yaml"))?,
-            i_os: read_yml_if_exists(&path.join("ios.yaml"))?,
-            mac_os: read_yml_if_exists(&path.join("macos.yaml"))?,
-            windows: read_yml_if_exists(&path.join("windows.yaml"))?,
-            chrome: read_yml_if_exists(&path.join("chrome.yaml"))?,
-            x11: read_yml_if_exists(&path.join("x11.yaml"))?,
-            mim: read_yml_if_exists(&path.join("mim.yaml"))?,
-        })
+        load_targets(path.as_ref(), None, &mut Err)
+    }
+}
+
+fn load_targets(
+    path: &Path,
+    cache: Option<&Cache>,
+    on_error: &mut OnError,
+) -> Result<Targets, Error> {
+    Ok(Targets {
+        android: read_target(&path.join("android.yaml"), cache, on_error)?,
+        i_os: read_target(&path.join("ios.yaml"), cache, on_error)?,
+        mac_os: read_target(&path.join("macos.yaml"), cache, on_error)?,
+        windows: read_windows_target(path, cache, on_error)?,
+        chrome: read_target(&path.join("chrome.yaml"), cache, on_error)?,
+        x11: read_target(&path.join("x11.yaml"), cache, on_error)?,
+        mim: read_target(&path.join("mim.yaml"), cache, on_error)?,
+    })
+}
+
+/// The Windows target, from `win.yaml` like the generators read it or from
+/// `windows.yaml` like bundles are saved, with its `uuid` checked
+fn read_windows_target(
+    path: &Path,
+    cache: Option<&Cache>,
+    on_error: &mut OnError,
+) -> Result<Option<TargetWindows>, Error> {
+    let path = windows_target_path(path);
+    let target: TargetWindows = match read_target(&path, cache, on_error)? {
+        Some(target) => target,
+        None => return Ok(None),
+    };
+    if target.uuid.is_empty() {
+        on_error(Error::MissingUuid { path })?;
+        return Ok(None);
+    }
+    if !crate::is_guid(&target.uuid) {
+        on_error(Error::InvalidUuid {
+            path,
+            uuid: target.uuid,
+        })?;
+        return Ok(None);
+    }
+    Ok(Some(target))
+}
+
+/// The file of the Windows target in the `targets` directory of a bundle
+pub(crate) fn windows_target_path(targets_path: &Path) -> PathBuf {
+    let legacy = targets_path.join("windows.yaml");
+    if legacy.is_file() && !targets_path.join("win.yaml").is_file() {
+        return legacy;
     }
+    targets_path.join("win.yaml")
 }
 
-fn read_yml<T: DeserializeOwned>(path: &Path) -> Result<T, Error> {
-    use std::{fs::File, io::BufReader};
+fn read_target<T: DeserializeOwned + Serialize>(
+    path: &Path,
+    cache: Option<&Cache>,
+    on_error: &mut OnError,
+) -> Result<Option<T>, Error> {
+    match read_yml_if_exists(path, cache) {
+        Ok(target) => Ok(target),
+        Err(e) => on_error(e).map(|_| None),
+    }
+}
 
-    let file = File::open(path).map_err(|source| Error::ReadFile {
+pub(super) fn read_yml<T: DeserializeOwned + Serialize>(
+    path: &Path,
+    cache: Option<&Cache>,
+) -> Result<T, Error> {
+    let contents = std::fs::read(path).map_err(|source| Error::ReadFile {
         path: path.into(),
         source,
     })?;
-    let reader = BufReader::new(file);
-    let parsed = serde_yaml::from_reader(reader).map_err(|source| Error::ParseFile {
-        path: path.into(),
-        source,
+
+    if let Some(cached) = cache.and_then(|cache| cache.get(&contents)) {
+        trace!("Using cached {:?}", path);
+        return Ok(cached);
+    }
+
+    // Aliases are expanded by serde_yaml, but errors in them are reported
+    // where their anchor is
+    let parsed = serde_yaml::from_slice(&contents).map_err(|source| {
+        let alias = source.location().and_then(|location| {
+            aliases::note_for_line(&String::from_utf8_lossy(&contents), location.line())
+        });
+        Error::ParseFile {
+            path: path.into(),
+            source,
+            alias,
+        }
     })?;
+    if let Some(cache) = cache {
+        cache.put(&contents, &parsed);
+    }
     Ok(parsed)
 }
 
-fn read_yml_if_exists<T: DeserializeOwned>(path: impl AsRef<Path>) -> Result<Option<T>, Error> {
+fn read_yml_if_exists<T: DeserializeOwned + Serialize>(
+    path: impl AsRef<Path>,
+    cache: Option<&Cache>,
+) -> Result<Option<T>, Error> {
     let path: &Path = path.as_ref();
     if !path.is_file() {
         return Ok(None);
     }
-    read_yml(path)
+    read_yml(path, cache)
 }
 
 #[derive(Debug, Error)]
@@ -119,9 +394,58 @@ pub enum Error {
     },
     #[error("Could not parse file with malfolmed name:: `{}`", path.display())]
     MalformedFilename { path: PathBuf },
-    #[error("Could not parse `{}`: {}", path.display(), source)]
+    #[error("Could not parse `{}`: {}{}", path.display(), source, aliases::describe(alias))]
     ParseFile {
         path: PathBuf,
         source: serde_yaml::Error,
+        alias: Option<AliasNote>,
+    },
+    #[error(
+        "Layout `{}` is defined more than once, in {}",
+        name,
+        paths.iter().map(|p| format!("`{}`", p.display())).collect::<Vec<_>>().join(" and ")
+    )]
+    DuplicateLayout { name: String, paths: Vec<PathBuf> },
+    #[error("Invalid layout name in `{}`: {}", path.display(), source)]
+    InvalidLayoutName {
+        path: PathBuf,
+        source: crate::NameError,
+    },
+    #[error("Invalid mode in `{}`: {}{}", path.display(), source, aliases::describe(alias))]
+    InvalidModeName {
+        path: PathBuf,
+        source: crate::ModeNameError,
+        alias: Option<AliasNote>,
+    },
+    #[error(
+        "`deadKeyTerminators` in `{}` has `{}`, which is not in `deadKeys`",
+        path.display(),
+        dead_key
+    )]
+    UnknownDeadKeyTerminator { path: PathBuf, dead_key: String },
+    #[error("Invalid {} theme in `{}`: {}", target, path.display(), message)]
+    InvalidTheme {
+        path: PathBuf,
+        target: crate::Target,
+        message: String,
+    },
+    #[error("Invalid `render` in `{}`: {}", path.display(), message)]
+    InvalidRender { path: PathBuf, message: String },
+    #[error("{} in `{}`", source, path.display())]
+    InvalidTransformPattern {
+        path: PathBuf,
+        source: crate::TransformError,
+    },
+    #[error(
+        "`{}` has no `uuid`, run `kbdgen target win init` to generate one",
+        path.display()
+    )]
+    MissingUuid { path: PathBuf },
+    #[error("`uuid` in `{}` is not a GUID: `{}`", path.display(), uuid)]
+    InvalidUuid { path: PathBuf, uuid: String },
+    #[error("Could not verify the signature of `{}`: {}", path.display(), source)]
+    Signature {
+        path: PathBuf,
+        source: super::SignatureError,
     },
 }
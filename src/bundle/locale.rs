@@ -0,0 +1,161 @@
+//! Picking a display name for a UI locale
+//!
+//! Names are looked up along a fallback chain built as in BCP 47 lookup
+//! (RFC 4647): `smj-Latn-SE` falls back to `smj-Latn`, then `smj`. After the
+//! locale come the locales the project's `localeFallbacks` lists for it, then
+//! the project's `defaultLocale`, then `en`. When none of them has a name,
+//! the first name is used. Tags compare case-insensitively, and `_` is read
+//! as `-`.
+
+use crate::models::Project;
+use std::collections::BTreeMap;
+
+/// The locale every chain ends with
+pub const DEFAULT_LOCALE: &str = "en";
+
+fn normalize(tag: &str) -> String {
+    tag.trim().replace('_', "-").to_ascii_lowercase()
+}
+
+/// The tag and its truncations, like `smj-se` and `smj`, dropping a
+/// singleton like `x` along with the subtag after it
+fn truncations(tag: &str) -> Vec<String> {
+    let mut subtags: Vec<&str> = tag.split('-').filter(|s| !s.is_empty()).collect();
+    let mut tags = vec![];
+    while !subtags.is_empty() {
+        tags.push(subtags.join("-"));
+        subtags.pop();
+        if matches!(subtags.last(), Some(singleton) if singleton.len() == 1) {
+            subtags.pop();
+        }
+    }
+    tags
+}
+
+/// The fallbacks of a project
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LocaleFallbacks<'a> {
+    default_locale: Option<&'a str>,
+    fallbacks: Option<&'a BTreeMap<String, Vec<String>>>,
+}
+
+impl<'a> LocaleFallbacks<'a> {
+    pub fn of(project: &'a Project) -> LocaleFallbacks<'a> {
+        LocaleFallbacks {
+            default_locale: project.default_locale.as_deref(),
+            fallbacks: project.locale_fallbacks.as_ref(),
+        }
+    }
+
+    fn fallbacks_of(&self, tag: &str) -> Vec<&'a str> {
+        self.fallbacks
+            .iter()
+            .flat_map(|fallbacks| fallbacks.iter())
+            .filter(|(key, _)| normalize(key) == tag)
+            .flat_map(|(_, locales)| locales.iter().map(String::as_str))
+            .collect()
+    }
+
+    /// The locales to look a name up in for `locale`, in order and
+    /// normalized
+    pub fn chain(&self, locale: &str) -> Vec<String> {
+        let mut chain: Vec<String> = vec![];
+        let push = |tag: String, chain: &mut Vec<String>| {
+            if !chain.contains(&tag) {
+                chain.push(tag);
+            }
+        };
+        for tag in truncations(&normalize(locale)) {
+            let fallbacks = self.fallbacks_of(&tag);
+            push(tag, &mut chain);
+            for fallback in fallbacks {
+                for tag in truncations(&normalize(fallback)) {
+                    push(tag, &mut chain);
+                }
+            }
+        }
+        let defaults = self.default_locale.into_iter().chain(Some(DEFAULT_LOCALE));
+        for default in defaults {
+            for tag in truncations(&normalize(default)) {
+                push(tag, &mut chain);
+            }
+        }
+        chain
+    }
+
+    /// The value for `locale` of a map by locale, like `displayNames`
+    pub fn resolve<'b>(
+        &self,
+        values: &'b BTreeMap<String, String>,
+        locale: &str,
+    ) -> Option<&'b str> {
+        let values: Vec<(String, &str)> = values
+            .iter()
+            .map(|(tag, value)| (normalize(tag), value.as_str()))
+            .collect();
+        self.chain(locale)
+            .iter()
+            .find_map(|tag| values.iter().find(|(t, _)| t == tag).map(|(_, v)| *v))
+            .or_else(|| values.first().map(|(_, value)| *value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[(&str, &str)]) -> BTreeMap<String, String> {
+        names
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn locales_fall_back_to_their_language_then_the_defaults() {
+        let fallbacks = LocaleFallbacks::default();
+        assert_eq!(fallbacks.chain("smj_SE"), vec!["smj-se", "smj", "en"]);
+        assert_eq!(
+            fallbacks.chain("de-CH-x-phonebk"),
+            vec!["de-ch-x-phonebk", "de-ch", "de", "en"]
+        );
+
+        let display_names = names(&[("en", "Lule Sami"), ("SMJ", "julevsámegiella")]);
+        assert_eq!(
+            fallbacks.resolve(&display_names, "smj-SE"),
+            Some("julevsámegiella")
+        );
+        assert_eq!(fallbacks.resolve(&display_names, "nb"), Some("Lule Sami"));
+        assert_eq!(
+            fallbacks.resolve(&names(&[("fi", "luulajansaame")]), "nb"),
+            Some("luulajansaame")
+        );
+        assert_eq!(fallbacks.resolve(&BTreeMap::new(), "nb"), None);
+    }
+
+    #[test]
+    fn projects_add_fallbacks_and_a_default() {
+        let project = Project {
+            default_locale: Some("nb".into()),
+            locale_fallbacks: Some(
+                vec![("nn".to_string(), vec!["nb-NO".to_string()])]
+                    .into_iter()
+                    .collect(),
+            ),
+            ..Project::default()
+        };
+        let fallbacks = LocaleFallbacks::of(&project);
+        assert_eq!(
+            fallbacks.chain("nn-NO"),
+            vec!["nn-no", "nn", "nb-no", "nb", "en"]
+        );
+
+        let display_names = names(&[("en", "Sami"), ("nb", "Samisk"), ("se", "Sámegiella")]);
+        assert_eq!(fallbacks.resolve(&display_names, "nn"), Some("Samisk"));
+        assert_eq!(fallbacks.resolve(&display_names, "fi"), Some("Samisk"));
+        assert_eq!(
+            fallbacks.resolve(&display_names, "se-FI"),
+            Some("Sámegiella")
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! `{{name}}` macros in mode strings
+//!
+//! A key can be written as `{{currency}}`, `{{decimal}}` and so on, so that a
+//! base layout can be shared across locales with different conventions. The
+//! macros are replaced with their values before a layout is generated.
+//! Names of placeholders, like `zwj`, are not macros, see `placeholders`.
+
+use super::placeholders::placeholder;
+use crate::{models::Layout, KeyValue};
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use std::collections::{BTreeMap, BTreeSet};
+
+lazy_static! {
+    static ref MACRO: Regex = Regex::new(r"\{\{([A-Za-z][A-Za-z0-9_]*)\}\}").expect("valid regex");
+}
+
+impl Layout {
+    /// The names of all macros used in the modes of this layout
+    pub fn used_macros(&self) -> BTreeSet<String> {
+        let mut names = BTreeSet::new();
+        self.for_each_key(|key| {
+            if let KeyValue::Symbol(value) = key {
+                names.extend(
+                    MACRO
+                        .captures_iter(value)
+                        .map(|c| c[1].to_string())
+                        .filter(|name| placeholder(name).is_none()),
+                );
+            }
+        });
+        names
+    }
+
+    /// Replaces the macros in all modes of this layout
+    ///
+    /// Values in the layout's own `macros` take precedence over `values`.
+    pub fn expand_macros(&mut self, values: &BTreeMap<String, String>) -> Result<(), Error> {
+        let mut values = values.clone();
+        if let Some(own) = self.macros.as_ref() {
+            values.extend(own.clone());
+        }
+
+        let mut unknown = BTreeSet::new();
+        self.for_each_key_mut(|key| {
+            if let KeyValue::Symbol(value) = key {
+                let expanded = MACRO.replace_all(value, |c: &Captures| match values.get(&c[1]) {
+                    _ if placeholder(&c[1]).is_some() => c[0].to_string(),
+                    Some(v) => v.clone(),
+                    None => {
+                        unknown.insert(c[1].to_string());
+                        c[0].to_string()
+                    }
+                });
+                *value = expanded.into_owned();
+            }
+        });
+
+        if unknown.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::UnknownMacros {
+                names: unknown.into_iter().collect(),
+            })
+        }
+    }
+
+    pub(super) fn for_each_key(&self, mut f: impl FnMut(&KeyValue)) {
+        let modes = &self.modes;
+        for desktop in vec![
+            &modes.win,
+            &modes.mac,
+            &modes.chrome,
+            &modes.x11,
+            &modes.desktop,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            for key_map in desktop.0.values() {
+                key_map.0.values().for_each(&mut f);
+            }
+        }
+
+        for mobile in vec![
+            &modes.ios,
+            &modes.android,
+            &modes.ipad_9in,
+            &modes.ipad_12in,
+            &modes.mobile,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            for key_map in mobile.0.values() {
+                key_map.0.iter().flatten().for_each(&mut f);
+            }
+        }
+    }
+
+    pub(super) fn for_each_key_mut(&mut self, mut f: impl FnMut(&mut KeyValue)) {
+        let modes = &mut self.modes;
+        for desktop in vec![
+            &mut modes.win,
+            &mut modes.mac,
+            &mut modes.chrome,
+            &mut modes.x11,
+            &mut modes.desktop,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            for key_map in desktop.0.values_mut() {
+                key_map.0.values_mut().for_each(&mut f);
+            }
+        }
+
+        for mobile in vec![
+            &mut modes.ios,
+            &mut modes.android,
+            &mut modes.ipad_9in,
+            &mut modes.ipad_12in,
+            &mut modes.mobile,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            for key_map in mobile.0.values_mut() {
+                key_map.0.iter_mut().flatten().for_each(&mut f);
+            }
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("No value for macros {}", names.join(", "))]
+    UnknownMacros { names: Vec<String> },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout() -> Layout {
+        serde_yaml::from_str(
+            r#"
+displayNames:
+  en: Test
+modes:
+  desktop:
+    default: |
+      1 2 3 4 5 6 7 8 9 0 {{currency}} {{decimal}}
+  mobile:
+    default: |
+      a b {{currency}}
+macros:
+  decimal: ","
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn expands_macros_with_layout_values_first() {
+        let mut layout = layout();
+        assert_eq!(
+            layout.used_macros().into_iter().collect::<Vec<_>>(),
+            vec!["currency", "decimal"]
+        );
+
+        let mut values = BTreeMap::new();
+        values.insert("currency".to_string(), "kr".to_string());
+        values.insert("decimal".to_string(), ".".to_string());
+        layout.expand_macros(&values).unwrap();
+
+        assert!(layout.used_macros().is_empty());
+        let desktop = &layout.modes.desktop.as_ref().unwrap()["default"];
+        assert_eq!(
+            desktop.get_string(crate::models::IsoKey::E10),
+            Some("kr".into())
+        );
+        assert_eq!(
+            desktop.get_string(crate::models::IsoKey::E11),
+            Some(",".into())
+        );
+        let mobile = &layout.modes.mobile.as_ref().unwrap()[&crate::MobileMode::Default];
+        assert_eq!(mobile.0[0][2], KeyValue::Symbol("kr".into()));
+    }
+
+    #[test]
+    fn reports_unknown_macros() {
+        let mut layout = layout();
+        match layout.expand_macros(&BTreeMap::new()) {
+            Err(Error::UnknownMacros { names }) => assert_eq!(names, vec!["currency"]),
+            x => panic!("unexpected {:?}", x),
+        }
+    }
+}
@@ -1,9 +1,13 @@
-use crate::{DesktopKeyMap, MobileKeyMap};
+use crate::{
+    bundle::modifiers::find_mode, DesktopKeyMap, Feature, KeyValue, LocaleFallbacks, MobileKeyMap,
+    MobileMode, MobileTheme, ModeNameError, ModifierKey, ModifierRole, ModifierSet, ResolvedModes,
+    Target, DEFAULT_LOCALE,
+};
 use derive_collect_docs::CollectDocs;
 use serde::{Deserialize, Serialize};
 use serde_yaml as yaml;
 use shrinkwraprs::Shrinkwrap;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use strum_macros::{Display, EnumIter, EnumString};
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, CollectDocs)]
@@ -19,6 +23,76 @@ pub struct ProjectDesc {
     pub name: String,
     /// The description of the project.
     pub description: String,
+    /// The strings shown by the mobile apps, in this locale.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strings: Option<AppStrings>,
+}
+
+/// User-visible strings of the mobile apps, beyond the project name and
+/// description
+///
+/// Each string is optional; a locale that leaves one out falls back to the
+/// `en` value, and to the app's built-in English text after that.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Default, CollectDocs)]
+#[example(
+    yaml,
+    r#"
+    settingsTitle: Innstillinger
+    enableKeyboardTitle: Slå på tastaturet
+    enableKeyboardInstructions: Gå til Innstillinger og legg til tastaturet.
+    openSettings: Åpne innstillinger
+    "#
+)]
+pub struct AppStrings {
+    /// Title of the app's settings screen
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "settingsTitle")]
+    pub settings_title: Option<String>,
+
+    /// Heading of the prompt asking the user to enable the keyboard
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "enableKeyboardTitle")]
+    pub enable_keyboard_title: Option<String>,
+
+    /// The steps for enabling the keyboard in the system settings
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "enableKeyboardInstructions")]
+    pub enable_keyboard_instructions: Option<String>,
+
+    /// Label of the button that opens the system settings
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "openSettings")]
+    pub open_settings: Option<String>,
+
+    /// Why the keyboard asks for full access (iOS only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "fullAccessExplanation")]
+    pub full_access_explanation: Option<String>,
+}
+
+impl AppStrings {
+    /// The strings by the resource name used in the generated apps
+    pub fn entries(&self) -> Vec<(&'static str, &str)> {
+        vec![
+            ("settings_title", self.settings_title.as_deref()),
+            (
+                "enable_keyboard_title",
+                self.enable_keyboard_title.as_deref(),
+            ),
+            (
+                "enable_keyboard_instructions",
+                self.enable_keyboard_instructions.as_deref(),
+            ),
+            ("open_settings", self.open_settings.as_deref()),
+            (
+                "full_access_explanation",
+                self.full_access_explanation.as_deref(),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value?)))
+        .collect()
+    }
 }
 
 /// Meta data for the project, stored in the `project.yaml` file.
@@ -67,6 +141,84 @@ pub struct Project {
     pub copyright: String,
     /// The associated organisation. Put author here too if no organisation.
     pub organisation: String,
+    /// The license of the layouts, as an SPDX identifier or a short text.
+    /// Layouts can have a license of their own.
+    #[example(yaml, "license: CC-BY-4.0")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    /// Credits for work the layouts are based on, shown along with the
+    /// license in About screens, installers and generated files.
+    #[example(
+        yaml,
+        r#"
+        attributions:
+          - Based on the Sámi keyboards by Example Corp
+    "#
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attributions: Option<Vec<String>>,
+    /// The locale to take display names from when a UI locale, its language
+    /// and its `localeFallbacks` have none, before falling back to `en`.
+    #[example(yaml, "defaultLocale: nb")]
+    #[serde(rename = "defaultLocale", skip_serializing_if = "Option::is_none")]
+    pub default_locale: Option<String>,
+    /// More locales to try for a locale, after it and before its truncations,
+    /// like `nb` for `nn`. Locales are BCP 47 tags, and `smj-SE` falls back
+    /// to `smj` by itself.
+    #[example(
+        yaml,
+        r#"
+        localeFallbacks:
+          nn: [nb]
+          sma: [sma-NO, sme]
+    "#
+    )]
+    #[serde(rename = "localeFallbacks", skip_serializing_if = "Option::is_none")]
+    pub locale_fallbacks: Option<BTreeMap<String, Vec<String>>>,
+    /// The website of the project, linked from About screens, the Chrome
+    /// Web Store and the Windows installer.
+    #[example(yaml, "homepage: https://example.com/keyboards")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub homepage: Option<String>,
+    /// Where users get help, linked from About screens and the Windows
+    /// installer. App store releases need it.
+    #[example(yaml, "supportUrl: https://example.com/keyboards/support")]
+    #[serde(rename = "supportUrl", skip_serializing_if = "Option::is_none")]
+    pub support_url: Option<String>,
+    /// The privacy policy, linked from the About screens of the mobile apps.
+    /// App store releases need it.
+    #[example(yaml, "privacyPolicyUrl: https://example.com/privacy")]
+    #[serde(rename = "privacyPolicyUrl", skip_serializing_if = "Option::is_none")]
+    pub privacy_policy_url: Option<String>,
+    /// How the keyboards of the `svg` target look, for branded reference
+    /// cards.
+    #[example(
+        yaml,
+        r##"
+        render:
+          fontFamily: "'Noto Sans', sans-serif"
+          colors:
+            deadKey: "#cc0000"
+          keyLabels: outputs
+    "##
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub render: Option<crate::Render>,
+}
+
+impl Project {
+    /// The URLs that app stores need and the project does not set, by their
+    /// name in `project.yaml`
+    pub fn missing_store_urls(&self) -> Vec<&'static str> {
+        let urls = vec![
+            ("supportUrl", &self.support_url),
+            ("privacyPolicyUrl", &self.privacy_policy_url),
+        ];
+        urls.into_iter()
+            .filter(|(_, url)| !matches!(url, Some(url) if !url.trim().is_empty()))
+            .map(|(name, _)| name)
+            .collect()
+    }
 }
 
 /// Strings to be shown on some OSes
@@ -93,6 +245,97 @@ pub struct LayoutStrings {
 pub struct DeriveOptions {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub transforms: Option<bool>,
+
+    /// Derive `mobile` modes from the desktop `default` and `shift` modes,
+    /// unless the layout has `mobile` modes of its own.
+    ///
+    /// The letter keys of the three lower rows become the three rows of the
+    /// mobile layout; digits and punctuation are dropped. Instead of `true`,
+    /// this can be a map of options.
+    #[example(
+        yaml,
+        r#"
+        derive:
+          mobile:
+            from: mac
+            keep: "'"
+            drop: q w
+    "#
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mobile: Option<DeriveMobile>,
+
+    /// Derive `desktop` modes from the mobile `default` and `shift` modes,
+    /// unless the layout has desktop modes of its own.
+    ///
+    /// The rows of the mobile layout are placed on the letter keys of a base
+    /// layout, `us` unless set to `nordic`, and the other keys are taken from
+    /// the base layout. Instead of `true`, this can be a map of options.
+    #[example(
+        yaml,
+        r#"
+        derive:
+          desktop:
+            base: nordic
+    "#
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub desktop: Option<DeriveDesktop>,
+}
+
+/// Whether and how to derive desktop modes, see `derive`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum DeriveDesktop {
+    Enabled(bool),
+    Options(DeriveDesktopOptions),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Default, CollectDocs)]
+pub struct DeriveDesktopOptions {
+    /// The layout providing the keys that are not letters
+    #[serde(default)]
+    pub base: BaseLayout,
+
+    /// The target whose mobile modes to use, by default `mobile` or the
+    /// first mobile target the layout has modes for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<Target>,
+}
+
+/// Layouts that derived desktop modes are based on
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BaseLayout {
+    /// US English, on an ISO keyboard
+    #[default]
+    Us,
+    /// Swedish and Finnish
+    Nordic,
+}
+
+/// Whether and how to derive mobile modes, see `derive`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum DeriveMobile {
+    Enabled(bool),
+    Options(DeriveMobileOptions),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Default, CollectDocs)]
+pub struct DeriveMobileOptions {
+    /// The target whose desktop modes to use, by default `desktop` or the
+    /// first desktop target the layout has modes for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<Target>,
+
+    /// Space separated keys to keep although they are not letters
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep: Option<String>,
+
+    /// Space separated letters to leave out
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub drop: Option<String>,
 }
 
 /// ISO key codes
@@ -178,8 +421,23 @@ impl IsoKey {
     pub fn to_character(self) -> char {
         std::char::from_u32(u32::from(self.to_character_code())).expect("keycode is ascii")
     }
+
+    /// Returns the row (`0` for the `E` row through `3` for the `B` row) and
+    /// the number of the key within that row
+    pub fn grid_position(self) -> (usize, usize) {
+        let name = self.to_string();
+        let row = ISO_ROWS
+            .iter()
+            .position(|&letter| name.starts_with(letter))
+            .expect("ISO keys of the alphanumeric section");
+        let column = name[1..].parse().expect("ISO keys end in a number");
+        (row, column)
+    }
 }
 
+/// Row letters of the alphanumeric section, top to bottom
+pub(crate) const ISO_ROWS: &[char] = &['E', 'D', 'C', 'B'];
+
 /// Target specific modes
 ///
 /// This is a nested map with known keys (the fields below) for each supported
@@ -229,6 +487,13 @@ pub struct Modes {
     pub ios: Option<MobileModes>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub android: Option<MobileModes>,
+    /// iPads with screens of 9.7 to 11 inches, used by `ios` as they are,
+    /// without falling back to `ios` or `mobile`
+    #[serde(rename = "ipad-9in", skip_serializing_if = "Option::is_none")]
+    pub ipad_9in: Option<MobileModes>,
+    /// iPads with 12.9 inch screens, like `ipad-9in`
+    #[serde(rename = "ipad-12in", skip_serializing_if = "Option::is_none")]
+    pub ipad_12in: Option<MobileModes>,
     /// ChromeOS (used on Chrome Books)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub chrome: Option<DesktopModes>,
@@ -244,42 +509,74 @@ pub struct Modes {
 }
 
 impl Modes {
-    pub fn available_modes(&self) -> Vec<String> {
-        let mut res = Vec::new();
-        if self.win.is_some() {
-            res.push("win".into());
-        }
-        if self.mac.is_some() {
-            res.push("mac".into());
-        }
-        if self.ios.is_some() {
-            res.push("ios".into());
-        }
-        if self.android.is_some() {
-            res.push("android".into());
-        }
-        if self.chrome.is_some() {
-            res.push("chrome".into());
-        }
-        if self.x11.is_some() {
-            res.push("x11".into());
-        }
-        if self.desktop.is_some() {
-            res.push("desktop".into());
+    /// The targets that this layout defines modes for
+    pub fn available_modes(&self) -> Vec<Target> {
+        use strum::IntoEnumIterator;
+
+        Target::iter().filter(|&target| self.has(target)).collect()
+    }
+
+    /// The desktop modes defined for exactly this target, without fallback
+    pub fn desktop_for(&self, target: Target) -> Option<&DesktopModes> {
+        match target {
+            Target::Win => self.win.as_ref(),
+            Target::Mac => self.mac.as_ref(),
+            Target::Chrome => self.chrome.as_ref(),
+            Target::X11 => self.x11.as_ref(),
+            Target::Desktop => self.desktop.as_ref(),
+            Target::Ios | Target::Android | Target::Mobile => None,
         }
-        if self.mobile.is_some() {
-            res.push("mobile".into());
+    }
+
+    /// The mobile modes defined for exactly this target, without fallback
+    pub fn mobile_for(&self, target: Target) -> Option<&MobileModes> {
+        match target {
+            Target::Ios => self.ios.as_ref(),
+            Target::Android => self.android.as_ref(),
+            Target::Mobile => self.mobile.as_ref(),
+            _ => None,
         }
-        res
+    }
+
+    /// The iPad modes, by the name they have under `modes`
+    pub fn ipad(&self) -> impl Iterator<Item = (&'static str, &MobileModes)> {
+        let ipad_9in = self.ipad_9in.as_ref().map(|modes| ("ipad-9in", modes));
+        let ipad_12in = self.ipad_12in.as_ref().map(|modes| ("ipad-12in", modes));
+        ipad_9in.into_iter().chain(ipad_12in)
+    }
+
+    /// Whether any modes are defined for exactly this target
+    pub fn has(&self, target: Target) -> bool {
+        self.desktop_for(target).is_some() || self.mobile_for(target).is_some()
     }
 }
 
-/// Maps modifier combination to map of keys
+impl DesktopModes {
+    /// The key map for a mode, regardless of how the modifiers in its name
+    /// are ordered
+    ///
+    /// A mode with optional modifiers, like `alt+caps?`, is used for the
+    /// modes it covers that are not defined themselves.
+    pub fn get_mode(&self, mode: &ModifierSet) -> Option<&DesktopKeyMap> {
+        find_mode(&self.0, mode).map(|(_, key_map)| key_map)
+    }
+}
+
+impl MobileModes {
+    /// The key map for the mode typed while `mode` is held
+    pub fn get_mode(&self, mode: &ModifierSet) -> Option<&MobileKeyMap> {
+        self.0.get(&MobileMode::from_modifiers(mode)?)
+    }
+}
+
+/// Maps mobile modes to their rows of keys
 ///
-/// Both mobile-default and mobile-shift modes are required.
+/// Both mobile-default and mobile-shift modes are required. The modes are
+/// `default`, `shift`, `alt` and `alt+shift` for iPads, and `symbols-1` and
+/// `symbols-2` for the pages of symbols on iOS; other names are an error.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Default)]
 #[derive(Shrinkwrap, CollectDocs)]
-pub struct MobileModes(pub BTreeMap<String, MobileKeyMap>);
+pub struct MobileModes(pub BTreeMap<MobileMode, MobileKeyMap>);
 
 /// Maps modifier combination to map of keys
 ///
@@ -338,6 +635,23 @@ pub struct Layout {
     #[serde(rename = "displayNames")]
     pub display_names: BTreeMap<String, String>,
 
+    /// The license of this layout, instead of the project's `license`.
+    #[example(yaml, "license: MIT")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+
+    /// Credits for work this layout is based on, in addition to the
+    /// project's `attributions`.
+    #[example(
+        yaml,
+        r#"
+        attributions:
+          - Dead keys from the Lule Sámi typewriter layout
+    "#
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attributions: Option<Vec<String>>,
+
     /// The different modes.
     ///
     /// NOTE: Do not forget the `\|` symbol after the `<mode>` key or you will
@@ -383,7 +697,7 @@ pub struct Layout {
     "#
     )]
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub space: Option<BTreeMap<String, BTreeMap<String, String>>>,
+    pub space: Option<BTreeMap<Target, BTreeMap<String, String>>>,
 
     /// Defines the dead keys for a target for a given `<mode>`.
     ///
@@ -406,7 +720,44 @@ pub struct Layout {
     )]
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "deadKeys")]
-    pub dead_keys: Option<BTreeMap<String, BTreeMap<String, Vec<String>>>>,
+    pub dead_keys: Option<BTreeMap<Target, BTreeMap<String, Vec<String>>>>,
+
+    /// Options for individual dead keys, keyed by the dead key.
+    ///
+    /// `preview` is the glyph shown for the dead key, like `◌́` for a
+    /// combining acute. Without it, the dead key itself is shown. It is what
+    /// the `svg` target draws on the key, what the Android and iOS keyboards
+    /// show in the key's popup, and on macOS the terminator of the dead key's
+    /// state, which macOS shows while the dead key is pending, unless
+    /// `deadKeyTerminators` sets one.
+    #[example(
+        yaml,
+        r#"
+        deadKeyOptions:
+          "´":
+            preview: "◌́"
+    "#
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "deadKeyOptions")]
+    pub dead_key_options: Option<BTreeMap<String, DeadKeyOptions>>,
+
+    /// What each dead key types on its own, keyed by the dead key.
+    ///
+    /// Used when the dead key is followed by space or by a key it has no
+    /// transform for. Without it, the dead key's `" "` transform is used, or
+    /// else the dead key itself. Every key must be a dead key in `deadKeys`.
+    #[example(
+        yaml,
+        r#"
+        deadKeyTerminators:
+          "´": "´"
+          "˜": "~"
+    "#
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "deadKeyTerminators")]
+    pub dead_key_terminators: Option<BTreeMap<String, String>>,
 
     /// The items to be shown when a key is long-pressed. Values are space
     /// separated in one string.
@@ -422,6 +773,60 @@ pub struct Layout {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub longpress: Option<BTreeMap<String, String>>,
 
+    /// The items typed by flicking a key in a direction instead of tapping
+    /// it, keyed by the key's tap output, as CLDR's `<flicks>`.
+    ///
+    /// Only used by mobile targets.
+    #[example(
+        yaml,
+        r#"
+        flicks:
+          a:
+            up: á
+            down: à
+          o:
+            up: ó
+            right: ø
+    "#
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flicks: Option<BTreeMap<String, Flicks>>,
+
+    /// What screen readers like VoiceOver and TalkBack say for a key, keyed
+    /// by the key's output.
+    ///
+    /// Keys without a label are read by the Unicode names of their
+    /// characters. Only used by mobile targets.
+    #[example(
+        yaml,
+        r#"
+        accessibilityLabels:
+          ŋ: eng
+          "́": combining acute
+    "#
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "accessibilityLabels")]
+    pub accessibility_labels: Option<BTreeMap<String, String>>,
+
+    /// What to draw on a key instead of its output, keyed by the output.
+    ///
+    /// Keys typing combining marks are drawn on a dotted circle, and keys
+    /// typing invisible characters like ZWJ with their abbreviation, unless
+    /// they are given a label here. Used by mobile targets and rendered
+    /// previews.
+    #[example(
+        yaml,
+        r#"
+        displayOverrides:
+          "\u0301": ´
+          "\u200D": joiner
+    "#
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "displayOverrides")]
+    pub display_overrides: Option<BTreeMap<String, String>>,
+
     /// The chain of inputs necessary to provide an output after a deadkey is
     /// pressed. Keyed by each individual input.
     ///
@@ -445,6 +850,65 @@ pub struct Layout {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub transforms: Option<BTreeMap<String, BTreeMap<String, String>>>,
 
+    /// Whether a transform in progress, like a pending dead key, is shown
+    /// before it is finished, as in CLDR's `transformPartial`.
+    ///
+    /// Only Chrome OS and the mobile targets can do either; macOS always
+    /// shows it and Windows and X11 never do. Without it, Chrome OS hides it
+    /// and the mobile targets show it.
+    #[example(yaml, "transformPartial: show")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "transformPartial")]
+    pub transform_partial: Option<TransformPartial>,
+
+    /// Whether transforms of a combining mark and a base character can also
+    /// be typed the other way round.
+    ///
+    /// For each transform like `"́": {a: á}`, the swapped `a: {"́": á}` is
+    /// generated unless it is written out. Swapped transforms only apply
+    /// where their first key starts a transform, which on most targets means
+    /// it must be a dead key. `kbdgen lint` warns about swapped transforms
+    /// written out with a different output.
+    #[example(yaml, "symmetricTransforms: true")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "symmetricTransforms")]
+    pub symmetric_transforms: Option<bool>,
+
+    /// Rules for putting characters typed out of order into their stored
+    /// order, as in CLDR's `<reorders>`.
+    ///
+    /// Needed for scripts like Khmer and the Brahmic scripts, where a vowel
+    /// sign is typed before the consonant it is stored after. Only the mobile
+    /// targets support reordering; desktop targets warn and ignore them.
+    #[example(
+        yaml,
+        r#"
+        reorders:
+          - from: "\u{17C1}"
+            order: 3
+          - from: "\u{17D2}"
+            before: "\u{1780}"
+            order: 1
+    "#
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reorders: Option<Vec<Reorder>>,
+
+    /// Text before the cursor that a single backspace deletes or replaces,
+    /// as in CLDR's backspace transforms.
+    ///
+    /// Keyed by the text to match, with what it is replaced by. Only the
+    /// mobile targets support this; desktop targets warn and ignore it.
+    #[example(
+        yaml,
+        r#"
+        backspaces:
+          "\u{17D2}\u{1780}": ""
+    "#
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backspaces: Option<BTreeMap<String, String>>,
+
     /// Strings to be shown on some OSes
     ///
     /// Currently, they are used for specifying strings to be shown on the space
@@ -464,6 +928,123 @@ pub struct Layout {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub derive: Option<DeriveOptions>,
 
+    /// Flags that change how generators treat the layout
+    ///
+    /// `no-autocorrect` turns off autocorrection and suggestions on mobile
+    /// targets, `no-caps-lock` keeps caps lock from changing what the keys
+    /// type, and `spacebar-language-label` shows the layout's name on the
+    /// spacebar of mobile targets. Other names are an error.
+    #[example(
+        yaml,
+        r#"
+        features: [no-autocorrect, spacebar-language-label]
+    "#
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub features: Option<Vec<Feature>>,
+
+    /// Other roles for modifier keys, keyed by target and then by the key.
+    ///
+    /// Keys are `caps`, `left-alt`, `right-alt`, `left-ctrl`, `right-ctrl`
+    /// and `menu`; roles are `altgr` (or `level3`), `alt`, `ctrl`, `shift`,
+    /// `caps` and `none`. Keys a target does not remap fall back to the
+    /// remaps of `desktop`. The x11 symbols
+    /// include the matching XKB option, the Windows installer remaps the
+    /// keys' scancodes for the whole system and the macOS installer remaps
+    /// them for every user at login. `kbdgen lint` warns about remaps a
+    /// target cannot do.
+    #[example(
+        yaml,
+        r#"
+        modifierRemaps:
+          desktop:
+            caps: altgr
+    "#
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "modifierRemaps")]
+    pub modifier_remaps: Option<BTreeMap<Target, BTreeMap<ModifierKey, ModifierRole>>>,
+
+    /// Further layouts for the same locale, like a phonetic layout next to
+    /// the standard one, keyed by a name for the variant.
+    ///
+    /// Each variant is generated as a layout of its own, named after this
+    /// layout and the variant, as in `se-phonetic`. It shares everything with
+    /// this layout except what it sets itself. Its modes replace the modes of
+    /// the same target, modes for other targets are shared.
+    #[example(
+        yaml,
+        r#"
+        variants:
+          phonetic:
+            displayNames:
+              en: Northern Sami (phonetic)
+            modes:
+              desktop:
+                default: |
+                  § 1 2 3 4 5 6 7 8 9 0 + ´
+                    q w e r t y u i o p å ŋ
+                    a s d f g h j k l ö ä đ
+                  ž z č c v b n m , . -
+    "#
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variants: Option<BTreeMap<String, LayoutVariant>>,
+
+    /// The targets whose modes were made by `derive` when the layout was
+    /// loaded, rather than written in the file
+    #[serde(skip)]
+    pub derived_modes: BTreeSet<Target>,
+
+    /// Whether the layout is for a right-to-left script.
+    ///
+    /// Previews draw the rows from right to left, and the mobile keyboards
+    /// are marked as right-to-left.
+    ///
+    /// Keys still produce logical characters, and right-to-left text displays
+    /// `(` as `)`. Bracket pairs are therefore expected to be swapped compared
+    /// to a left-to-right layout (`)` left of `(`), as on the standard Arabic
+    /// and Hebrew keyboards; the tool warns about pairs that are not.
+    #[example(yaml, "rtl: true")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rtl: Option<bool>,
+
+    /// Values for `{{name}}` macros in the modes.
+    ///
+    /// A key can be written as a macro like `{{currency}}` to have it filled
+    /// in at generation time. `currency`, `decimal`, `group` and `percent` are
+    /// looked up in CLDR for the locale the layout is named after; values
+    /// given here take precedence, and can define further macros. Names of
+    /// placeholders for invisible characters, like `zwj`, are reserved.
+    #[example(
+        yaml,
+        r#"
+        macros:
+          currency: kr
+    "#
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub macros: Option<BTreeMap<String, String>>,
+
+    /// Where the modes of each target were imported from CLDR, as recorded
+    /// by `kbdgen cldr import`.
+    ///
+    /// `file` is the keyboard's path in the CLDR repository, and `commit` the
+    /// commit it was imported from, if it is known. `kbdgen cldr
+    /// diff-upstream` uses them to tell the changes made in CLDR since the
+    /// import from the changes made to the layout.
+    #[example(
+        yaml,
+        r#"
+        cldr:
+          win:
+            file: keyboards/windows/se-t-k0-windows.xml
+            commit: 0d5b1b6c8e2f
+    "#
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cldr: Option<BTreeMap<Target, CldrSource>>,
+
     /// A map of target-specific customisation properties.
     ///
     /// Key is the code for the target. Only necessary if you need to set a
@@ -480,13 +1061,396 @@ pub struct Layout {
     pub targets: Option<LayoutTarget>,
 }
 
+/// Characters that right-to-left text displays mirrored, as (opening,
+/// closing) pairs
+const MIRRORED_PAIRS: &[(&str, &str)] =
+    &[("(", ")"), ("[", "]"), ("{", "}"), ("<", ">"), ("«", "»")];
+
+/// A bracket pair of a right-to-left layout that is in left-to-right order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnmirroredPair {
+    pub target: Target,
+    pub mode: String,
+    pub open: IsoKey,
+    pub close: IsoKey,
+}
+
 impl Layout {
+    /// The display name in `en`, see `display_name`
     pub fn name(&self) -> Option<String> {
-        self.display_names
-            .get("en")
-            .or_else(|| self.display_names.values().next())
+        self.display_name(DEFAULT_LOCALE, &LocaleFallbacks::default())
+    }
+
+    /// The display name for a UI locale, falling back along the project's
+    /// locale fallbacks
+    pub fn display_name(&self, locale: &str, fallbacks: &LocaleFallbacks<'_>) -> Option<String> {
+        fallbacks
+            .resolve(&self.display_names, locale)
+            .map(str::to_string)
+    }
+
+    /// What the dead key types on its own, see `deadKeyTerminators`
+    pub fn dead_key_terminator(&self, dead_key: &str) -> String {
+        self.dead_key_terminators
+            .as_ref()
+            .and_then(|terminators| terminators.get(dead_key))
+            .or_else(|| self.transforms.as_ref()?.get(dead_key)?.get(" "))
             .cloned()
+            .unwrap_or_else(|| dead_key.to_string())
+    }
+
+    /// The dead keys of every target and mode
+    pub fn all_dead_keys(&self) -> BTreeSet<&str> {
+        self.dead_keys
+            .iter()
+            .flat_map(|dead_keys| dead_keys.values())
+            .flat_map(|modes| modes.values())
+            .flatten()
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Keys of `deadKeyTerminators` that are not a dead key for any target
+    pub fn unknown_dead_key_terminators(&self) -> Vec<&str> {
+        let dead_keys = self.all_dead_keys();
+
+        self.dead_key_terminators
+            .iter()
+            .flat_map(|terminators| terminators.keys())
+            .map(String::as_str)
+            .filter(|key| !dead_keys.contains(key))
+            .collect()
+    }
+
+    /// What screen readers say for a key typing `output`, see
+    /// `accessibilityLabels`
+    pub fn accessibility_label(&self, output: &str) -> Option<String> {
+        if let Some(label) = self
+            .accessibility_labels
+            .as_ref()
+            .and_then(|labels| labels.get(output))
+        {
+            return Some(label.clone());
+        }
+        if output.is_empty() {
+            return None;
+        }
+        output
+            .chars()
+            .map(|c| unic_ucd_name::Name::of(c).map(|name| name.to_string().to_lowercase()))
+            .collect::<Option<Vec<_>>>()
+            .map(|names| names.join(" "))
     }
+
+    /// The label drawn on the spacebar of the layout's `locale`, for a target
+    /// with the given `spacebar` options
+    pub fn spacebar_label(&self, locale: &str, spacebar: Option<&Spacebar>) -> Option<String> {
+        let label = match spacebar.and_then(|spacebar| spacebar.label) {
+            Some(label) => label,
+            None if self.has_feature(Feature::SpacebarLanguageLabel) => SpacebarLabel::DisplayName,
+            None => SpacebarLabel::Space,
+        };
+        match label {
+            SpacebarLabel::Space => self.strings.as_ref().map(|strings| strings.space.clone()),
+            SpacebarLabel::DisplayName => self.display_names.get(locale).cloned(),
+            SpacebarLabel::None => None,
+        }
+    }
+
+    /// The layout's `license`, or else the project's
+    pub fn license<'a>(&'a self, project: &'a Project) -> Option<&'a str> {
+        self.license.as_deref().or(project.license.as_deref())
+    }
+
+    /// The project's `attributions` followed by the layout's
+    pub fn attributions<'a>(&'a self, project: &'a Project) -> Vec<&'a str> {
+        let mut attributions: Vec<&str> = vec![];
+        for attribution in project
+            .attributions
+            .iter()
+            .chain(&self.attributions)
+            .flatten()
+        {
+            if !attributions.contains(&attribution.as_str()) {
+                attributions.push(attribution);
+            }
+        }
+        attributions
+    }
+
+    /// The license and attributions as lines for the headers of generated
+    /// files
+    pub fn license_notice(&self, project: &Project) -> Vec<String> {
+        self.license(project)
+            .map(|license| format!("License: {}", license))
+            .into_iter()
+            .chain(self.attributions(project).into_iter().map(String::from))
+            .collect()
+    }
+
+    pub fn is_rtl(&self) -> bool {
+        self.rtl.unwrap_or(false)
+    }
+
+    /// Keys with `flicks` that no mobile mode of the layout has
+    pub fn unknown_flick_keys(&self) -> Vec<&str> {
+        let modes = &self.modes;
+        let keys: BTreeSet<&str> = vec![&modes.ios, &modes.android, &modes.mobile]
+            .into_iter()
+            .flatten()
+            .chain(modes.ipad().map(|(_, modes)| modes))
+            .flat_map(|modes| modes.0.values())
+            .flat_map(|key_map| key_map.0.iter().flatten())
+            .filter_map(|key| match key {
+                KeyValue::Symbol(value) => Some(value.as_str()),
+                _ => None,
+            })
+            .collect();
+
+        self.flicks
+            .iter()
+            .flat_map(|flicks| flicks.keys())
+            .map(String::as_str)
+            .filter(|key| !keys.contains(key))
+            .collect()
+    }
+
+    pub fn has_reordering(&self) -> bool {
+        self.reorders.iter().flatten().next().is_some()
+            || self.backspaces.iter().flatten().next().is_some()
+    }
+
+    /// Desktop targets with modes in this layout that will not get its
+    /// `reorders` and `backspaces`, as they cannot express them
+    pub fn targets_without_reordering(&self) -> Vec<Target> {
+        if !self.has_reordering() {
+            return vec![];
+        }
+        use strum::IntoEnumIterator;
+        Target::iter()
+            .filter(|&target| !target.is_mobile() && target != Target::Desktop)
+            .filter(|&target| self.modes.resolve(target).is_some())
+            .collect()
+    }
+
+    /// Bracket pairs whose opening bracket is left of its closing bracket in
+    /// the same row of a desktop mode
+    ///
+    /// Only meaningful for right-to-left layouts, see `rtl`.
+    pub fn unmirrored_pairs(&self) -> Vec<UnmirroredPair> {
+        use strum::IntoEnumIterator;
+
+        let mut res = vec![];
+        for target in Target::iter() {
+            let modes = match self.modes.desktop_for(target) {
+                Some(modes) => modes,
+                None => continue,
+            };
+
+            for (mode, key_map) in modes.iter() {
+                let position = |value: &str| {
+                    key_map
+                        .0
+                        .iter()
+                        .find(|(_, key)| **key == KeyValue::Symbol(value.to_string()))
+                        .map(|(&iso_key, _)| iso_key)
+                };
+
+                for (open, close) in MIRRORED_PAIRS {
+                    if let (Some(open), Some(close)) = (position(open), position(close)) {
+                        let (open_row, open_column) = open.grid_position();
+                        let (close_row, close_column) = close.grid_position();
+                        if open_row == close_row && open_column < close_column {
+                            res.push(UnmirroredPair {
+                                target,
+                                mode: mode.clone(),
+                                open,
+                                close,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        res
+    }
+
+    /// Mode names under `modes` and `deadKeys` that their target does not
+    /// have
+    pub fn mode_name_errors(&self) -> Vec<ModeNameError> {
+        use strum::IntoEnumIterator;
+
+        let desktop = Target::iter().flat_map(|target| {
+            let names = self.modes.desktop_for(target).map(|modes| modes.0.keys());
+            names.into_iter().flatten().map(move |name| (target, name))
+        });
+        // Unknown mobile modes are an error when the layout is read. The
+        // iPad modes are generated for iOS
+        let ipad = self
+            .modes
+            .ipad()
+            .flat_map(|(_, modes)| modes.0.keys().map(|&mode| (Target::Ios, mode)));
+        let mobile = Target::iter()
+            .flat_map(|target| {
+                let modes = self.modes.mobile_for(target).map(|modes| modes.0.keys());
+                modes.into_iter().flatten().map(move |&mode| (target, mode))
+            })
+            .chain(ipad);
+        let dead_keys = self
+            .dead_keys
+            .iter()
+            .flatten()
+            .flat_map(|(&target, modes)| modes.keys().map(move |name| (target, name)));
+
+        let dead_key_error = |target: Target, name: &String| {
+            if !target.is_mobile() {
+                return crate::parse_mode_name(target, name).err();
+            }
+            match name.parse::<MobileMode>() {
+                Ok(mode) => crate::check_mobile_mode(target, mode).err(),
+                Err(e) => Some(ModeNameError::Unknown {
+                    mode: name.clone(),
+                    target,
+                    suggestion: e.suggestion,
+                }),
+            }
+        };
+
+        desktop
+            .filter_map(|(target, name)| crate::parse_mode_name(target, name).err())
+            .chain(mobile.filter_map(|(target, mode)| crate::check_mobile_mode(target, mode).err()))
+            .chain(dead_keys.filter_map(|(target, name)| dead_key_error(target, name)))
+            .collect()
+    }
+
+    /// The value of a key in the given mode for a target
+    ///
+    /// If the layout defines no modes for `target`, the modes of its fallback
+    /// target (`desktop` for desktop targets, `mobile` for mobile ones) are
+    /// used instead, see `Modes::resolve`.
+    ///
+    /// Mobile key maps are not tied to physical keys, so their rows are
+    /// mapped onto the `D`, `C` and `B` rows, starting at `D01`, `C01` and
+    /// `B01` respectively.
+    pub fn key(&self, target: Target, mode: &ModifierSet, key: IsoKey) -> Option<&KeyValue> {
+        match self.modes.resolve(target)? {
+            ResolvedModes::Mobile(_, modes) => {
+                let (row, column) = key.grid_position();
+                modes
+                    .get_mode(mode)?
+                    .0
+                    .get(row.checked_sub(1)?)?
+                    .get(column.checked_sub(1)?)
+            }
+            ResolvedModes::Desktop(_, modes) => modes.get_mode(mode)?.get(&key),
+        }
+    }
+}
+
+/// What flicking a key in each direction types, see `flicks`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Default, CollectDocs)]
+pub struct Flicks {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub up: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub down: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub left: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub right: Option<String>,
+}
+
+/// Where the modes of a target were imported from, see `cldr`
+#[derive(
+    Clone,
+    Debug,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    Default,
+    CollectDocs
+)]
+pub struct CldrSource {
+    /// The keyboard's path in the CLDR repository, like
+    /// `keyboards/osx/se-t-k0-osx.xml`
+    pub file: String,
+
+    /// The commit of the CLDR repository the keyboard was imported from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+}
+
+/// A reordering rule, see `reorders`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Default, CollectDocs)]
+pub struct Reorder {
+    /// The characters the rule applies to
+    pub from: String,
+
+    /// Text that must come directly before `from` for the rule to apply
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<String>,
+
+    /// Where `from` sorts within a cluster, lower orders come first
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order: Option<i32>,
+
+    /// The order of `from` relative to the tertiary base of a cluster
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tertiary: Option<i32>,
+
+    /// Whether `from` is the base that tertiary characters sort around
+    #[serde(rename = "tertiaryBase", skip_serializing_if = "Option::is_none")]
+    pub tertiary_base: Option<bool>,
+
+    /// Whether `from` starts a cluster even though it is not a base character
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prebase: Option<bool>,
+}
+
+/// A further layout in the same file, see `variants`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Default, CollectDocs)]
+pub struct LayoutVariant {
+    /// Display names, instead of the layout's
+    #[serde(rename = "displayNames", skip_serializing_if = "Option::is_none")]
+    pub display_names: Option<BTreeMap<String, String>>,
+
+    /// Modes replacing the layout's modes for the same targets
+    #[serde(default)]
+    pub modes: Modes,
+
+    /// Dead keys, instead of the layout's
+    #[serde(rename = "deadKeys", skip_serializing_if = "Option::is_none")]
+    pub dead_keys: Option<BTreeMap<Target, BTreeMap<String, Vec<String>>>>,
+
+    /// Long-press items, instead of the layout's
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub longpress: Option<BTreeMap<String, String>>,
+
+    /// Transforms, instead of the layout's
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transforms: Option<BTreeMap<String, BTreeMap<String, String>>>,
+}
+
+/// Whether a transform in progress is shown, see `transformPartial`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Display, EnumString)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum TransformPartial {
+    /// Nothing is shown until the transform is finished
+    Hide,
+    /// What has been typed so far is shown, marked as in progress
+    Show,
+}
+
+/// Options for a single dead key
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Default, CollectDocs)]
+pub struct DeadKeyOptions {
+    /// The glyph shown for the dead key, see `deadKeyOptions`
+    #[example(yaml, r#"preview: "◌́""#)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preview: Option<String>,
 }
 
 /// Targets for settings per layout
@@ -494,13 +1458,20 @@ impl Layout {
 #[derive(Serialize, Deserialize, Default, CollectDocs)]
 pub struct LayoutTarget {
     #[serde(skip_serializing_if = "Option::is_none")]
-    win: Option<LayoutTargetWindows>,
+    pub win: Option<LayoutTargetWindows>,
 
+    #[example(
+        yaml,
+        r#"
+        mac:
+          language: se
+          icon: resources/mac/icon.se-NO.png"#
+    )]
     #[serde(skip_serializing_if = "Option::is_none")]
-    mac: Option<YamlValue>,
+    pub mac: Option<LayoutTargetMacOS>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    ios: Option<LayoutTargetIOS>,
+    pub ios: Option<LayoutTargetIOS>,
 
     #[example(
         yaml,
@@ -509,7 +1480,7 @@ pub struct LayoutTarget {
           legacyName: kpv"#
     )]
     #[serde(skip_serializing_if = "Option::is_none")]
-    android: Option<LayoutTargetAndroid>,
+    pub android: Option<LayoutTargetAndroid>,
 
     #[example(
         yaml,
@@ -520,16 +1491,16 @@ pub struct LayoutTarget {
     "#
     )]
     #[serde(skip_serializing_if = "Option::is_none")]
-    chrome: Option<YamlValue>,
+    pub chrome: Option<YamlValue>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    x11: Option<YamlValue>,
+    pub x11: Option<YamlValue>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    desktop: Option<YamlValue>,
+    pub desktop: Option<YamlValue>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    mobile: Option<YamlValue>,
+    pub mobile: Option<YamlValue>,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, CollectDocs)]
@@ -546,6 +1517,29 @@ pub struct LayoutTargetWindows {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub id: Option<String>,
+
+    /// The name of the layout in the input indicator's list of layouts and
+    /// in Settings, instead of its display name. It is written as the
+    /// `Layout Text` and `Layout Display Name` of the layout's registry key.
+    #[example(yaml, "label: Julevsámegiella")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Default, CollectDocs)]
+pub struct LayoutTargetMacOS {
+    /// The language Input Sources lists the layout under, as a BCP 47 tag.
+    /// This becomes the `TISIntendedLanguage` of the layout. Defaults to the
+    /// name of the layout.
+    #[example(yaml, "language: se")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+
+    /// Path to the PNG icon shown in the menu bar for this layout, relative to
+    /// the bundle. Defaults to `resources/mac/icon.<layout>.png` if it exists,
+    /// and the icon of the macOS target otherwise.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, CollectDocs)]
@@ -554,6 +1548,10 @@ pub struct LayoutTargetIOS {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "legacyName")]
     pub legacy_name: Option<String>,
+
+    /// How the keys look, the same as for `android`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub theme: Option<MobileTheme>,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, CollectDocs)]
@@ -571,9 +1569,23 @@ pub struct LayoutTargetAndroid {
     #[serde(rename = "minimumSdk")]
     pub minimum_sdk: Option<u32>,
 
-    /// Styles
+    /// How the keys look: their colors in light and dark mode, how round
+    /// their corners are and the font of their labels. Unknown properties
+    /// and invalid colors are an error.
+    #[example(
+        yaml,
+        r##"
+        theme:
+          colors:
+            background: "#d1d5db"
+            key: "#ffffff"
+          darkColors:
+            key: "#6b6b6b"
+          keyCornerRadius: 8
+    "##
+    )]
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub style: Option<BTreeMap<String, YamlValue>>,
+    pub theme: Option<MobileTheme>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "legacyName")]
@@ -624,6 +1636,163 @@ pub struct TargetAndroid {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "keyAlias")]
     pub key_alias: Option<String>,
+
+    /// The app project to build the keyboards in, see <<Template>>
+    #[example(
+        yaml,
+        r#"
+        template:
+          git: https://github.com/divvun/giellakbd-android
+          tag: v1.4.0
+    "#
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template: Option<Template>,
+
+    /// The spacebar of the keyboards, see <<Spacebar>>
+    #[example(
+        yaml,
+        r#"
+        spacebar:
+          languageSwitch: false
+          label: displayName
+    "#
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spacebar: Option<Spacebar>,
+
+    /// The sound and haptics of key presses, see <<KeyFeedback>>
+    #[example(
+        yaml,
+        r#"
+        feedback:
+          sound: true
+          haptics: false
+    "#
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub feedback: Option<KeyFeedback>,
+
+    /// The names of the events that tell which layers and long-presses are
+    /// used, see <<Analytics>>
+    #[example(
+        yaml,
+        r#"
+        analytics:
+          prefix: divvun
+          longpress: false
+    "#
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub analytics: Option<Analytics>,
+
+    /// TrueType or OpenType fonts to draw key labels with, relative to the
+    /// bundle. A character is drawn with the first font that has it, and
+    /// with the system font if none does.
+    #[example(
+        yaml,
+        r#"
+        fonts:
+          - resources/fonts/NotoSansAdlam-Regular.ttf
+    "#
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fonts: Option<Vec<String>>,
+}
+
+/// Where the app project that a target's keyboards are built in comes from
+///
+/// Either a directory in the bundle or a git repository. A tag is downloaded
+/// once and kept, while without a tag the default branch is updated before
+/// every build. A `sha256` pins the contents of the template, see
+/// `templates::checksum`. Text files in the template can use `{{ variable }}` for values from the bundle, like
+/// `{{ packageId }}`, `{{ version }}`, `{{ build }}` or `{{ name }}`; see
+/// `templates::variables` for all of them.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, CollectDocs)]
+pub struct Template {
+    /// A directory, relative to the bundle
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+
+    /// The URL of a git repository
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git: Option<String>,
+
+    /// The tag of `git` to use, by default its default branch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+
+    /// The checksum the template must have, as written by
+    /// `kbdgen templates vendor`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+/// What the spacebar of a mobile target's keyboards does and shows
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, CollectDocs)]
+pub struct Spacebar {
+    /// Whether long-pressing space opens the language switcher, by default
+    /// `true`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "languageSwitch")]
+    pub language_switch: Option<bool>,
+
+    /// What is drawn on the spacebar: `space` for the layout's `strings.space`,
+    /// `displayName` for its name in its own language, or `none`. By default
+    /// `space`, or `displayName` for layouts with the `spacebar-language-label`
+    /// feature.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<SpacebarLabel>,
+}
+
+/// What is drawn on the spacebar, see `Spacebar`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SpacebarLabel {
+    Space,
+    DisplayName,
+    None,
+}
+
+/// What a mobile target's keyboards do when a key is pressed
+///
+/// These are the defaults of the app's settings, which users can change.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, CollectDocs)]
+pub struct KeyFeedback {
+    /// Whether pressing a key makes a click, by default `false`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sound: Option<bool>,
+
+    /// Whether pressing a key vibrates the device, by default `true`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub haptics: Option<bool>,
+
+    /// Whether the app lets users set the sound and haptics of each keyboard
+    /// on its own, instead of once for all of them, by default `false`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "perLocale")]
+    pub per_locale: Option<bool>,
+}
+
+/// The usage events of a mobile target's keyboards
+///
+/// kbdgen only names the events, in each layout's configuration, so that the
+/// apps built from a bundle report the same ones. An app counts them without
+/// what was typed or who typed it, and sends them wherever it is set up to.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, CollectDocs)]
+pub struct Analytics {
+    /// What every event name starts with, by default `keyboard`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+
+    /// Whether there is an event for each layer, by default `true`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layers: Option<bool>,
+
+    /// Whether there is an event for each long-press alternative, by default
+    /// `true`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub longpress: Option<bool>,
 }
 
 // TODO: Keyboards have a provisioningProfileId -- add this here?
@@ -669,6 +1838,26 @@ pub struct TargetIOS {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub chfst: Option<bool>,
+
+    /// The app project to build the keyboards in, see <<Template>>
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template: Option<Template>,
+
+    /// The spacebar of the keyboards, see <<Spacebar>>
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spacebar: Option<Spacebar>,
+
+    /// The sound and haptics of key presses, see <<KeyFeedback>>
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub feedback: Option<KeyFeedback>,
+
+    /// The usage events of the keyboards, the same as for `android`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub analytics: Option<Analytics>,
+
+    /// Fonts to draw key labels with, the same as for `android`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fonts: Option<Vec<String>>,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, CollectDocs)]
@@ -683,7 +1872,10 @@ pub struct TargetWindows {
     #[example(yaml, r#"url: 'http://divvun.no'"#)]
     pub url: String,
 
+    /// The GUID the installer registers the layouts under, which has to stay
+    /// the same across versions. `kbdgen target win init` generates one.
     #[example(yaml, r#"uuid: 0D18406F-1209-43EF-B18F-58961BC8E2E3"#)]
+    #[serde(default)]
     pub uuid: String,
 
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -701,6 +1893,67 @@ pub struct TargetWindows {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "readmePath")]
     pub readme_path: Option<String>,
+
+    /// Characters to replace in an additional layout for legacy applications
+    /// that cannot handle them, keyed by the character to replace
+    ///
+    /// The additional layout is installed next to each layout, with
+    /// "(legacy)" after its display names and a `c` as the last character of
+    /// its id. Each character is replaced once, in the keys, the dead keys and
+    /// their transforms.
+    #[example(
+        yaml,
+        r#"
+        compatMap:
+          "ʼ": "’"
+          "ʻ": "‘"
+    "#
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "compatMap")]
+    pub compat_map: Option<BTreeMap<String, String>>,
+
+    /// The letters typed with ctrl for shortcuts that layouts should keep
+    /// where they are, see `kbdgen lint`. An empty list turns the check off.
+    #[example(yaml, r#"shortcuts: [a, c, v, x, z]"#)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shortcuts: Option<Vec<String>>,
+
+    /// How the layouts are packaged, an Inno Setup installer by default
+    #[example(yaml, r#"packager: msix"#)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub packager: Option<WindowsPackager>,
+
+    /// The subject of the code signing certificate, which MSIX packages must
+    /// name as their publisher. By default `CN=` and the project's
+    /// organisation.
+    #[example(yaml, r#"publisher: "CN=Divvun, O=UiT, C=NO""#)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub publisher: Option<String>,
+
+    /// The processor architectures to build the layouts for, all of which
+    /// go into the one installer. By default `x86` and `x64`, or only `x64`
+    /// for MSIX packages.
+    #[example(yaml, r#"architectures: [x86, x64, arm64]"#)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub architectures: Option<Vec<WindowsArchitecture>>,
+}
+
+/// The kind of package built for Windows, see `TargetWindows`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowsPackager {
+    Inno,
+    Msix,
+}
+
+/// A processor architecture of Windows, see `TargetWindows`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowsArchitecture {
+    X86,
+    X64,
+    Arm64,
 }
 
 // TODO: Keyboards have a provisioningProfileId -- add this here?
@@ -735,6 +1988,67 @@ pub struct TargetMacOS {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "codeSignId")]
     pub code_sign_id: Option<String>,
+
+    /// The letters typed with cmd for shortcuts that layouts should keep
+    /// where they are, see `kbdgen lint`. An empty list turns the check off.
+    #[example(yaml, r#"shortcuts: [a, c, v, x, z, q, w]"#)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shortcuts: Option<Vec<String>>,
+
+    /// How release installers are notarized, see <<Notarization>>. Without
+    /// it, installers are only notarized if the credentials are in the
+    /// environment.
+    #[example(
+        yaml,
+        r#"
+        notarization:
+          keychainProfile: kbdgen-notary
+    "#
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notarization: Option<Notarization>,
+}
+
+/// Submitting a macOS installer to Apple's notary service
+///
+/// `notarytool` needs a `keychainProfile` stored with `xcrun notarytool
+/// store-credentials`, as it would otherwise get the password on its command
+/// line. `altool` takes an `appleId` with the name of the keychain item
+/// holding its app-specific password. Each can also come from the
+/// environment, see the macOS target documentation.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, CollectDocs)]
+pub struct Notarization {
+    /// `notarytool`, the default, or `altool` for Xcode before 13
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool: Option<NotarizationTool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "keychainProfile")]
+    pub keychain_profile: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "appleId")]
+    pub apple_id: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "passwordChainItem")]
+    pub password_chain_item: Option<String>,
+
+    /// Whether the ticket is stapled to the installer, by default `true`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub staple: Option<bool>,
+
+    /// How long to wait for the notary service, in minutes, by default 30
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u32>,
+}
+
+/// The tool that submits to the notary service, see `Notarization`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotarizationTool {
+    Notarytool,
+    Altool,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, CollectDocs)]
@@ -744,12 +2058,59 @@ pub struct TargetChrome {
 
     #[serde(rename = "appId")]
     pub app_id: String,
+
+    /// Path to the PEM encoded RSA private key used to sign the `.crx`
+    /// package, relative to the bundle. Without it, only the zip for uploading
+    /// to the web store is made.
+    ///
+    /// NOTE: Keep this key out of version control. The web store signs
+    /// uploaded packages itself; the key is only needed for self-hosting.
+    #[example(yaml, r#"signingKey: ../keys/chrome.pem"#)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "signingKey")]
+    pub signing_key: Option<String>,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, CollectDocs)]
 pub struct TargetX11 {
     pub version: String,
     pub build: u32,
+
+    /// Symbols for a second group, to switch to with the group-switch key,
+    /// see <<SecondGroup>>
+    #[example(
+        yaml,
+        r#"
+        secondGroup:
+          layout: "no"
+          toggle: alt_shift_toggle
+    "#
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "secondGroup")]
+    pub second_group: Option<SecondGroup>,
+}
+
+/// A second group for bilingual setups
+///
+/// Each symbols file gets a `bilingual` variant with the layout in the first
+/// group and these symbols in the second, like a national layout next to a
+/// minority language layout.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, CollectDocs)]
+pub struct SecondGroup {
+    /// XKB symbols, like `no` or `fi(classic)`, or the name of another layout
+    /// in the bundle
+    pub layout: String,
+
+    /// The name of the group in layout indicators, by default the layout's
+    /// name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// The option from the `group` symbols that switches groups, by default
+    /// `alt_shift_toggle`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub toggle: Option<String>,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, CollectDocs)]
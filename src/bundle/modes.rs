@@ -1,4 +1,10 @@
-use serde::{Deserialize, Serialize};
+use super::modifiers::MODIFIER_NAMES;
+use crate::{utils::closest, ModeName, ModifierSet, Target};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{fmt, str::FromStr};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+use thiserror::Error;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[derive(Serialize, Deserialize)]
@@ -37,3 +43,304 @@ pub struct Desktop<T> {
     #[serde(rename = "cmd+alt+shift", skip_serializing_if = "Option::is_none")]
     pub cmd_alt_shift: Option<T>,
 }
+
+const WIN_MODES: &[&str] = &[
+    "default",
+    "shift",
+    "caps",
+    "caps+shift",
+    "alt",
+    "alt+shift",
+    "caps+alt",
+    "ctrl",
+];
+const MAC_MODES: &[&str] = &[
+    "default",
+    "shift",
+    "caps",
+    "caps+shift",
+    "alt",
+    "alt+shift",
+    "caps+alt",
+    "caps+alt+shift",
+    "ctrl",
+    "cmd",
+    "cmd+shift",
+    "cmd+alt",
+    "cmd+alt+shift",
+    "cmd+caps",
+    "cmd+caps+shift",
+    "cmd+caps+alt",
+];
+/// iPads have an alt layer, phones only the first two, and both can have two
+/// pages of symbols
+const IOS_MODES: &[&str] = &[
+    "default",
+    "shift",
+    "alt",
+    "alt+shift",
+    "symbols-1",
+    "symbols-2",
+    "mobile-6rows",
+];
+const ANDROID_MODES: &[&str] = &["default", "shift", "mobile-6rows"];
+
+/// The modes of mobile layouts
+///
+/// Mobile modes are layers of keys rather than modifiers held down, so there
+/// is a fixed set of them. Modifier names are accepted in any order, like for
+/// desktop modes, and optional modifiers are ignored as no mobile target has
+/// a caps lock layer.
+///
+/// The iPad layers are used by `ios` and by the iPad modes `ipad-9in` and
+/// `ipad-12in` of a layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, EnumIter)]
+pub enum MobileMode {
+    Default,
+    Shift,
+    /// iPad only
+    Alt,
+    /// iPad only
+    AltShift,
+    /// The first page of symbols on iOS
+    Symbols1,
+    /// The second page of symbols on iOS
+    Symbols2,
+    /// Six rows of keys, with the digits and punctuation of the symbols
+    /// pages above the letters, for screens with room for them
+    Mobile6Rows,
+}
+
+impl MobileMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            MobileMode::Default => "default",
+            MobileMode::Shift => "shift",
+            MobileMode::Alt => "alt",
+            MobileMode::AltShift => "alt+shift",
+            MobileMode::Symbols1 => "symbols-1",
+            MobileMode::Symbols2 => "symbols-2",
+            MobileMode::Mobile6Rows => "mobile-6rows",
+        }
+    }
+
+    /// The mode typed while `held` is held, if there is one
+    pub fn from_modifiers(held: &ModifierSet) -> Option<MobileMode> {
+        let name = held.to_string();
+        MobileMode::iter().find(|mode| mode.name() == name)
+    }
+}
+
+impl fmt::Display for MobileMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for MobileMode {
+    type Err = MobileModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(mode) = MobileMode::iter().find(|mode| mode.name() == s) {
+            return Ok(mode);
+        }
+        s.parse::<ModeName>()
+            .ok()
+            .and_then(|mode| MobileMode::from_modifiers(&mode.required))
+            .ok_or_else(|| MobileModeError {
+                mode: s.to_string(),
+                suggestion: closest(s, MobileMode::iter().map(MobileMode::name))
+                    .map(str::to_string),
+            })
+    }
+}
+
+impl Serialize for MobileMode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for MobileMode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Unknown mobile mode `{}`{}", .mode, did_you_mean(.suggestion))]
+pub struct MobileModeError {
+    pub mode: String,
+    pub suggestion: Option<String>,
+}
+
+/// Checks that `target` has the mobile `mode`
+pub fn check_mobile_mode(target: Target, mode: MobileMode) -> Result<(), ModeNameError> {
+    let allowed = allowed_modes(target);
+    if allowed.contains(&mode.name()) {
+        return Ok(());
+    }
+    Err(ModeNameError::Unsupported {
+        mode: mode.name().to_string(),
+        target,
+        suggestion: closest(mode.name(), allowed.iter().copied()).map(str::to_string),
+    })
+}
+
+/// The modes the generators for a target know what to do with
+///
+/// `desktop` and `mobile` allow everything any of the targets falling back
+/// to them allow.
+pub fn allowed_modes(target: Target) -> &'static [&'static str] {
+    match target {
+        Target::Win | Target::Chrome | Target::X11 => WIN_MODES,
+        Target::Mac | Target::Desktop => MAC_MODES,
+        Target::Ios | Target::Mobile => IOS_MODES,
+        Target::Android => ANDROID_MODES,
+    }
+}
+
+/// Parses the name of a mode used for `target`, checking that the target has
+/// such a mode
+///
+/// Only the required modifiers of a name like `alt+caps?` need to make a mode
+/// the target has; the optional ones just cover fewer modes on targets that
+/// lack them.
+pub fn parse_mode_name(target: Target, name: &str) -> Result<ModeName, ModeNameError> {
+    let allowed = allowed_modes(target);
+    let is_allowed = |mode: &ModifierSet| {
+        allowed
+            .iter()
+            .any(|a| a.parse::<ModifierSet>().ok().as_ref() == Some(mode))
+    };
+
+    match name.parse::<ModeName>() {
+        Ok(mode) if is_allowed(&mode.required) => Ok(mode),
+        Ok(_) => Err(ModeNameError::Unsupported {
+            mode: name.to_string(),
+            target,
+            suggestion: closest(name, allowed.iter().copied()).map(str::to_string),
+        }),
+        Err(_) => {
+            // Fix each misspelled modifier, or failing that the whole name
+            let fixed = name
+                .split('+')
+                .map(|part| {
+                    let part = part.trim_end_matches('?');
+                    if MODIFIER_NAMES.contains(&part) || part == "default" {
+                        Some(part)
+                    } else {
+                        closest(part, MODIFIER_NAMES.iter().copied().chain(Some("default")))
+                    }
+                })
+                .collect::<Option<Vec<_>>>()
+                .and_then(|parts| parts.join("+").parse::<ModifierSet>().ok())
+                .filter(|mode| is_allowed(mode))
+                .map(|mode| mode.to_string());
+            Err(ModeNameError::Unknown {
+                mode: name.to_string(),
+                target,
+                suggestion: fixed
+                    .or_else(|| closest(name, allowed.iter().copied()).map(str::to_string)),
+            })
+        }
+    }
+}
+
+fn did_you_mean(suggestion: &Option<String>) -> String {
+    match suggestion {
+        Some(suggestion) => format!(", did you mean `{}`?", suggestion),
+        None => String::new(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ModeNameError {
+    #[error("Unknown mode `{}` for {}{}", .mode, .target, did_you_mean(.suggestion))]
+    Unknown {
+        mode: String,
+        target: Target,
+        suggestion: Option<String>,
+    },
+    #[error("{} has no `{}` mode{}", .target, .mode, did_you_mean(.suggestion))]
+    Unsupported {
+        mode: String,
+        target: Target,
+        suggestion: Option<String>,
+    },
+}
+
+impl ModeNameError {
+    pub fn mode(&self) -> &str {
+        match self {
+            ModeNameError::Unknown { mode, .. } | ModeNameError::Unsupported { mode, .. } => mode,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mobile_modes_have_fixed_names() {
+        assert_eq!("shift+alt".parse(), Ok(MobileMode::AltShift));
+        assert_eq!("symbols-2".parse(), Ok(MobileMode::Symbols2));
+        assert_eq!("mobile-6rows".parse(), Ok(MobileMode::Mobile6Rows));
+        assert_eq!(
+            "symbol-1".parse::<MobileMode>().unwrap_err().to_string(),
+            "Unknown mobile mode `symbol-1`, did you mean `symbols-1`?"
+        );
+        assert!(check_mobile_mode(Target::Ios, MobileMode::Symbols1).is_ok());
+        assert_eq!(
+            check_mobile_mode(Target::Android, MobileMode::Alt)
+                .unwrap_err()
+                .to_string(),
+            "android has no `alt` mode"
+        );
+        assert!(check_mobile_mode(Target::Android, MobileMode::Mobile6Rows).is_ok());
+    }
+
+    #[test]
+    fn aliases_and_any_order_are_accepted() {
+        let mode = parse_mode_name(Target::Mac, "shift+opt").unwrap();
+        assert_eq!(mode.to_string(), "alt+shift");
+        assert!(parse_mode_name(Target::Win, "control").is_ok());
+    }
+
+    #[test]
+    fn optional_modifiers_need_not_be_supported() {
+        let mode = parse_mode_name(Target::Android, "shift+caps?").unwrap();
+        assert_eq!(mode.required.to_string(), "shift");
+        assert!(parse_mode_name(Target::Android, "alt+caps?").is_err());
+    }
+
+    #[test]
+    fn typos_are_errors_with_suggestions() {
+        assert_eq!(
+            parse_mode_name(Target::Mac, "defualt")
+                .unwrap_err()
+                .to_string(),
+            "Unknown mode `defualt` for mac, did you mean `default`?"
+        );
+        assert_eq!(
+            parse_mode_name(Target::Win, "shfit+alt").unwrap_err(),
+            ModeNameError::Unknown {
+                mode: "shfit+alt".to_string(),
+                target: Target::Win,
+                suggestion: Some("alt+shift".to_string()),
+            }
+        );
+        assert_eq!(
+            parse_mode_name(Target::Android, "alt")
+                .unwrap_err()
+                .to_string(),
+            "android has no `alt` mode"
+        );
+        assert_eq!(
+            parse_mode_name(Target::Win, "cmd").unwrap_err().to_string(),
+            "win has no `cmd` mode"
+        );
+    }
+}
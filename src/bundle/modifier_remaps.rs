@@ -0,0 +1,153 @@
+//! Giving modifier keys other roles, like Caps Lock as AltGr
+//!
+//! A layout's `modifierRemaps` are keyed by target, and fall back key by key
+//! like dead keys.
+//! Each generator applies them its own way: XKB symbols include the symbols
+//! of the matching option, the Windows installer writes a `Scancode Map` and
+//! the macOS installer maps the keys with `hidutil` at login. What a target
+//! cannot do is reported by `kbdgen lint`.
+
+use crate::{models::Layout, Target};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use strum_macros::{Display, EnumIter, EnumString};
+
+/// A modifier key, by where it is on the keyboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Serialize, Deserialize, Display, EnumString, EnumIter)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum ModifierKey {
+    Caps,
+    LeftAlt,
+    RightAlt,
+    LeftCtrl,
+    RightCtrl,
+    /// The context menu key, right of the space bar on PC keyboards
+    Menu,
+}
+
+/// What a remapped modifier key does
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Serialize, Deserialize, Display, EnumString, EnumIter)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum ModifierRole {
+    /// Chooses the `alt` modes, as AltGr does on PC keyboards and Option
+    /// does on macOS. Also known as level 3.
+    #[serde(alias = "level3")]
+    #[strum(serialize = "altgr", serialize = "level3")]
+    AltGr,
+    Alt,
+    Ctrl,
+    Shift,
+    Caps,
+    /// Does nothing
+    None,
+}
+
+/// The targets whose generators apply modifier remaps
+pub const REMAP_TARGETS: &[Target] = &[Target::X11, Target::Win, Target::Mac];
+
+/// The XKB option for a remap, and the symbols it includes
+pub fn xkb_option(key: ModifierKey, role: ModifierRole) -> Option<(&'static str, &'static str)> {
+    use ModifierKey as K;
+    use ModifierRole as R;
+
+    Some(match (key, role) {
+        (K::Caps, R::AltGr) => ("lv3:caps_switch", "level3(caps_switch)"),
+        (K::Caps, R::Ctrl) => ("ctrl:nocaps", "ctrl(nocaps)"),
+        (K::Caps, R::None) => ("caps:none", "capslock(none)"),
+        (K::LeftAlt, R::AltGr) => ("lv3:lalt_switch", "level3(lalt_switch)"),
+        (K::RightAlt, R::AltGr) => ("lv3:ralt_switch", "level3(ralt_switch)"),
+        (K::RightAlt, R::Alt) => ("lv3:ralt_alt", "level3(ralt_alt)"),
+        (K::Menu, R::AltGr) => ("lv3:menu_switch", "level3(menu_switch)"),
+        (K::Menu, R::Ctrl) => ("ctrl:menu_rctrl", "ctrl(menu_rctrl)"),
+        _ => return None,
+    })
+}
+
+/// Whether `target` can give `key` the role `role`
+pub fn is_supported(target: Target, key: ModifierKey, role: ModifierRole) -> bool {
+    match target {
+        Target::X11 => xkb_option(key, role).is_some(),
+        Target::Win => true,
+        // Macs have no menu key, and `hidutil` cannot turn a key off
+        Target::Mac => key != ModifierKey::Menu && role != ModifierRole::None,
+        _ => false,
+    }
+}
+
+impl Layout {
+    /// The modifier remaps for `target`, with those of the targets it falls
+    /// back to for keys it does not remap itself
+    pub fn modifier_remaps(&self, target: Target) -> BTreeMap<ModifierKey, ModifierRole> {
+        let remaps = match &self.modifier_remaps {
+            Some(remaps) => remaps,
+            None => return BTreeMap::new(),
+        };
+        let chain = target.fallback_chain().collect::<Vec<_>>();
+        chain
+            .into_iter()
+            .rev()
+            .filter_map(|target| remaps.get(&target))
+            .flatten()
+            .map(|(&key, &role)| (key, role))
+            .collect()
+    }
+
+    /// Remaps that a target they apply to cannot do, and remaps for targets
+    /// that no generator applies them for
+    pub fn ignored_modifier_remaps(&self) -> Vec<(Target, ModifierKey, ModifierRole)> {
+        let remaps = match &self.modifier_remaps {
+            Some(remaps) => remaps,
+            None => return vec![],
+        };
+        let unsupported = REMAP_TARGETS.iter().flat_map(|&target| {
+            self.modifier_remaps(target)
+                .into_iter()
+                .filter(move |&(key, role)| !is_supported(target, key, role))
+                .map(move |(key, role)| (target, key, role))
+        });
+        let unused = remaps
+            .iter()
+            .filter(|(&target, _)| {
+                !REMAP_TARGETS
+                    .iter()
+                    .any(|t| t.fallback_chain().any(|t| t == target))
+            })
+            .flat_map(|(&target, remaps)| {
+                remaps.iter().map(move |(&key, &role)| (target, key, role))
+            });
+        unsupported.chain(unused).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remaps_fall_back_like_modes() {
+        let layout: Layout = serde_yaml::from_str(
+            "displayNames:\n  en: Test\nmodes: {}\nmodifierRemaps:\n  desktop:\n    caps: level3\n    menu: none\n  mac:\n    caps: ctrl\n  ios:\n    caps: altgr\n",
+        )
+        .unwrap();
+        assert_eq!(
+            layout.modifier_remaps(Target::X11)[&ModifierKey::Caps],
+            ModifierRole::AltGr
+        );
+        assert_eq!(
+            layout.modifier_remaps(Target::Mac)[&ModifierKey::Caps],
+            ModifierRole::Ctrl
+        );
+        assert_eq!(
+            layout.ignored_modifier_remaps(),
+            vec![
+                (Target::X11, ModifierKey::Menu, ModifierRole::None),
+                (Target::Mac, ModifierKey::Menu, ModifierRole::None),
+                (Target::Ios, ModifierKey::Caps, ModifierRole::AltGr),
+            ]
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use std::{collections::BTreeSet, fmt, str::FromStr};
+use strum_macros::{Display, EnumIter, EnumString};
+use thiserror::Error;
+
+/// Modifier keys that can be part of a mode name
+///
+/// The order of the variants is the order in which they are written in a
+/// mode name, e.g. `cmd+alt+shift`. The names of the modifiers on a Mac are
+/// accepted as aliases.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(EnumString, Display, EnumIter)]
+#[strum(serialize_all = "lowercase")]
+pub enum Modifier {
+    #[strum(to_string = "cmd", serialize = "command")]
+    Cmd,
+    #[strum(to_string = "ctrl", serialize = "control")]
+    Ctrl,
+    Caps,
+    #[strum(to_string = "alt", serialize = "opt", serialize = "option")]
+    Alt,
+    Shift,
+}
+
+/// Every name a modifier can be written as, canonical names first
+pub(crate) const MODIFIER_NAMES: &[&str] = &[
+    "cmd", "ctrl", "caps", "alt", "shift", "command", "control", "opt", "option",
+];
+
+/// A combination of modifiers, as used for the keys of `DesktopModes` and
+/// `MobileModes`
+///
+/// Mode names are parsed regardless of the order of their modifiers, so
+/// `shift+alt` and `alt+shift` are the same set. The empty set is called
+/// `default`.
+///
+/// ```rust
+/// use kbdgen::{Modifier, ModifierSet};
+///
+/// let mode: ModifierSet = "shift+alt".parse().unwrap();
+/// assert!(mode.contains(Modifier::Alt));
+/// assert_eq!(mode.to_string(), "alt+shift");
+/// assert_eq!("opt+shift".parse::<ModifierSet>().unwrap(), mode);
+/// assert_eq!("default".parse::<ModifierSet>().unwrap(), ModifierSet::default());
+/// ```
+#[derive(Debug, Clone, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ModifierSet(BTreeSet<Modifier>);
+
+impl ModifierSet {
+    pub fn contains(&self, modifier: Modifier) -> bool {
+        self.0.contains(&modifier)
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Modifier> + '_ {
+        self.0.iter().copied()
+    }
+}
+
+impl std::iter::FromIterator<Modifier> for ModifierSet {
+    fn from_iter<I: IntoIterator<Item = Modifier>>(iter: I) -> Self {
+        ModifierSet(iter.into_iter().collect())
+    }
+}
+
+impl FromStr for ModifierSet {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "default" {
+            return Ok(ModifierSet::default());
+        }
+
+        s.split('+')
+            .map(|m| {
+                Modifier::from_str(m).map_err(|_| Error::UnknownModifier {
+                    mode: s.to_string(),
+                    modifier: m.to_string(),
+                })
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for ModifierSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_default() {
+            return write!(f, "default");
+        }
+
+        let names: Vec<String> = self.iter().map(|m| m.to_string()).collect();
+        write!(f, "{}", names.join("+"))
+    }
+}
+
+/// The name of a mode as written in a layout, which may mark modifiers as
+/// optional with a `?`
+///
+/// This is how kbdgen 1.x bundles (and CLDR) say "derive unless specified":
+/// `alt+caps?` is the `alt` mode, and also `caps+alt` unless the layout has a
+/// mode of that name.
+///
+/// ```rust
+/// use kbdgen::{ModeName, ModifierSet};
+///
+/// let name: ModeName = "alt+caps?".parse().unwrap();
+/// assert!(name.matches(&"alt".parse().unwrap()));
+/// assert!(name.matches(&"caps+alt".parse().unwrap()));
+/// assert!(!name.matches(&"alt+shift".parse().unwrap()));
+/// assert_eq!(name.to_string(), "caps?+alt");
+/// ```
+#[derive(Debug, Clone, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ModeName {
+    pub required: ModifierSet,
+    pub optional: ModifierSet,
+}
+
+impl ModeName {
+    /// Whether the mode is used while exactly `held` is held
+    pub fn matches(&self, held: &ModifierSet) -> bool {
+        self.required.iter().all(|m| held.contains(m))
+            && held
+                .iter()
+                .all(|m| self.required.contains(m) || self.optional.contains(m))
+    }
+
+    /// Whether no modifiers are optional
+    pub fn is_exact(&self) -> bool {
+        self.optional.is_default()
+    }
+}
+
+impl FromStr for ModeName {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "default" {
+            return Ok(ModeName::default());
+        }
+
+        let mut required = vec![];
+        let mut optional = vec![];
+        for part in s.split('+') {
+            let (name, list) = match part.strip_suffix('?') {
+                Some(name) => (name, &mut optional),
+                None => (part, &mut required),
+            };
+            list.push(
+                Modifier::from_str(name).map_err(|_| Error::UnknownModifier {
+                    mode: s.to_string(),
+                    modifier: part.to_string(),
+                })?,
+            );
+        }
+        Ok(ModeName {
+            required: required.into_iter().collect(),
+            optional: optional.into_iter().collect(),
+        })
+    }
+}
+
+impl fmt::Display for ModeName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_exact() {
+            return write!(f, "{}", self.required);
+        }
+
+        let mut modifiers: Vec<(Modifier, bool)> = self
+            .required
+            .iter()
+            .map(|m| (m, false))
+            .chain(self.optional.iter().map(|m| (m, true)))
+            .collect();
+        modifiers.sort();
+        let names: Vec<String> = modifiers
+            .into_iter()
+            .map(|(m, optional)| {
+                if optional {
+                    format!("{}?", m)
+                } else {
+                    m.to_string()
+                }
+            })
+            .collect();
+        write!(f, "{}", names.join("+"))
+    }
+}
+
+/// The entry of a map keyed by mode name that is used while `held` is held
+///
+/// A name without optional modifiers wins, then the one requiring the most
+/// modifiers.
+pub(crate) fn find_mode<'a, T>(
+    modes: impl IntoIterator<Item = (&'a String, &'a T)>,
+    held: &ModifierSet,
+) -> Option<(&'a String, &'a T)> {
+    modes
+        .into_iter()
+        .filter_map(|(name, value)| {
+            let mode_name = name.parse::<ModeName>().ok()?;
+            if !mode_name.matches(held) {
+                return None;
+            }
+            let rank = (mode_name.is_exact(), mode_name.required.iter().count());
+            Some((rank, (name, value)))
+        })
+        .max_by_key(|(rank, _)| *rank)
+        .map(|(_, entry)| entry)
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Unknown modifier `{modifier}` in mode `{mode}`")]
+    UnknownModifier { mode: String, modifier: String },
+}
@@ -0,0 +1,126 @@
+//! Layout names that are safe to use in file paths
+//!
+//! The name of a layout, from its file name or a `variants` key, becomes the
+//! file name of what every generator writes for it, and part of identifiers
+//! like Android resource names. Names are checked as layouts are loaded and
+//! imported, so that they cannot point outside the output directory, and are
+//! valid file names on Windows, macOS and Linux alike.
+
+use std::path::{Path, PathBuf};
+
+/// Device names that Windows reserves in every directory, with any extension
+const WINDOWS_RESERVED: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Characters that Windows does not allow in file names, besides controls
+const WINDOWS_INVALID: &[char] = &['<', '>', ':', '"', '|', '?', '*'];
+
+/// Why a layout name cannot be used as a file name
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum NameError {
+    #[error("the name is empty")]
+    Empty,
+
+    #[error("`{}` is a path, not a name", name)]
+    Separator { name: String },
+
+    #[error("`{}` starts with a dot", name)]
+    LeadingDot { name: String },
+
+    #[error("`{}` ends with a dot or a space, which Windows drops", name)]
+    Trailing { name: String },
+
+    #[error("`{}` has {:?}, which is not allowed in file names", name, character)]
+    InvalidCharacter { name: String, character: char },
+
+    #[error("`{}` is a device name on Windows", name)]
+    Reserved { name: String },
+}
+
+/// Checks that `name` can be the name of a file in any directory, on any
+/// system
+pub fn check_layout_name(name: &str) -> Result<(), NameError> {
+    let error_name = || name.to_string();
+    if name.is_empty() {
+        return Err(NameError::Empty);
+    }
+    if name.contains(&['/', '\\'][..]) {
+        return Err(NameError::Separator { name: error_name() });
+    }
+    if name.starts_with('.') {
+        return Err(NameError::LeadingDot { name: error_name() });
+    }
+    if name.ends_with(&['.', ' '][..]) {
+        return Err(NameError::Trailing { name: error_name() });
+    }
+    if let Some(character) = name
+        .chars()
+        .find(|&c| c.is_control() || WINDOWS_INVALID.contains(&c))
+    {
+        return Err(NameError::InvalidCharacter {
+            name: error_name(),
+            character,
+        });
+    }
+    let stem = name.split('.').next().unwrap_or_default();
+    if WINDOWS_RESERVED
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+    {
+        return Err(NameError::Reserved { name: error_name() });
+    }
+    Ok(())
+}
+
+/// The file of the layout `name` in a bundle, if the name is safe to use
+pub fn layout_file(project_path: &Path, name: &str) -> Result<PathBuf, NameError> {
+    check_layout_name(name)?;
+    Ok(project_path.join("layouts").join(format!("{}.yaml", name)))
+}
+
+/// `name` with everything but ASCII letters and digits replaced by `_`, for
+/// identifiers that allow nothing else
+pub fn identifier(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_that_are_not_file_names_are_rejected() {
+        for name in &["se", "se-NO", "smj.Latn", "ru-phonetic", "ᓀᐦᐃᔭᐍᐏᐣ"] {
+            assert_eq!(check_layout_name(name), Ok(()), "{}", name);
+        }
+        assert_eq!(check_layout_name(""), Err(NameError::Empty));
+        assert!(matches!(
+            check_layout_name("../evil"),
+            Err(NameError::Separator { .. })
+        ));
+        assert!(matches!(
+            check_layout_name(".."),
+            Err(NameError::LeadingDot { .. })
+        ));
+        assert!(matches!(
+            check_layout_name("se "),
+            Err(NameError::Trailing { .. })
+        ));
+        assert_eq!(
+            check_layout_name("se:NO"),
+            Err(NameError::InvalidCharacter {
+                name: "se:NO".to_string(),
+                character: ':'
+            })
+        );
+        assert!(matches!(
+            check_layout_name("con.se"),
+            Err(NameError::Reserved { .. })
+        ));
+        assert_eq!(identifier("se-NO.Latn"), "se_NO_Latn");
+    }
+}
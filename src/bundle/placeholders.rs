@@ -0,0 +1,189 @@
+//! `{{zwj}}` and other placeholders for invisible characters
+//!
+//! Joiners, non-breaking spaces and the like cannot be seen in a YAML file, so
+//! a layout typing them is hard to review and easy to break by accident. They
+//! can be written as placeholders instead, named after their abbreviation in
+//! lower case: `{{zwj}}`, `{{zwnj}}`, `{{nbsp}}` and so on. Placeholders work
+//! in modes, `longpress` and `transforms`, and are replaced with the
+//! characters themselves when a layout is loaded, so generators never see
+//! them. Their names are reserved and cannot be used as `macros`.
+
+use super::display::{is_invisible, ABBREVIATIONS};
+use crate::{models::Layout, KeyValue};
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use std::{borrow::Cow, collections::BTreeMap, fmt};
+
+lazy_static! {
+    static ref PLACEHOLDER: Regex = Regex::new(r"\{\{([a-z]+)\}\}").expect("valid regex");
+}
+
+/// The character of the placeholder `name`, like `zwj` for U+200D
+pub fn placeholder(name: &str) -> Option<char> {
+    ABBREVIATIONS
+        .iter()
+        .find(|(_, abbreviation)| abbreviation.eq_ignore_ascii_case(name))
+        .map(|(c, _)| *c)
+}
+
+/// `text` with its placeholders replaced, leaving `{{name}}` of other names,
+/// like macros, as they are
+pub fn expand_placeholders(text: &str) -> Cow<'_, str> {
+    PLACEHOLDER.replace_all(text, |c: &Captures| match placeholder(&c[1]) {
+        Some(character) => character.to_string(),
+        None => c[0].to_string(),
+    })
+}
+
+fn expand_map(map: &mut BTreeMap<String, String>) {
+    *map = std::mem::take(map)
+        .into_iter()
+        .map(|(key, value)| {
+            (
+                expand_placeholders(&key).into_owned(),
+                expand_placeholders(&value).into_owned(),
+            )
+        })
+        .collect();
+}
+
+impl Layout {
+    /// Replaces the placeholders in the modes, `longpress` and `transforms`
+    /// of this layout
+    pub fn expand_placeholders(&mut self) {
+        self.for_each_key_mut(|key| {
+            if let KeyValue::Symbol(value) = key {
+                if let Cow::Owned(expanded) = expand_placeholders(value) {
+                    *value = expanded;
+                }
+            }
+        });
+        if let Some(longpress) = self.longpress.as_mut() {
+            expand_map(longpress);
+        }
+        if let Some(transforms) = self.transforms.as_mut() {
+            *transforms = std::mem::take(transforms)
+                .into_iter()
+                .map(|(key, mut map)| {
+                    expand_map(&mut map);
+                    (expand_placeholders(&key).into_owned(), map)
+                })
+                .collect();
+        }
+    }
+}
+
+/// An invisible character written as it is in the YAML of a layout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawInvisible {
+    pub character: char,
+    /// Counting from 1
+    pub line: usize,
+    /// In characters, counting from 1
+    pub column: usize,
+}
+
+impl fmt::Display for RawInvisible {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: U+{:04X} is written as it is, ",
+            self.line, self.column, self.character as u32
+        )?;
+        match ABBREVIATIONS.iter().find(|(c, _)| *c == self.character) {
+            Some((_, abbreviation)) => {
+                write!(f, "write `{{{{{}}}}}` instead", abbreviation.to_lowercase())
+            }
+            None => write!(f, "write `\\u{{{:X}}}` instead", self.character as u32),
+        }
+    }
+}
+
+/// The invisible characters in `text`, the YAML of a layout, that are not
+/// written as placeholders or escapes, other than tabs, line breaks and a
+/// leading byte order mark
+pub fn raw_invisible_characters(text: &str) -> Vec<RawInvisible> {
+    let text = text.strip_prefix('\u{FEFF}').unwrap_or(text);
+    let mut found = vec![];
+    for (line, content) in text.lines().enumerate() {
+        for (column, character) in content.chars().enumerate() {
+            if matches!(character, '\t' | '\r') || !is_invisible(character) {
+                continue;
+            }
+            found.push(RawInvisible {
+                character,
+                line: line + 1,
+                column: column + 1,
+            });
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_are_expanded_everywhere() {
+        let mut layout: Layout = serde_yaml::from_str(
+            r#"
+displayNames:
+  en: Test
+modes:
+  mobile:
+    default: |
+      a {{zwj}} b{{zwnj}}c {{currency}}
+longpress:
+  a: "{{nbsp}} ä"
+transforms:
+  "{{zwj}}":
+    " ": "{{zwj}}{{unknown}}"
+"#,
+        )
+        .unwrap();
+        layout.expand_placeholders();
+
+        let keys = &layout.modes.mobile.as_ref().unwrap()[&crate::MobileMode::Default].0[0];
+        let symbol = |s: &str| KeyValue::Symbol(s.to_string());
+        assert_eq!(
+            keys,
+            &vec![
+                symbol("a"),
+                symbol("\u{200D}"),
+                symbol("b\u{200C}c"),
+                symbol("{{currency}}")
+            ]
+        );
+        assert_eq!(layout.longpress.as_ref().unwrap()["a"], "\u{A0} ä");
+        assert_eq!(
+            layout.transforms.as_ref().unwrap()["\u{200D}"][" "],
+            "\u{200D}{{unknown}}"
+        );
+        assert_eq!(
+            layout.used_macros().into_iter().collect::<Vec<_>>(),
+            vec!["currency"]
+        );
+    }
+
+    #[test]
+    fn raw_invisible_characters_are_found() {
+        let text = "\u{FEFF}modes:\n\tdefault: |\r\n  a \u{200D} b\u{A0}\n  \u{2061}\n";
+        let found = raw_invisible_characters(text);
+        assert_eq!(
+            found
+                .iter()
+                .map(|raw| (raw.character, raw.line, raw.column))
+                .collect::<Vec<_>>(),
+            vec![('\u{200D}', 3, 5), ('\u{A0}', 3, 8), ('\u{2061}', 4, 3)]
+        );
+        assert_eq!(
+            found[0].to_string(),
+            "line 3, column 5: U+200D is written as it is, write `{{zwj}}` instead"
+        );
+        assert_eq!(
+            found[2].to_string(),
+            "line 4, column 3: U+2061 is written as it is, write `\\u{2061}` instead"
+        );
+    }
+}
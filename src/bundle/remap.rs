@@ -0,0 +1,197 @@
+//! Layouts on ergonomic bases
+//!
+//! Users of Dvorak or Colemak ask for a layout of their language on the same
+//! arrangement. The keys of such a base are a permutation of the US keys, so
+//! a layout made for QWERTY can be moved onto it key by key: whatever the
+//! layout types where US has `p` is typed where the base has `p`. Every
+//! output stays in the layout, only the positions change.
+
+use crate::{
+    models::{DesktopModes, IsoKey, Layout},
+    DesktopKeyMap,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use strum_macros::{Display, EnumIter, EnumString};
+
+/// The US layout the bases are permutations of
+const US: &str = r#"` 1 2 3 4 5 6 7 8 9 0 - =
+                      q w e r t y u i o p [ ]
+                      a s d f g h j k l ; ' \
+                    \ z x c v b n m , . /"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Serialize, Deserialize, Display, EnumString, EnumIter)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum ErgonomicBase {
+    Dvorak,
+    Colemak,
+    Workman,
+}
+
+impl ErgonomicBase {
+    /// The `default` mode of the base's US layout
+    fn keys(self) -> &'static str {
+        match self {
+            ErgonomicBase::Dvorak => {
+                r#"` 1 2 3 4 5 6 7 8 9 0 [ ]
+                     ' , . p y f g c r l / =
+                     a o e u i d h t n s - \
+                   \ ; q j k x b m w v z"#
+            }
+            ErgonomicBase::Colemak => {
+                r#"` 1 2 3 4 5 6 7 8 9 0 - =
+                     q w f p g j l u y ; [ ]
+                     a r s t d h n e i o ' \
+                   \ z x c v b k m , . /"#
+            }
+            ErgonomicBase::Workman => {
+                r#"` 1 2 3 4 5 6 7 8 9 0 - =
+                     q d r w b j f u p ; [ ]
+                     a s h t g y n e o i ' \
+                   \ z x m c v k l , . /"#
+            }
+        }
+    }
+
+    /// The name of the base, for display names
+    pub fn title(self) -> &'static str {
+        match self {
+            ErgonomicBase::Dvorak => "Dvorak",
+            ErgonomicBase::Colemak => "Colemak",
+            ErgonomicBase::Workman => "Workman",
+        }
+    }
+
+    /// For every key of the base, the key of the US layout that types the
+    /// same character
+    pub fn positions(self) -> BTreeMap<IsoKey, IsoKey> {
+        let us: DesktopKeyMap = US.parse().expect("valid US layout");
+        let base: DesktopKeyMap = self.keys().parse().expect("valid base layout");
+
+        base.0
+            .iter()
+            .map(|(&key, value)| {
+                // Keys that type the same as on US stay, which also keeps the
+                // two backslash keys apart
+                if us.0.get(&key) == Some(value) {
+                    return (key, key);
+                }
+                let from =
+                    us.0.iter()
+                        .find(|(_, us_value)| *us_value == value)
+                        .map(|(&from, _)| from)
+                        .expect("bases are permutations of the US layout");
+                (key, from)
+            })
+            .collect()
+    }
+}
+
+fn remap_modes(modes: &DesktopModes, positions: &BTreeMap<IsoKey, IsoKey>) -> DesktopModes {
+    DesktopModes(
+        modes
+            .0
+            .iter()
+            .map(|(name, key_map)| {
+                let remapped = key_map
+                    .0
+                    .keys()
+                    .filter_map(|&key| {
+                        let from = positions.get(&key).copied().unwrap_or(key);
+                        Some((key, key_map.0.get(&from)?.clone()))
+                    })
+                    .collect::<BTreeMap<_, _>>();
+                (name.clone(), DesktopKeyMap(remapped))
+            })
+            .collect(),
+    )
+}
+
+impl Layout {
+    /// The layout with the keys of its desktop modes moved onto `base`, and
+    /// the name of the base after its display names. Mobile modes are kept as
+    /// they are.
+    pub fn remapped(&self, base: ErgonomicBase) -> Layout {
+        let positions = base.positions();
+        let mut layout = self.without_derived_modes();
+
+        for modes in [
+            &mut layout.modes.desktop,
+            &mut layout.modes.win,
+            &mut layout.modes.mac,
+            &mut layout.modes.chrome,
+            &mut layout.modes.x11,
+        ] {
+            if let Some(modes) = modes.as_mut() {
+                *modes = remap_modes(modes, &positions);
+            }
+        }
+
+        for name in layout.display_names.values_mut() {
+            *name = format!("{} ({})", name, base.title());
+        }
+        layout
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use strum::IntoEnumIterator;
+
+    #[test]
+    fn bases_are_permutations_of_us() {
+        for base in ErgonomicBase::iter() {
+            let positions = base.positions();
+            let mut from = positions.values().collect::<Vec<_>>();
+            from.sort();
+            from.dedup();
+            assert_eq!(from.len(), positions.len(), "{}", base);
+        }
+    }
+
+    #[test]
+    fn outputs_move_with_their_us_key() {
+        let layout: Layout = serde_yaml::from_str(
+            r#"
+displayNames:
+  se: Davvisámegiella
+modes:
+  desktop:
+    default: |
+      § 1 2 3 4 5 6 7 8 9 0 + ´
+        á š e r t y u i o p å ŋ
+        a s d f g h j k l ö æ đ
+      ž z č c v b n m , . -
+"#,
+        )
+        .unwrap();
+        let remapped = layout.remapped(ErgonomicBase::Dvorak);
+        let default = &remapped.modes.desktop.as_ref().unwrap().0["default"];
+
+        // Dvorak has `'` where US has `q`, typed by US `'` which is `æ` here
+        assert_eq!(default.get_string(IsoKey::D01).as_deref(), Some("æ"));
+        assert_eq!(default.get_string(IsoKey::C02).as_deref(), Some("o"));
+        assert_eq!(default.get_string(IsoKey::E01).as_deref(), Some("1"));
+        assert_eq!(
+            remapped.display_names["se"],
+            "Davvisámegiella (Dvorak)".to_string()
+        );
+
+        let mut before = layout.modes.desktop.as_ref().unwrap().0["default"]
+            .0
+            .values()
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>();
+        let mut after = default
+            .0
+            .values()
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>();
+        before.sort();
+        after.sort();
+        assert_eq!(before, after);
+    }
+}
@@ -0,0 +1,193 @@
+//! How keyboards look on printed reference cards
+//!
+//! The `render` section of `project.yaml` themes the keyboards drawn by the
+//! `svg` target, and the `layout.html` that puts them together for printing,
+//! so that an organisation can hand out reference cards in its own fonts and
+//! colors. Colors are checked as the project is parsed and the other values
+//! when it is loaded. The generator fills in what the section leaves out with
+//! the look the cards have without it, see `render_theme` in
+//! `kbdgen.gen.base`.
+
+use super::theme::Color;
+use derive_collect_docs::CollectDocs;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use strum_macros::{Display, EnumString};
+
+/// The colors of a reference card
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+#[derive(Serialize, Deserialize, CollectDocs)]
+#[serde(deny_unknown_fields)]
+pub struct RenderColors {
+    /// Behind the keys. Defaults to none, the paper.
+    #[example(yaml, r##"background: "#f4f4f4""##)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background: Option<Color>,
+
+    /// Keys that type characters
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<Color>,
+
+    /// Keys like shift, tab and return
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "functionKey")]
+    pub function_key: Option<Color>,
+
+    /// The outlines of keys
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub border: Option<Color>,
+
+    /// The ISO position of each key, like `E01`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "isoLabel")]
+    pub iso_label: Option<Color>,
+
+    /// What the `default` and `shift` modes type
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<Color>,
+
+    /// What the `alt` and `alt+shift` modes type
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alt: Option<Color>,
+
+    /// What the `caps` and `caps+shift` modes type
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub caps: Option<Color>,
+
+    /// What the `caps+alt` and `caps+alt+shift` modes type
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "capsAlt")]
+    pub caps_alt: Option<Color>,
+
+    /// Dead keys in every mode, instead of a color of their own for each
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "deadKey")]
+    pub dead_key: Option<Color>,
+}
+
+/// How dead keys stand out from the other keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Display, EnumString, Serialize, Deserialize)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum DeadKeyMarker {
+    /// In bold, in the dead key color of their mode
+    Bold,
+    /// Underlined, in the color of their mode
+    Underline,
+    /// Like any other key
+    None,
+}
+
+/// What the keys are labelled with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Display, EnumString, Serialize, Deserialize)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum KeyLabels {
+    /// What the keys type, with their ISO positions in a corner
+    Both,
+    /// Only what the keys type
+    Outputs,
+    /// Only the ISO positions, for a card that explains them
+    Iso,
+}
+
+/// How the keyboards of the `svg` target look
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+#[derive(Serialize, Deserialize, CollectDocs)]
+#[serde(deny_unknown_fields)]
+pub struct Render {
+    /// The font family of the labels, as in CSS. Defaults to `sans-serif`.
+    #[example(yaml, r#"fontFamily: "'Noto Sans', sans-serif""#)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "fontFamily")]
+    pub font_family: Option<String>,
+
+    /// The colors of the cards. Colors left out keep their defaults.
+    #[example(
+        yaml,
+        r##"
+        colors:
+          key: "#ffffff"
+          alt: "#0b5394"
+          deadKey: "#cc0000"
+    "##
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub colors: Option<RenderColors>,
+
+    /// `bold`, `underline` or `none`. Defaults to `bold`.
+    #[example(yaml, "deadKeyMarker: underline")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "deadKeyMarker")]
+    pub dead_key_marker: Option<DeadKeyMarker>,
+
+    /// `both`, `outputs` or `iso`. Defaults to `both`.
+    #[example(yaml, "keyLabels: outputs")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "keyLabels")]
+    pub key_labels: Option<KeyLabels>,
+
+    /// The order `layout.html` prints the layouts in, by name. Layouts left
+    /// out follow in order of their file names.
+    #[example(yaml, "layoutOrder: [se-NO, se-SE, se-FI]")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "layoutOrder")]
+    pub layout_order: Option<Vec<String>>,
+}
+
+impl Render {
+    /// What is wrong with the section, beyond what its types check
+    pub fn errors(&self) -> Vec<String> {
+        let mut errors = vec![];
+        if let Some(font) = &self.font_family {
+            if font.trim().is_empty() {
+                errors.push("fontFamily is empty".to_string());
+            }
+        }
+        if let Some(order) = &self.layout_order {
+            let mut seen = BTreeSet::new();
+            for name in order {
+                if !seen.insert(name) {
+                    errors.push(format!("layoutOrder has `{}` more than once", name));
+                }
+            }
+        }
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_sections_are_validated() {
+        let render: Render = serde_yaml::from_str(
+            r##"
+fontFamily: " "
+colors:
+  deadKey: "#C00"
+deadKeyMarker: underline
+keyLabels: iso
+layoutOrder: [se-NO, se-SE, se-NO]
+"##,
+        )
+        .unwrap();
+        let colors = render.colors.clone().unwrap();
+        assert_eq!(colors.dead_key.unwrap().to_string(), "#cc0000");
+        assert_eq!(render.dead_key_marker, Some(DeadKeyMarker::Underline));
+        assert_eq!(render.key_labels, Some(KeyLabels::Iso));
+        assert_eq!(
+            render.errors(),
+            vec![
+                "fontFamily is empty".to_string(),
+                "layoutOrder has `se-NO` more than once".to_string()
+            ]
+        );
+
+        assert!(serde_yaml::from_str::<Render>("deadKeyMarker: circle").is_err());
+        assert!(serde_yaml::from_str::<Render>("colors:\n  key: white").is_err());
+    }
+}
@@ -0,0 +1,179 @@
+//! Which modes of a layout the keyboards of a target are generated from
+//!
+//! A layout can give modes for a target, like `win`, and for `desktop` or
+//! `mobile`, which the targets without modes of their own use. Every
+//! generator and check goes through `Modes::resolve`, so that they all agree
+//! on which modes a target gets.
+
+use crate::{
+    models::{DesktopModes, MobileModes, Modes},
+    Target,
+};
+
+/// The modes that the keyboards of a target are generated from, and the
+/// target they are defined for, see `Modes::resolve`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResolvedModes<'a> {
+    Desktop(Target, &'a DesktopModes),
+    Mobile(Target, &'a MobileModes),
+}
+
+impl<'a> ResolvedModes<'a> {
+    /// The target the modes are defined for, the target resolved or its
+    /// fallback
+    pub fn target(self) -> Target {
+        match self {
+            ResolvedModes::Desktop(target, _) | ResolvedModes::Mobile(target, _) => target,
+        }
+    }
+
+    pub fn desktop(self) -> Option<&'a DesktopModes> {
+        match self {
+            ResolvedModes::Desktop(_, modes) => Some(modes),
+            ResolvedModes::Mobile(..) => None,
+        }
+    }
+
+    pub fn mobile(self) -> Option<&'a MobileModes> {
+        match self {
+            ResolvedModes::Mobile(_, modes) => Some(modes),
+            ResolvedModes::Desktop(..) => None,
+        }
+    }
+}
+
+impl Modes {
+    /// The modes that the keyboards of `target` are generated from: the
+    /// target's own if the layout defines any, otherwise those of its
+    /// fallback, `desktop` for desktop targets and `mobile` for mobile ones
+    ///
+    /// The modes of a target replace those of its fallback as a whole, so a
+    /// `win` with only a `default` mode does not get the `shift` mode of
+    /// `desktop`. Desktop targets never get mobile modes, nor the other way
+    /// around.
+    pub fn resolve(&self, target: Target) -> Option<ResolvedModes<'_>> {
+        target.fallback_chain().find_map(|target| {
+            if let Some(modes) = self.desktop_for(target) {
+                Some(ResolvedModes::Desktop(target, modes))
+            } else {
+                Some(ResolvedModes::Mobile(target, self.mobile_for(target)?))
+            }
+        })
+    }
+
+    /// The desktop modes of `target`, see `resolve`
+    pub fn resolve_desktop(&self, target: Target) -> Option<(Target, &DesktopModes)> {
+        let resolved = self.resolve(target)?;
+        Some((resolved.target(), resolved.desktop()?))
+    }
+
+    /// The mobile modes of `target`, see `resolve`
+    pub fn resolve_mobile(&self, target: Target) -> Option<(Target, &MobileModes)> {
+        let resolved = self.resolve(target)?;
+        Some((resolved.target(), resolved.mobile()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Layout;
+    use strum::IntoEnumIterator;
+
+    fn modes(yaml: &str) -> Modes {
+        let layout: Layout = serde_yaml::from_str(yaml).unwrap();
+        layout.modes
+    }
+
+    #[test]
+    fn targets_fall_back_to_desktop_and_mobile_as_a_whole() {
+        let modes = modes(
+            r#"
+displayNames:
+  en: Test
+modes:
+  desktop:
+    default: |
+      § 1 2 3 4 5 6 7 8 9 0 + ´
+    shift: |
+      ° ! " # $ % & / ( ) = ? `
+  win:
+    default: |
+      § 1 2 3 4 5 6 7 8 9 0 + ´
+  mobile:
+    default: |
+      q w e r t y u i o p å
+    shift: |
+      Q W E R T Y U I O P Å
+"#,
+        );
+
+        let win = modes.resolve(Target::Win).unwrap();
+        assert_eq!(win.target(), Target::Win);
+        assert_eq!(win.desktop().unwrap().0.len(), 1);
+        assert_eq!(modes.resolve_mobile(Target::Win), None);
+
+        for target in [Target::Mac, Target::Chrome, Target::X11, Target::Desktop] {
+            assert_eq!(
+                modes.resolve_desktop(target),
+                Some((Target::Desktop, modes.desktop.as_ref().unwrap()))
+            );
+        }
+        for target in [Target::Android, Target::Ios, Target::Mobile] {
+            assert_eq!(
+                modes.resolve(target),
+                Some(ResolvedModes::Mobile(
+                    Target::Mobile,
+                    modes.mobile.as_ref().unwrap()
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn desktop_and_mobile_targets_do_not_cross_over() {
+        let modes = modes(
+            r#"
+displayNames:
+  en: Test
+modes:
+  ios:
+    default: |
+      q w e r t y u i o p å
+    shift: |
+      Q W E R T Y U I O P Å
+"#,
+        );
+
+        let resolved = Target::iter()
+            .filter_map(|target| Some((target, modes.resolve(target)?.target())))
+            .collect::<Vec<_>>();
+        assert_eq!(resolved, vec![(Target::Ios, Target::Ios)]);
+    }
+
+    #[test]
+    fn ipad_modes_are_kept_apart_and_checked_for_ios() {
+        let yaml = r#"
+displayNames:
+  en: Test
+modes:
+  ipad-9in:
+    default: |
+      q w e r t y u i o p å
+    alt: |
+      1 2 3 4 5 6 7 8 9 0 +
+  ipad-12in:
+    mobile-6rows: |
+      1 2 3 4 5 6 7 8 9 0 +
+"#;
+        let layout: Layout = serde_yaml::from_str(yaml).unwrap();
+        let names = layout
+            .modes
+            .ipad()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["ipad-9in", "ipad-12in"]);
+        assert_eq!(layout.modes.resolve(Target::Ios), None);
+        assert!(layout.mode_name_errors().is_empty());
+    }
+}
@@ -55,7 +55,12 @@ impl<S: BuildHasher> Save for HashMap<String, Layout, S> {
         })?;
 
         for (name, data) in self {
-            write_yaml(&path.join(&name).with_extension("yaml"), data)?;
+            // Written as part of the layout they are a variant of
+            if super::variants::is_variant(self, name) {
+                continue;
+            }
+            let data = data.without_derived_modes();
+            write_yaml(path.join(format!("{}.yaml", name)), &data)?;
         }
 
         Ok(())
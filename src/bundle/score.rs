@@ -0,0 +1,231 @@
+//! Completeness scores for layouts
+//!
+//! Bundles with dozens of languages need a way to see which layouts are
+//! mature and which are stubs. A score has a part for each aspect of a
+//! layout, each between 0 and 1, and parts that do not apply to the layout
+//! are left out of its total.
+
+use crate::{models::Layout, KeyValue, MobileMode, ModeName, Modifier, Target};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use strum::IntoEnumIterator;
+
+const DESKTOP_TARGETS: [Target; 4] = [Target::Win, Target::Mac, Target::Chrome, Target::X11];
+const MOBILE_TARGETS: [Target; 2] = [Target::Ios, Target::Android];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Score {
+    /// The share of the orthography's characters the layout types, if the
+    /// orthography is known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub orthography: Option<f64>,
+
+    /// The share of the targets the layout has modes for
+    pub targets: f64,
+
+    /// The share of desktop targets with a caps lock mode, if the layout has
+    /// desktop modes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub caps: Option<f64>,
+
+    /// The share of letters on the mobile keyboards with long-press
+    /// alternatives, if the layout has mobile modes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub longpress: Option<f64>,
+}
+
+impl Score {
+    /// The mean of the parts that apply
+    pub fn total(&self) -> f64 {
+        let parts = [
+            self.orthography,
+            Some(self.targets),
+            self.caps,
+            self.longpress,
+        ];
+        let parts = parts.iter().flatten().collect::<Vec<_>>();
+        parts.iter().copied().sum::<f64>() / parts.len() as f64
+    }
+
+    /// The total as a whole percentage
+    pub fn percent(&self) -> u32 {
+        (self.total() * 100.0).round() as u32
+    }
+}
+
+fn share(count: usize, total: usize) -> Option<f64> {
+    if total == 0 {
+        None
+    } else {
+        Some(count as f64 / total as f64)
+    }
+}
+
+impl Layout {
+    /// Everything a single key press, a dead key and a key, or a long-press
+    /// alternative types in any mode
+    fn typeable_outputs(&self) -> BTreeSet<String> {
+        let mut outputs = BTreeSet::new();
+        self.for_each_key(|key| {
+            if let KeyValue::Symbol(value) = key {
+                outputs.insert(value.clone());
+            }
+        });
+
+        let dead_keys = self
+            .transforms
+            .iter()
+            .flat_map(|transforms| transforms.keys());
+        let transformed = dead_keys
+            .flat_map(|dead_key| {
+                outputs
+                    .iter()
+                    .filter_map(move |input| self.transform(dead_key, input))
+            })
+            .collect::<Vec<_>>();
+        outputs.extend(transformed);
+
+        let longpress = self.longpress.iter().flatten();
+        for alternatives in longpress.map(|(_, alternatives)| alternatives) {
+            outputs.extend(alternatives.split_ascii_whitespace().map(str::to_string));
+        }
+        outputs
+    }
+
+    /// The targets of `targets` the layout has modes for, by the target its
+    /// modes come from
+    fn resolved_targets(&self, targets: &[Target]) -> BTreeSet<Target> {
+        targets
+            .iter()
+            .filter_map(|&target| Some(self.modes.resolve(target)?.target()))
+            .collect()
+    }
+
+    fn caps_share(&self) -> Option<f64> {
+        let targets = DESKTOP_TARGETS
+            .iter()
+            .filter_map(|&target| self.modes.resolve_desktop(target))
+            .collect::<Vec<_>>();
+        let with_caps = targets
+            .iter()
+            .filter(|(_, modes)| {
+                modes.0.keys().any(|name| {
+                    matches!(name.parse::<ModeName>(), Ok(name)
+                        if name.required.contains(Modifier::Caps) || name.optional.contains(Modifier::Caps))
+                })
+            })
+            .count();
+        share(with_caps, targets.len())
+    }
+
+    fn longpress_share(&self) -> Option<f64> {
+        let mut letters = BTreeSet::new();
+        for target in self.resolved_targets(&MOBILE_TARGETS) {
+            let key_map = self
+                .modes
+                .mobile_for(target)
+                .and_then(|modes| modes.0.get(&MobileMode::Default));
+            for key in key_map
+                .iter()
+                .flat_map(|key_map| key_map.0.iter().flatten())
+            {
+                if let KeyValue::Symbol(value) = key {
+                    if value.chars().all(char::is_alphabetic) {
+                        letters.insert(value.as_str());
+                    }
+                }
+            }
+        }
+
+        let longpress = self.longpress.as_ref();
+        let with_longpress = letters
+            .iter()
+            .filter(|&&letter| {
+                longpress
+                    .and_then(|longpress| longpress.get(letter))
+                    .map(|alternatives| alternatives.split_ascii_whitespace().next().is_some())
+                    .unwrap_or(false)
+            })
+            .count();
+        share(with_longpress, letters.len())
+    }
+
+    /// How complete the layout is, with `orthography` listing the characters
+    /// (or sequences) the language is written with, if known
+    pub fn score(&self, orthography: Option<&[String]>) -> Score {
+        let orthography = orthography.and_then(|characters| {
+            let outputs = self.typeable_outputs();
+            let typed = characters.iter().filter(|c| outputs.contains(*c)).count();
+            share(typed, characters.len())
+        });
+
+        let targets = Target::iter()
+            .filter(|target| target.fallback().is_some())
+            .collect::<Vec<_>>();
+        let with_modes = targets
+            .iter()
+            .filter(|&&target| self.modes.resolve(target).is_some())
+            .count();
+
+        Score {
+            orthography,
+            targets: share(with_modes, targets.len()).unwrap_or(0.0),
+            caps: self.caps_share(),
+            longpress: self.longpress_share(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parts_that_do_not_apply_are_left_out() {
+        let layout: Layout = serde_yaml::from_str(
+            r#"
+displayNames:
+  en: Test
+modes:
+  desktop:
+    default: |
+      ´ 1 2 3 4 5 6 7 8 9 0 + '
+        q w e r t y u i o p å ¨
+        a s d f g h j k l ö ä '
+      < z x c v b n m , . -
+    caps: |
+      ´ 1 2 3 4 5 6 7 8 9 0 + '
+        Q W E R T Y U I O P Å ¨
+        A S D F G H J K L Ö Ä '
+      > Z X C V B N M , . -
+  mac:
+    default: |
+      ´ 1 2 3 4 5 6 7 8 9 0 + '
+        q w e r t y u i o p å ¨
+        a s d f g h j k l ö ä '
+      < z x c v b n m , . -
+deadKeys:
+  desktop:
+    default: ["´"]
+transforms:
+  ´:
+    a: á
+"#,
+        )
+        .unwrap();
+
+        let orthography = ["a", "á", "ŋ", "ö"]
+            .iter()
+            .map(|c| c.to_string())
+            .collect::<Vec<_>>();
+        let score = layout.score(Some(&orthography));
+        assert_eq!(score.orthography, Some(0.75));
+        assert_eq!(score.targets, 4.0 / 6.0);
+        // Windows, ChromeOS and X11 share the desktop modes with caps
+        assert_eq!(score.caps, Some(0.75));
+        assert_eq!(score.longpress, None);
+        assert_eq!(score.percent(), 72);
+
+        assert_eq!(layout.score(None).orthography, None);
+    }
+}
@@ -0,0 +1,192 @@
+//! Keys that take the letters of common shortcuts away from them
+//!
+//! Shortcuts like cmd+C on macOS and ctrl+C elsewhere are found by what the
+//! key types in the shortcut mode. A layout whose `cmd` or `ctrl` mode moves
+//! or drops one of those letters breaks the shortcut for its users.
+
+use crate::{
+    bundle::Targets,
+    models::{IsoKey, Layout},
+    DesktopKeyMap, KeyValue, Modifier, ModifierSet, Target,
+};
+
+const MAC_SHORTCUTS: &[&str] = &["a", "c", "f", "q", "s", "v", "w", "x", "z"];
+const CTRL_SHORTCUTS: &[&str] = &["a", "c", "f", "s", "v", "w", "x", "y", "z"];
+
+/// A shortcut that a layout moves or drops in a target's shortcut mode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShortcutConflict {
+    pub target: Target,
+    /// The modifier shortcuts are typed with
+    pub modifier: Modifier,
+    /// The letter of the shortcut
+    pub shortcut: String,
+    /// The key that types the letter without modifiers, and what it types
+    /// in the shortcut mode instead, if anything
+    pub key: Option<(IsoKey, Option<String>)>,
+    /// The key that types the letter in the shortcut mode, if any
+    pub moved_to: Option<IsoKey>,
+}
+
+impl std::fmt::Display for ShortcutConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let shortcut = format!("{}+{}", self.modifier, self.shortcut.to_uppercase());
+        if let Some((key, output)) = &self.key {
+            match output {
+                Some(output) => write!(
+                    f,
+                    "{} key {:?} types `{}` with {} instead of `{}`",
+                    self.target, key, output, self.modifier, self.shortcut
+                )?,
+                None => write!(
+                    f,
+                    "{} key {:?} types nothing with {} instead of `{}`",
+                    self.target, key, self.modifier, self.shortcut
+                )?,
+            }
+            f.write_str(", ")?;
+        } else {
+            write!(f, "{}: ", self.target)?;
+        }
+        match self.moved_to {
+            Some(moved_to) => write!(f, "{} is on {:?}", shortcut, moved_to),
+            None => write!(f, "{} cannot be typed", shortcut),
+        }
+    }
+}
+
+/// The modifier and letters of the target's shortcuts
+fn shortcuts(target: Target, targets: &Targets) -> (Modifier, Vec<String>) {
+    let (modifier, configured, defaults) = match target {
+        Target::Mac => (
+            Modifier::Cmd,
+            targets.mac_os.as_ref().and_then(|t| t.shortcuts.clone()),
+            MAC_SHORTCUTS,
+        ),
+        _ => (
+            Modifier::Ctrl,
+            targets.windows.as_ref().and_then(|t| t.shortcuts.clone()),
+            CTRL_SHORTCUTS,
+        ),
+    };
+    let letters = configured.unwrap_or_else(|| defaults.iter().map(|s| s.to_string()).collect());
+    (modifier, letters)
+}
+
+fn symbol(value: &KeyValue) -> Option<&str> {
+    match value {
+        KeyValue::Symbol(value) => Some(value),
+        _ => None,
+    }
+}
+
+impl Layout {
+    /// Shortcuts that the layout's `cmd` mode on macOS, or `ctrl` mode on
+    /// Windows, moves to another key or leaves out
+    ///
+    /// Layouts without a shortcut mode for a target are left to the system's
+    /// defaults and not checked.
+    pub fn shortcut_conflicts(&self, targets: &Targets) -> Vec<ShortcutConflict> {
+        let mut conflicts = vec![];
+
+        for target in [Target::Mac, Target::Win] {
+            let modes = match self.modes.resolve_desktop(target) {
+                Some((_, modes)) => modes,
+                None => continue,
+            };
+            let (modifier, letters) = shortcuts(target, targets);
+            let shortcut_mode = std::iter::once(modifier).collect::<ModifierSet>();
+            let (default, shortcut_map) = match (
+                modes.get_mode(&ModifierSet::default()),
+                modes.get_mode(&shortcut_mode),
+            ) {
+                (Some(default), Some(shortcut_map)) => (default, shortcut_map),
+                _ => continue,
+            };
+
+            let find = |map: &DesktopKeyMap, letter: &str| {
+                map.0
+                    .iter()
+                    .find(|(_, value)| symbol(value) == Some(letter))
+                    .map(|(&key, _)| key)
+            };
+            for letter in letters {
+                let key = find(default, &letter);
+                let moved_to = find(shortcut_map, &letter);
+                if key.is_some() && key == moved_to {
+                    continue;
+                }
+                conflicts.push(ShortcutConflict {
+                    target,
+                    modifier,
+                    key: key.map(|key| {
+                        let output = shortcut_map.0.get(&key).and_then(symbol);
+                        (key, output.map(String::from))
+                    }),
+                    shortcut: letter,
+                    moved_to,
+                });
+            }
+        }
+
+        conflicts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A layout typing `q w e` on D01 to D03, and the given keys there with
+    /// `cmd`
+    fn layout(cmd: &str) -> Layout {
+        let row = |keys: &str| format!("|\n      {}\n      {}\n", r"\u{0} ".repeat(13), keys);
+        serde_yaml::from_str(&format!(
+            "displayNames:\n  en: Test\nmodes:\n  mac:\n    default: {}    cmd: {}",
+            row("q w e"),
+            row(cmd)
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn moved_and_missing_shortcuts_conflict() {
+        let mut targets = Targets::default();
+        assert!(!layout("q w e").shortcut_conflicts(&targets).is_empty());
+
+        targets.mac_os = serde_yaml::from_str(
+            "version: 1.0.0\nbuild: 1\npackageId: a.b\nbundleName: Test\nshortcuts: [q, w]\n",
+        )
+        .unwrap();
+        assert!(layout("q w e").shortcut_conflicts(&targets).is_empty());
+
+        let conflicts = layout("w q e").shortcut_conflicts(&targets);
+        assert_eq!(
+            conflicts
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec![
+                "mac key D01 types `w` with cmd instead of `q`, cmd+Q is on D02",
+                "mac key D02 types `q` with cmd instead of `w`, cmd+W is on D01",
+            ]
+        );
+
+        let conflicts = layout(r"q \u{0} e").shortcut_conflicts(&targets);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].key, Some((IsoKey::D02, None)));
+        assert_eq!(
+            conflicts[0].to_string(),
+            "mac key D02 types nothing with cmd instead of `w`, cmd+W cannot be typed"
+        );
+    }
+
+    #[test]
+    fn layouts_without_a_shortcut_mode_are_not_checked() {
+        let layout: Layout = serde_yaml::from_str(
+            "displayNames:\n  en: Test\nmodes:\n  win:\n    default: |\n      q w e\n",
+        )
+        .unwrap();
+        assert!(layout.shortcut_conflicts(&Targets::default()).is_empty());
+    }
+}
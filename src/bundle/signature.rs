@@ -0,0 +1,382 @@
+//! Detached ed25519 signatures of bundles
+//!
+//! A bundle is signed over its manifest: the SHA-256 of every file in it, by
+//! path relative to the bundle, one `<hash>  <path>` line per file in order of
+//! path. Hidden files, like `.git`, and the signature itself are left out,
+//! so that a checkout and a copy of the bundle have the same manifest.
+//!
+//! The signature is written to `signature.json` in the bundle, with the
+//! public key that made it and the hashes it was made over, so that a
+//! verification failure can say which files changed. Keys are stored as hex,
+//! the secret key in its own file and the public key next to it with a
+//! `.pub` extension. On Unix the secret key is only readable by its owner, and
+//! a secret key that others can read is refused.
+
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature, Signer, Verifier};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    convert::TryFrom,
+    fmt, fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// The name of the signature file in a signed bundle
+pub const SIGNATURE_FILE: &str = "signature.json";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SignatureFile {
+    public_key: String,
+    signature: String,
+    /// The SHA-256 of each file, by path
+    files: BTreeMap<String, String>,
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    text.trim()
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [_, _] => u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The files of the bundle, as paths relative to it separated by `/`
+fn list_files(dir: &Path, prefix: &str, files: &mut Vec<String>) -> Result<(), Error> {
+    let read_error = |source| Error::CannotRead {
+        path: dir.to_path_buf(),
+        source,
+    };
+    for entry in fs::read_dir(dir).map_err(read_error)? {
+        let entry = entry.map_err(read_error)?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let relative = format!("{}{}", prefix, name);
+        if name.starts_with('.') || relative == SIGNATURE_FILE {
+            continue;
+        }
+        if entry.path().is_dir() {
+            list_files(&entry.path(), &format!("{}/", relative), files)?;
+        } else {
+            files.push(relative);
+        }
+    }
+    Ok(())
+}
+
+/// The SHA-256 of each file of the bundle, by path
+fn hashes(bundle_path: &Path) -> Result<BTreeMap<String, String>, Error> {
+    let mut files = vec![];
+    list_files(bundle_path, "", &mut files)?;
+    files
+        .into_iter()
+        .map(|file| {
+            let path = bundle_path.join(&file);
+            let contents = fs::read(&path).map_err(|source| Error::CannotRead { path, source })?;
+            Ok((file, to_hex(&Sha256::digest(&contents))))
+        })
+        .collect()
+}
+
+fn manifest_of(hashes: &BTreeMap<String, String>) -> String {
+    hashes
+        .iter()
+        .map(|(path, hash)| format!("{}  {}\n", hash, path))
+        .collect()
+}
+
+/// The canonical serialization of the bundle that signatures are made over
+pub fn manifest(bundle_path: &Path) -> Result<String, Error> {
+    Ok(manifest_of(&hashes(bundle_path)?))
+}
+
+/// A key to sign bundles with
+pub struct SigningKey(Keypair);
+
+impl SigningKey {
+    pub fn generate() -> SigningKey {
+        SigningKey(Keypair::generate(&mut rand::rngs::OsRng))
+    }
+
+    /// Reads a secret key written by `SigningKey::write`
+    pub fn read(path: &Path) -> Result<SigningKey, Error> {
+        check_private(path)?;
+        let text = fs::read_to_string(path).map_err(|source| Error::CannotRead {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let secret = from_hex(&text)
+            .and_then(|bytes| SecretKey::from_bytes(&bytes).ok())
+            .ok_or_else(|| Error::InvalidKey {
+                path: path.to_path_buf(),
+            })?;
+        let public = PublicKey::from(&secret);
+        Ok(SigningKey(Keypair { secret, public }))
+    }
+
+    /// Writes the secret key to `path`, and the public key to `path` with a
+    /// `.pub` extension
+    ///
+    /// The secret key is created with mode 0600, and is not written over if
+    /// it exists.
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options
+            .open(path)
+            .and_then(|mut file| writeln!(file, "{}", to_hex(self.0.secret.as_bytes())))
+            .map_err(|source| Error::CannotWrite {
+                path: path.to_path_buf(),
+                source,
+            })?;
+
+        let path = public_key_path(path);
+        fs::write(&path, format!("{}\n", self.verifying_key()))
+            .map_err(|source| Error::CannotWrite { path, source })
+    }
+
+    pub fn verifying_key(&self) -> VerifyingKey {
+        VerifyingKey(self.0.public)
+    }
+
+    /// Signs the bundle, writing `signature.json` into it
+    pub fn sign_bundle(&self, bundle_path: &Path) -> Result<(), Error> {
+        let files = hashes(bundle_path)?;
+        let signature = self.0.sign(manifest_of(&files).as_bytes());
+        let file = SignatureFile {
+            public_key: self.verifying_key().to_string(),
+            signature: to_hex(&signature.to_bytes()),
+            files,
+        };
+        let path = bundle_path.join(SIGNATURE_FILE);
+        let json = serde_json::to_string_pretty(&file).expect("signatures serialize");
+        fs::write(&path, json + "\n").map_err(|source| Error::CannotWrite { path, source })
+    }
+}
+
+/// Fails if anyone but the owner can read or write the secret key at `path`
+#[cfg(unix)]
+fn check_private(path: &Path) -> Result<(), Error> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = fs::metadata(path)
+        .map_err(|source| Error::CannotRead {
+            path: path.to_path_buf(),
+            source,
+        })?
+        .permissions()
+        .mode();
+    if mode & 0o077 != 0 {
+        return Err(Error::KeyNotPrivate {
+            path: path.to_path_buf(),
+            mode: mode & 0o777,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_private(_path: &Path) -> Result<(), Error> {
+    Ok(())
+}
+
+/// The path of the public key for the secret key at `path`
+pub fn public_key_path(path: &Path) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".pub");
+    path.into()
+}
+
+/// A public key that bundles are trusted to be signed with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerifyingKey(PublicKey);
+
+impl VerifyingKey {
+    /// Reads a public key written by `SigningKey::write`
+    pub fn read(path: &Path) -> Result<VerifyingKey, Error> {
+        let text = fs::read_to_string(path).map_err(|source| Error::CannotRead {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        from_hex(&text)
+            .and_then(|bytes| PublicKey::from_bytes(&bytes).ok())
+            .map(VerifyingKey)
+            .ok_or_else(|| Error::InvalidKey {
+                path: path.to_path_buf(),
+            })
+    }
+
+    /// Checks that the bundle is signed with this key, and has not changed
+    /// since
+    pub fn verify_bundle(&self, bundle_path: &Path) -> Result<(), Error> {
+        let path = bundle_path.join(SIGNATURE_FILE);
+        if !path.is_file() {
+            return Err(Error::Unsigned {
+                path: bundle_path.to_path_buf(),
+            });
+        }
+        let text = fs::read_to_string(&path).map_err(|source| Error::CannotRead {
+            path: path.clone(),
+            source,
+        })?;
+        let file: SignatureFile = serde_json::from_str(&text)
+            .map_err(|_| Error::InvalidSignature { path: path.clone() })?;
+
+        if file.public_key != self.to_string() {
+            return Err(Error::UntrustedKey {
+                key: file.public_key,
+            });
+        }
+
+        let files = hashes(bundle_path)?;
+        let mut changed: Vec<String> = files
+            .iter()
+            .filter(|(path, hash)| file.files.get(*path) != Some(hash))
+            .map(|(path, _)| path.clone())
+            .collect();
+        changed.extend(
+            file.files
+                .keys()
+                .filter(|path| !files.contains_key(*path))
+                .cloned(),
+        );
+        if !changed.is_empty() {
+            changed.sort();
+            return Err(Error::Changed { files: changed });
+        }
+
+        let signature = from_hex(&file.signature)
+            .and_then(|bytes| Signature::try_from(bytes.as_slice()).ok())
+            .ok_or(Error::InvalidSignature { path })?;
+        self.0
+            .verify(manifest_of(&files).as_bytes(), &signature)
+            .map_err(|_| Error::BadSignature)
+    }
+}
+
+impl fmt::Display for VerifyingKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&to_hex(self.0.as_bytes()))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not read `{}`", path.display())]
+    CannotRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Could not write `{}`", path.display())]
+    CannotWrite {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("`{}` is not an ed25519 key in hex", path.display())]
+    InvalidKey { path: PathBuf },
+
+    #[error(
+        "`{}` can be read by others (mode {:o}), run `chmod 600` on it",
+        path.display(),
+        mode
+    )]
+    KeyNotPrivate { path: PathBuf, mode: u32 },
+
+    #[error("`{}` is not signed", path.display())]
+    Unsigned { path: PathBuf },
+
+    #[error("`{}` is not a valid bundle signature", path.display())]
+    InvalidSignature { path: PathBuf },
+
+    #[error("The bundle is signed with `{}`, which is not the trusted key", key)]
+    UntrustedKey { key: String },
+
+    #[error("The bundle has changed since it was signed: {}", files.join(", "))]
+    Changed { files: Vec<String> },
+
+    #[error("The signature does not match the bundle")]
+    BadSignature,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signed_bundles_verify_until_changed() {
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = dir.path().join("test.kbdgen");
+        fs::create_dir_all(bundle.join("layouts")).unwrap();
+        fs::create_dir_all(bundle.join(".git")).unwrap();
+        fs::write(bundle.join("project.yaml"), "locales: {}\n").unwrap();
+        fs::write(bundle.join("layouts/se.yaml"), "displayNames: {}\n").unwrap();
+        fs::write(bundle.join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+
+        let key = SigningKey::generate();
+        let key_path = dir.path().join("signing.key");
+        key.write(&key_path).unwrap();
+        let key = SigningKey::read(&key_path).unwrap();
+        let trusted = VerifyingKey::read(&public_key_path(&key_path)).unwrap();
+        assert_eq!(key.verifying_key(), trusted);
+
+        assert!(matches!(
+            trusted.verify_bundle(&bundle),
+            Err(Error::Unsigned { .. })
+        ));
+        key.sign_bundle(&bundle).unwrap();
+        trusted.verify_bundle(&bundle).unwrap();
+        assert_eq!(
+            manifest(&bundle).unwrap().lines().count(),
+            2,
+            "hidden files and the signature are left out"
+        );
+
+        let other = SigningKey::generate().verifying_key();
+        assert!(matches!(
+            other.verify_bundle(&bundle),
+            Err(Error::UntrustedKey { .. })
+        ));
+
+        fs::write(bundle.join("layouts/se.yaml"), "displayNames: { en: Se }\n").unwrap();
+        match trusted.verify_bundle(&bundle) {
+            Err(Error::Changed { files }) => assert_eq!(files, vec!["layouts/se.yaml"]),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn secret_keys_are_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let key_path = dir.path().join("signing.key");
+        SigningKey::generate().write(&key_path).unwrap();
+        let mode = fs::metadata(&key_path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        SigningKey::read(&key_path).unwrap();
+
+        assert!(matches!(
+            SigningKey::generate().write(&key_path),
+            Err(Error::CannotWrite { .. })
+        ));
+
+        fs::set_permissions(&key_path, fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(matches!(
+            SigningKey::read(&key_path),
+            Err(Error::KeyNotPrivate { mode: 0o644, .. })
+        ));
+    }
+}
@@ -0,0 +1,128 @@
+//! Typing simulation: what a sequence of key presses produces on a target
+
+use crate::{
+    bundle::modifiers::find_mode,
+    models::{IsoKey, Layout},
+    KeyValue, ModifierError, ModifierSet, Target,
+};
+use std::{fmt, str::FromStr};
+
+/// A single key press, with the modifiers held down
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyPress {
+    pub key: IsoKey,
+    pub mode: ModifierSet,
+}
+
+impl KeyPress {
+    /// Key presses separated by whitespace, like `E12 shift+C01`
+    pub fn parse_sequence(s: &str) -> Result<Vec<KeyPress>, KeyPressError> {
+        s.split_whitespace().map(str::parse).collect()
+    }
+}
+
+/// The key's ISO name, after the modifiers held down and a `+`, like
+/// `shift+E05` or `E05`
+impl FromStr for KeyPress {
+    type Err = KeyPressError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (mode, key) = match s.rfind('+') {
+            Some(index) => (&s[..index], &s[index + 1..]),
+            None => ("default", s),
+        };
+        Ok(KeyPress {
+            key: key.parse().map_err(|_| KeyPressError::UnknownKey {
+                key: key.to_string(),
+            })?,
+            mode: mode
+                .parse()
+                .map_err(|source| KeyPressError::InvalidMode { source })?,
+        })
+    }
+}
+
+impl fmt::Display for KeyPress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.mode.is_default() {
+            write!(f, "{}", self.key)
+        } else {
+            write!(f, "{}+{}", self.mode, self.key)
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum KeyPressError {
+    #[error("Unknown key `{key}`, expected an ISO key like `E05`")]
+    UnknownKey { key: String },
+
+    #[error("Invalid mode")]
+    InvalidMode { source: ModifierError },
+}
+
+impl Layout {
+    /// The text typed by `presses` on `target`
+    ///
+    /// Dead keys are combined with the following key through the layout's
+    /// transforms. A dead key followed by space, or by a key it has no
+    /// transform for, produces its terminator, with the key after it as most
+    /// platforms do. A trailing dead key produces its terminator. A dead key
+    /// that continues a dead key chain with transforms, like `^´`, waits for
+    /// the next key along with the dead keys before it.
+    pub fn simulate(&self, target: Target, presses: &[KeyPress]) -> String {
+        let mut output = String::new();
+        let mut pending: Option<String> = None;
+
+        for press in presses {
+            let value = match self.key(target, &press.mode, press.key) {
+                Some(KeyValue::Symbol(value)) => value.as_str(),
+                _ => continue,
+            };
+
+            if let Some(dead_key) = pending.take() {
+                let chain = format!("{}{}", dead_key, value);
+                if self.is_dead_key(target, &press.mode, value)
+                    && matches!(&self.transforms, Some(transforms) if transforms.contains_key(&chain))
+                {
+                    pending = Some(chain);
+                    continue;
+                }
+                match self.transform(&dead_key, value) {
+                    Some(_) if value == " " => {
+                        output.push_str(&self.dead_key_terminator(&dead_key))
+                    }
+                    Some(transformed) => output.push_str(&transformed),
+                    None => {
+                        output.push_str(&self.dead_key_terminator(&dead_key));
+                        output.push_str(value);
+                    }
+                }
+            } else if self.is_dead_key(target, &press.mode, value) {
+                pending = Some(value.to_string());
+            } else {
+                output.push_str(value);
+            }
+        }
+
+        if let Some(dead_key) = pending {
+            output.push_str(&self.dead_key_terminator(&dead_key));
+        }
+        output
+    }
+
+    /// Whether `value` is a dead key in `mode` on `target`, using the same
+    /// target fallback as `Layout::key`
+    pub fn is_dead_key(&self, target: Target, mode: &ModifierSet, value: &str) -> bool {
+        let target = match self.modes.resolve(target) {
+            Some(resolved) => resolved.target(),
+            None => return false,
+        };
+
+        self.dead_keys
+            .as_ref()
+            .and_then(|dead_keys| find_mode(dead_keys.get(&target)?, mode))
+            .map(|(_, keys)| keys.iter().any(|key| key == value))
+            .unwrap_or(false)
+    }
+}
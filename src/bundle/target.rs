@@ -0,0 +1,44 @@
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumIter, EnumString};
+
+/// Platforms that layouts can be generated for
+///
+/// `Desktop` and `Mobile` are not platforms of their own, but hold the modes
+/// that the concrete desktop and mobile targets fall back to.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(EnumString, Display, EnumIter)]
+#[derive(Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    Win,
+    Mac,
+    Ios,
+    Android,
+    Chrome,
+    X11,
+    Desktop,
+    Mobile,
+}
+
+impl Target {
+    /// Whether this target uses `MobileModes` (as opposed to `DesktopModes`)
+    pub fn is_mobile(self) -> bool {
+        matches!(self, Target::Ios | Target::Android | Target::Mobile)
+    }
+
+    /// The target whose modes are used when a layout defines none for this
+    /// target
+    pub fn fallback(self) -> Option<Target> {
+        match self {
+            Target::Win | Target::Mac | Target::Chrome | Target::X11 => Some(Target::Desktop),
+            Target::Ios | Target::Android => Some(Target::Mobile),
+            Target::Desktop | Target::Mobile => None,
+        }
+    }
+
+    /// This target followed by its fallbacks, in order of precedence
+    pub fn fallback_chain(self) -> impl Iterator<Item = Target> {
+        std::iter::successors(Some(self), |target| target.fallback())
+    }
+}
@@ -0,0 +1,205 @@
+//! How the keys of mobile keyboards look
+//!
+//! `targets.android.theme` and `targets.ios.theme` of a layout share one
+//! schema, so that a layout looks the same on both. Colors are checked as the
+//! layout is parsed and the other values when it is loaded. The generators
+//! fill in what a theme leaves out with the same defaults for both apps, see
+//! `mobile_theme` in `kbdgen.gen.base`.
+
+use crate::{models::Layout, Target};
+use derive_collect_docs::CollectDocs;
+use serde::{Deserialize, Serialize};
+use std::{convert::TryFrom, fmt};
+
+/// The largest `keyCornerRadius` that still leaves a key a rectangle
+pub const MAX_KEY_CORNER_RADIUS: f64 = 24.0;
+
+/// A color as `#rgb`, `#rrggbb` or `#rrggbbaa`, kept as lowercase
+/// `#rrggbb` or `#rrggbbaa`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Color(String);
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Color, String> {
+        let invalid = || {
+            format!(
+                "`{}` is not a color, expected `#rgb`, `#rrggbb` or `#rrggbbaa`",
+                value
+            )
+        };
+        let hex = value.strip_prefix('#').ok_or_else(invalid)?;
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let hex = match hex.len() {
+            3 => hex.chars().flat_map(|c| vec![c, c]).collect(),
+            6 | 8 => hex.to_string(),
+            _ => return Err(invalid()),
+        };
+        Ok(Color(format!("#{}", hex.to_ascii_lowercase())))
+    }
+}
+
+impl From<Color> for String {
+    fn from(color: Color) -> String {
+        color.0
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// The colors of a keyboard. Colors left out keep the default of the app.
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+#[derive(Serialize, Deserialize, CollectDocs)]
+#[serde(deny_unknown_fields)]
+pub struct ThemeColors {
+    /// Behind the keys
+    #[example(yaml, r##"background: "#d1d5db""##)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background: Option<Color>,
+
+    /// Keys that type characters
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<Color>,
+
+    /// Keys like shift, backspace and return
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "specialKey")]
+    pub special_key: Option<Color>,
+
+    /// A key while it is held down
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "keyPressed")]
+    pub key_pressed: Option<Color>,
+
+    /// The labels of keys
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<Color>,
+
+    /// The labels of long-press popups and of the keys' hints
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "secondaryText")]
+    pub secondary_text: Option<Color>,
+}
+
+/// How the keys of a mobile keyboard look
+///
+/// Used by `targets.android.theme` and `targets.ios.theme` of a layout.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Default, CollectDocs)]
+#[serde(deny_unknown_fields)]
+pub struct MobileTheme {
+    /// The colors in light mode
+    #[example(
+        yaml,
+        r##"
+        colors:
+          key: "#ffffff"
+          text: "#000000"
+    "##
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub colors: Option<ThemeColors>,
+
+    /// The colors in dark mode. Colors left out are the app's dark defaults,
+    /// not the light `colors`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "darkColors")]
+    pub dark_colors: Option<ThemeColors>,
+
+    /// How round the corners of keys are, in points on iOS and dp on
+    /// Android, from 0 to 24. Defaults to 5.
+    #[example(yaml, "keyCornerRadius: 8")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "keyCornerRadius")]
+    pub key_corner_radius: Option<f64>,
+
+    /// The font family of key labels, instead of the system font
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub font: Option<String>,
+}
+
+impl MobileTheme {
+    /// What is wrong with the theme, beyond what its types check
+    pub fn errors(&self) -> Vec<String> {
+        let mut errors = vec![];
+        if let Some(radius) = self.key_corner_radius {
+            if !(0.0..=MAX_KEY_CORNER_RADIUS).contains(&radius) {
+                errors.push(format!(
+                    "keyCornerRadius {} is not between 0 and {}",
+                    radius, MAX_KEY_CORNER_RADIUS
+                ));
+            }
+        }
+        if let Some(font) = &self.font {
+            if font.trim().is_empty() {
+                errors.push("font is empty".to_string());
+            }
+        }
+        errors
+    }
+}
+
+impl Layout {
+    /// The theme of a mobile target, if the layout has one
+    pub fn theme(&self, target: Target) -> Option<&MobileTheme> {
+        let targets = self.targets.as_ref()?;
+        match target {
+            Target::Android => targets.android.as_ref()?.theme.as_ref(),
+            Target::Ios => targets.ios.as_ref()?.theme.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// What is wrong with the themes of the layout, by target
+    pub fn theme_errors(&self) -> Vec<(Target, String)> {
+        [Target::Android, Target::Ios]
+            .iter()
+            .filter_map(|&target| Some((target, self.theme(target)?)))
+            .flat_map(|(target, theme)| {
+                theme.errors().into_iter().map(move |error| (target, error))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(theme: &str) -> Result<Layout, serde_yaml::Error> {
+        serde_yaml::from_str(&format!(
+            "displayNames:\n  en: Test\nmodes: {{}}\ntargets:\n  ios:\n    theme:\n{}",
+            theme
+        ))
+    }
+
+    #[test]
+    fn themes_are_validated() {
+        let themed =
+            layout("      colors:\n        key: '#FFF'\n      keyCornerRadius: 30\n").unwrap();
+        let colors = themed.theme(Target::Ios).unwrap().colors.clone().unwrap();
+        assert_eq!(colors.key.unwrap().to_string(), "#ffffff");
+        assert!(themed.theme(Target::Android).is_none());
+        assert_eq!(
+            themed.theme_errors(),
+            vec![(
+                Target::Ios,
+                "keyCornerRadius 30 is not between 0 and 24".to_string()
+            )]
+        );
+
+        let error = layout("      colors:\n        key: white\n")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("`white` is not a color"), "{}", error);
+        let error = layout("      colours: {}\n").unwrap_err().to_string();
+        assert!(error.contains("unknown field `colours`"), "{}", error);
+    }
+}
@@ -0,0 +1,529 @@
+//! Transform patterns and dead key chains
+//!
+//! Instead of listing every pair, the input of a transform can be a character
+//! class like `[aeiou]` or `[\p{Lu}]`, with `$0` in the output standing for
+//! the character that was typed. Targets need explicit pairs, so patterns are
+//! expanded against the characters the layout's modes can produce.
+//!
+//! A transform can also be for several dead keys typed in turn, like `^´`
+//! for a circumflex followed by an acute. Only macOS can express these
+//! chains; other targets leave them out.
+//!
+//! Whether a transform in progress is shown depends on the target, and only
+//! some targets follow the layout's `transformPartial`.
+//!
+//! With `symmetricTransforms`, a transform of a combining mark and a base
+//! character also applies with the two typed the other way round.
+
+use crate::{
+    models::{Layout, TransformPartial},
+    KeyValue, Target,
+};
+use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+/// Whether a transform input is a character class rather than a literal
+pub fn is_pattern(input: &str) -> bool {
+    input.len() > 2 && input.starts_with('[') && input.ends_with(']')
+}
+
+fn compile(pattern: &str) -> Result<Regex, regex::Error> {
+    Regex::new(&format!("^{}$", pattern))
+}
+
+/// The output for `input` matched by a pattern, composed to NFC so that a
+/// combining mark in the output joins the typed character
+fn pattern_output(output: &str, input: &str) -> String {
+    output.replace("$0", input).nfc().collect()
+}
+
+impl TransformPartial {
+    /// What `target` does for layouts that do not set `transformPartial`
+    pub fn default_for(target: Target) -> TransformPartial {
+        match target {
+            Target::Mac | Target::Ios | Target::Android | Target::Mobile => TransformPartial::Show,
+            Target::Win | Target::Chrome | Target::X11 | Target::Desktop => TransformPartial::Hide,
+        }
+    }
+
+    /// Whether `target` can do either, rather than always what it does by
+    /// default
+    pub fn is_configurable_for(target: Target) -> bool {
+        !matches!(target, Target::Mac | Target::Win | Target::X11)
+    }
+}
+
+/// Whether `s` is only combining marks, like U+0301 COMBINING ACUTE ACCENT
+fn is_marks(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(is_combining_mark)
+}
+
+/// Whether a transform of `first` and `second` can be swapped for
+/// `symmetricTransforms`
+fn is_swappable(first: &str, second: &str) -> bool {
+    second != " " && !is_pattern(second) && is_marks(first) != is_marks(second)
+}
+
+/// `input` split into dead keys, trying longer dead keys first
+fn split_dead_keys<'a>(input: &'a str, dead_keys: &BTreeSet<&str>) -> Option<Vec<&'a str>> {
+    if input.is_empty() {
+        return Some(vec![]);
+    }
+    let mut candidates: Vec<usize> = dead_keys
+        .iter()
+        .filter(|key| !key.is_empty() && input.starts_with(**key))
+        .map(|key| key.len())
+        .collect();
+    candidates.sort_unstable_by(|a, b| b.cmp(a));
+    candidates.into_iter().find_map(|len| {
+        let mut keys = vec![&input[..len]];
+        keys.extend(split_dead_keys(&input[len..], dead_keys)?);
+        Some(keys)
+    })
+}
+
+impl Layout {
+    pub fn has_transform_patterns(&self) -> bool {
+        self.transforms
+            .iter()
+            .flat_map(|transforms| transforms.values())
+            .flat_map(|transform| transform.keys())
+            .any(|input| is_pattern(input))
+    }
+
+    /// The dead keys typed in turn to reach the transform for `dead_keys`,
+    /// if it is a chain of several, like `^´`
+    pub fn dead_key_chain<'a>(&self, dead_keys: &'a str) -> Option<Vec<&'a str>> {
+        let all = self.all_dead_keys();
+        if all.contains(dead_keys) {
+            return None;
+        }
+        split_dead_keys(dead_keys, &all).filter(|keys| keys.len() > 1)
+    }
+
+    /// The transforms that are for dead key chains
+    pub fn dead_key_chains(&self) -> Vec<&str> {
+        self.transforms
+            .iter()
+            .flat_map(|transforms| transforms.keys())
+            .map(String::as_str)
+            .filter(|dead_keys| self.dead_key_chain(dead_keys).is_some())
+            .collect()
+    }
+
+    /// Desktop targets with modes in this layout that will not get its dead
+    /// key chains, as they cannot express them
+    pub fn targets_without_dead_key_chains(&self) -> Vec<Target> {
+        if self.dead_key_chains().is_empty() {
+            return vec![];
+        }
+        use strum::IntoEnumIterator;
+        Target::iter()
+            .filter(|&target| {
+                !target.is_mobile() && !matches!(target, Target::Desktop | Target::Mac)
+            })
+            .filter(|&target| self.modes.resolve(target).is_some())
+            .collect()
+    }
+
+    /// What `target` does with a transform in progress
+    pub fn transform_partial(&self, target: Target) -> TransformPartial {
+        match self.transform_partial {
+            Some(partial) if TransformPartial::is_configurable_for(target) => partial,
+            _ => TransformPartial::default_for(target),
+        }
+    }
+
+    /// Targets with modes in this layout that cannot do what its
+    /// `transformPartial` asks for
+    pub fn targets_ignoring_transform_partial(&self) -> Vec<Target> {
+        let partial = match self.transform_partial {
+            Some(partial) => partial,
+            None => return vec![],
+        };
+        use strum::IntoEnumIterator;
+        Target::iter()
+            .filter(|&target| self.transform_partial(target) != partial)
+            .filter(|&target| self.modes.resolve(target).is_some())
+            .collect()
+    }
+
+    /// The output of typing `input` after `dead_key`
+    ///
+    /// Explicit pairs take precedence over patterns. When several patterns
+    /// match, the one that sorts first wins. Invalid patterns never match.
+    pub fn transform(&self, dead_key: &str, input: &str) -> Option<String> {
+        let transforms = self.transforms.as_ref()?;
+        let transform = match transforms.get(dead_key) {
+            Some(transform) => transform,
+            None => return self.swapped_transform(input, dead_key),
+        };
+        if let Some(output) = transform.get(input) {
+            return Some(output.clone());
+        }
+
+        transform
+            .iter()
+            .filter(|(pattern, _)| is_pattern(pattern))
+            .find(|(pattern, _)| matches!(compile(pattern), Ok(re) if re.is_match(input)))
+            .map(|(_, output)| pattern_output(output, input))
+            .or_else(|| self.swapped_transform(input, dead_key))
+    }
+
+    /// The output of the transform of `first` and `second`, when typing them
+    /// the other way round is generated by `symmetricTransforms`
+    fn swapped_transform(&self, first: &str, second: &str) -> Option<String> {
+        if !self.symmetric_transforms.unwrap_or(false) || !is_swappable(first, second) {
+            return None;
+        }
+        self.transforms.as_ref()?.get(first)?.get(second).cloned()
+    }
+
+    /// Transforms written out that type something else than the ones
+    /// `symmetricTransforms` generates for the same keys
+    pub fn symmetric_transform_conflicts(&self) -> Vec<TransformConflict> {
+        if !self.symmetric_transforms.unwrap_or(false) {
+            return vec![];
+        }
+        let transforms = match self.transforms.as_ref() {
+            Some(transforms) => transforms,
+            None => return vec![],
+        };
+
+        let nfc = |s: &str| s.nfc().collect::<String>();
+        let mut conflicts = vec![];
+        for (first, transform) in transforms.iter().filter(|(first, _)| is_marks(first)) {
+            for (second, output) in transform.iter().filter(|(s, _)| is_swappable(first, s)) {
+                let written = match transforms.get(second).and_then(|t| t.get(first.as_str())) {
+                    Some(written) => written,
+                    None => continue,
+                };
+                let outputs = vec![nfc(written), nfc(output)]
+                    .into_iter()
+                    .collect::<BTreeSet<_>>();
+                if outputs.len() > 1 {
+                    conflicts.push(TransformConflict {
+                        dead_key: nfc(second),
+                        input: nfc(first),
+                        outputs: outputs.into_iter().collect(),
+                    });
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// Characters a single key press can produce in any mode
+    fn typeable_characters(&self) -> BTreeSet<String> {
+        let mut characters = BTreeSet::new();
+        self.for_each_key(|key| {
+            if let KeyValue::Symbol(value) = key {
+                if value.chars().count() == 1 {
+                    characters.insert(value.clone());
+                }
+            }
+        });
+        characters
+    }
+
+    /// Replaces the patterns in all transforms with the explicit pairs they
+    /// match
+    pub fn expand_transform_patterns(&mut self) -> Result<(), Error> {
+        if !self.has_transform_patterns() {
+            return Ok(());
+        }
+        let characters = self.typeable_characters();
+
+        for (dead_key, transform) in self.transforms.iter_mut().flatten() {
+            let (patterns, explicit): (BTreeMap<_, _>, BTreeMap<_, _>) = std::mem::take(transform)
+                .into_iter()
+                .partition(|(input, _)| is_pattern(input));
+
+            let mut expanded = BTreeMap::new();
+            for (pattern, output) in patterns {
+                let re = compile(&pattern).map_err(|source| Error::InvalidPattern {
+                    dead_key: dead_key.clone(),
+                    pattern: pattern.clone(),
+                    source,
+                })?;
+                for input in characters.iter().filter(|c| re.is_match(c)) {
+                    expanded
+                        .entry(input.clone())
+                        .or_insert_with(|| pattern_output(&output, input));
+                }
+            }
+
+            expanded.extend(explicit);
+            *transform = expanded;
+        }
+
+        Ok(())
+    }
+
+    /// Removes transforms that are canonically equivalent to another one,
+    /// like `é` written precomposed and decomposed, and type the same output.
+    /// Returns the number of transforms removed.
+    ///
+    /// Of each set of equivalent transforms, the one written in NFC is kept,
+    /// or else the one that sorts first. Nothing is removed if any set types
+    /// different outputs.
+    pub fn dedup_transforms(&mut self) -> Result<usize, Vec<TransformConflict>> {
+        let transforms = match self.transforms.as_mut() {
+            Some(transforms) => transforms,
+            None => return Ok(0),
+        };
+
+        let nfc = |s: &str| s.nfc().collect::<String>();
+        let mut equivalent: BTreeMap<(String, String), Vec<(&String, &String)>> = BTreeMap::new();
+        for (dead_key, transform) in transforms.iter() {
+            for input in transform.keys() {
+                equivalent
+                    .entry((nfc(dead_key), nfc(input)))
+                    .or_default()
+                    .push((dead_key, input));
+            }
+        }
+
+        let mut conflicts = vec![];
+        let mut duplicates = vec![];
+        for ((dead_key, input), spellings) in equivalent {
+            if spellings.len() < 2 {
+                continue;
+            }
+            let outputs = spellings
+                .iter()
+                .map(|(d, i)| nfc(&transforms[*d][*i]))
+                .collect::<BTreeSet<_>>();
+            if outputs.len() > 1 {
+                conflicts.push(TransformConflict {
+                    dead_key,
+                    input,
+                    outputs: outputs.into_iter().collect(),
+                });
+                continue;
+            }
+            let keep = spellings
+                .iter()
+                .position(|(d, i)| **d == dead_key && **i == input)
+                .unwrap_or(0);
+            duplicates.extend(
+                spellings
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(n, _)| *n != keep)
+                    .map(|(_, (d, i))| (d.clone(), i.clone())),
+            );
+        }
+
+        if !conflicts.is_empty() {
+            return Err(conflicts);
+        }
+
+        for (dead_key, input) in &duplicates {
+            if let Some(transform) = transforms.get_mut(dead_key) {
+                transform.remove(input);
+                if transform.is_empty() {
+                    transforms.remove(dead_key);
+                }
+            }
+        }
+        Ok(duplicates.len())
+    }
+}
+
+/// Canonically equivalent transforms that type different outputs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransformConflict {
+    /// The dead key and input in NFC
+    pub dead_key: String,
+    pub input: String,
+    /// The outputs in NFC
+    pub outputs: Vec<String>,
+}
+
+impl std::fmt::Display for TransformConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "`{}` + `{}` types ", self.dead_key, self.input)?;
+        let outputs = self
+            .outputs
+            .iter()
+            .map(|output| format!("`{}`", output))
+            .collect::<Vec<_>>();
+        write!(f, "{}", outputs.join(" or "))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Invalid transform pattern `{}` for dead key `{}`", pattern, dead_key)]
+    InvalidPattern {
+        dead_key: String,
+        pattern: String,
+        source: regex::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(transforms: &str) -> Layout {
+        serde_yaml::from_str(&format!(
+            r#"
+displayNames:
+  en: Test
+modes:
+  desktop:
+    default: |
+      ´ 1 2
+        q w e r t y u i o p
+        a s d f g h j k l
+    shift: |
+      ´ ! "
+        Q W E R T Y U I O P
+        A S D F G H J K L
+transforms:
+{}
+"#,
+            transforms
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn patterns_expand_to_typeable_characters() {
+        let mut layout = layout(
+            r#"
+  ´:
+    "[aeiouAEIOU]": "$0́"
+    "[\\p{Lu}]": "$0'"
+    e: ê
+"#,
+        );
+        assert!(layout.has_transform_patterns());
+        assert_eq!(layout.transform("´", "o").as_deref(), Some("ó"));
+        assert_eq!(layout.transform("´", "S").as_deref(), Some("S'"));
+
+        layout.expand_transform_patterns().unwrap();
+        assert!(!layout.has_transform_patterns());
+        let transform = &layout.transforms.as_ref().unwrap()["´"];
+        assert_eq!(transform["a"], "á");
+        assert_eq!(transform["Q"], "Q'");
+        // The pattern that sorts first wins, explicit pairs win over patterns
+        assert_eq!(transform["U"], "U'");
+        assert_eq!(transform["e"], "ê");
+        // Only characters the layout can type are expanded
+        assert!(!transform.contains_key("z"));
+        assert_eq!(transform.len(), 19 + 5);
+    }
+
+    #[test]
+    fn invalid_patterns_are_reported() {
+        let mut layout = layout(
+            r#"
+  ´:
+    "[\\p{Nope}]": x
+"#,
+        );
+        assert_eq!(layout.transform("´", "a"), None);
+        match layout.expand_transform_patterns() {
+            Err(Error::InvalidPattern { pattern, .. }) => assert_eq!(pattern, "[\\p{Nope}]"),
+            x => panic!("unexpected {:?}", x),
+        }
+    }
+
+    #[test]
+    fn equivalent_transforms_are_deduplicated() {
+        let mut layout = layout(
+            r#"
+  ´:
+    é: é
+    "é": "é"
+    a: á
+  "́":
+    a: á
+"#,
+        );
+        assert_eq!(layout.dedup_transforms(), Ok(1));
+        let transforms = layout.transforms.as_ref().unwrap();
+        assert_eq!(transforms["´"].len(), 2);
+        assert_eq!(transforms["´"]["é"], "é");
+        // Different dead keys are not equivalent
+        assert_eq!(transforms["\u{301}"]["a"], "á");
+    }
+
+    #[test]
+    fn equivalent_transforms_with_different_outputs_conflict() {
+        let mut layout = layout(
+            r#"
+  ´:
+    é: é
+    "é": ê
+"#,
+        );
+        let conflicts = layout.dedup_transforms().unwrap_err();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].input, "é");
+        assert_eq!(conflicts[0].outputs, vec!["é".to_string(), "ê".to_string()]);
+        assert_eq!(layout.transforms.as_ref().unwrap()["´"].len(), 2);
+    }
+
+    #[test]
+    fn transform_partial_is_only_honoured_where_targets_can() {
+        let mut layout = layout("  ´:\n    a: á\n");
+        assert_eq!(
+            layout.transform_partial(Target::Chrome),
+            TransformPartial::Hide
+        );
+        assert_eq!(
+            layout.transform_partial(Target::Android),
+            TransformPartial::Show
+        );
+        assert!(layout.targets_ignoring_transform_partial().is_empty());
+
+        layout.transform_partial = Some(TransformPartial::Show);
+        assert_eq!(
+            layout.transform_partial(Target::Chrome),
+            TransformPartial::Show
+        );
+        assert_eq!(
+            layout.transform_partial(Target::Win),
+            TransformPartial::Hide
+        );
+        assert_eq!(
+            layout.targets_ignoring_transform_partial(),
+            vec![Target::Win, Target::X11]
+        );
+    }
+
+    #[test]
+    fn symmetric_transforms_swap_marks_and_base_characters() {
+        let mut layout = layout(
+            r#"
+  "\u0301":
+    a: á
+    e: é
+    " ": ´
+  e:
+    "\u0301": è
+"#,
+        );
+        assert_eq!(layout.transform("a", "\u{301}"), None);
+        assert!(layout.symmetric_transform_conflicts().is_empty());
+
+        layout.symmetric_transforms = Some(true);
+        assert_eq!(layout.transform("a", "\u{301}").as_deref(), Some("á"));
+        // Transforms written out win
+        assert_eq!(layout.transform("e", "\u{301}").as_deref(), Some("è"));
+        assert_eq!(layout.transform(" ", "\u{301}"), None);
+        assert_eq!(
+            layout.symmetric_transform_conflicts(),
+            vec![TransformConflict {
+                dead_key: "e".into(),
+                input: "\u{301}".into(),
+                outputs: vec!["è".into(), "é".into()],
+            }]
+        );
+    }
+}
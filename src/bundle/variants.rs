@@ -0,0 +1,136 @@
+//! Secondary layouts defined in the file of another layout
+//!
+//! A locale can have more than one layout, like a standard and a phonetic
+//! one. Rather than a second file repeating display names and targets, the
+//! further layouts are `variants` of the first and become layouts of their own
+//! when the bundle is loaded.
+
+use crate::models::{Layout, Modes};
+use std::collections::HashMap;
+
+/// The name of the layout generated for `variant` of the layout `name`
+pub fn variant_name(name: &str, variant: &str) -> String {
+    format!("{}-{}", name, variant)
+}
+
+impl Modes {
+    /// These modes, with the targets `other` has modes for replaced by
+    /// those
+    pub fn overlay(&self, other: &Modes) -> Modes {
+        fn pick<T: Clone>(base: &Option<T>, other: &Option<T>) -> Option<T> {
+            other.as_ref().or(base.as_ref()).cloned()
+        }
+
+        Modes {
+            win: pick(&self.win, &other.win),
+            mac: pick(&self.mac, &other.mac),
+            ios: pick(&self.ios, &other.ios),
+            android: pick(&self.android, &other.android),
+            ipad_9in: pick(&self.ipad_9in, &other.ipad_9in),
+            ipad_12in: pick(&self.ipad_12in, &other.ipad_12in),
+            chrome: pick(&self.chrome, &other.chrome),
+            x11: pick(&self.x11, &other.x11),
+            desktop: pick(&self.desktop, &other.desktop),
+            mobile: pick(&self.mobile, &other.mobile),
+        }
+    }
+}
+
+impl Layout {
+    /// The layouts defined by `variants`, by variant name
+    pub fn variant_layouts(&self) -> Vec<(String, Layout)> {
+        self.variants
+            .iter()
+            .flatten()
+            .map(|(name, variant)| {
+                let mut layout = self.clone();
+                layout.variants = None;
+                layout.modes = self.modes.overlay(&variant.modes);
+                if let Some(display_names) = &variant.display_names {
+                    layout.display_names = display_names.clone();
+                }
+                if variant.dead_keys.is_some() {
+                    layout.dead_keys = variant.dead_keys.clone();
+                }
+                if variant.longpress.is_some() {
+                    layout.longpress = variant.longpress.clone();
+                }
+                if variant.transforms.is_some() {
+                    layout.transforms = variant.transforms.clone();
+                }
+                (name.clone(), layout)
+            })
+            .collect()
+    }
+}
+
+/// Whether the layout `name` was generated from the variants of another
+/// layout, rather than read from a file of its own
+pub(crate) fn is_variant<S>(layouts: &HashMap<String, Layout, S>, name: &str) -> bool
+where
+    S: std::hash::BuildHasher,
+{
+    layouts.iter().any(|(base, layout)| {
+        layout
+            .variants
+            .iter()
+            .flat_map(|variants| variants.keys())
+            .any(|variant| variant_name(base, variant) == name)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{models::IsoKey, KeyValue, Target};
+
+    #[test]
+    fn variants_share_everything_they_do_not_set() {
+        let layout: Layout = serde_yaml::from_str(
+            r#"
+displayNames:
+  en: Test
+modes:
+  desktop:
+    default: |
+      § 1 2 3 4 5 6 7 8 9 0 + ´
+        q w e r t y u i o p å ¨
+  mobile:
+    default: |
+      q w e r t y u i o p å
+deadKeys:
+  desktop:
+    default: ['´']
+variants:
+  phonetic:
+    modes:
+      desktop:
+        default: |
+          § 1 2 3 4 5 6 7 8 9 0 + ´
+            я в е р т ы у и о п ш щ
+"#,
+        )
+        .unwrap();
+
+        let variants = layout.variant_layouts();
+        assert_eq!(variants.len(), 1);
+        let (name, phonetic) = &variants[0];
+        assert_eq!(variant_name("ru", name), "ru-phonetic");
+        assert_eq!(phonetic.variants, None);
+        assert_eq!(phonetic.display_names, layout.display_names);
+        assert_eq!(phonetic.dead_keys, layout.dead_keys);
+
+        let default = "default".parse().unwrap();
+        assert_eq!(
+            phonetic.key(Target::Win, &default, IsoKey::D01),
+            Some(&KeyValue::Symbol("я".into()))
+        );
+        assert_eq!(phonetic.modes.mobile, layout.modes.mobile);
+
+        let mut layouts = HashMap::new();
+        layouts.insert("ru".to_string(), layout.clone());
+        layouts.insert("ru-phonetic".to_string(), phonetic.clone());
+        assert!(is_variant(&layouts, "ru-phonetic"));
+        assert!(!is_variant(&layouts, "ru"));
+    }
+}
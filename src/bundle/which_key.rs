@@ -0,0 +1,243 @@
+//! Reverse lookup: which keys type a given output on a target
+//!
+//! Support requests are usually of the form "how do I type ŧ on Windows?".
+//! Every single key press, and every dead key followed by another press, is
+//! typed with `Layout::simulate`, so the answers go through the transforms
+//! exactly as typing would. On mobile targets, long-press alternatives are
+//! searched as well.
+
+use crate::{
+    models::{IsoKey, Layout},
+    KeyPress, KeyValue, ModeName, ModifierSet, ResolvedModes, Target,
+};
+use std::{collections::BTreeMap, fmt};
+use strum::IntoEnumIterator;
+
+/// A way of typing an output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Way {
+    /// Pressing the keys in order
+    Keys(Vec<KeyPress>),
+    /// Long-pressing the key and picking the output from its alternatives
+    Longpress(KeyPress),
+}
+
+impl fmt::Display for Way {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Way::Keys(presses) => {
+                let presses = presses.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+                write!(f, "{}", presses.join(" "))
+            }
+            Way::Longpress(press) => write!(f, "long-press {}", press),
+        }
+    }
+}
+
+impl Layout {
+    /// The modes of `target`, as held modifiers, fewest modifiers first.
+    /// Symbol pages of mobile targets are left out as they are not reached
+    /// by holding modifiers.
+    fn held_modes(&self, target: Target) -> Vec<ModifierSet> {
+        let mut modes = match self.modes.resolve(target) {
+            Some(ResolvedModes::Desktop(_, desktop)) => desktop
+                .0
+                .keys()
+                .filter_map(|name| name.parse::<ModeName>().ok())
+                .map(|name| name.required)
+                .collect::<Vec<_>>(),
+            Some(ResolvedModes::Mobile(_, mobile)) => mobile
+                .0
+                .keys()
+                .filter_map(|mode| mode.name().parse::<ModifierSet>().ok())
+                .collect(),
+            None => return vec![],
+        };
+        modes.sort_by_key(|mode| (mode.iter().count(), mode.to_string()));
+        modes.dedup();
+        modes
+    }
+
+    /// Every single key press that types something on `target`, with what it
+    /// types
+    fn presses(&self, target: Target, modes: &[ModifierSet]) -> Vec<(KeyPress, &str)> {
+        self.held_modes(target)
+            .into_iter()
+            .filter(|mode| modes.is_empty() || modes.contains(mode))
+            .flat_map(|mode| {
+                IsoKey::iter().filter_map(move |key| match self.key(target, &mode, key) {
+                    Some(KeyValue::Symbol(value)) => Some((
+                        KeyPress {
+                            key,
+                            mode: mode.clone(),
+                        },
+                        value.as_str(),
+                    )),
+                    _ => None,
+                })
+            })
+            .collect()
+    }
+
+    /// The ways of typing `output` on `target`, shortest first
+    ///
+    /// Only presses in `modes` are used, or in any mode if `modes` is empty.
+    /// Sequences are at most a dead key and one other press long.
+    pub fn which_key(&self, target: Target, output: &str, modes: &[ModifierSet]) -> Vec<Way> {
+        let presses = self.presses(target, modes);
+        let mut ways = vec![];
+
+        for (press, _) in &presses {
+            let sequence = vec![press.clone()];
+            if self.simulate(target, &sequence) == output {
+                ways.push(Way::Keys(sequence));
+            }
+        }
+
+        for (dead, value) in &presses {
+            if !self.is_dead_key(target, &dead.mode, value) {
+                continue;
+            }
+            for (press, _) in &presses {
+                let sequence = vec![dead.clone(), press.clone()];
+                if self.simulate(target, &sequence) == output {
+                    ways.push(Way::Keys(sequence));
+                }
+            }
+        }
+
+        if target.is_mobile() {
+            let longpress = self.longpress.iter().flatten();
+            for (base, alternatives) in longpress {
+                if !alternatives
+                    .split_ascii_whitespace()
+                    .any(|alt| alt == output)
+                {
+                    continue;
+                }
+                ways.extend(
+                    presses
+                        .iter()
+                        .filter(|(_, value)| value == base)
+                        .map(|(press, _)| Way::Longpress(press.clone())),
+                );
+            }
+        }
+
+        ways
+    }
+
+    /// Every output that a press, a dead key and a press, or a long-press
+    /// types on `target`, with the shortest way of typing it
+    pub fn ways(&self, target: Target) -> BTreeMap<String, Way> {
+        let presses = self.presses(target, &[]);
+        let mut ways = BTreeMap::new();
+
+        for (press, _) in &presses {
+            let sequence = vec![press.clone()];
+            ways.entry(self.simulate(target, &sequence))
+                .or_insert(Way::Keys(sequence));
+        }
+
+        for (dead, value) in &presses {
+            if !self.is_dead_key(target, &dead.mode, value) {
+                continue;
+            }
+            for (press, _) in &presses {
+                let sequence = vec![dead.clone(), press.clone()];
+                ways.entry(self.simulate(target, &sequence))
+                    .or_insert(Way::Keys(sequence));
+            }
+        }
+
+        if target.is_mobile() {
+            for (base, alternatives) in self.longpress.iter().flatten() {
+                for (press, _) in presses.iter().filter(|(_, value)| value == base) {
+                    for alternative in alternatives.split_ascii_whitespace() {
+                        ways.entry(alternative.to_string())
+                            .or_insert_with(|| Way::Longpress(press.clone()));
+                    }
+                }
+            }
+        }
+
+        ways.remove("");
+        ways
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(s: &str) -> KeyPress {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn outputs_are_found_directly_through_dead_keys_and_longpress() {
+        let layout: Layout = serde_yaml::from_str(
+            r#"
+displayNames:
+  en: Test
+modes:
+  desktop:
+    default: |
+      ´ 1 2 3 4 5 6 7 8 9 0 + '
+        q w e r t y u i o p å ¨
+        a s d f g h j k l ö ä '
+      < z x c v b n m , . -
+    shift: |
+      ` ! " # $ % & / ( ) = ? *
+        Q W E R T Y U I O P Å ^
+        A S D F G H J K L Ö Ä *
+      > Z X C V B N M ; : _
+  android:
+    default: |
+      q w e r t y u i o p
+      a s d f g h j k l
+      z x c v b n m
+deadKeys:
+  desktop:
+    default: ["´"]
+transforms:
+  ´:
+    a: á
+    A: Á
+    " ": ´
+longpress:
+  a: á à
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            layout.which_key(Target::Win, "Q", &[]),
+            vec![Way::Keys(vec![press("shift+D01")])]
+        );
+        assert_eq!(
+            layout.which_key(Target::Win, "Á", &[]),
+            vec![Way::Keys(vec![press("E00"), press("shift+C01")])]
+        );
+        assert_eq!(
+            layout.which_key(Target::Win, "Á", &["default".parse().unwrap()]),
+            vec![]
+        );
+        assert_eq!(
+            layout.which_key(Target::Android, "à", &[]),
+            vec![Way::Longpress(press("C01"))]
+        );
+        assert_eq!(
+            Way::Keys(vec![press("E00"), press("shift+C01")]).to_string(),
+            "E00 shift+C01"
+        );
+
+        let ways = layout.ways(Target::Android);
+        assert_eq!(ways["a"], Way::Keys(vec![press("C01")]));
+        assert_eq!(ways["à"], Way::Longpress(press("C01")));
+        assert_eq!(
+            layout.ways(Target::Win)["Á"],
+            Way::Keys(vec![press("E00"), press("shift+C01")])
+        );
+    }
+}
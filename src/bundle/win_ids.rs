@@ -0,0 +1,374 @@
+//! Keyboard ids of the Windows target
+//!
+//! Each layout with modes for Windows becomes a DLL named `kbd<id>.dll`,
+//! installed into `System32` next to the layouts Windows comes with. The id
+//! is `targets.win.id` of the layout, or the first five letters, digits and
+//! hyphens of its name, and a `compatMap` adds a legacy layout whose id is
+//! allocated around all others, see `ProjectBundle::compat_win_ids`. Two layouts with the same id overwrite each other's DLL, and a
+//! layout with the id of a system layout replaces that one for every user of
+//! the machine.
+
+use crate::{models::Layout, ProjectBundle, Target};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+/// The longest id a keyboard DLL can have, as its name is at most eight
+/// characters
+pub const MAX_ID_LENGTH: usize = 5;
+
+/// The ids of the keyboard DLLs that Windows comes with
+pub const SYSTEM_IDS: &[&str] = &[
+    "101", "103", "106", "a1", "a2", "a3", "al", "arme", "armw", "aze", "azel", "be", "bene",
+    "bhc", "blr", "br", "bu", "ca", "can", "cr", "cz", "cz1", "cz2", "da", "dv", "es", "est", "fa",
+    "fc", "fi", "fi1", "fo", "fr", "gae", "geo", "gkl", "gr", "gr1", "he", "he220", "he319", "heb",
+    "hela2", "hela3", "hept", "hu", "hu1", "ic", "inasa", "inbe1", "inben", "inbe2", "inen",
+    "inguj", "inhin", "inkan", "inmal", "inmar", "inori", "inpun", "intam", "intel", "inuk2", "ir",
+    "it", "it142", "iulat", "jpn", "kaz", "kor", "kyr", "la", "lao", "lt", "lt1", "lt2", "lv",
+    "lv1", "mac", "maori", "mlt47", "mlt48", "mon", "mons", "ne", "nepr", "no", "no1", "nso", "pl",
+    "pl1", "po", "ro", "ropr", "rost", "ru", "ru1", "sf", "sg", "sl", "sl1", "smsfi", "smsno",
+    "sn1", "sorex", "sors1", "sorst", "sp", "sw", "sw09", "syr1", "syr2", "tajik", "tat", "th0",
+    "th1", "th2", "th3", "tiprc", "tuf", "tuq", "turme", "ughr", "ughr1", "uk", "ukx", "ur", "ur1",
+    "urdu", "us", "usa", "usl", "usr", "usx", "uzb", "vntc", "wol", "yak", "yba", "ycc", "ycl",
+];
+
+/// The id of a layout without `targets.win.id`, like the Windows generator
+/// makes it up
+pub fn default_win_id(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .take(MAX_ID_LENGTH)
+        .collect()
+}
+
+/// The id the legacy layout generated with a `compatMap` gets if it is free
+pub fn compat_win_id(id: &str) -> String {
+    let mut compat = id.chars().take(MAX_ID_LENGTH - 1).collect::<String>();
+    compat.push('c');
+    compat
+}
+
+/// `compat_win_id(id)`, then ids that keep as much of the start of `id` as
+/// fits with `c` and a number after it
+fn compat_candidates(id: &str) -> impl Iterator<Item = String> + '_ {
+    let numbered = (1..100_000u32).map(move |n| {
+        let number = n.to_string();
+        let keep = MAX_ID_LENGTH.saturating_sub(number.len() + 1);
+        format!("{}c{}", id.chars().take(keep).collect::<String>(), number)
+    });
+    std::iter::once(compat_win_id(id)).chain(numbered)
+}
+
+fn is_system_id(id: &str) -> bool {
+    SYSTEM_IDS
+        .iter()
+        .any(|system| system.eq_ignore_ascii_case(id))
+}
+
+impl Layout {
+    /// `targets.win.id`, if the layout has one
+    pub fn win_id(&self) -> Option<&str> {
+        self.targets.as_ref()?.win.as_ref()?.id.as_deref()
+    }
+}
+
+/// The keyboard DLL of a layout
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WinId {
+    pub layout: String,
+    pub id: String,
+    /// Whether this is the legacy layout of a `compatMap`
+    pub compat: bool,
+}
+
+/// Layouts whose keyboard DLLs have the same name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WinIdCollision {
+    /// The id of a layout is that of a layout Windows comes with
+    System(WinId),
+    /// Layouts of the bundle share the id
+    Layouts { id: String, ids: Vec<WinId> },
+}
+
+impl fmt::Display for WinId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.compat {
+            write!(f, "the legacy layout of `{}`", self.layout)
+        } else {
+            write!(f, "`{}`", self.layout)
+        }
+    }
+}
+
+impl fmt::Display for WinIdCollision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WinIdCollision::System(id) => write!(
+                f,
+                "{} would replace the system keyboard `kbd{}.dll` of Windows",
+                id, id.id
+            ),
+            WinIdCollision::Layouts { id, ids } => write!(
+                f,
+                "{} are all built into `kbd{}.dll`",
+                ids.iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(" and "),
+                id
+            ),
+        }
+    }
+}
+
+/// New ids for the layouts of a bundle, see `ProjectBundle::allocate_win_ids`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WinIdAllocation {
+    /// The id for each layout that had none
+    pub ids: BTreeMap<String, String>,
+    /// Layouts without an id that have variants, which would share the id
+    pub skipped: Vec<String>,
+}
+
+impl ProjectBundle {
+    /// The keyboard DLLs of the layouts with modes for Windows, sorted by
+    /// layout
+    pub fn win_ids(&self) -> Vec<WinId> {
+        let mut compat_ids = self.compat_win_ids();
+        let mut ids = vec![];
+        for (name, id) in self.layout_win_ids() {
+            if let Some(compat) = compat_ids.remove(&name) {
+                ids.push(WinId {
+                    layout: name.clone(),
+                    id: compat,
+                    compat: true,
+                });
+            }
+            ids.push(WinId {
+                layout: name,
+                id,
+                compat: false,
+            });
+        }
+        ids
+    }
+
+    /// The ids of the layouts with modes for Windows, sorted by layout
+    fn layout_win_ids(&self) -> Vec<(String, String)> {
+        let mut ids = self
+            .layouts
+            .iter()
+            .filter(|(_, layout)| layout.modes.resolve(Target::Win).is_some())
+            .map(|(name, layout)| {
+                let id = layout
+                    .win_id()
+                    .map(str::to_string)
+                    .unwrap_or_else(|| default_win_id(name));
+                (name.clone(), id)
+            })
+            .collect::<Vec<_>>();
+        ids.sort();
+        ids
+    }
+
+    /// The ids of the legacy layouts of a `compatMap`, by layout, or none if
+    /// the target has no `compatMap`
+    ///
+    /// Each is `compat_win_id` of the layout's id if that is free, otherwise
+    /// one with a number, so that it collides neither with a system keyboard
+    /// nor with any other layout of the bundle. The Windows generator is
+    /// passed these in `KBDGEN_WIN_COMPAT_IDS`.
+    pub fn compat_win_ids(&self) -> BTreeMap<String, String> {
+        let compat = self
+            .targets
+            .windows
+            .as_ref()
+            .and_then(|target| target.compat_map.as_ref())
+            .is_some_and(|map| !map.is_empty());
+        if !compat {
+            return Default::default();
+        }
+
+        let ids = self.layout_win_ids();
+        let mut taken = ids
+            .iter()
+            .map(|(_, id)| id.to_lowercase())
+            .collect::<BTreeSet<_>>();
+        let mut compat_ids = BTreeMap::new();
+        for (name, id) in ids {
+            let compat_id = compat_candidates(&id)
+                .find(|candidate| {
+                    let candidate = candidate.to_lowercase();
+                    !is_system_id(&candidate) && !taken.contains(&candidate)
+                })
+                .expect("there are more candidates than layouts");
+            taken.insert(compat_id.to_lowercase());
+            compat_ids.insert(name, compat_id);
+        }
+        compat_ids
+    }
+
+    /// Layouts whose keyboard DLLs would replace a system one or each other
+    pub fn win_id_collisions(&self) -> Vec<WinIdCollision> {
+        let mut by_id = BTreeMap::<String, Vec<WinId>>::new();
+        let mut collisions = vec![];
+        for id in self.win_ids() {
+            if is_system_id(&id.id) {
+                collisions.push(WinIdCollision::System(id.clone()));
+            }
+            by_id.entry(id.id.to_lowercase()).or_default().push(id);
+        }
+        for (_, ids) in by_id {
+            if ids.len() > 1 {
+                collisions.push(WinIdCollision::Layouts {
+                    id: ids[0].id.clone(),
+                    ids,
+                });
+            }
+        }
+        collisions
+    }
+
+    /// Ids for the layouts with modes for Windows but no `targets.win.id`,
+    /// that collide neither with a system keyboard nor with each other, so
+    /// that they can be written into the layouts and no longer change with
+    /// the layout's name
+    ///
+    /// A layout keeps the id it would get without one if it is free.
+    /// Layouts with an id are left as they are, even if it collides.
+    pub fn allocate_win_ids(&self) -> WinIdAllocation {
+        let ids = self.win_ids();
+        let has_variants = |name: &str| {
+            self.layouts
+                .get(name)
+                .and_then(|layout| layout.variants.as_ref())
+                .is_some_and(|variants| !variants.is_empty())
+        };
+        let needs_id = |name: &str| {
+            self.layouts.get(name).is_some_and(|l| l.win_id().is_none())
+                && !has_variants(name)
+                && !super::variants::is_variant(&self.layouts, name)
+        };
+        // The ids of legacy layouts are allocated around these afterwards
+        let mut taken = ids
+            .iter()
+            .filter(|id| !id.compat && !needs_id(&id.layout))
+            .map(|id| id.id.to_lowercase())
+            .collect::<BTreeSet<_>>();
+        let mut allocation = WinIdAllocation::default();
+        for id in ids.iter().filter(|id| !id.compat) {
+            if self.layouts[&id.layout].win_id().is_some() {
+                continue;
+            }
+            if !needs_id(&id.layout) {
+                if has_variants(&id.layout) {
+                    allocation.skipped.push(id.layout.clone());
+                }
+                continue;
+            }
+            let free = |candidate: &str| {
+                let candidate = candidate.to_lowercase();
+                !is_system_id(&candidate) && !taken.contains(&candidate)
+            };
+            let new_id = candidates(&id.id)
+                .find(|candidate| free(candidate))
+                .expect("there are more candidates than layouts");
+            taken.insert(new_id.to_lowercase());
+            allocation.ids.insert(id.layout.clone(), new_id);
+        }
+        allocation
+    }
+}
+
+/// `id`, then ids that keep as much of its start as fits with a number
+/// after it
+fn candidates(id: &str) -> impl Iterator<Item = String> + '_ {
+    let id = if id.is_empty() { "kbd" } else { id };
+    let numbered = (1..100_000u32).map(move |n| {
+        let number = n.to_string();
+        let keep = MAX_ID_LENGTH.saturating_sub(number.len());
+        format!("{}{}", id.chars().take(keep).collect::<String>(), number)
+    });
+    std::iter::once(id.to_string()).chain(numbered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bundle(layouts: &[(&str, &str)]) -> ProjectBundle {
+        let mut bundle: ProjectBundle = Default::default();
+        for (name, yaml) in layouts {
+            let layout = format!(
+                "displayNames:\n  en: Test\nmodes:\n  win:\n    default: a\n{}",
+                yaml
+            );
+            bundle
+                .layouts
+                .insert(name.to_string(), serde_yaml::from_str(&layout).unwrap());
+        }
+        bundle
+    }
+
+    #[test]
+    fn ids_are_allocated_around_collisions() {
+        let bundle = bundle(&[
+            ("fi", ""),
+            ("se-FI", ""),
+            ("se-FIN", ""),
+            ("smn", "targets:\n  win:\n    locale: smn\n    id: se-F1\n"),
+        ]);
+        let collisions = bundle
+            .win_id_collisions()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            collisions,
+            vec![
+                "`fi` would replace the system keyboard `kbdfi.dll` of Windows",
+                "`se-FI` and `se-FIN` are all built into `kbdse-FI.dll`",
+            ]
+        );
+
+        let allocation = bundle.allocate_win_ids();
+        let ids = allocation
+            .ids
+            .iter()
+            .map(|(layout, id)| (layout.as_str(), id.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ids,
+            vec![("fi", "fi2"), ("se-FI", "se-FI"), ("se-FIN", "se-F2")]
+        );
+        assert!(allocation.skipped.is_empty());
+    }
+
+    #[test]
+    fn compat_ids_are_allocated_around_collisions() {
+        let mut bundle = bundle(&[("f", ""), ("smj-NO", ""), ("smj-SE", ""), ("smj-c", "")]);
+        bundle.targets.windows = Some(
+            serde_yaml::from_str("version: 1.0.0\nappName: Test\nurl: x\ncompatMap:\n  a: b\n")
+                .unwrap(),
+        );
+        let ids = bundle.compat_win_ids();
+        let ids = ids
+            .iter()
+            .map(|(layout, id)| (layout.as_str(), id.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ids,
+            vec![
+                ("f", "fc1"),
+                ("smj-NO", "smjc1"),
+                ("smj-SE", "smjc2"),
+                ("smj-c", "smjc3"),
+            ]
+        );
+        assert!(bundle
+            .win_id_collisions()
+            .iter()
+            .all(|collision| !matches!(collision, WinIdCollision::System(id) if id.compat)));
+    }
+}
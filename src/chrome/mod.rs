@@ -0,0 +1,75 @@
+//! Packaging of the generated ChromeOS extension for the Chrome Web Store
+
+use std::{
+    io::{self, Cursor, Write},
+    path::{Path, PathBuf},
+};
+use zip::{write::FileOptions, ZipWriter};
+
+mod crx;
+pub use crx::{write_crx, Error as CrxError, SigningKey};
+
+/// Files that are never shipped in a package, namely earlier packages and
+/// the signing key
+const EXCLUDED_EXTENSIONS: &[&str] = &["zip", "crx", "pem"];
+
+/// Zips up the unpacked extension in `dir` into the archive format the web
+/// store accepts for upload
+pub fn webstore_zip(dir: &Path) -> Result<Vec<u8>, Error> {
+    let mut files = vec![];
+    collect_files(dir, &mut files).map_err(|source| Error::CannotReadDir {
+        path: dir.to_path_buf(),
+        source,
+    })?;
+    files.sort();
+
+    let mut zip = ZipWriter::new(Cursor::new(vec![]));
+    for path in files {
+        let name = path
+            .strip_prefix(dir)
+            .expect("collected below dir")
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let data = std::fs::read(&path).map_err(|source| Error::CannotReadFile {
+            path: path.clone(),
+            source,
+        })?;
+
+        zip.start_file(name, FileOptions::default())
+            .map_err(|source| Error::CannotZip { source })?;
+        zip.write_all(&data).map_err(|source| Error::CannotZip {
+            source: source.into(),
+        })?;
+    }
+
+    let zip = zip.finish().map_err(|source| Error::CannotZip { source })?;
+    Ok(zip.into_inner())
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else if !path
+            .extension()
+            .map(|ext| EXCLUDED_EXTENSIONS.iter().any(|x| ext == *x))
+            .unwrap_or(false)
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not read directory `{}`", path.display())]
+    CannotReadDir { path: PathBuf, source: io::Error },
+    #[error("Could not read file `{}`", path.display())]
+    CannotReadFile { path: PathBuf, source: io::Error },
+    #[error("Could not create zip archive")]
+    CannotZip { source: zip::result::ZipError },
+}
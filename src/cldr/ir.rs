@@ -1,5 +1,20 @@
+//! An intermediate form of CLDR keyboards, between the XML and kbdgen modes
+//!
+//! Each `<keyMap>` of a CLDR keyboard becomes a layer, named after the kbdgen
+//! mode its `modifiers` attribute selects:
+//!
+//! ```rust
+//! use kbdgen::cldr::ir::parse_modifiers;
+//!
+//! let mode = parse_modifiers(Some("altR+caps? ctrl+alt+caps?")).unwrap();
+//! assert_eq!(mode.to_string(), "caps?+alt");
+//! assert_eq!(mode.required.to_string(), "alt");
+//! assert!(parse_modifiers(Some("opt+fn")).is_err());
+//! ```
+
 use super::{models::RawIsoKey, KeyMap};
-use std::collections::BTreeMap;
+use crate::{ModeName, Modifier, ModifierSet};
+use std::{collections::BTreeMap, convert::TryFrom, str::FromStr};
 
 #[derive(Debug, Clone)]
 pub enum Layer {
@@ -7,65 +22,106 @@ pub enum Layer {
     Mobile(MobileLayer),
 }
 
-pub(crate) fn parse_modifiers(mods: Option<&String>) -> String {
-    let mess = match mods {
-        Some(v) => {
-            if v == "shift caps" || v == "caps shift" {
-                return "shift".into();
-            }
+/// The mode selected by the `modifiers` attribute of a CLDR `<keyMap>`
+///
+/// The attribute is a space separated list of alternatives, each of which is
+/// a `+` separated list of modifiers. Modifiers ending in `?` are optional,
+/// and the left and right variants, like `altR` and `shiftL`, are the same as
+/// the modifier itself. Of the alternatives, the one holding the fewest
+/// modifiers is used, preferring those without caps lock, so `shift caps` is
+/// the shift mode. No attribute is the default mode.
+pub fn parse_modifiers(modifiers: Option<&str>) -> Result<ModeName, ModifiersError> {
+    let modifiers = match modifiers.map(str::trim) {
+        Some(modifiers) if !modifiers.is_empty() => modifiers,
+        _ => return Ok(ModeName::default()),
+    };
 
-            v
+    let mut alternatives = vec![];
+    for alternative in modifiers.split_whitespace() {
+        let mut required = vec![];
+        let mut optional = vec![];
+        for part in alternative.split('+') {
+            let (name, list) = match part.strip_suffix('?') {
+                Some(name) => (name, &mut optional),
+                None => (part, &mut required),
+            };
+            list.push(
+                parse_modifier(name).ok_or_else(|| ModifiersError::UnknownModifier {
+                    modifiers: modifiers.to_string(),
+                    modifier: part.to_string(),
+                })?,
+            );
         }
-        None => return "default".into(),
-    };
+        alternatives.push(ModeName {
+            required: required.into_iter().collect::<ModifierSet>(),
+            optional: optional.into_iter().collect::<ModifierSet>(),
+        });
+    }
 
-    mess.split(' ')
-        .map(|chunk| {
-            chunk
-                .split('+')
-                .filter(|x| !x.ends_with('?'))
-                .map(|v| match v {
-                    "shift" | "shiftR" | "shiftL" => "shift".into(),
-                    "ctrl" | "ctrlR" | "ctrlL" => "ctrl".into(),
-                    "opt" | "optR" | "optL" => "alt".into(),
-                    x if x.ends_with('R') || x.ends_with('L') => x[..x.len() - 1].into(),
-                    _ => v.into(),
-                })
-                .collect::<Vec<String>>()
+    Ok(alternatives
+        .into_iter()
+        .enumerate()
+        .min_by_key(|(i, mode)| {
+            (
+                mode.required.iter().count(),
+                mode.required.contains(Modifier::Caps),
+                *i,
+            )
         })
-        .min()
-        .unwrap()
-        .join("+")
+        .map(|(_, mode)| mode)
+        .expect("a non-empty attribute has an alternative"))
+}
+
+fn parse_modifier(name: &str) -> Option<Modifier> {
+    let base = name
+        .strip_suffix('L')
+        .or_else(|| name.strip_suffix('R'))
+        .filter(|base| Modifier::from_str(base).is_ok())
+        .unwrap_or(name);
+    Modifier::from_str(base).ok()
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ModifiersError {
+    #[error("Unknown modifier `{}` in `{}`", modifier, modifiers)]
+    UnknownModifier { modifiers: String, modifier: String },
+}
+
+/// The keys of a desktop `<keyMap>`, by ISO key
 #[derive(Debug, Clone)]
 pub struct DesktopLayer {
+    /// The kbdgen mode, without optional modifiers
     pub mode: String,
     pub keys: BTreeMap<RawIsoKey, String>,
 }
 
+/// The keys of a mobile `<keyMap>`, in rows
 #[derive(Debug, Clone)]
 pub struct MobileLayer {
+    /// The kbdgen mode, without optional modifiers
     pub mode: String,
     pub keys: Vec<Vec<String>>,
 }
 
 impl DesktopLayer {
-    pub(crate) fn new(mode: String, keys: BTreeMap<RawIsoKey, String>) -> DesktopLayer {
+    pub fn new(mode: String, keys: BTreeMap<RawIsoKey, String>) -> DesktopLayer {
         DesktopLayer { mode, keys }
     }
 
+    /// Every key position of the ISO layout, row by row, with the key's
+    /// output if the layer has one
     pub fn iter(&self) -> DesktopLayerIterator {
         DesktopLayerIterator::new(self)
     }
 }
 
 impl MobileLayer {
-    pub(crate) fn new(mode: String, keys: Vec<Vec<String>>) -> MobileLayer {
+    pub fn new(mode: String, keys: Vec<Vec<String>>) -> MobileLayer {
         MobileLayer { mode, keys }
     }
 }
 
+/// See `DesktopLayer::iter`
 pub struct DesktopLayerIterator<'a> {
     layer: &'a DesktopLayer,
     letter: &'static str,
@@ -96,8 +152,6 @@ impl<'a> Iterator for DesktopLayerIterator<'a> {
     type Item = (&'a str, u8, Option<&'a str>);
 
     fn next(&mut self) -> Option<Self::Item> {
-        use std::str::FromStr;
-
         if self.letter == "A" {
             return None;
         }
@@ -154,10 +208,10 @@ impl<'a> Iterator for DesktopLayerIterator<'a> {
     }
 }
 
-impl From<&KeyMap> for DesktopLayer {
-    fn from(key_map: &KeyMap) -> DesktopLayer {
-        use std::str::FromStr;
+impl TryFrom<&KeyMap> for DesktopLayer {
+    type Error = ModifiersError;
 
+    fn try_from(key_map: &KeyMap) -> Result<DesktopLayer, ModifiersError> {
         let mut keys = BTreeMap::new();
 
         for map in key_map.keys.iter() {
@@ -166,12 +220,17 @@ impl From<&KeyMap> for DesktopLayer {
             }
         }
 
-        DesktopLayer::new(parse_modifiers(key_map.modifiers.as_ref()), keys)
+        let mode = parse_modifiers(key_map.modifiers.as_deref())?;
+        Ok(DesktopLayer::new(mode.required.to_string(), keys))
     }
 }
 
-impl From<&KeyMap> for MobileLayer {
-    fn from(key_map: &KeyMap) -> MobileLayer {
+impl TryFrom<&KeyMap> for MobileLayer {
+    type Error = ModifiersError;
+
+    fn try_from(key_map: &KeyMap) -> Result<MobileLayer, ModifiersError> {
+        let mode = parse_modifiers(key_map.modifiers.as_deref())?;
+
         let mut keys = vec![];
         let mut next = vec![];
 
@@ -187,7 +246,7 @@ impl From<&KeyMap> for MobileLayer {
         }
         keys.push(next);
 
-        MobileLayer::new(parse_modifiers(key_map.modifiers.as_ref()), keys)
+        Ok(MobileLayer::new(mode.required.to_string(), keys))
     }
 }
 
@@ -243,3 +302,79 @@ impl From<&DesktopLayer> for serde_yaml::Value {
         serde_yaml::Value::String(String::from(layer))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mode(modifiers: &str) -> String {
+        parse_modifiers(Some(modifiers)).unwrap().to_string()
+    }
+
+    #[test]
+    fn no_modifiers_is_the_default_mode() {
+        assert_eq!(parse_modifiers(None).unwrap(), ModeName::default());
+        assert_eq!(mode(""), "default");
+    }
+
+    #[test]
+    fn sides_and_aliases_are_the_same_modifier() {
+        assert_eq!(mode("shiftL"), "shift");
+        assert_eq!(mode("altR"), "alt");
+        assert_eq!(mode("optL+shiftR"), "alt+shift");
+        assert_eq!(mode("ctrlR+cmd"), "cmd+ctrl");
+    }
+
+    #[test]
+    fn optional_modifiers_are_kept_apart() {
+        let name = parse_modifiers(Some("opt+caps?")).unwrap();
+        assert_eq!(name.required.to_string(), "alt");
+        assert_eq!(name.optional.to_string(), "caps");
+        assert_eq!(mode("cmd+shift?+caps?"), "cmd+caps?+shift?");
+    }
+
+    #[test]
+    fn the_alternative_with_fewest_modifiers_wins() {
+        assert_eq!(mode("ctrl+alt altR"), "alt");
+        assert_eq!(mode("altR+caps? ctrl+alt+caps?"), "caps?+alt");
+        assert_eq!(mode("shift caps"), "shift");
+        assert_eq!(mode("caps shift"), "shift");
+        assert_eq!(mode("caps"), "caps");
+    }
+
+    #[test]
+    fn unknown_modifiers_are_errors() {
+        assert_eq!(
+            parse_modifiers(Some("opt+fn?")),
+            Err(ModifiersError::UnknownModifier {
+                modifiers: "opt+fn?".into(),
+                modifier: "fn?".into(),
+            })
+        );
+        assert!(parse_modifiers(Some("shift+")).is_err());
+        assert!(parse_modifiers(Some("shift??")).is_err());
+        assert!(parse_modifiers(Some("capsX")).is_err());
+    }
+
+    #[test]
+    fn layers_are_named_without_optional_modifiers() {
+        let key_map = KeyMap {
+            keys: vec![super::super::Map {
+                iso: "D01".into(),
+                to: "Q".into(),
+                ..Default::default()
+            }],
+            modifiers: Some("shift+caps?".into()),
+        };
+        let layer = DesktopLayer::try_from(&key_map).unwrap();
+        assert_eq!(layer.mode, "shift");
+        assert_eq!(
+            layer
+                .iter()
+                .find(|(l, n, _)| *l == "D" && *n == 1)
+                .unwrap()
+                .2,
+            Some("Q")
+        );
+    }
+}
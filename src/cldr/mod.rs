@@ -1,8 +1,9 @@
 use serde::Deserialize;
 
-mod ir;
+pub mod ir;
 mod models;
 pub use models::*;
+pub mod numbers;
 mod ser;
 pub use ser::ToXml;
 
@@ -53,6 +54,14 @@ pub struct Names {
     pub values: Vec<Name>,
 }
 
+#[derive(Debug, Clone, PartialEq, Deserialize, Default)]
+pub struct Settings {
+    #[serde(rename = "transformFailure")]
+    pub transform_failure: Option<String>,
+    #[serde(rename = "transformPartial")]
+    pub transform_partial: Option<String>,
+}
+
 #[derive(Debug, Clone, PartialEq, Deserialize, Default)]
 pub struct Keyboard {
     pub locale: String,
@@ -60,17 +69,18 @@ pub struct Keyboard {
     pub version: Version,
     #[serde(rename = "keyMap")]
     pub key_maps: Vec<KeyMap>,
+    pub settings: Option<Settings>,
     pub transforms: Option<Vec<Transforms>>,
-    // <settings transformFailure="omit" transformPartial="hide"/>
 }
 
-use self::ir::{parse_modifiers, DesktopLayer, MobileLayer};
+use self::ir::{parse_modifiers, DesktopLayer, MobileLayer, ModifiersError};
 use crate::bundle::{
     key_map::{DesktopKeyMap, MobileKeyMap},
     keys,
-    models::{DesktopModes, IsoKey, MobileModes, Mode},
+    models::{DesktopModes, IsoKey, MobileModes, Mode, TransformPartial},
+    MobileMode, Target,
 };
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, convert::TryFrom};
 
 impl Keyboard {
     pub fn is_mobile(&self) -> bool {
@@ -78,35 +88,56 @@ impl Keyboard {
         self.locale.contains("android")
     }
 
-    pub fn mode_name(&self) -> &'static str {
+    /// The `transformPartial` of the keyboard's settings, if it is one kbdgen
+    /// knows
+    pub fn transform_partial(&self) -> Option<TransformPartial> {
+        let partial = self.settings.as_ref()?.transform_partial.as_deref()?;
+        match partial.parse() {
+            Ok(partial) => Some(partial),
+            Err(_) => {
+                log::warn!("ignoring unknown transformPartial `{}`", partial);
+                None
+            }
+        }
+    }
+
+    /// The target whose modes this keyboard describes, if it is known
+    pub fn target(&self) -> Option<Target> {
         if self.locale.contains("android") {
-            "mobile"
+            Some(Target::Mobile)
         } else if self.locale.contains("windows") {
-            "win"
+            Some(Target::Win)
         } else if self.locale.contains("osx") {
-            "mac"
+            Some(Target::Mac)
         } else if self.locale.contains("chrome") {
-            "chrome"
+            Some(Target::Chrome)
         } else {
-            "unknown"
+            None
         }
     }
 
-    pub fn to_mode(&self) -> Mode {
-        if self.is_mobile() {
-            Mode::Mobile(self.to_mobile_modes())
+    pub fn to_mode(&self) -> Result<Mode, ModifiersError> {
+        Ok(if self.is_mobile() {
+            Mode::Mobile(self.to_mobile_modes()?)
         } else {
-            Mode::Desktop(self.to_desktop_modes())
-        }
+            Mode::Desktop(self.to_desktop_modes()?)
+        })
     }
 
-    pub fn to_mobile_modes(&self) -> MobileModes {
+    pub fn to_mobile_modes(&self) -> Result<MobileModes, ModifiersError> {
         let mut out = BTreeMap::new();
 
         for key_map in self.key_maps.iter() {
-            let layer = MobileLayer::from(key_map);
+            let layer = MobileLayer::try_from(key_map)?;
+            let mode = match layer.mode.parse::<MobileMode>() {
+                Ok(mode) => mode,
+                Err(_) => {
+                    log::warn!("skipping mobile key map for `{}`, no such mode", layer.mode);
+                    continue;
+                }
+            };
             out.insert(
-                layer.mode,
+                mode,
                 MobileKeyMap(
                     layer
                         .keys
@@ -121,10 +152,10 @@ impl Keyboard {
             );
         }
 
-        MobileModes(out)
+        Ok(MobileModes(out))
     }
 
-    pub fn to_desktop_modes(&self) -> DesktopModes {
+    pub fn to_desktop_modes(&self) -> Result<DesktopModes, ModifiersError> {
         use std::str::FromStr;
 
         let mut out = BTreeMap::new();
@@ -138,7 +169,9 @@ impl Keyboard {
                 }
             }
 
-            let mods = parse_modifiers(key_map.modifiers.as_ref());
+            let mods = parse_modifiers(key_map.modifiers.as_deref())?
+                .required
+                .to_string();
 
             let layer = DesktopLayer::new(mods.clone(), keys);
             let mut keys_out: BTreeMap<IsoKey, keys::KeyValue> = BTreeMap::new();
@@ -153,6 +186,6 @@ impl Keyboard {
             out.insert(mods, DesktopKeyMap(keys_out));
         }
 
-        DesktopModes(out)
+        Ok(DesktopModes(out))
     }
 }
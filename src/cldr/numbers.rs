@@ -0,0 +1,206 @@
+//! Number and currency symbols from the CLDR locale data
+//!
+//! Reads `common/main/<locale>.xml` along the locale's parent chain (`se_NO`,
+//! `se`, `root`) and the region's current currency from
+//! `common/supplemental/supplementalData.xml`.
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+use xml::{
+    attribute::OwnedAttribute,
+    reader::{EventReader, XmlEvent},
+};
+
+/// The symbols a locale writes numbers with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NumberSymbols {
+    pub decimal: String,
+    pub group: String,
+    pub percent: String,
+    /// The symbol of the currency of the locale's region, if the locale has
+    /// a region
+    pub currency: Option<String>,
+}
+
+impl NumberSymbols {
+    /// Loads the symbols for a BCP 47 locale tag like `se-NO`
+    pub fn load(cldr_dir: &Path, locale: &str) -> Result<Self, Error> {
+        let subtags = locale.split(&['-', '_'][..]).collect::<Vec<_>>();
+        let mut chain = (1..=subtags.len())
+            .rev()
+            .map(|len| subtags[..len].join("_"))
+            .collect::<Vec<_>>();
+        chain.push("root".to_string());
+
+        let mut values = BTreeMap::new();
+        for name in &chain {
+            let path = cldr_dir
+                .join("common/main")
+                .join(name)
+                .with_extension("xml");
+            if !path.exists() {
+                continue;
+            }
+            for (key, value) in read_main(&path)? {
+                values.entry(key).or_insert(value);
+            }
+        }
+
+        let currency = match region(&subtags) {
+            Some(region) => {
+                let supplemental = cldr_dir.join("common/supplemental/supplementalData.xml");
+                read_region_currency(&supplemental, region)?
+                    .and_then(|code| values.remove(&format!("currency:{}", code)).or(Some(code)))
+            }
+            None => None,
+        };
+
+        let mut take = |key: &str| {
+            values.remove(key).ok_or_else(|| Error::MissingSymbol {
+                locale: locale.to_string(),
+                symbol: key.to_string(),
+            })
+        };
+
+        Ok(NumberSymbols {
+            decimal: take("decimal")?,
+            group: take("group")?,
+            percent: take("percentSign")?,
+            currency,
+        })
+    }
+
+    /// The symbols by the name they are used with in layout macros
+    pub fn to_macros(&self) -> BTreeMap<String, String> {
+        let mut macros = BTreeMap::new();
+        macros.insert("decimal".to_string(), self.decimal.clone());
+        macros.insert("group".to_string(), self.group.clone());
+        macros.insert("percent".to_string(), self.percent.clone());
+        if let Some(currency) = self.currency.as_ref() {
+            macros.insert("currency".to_string(), currency.clone());
+        }
+        macros
+    }
+}
+
+/// Region subtags are two letters or three digits, after the language
+fn region<'a>(subtags: &[&'a str]) -> Option<&'a str> {
+    subtags.iter().skip(1).copied().find(|tag| {
+        (tag.len() == 2 && tag.chars().all(|c| c.is_ascii_alphabetic()))
+            || (tag.len() == 3 && tag.chars().all(|c| c.is_ascii_digit()))
+    })
+}
+
+fn attr<'a>(attributes: &'a [OwnedAttribute], name: &str) -> Option<&'a str> {
+    attributes
+        .iter()
+        .find(|a| a.name.local_name == name)
+        .map(|a| a.value.as_str())
+}
+
+fn open(path: &Path) -> Result<EventReader<BufReader<File>>, Error> {
+    let file = File::open(path).map_err(|source| Error::CannotOpenFile {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    Ok(EventReader::new(BufReader::new(file)))
+}
+
+/// The latin number symbols and the currency symbols, keyed by
+/// `currency:<code>`
+fn read_main(path: &Path) -> Result<BTreeMap<String, String>, Error> {
+    let mut values = BTreeMap::new();
+    let mut stack: Vec<(String, Vec<OwnedAttribute>)> = vec![];
+
+    for event in open(path)? {
+        match event.map_err(|source| Error::CannotReadXml {
+            path: path.to_path_buf(),
+            source,
+        })? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => stack.push((name.local_name, attributes)),
+            XmlEvent::EndElement { .. } => {
+                stack.pop();
+            }
+            XmlEvent::Characters(text) | XmlEvent::Whitespace(text) => {
+                let names = stack.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>();
+                let (element, attributes) = match stack.last() {
+                    Some(last) => last,
+                    None => continue,
+                };
+                if attr(attributes, "alt").is_some() {
+                    continue;
+                }
+
+                match names.as_slice() {
+                    [.., "numbers", "symbols", _] => {
+                        let symbols = &stack[stack.len() - 2].1;
+                        if attr(symbols, "numberSystem") == Some("latn") {
+                            values.entry(element.clone()).or_insert(text);
+                        }
+                    }
+                    [.., "numbers", "currencies", "currency", "symbol"] => {
+                        if let Some(code) = attr(&stack[stack.len() - 2].1, "type") {
+                            values.entry(format!("currency:{}", code)).or_insert(text);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(values)
+}
+
+/// The first currency of the region that is still in use
+fn read_region_currency(path: &Path, region: &str) -> Result<Option<String>, Error> {
+    let mut in_region = false;
+
+    for event in open(path)? {
+        match event.map_err(|source| Error::CannotReadXml {
+            path: path.to_path_buf(),
+            source,
+        })? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => match name.local_name.as_str() {
+                "region" => in_region = attr(&attributes, "iso3166") == Some(region),
+                "currency"
+                    if in_region
+                        && attr(&attributes, "to").is_none()
+                        && attr(&attributes, "tender") != Some("false") =>
+                {
+                    return Ok(attr(&attributes, "iso4217").map(str::to_string));
+                }
+                _ => {}
+            },
+            XmlEvent::EndElement { name } if name.local_name == "currencyData" => break,
+            _ => {}
+        }
+    }
+
+    Ok(None)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not open CLDR file `{}`", path.display())]
+    CannotOpenFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Could not read CLDR file `{}`", path.display())]
+    CannotReadXml {
+        path: PathBuf,
+        source: xml::reader::Error,
+    },
+    #[error("CLDR has no {symbol} symbol for locale `{locale}`")]
+    MissingSymbol { locale: String, symbol: String },
+}
@@ -1,94 +1,133 @@
 use super::*;
-use crate::pad::PadAdapter;
+use crate::xml_writer::XmlWriter;
 use std::io::{Result, Write};
-use xml::escape::escape_str_attribute as escape;
 
 pub trait ToXml {
-    fn write_xml(&self, w: impl Write) -> Result<()>;
+    /// Writes this as (part of) a document
+    fn to_xml<W: Write>(&self, w: &mut XmlWriter<W>) -> Result<()>;
+
+    fn write_xml(&self, w: impl Write) -> Result<()> {
+        let mut xml = XmlWriter::new(w);
+        self.to_xml(&mut xml)?;
+        xml.finish().map(drop)
+    }
 }
 
-impl ToXml for Keyboard {
-    fn write_xml(&self, mut w: impl Write) -> Result<()> {
-        writeln!(w, r#"<?xml version="1.0" encoding="UTF-8" ?>"#)?;
-        writeln!(w, r#"<!DOCTYPE keyboard SYSTEM "../dtd/ldmlKeyboard.dtd">"#)?;
-        writeln!(w, r#"<keyboard locale="{}">"#, escape(&self.locale))?;
+impl Keyboard {
+    /// Writes the keyboard with `header` as the comment that files in the
+    /// CLDR repository have before their root element
+    pub fn write_xml_with_header(&self, header: &[String], w: impl Write) -> Result<()> {
+        let mut xml = XmlWriter::new(w);
+        self.write_document(&mut xml, header)?;
+        xml.finish().map(drop)
+    }
+
+    fn write_document<W: Write>(&self, w: &mut XmlWriter<W>, header: &[String]) -> Result<()> {
+        w.declaration()?;
+        w.doctype(r#"keyboard SYSTEM "../dtd/ldmlKeyboard.dtd""#)?;
+        if !header.is_empty() {
+            w.comment(&format!("\n{}\n", header.join("\n")))?;
+        }
+        w.start("keyboard", &[("locale", &self.locale)])?;
 
-        let mut inner = PadAdapter::wrap(&mut w);
-        self.version.write_xml(&mut inner)?;
+        self.version.to_xml(w)?;
         for names in &self.names {
-            names.write_xml(&mut inner)?;
+            names.to_xml(w)?;
+        }
+        if let Some(settings) = self.settings.as_ref() {
+            settings.to_xml(w)?;
         }
         for keymap in &self.key_maps {
-            keymap.write_xml(&mut inner)?;
+            keymap.to_xml(w)?;
+        }
+        for transforms in self.transforms.iter().flatten() {
+            transforms.to_xml(w)?;
         }
 
-        writeln!(w, "</keyboard>")?;
-        Ok(())
+        w.end()
+    }
+}
+
+impl ToXml for Keyboard {
+    fn to_xml<W: Write>(&self, w: &mut XmlWriter<W>) -> Result<()> {
+        self.write_document(w, &[])
     }
 }
 
 impl ToXml for Version {
-    fn write_xml(&self, mut w: impl Write) -> Result<()> {
-        writeln!(
-            w,
-            r#"<version platform="{}" number="{}"/>"#,
-            escape(&self.platform),
-            escape(&self.number)
+    fn to_xml<W: Write>(&self, w: &mut XmlWriter<W>) -> Result<()> {
+        w.empty(
+            "version",
+            &[("platform", &self.platform), ("number", &self.number)],
         )
     }
 }
 
-impl ToXml for Names {
-    fn write_xml(&self, mut w: impl Write) -> Result<()> {
-        writeln!(w, "<names>")?;
+impl ToXml for Settings {
+    fn to_xml<W: Write>(&self, w: &mut XmlWriter<W>) -> Result<()> {
+        let mut attributes = vec![];
+        if let Some(failure) = self.transform_failure.as_ref() {
+            attributes.push(("transformFailure", failure.as_str()));
+        }
+        if let Some(partial) = self.transform_partial.as_ref() {
+            attributes.push(("transformPartial", partial.as_str()));
+        }
+        w.empty("settings", &attributes)
+    }
+}
 
-        let mut inner = PadAdapter::wrap(&mut w);
+impl ToXml for Names {
+    fn to_xml<W: Write>(&self, w: &mut XmlWriter<W>) -> Result<()> {
+        w.start("names", &[])?;
         for name in &self.values {
-            name.write_xml(&mut inner)?;
+            name.to_xml(w)?;
         }
-
-        writeln!(w, "</names>")?;
-        Ok(())
+        w.end()
     }
 }
 
 impl ToXml for Name {
-    fn write_xml(&self, mut w: impl Write) -> Result<()> {
-        writeln!(w, r#"<name value="{}"/>"#, self.value)
+    fn to_xml<W: Write>(&self, w: &mut XmlWriter<W>) -> Result<()> {
+        w.empty("name", &[("value", &self.value)])
     }
 }
 
 impl ToXml for KeyMap {
-    fn write_xml(&self, mut w: impl Write) -> Result<()> {
-        write!(w, "<keyMap")?;
+    fn to_xml<W: Write>(&self, w: &mut XmlWriter<W>) -> Result<()> {
+        let mut attributes = vec![];
         if let Some(modifiers) = self.modifiers.as_ref() {
-            write!(w, r#" modifiers="{}""#, escape(&modifiers))?;
+            attributes.push(("modifiers", modifiers.as_str()));
         }
-        writeln!(w, ">")?;
-
-        let mut inner = PadAdapter::wrap(&mut w);
+        w.start("keyMap", &attributes)?;
         for key in &self.keys {
-            key.write_xml(&mut inner)?;
+            key.to_xml(w)?;
         }
+        w.end()
+    }
+}
 
-        writeln!(w, "</keyMap>")?;
-        Ok(())
+impl ToXml for Transforms {
+    fn to_xml<W: Write>(&self, w: &mut XmlWriter<W>) -> Result<()> {
+        w.start("transforms", &[("type", &self.type_)])?;
+        for transform in &self.values {
+            w.empty(
+                "transform",
+                &[("from", &transform.from), ("to", &transform.to)],
+            )?;
+        }
+        w.end()
     }
 }
 
 impl ToXml for Map {
-    fn write_xml(&self, mut w: impl Write) -> Result<()> {
-        write!(w, r#"<map"#)?;
-        write!(w, r#" iso="{}""#, escape(&self.iso))?;
-        write!(w, r#" to="{}""#, escape(&self.to))?;
+    fn to_xml<W: Write>(&self, w: &mut XmlWriter<W>) -> Result<()> {
+        let mut attributes = vec![("iso", self.iso.as_str()), ("to", self.to.as_str())];
         if let Some(transform) = self.transform.as_ref() {
-            write!(w, r#" transform="{}""#, escape(&transform))?;
+            attributes.push(("transform", transform));
         }
         if let Some(long_press) = self.long_press.as_ref() {
-            write!(w, r#" longPress="{}""#, escape(&long_press))?;
+            attributes.push(("longPress", long_press));
         }
-        writeln!(w, r#"/>"#)?;
-
-        Ok(())
+        w.empty("map", &attributes)
     }
 }
@@ -0,0 +1,68 @@
+//! The adjacency graph of a mobile layer, see `Layout::adjacency_graph`
+
+use crate::{AdjacencyGraph, LazyLayout, MobileMode, Target};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// The layout file, in the `layouts` directory of a bundle
+    pub layout: PathBuf,
+    pub target: Target,
+    pub mode: MobileMode,
+}
+
+pub fn adjacency(options: &Options) -> Result<AdjacencyGraph, Error> {
+    // Only the modes of the target are needed
+    let layout = LazyLayout::load(&options.layout)
+        .and_then(|layout| layout.for_target(options.target))
+        .map_err(|source| Error::CannotLoad { source })?;
+    if !options.target.is_mobile() {
+        return Err(Error::NotMobile {
+            target: options.target,
+        });
+    }
+    layout
+        .adjacency_graph(options.target, options.mode)
+        .ok_or(Error::NoMode {
+            target: options.target,
+            mode: options.mode,
+        })
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not load layout")]
+    CannotLoad { source: crate::LoadError },
+
+    #[error("`{}` is not a mobile target", target)]
+    NotMobile { target: Target },
+
+    #[error("Layout has no `{}` mode for `{}`", mode, target)]
+    NoMode { target: Target, mode: MobileMode },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn example_layout_has_neighbours() {
+        let options = Options {
+            layout: "examples/sme.kbdgen/layouts/se.yaml".into(),
+            target: Target::Android,
+            mode: MobileMode::Default,
+        };
+        let graph = adjacency(&options).unwrap();
+        let key = graph.keys.values().find(|key| key.label == "s").unwrap();
+        assert!(key
+            .neighbours
+            .iter()
+            .any(|n| graph.keys[&n.key].label == "a"));
+
+        let options = Options {
+            target: Target::Win,
+            ..options
+        };
+        assert!(matches!(adjacency(&options), Err(Error::NotMobile { .. })));
+    }
+}
@@ -0,0 +1,215 @@
+//! Listing what a layout types, character by character
+//!
+//! Lookalikes such as U+0027 APOSTROPHE and U+02BC MODIFIER LETTER APOSTROPHE
+//! cannot be told apart when reviewing a YAML file. Annotating every output
+//! with its codepoints and character names makes them stand out.
+
+use crate::{models::Layout, KeyValue, Load, Target};
+use std::path::PathBuf;
+use unic_ucd_name::Name;
+use unicode_width::UnicodeWidthStr;
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// The layout file, in the `layouts` directory of a bundle
+    pub layout: PathBuf,
+    /// Only annotate the modes for this target
+    pub target: Option<Target>,
+}
+
+/// Something the layout types, and where it is defined
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Annotation {
+    /// Like `win default D01`, `mobile shift 1:3`, `longpress a` or `´ + a`
+    pub location: String,
+    pub output: String,
+}
+
+impl Annotation {
+    /// `U+XXXX` for each character of the output
+    pub fn codepoints(&self) -> Vec<String> {
+        self.output
+            .chars()
+            .map(|c| format!("U+{:04X}", c as u32))
+            .collect()
+    }
+
+    /// The Unicode name of each character of the output
+    pub fn names(&self) -> Vec<String> {
+        self.output
+            .chars()
+            .map(|c| match Name::of(c) {
+                Some(name) => name.to_string(),
+                None => "<unnamed>".to_string(),
+            })
+            .collect()
+    }
+}
+
+pub fn annotate(options: &Options) -> Result<Vec<Annotation>, Error> {
+    let layout = Layout::load(&options.layout).map_err(|source| Error::CannotLoad { source })?;
+    if let Some(target) = options.target {
+        if !layout.modes.has(target) {
+            return Err(Error::NoModes { target });
+        }
+    }
+    Ok(annotate_layout(&layout, options.target))
+}
+
+/// Every output of the modes (only those of `target`, if given), long-press
+/// keys and transforms of the layout
+pub fn annotate_layout(layout: &Layout, target: Option<Target>) -> Vec<Annotation> {
+    let mut annotations = vec![];
+    let mut push = |location: String, output: &str| {
+        if !output.is_empty() {
+            annotations.push(Annotation {
+                location,
+                output: output.to_string(),
+            });
+        }
+    };
+
+    let targets = layout.modes.available_modes();
+    for target in targets
+        .into_iter()
+        .filter(|&t| target.is_none() || target == Some(t))
+    {
+        if let Some(modes) = layout.modes.desktop_for(target) {
+            for (mode, key_map) in &modes.0 {
+                for (key, value) in &key_map.0 {
+                    if let KeyValue::Symbol(value) = value {
+                        push(format!("{} {} {}", target, mode, key), value);
+                    }
+                }
+            }
+        }
+        if let Some(modes) = layout.modes.mobile_for(target) {
+            for (mode, key_map) in &modes.0 {
+                for (row, keys) in key_map.0.iter().enumerate() {
+                    for (column, value) in keys.iter().enumerate() {
+                        if let KeyValue::Symbol(value) = value {
+                            let location =
+                                format!("{} {} {}:{}", target, mode, row + 1, column + 1);
+                            push(location, value);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    for (key, values) in layout.longpress.iter().flatten() {
+        for value in values.split_ascii_whitespace() {
+            push(format!("longpress {}", key), value);
+        }
+    }
+
+    for (dead_key, transform) in layout.transforms.iter().flatten() {
+        for (input, output) in transform {
+            push(format!("{} + {}", dead_key, input), output);
+        }
+    }
+
+    annotations
+}
+
+/// The annotations as a table, with the locations lined up
+pub fn format_table(annotations: &[Annotation]) -> String {
+    let location_width = annotations
+        .iter()
+        .map(|a| a.location.width())
+        .max()
+        .unwrap_or(0);
+    let output_width = annotations
+        .iter()
+        .map(|a| a.output.width())
+        .max()
+        .unwrap_or(0);
+
+    let mut table = String::new();
+    for annotation in annotations {
+        let codepoints = annotation.codepoints().join(" ");
+        table.push_str(&format!(
+            "{}{}  {}{}  {}  {}\n",
+            annotation.location,
+            " ".repeat(location_width - annotation.location.width()),
+            annotation.output,
+            " ".repeat(output_width - annotation.output.width()),
+            codepoints,
+            annotation.names().join(", ")
+        ));
+    }
+    table
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not load layout")]
+    CannotLoad { source: crate::LoadError },
+    #[error("Layout has no modes for `{}`", target)]
+    NoModes { target: Target },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAYOUT: &str = r#"
+displayNames:
+  en: Test
+modes:
+  mobile:
+    default: |
+      q w ʼ
+    shift: |
+      Q W '
+longpress:
+  a: á à
+transforms:
+  ´:
+    e: é
+"#;
+
+    #[test]
+    fn lookalikes_get_different_names() {
+        let layout: Layout = serde_yaml::from_str(LAYOUT).unwrap();
+        let annotations = annotate_layout(&layout, None);
+
+        let find = |location: &str| {
+            annotations
+                .iter()
+                .find(|a| a.location == location)
+                .unwrap_or_else(|| panic!("no annotation for {}", location))
+        };
+        assert_eq!(find("mobile default 1:3").codepoints(), vec!["U+02BC"]);
+        assert_eq!(
+            find("mobile default 1:3").names(),
+            vec!["MODIFIER LETTER APOSTROPHE"]
+        );
+        assert_eq!(find("mobile shift 1:3").names(), vec!["APOSTROPHE"]);
+        assert_eq!(find("´ + e").output, "é");
+        assert_eq!(
+            annotations
+                .iter()
+                .filter(|a| a.location == "longpress a")
+                .count(),
+            2
+        );
+
+        let table = format_table(&annotations);
+        assert!(table.contains("mobile shift 1:3    '  U+0027  APOSTROPHE\n"));
+    }
+
+    #[test]
+    fn decomposed_outputs_list_every_codepoint() {
+        let annotation = Annotation {
+            location: "´ + e".to_string(),
+            output: "e\u{301}".to_string(),
+        };
+        assert_eq!(annotation.codepoints(), vec!["U+0065", "U+0301"]);
+        assert_eq!(
+            annotation.names(),
+            vec!["LATIN SMALL LETTER E", "COMBINING ACUTE ACCENT"]
+        );
+    }
+}
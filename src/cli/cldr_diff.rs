@@ -0,0 +1,323 @@
+//! Comparing layouts imported from CLDR with CLDR as it is now
+//!
+//! `kbdgen cldr import` records where the modes of each target came from,
+//! see `Layout::cldr`. With the commit they were imported from, each key that
+//! differs between the layout and the current CLDR keyboard is put down to a
+//! change in CLDR since the import, a change to the layout that was never
+//! sent upstream, or a change on both sides. Without the commit, differences
+//! can only be listed.
+//!
+//! Keys are compared by what they type, desktop keys one by one and mobile
+//! keys a row at a time, so that moving a key along a row is one difference.
+
+use crate::{
+    cldr::Keyboard,
+    cli::{
+        from_cldr::{self, REPO_URL},
+        repos::{cldr_dir, git_output, update_repo},
+    },
+    models::{CldrSource, DesktopModes, Layout, MobileModes},
+    Load, ModeName, Target,
+};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    path::PathBuf,
+};
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// The layout file, in the `layouts` directory of a bundle
+    pub layout: PathBuf,
+    /// Compare with the CLDR checkout as it is, instead of updating it first
+    pub offline: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    /// Changed in CLDR since the import
+    Upstream,
+    /// Changed in the layout, and not in CLDR
+    Local,
+    /// Changed on both sides, differently
+    Both,
+    /// Different, but the import's commit is not known
+    Unknown,
+}
+
+/// A key, or a row of keys, that the layout and CLDR disagree on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Difference {
+    pub target: Target,
+    /// Like `shift D01`, or `default row 2` on mobile targets
+    pub key: String,
+    pub change: Change,
+    pub local: Option<String>,
+    pub upstream: Option<String>,
+    /// What the key was when it was imported, if that is known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub imported: Option<String>,
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = |value: &Option<String>| match value {
+            Some(value) => format!("{:?}", value),
+            None => "nothing".to_string(),
+        };
+        let change = match self.change {
+            Change::Upstream => "changed upstream",
+            Change::Local => "changed locally",
+            Change::Both => "changed on both sides",
+            Change::Unknown => "differs",
+        };
+        write!(
+            f,
+            "{} {}: {}, layout has {}, CLDR has {}",
+            self.target,
+            self.key,
+            change,
+            value(&self.local),
+            value(&self.upstream)
+        )?;
+        if let Some(imported) = &self.imported {
+            write!(f, ", imported {:?}", imported)?;
+        }
+        Ok(())
+    }
+}
+
+/// The differences between the layout and CLDR, for every target imported
+/// from CLDR
+pub fn diff_upstream(options: &Options) -> Result<Vec<Difference>, Error> {
+    let layout = Layout::load(&options.layout).map_err(|source| Error::CannotLoad { source })?;
+    let sources = match &layout.cldr {
+        Some(sources) if !sources.is_empty() => sources,
+        _ => {
+            return Err(Error::NotImported {
+                path: options.layout.clone(),
+            })
+        }
+    };
+
+    let dir = cldr_dir();
+    if !options.offline {
+        update_repo("cldr", &dir, REPO_URL).map_err(|source| Error::CannotUpdate { source })?;
+    }
+
+    let mut differences = vec![];
+    for (&target, source) in sources {
+        let upstream = from_cldr::parse_file(&dir.join(&source.file)).map_err(|e| {
+            Error::CannotReadUpstream {
+                file: source.file.clone(),
+                source: e,
+            }
+        })?;
+        let imported = imported_keyboard(source);
+        differences.extend(diff(&layout, target, &upstream, imported.as_ref())?);
+    }
+    Ok(differences)
+}
+
+/// The keyboard as it was at the commit it was imported from, fetching the
+/// commit into the CLDR checkout if it is not there
+fn imported_keyboard(source: &CldrSource) -> Option<Keyboard> {
+    let commit = source.commit.as_deref()?;
+    let dir = cldr_dir();
+    let object = format!("{}:{}", commit, source.file);
+    let xml = git_output(&dir, &["show", &object]).or_else(|| {
+        git_output(&dir, &["fetch", "--depth", "1", "origin", commit])?;
+        git_output(&dir, &["show", &object])
+    });
+    let xml = match xml {
+        Some(xml) => xml,
+        None => {
+            log::warn!(
+                "Could not get `{}` at commit {}, the differences cannot be told apart",
+                source.file,
+                commit
+            );
+            return None;
+        }
+    };
+    serde_xml_rs::from_str(&xml)
+        .map_err(|e| log::warn!("Could not read `{}` at {}: {}", source.file, commit, e))
+        .ok()
+}
+
+/// The differences between the modes of the layout for a target and a CLDR
+/// keyboard, told apart by the keyboard as it was imported if there is one
+pub fn diff(
+    layout: &Layout,
+    target: Target,
+    upstream: &Keyboard,
+    imported: Option<&Keyboard>,
+) -> Result<Vec<Difference>, Error> {
+    let keys = |keyboard: &Keyboard| -> Result<BTreeMap<String, String>, Error> {
+        let invalid = |source| Error::InvalidModifiers { source };
+        Ok(if target == Target::Mobile {
+            mobile_keys(&keyboard.to_mobile_modes().map_err(invalid)?)
+        } else {
+            desktop_keys(&keyboard.to_desktop_modes().map_err(invalid)?)
+        })
+    };
+    let upstream = keys(upstream)?;
+    let imported = imported.map(keys).transpose()?;
+    let local = match (
+        layout.modes.desktop_for(target),
+        layout.modes.mobile_for(target),
+    ) {
+        (Some(modes), _) => desktop_keys(modes),
+        (_, Some(modes)) => mobile_keys(modes),
+        _ => BTreeMap::new(),
+    };
+
+    let all = local
+        .keys()
+        .chain(upstream.keys())
+        .chain(imported.iter().flat_map(|keys| keys.keys()))
+        .collect::<BTreeSet<_>>();
+    Ok(all
+        .into_iter()
+        .filter_map(|key| {
+            let local = local.get(key).cloned();
+            let upstream = upstream.get(key).cloned();
+            if local == upstream {
+                return None;
+            }
+            let imported = imported.as_ref().map(|keys| keys.get(key).cloned());
+            let change = match &imported {
+                None => Change::Unknown,
+                Some(imported) if *imported == local => Change::Upstream,
+                Some(imported) if *imported == upstream => Change::Local,
+                Some(_) => Change::Both,
+            };
+            Some(Difference {
+                target,
+                key: key.clone(),
+                change,
+                local,
+                upstream,
+                imported: imported.flatten(),
+            })
+        })
+        .collect())
+}
+
+/// Modes are named by their modifiers in a fixed order, so that `alt+shift`
+/// and `shift+alt` are the same mode
+fn normalized_mode(mode: &str) -> String {
+    mode.parse::<ModeName>()
+        .map(|name| name.to_string())
+        .unwrap_or_else(|_| mode.to_string())
+}
+
+fn desktop_keys(modes: &DesktopModes) -> BTreeMap<String, String> {
+    modes
+        .0
+        .iter()
+        .flat_map(|(mode, key_map)| {
+            let mode = normalized_mode(mode);
+            key_map
+                .0
+                .iter()
+                .filter(|(_, value)| **value != crate::KeyValue::None)
+                .map(move |(key, value)| (format!("{} {}", mode, key), value.to_string()))
+        })
+        .collect()
+}
+
+fn mobile_keys(modes: &MobileModes) -> BTreeMap<String, String> {
+    modes
+        .0
+        .iter()
+        .flat_map(|(mode, key_map)| {
+            key_map.0.iter().enumerate().map(move |(row, keys)| {
+                let keys = keys.iter().map(|k| k.to_string()).collect::<Vec<_>>();
+                (format!("{} row {}", mode, row + 1), keys.join(" "))
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not load layout")]
+    CannotLoad { source: crate::LoadError },
+
+    #[error("`{}` was not imported from CLDR, it has no `cldr`", path.display())]
+    NotImported { path: PathBuf },
+
+    #[error("Could not update the CLDR repository")]
+    CannotUpdate { source: crate::cli::repos::Error },
+
+    #[error("Could not read `{}` from CLDR", file)]
+    CannotReadUpstream {
+        file: String,
+        source: from_cldr::Error,
+    },
+
+    #[error("Could not convert CLDR keyboard")]
+    InvalidModifiers {
+        source: crate::cldr::ir::ModifiersError,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyboard(keys: &str) -> Keyboard {
+        serde_xml_rs::from_str(&format!(
+            r#"<keyboard locale="zz-t-k0-windows">
+  <version platform="10" number="1"/>
+  <names><name value="Zed"/></names>
+  <keyMap>{}</keyMap>
+</keyboard>"#,
+            keys
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn differences_are_put_down_to_either_side() {
+        let imported = keyboard(
+            r#"<map iso="D01" to="q"/><map iso="D02" to="w"/><map iso="D03" to="e"/><map iso="D04" to="r"/>"#,
+        );
+        let upstream = keyboard(
+            r#"<map iso="D01" to="á"/><map iso="D02" to="w"/><map iso="D03" to="é"/><map iso="D04" to="r"/>"#,
+        );
+        let mut layout = Layout::default();
+        layout.modes.win = Some(imported.to_desktop_modes().unwrap());
+        let keys = &mut layout.modes.win.as_mut().unwrap().0;
+        let default = keys.values_mut().next().unwrap();
+        let mut set = |key: &str, value: &str| {
+            default
+                .0
+                .insert(key.parse().unwrap(), crate::KeyValue::Symbol(value.into()));
+        };
+        set("D02", "ŵ");
+        set("D03", "ē");
+
+        let changes = |imported| {
+            diff(&layout, Target::Win, &upstream, imported)
+                .unwrap()
+                .into_iter()
+                .map(|d| (d.key, d.change))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            changes(Some(&imported)),
+            vec![
+                ("default D01".to_string(), Change::Upstream),
+                ("default D02".to_string(), Change::Local),
+                ("default D03".to_string(), Change::Both),
+            ]
+        );
+        assert!(changes(None)
+            .iter()
+            .all(|(_, change)| *change == Change::Unknown));
+    }
+}
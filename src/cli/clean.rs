@@ -0,0 +1,164 @@
+//! Removing what generators wrote, see `crate::generator`
+
+use crate::{generator, Target};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub project_path: PathBuf,
+    pub output_path: PathBuf,
+    /// The targets to clean, or all of them if empty
+    pub targets: Vec<Target>,
+    /// The layouts to clean, or all of them if empty. Outputs with every
+    /// layout in them, like installers, are only removed without layouts.
+    pub layouts: Vec<String>,
+    /// Also remove the targets' caches
+    pub caches: bool,
+    /// List what would be removed without removing it
+    pub dry_run: bool,
+}
+
+/// Removes the outputs of the selected targets and layouts, returning what was
+/// removed
+///
+/// Anything in the bundle itself is left alone, even if the output directory
+/// is the bundle.
+pub fn clean(options: &Options) -> Result<Vec<PathBuf>, Error> {
+    let bundle =
+        super::load_bundle(&options.project_path).map_err(|source| Error::CannotLoad { source })?;
+    if let Some(layout) = options
+        .layouts
+        .iter()
+        .find(|name| !bundle.layouts.contains_key(*name))
+    {
+        return Err(Error::UnknownLayout {
+            layout: layout.clone(),
+        });
+    }
+
+    let mut generators = vec![];
+    for &target in &options.targets {
+        generators.push(generator::generator(target).ok_or(Error::NoGenerator { target })?);
+    }
+    if generators.is_empty() {
+        generators.extend_from_slice(generator::generators());
+    }
+
+    let mut layouts = options.layouts.clone();
+    if layouts.is_empty() {
+        layouts.extend(bundle.layouts.keys().cloned());
+        layouts.sort();
+    }
+
+    let bundle_path = options.project_path.canonicalize().ok();
+    let mut paths = vec![];
+    for generator in generators {
+        let mut patterns = layouts
+            .iter()
+            .flat_map(|layout| generator.layout_outputs(&bundle, layout))
+            .collect::<Vec<_>>();
+        if options.layouts.is_empty() {
+            patterns.extend(generator.bundle_outputs());
+        }
+        for pattern in patterns {
+            for path in generator::expand(&options.output_path, &pattern) {
+                if is_in(&path, bundle_path.as_deref()) {
+                    log::debug!("Keeping `{}`, which is in the bundle", path.display());
+                } else if !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+        }
+        if options.caches && options.layouts.is_empty() {
+            paths.extend(generator.caches(&bundle).into_iter().filter(|p| p.exists()));
+        }
+    }
+
+    if !options.dry_run {
+        for path in &paths {
+            log::info!("Removing `{}`…", path.display());
+            let result = if path.is_dir() {
+                std::fs::remove_dir_all(path)
+            } else {
+                std::fs::remove_file(path)
+            };
+            result.map_err(|source| Error::CannotRemove {
+                path: path.clone(),
+                source,
+            })?;
+        }
+    }
+
+    Ok(paths)
+}
+
+fn is_in(path: &Path, dir: Option<&Path>) -> bool {
+    match (path.canonicalize(), dir) {
+        (Ok(path), Some(dir)) => path.starts_with(dir),
+        _ => false,
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not load kbdgen bundle")]
+    CannotLoad { source: crate::LoadError },
+    #[error("The bundle has no layout `{}`", layout)]
+    UnknownLayout { layout: String },
+    #[error("There is no generator for `{}`", target)]
+    NoGenerator { target: Target },
+    #[error("Could not remove `{}`", path.display())]
+    CannotRemove {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(output: &Path, targets: Vec<Target>, layouts: Vec<&str>) -> Options {
+        Options {
+            project_path: "examples/sme.kbdgen".into(),
+            output_path: output.to_path_buf(),
+            targets,
+            layouts: layouts.into_iter().map(String::from).collect(),
+            caches: false,
+            dry_run: false,
+        }
+    }
+
+    #[test]
+    fn only_outputs_of_the_selection_are_removed() {
+        let output = tempfile::tempdir().unwrap();
+        let out = output.path();
+        std::fs::create_dir_all(out.join("linux")).unwrap();
+        std::fs::create_dir_all(out.join("deps/giellakbd-android")).unwrap();
+        for file in &["linux/se-SE.xkb", "kbdse-SE.klc", "notes.txt"] {
+            std::fs::write(out.join(file), "").unwrap();
+        }
+
+        let removed = clean(&options(out, vec![Target::X11], vec![])).unwrap();
+        assert_eq!(removed, vec![out.join("linux/se-SE.xkb")]);
+
+        // Android's app project has every layout in it
+        let removed = clean(&options(out, vec![], vec!["se-SE"])).unwrap();
+        assert_eq!(removed, vec![out.join("kbdse-SE.klc")]);
+        assert!(out.join("deps").exists());
+
+        let removed = clean(&options(out, vec![Target::Android], vec![])).unwrap();
+        assert_eq!(removed, vec![out.join("deps")]);
+        assert!(out.join("notes.txt").exists());
+    }
+
+    #[test]
+    fn the_bundle_is_left_alone() {
+        let options = options(Path::new("examples/sme.kbdgen"), vec![], vec![]);
+        let options = Options {
+            dry_run: true,
+            ..options
+        };
+        assert!(clean(&options).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,354 @@
+//! Comparing a layout with the one installed on this system
+//!
+//! Reads the installed layout back (or an exported copy of it) and reports
+//! every key, dead key and dead key combination that differs from the layout
+//! file, to check that an installer shipped what the YAML says.
+
+use crate::{
+    macos::{keylayout, KeyLayout, KeyOutput},
+    models::{IsoKey, Layout},
+    Load, ModifierSet, Target,
+};
+use std::{
+    fmt,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+use strum::IntoEnumIterator;
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub target: Target,
+    /// The layout file, in the `layouts` directory of a bundle
+    pub layout: PathBuf,
+    /// The installed layout to read, instead of looking in the system's
+    /// install locations
+    pub system_file: Option<PathBuf>,
+}
+
+/// How a key behaves, in either layout
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyBehavior {
+    Output(String),
+    Dead(String),
+    Missing,
+}
+
+impl fmt::Display for KeyBehavior {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyBehavior::Output(output) => write!(f, "{:?}", output),
+            KeyBehavior::Dead(key) => write!(f, "dead key {:?}", key),
+            KeyBehavior::Missing => write!(f, "nothing"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Difference {
+    /// No modifier combination of the installed layout selects this mode
+    Mode { mode: String },
+    Key {
+        mode: String,
+        key: IsoKey,
+        expected: KeyBehavior,
+        installed: KeyBehavior,
+    },
+    Combination {
+        dead_key: String,
+        input: String,
+        expected: String,
+        installed: Option<String>,
+    },
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Difference::Mode { mode } => write!(f, "mode `{}` is not installed", mode),
+            Difference::Key {
+                mode,
+                key,
+                expected,
+                installed,
+            } => write!(
+                f,
+                "{} {}: expected {}, installed {}",
+                mode, key, expected, installed
+            ),
+            Difference::Combination {
+                dead_key,
+                input,
+                expected,
+                installed,
+            } => write!(
+                f,
+                "{} + {}: expected {:?}, installed {}",
+                dead_key,
+                input,
+                expected,
+                installed
+                    .as_ref()
+                    .map(|i| format!("{:?}", i))
+                    .unwrap_or_else(|| "nothing".to_string())
+            ),
+        }
+    }
+}
+
+/// Where keyboard layouts are installed, for all users and for this one
+fn mac_layout_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![PathBuf::from("/Library/Keyboard Layouts")];
+    if let Some(home) = std::env::var_os("HOME") {
+        dirs.insert(0, Path::new(&home).join("Library/Keyboard Layouts"));
+    }
+    dirs
+}
+
+/// Finds `<name>.keylayout` in the install locations, either on its own or
+/// inside a keyboard layout bundle
+fn find_keylayout(name: &str) -> Option<PathBuf> {
+    let file_name = format!("{}.keylayout", name);
+    mac_layout_dirs().into_iter().find_map(|dir| {
+        let direct = dir.join(&file_name);
+        if direct.is_file() {
+            return Some(direct);
+        }
+        std::fs::read_dir(&dir)
+            .ok()?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path().join("Contents/Resources").join(&file_name))
+            .find(|path| path.is_file())
+    })
+}
+
+pub fn compare_system(options: &Options) -> Result<Vec<Difference>, Error> {
+    let layout = Layout::load(&options.layout).map_err(|source| Error::CannotLoad { source })?;
+    let name = options
+        .layout
+        .file_stem()
+        .ok_or_else(|| Error::MalformedFilename {
+            path: options.layout.clone(),
+        })?
+        .to_string_lossy()
+        .to_string();
+
+    match options.target {
+        Target::Mac => {
+            let path = match &options.system_file {
+                Some(path) => path.clone(),
+                None => find_keylayout(&name).ok_or(Error::NotInstalled { name })?,
+            };
+            log::info!("Comparing with `{}`", path.display());
+            let file = File::open(&path).map_err(|source| Error::CannotRead {
+                path: path.clone(),
+                source,
+            })?;
+            let installed = KeyLayout::parse(BufReader::new(file))
+                .map_err(|source| Error::CannotParseKeylayout { path, source })?;
+            compare_mac(&layout, &installed)
+        }
+        target => Err(Error::UnsupportedTarget { target }),
+    }
+}
+
+/// Differences between the `mac` modes of a layout, or the `desktop` modes
+/// it falls back to, and an installed keylayout
+pub fn compare_mac(layout: &Layout, installed: &KeyLayout) -> Result<Vec<Difference>, Error> {
+    let target = Target::Mac;
+    let (_, modes) = layout
+        .modes
+        .resolve_desktop(target)
+        .ok_or(Error::NoModes { target })?;
+
+    let mut differences = vec![];
+    for (name, key_map) in &modes.0 {
+        let mode: ModifierSet = match name.parse() {
+            Ok(mode) => mode,
+            Err(e) => {
+                log::warn!("Skipping mode `{}`: {}", name, e);
+                continue;
+            }
+        };
+        if !installed.selects(&mode) {
+            differences.push(Difference::Mode { mode: name.clone() });
+            continue;
+        }
+
+        for key in IsoKey::iter() {
+            let expected = match key_map.get_string(key) {
+                Some(value) if layout.is_dead_key(target, &mode, &value) => {
+                    KeyBehavior::Dead(value)
+                }
+                Some(value) => KeyBehavior::Output(value),
+                // Keys the layout leaves out are filled in by the generator
+                None => continue,
+            };
+            let installed = match installed.key(&mode, key) {
+                Some(KeyOutput::Output(output)) => KeyBehavior::Output(output),
+                Some(KeyOutput::Dead(terminator)) => KeyBehavior::Dead(terminator),
+                None => KeyBehavior::Missing,
+            };
+            if expected != installed {
+                differences.push(Difference::Key {
+                    mode: name.clone(),
+                    key,
+                    expected,
+                    installed,
+                });
+            }
+        }
+    }
+
+    let combinations = installed.dead_key_combinations();
+    for (dead_key, transforms) in layout.transforms.iter().flatten() {
+        for (input, expected) in transforms {
+            let installed = combinations
+                .get(dead_key)
+                .and_then(|c| c.get(input))
+                .cloned();
+            if installed.as_ref() != Some(expected) {
+                differences.push(Difference::Combination {
+                    dead_key: dead_key.clone(),
+                    input: input.clone(),
+                    expected: expected.clone(),
+                    installed,
+                });
+            }
+        }
+    }
+
+    Ok(differences)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not load layout")]
+    CannotLoad { source: crate::LoadError },
+    #[error("Could not parse file with malformed name: `{}`", path.display())]
+    MalformedFilename { path: PathBuf },
+    #[error("Layout has no modes for `{}`", target)]
+    NoModes { target: Target },
+    #[error("Comparing with installed `{}` layouts is not supported", target)]
+    UnsupportedTarget { target: Target },
+    #[error(
+        "No installed keylayout named `{}` found, pass it with --system-file",
+        name
+    )]
+    NotInstalled { name: String },
+    #[error("Could not read `{}`", path.display())]
+    CannotRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Could not parse `{}`", path.display())]
+    CannotParseKeylayout {
+        path: PathBuf,
+        source: keylayout::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAYOUT: &str = r#"
+displayNames:
+  en: Test
+modes:
+  mac:
+    default: |
+      § 1 2 3 4 5 6 7 8 9 0 + ´
+        q w e r t y u i o p å ¨
+        a s d f g h j k l ö ä '
+      < z x c v b n m , . -
+    ctrl: |
+      § 1 2 3 4 5 6 7 8 9 0 + ´
+        q w e r t y u i o p å ¨
+        a s d f g h j k l ö ä '
+      < z x c v b n m , . -
+deadKeys:
+  mac:
+    default: ['´']
+transforms:
+  ´:
+    a: á
+    e: é
+"#;
+
+    const KEYLAYOUT: &str = r#"<?xml version="1.1" encoding="UTF-8"?>
+<keyboard group="126" id="-1" name="Test">
+  <layouts>
+    <layout first="0" last="17" mapSet="default" modifiers="modifiers"/>
+  </layouts>
+  <modifierMap id="modifiers" defaultIndex="0">
+    <keyMapSelect mapIndex="0">
+      <modifier keys="command?"/>
+    </keyMapSelect>
+  </modifierMap>
+  <keyMapSet id="default">
+    <keyMap index="0">
+      <key code="0" action="a"/>
+      <key code="24" action="dead"/>
+      <key code="12" output="Q"/>
+    </keyMap>
+  </keyMapSet>
+  <actions>
+    <action id="a">
+      <when state="none" output="a"/>
+      <when state="acute" output="á"/>
+    </action>
+    <action id="dead">
+      <when state="none" next="acute"/>
+    </action>
+  </actions>
+  <terminators>
+    <when state="acute" output="´"/>
+  </terminators>
+</keyboard>
+"#;
+
+    #[test]
+    fn reports_keys_and_combinations_that_differ() {
+        let layout: Layout = serde_yaml::from_str(LAYOUT).unwrap();
+        let installed = KeyLayout::parse(KEYLAYOUT.as_bytes()).unwrap();
+        let differences = compare_mac(&layout, &installed).unwrap();
+
+        assert!(differences.contains(&Difference::Key {
+            mode: "default".to_string(),
+            key: IsoKey::D01,
+            expected: KeyBehavior::Output("q".to_string()),
+            installed: KeyBehavior::Output("Q".to_string()),
+        }));
+        assert!(differences.contains(&Difference::Key {
+            mode: "default".to_string(),
+            key: IsoKey::C02,
+            expected: KeyBehavior::Output("s".to_string()),
+            installed: KeyBehavior::Missing,
+        }));
+        assert!(differences.contains(&Difference::Mode {
+            mode: "ctrl".to_string()
+        }));
+        assert_eq!(
+            differences
+                .iter()
+                .filter(|d| matches!(d, Difference::Combination { .. }))
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec![r#"´ + e: expected "é", installed nothing"#]
+        );
+
+        // The dead key and the `a` key match
+        assert!(!differences.iter().any(|d| matches!(
+            d,
+            Difference::Key {
+                key: IsoKey::E12,
+                ..
+            } | Difference::Key {
+                key: IsoKey::C01,
+                ..
+            }
+        )));
+    }
+}
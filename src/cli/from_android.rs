@@ -0,0 +1,561 @@
+//! Importing existing Android keyboards into a bundle
+//!
+//! Two kinds of files are read:
+//!
+//! - key character maps (`.kcm`) of physical keyboards, the inverse of
+//!   `to_android_kcm`, into the `desktop` modes;
+//! - AOSP LatinIME keyboard XML, with its `<Row>`s of `<Key>`s and the files
+//!   they `<include>`, into the `android` modes, with `moreKeys` as
+//!   long-press alternatives.
+//!
+//! See <https://source.android.com/devices/input/key-character-map-files>.
+
+use super::to_android_kcm::{key_code, DEAD_KEY_ACCENTS};
+use crate::{
+    bundle::keys::KeyValue,
+    models::{DesktopModes, IsoKey, Layout, MobileModes},
+    DesktopKeyMap, Load, MobileKeyMap, MobileMode, Modifier, ModifierSet, Target,
+};
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+use strum::IntoEnumIterator;
+use xml::{
+    attribute::OwnedAttribute,
+    reader::{EventReader, XmlEvent},
+};
+
+/// How deep `<include>`s may nest, to stop on includes of themselves
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    /// The bundle to import into
+    pub project_path: PathBuf,
+    /// `.kcm` and AOSP keyboard `.xml` files
+    pub files: Vec<PathBuf>,
+    /// The layout to import into, instead of the one named after the first
+    /// file
+    pub layout: Option<String>,
+    /// Replace the modes of targets the layout already has
+    pub overwrite: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportReport {
+    pub layout: String,
+    /// Whether the layout did not exist before
+    pub created: bool,
+    pub imported: Vec<Target>,
+    /// Targets that the layout already had modes for
+    pub skipped: Vec<Target>,
+}
+
+/// The desktop modes and dead keys of a `.kcm`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KcmImport {
+    pub modes: DesktopModes,
+    /// Dead keys by mode name, as the spacing accents of Android's combining
+    /// accents
+    pub dead_keys: BTreeMap<String, Vec<String>>,
+}
+
+/// The mode of a `.kcm` behaviour like `shift+ralt`, if kbdgen has one
+fn behaviour_mode(behaviour: &str) -> Option<ModifierSet> {
+    behaviour
+        .split('+')
+        .map(str::trim)
+        .filter(|part| *part != "base")
+        .map(|part| match part {
+            "shift" | "lshift" | "rshift" => Some(Modifier::Shift),
+            "capslock" => Some(Modifier::Caps),
+            "alt" | "lalt" | "ralt" => Some(Modifier::Alt),
+            "ctrl" | "lctrl" | "rctrl" => Some(Modifier::Ctrl),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The character of a `.kcm` character literal like `'a'` or `'á'`
+fn parse_char_literal(value: &str) -> Option<char> {
+    let inner = value.strip_prefix('\'')?.strip_suffix('\'')?;
+    let mut chars = inner.chars();
+    match (chars.next()?, chars.as_str()) {
+        ('\\', "n") => Some('\n'),
+        ('\\', "t") => Some('\t'),
+        ('\\', escaped) if escaped.starts_with('u') => {
+            std::char::from_u32(u32::from_str_radix(&escaped[1..], 16).ok()?)
+        }
+        ('\\', escaped) => escaped.chars().next(),
+        (c, "") => Some(c),
+        _ => None,
+    }
+}
+
+/// Reads the keys of a `.kcm` that are on the alphanumeric section
+pub fn parse_kcm(source: &str) -> KcmImport {
+    let mut keys: BTreeMap<ModifierSet, BTreeMap<IsoKey, KeyValue>> = BTreeMap::new();
+    let mut dead_keys: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut current: Option<IsoKey> = None;
+
+    for line in source.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("key ") {
+            let code = rest.trim_end_matches('{').trim();
+            current = IsoKey::iter().find(|&key| key_code(key) == code);
+            continue;
+        }
+        if line == "}" {
+            current = None;
+            continue;
+        }
+        let key = match current {
+            Some(key) => key,
+            None => continue,
+        };
+        let (behaviours, value) = match line.split_once(':') {
+            Some(split) => split,
+            None => continue,
+        };
+        let c = match parse_char_literal(value.trim()) {
+            Some(c) => c,
+            // `none`, `fallback` and `replace` have no character
+            None => continue,
+        };
+
+        // Android types combining accents as dead keys
+        let dead_key = DEAD_KEY_ACCENTS
+            .iter()
+            .find(|(_, accent)| *accent == c)
+            .map(|(spacing, _)| spacing.to_string());
+        let value = dead_key.clone().unwrap_or_else(|| c.to_string());
+
+        for behaviour in behaviours.split(',').map(str::trim) {
+            if behaviour == "label" || behaviour == "number" {
+                continue;
+            }
+            let mode = match behaviour_mode(behaviour) {
+                Some(mode) => mode,
+                None => {
+                    log::debug!("Skipping `{}`, which kbdgen has no mode for", behaviour);
+                    continue;
+                }
+            };
+            if let Some(dead_key) = &dead_key {
+                let list = dead_keys.entry(mode.to_string()).or_default();
+                if !list.contains(dead_key) {
+                    list.push(dead_key.clone());
+                }
+            }
+            keys.entry(mode)
+                .or_default()
+                .insert(key, KeyValue::Symbol(value.clone()));
+        }
+    }
+
+    KcmImport {
+        modes: DesktopModes(
+            keys.into_iter()
+                .map(|(mode, keys)| (mode.to_string(), DesktopKeyMap(keys)))
+                .collect(),
+        ),
+        dead_keys,
+    }
+}
+
+/// The mobile modes and long-press alternatives of an AOSP keyboard
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AospImport {
+    pub modes: MobileModes,
+    pub longpress: BTreeMap<String, String>,
+}
+
+/// A `<Key>` of an AOSP keyboard
+#[derive(Debug, Clone, PartialEq)]
+struct AospKey {
+    label: String,
+    more_keys: Vec<String>,
+}
+
+fn attr<'a>(attributes: &'a [OwnedAttribute], name: &str) -> Option<&'a str> {
+    attributes
+        .iter()
+        .find(|a| a.name.local_name == name)
+        .map(|a| a.value.as_str())
+}
+
+/// The text a key spec like `a` or `ä|ae` types, unless it is a special
+/// key like `!code/key_shift`
+fn key_spec_label(spec: &str) -> Option<String> {
+    if spec.is_empty() || spec.starts_with('!') {
+        return None;
+    }
+    let label = spec.split('|').next().unwrap_or(spec);
+    Some(label.replace('\\', ""))
+}
+
+/// The entries of a `moreKeys` list, separated by unescaped commas, leaving
+/// out references to resources and flags
+fn parse_more_keys(value: &str) -> Vec<String> {
+    let mut entries = vec![];
+    let mut entry = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => entry.extend(chars.next()),
+            ',' => entries.push(std::mem::take(&mut entry)),
+            c => entry.push(c),
+        }
+    }
+    entries.push(entry);
+
+    entries
+        .iter()
+        .filter(|entry| *entry != "%")
+        .filter_map(|entry| key_spec_label(entry.trim()))
+        .collect()
+}
+
+/// Reads the rows of an AOSP keyboard file, following `<include>`s relative
+/// to its directory. The keys of a file included in a row go into that row.
+fn read_aosp_rows(
+    path: &Path,
+    rows: &mut Vec<Vec<AospKey>>,
+    depth: usize,
+    mut in_row: bool,
+) -> Result<(), Error> {
+    if depth > MAX_INCLUDE_DEPTH {
+        return Err(Error::IncludesTooDeep {
+            path: path.to_path_buf(),
+        });
+    }
+    let file = File::open(path).map_err(|source| Error::CannotRead {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let mut in_case = 0;
+
+    for event in EventReader::new(BufReader::new(file)) {
+        match event.map_err(|source| Error::CannotReadXml {
+            path: path.to_path_buf(),
+            source,
+        })? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
+                // Only the `<default>` of a `<switch>` is imported
+                if name.local_name == "case" || in_case > 0 {
+                    in_case += 1;
+                    continue;
+                }
+                match name.local_name.as_str() {
+                    "Row" => {
+                        rows.push(vec![]);
+                        in_row = true;
+                    }
+                    "Key" if in_row => {
+                        let spec = attr(&attributes, "keySpec").or(attr(&attributes, "keyLabel"));
+                        if let Some(label) = spec.and_then(key_spec_label) {
+                            let more_keys = ["moreKeys", "additionalMoreKeys"]
+                                .iter()
+                                .filter_map(|name| attr(&attributes, name))
+                                .flat_map(parse_more_keys)
+                                .collect();
+                            if let Some(row) = rows.last_mut() {
+                                row.push(AospKey { label, more_keys });
+                            }
+                        }
+                    }
+                    "include" => {
+                        let layout = attr(&attributes, "keyboardLayout").unwrap_or_default();
+                        if let Some(name) = layout.strip_prefix("@xml/") {
+                            let included = path.with_file_name(format!("{}.xml", name));
+                            read_aosp_rows(&included, rows, depth + 1, in_row)?;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            XmlEvent::EndElement { name } => {
+                if in_case > 0 {
+                    in_case -= 1;
+                } else if name.local_name == "Row" {
+                    in_row = false;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// The uppercase of a label that is a lowercase letter
+fn shifted(label: &str) -> String {
+    if label.chars().all(char::is_lowercase) {
+        label.to_uppercase()
+    } else {
+        label.to_string()
+    }
+}
+
+/// Reads an AOSP keyboard into `default` and `shift` modes, with the
+/// `moreKeys` of each key as its long-press alternatives
+pub fn read_aosp(path: &Path) -> Result<AospImport, Error> {
+    let mut rows = vec![];
+    read_aosp_rows(path, &mut rows, 0, false)?;
+    rows.retain(|row| !row.is_empty());
+
+    let mut import = AospImport::default();
+    let mut default = vec![];
+    let mut shift = vec![];
+    for row in &rows {
+        let symbols = |f: &dyn Fn(&str) -> String| {
+            row.iter()
+                .map(|key| KeyValue::Symbol(f(&key.label)))
+                .collect::<Vec<_>>()
+        };
+        default.push(symbols(&|label| label.to_string()));
+        shift.push(symbols(&shifted));
+
+        for key in row.iter().filter(|key| !key.more_keys.is_empty()) {
+            import
+                .longpress
+                .insert(key.label.clone(), key.more_keys.join(" "));
+            let upper = shifted(&key.label);
+            if upper != key.label {
+                let more_keys = key.more_keys.iter().map(|more| shifted(more));
+                import
+                    .longpress
+                    .insert(upper, more_keys.collect::<Vec<_>>().join(" "));
+            }
+        }
+    }
+
+    import
+        .modes
+        .0
+        .insert(MobileMode::Default, MobileKeyMap(default));
+    import
+        .modes
+        .0
+        .insert(MobileMode::Shift, MobileKeyMap(shift));
+    Ok(import)
+}
+
+/// The layout name a file suggests, like `sme` for `keyboard_layout_sme.kcm`
+/// or `rows_sme.xml`
+fn layout_name(path: &Path) -> String {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    ["keyboard_layout_", "rows_", "kbd_"]
+        .iter()
+        .find_map(|prefix| stem.strip_prefix(prefix))
+        .unwrap_or(&stem)
+        .to_string()
+}
+
+/// Imports `.kcm` files into the `desktop` modes and AOSP keyboards into the
+/// `android` modes of a layout, creating the layout if needed
+///
+/// Like `cldr import`, only targets without modes are filled unless
+/// `overwrite` is set.
+pub fn import(options: &ImportOptions) -> Result<ImportReport, Error> {
+    let name = match (&options.layout, options.files.first()) {
+        (Some(name), _) => name.clone(),
+        (None, Some(file)) => layout_name(file),
+        (None, None) => return Err(Error::NoFiles),
+    };
+    let path = crate::layout_file(&options.project_path, &name)
+        .map_err(|source| Error::InvalidLayoutName { source })?;
+
+    let created = !path.exists();
+    let mut layout = if created {
+        Layout::default()
+    } else {
+        Layout::load(&path).map_err(|source| Error::CannotLoadLayout { source })?
+    };
+    let mut report = ImportReport {
+        layout: name,
+        created,
+        ..Default::default()
+    };
+
+    for file in &options.files {
+        let is_kcm = file.extension() == Some(OsStr::new("kcm"));
+        let target = if is_kcm {
+            Target::Desktop
+        } else {
+            Target::Android
+        };
+        let has_modes = match target {
+            Target::Desktop => layout.modes.desktop.is_some(),
+            _ => layout.modes.android.is_some(),
+        };
+        if has_modes && !options.overwrite {
+            report.skipped.push(target);
+            continue;
+        }
+
+        if is_kcm {
+            let source = std::fs::read_to_string(file).map_err(|source| Error::CannotRead {
+                path: file.clone(),
+                source,
+            })?;
+            let kcm = parse_kcm(&source);
+            layout.modes.desktop = Some(kcm.modes);
+            let dead_keys = layout.dead_keys.get_or_insert_with(Default::default);
+            dead_keys.remove(&Target::Desktop);
+            if !kcm.dead_keys.is_empty() {
+                dead_keys.insert(Target::Desktop, kcm.dead_keys);
+            }
+        } else {
+            let aosp = read_aosp(file)?;
+            layout.modes.android = Some(aosp.modes);
+            if !aosp.longpress.is_empty() {
+                layout
+                    .longpress
+                    .get_or_insert_with(Default::default)
+                    .extend(aosp.longpress);
+            }
+        }
+        report.imported.push(target);
+    }
+
+    if matches!(&layout.dead_keys, Some(dead_keys) if dead_keys.is_empty()) {
+        layout.dead_keys = None;
+    }
+
+    if !report.imported.is_empty() {
+        let yaml = serde_yaml::to_string(&layout.without_derived_modes())
+            .map_err(|source| Error::CannotSerialize { source })?;
+        std::fs::create_dir_all(path.parent().expect("is in layouts"))
+            .and_then(|_| std::fs::write(&path, yaml))
+            .map_err(|source| Error::CannotWrite { path, source })?;
+    }
+
+    Ok(report)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("No Android keyboard files given")]
+    NoFiles,
+    #[error("Cannot import into this layout")]
+    InvalidLayoutName { source: crate::NameError },
+    #[error("Could not read `{}`", path.display())]
+    CannotRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Could not read XML of `{}`", path.display())]
+    CannotReadXml {
+        path: PathBuf,
+        source: xml::reader::Error,
+    },
+    #[error("Includes nest too deep at `{}`", path.display())]
+    IncludesTooDeep { path: PathBuf },
+    #[error("Could not load the layout to import into")]
+    CannotLoadLayout { source: crate::LoadError },
+    #[error("Could not serialize layout")]
+    CannotSerialize { source: serde_yaml::Error },
+    #[error("Could not write `{}`", path.display())]
+    CannotWrite {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_character_maps_become_desktop_modes() {
+        let kcm = parse_kcm(
+            r"type OVERLAY
+
+# The acute accent is a dead key
+key EQUALS {
+    label:                  '́'
+    base:                   '́'
+    shift:                  '`'
+}
+
+key Q {
+    label:                  'Á'
+    base:                   'á'
+    shift, capslock:        'Á'
+    ralt:                   'q'
+    meta:                   none
+}
+
+key SPACE {
+    base:                   ' '
+}
+",
+        );
+
+        let default = &kcm.modes.0["default"];
+        assert_eq!(default.get_string(IsoKey::E12).as_deref(), Some("´"));
+        assert_eq!(default.get_string(IsoKey::D01).as_deref(), Some("á"));
+        assert_eq!(
+            kcm.modes.0["caps"].get_string(IsoKey::D01).as_deref(),
+            Some("Á")
+        );
+        assert_eq!(
+            kcm.modes.0["alt"].get_string(IsoKey::D01).as_deref(),
+            Some("q")
+        );
+        assert_eq!(kcm.dead_keys["default"], vec!["´".to_string()]);
+    }
+
+    #[test]
+    fn aosp_rows_and_more_keys_are_read_through_includes() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("rows_sme.xml"),
+            r#"<merge xmlns:latin="http://schemas.android.com/apk/res-auto">
+    <Row>
+        <include latin:keyboardLayout="@xml/rowkeys_sme1" />
+    </Row>
+    <Row>
+        <Key latin:keySpec="!code/key_shift" />
+        <Key latin:keySpec="z" />
+        <switch>
+            <case latin:languageCode="fi"><Key latin:keySpec="x" /></case>
+            <default><Key latin:keySpec="č" latin:moreKeys="ç,\,,!text/morekeys_c" /></default>
+        </switch>
+    </Row>
+</merge>"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("rowkeys_sme1.xml"),
+            r#"<merge xmlns:latin="http://schemas.android.com/apk/res-auto">
+    <Key latin:keySpec="á" latin:moreKeys="à,â" />
+    <Key latin:keySpec="š" />
+</merge>"#,
+        )
+        .unwrap();
+
+        let aosp = read_aosp(&dir.path().join("rows_sme.xml")).unwrap();
+        let shift = &aosp.modes.0[&MobileMode::Shift];
+        assert_eq!(shift.0[0][0], KeyValue::Symbol("Á".into()));
+        assert_eq!(
+            aosp.modes.0[&MobileMode::Default].0[1],
+            vec![KeyValue::Symbol("z".into()), KeyValue::Symbol("č".into())]
+        );
+        assert_eq!(aosp.longpress["á"], "à â");
+        assert_eq!(aosp.longpress["Á"], "À Â");
+        assert_eq!(aosp.longpress["č"], "ç ,");
+    }
+}
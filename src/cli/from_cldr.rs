@@ -1,11 +1,63 @@
 use crate::{
     bundle::Save,
     cldr::Keyboard,
-    cli::repos::{cldr_dir, update_repo},
+    cli::repos::{cldr_dir, git_output, update_repo},
+    models::{CldrSource, Layout},
+    Load, Target,
 };
-use std::{collections::BTreeMap, path::Path};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+pub(crate) const REPO_URL: &str = "https://github.com/unicode-org/cldr";
 
-const REPO_URL: &str = "https://github.com/unicode-org/cldr";
+/// The directory of the CLDR repository with the keyboards of a target
+pub(crate) fn platform_dir(target: Target) -> Option<&'static str> {
+    match target {
+        Target::Android | Target::Mobile => Some("android"),
+        Target::Win => Some("windows"),
+        Target::Mac => Some("osx"),
+        Target::Chrome => Some("chromeos"),
+        _ => None,
+    }
+}
+
+/// Where a keyboard file is in the CLDR repository, and at which commit if
+/// the file is in a checkout of it
+///
+/// Files outside a checkout are assumed to be where CLDR keeps keyboards of
+/// their target.
+pub(crate) fn cldr_source(path: &Path, target: Target) -> CldrSource {
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let in_checkout = git_output(dir, &["rev-parse", "--show-toplevel"]).and_then(|root| {
+        let path = path.canonicalize().ok()?;
+        let root = Path::new(&root).canonicalize().ok()?;
+        let file = path
+            .strip_prefix(root)
+            .ok()?
+            .to_string_lossy()
+            .replace('\\', "/");
+        if !file.starts_with("keyboards/") {
+            return None;
+        }
+        Some(CldrSource {
+            file,
+            commit: git_output(dir, &["rev-parse", "HEAD"]),
+        })
+    });
+    in_checkout.unwrap_or_else(|| CldrSource {
+        file: format!(
+            "keyboards/{}/{}",
+            platform_dir(target).unwrap_or_default(),
+            path.file_name().unwrap_or_default().to_string_lossy()
+        ),
+        commit: None,
+    })
+}
 
 pub fn cldr_to_kbdgen(output: &Path, bundle_name: &str) -> Result<(), Error> {
     update_repo("cldr", &cldr_dir(), REPO_URL)
@@ -17,28 +69,39 @@ pub fn cldr_to_kbdgen(output: &Path, bundle_name: &str) -> Result<(), Error> {
 
     let mut modes = crate::models::Modes::default();
 
-    let xml_map: Vec<Keyboard> = locale
+    let xml_map: Vec<(Keyboard, PathBuf)> = locale
         .1
         .into_iter()
         .map(|(key, mut v)| {
             v.sort();
             let last = v.last().unwrap();
-            parse_path(&key, last)
+            parse_path(&key, last).map(|keyboard| (keyboard, cldr_path(&key, last)))
         })
         .collect::<Result<_, _>>()?;
 
-    for keyboard in xml_map {
-        match keyboard.mode_name() {
-            "mobile" => modes.mobile = Some(keyboard.to_mobile_modes()),
-            "mac" => modes.mac = Some(keyboard.to_desktop_modes()),
-            "win" => modes.win = Some(keyboard.to_desktop_modes()),
-            "chrome" => modes.chrome = Some(keyboard.to_desktop_modes()),
+    let mut sources = std::collections::BTreeMap::new();
+    for (keyboard, path) in &xml_map {
+        if let Some(target) = keyboard.target() {
+            sources.insert(target, cldr_source(path, target));
+        }
+        let invalid = |source| Error::InvalidModifiers { source };
+        match keyboard.target() {
+            Some(Target::Mobile) => {
+                modes.mobile = Some(keyboard.to_mobile_modes().map_err(invalid)?)
+            }
+            Some(Target::Mac) => modes.mac = Some(keyboard.to_desktop_modes().map_err(invalid)?),
+            Some(Target::Win) => modes.win = Some(keyboard.to_desktop_modes().map_err(invalid)?),
+            Some(Target::Chrome) => {
+                modes.chrome = Some(keyboard.to_desktop_modes().map_err(invalid)?)
+            }
             _ => {}
         }
     }
 
     let mut layout = crate::models::Layout::default();
     layout.modes = modes;
+    layout.transform_partial = xml_map.iter().find_map(|(k, _)| k.transform_partial());
+    layout.cldr = Some(sources);
 
     let mut bundle = crate::bundle::ProjectBundle::default();
     bundle.layouts.insert(locale.0, layout);
@@ -57,18 +120,156 @@ pub fn cldr_to_kbdgen(output: &Path, bundle_name: &str) -> Result<(), Error> {
     Ok(())
 }
 
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    /// The bundle to import into
+    pub project_path: PathBuf,
+    /// The CLDR keyboard files
+    pub files: Vec<PathBuf>,
+    /// The layout to import into, instead of the one named after the
+    /// keyboards' locale
+    pub layout: Option<String>,
+    /// Replace the modes of targets the layout already has
+    pub overwrite: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportReport {
+    pub layout: String,
+    /// Whether the layout did not exist before
+    pub created: bool,
+    pub imported: Vec<Target>,
+    /// Targets that the layout already had modes for
+    pub skipped: Vec<Target>,
+}
+
+/// Imports the modes of CLDR keyboard files into a layout of an existing
+/// bundle, creating the layout if needed
+///
+/// Only targets without modes are filled, unless `overwrite` is set, so that
+/// hand-made changes to the layout survive importing a newer CLDR release.
+pub fn import(options: &ImportOptions) -> Result<ImportReport, Error> {
+    let keyboards = options
+        .files
+        .iter()
+        .map(|path| parse_file(path))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let name = match &options.layout {
+        Some(name) => name.clone(),
+        None => {
+            let keyboard = keyboards.first().ok_or(Error::NoFiles)?;
+            keyboard
+                .locale
+                .split("-t-")
+                .next()
+                .unwrap_or_default()
+                .to_string()
+        }
+    };
+    let path = crate::layout_file(&options.project_path, &name)
+        .map_err(|source| Error::InvalidLayoutName { source })?;
+
+    let created = !path.exists();
+    let mut layout = if created {
+        Layout::default()
+    } else {
+        Layout::load(&path).map_err(|source| Error::CannotLoadLayout { source })?
+    };
+    let mut report = ImportReport {
+        layout: name,
+        created,
+        ..Default::default()
+    };
+
+    for (keyboard, file) in keyboards.iter().zip(&options.files) {
+        let target = keyboard.target().ok_or_else(|| Error::UnknownTarget {
+            path: file.clone(),
+            locale: keyboard.locale.clone(),
+        })?;
+        let invalid = |source| Error::InvalidModifiers { source };
+        let modes = &mut layout.modes;
+        let has_modes = match target {
+            Target::Mobile => modes.mobile.is_some(),
+            Target::Mac => modes.mac.is_some(),
+            Target::Win => modes.win.is_some(),
+            Target::Chrome => modes.chrome.is_some(),
+            _ => unreachable!("CLDR keyboards are only for these targets"),
+        };
+        if has_modes && !options.overwrite {
+            report.skipped.push(target);
+            continue;
+        }
+        match target {
+            Target::Mobile => modes.mobile = Some(keyboard.to_mobile_modes().map_err(invalid)?),
+            Target::Mac => modes.mac = Some(keyboard.to_desktop_modes().map_err(invalid)?),
+            Target::Win => modes.win = Some(keyboard.to_desktop_modes().map_err(invalid)?),
+            _ => modes.chrome = Some(keyboard.to_desktop_modes().map_err(invalid)?),
+        }
+        layout
+            .cldr
+            .get_or_insert_with(BTreeMap::new)
+            .insert(target, cldr_source(file, target));
+        if layout.transform_partial.is_none() || options.overwrite {
+            if let Some(partial) = keyboard.transform_partial() {
+                layout.transform_partial = Some(partial);
+            }
+        }
+        report.imported.push(target);
+    }
+
+    if layout.display_names.is_empty() {
+        let names = keyboards
+            .iter()
+            .flat_map(|k| &k.names)
+            .flat_map(|n| &n.values);
+        if let Some(name) = names.map(|name| name.value.clone()).next() {
+            layout.display_names.insert("en".into(), name);
+        }
+    }
+
+    if !report.imported.is_empty() {
+        let yaml = serde_yaml::to_string(&layout.without_derived_modes())
+            .map_err(|source| Error::CannotSerialize { source })?;
+        std::fs::create_dir_all(path.parent().expect("is in layouts"))
+            .and_then(|_| std::fs::write(&path, yaml))
+            .map_err(|source| Error::CannotWrite { path, source })?;
+    }
+
+    Ok(report)
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("Updating CLDR repo failed")]
     CldrRepoUpdate { source: crate::cli::repos::Error },
     #[error("No locale selected")]
     NoLocaleSelected,
+    #[error("Cannot import into this layout")]
+    InvalidLayoutName { source: crate::NameError },
     #[error("Could not load CLDR file")]
     CannotOpenFile { source: std::io::Error },
     #[error("Could not load CLDR file")]
     CannotReadXml { source: serde_xml_rs::Error },
     #[error("Could not write kbdgen bundle")]
     CannotSave { source: crate::SaveError },
+    #[error("Could not convert CLDR keyboard")]
+    InvalidModifiers {
+        source: crate::cldr::ir::ModifiersError,
+    },
+    #[error("No CLDR keyboard files given")]
+    NoFiles,
+    #[error("Could not load the layout to import into")]
+    CannotLoadLayout { source: crate::LoadError },
+    #[error("`{}` is for `{}`, which is not a known CLDR platform", path.display(), locale)]
+    UnknownTarget { path: PathBuf, locale: String },
+    #[error("Could not serialize layout")]
+    CannotSerialize { source: serde_yaml::Error },
+    #[error("Could not write `{}`", path.display())]
+    CannotWrite {
+        path: PathBuf,
+        source: std::io::Error,
+    },
 }
 
 #[cfg(windows)]
@@ -229,10 +430,92 @@ pub fn select_base_locale() -> Option<(String, BTreeMap<String, Vec<String>>)> {
 }
 
 pub fn parse_path(os: &str, file: &str) -> Result<Keyboard, Error> {
-    let fn_ = cldr_dir().join("keyboards").join(os).join(file);
+    parse_file(&cldr_path(os, file))
+}
+
+fn cldr_path(os: &str, file: &str) -> PathBuf {
+    cldr_dir().join("keyboards").join(os).join(file)
+}
 
-    let f = std::fs::File::open(fn_).map_err(|source| Error::CannotOpenFile { source })?;
+pub fn parse_file(path: &Path) -> Result<Keyboard, Error> {
+    let f = std::fs::File::open(path).map_err(|source| Error::CannotOpenFile { source })?;
     let kbd: Keyboard =
         serde_xml_rs::from_reader(f).map_err(|source| Error::CannotReadXml { source })?;
     Ok(kbd)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOWS: &str = r#"<keyboard locale="zz-t-k0-windows">
+  <version platform="10" number="1"/>
+  <names><name value="Zed"/></names>
+  <keyMap><map iso="D01" to="q"/></keyMap>
+  <keyMap modifiers="shift"><map iso="D01" to="Q"/></keyMap>
+</keyboard>"#;
+
+    const OSX: &str = r#"<keyboard locale="zz-t-k0-osx">
+  <version platform="10" number="1"/>
+  <names><name value="Zed"/></names>
+  <settings transformPartial="show"/>
+  <keyMap><map iso="D01" to="w"/></keyMap>
+</keyboard>"#;
+
+    #[test]
+    fn only_missing_targets_are_imported() {
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = dir.path().join("test.kbdgen");
+        let windows = dir.path().join("zz-t-k0-windows.xml");
+        let osx = dir.path().join("zz-t-k0-osx.xml");
+        std::fs::write(&windows, WINDOWS).unwrap();
+        std::fs::write(&osx, OSX).unwrap();
+
+        let mut options = ImportOptions {
+            project_path: bundle.clone(),
+            files: vec![windows.clone()],
+            layout: None,
+            overwrite: false,
+        };
+        let report = import(&options).unwrap();
+        assert_eq!(report.layout, "zz");
+        assert!(report.created);
+        assert_eq!(report.imported, vec![Target::Win]);
+
+        let layout_path = bundle.join("layouts/zz.yaml");
+        let mut yaml = std::fs::read_to_string(&layout_path).unwrap();
+        yaml = yaml.replace("Q", "Ǫ");
+        std::fs::write(&layout_path, yaml).unwrap();
+
+        options.files = vec![windows, osx];
+        let report = import(&options).unwrap();
+        assert!(!report.created);
+        assert_eq!(report.imported, vec![Target::Mac]);
+        assert_eq!(report.skipped, vec![Target::Win]);
+
+        let layout = Layout::load(&layout_path).unwrap();
+        assert_eq!(layout.display_names["en"], "Zed");
+        let sources = layout.cldr.as_ref().unwrap();
+        assert_eq!(
+            sources[&Target::Win].file,
+            "keyboards/windows/zz-t-k0-windows.xml"
+        );
+        assert_eq!(sources[&Target::Mac].file, "keyboards/osx/zz-t-k0-osx.xml");
+        assert_eq!(sources[&Target::Mac].commit, None);
+        assert!(layout.modes.mac.is_some());
+        assert_eq!(
+            layout.transform_partial,
+            Some(crate::models::TransformPartial::Show)
+        );
+        let shift = "shift".parse().unwrap();
+        let d01 = crate::models::IsoKey::D01;
+        let key = |layout: &Layout| layout.key(Target::Win, &shift, d01).cloned();
+        assert_eq!(key(&layout), Some(crate::KeyValue::Symbol("Ǫ".into())));
+
+        options.overwrite = true;
+        let report = import(&options).unwrap();
+        assert_eq!(report.imported, vec![Target::Win, Target::Mac]);
+        let layout = Layout::load(&layout_path).unwrap();
+        assert_eq!(key(&layout), Some(crate::KeyValue::Symbol("Q".into())));
+    }
+}
@@ -0,0 +1,177 @@
+//! Importing Keyman keyboards into a bundle, see `kmn::KmnFile::convert`
+
+use crate::{
+    kmn::{self, KmnFile, Skipped},
+    models::Layout,
+    Load, Target,
+};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// The bundle to import into
+    pub project_path: PathBuf,
+    /// The Keyman `.kmn` file
+    pub file: PathBuf,
+    /// The layout to import into, instead of the one named after the file
+    pub layout: Option<String>,
+    /// Replace the desktop modes if the layout already has them
+    pub overwrite: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    pub layout: String,
+    /// Whether the layout did not exist before
+    pub created: bool,
+    /// Whether the layout already had desktop modes, and was left as it was
+    pub kept: bool,
+    /// The number of dead key transforms imported
+    pub transforms: usize,
+    /// The rules that were not imported
+    pub skipped: Vec<Skipped>,
+}
+
+/// Imports a Keyman keyboard as the desktop modes, dead keys and transforms
+/// of a layout, creating the layout if needed
+pub fn import(options: &Options) -> Result<Report, Error> {
+    let bytes = std::fs::read(&options.file).map_err(|source| Error::CannotRead {
+        path: options.file.clone(),
+        source,
+    })?;
+    let file: KmnFile = kmn::decode(&bytes)
+        .and_then(|source| source.parse())
+        .map_err(|source| Error::CannotParse {
+            path: options.file.clone(),
+            source,
+        })?;
+
+    let name = match &options.layout {
+        Some(name) => name.clone(),
+        None => options
+            .file
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default(),
+    };
+    let path = crate::layout_file(&options.project_path, &name)
+        .map_err(|source| Error::InvalidLayoutName { source })?;
+
+    let created = !path.exists();
+    let mut layout = if created {
+        Layout::default()
+    } else {
+        Layout::load(&path).map_err(|source| Error::CannotLoadLayout { source })?
+    };
+    let mut report = Report {
+        layout: name,
+        created,
+        ..Default::default()
+    };
+
+    if layout.modes.desktop.is_some() && !options.overwrite {
+        report.kept = true;
+        return Ok(report);
+    }
+
+    let conversion = file.convert();
+    layout.modes.desktop = Some(conversion.modes);
+
+    let mut dead_keys = layout.dead_keys.take().unwrap_or_default();
+    dead_keys.remove(&Target::Desktop);
+    if !conversion.dead_keys.is_empty() {
+        dead_keys.insert(Target::Desktop, conversion.dead_keys);
+    }
+    layout.dead_keys = Some(dead_keys).filter(|dead_keys| !dead_keys.is_empty());
+
+    let transforms = layout.transforms.get_or_insert_with(Default::default);
+    for (dead_key, transform) in conversion.transforms {
+        report.transforms += transform.len();
+        transforms.entry(dead_key).or_default().extend(transform);
+    }
+    if transforms.is_empty() {
+        layout.transforms = None;
+    }
+
+    if layout.display_names.is_empty() {
+        if let Some(name) = file.name() {
+            layout.display_names.insert("en".into(), name);
+        }
+    }
+    report.skipped = conversion.skipped;
+
+    let yaml = serde_yaml::to_string(&layout.without_derived_modes())
+        .map_err(|source| Error::CannotSerialize { source })?;
+    std::fs::create_dir_all(path.parent().expect("is in layouts"))
+        .and_then(|_| std::fs::write(&path, yaml))
+        .map_err(|source| Error::CannotWrite { path, source })?;
+
+    Ok(report)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not read `{}`", path.display())]
+    CannotRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Could not parse `{}`", path.display())]
+    CannotParse { path: PathBuf, source: kmn::Error },
+
+    #[error("Could not load the layout to import into")]
+    CannotLoadLayout { source: crate::LoadError },
+
+    #[error("Cannot import into this layout")]
+    InvalidLayoutName { source: crate::NameError },
+
+    #[error("Could not serialize layout")]
+    CannotSerialize { source: serde_yaml::Error },
+
+    #[error("Could not write `{}`", path.display())]
+    CannotWrite {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyPress;
+
+    #[test]
+    fn keyboards_are_imported_as_layouts() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("sme_test.kmn");
+        std::fs::write(
+            &file,
+            "store(&NAME) 'Sámi Test'\n\
+             begin Unicode > use(main)\n\
+             group(main) using keys\n\
+             + [K_Q] > 'á'\n\
+             + [K_EQUAL] > dk(acute)\n\
+             dk(acute) + 'e' > 'é'\n",
+        )
+        .unwrap();
+
+        let options = Options {
+            project_path: dir.path().join("test.kbdgen"),
+            file,
+            layout: None,
+            overwrite: false,
+        };
+        let report = import(&options).unwrap();
+        assert!(report.created);
+        assert_eq!(report.transforms, 1);
+        assert_eq!(report.skipped, vec![]);
+
+        let layout = Layout::load(dir.path().join("test.kbdgen/layouts/sme_test.yaml")).unwrap();
+        assert_eq!(layout.display_names["en"], "Sámi Test");
+        let presses = KeyPress::parse_sequence("D01 E12 D03").unwrap();
+        assert_eq!(layout.simulate(Target::Win, &presses), "áé");
+
+        assert!(import(&options).unwrap().kept);
+    }
+}
@@ -4,7 +4,7 @@ use crate::{
         KeyValue,
     },
     cli::repos::{update_repo, xkb_dir},
-    Load, ProjectBundle, Save,
+    Load, ProjectBundle, Save, Target,
 };
 use std::{
     collections::BTreeMap,
@@ -57,6 +57,7 @@ pub fn xkb_to_kbdgen(output: &Path, is_updating_bundle: bool) -> Result<(), Erro
     bundle.targets.x11 = Some(TargetX11 {
         version: chrono::Utc::now().format("%Y-%m-%d").to_string(),
         build: 1,
+        second_group: None,
     });
 
     // Update X11 layout entry
@@ -67,7 +68,7 @@ pub fn xkb_to_kbdgen(output: &Path, is_updating_bundle: bool) -> Result<(), Erro
 
     // Do a neat switcheroo with possibly-None dead keys map
     let mut dead = layout.dead_keys.take().unwrap_or_default();
-    dead.insert("x11".to_string(), dead_keys);
+    dead.insert(Target::X11, dead_keys);
     layout.dead_keys = Some(dead);
 
     bundle
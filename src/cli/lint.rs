@@ -0,0 +1,67 @@
+//! Reporting what the linter finds in a bundle, see `crate::lint`
+
+use crate::{lint::Warning, Font};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub project_path: PathBuf,
+    /// Lint the layouts and targets that load, and report the errors of
+    /// those that do not, instead of stopping at the first error
+    pub keep_going: bool,
+    /// Fonts to check every character of the layouts against, see
+    /// `crate::lint::lint_fonts`
+    pub fonts: Vec<PathBuf>,
+}
+
+#[derive(Debug)]
+pub struct Report {
+    /// The errors of files that could not be loaded, with `keep_going`
+    pub errors: Vec<crate::LoadError>,
+    /// The warnings of rules the bundle's `.kbdgen-lint.yaml` denies
+    pub denied: Vec<Warning>,
+    pub warnings: Vec<Warning>,
+}
+
+pub fn lint(options: &Options) -> Result<Report, Error> {
+    let path = &options.project_path;
+    let (bundle, errors) = if options.keep_going {
+        let partial =
+            super::load_partial_bundle(path).map_err(|source| Error::CannotLoad { source })?;
+        (partial.bundle, partial.errors)
+    } else {
+        let bundle = super::load_bundle(path).map_err(|source| Error::CannotLoad { source })?;
+        (bundle, vec![])
+    };
+    let config =
+        crate::lint::Config::load(path).map_err(|source| Error::CannotLoadConfig { source })?;
+    let fonts = options
+        .fonts
+        .iter()
+        .map(Font::load)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|source| Error::CannotLoadFont { source })?;
+
+    let mut warnings = crate::lint::lint(&bundle);
+    if !fonts.is_empty() {
+        warnings.extend(crate::lint::lint_fonts(&bundle, &fonts));
+    }
+    let (denied, warnings) = config.apply(warnings);
+    Ok(Report {
+        errors,
+        denied,
+        warnings,
+    })
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not load kbdgen bundle")]
+    CannotLoad { source: crate::LoadError },
+
+    #[error("Could not load lint configuration")]
+    CannotLoadConfig { source: crate::lint::Error },
+
+    #[error("Could not load font")]
+    CannotLoadFont { source: crate::FontError },
+}
@@ -0,0 +1,90 @@
+use crate::{
+    cldr::numbers::{self, NumberSymbols},
+    cli::repos::{cldr_dir, update_repo},
+    models::Layout,
+    MacroError, ProjectBundle,
+};
+use std::{collections::BTreeMap, path::Path};
+
+const CLDR_REPO_URL: &str = "https://github.com/unicode-org/cldr";
+
+/// The values the Python generators expand macros with, by layout, see
+/// [`export_macro_values`]
+pub const MACROS_VAR: &str = "KBDGEN_MACROS";
+
+/// Expands the `{{name}}` macros of all layouts in the bundle
+///
+/// CLDR is only consulted (and downloaded, the first time) for layouts that
+/// use macros their own `macros` do not define.
+pub(crate) fn expand_layout_macros(bundle: &mut ProjectBundle) -> Result<(), Error> {
+    for (name, layout) in bundle.layouts.iter_mut() {
+        let used = layout.used_macros();
+        if used.is_empty() {
+            continue;
+        }
+
+        let values = cldr_values(name, layout)?;
+        log::debug!("Expanding macros {:?} in layout `{}`", used, name);
+        layout
+            .expand_macros(&values)
+            .map_err(|source| Error::CannotExpand {
+                layout: name.clone(),
+                source,
+            })?;
+    }
+
+    Ok(())
+}
+
+/// The values from CLDR for the macros `layout` uses, or none if its own
+/// `macros` define all of them
+fn cldr_values(name: &str, layout: &Layout) -> Result<BTreeMap<String, String>, Error> {
+    let own = layout.macros.clone().unwrap_or_default();
+    if layout.used_macros().iter().all(|m| own.contains_key(m)) {
+        return Ok(Default::default());
+    }
+
+    let dir = cldr_dir();
+    if !dir.exists() {
+        update_repo("cldr", &dir, CLDR_REPO_URL)
+            .map_err(|source| Error::CldrRepoUpdate { source })?;
+    }
+    Ok(NumberSymbols::load(&dir, name)
+        .map_err(|source| Error::CannotLoadCldr {
+            layout: name.to_string(),
+            source,
+        })?
+        .to_macros())
+}
+
+/// Makes the values from CLDR of the macros of the bundle's layouts
+/// available to the Python generators, which expand macros as they load the
+/// bundle, with the layouts' own `macros` taking precedence
+pub fn export_macro_values(project_path: &Path) -> Result<(), Error> {
+    let bundle =
+        crate::cli::load_bundle(project_path).map_err(|source| Error::CannotLoad { source })?;
+    let mut values = BTreeMap::new();
+    for (name, layout) in &bundle.layouts {
+        if !layout.used_macros().is_empty() {
+            values.insert(name.clone(), cldr_values(name, layout)?);
+        }
+    }
+    let json = serde_json::to_string(&values).expect("macro values serialize");
+    std::env::set_var(MACROS_VAR, json);
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not load bundle")]
+    CannotLoad { source: crate::LoadError },
+    #[error("Updating CLDR repo failed")]
+    CldrRepoUpdate { source: crate::cli::repos::Error },
+    #[error("Could not look up number symbols for layout `{layout}`")]
+    CannotLoadCldr {
+        layout: String,
+        source: numbers::Error,
+    },
+    #[error("Could not expand macros in layout `{layout}`")]
+    CannotExpand { layout: String, source: MacroError },
+}
@@ -0,0 +1,164 @@
+//! Manual pages for the command tree
+//!
+//! Every command gets a page, named after the commands leading to it like
+//! `kbdgen-build-android.1`, with its long help as the description. Hidden
+//! commands are left out.
+
+use std::path::{Path, PathBuf};
+use structopt::clap::{App, AppSettings};
+
+/// Escapes text for roff, keeping its lines as they are
+fn escape(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            let line = line.replace('\\', "\\e").replace('-', "\\-");
+            if line.starts_with('.') || line.starts_with('\'') {
+                format!("\\&{}\n", line)
+            } else {
+                format!("{}\n", line)
+            }
+        })
+        .collect()
+}
+
+/// The manual page of the command at `path`, like `["kbdgen", "build"]`, of
+/// kbdgen at `version`
+pub fn manpage(app: &App<'_, '_>, path: &[String], version: &str) -> Result<String, Error> {
+    let name = path.join("-");
+    let mut app = app.clone();
+    app.p.meta.bin_name = Some(path.join(" "));
+    let mut help = vec![];
+    app.write_long_help(&mut help)
+        .map_err(|source| Error::CannotRenderHelp {
+            command: path.join(" "),
+            source,
+        })?;
+    let help = String::from_utf8_lossy(&help);
+
+    let meta = &app.p.meta;
+    let mut page = format!(
+        ".TH {} 1 \"\" \"kbdgen {}\"\n",
+        name.to_uppercase(),
+        version
+    );
+    page.push_str(".SH NAME\n");
+    match meta.about {
+        Some(about) => page.push_str(&escape(&format!("{} - {}", name, about))),
+        None => page.push_str(&escape(&name)),
+    }
+    page.push_str(".SH DESCRIPTION\n.nf\n");
+    page.push_str(&escape(&help));
+    page.push_str(".fi\n");
+
+    let subcommands: Vec<_> = visible_subcommands(&app)
+        .map(|sub| format!("\\fB{}\\-{}\\fR(1)", escape(&name).trim(), sub.get_name()))
+        .collect();
+    let parent = Some(&path[..path.len().saturating_sub(1)])
+        .filter(|parent| !parent.is_empty())
+        .map(|parent| format!("\\fB{}\\fR(1)", escape(&parent.join("-")).trim()));
+    let see_also: Vec<_> = parent.into_iter().chain(subcommands).collect();
+    if !see_also.is_empty() {
+        page.push_str(".SH SEE ALSO\n");
+        page.push_str(&see_also.join(",\n"));
+        page.push('\n');
+    }
+    Ok(page)
+}
+
+fn visible_subcommands<'x, 'a, 'b>(app: &'x App<'a, 'b>) -> impl Iterator<Item = &'x App<'a, 'b>> {
+    app.p
+        .subcommands
+        .iter()
+        .filter(|sub| !sub.p.is_set(AppSettings::Hidden))
+}
+
+fn write_pages(
+    app: &App<'_, '_>,
+    path: &mut Vec<String>,
+    version: &str,
+    dir: &Path,
+    written: &mut Vec<PathBuf>,
+) -> Result<(), Error> {
+    let file = dir.join(format!("{}.1", path.join("-")));
+    let page = manpage(app, path, version)?;
+    std::fs::write(&file, page).map_err(|source| Error::CannotWrite {
+        path: file.clone(),
+        source,
+    })?;
+    written.push(file);
+
+    for sub in visible_subcommands(app) {
+        path.push(sub.get_name().to_string());
+        write_pages(sub, path, version, dir, written)?;
+        path.pop();
+    }
+    Ok(())
+}
+
+/// Writes the manual pages of the command and its subcommands to `dir`,
+/// returning the files written
+pub fn write_manpages(app: &App<'_, '_>, dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    std::fs::create_dir_all(dir).map_err(|source| Error::CannotWrite {
+        path: dir.to_path_buf(),
+        source,
+    })?;
+    let version = app.p.meta.version.unwrap_or(env!("CARGO_PKG_VERSION"));
+    let mut written = vec![];
+    let mut path = vec![app.get_name().to_string()];
+    write_pages(app, &mut path, version, dir, &mut written)?;
+    Ok(written)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not render the help of `{}`", command)]
+    CannotRenderHelp {
+        command: String,
+        source: structopt::clap::Error,
+    },
+
+    #[error("Could not write `{}`", path.display())]
+    CannotWrite {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use structopt::clap::{Arg, SubCommand};
+
+    #[test]
+    fn every_visible_command_gets_a_page() {
+        let app = App::new("kbdgen")
+            .version("2.0.0")
+            .about("Keyboard layout generator")
+            .subcommand(
+                SubCommand::with_name("build")
+                    .about("Generate output for a given .kbdgen bundle")
+                    .subcommand(
+                        SubCommand::with_name("x11")
+                            .about("Generates X11 (XKB) output")
+                            .arg(Arg::with_name("standalone").long("standalone")),
+                    ),
+            )
+            .subcommand(SubCommand::with_name("repl").setting(AppSettings::Hidden));
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut written = write_manpages(&app, dir.path()).unwrap();
+        written.sort();
+        let names: Vec<_> = written
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, ["kbdgen-build-x11.1", "kbdgen-build.1", "kbdgen.1"]);
+
+        let page = std::fs::read_to_string(dir.path().join("kbdgen-build-x11.1")).unwrap();
+        assert!(page.starts_with(".TH KBDGEN-BUILD-X11 1 \"\" \"kbdgen 2.0.0\"\n"));
+        assert!(page.contains("kbdgen\\-build\\-x11 \\- Generates X11 (XKB) output\n"));
+        assert!(page.contains("kbdgen build x11"));
+        assert!(page.contains("\\-\\-standalone"));
+        assert!(page.contains("\\fBkbdgen\\-build\\fR(1)"));
+    }
+}
@@ -1,9 +1,84 @@
+pub(crate) mod macros;
+pub use macros::{export_macro_values, MACROS_VAR};
 pub(crate) mod repos;
+pub(crate) mod yaml_edit;
 
+pub mod adjacency;
+pub mod annotate;
+pub mod cldr_diff;
+pub mod clean;
+pub mod compare_system;
+pub mod from_android;
 pub mod from_cldr;
+pub mod from_kmn;
 #[cfg(unix)]
 pub mod from_xkb;
+pub mod lint;
+pub mod manpages;
+pub mod output;
+pub mod package_chrome;
+pub mod remap;
+pub mod score;
+#[cfg(unix)]
+pub mod serve;
+pub mod sign_bundle;
+pub mod sort_transforms;
+pub mod templates;
+pub mod test;
+pub mod to_android_kcm;
+pub mod to_autocorrect;
 pub mod to_cldr;
 pub mod to_errormodel;
 pub mod to_m17n_mim;
+pub mod to_macos_resources;
+pub mod to_mobile_strings;
+pub mod to_screenshots;
+pub mod to_web;
 pub mod to_xkb;
+pub mod verify_install;
+pub mod which_key;
+pub mod win_ids;
+pub mod win_init;
+pub mod workspace;
+
+use crate::{Cache, LoadError, PartialBundle, ProjectBundle, VerifyingKey};
+use std::path::{Path, PathBuf};
+
+/// The public key file that bundles must be signed with, if set
+pub const REQUIRE_SIGNATURE_VAR: &str = "KBDGEN_REQUIRE_SIGNATURE";
+
+/// Checks the bundle's signature against the key in
+/// `KBDGEN_REQUIRE_SIGNATURE`, if it is set
+pub(crate) fn verify_signature(path: &Path) -> Result<(), LoadError> {
+    let key_path = match std::env::var_os(REQUIRE_SIGNATURE_VAR) {
+        Some(key_path) => PathBuf::from(key_path),
+        None => return Ok(()),
+    };
+    VerifyingKey::read(&key_path)
+        .and_then(|key| key.verify_bundle(path))
+        .map_err(|source| LoadError::Signature {
+            path: path.to_path_buf(),
+            source,
+        })
+}
+
+/// Loads a bundle for a command, through the bundle cache in the user's cache
+/// directory unless `KBDGEN_NO_CACHE` is set, after checking its signature if
+/// `KBDGEN_REQUIRE_SIGNATURE` is set
+pub(crate) fn load_bundle(path: &Path) -> Result<ProjectBundle, LoadError> {
+    verify_signature(path)?;
+    if std::env::var_os("KBDGEN_NO_CACHE").is_some() {
+        return crate::Load::load(path);
+    }
+    ProjectBundle::load_cached(path, &Cache::new(repos::bundle_cache_dir()))
+}
+
+/// Like `load_bundle`, but leaves out the layouts and targets that cannot be
+/// loaded, see `ProjectBundle::load_partial`
+pub(crate) fn load_partial_bundle(path: &Path) -> Result<PartialBundle, LoadError> {
+    verify_signature(path)?;
+    if std::env::var_os("KBDGEN_NO_CACHE").is_some() {
+        return ProjectBundle::load_partial(path, None);
+    }
+    ProjectBundle::load_partial(path, Some(&Cache::new(repos::bundle_cache_dir())))
+}
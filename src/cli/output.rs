@@ -0,0 +1,349 @@
+//! What commands print, and the exit codes they end with
+//!
+//! Every command exits with 0 when it succeeds, 1 when it ran but found
+//! problems, like lint errors, failing layout tests or unsorted transforms,
+//! and 2 when it could not run at all.
+//!
+//! With `--json`, a command prints nothing but one JSON envelope to stdout,
+//! whatever happens:
+//!
+//! ```json
+//! {
+//!   "command": "lint",
+//!   "status": "failed",
+//!   "exitCode": 1,
+//!   "diagnostics": [{ "severity": "error", "message": "…" }],
+//!   "artifacts": [],
+//!   "messages": [],
+//!   "data": { … }
+//! }
+//! ```
+//!
+//! `diagnostics` are the errors and warnings, with their stable code, like
+//! `W014`, as their `code`, see `kbdgen::codes`, `artifacts` the files written, `messages` what the command would
+//! have printed, and `data` the command's own result, if it has one. Logs
+//! still go to stderr. The result of `build` is the keys and rules that the
+//! target could not represent, see `kbdgen::loss`.
+
+use crate::{
+    build_report::{Recorder, TargetReport},
+    codes::Code,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error as StdError,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    /// The command ran, but found problems
+    Failed,
+    /// The command could not run
+    Error,
+}
+
+impl Status {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Status::Ok => 0,
+            Status::Failed => 1,
+            Status::Error => 2,
+        }
+    }
+
+    /// `Failed` if there were problems, otherwise `Ok`
+    pub fn failed_if(problems: bool) -> Status {
+        if problems {
+            Status::Failed
+        } else {
+            Status::Ok
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// What kind of error or warning it is, which every diagnostic a command
+    /// finds has, and errors that stop a command have if they are about
+    /// loading the bundle
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<Code>,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            code: None,
+            message: message.into(),
+            path: None,
+        }
+    }
+
+    pub fn warning(message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::error(message)
+        }
+    }
+
+    pub fn with_code(self, code: impl Into<Code>) -> Diagnostic {
+        Diagnostic {
+            code: Some(code.into()),
+            ..self
+        }
+    }
+
+    pub fn with_path(self, path: impl Into<PathBuf>) -> Diagnostic {
+        Diagnostic {
+            path: Some(path.into()),
+            ..self
+        }
+    }
+}
+
+/// Like `warning[W014]: message`, after the path if there is one
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{}: ", path.display())?;
+        }
+        match self.severity {
+            Severity::Error => f.write_str("error")?,
+            Severity::Warning => f.write_str("warning")?,
+        }
+        if let Some(code) = &self.code {
+            write!(f, "[{}]", code)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// What a command printed with `--json`, which `kbdgen workspace build` reads
+/// back from the build of each bundle
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Envelope {
+    pub command: String,
+    pub status: Status,
+    pub exit_code: i32,
+    pub diagnostics: Vec<Diagnostic>,
+    pub artifacts: Vec<PathBuf>,
+    pub messages: Vec<String>,
+    #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
+    pub data: serde_json::Value,
+}
+
+/// An error and its sources, like `Could not load kbdgen bundle: …`
+fn describe(error: &(dyn StdError + 'static)) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        message.push_str(": ");
+        message.push_str(&error.to_string());
+        source = error.source();
+    }
+    message
+}
+
+/// The output of a command, printed as it comes or as one JSON envelope when
+/// the command finishes
+pub struct Output {
+    json: bool,
+    command: String,
+    diagnostics: Vec<Diagnostic>,
+    artifacts: Vec<PathBuf>,
+    messages: Vec<String>,
+    data: serde_json::Value,
+}
+
+impl Output {
+    pub fn new(command: &str, json: bool) -> Output {
+        Output {
+            json,
+            command: command.to_string(),
+            diagnostics: vec![],
+            artifacts: vec![],
+            messages: vec![],
+            data: serde_json::Value::Null,
+        }
+    }
+
+    pub fn message(&mut self, message: impl Into<String>) {
+        let message = message.into();
+        if !self.json {
+            println!("{}", message);
+        }
+        self.messages.push(message);
+    }
+
+    pub fn diagnostic(&mut self, diagnostic: Diagnostic) {
+        if !self.json {
+            println!("{}", diagnostic);
+        }
+        self.diagnostics.push(diagnostic);
+    }
+
+    pub fn artifact(&mut self, path: impl Into<PathBuf>) {
+        self.artifacts.push(path.into());
+    }
+
+    /// Sets the command's own result, which is only printed as JSON
+    pub fn data(&mut self, data: impl Serialize) {
+        self.data = serde_json::to_value(data).expect("command results serialize");
+    }
+
+    /// Adds what a build wrote, warned about and could not represent, with
+    /// the losses as the command's result
+    pub fn build(&mut self, output_path: &Path, report: &TargetReport) {
+        for artifact in &report.artifacts {
+            self.artifact(output_path.join(&artifact.path));
+        }
+        for warning in &report.warnings {
+            let diagnostic =
+                Diagnostic::warning(format!("{}: {}", warning.target, warning.message))
+                    .with_code(Code::BuildWarning);
+            // The warnings were logged already
+            self.diagnostics.push(diagnostic);
+        }
+        for loss in report.losses.iter() {
+            let diagnostic = Diagnostic::warning(loss.to_string()).with_code(Code::Loss);
+            self.diagnostics.push(diagnostic);
+        }
+        if !report.losses.is_empty() {
+            self.message(report.losses.summary());
+        }
+        self.data(serde_json::json!({ "losses": report.losses }));
+    }
+
+    pub fn envelope(self, status: Status) -> Envelope {
+        Envelope {
+            command: self.command,
+            status,
+            exit_code: status.exit_code(),
+            diagnostics: self.diagnostics,
+            artifacts: self.artifacts,
+            messages: self.messages,
+            data: self.data,
+        }
+    }
+
+    /// Prints the envelope with `--json`, and exits with the status's code
+    pub fn finish(self, status: Status) -> ! {
+        if self.json {
+            let envelope = self.envelope(status);
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&envelope).expect("envelopes serialize")
+            );
+        }
+        std::process::exit(status.exit_code())
+    }
+
+    /// Reports that the command could not run, and exits
+    pub fn error(mut self, error: &(dyn StdError + 'static)) -> ! {
+        let code = Code::of_error(error);
+        if self.json {
+            self.diagnostics.push(Diagnostic {
+                code,
+                ..Diagnostic::error(describe(error))
+            });
+        } else {
+            eprintln!("ERROR: {:?}", error);
+            if let Some(code) = code {
+                eprintln!("See `kbdgen explain {}` for how to fix it.", code);
+            }
+        }
+        self.finish(Status::Error)
+    }
+
+    /// Finishes a build with its outcome, adding the report of the recorder
+    /// if there is one
+    pub fn finish_build(
+        mut self,
+        recorder: Option<Recorder>,
+        output_path: &Path,
+        result: Result<(), Box<dyn StdError>>,
+    ) -> ! {
+        if let Some(recorder) = recorder {
+            match recorder.finish(result.is_ok()) {
+                Ok(report) => self.build(output_path, &report),
+                Err(e) => self.diagnostic(Diagnostic::warning(describe(&e))),
+            }
+        }
+        match result {
+            Ok(()) => self.finish(Status::Ok),
+            Err(e) => self.error(&*e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn envelopes_have_the_exit_code_of_their_status() {
+        let mut output = Output::new("lint", true);
+        output.diagnostic(
+            Diagnostic::warning("se: flicks are defined for `å`").with_code(Code::UnknownFlickKeys),
+        );
+        output.diagnostic(Diagnostic::error("Could not parse").with_path("layouts/se.yaml"));
+        output.message("1 error");
+        output.artifact("out/se.svg");
+        output.data(vec![1, 2]);
+
+        let json = serde_json::to_value(output.envelope(Status::Failed)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "command": "lint",
+                "status": "failed",
+                "exitCode": 1,
+                "diagnostics": [
+                    {
+                        "severity": "warning",
+                        "code": "W001",
+                        "message": "se: flicks are defined for `å`"
+                    },
+                    {
+                        "severity": "error",
+                        "message": "Could not parse",
+                        "path": "layouts/se.yaml"
+                    }
+                ],
+                "artifacts": ["out/se.svg"],
+                "messages": ["1 error"],
+                "data": [1, 2]
+            })
+        );
+        let envelope: Envelope = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(envelope.diagnostics[0].code, Some(Code::UnknownFlickKeys));
+        assert_eq!(serde_json::to_value(envelope).unwrap(), json);
+
+        assert_eq!(
+            Diagnostic::warning("se: flicks are defined for `å`")
+                .with_code(Code::UnknownFlickKeys)
+                .to_string(),
+            "warning[W001]: se: flicks are defined for `å`"
+        );
+        let envelope = Output::new("test", true).envelope(Status::Error);
+        assert_eq!(envelope.exit_code, 2);
+        assert!(!serde_json::to_string(&envelope).unwrap().contains("data"));
+    }
+}
@@ -0,0 +1,146 @@
+//! Packaging step run after the ChromeOS extension has been generated
+//!
+//! Always produces a zip for uploading to the web store, and a signed `.crx`
+//! when the Chrome target names a signing key. Release builds upload the zip
+//! to the web store.
+
+use crate::chrome::{webstore_zip, write_crx, CrxError, SigningKey};
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+/// What `package_chrome` wrote
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Package {
+    pub app_id: String,
+    pub zip_path: PathBuf,
+    pub crx_path: Option<PathBuf>,
+}
+
+/// The path of a package, `<appId>-<version>.<extension>`
+fn package_path(output: &Path, app_id: &str, version: &str, extension: &str) -> PathBuf {
+    output.join(format!("{}-{}.{}", app_id, version, extension))
+}
+
+pub fn package_chrome(input: &Path, output: &Path) -> Result<Package, Error> {
+    let bundle = crate::cli::load_bundle(input).map_err(|source| Error::CannotLoad { source })?;
+    let target = bundle
+        .targets
+        .chrome
+        .as_ref()
+        .ok_or(Error::NoChromeTarget)?;
+
+    let extension_dir = find_extension_dir(output).ok_or_else(|| Error::NoExtension {
+        path: output.to_path_buf(),
+    })?;
+    log::debug!("Packaging extension in `{}`", extension_dir.display());
+
+    let zip = webstore_zip(&extension_dir).map_err(|source| Error::CannotZip { source })?;
+
+    let zip_path = package_path(output, &target.app_id, &target.version, "zip");
+    std::fs::write(&zip_path, &zip).map_err(|source| Error::CannotWriteFile {
+        path: zip_path.clone(),
+        source,
+    })?;
+    log::info!("Wrote to file `{}`", zip_path.display());
+    let mut package = Package {
+        app_id: target.app_id.clone(),
+        zip_path,
+        crx_path: None,
+    };
+
+    let key_path = match target.signing_key.as_ref() {
+        Some(key_path) => input.join(key_path),
+        None => {
+            log::debug!("No signing key configured, not making a .crx");
+            return Ok(package);
+        }
+    };
+    let pem = std::fs::read_to_string(&key_path).map_err(|source| Error::CannotReadKey {
+        path: key_path.clone(),
+        source,
+    })?;
+    let key = SigningKey::from_pem(&pem).map_err(|source| Error::InvalidKey {
+        path: key_path.clone(),
+        source,
+    })?;
+    if key.extension_id() != target.app_id {
+        log::warn!(
+            "Signing key gives extension id `{}`, but the configured appId is `{}`",
+            key.extension_id(),
+            target.app_id
+        );
+    }
+
+    let crx_path = package_path(output, &target.app_id, &target.version, "crx");
+    let file = File::create(&crx_path).map_err(|source| Error::CannotWriteFile {
+        path: crx_path.clone(),
+        source,
+    })?;
+    write_crx(&zip, &key, BufWriter::new(file)).map_err(|source| Error::CannotSign { source })?;
+    log::info!("Wrote to file `{}`", crx_path.display());
+    package.crx_path = Some(crx_path);
+
+    Ok(package)
+}
+
+/// The unpacked extension is either the output directory itself or a
+/// directory directly inside it, whichever has the `manifest.json`
+fn find_extension_dir(output: &Path) -> Option<PathBuf> {
+    if output.join("manifest.json").is_file() {
+        return Some(output.to_path_buf());
+    }
+
+    let mut dirs = std::fs::read_dir(output)
+        .ok()?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.join("manifest.json").is_file())
+        .collect::<Vec<_>>();
+    dirs.sort();
+    dirs.into_iter().next()
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not load kbdgen bundle")]
+    CannotLoad { source: crate::LoadError },
+    #[error("Bundle has no Chrome target configuration")]
+    NoChromeTarget,
+    #[error("No extension with a manifest.json found in `{}`", path.display())]
+    NoExtension { path: PathBuf },
+    #[error("Could not package extension")]
+    CannotZip { source: crate::chrome::Error },
+    #[error("Could not read signing key `{}`", path.display())]
+    CannotReadKey {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Signing key `{}` cannot be used", path.display())]
+    InvalidKey { path: PathBuf, source: CrxError },
+    #[error("Could not sign extension")]
+    CannotSign { source: CrxError },
+    #[error("Could not write to file `{}`", path.display())]
+    CannotWriteFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packages_keep_the_whole_version() {
+        assert_eq!(
+            package_path(Path::new("out"), "abcdef", "1.2.3", "zip"),
+            Path::new("out/abcdef-1.2.3.zip")
+        );
+        assert_eq!(
+            package_path(Path::new("out"), "abcdef", "1.2.3", "crx"),
+            Path::new("out/abcdef-1.2.3.crx")
+        );
+    }
+}
@@ -0,0 +1,105 @@
+//! Layouts on ergonomic bases, see `ErgonomicBase`
+
+use crate::{models::Layout, ErgonomicBase, Load};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// The layout file, in the `layouts` directory of a bundle
+    pub layout: PathBuf,
+    pub base: ErgonomicBase,
+    /// The file to write, by default a file next to the layout named like a
+    /// variant of it, like `se-dvorak.yaml`
+    pub output: Option<PathBuf>,
+}
+
+/// The file a remapped layout is written to without `output`
+pub fn default_output(layout: &Path, base: ErgonomicBase) -> PathBuf {
+    let name = layout
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let file_name = format!("{}.yaml", crate::variant_name(&name, &base.to_string()));
+    layout.with_file_name(file_name)
+}
+
+/// Writes the layout with its desktop keys moved onto `base` to a new file,
+/// returning its path
+pub fn remap(options: &Options) -> Result<PathBuf, Error> {
+    let layout = Layout::load(&options.layout).map_err(|source| Error::CannotLoad { source })?;
+    let remapped = layout.remapped(options.base);
+
+    let path = options
+        .output
+        .clone()
+        .unwrap_or_else(|| default_output(&options.layout, options.base));
+    if path == options.layout {
+        return Err(Error::WouldOverwrite { path });
+    }
+    let text =
+        serde_yaml::to_string(&remapped).map_err(|source| Error::CannotSerialize { source })?;
+    std::fs::write(&path, text).map_err(|source| Error::CannotWrite {
+        path: path.clone(),
+        source,
+    })?;
+
+    Ok(path)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not load layout")]
+    CannotLoad { source: crate::LoadError },
+
+    #[error("Refusing to overwrite the layout `{}` with its remapped copy", path.display())]
+    WouldOverwrite { path: PathBuf },
+
+    #[error("Could not serialize layout")]
+    CannotSerialize { source: serde_yaml::Error },
+
+    #[error("Could not write `{}`", path.display())]
+    CannotWrite {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remapped_layouts_are_written_next_to_the_original() {
+        let dir = tempfile::tempdir().unwrap();
+        let layout = dir.path().join("se.yaml");
+        std::fs::write(
+            &layout,
+            "displayNames:\n  se: Davvisámegiella\nmodes:\n  desktop:\n    default: |\n      \
+             § 1 2 3 4 5 6 7 8 9 0 + ´\n        \
+             á š e r t y u i o p å ŋ\n        \
+             a s d f g h j k l ö æ đ\n      \
+             ž z č c v b n m , . -\n",
+        )
+        .unwrap();
+
+        let options = Options {
+            layout: layout.clone(),
+            base: ErgonomicBase::Colemak,
+            output: None,
+        };
+        let path = remap(&options).unwrap();
+        assert_eq!(path, dir.path().join("se-colemak.yaml"));
+
+        let remapped = Layout::load(&path).unwrap();
+        assert_eq!(
+            remapped.display_names["se"],
+            "Davvisámegiella (Colemak)".to_string()
+        );
+
+        let options = Options {
+            output: Some(layout.clone()),
+            ..options
+        };
+        assert!(matches!(remap(&options), Err(Error::WouldOverwrite { .. })));
+    }
+}
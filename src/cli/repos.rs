@@ -8,12 +8,32 @@ fn kbdgen_dirs() -> pathos::user::AppDirs {
     pathos::user::AppDirs::new("kbdgen").unwrap()
 }
 
+/// The user's cache directory for kbdgen, unless `KBDGEN_CACHE_DIR` is set
+fn cache_dir() -> PathBuf {
+    match std::env::var_os(crate::config::CACHE_DIR_VAR) {
+        Some(dir) => PathBuf::from(dir),
+        None => kbdgen_dirs().cache_dir().to_path_buf(),
+    }
+}
+
+pub fn config_dir() -> PathBuf {
+    kbdgen_dirs().config_dir().to_path_buf()
+}
+
 pub fn cldr_dir() -> PathBuf {
-    kbdgen_dirs().cache_dir().join("cldr")
+    cache_dir().join("cldr")
 }
 
 pub fn xkb_dir() -> PathBuf {
-    kbdgen_dirs().cache_dir().join("xkb")
+    cache_dir().join("xkb")
+}
+
+pub fn bundle_cache_dir() -> PathBuf {
+    cache_dir().join("bundles")
+}
+
+pub fn templates_dir() -> PathBuf {
+    cache_dir().join("templates")
 }
 
 pub fn update_repo(name: &str, dir: &Path, repo: &str) -> Result<(), Error> {
@@ -42,6 +62,19 @@ pub fn update_repo(name: &str, dir: &Path, repo: &str) -> Result<(), Error> {
     Ok(())
 }
 
+/// What a git command in `dir` prints, if it succeeds
+pub fn git_output(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8(output.stdout).ok()?.trim().to_string())
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("Failed to cloning CLDR repo")]
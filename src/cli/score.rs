@@ -0,0 +1,195 @@
+//! Completeness scores and badges for the layouts of a bundle, see `Score`
+//!
+//! The characters a layout's language is written with are read from
+//! `orthography/<layout>.txt` in the bundle, separated by whitespace. Badges
+//! are written as JSON for shields.io's endpoint badges, and as ready-made
+//! SVG, for each layout.
+
+use crate::Score;
+use serde::Serialize;
+use std::{
+    fmt::Write as _,
+    path::{Path, PathBuf},
+};
+use xml::escape::escape_str_pcdata as escape;
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub project_path: PathBuf,
+    /// The directory to write `<layout>.json` and `<layout>.svg` badges to
+    pub badges: Option<PathBuf>,
+}
+
+/// The characters listed in the orthography file of the layout, if it has
+/// one
+fn orthography(project_path: &Path, layout: &str) -> Result<Option<Vec<String>>, Error> {
+    let path = project_path
+        .join("orthography")
+        .join(format!("{}.txt", layout));
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let text = std::fs::read_to_string(&path).map_err(|source| Error::CannotRead {
+        path: path.clone(),
+        source,
+    })?;
+    Ok(Some(text.split_whitespace().map(str::to_string).collect()))
+}
+
+/// The score of every layout of the bundle, by layout name, writing badges
+/// if asked to
+pub fn score(options: &Options) -> Result<Vec<(String, Score)>, Error> {
+    let bundle =
+        super::load_bundle(&options.project_path).map_err(|source| Error::CannotLoad { source })?;
+
+    let mut scores = vec![];
+    for (name, layout) in &bundle.layouts {
+        let orthography = orthography(&options.project_path, name)?;
+        scores.push((name.clone(), layout.score(orthography.as_deref())));
+    }
+    scores.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    if let Some(dir) = &options.badges {
+        std::fs::create_dir_all(dir).map_err(|source| Error::CannotWrite {
+            path: dir.clone(),
+            source,
+        })?;
+        for (name, score) in &scores {
+            for (extension, contents) in [
+                ("json", badge_json(name, score)),
+                ("svg", badge_svg(name, score)),
+            ] {
+                let path = dir.join(format!("{}.{}", name, extension));
+                std::fs::write(&path, contents).map_err(|source| Error::CannotWrite {
+                    path: path.clone(),
+                    source,
+                })?;
+                log::info!("Wrote to file `{}`", path.display());
+            }
+        }
+    }
+
+    Ok(scores)
+}
+
+/// The shields.io name and the hex value of the badge colour for a score
+fn color(score: &Score) -> (&'static str, &'static str) {
+    match score.percent() {
+        90..=100 => ("brightgreen", "#4c1"),
+        75..=89 => ("green", "#97ca00"),
+        50..=74 => ("yellow", "#dfb317"),
+        25..=49 => ("orange", "#fe7d37"),
+        _ => ("red", "#e05d44"),
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Badge<'a> {
+    schema_version: u32,
+    label: &'a str,
+    message: String,
+    color: &'static str,
+}
+
+/// A shields.io endpoint badge for the layout
+pub fn badge_json(name: &str, score: &Score) -> String {
+    let badge = Badge {
+        schema_version: 1,
+        label: name,
+        message: format!("{}%", score.percent()),
+        color: color(score).0,
+    };
+    serde_json::to_string_pretty(&badge).expect("badges serialize")
+}
+
+/// A flat badge for the layout, sized for its name
+pub fn badge_svg(name: &str, score: &Score) -> String {
+    let message = format!("{}%", score.percent());
+    // Roughly the advance of Verdana at 11px, which shields.io badges use
+    let width = |text: &str| text.chars().count() as u32 * 7 + 10;
+    let (label_w, message_w) = (width(name), width(&message));
+    let total_w = label_w + message_w;
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="20" role="img" aria-label="{}: {}">"#,
+        escape(name),
+        message,
+        w = total_w
+    );
+    let _ = writeln!(
+        svg,
+        r##"  <rect width="{}" height="20" rx="3" fill="#555"/>"##,
+        total_w
+    );
+    let _ = writeln!(
+        svg,
+        r##"  <rect x="{}" width="{}" height="20" rx="3" fill="{}"/>"##,
+        label_w,
+        message_w,
+        color(score).1
+    );
+    let _ = writeln!(
+        svg,
+        r##"  <g fill="#fff" text-anchor="middle" font-family="Verdana,DejaVu Sans,sans-serif" font-size="11">"##
+    );
+    let _ = writeln!(
+        svg,
+        r#"    <text x="{}" y="14">{}</text>"#,
+        label_w as f32 / 2.0,
+        escape(name)
+    );
+    let _ = writeln!(
+        svg,
+        r#"    <text x="{}" y="14">{}</text>"#,
+        label_w as f32 + message_w as f32 / 2.0,
+        message
+    );
+    svg.push_str("  </g>\n</svg>\n");
+    svg
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not load kbdgen bundle")]
+    CannotLoad { source: crate::LoadError },
+
+    #[error("Could not read `{}`", path.display())]
+    CannotRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Could not write `{}`", path.display())]
+    CannotWrite {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn badges_are_written_for_each_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = Options {
+            project_path: "examples/sme.kbdgen".into(),
+            badges: Some(dir.path().to_path_buf()),
+        };
+        let scores = score(&options).unwrap();
+        assert_eq!(scores[0].0, "se");
+        assert!(scores.iter().all(|(_, score)| score.total() > 0.0));
+        let se_no = &scores.iter().find(|(name, _)| name == "se-NO").unwrap().1;
+        assert_eq!(se_no.orthography, Some(1.0));
+
+        let json = std::fs::read_to_string(dir.path().join("se-NO.json")).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json["label"], "se-NO");
+        assert_eq!(json["schemaVersion"], 1);
+        assert!(dir.path().join("se-NO.svg").is_file());
+    }
+}
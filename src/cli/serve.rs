@@ -0,0 +1,438 @@
+//! `kbdgen serve`: a long-running JSON-RPC 2.0 server
+//!
+//! Editor plugins and the web preview keep one process around instead of
+//! paying start-up and bundle parsing costs on every call. Requests and
+//! responses are single lines of JSON over a Unix domain socket, which only
+//! the user running the server can connect to. A line that is not a JSON-RPC
+//! request, like the request line of an HTTP client, closes the connection.
+//! Loaded bundles are kept in memory until they are reloaded.
+//!
+//! Methods, all taking named parameters:
+//!
+//! - `load { path }`: (re)loads a bundle and lists its layouts
+//! - `validate { path }`: reloads a bundle and reports problems with it,
+//!   linting the layouts that load even if others do not
+//! - `generate { path, format, output }`: writes `xkb` or `m17n` output to
+//!   `output`, a directory in the bundle or in the server's `--output-root`
+//! - `simulate { path, layout, target, keys }`: the text typed by a sequence
+//!   of keys, each given as `{ "key": "D01", "mode": "shift" }`
+
+use crate::{
+    cli::{macros::expand_layout_macros, to_m17n_mim, to_xkb},
+    models::IsoKey,
+    KeyPress, ModifierSet, ProjectBundle, Target,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Write},
+    os::unix::{
+        fs::PermissionsExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_ERROR: i64 = -32000;
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// The socket to listen on, see [`default_socket`]
+    pub socket: PathBuf,
+    /// Where `generate` may write besides the bundle
+    pub output_root: Option<PathBuf>,
+}
+
+/// `kbdgen.sock` in `XDG_RUNTIME_DIR`, or in the temporary directory
+pub fn default_socket() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("kbdgen.sock"),
+        None => std::env::temp_dir().join(format!(
+            "kbdgen-{}.sock",
+            std::env::var("USER").unwrap_or_default()
+        )),
+    }
+}
+
+type Bundles = Arc<Mutex<HashMap<PathBuf, Arc<ProjectBundle>>>>;
+
+pub fn serve(options: &Options) -> Result<(), Error> {
+    let listener = bind(&options.socket)?;
+    log::info!("Listening on {}", options.socket.display());
+
+    let bundles = Bundles::default();
+    let output_root = Arc::new(options.output_root.clone());
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                log::warn!("Could not accept connection: {}", e);
+                continue;
+            }
+        };
+        let bundles = bundles.clone();
+        let output_root = output_root.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = handle_connection(stream, &bundles, output_root.as_deref()) {
+                log::debug!("Connection closed: {}", e);
+            }
+        });
+    }
+
+    Ok(())
+}
+
+/// Listens on `path`, replacing a socket left behind by a server that is no
+/// longer running, and lets only the current user connect
+fn bind(path: &Path) -> Result<UnixListener, Error> {
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(Error::InUse {
+                path: path.to_path_buf(),
+            });
+        }
+        std::fs::remove_file(path).map_err(|source| Error::CannotBind {
+            path: path.to_path_buf(),
+            source,
+        })?;
+    }
+    let listener = UnixListener::bind(path).map_err(|source| Error::CannotBind {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600)).map_err(|source| {
+        Error::CannotBind {
+            path: path.to_path_buf(),
+            source,
+        }
+    })?;
+    Ok(listener)
+}
+
+fn handle_connection(
+    stream: UnixStream,
+    bundles: &Bundles,
+    output_root: Option<&Path>,
+) -> std::io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let request = match serde_json::from_str::<Request>(&line) {
+            Ok(request) if request.jsonrpc == "2.0" => request,
+            Ok(_) => {
+                let error = (INVALID_REQUEST, "Not a JSON-RPC 2.0 request".to_string());
+                return reject(&mut writer, error);
+            }
+            Err(e) => return reject(&mut writer, (PARSE_ERROR, e.to_string())),
+        };
+
+        let id = request.id.clone();
+        let response = match call(&request.method, request.params, bundles, output_root) {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": code, "message": message },
+            }),
+        };
+
+        writeln!(writer, "{}", response)?;
+    }
+    Ok(())
+}
+
+/// Answers a line that is not a request with `error` and closes the
+/// connection, as whatever sent it is not a JSON-RPC client
+fn reject(writer: &mut UnixStream, (code, message): (i64, String)) -> std::io::Result<()> {
+    let response = json!({
+        "jsonrpc": "2.0",
+        "id": Value::Null,
+        "error": { "code": code, "message": message },
+    });
+    writeln!(writer, "{}", response)
+}
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    jsonrpc: String,
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct PathParams {
+    path: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+struct GenerateParams {
+    path: PathBuf,
+    format: String,
+    output: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+struct SimulateParams {
+    path: PathBuf,
+    layout: String,
+    target: Target,
+    keys: Vec<SimulatedKey>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SimulatedKey {
+    key: String,
+    #[serde(default)]
+    mode: Option<String>,
+}
+
+type RpcResult = Result<Value, (i64, String)>;
+
+fn params<T: serde::de::DeserializeOwned>(params: Value) -> Result<T, (i64, String)> {
+    serde_json::from_value(params).map_err(|e| (INVALID_PARAMS, e.to_string()))
+}
+
+fn call(
+    method: &str,
+    raw_params: Value,
+    bundles: &Bundles,
+    output_root: Option<&Path>,
+) -> RpcResult {
+    match method {
+        "load" => {
+            let p: PathParams = params(raw_params)?;
+            let bundle = load(&p.path, bundles).map_err(server_error)?;
+            let mut layouts = bundle.layouts.keys().collect::<Vec<_>>();
+            layouts.sort();
+            Ok(json!({
+                "layouts": layouts,
+                "locales": bundle.project.locales.keys().collect::<Vec<_>>(),
+            }))
+        }
+        "validate" => {
+            let p: PathParams = params(raw_params)?;
+            let partial = match crate::cli::load_partial_bundle(&p.path) {
+                Ok(partial) => partial,
+                Err(e) => {
+                    let errors = [error_chain(&e)];
+                    return Ok(json!({ "valid": false, "errors": errors, "warnings": [] }));
+                }
+            };
+            let mut errors = partial
+                .errors
+                .iter()
+                .map(|e| error_chain(e))
+                .collect::<Vec<_>>();
+            if errors.is_empty() {
+                if let Err(e) = load(&p.path, bundles) {
+                    errors.push(e);
+                }
+            }
+            let config = crate::lint::Config::load(&p.path).unwrap_or_else(|e| {
+                errors.push(error_chain(&e));
+                crate::lint::Config::default()
+            });
+            let (denied, warnings) = config.apply(crate::lint::lint(&partial.bundle));
+            errors.extend(denied.iter().map(ToString::to_string));
+            let warnings = warnings.iter().map(ToString::to_string).collect::<Vec<_>>();
+            Ok(json!({ "valid": errors.is_empty(), "errors": errors, "warnings": warnings }))
+        }
+        "generate" => {
+            let p: GenerateParams = params(raw_params)?;
+            let output = confine_output(&p.path, &p.output, output_root)?;
+            let bundle = cached(&p.path, bundles).map_err(server_error)?;
+            match p.format.as_str() {
+                "xkb" => to_xkb::bundle_to_xkb(
+                    &bundle,
+                    &output,
+                    &to_xkb::Options {
+                        standalone: false,
+                        verify: false,
+                    },
+                )
+                .map_err(|e| server_error(error_chain(&e))),
+                "m17n" => to_m17n_mim::bundle_to_mim(&bundle, &output)
+                    .map_err(|e| server_error(error_chain(&e))),
+                format => Err((
+                    INVALID_PARAMS,
+                    format!("No output format `{}`, expected `xkb` or `m17n`", format),
+                )),
+            }?;
+            Ok(json!({ "output": output }))
+        }
+        "simulate" => {
+            let p: SimulateParams = params(raw_params)?;
+            let bundle = cached(&p.path, bundles).map_err(server_error)?;
+            let layout = bundle
+                .layouts
+                .get(&p.layout)
+                .ok_or_else(|| (INVALID_PARAMS, format!("No layout `{}`", p.layout)))?;
+            let presses = p
+                .keys
+                .iter()
+                .map(|k| {
+                    Ok(KeyPress {
+                        key: k
+                            .key
+                            .parse::<IsoKey>()
+                            .map_err(|_| (INVALID_PARAMS, format!("No key `{}`", k.key)))?,
+                        mode: k
+                            .mode
+                            .as_deref()
+                            .unwrap_or("default")
+                            .parse::<ModifierSet>()
+                            .map_err(|e| (INVALID_PARAMS, e.to_string()))?,
+                    })
+                })
+                .collect::<Result<Vec<_>, (i64, String)>>()?;
+            Ok(json!({ "output": layout.simulate(p.target, &presses) }))
+        }
+        _ => Err((METHOD_NOT_FOUND, format!("No method `{}`", method))),
+    }
+}
+
+/// Where `generate` writes `output`, which has to be in the bundle or in
+/// `output_root`. A relative `output` is relative to the bundle.
+fn confine_output(
+    bundle: &Path,
+    output: &Path,
+    output_root: Option<&Path>,
+) -> Result<PathBuf, (i64, String)> {
+    let outside = || {
+        (
+            INVALID_PARAMS,
+            format!(
+                "Output `{}` is not in the bundle or the output root",
+                output.display()
+            ),
+        )
+    };
+    if output.components().any(|c| c == Component::ParentDir) {
+        return Err(outside());
+    }
+    let bundle = bundle
+        .canonicalize()
+        .map_err(|e| server_error(format!("{}: {}", bundle.display(), e)))?;
+    if output.is_relative() {
+        return Ok(bundle.join(output));
+    }
+    let roots = std::iter::once(bundle).chain(output_root.and_then(|r| r.canonicalize().ok()));
+    for root in roots {
+        if output.starts_with(&root) {
+            return Ok(output.to_path_buf());
+        }
+    }
+    Err(outside())
+}
+
+fn server_error(message: String) -> (i64, String) {
+    (SERVER_ERROR, message)
+}
+
+/// Loads the bundle from disk, replacing any cached copy
+fn load(path: &Path, bundles: &Bundles) -> Result<Arc<ProjectBundle>, String> {
+    let mut bundle = crate::cli::load_bundle(path).map_err(|e| error_chain(&e))?;
+    expand_layout_macros(&mut bundle).map_err(|e| error_chain(&e))?;
+    let bundle = Arc::new(bundle);
+    bundles
+        .lock()
+        .expect("bundle cache poisoned")
+        .insert(path.to_path_buf(), bundle.clone());
+    Ok(bundle)
+}
+
+/// The cached bundle, loading it on first use
+fn cached(path: &Path, bundles: &Bundles) -> Result<Arc<ProjectBundle>, String> {
+    let cached = bundles
+        .lock()
+        .expect("bundle cache poisoned")
+        .get(path)
+        .cloned();
+    match cached {
+        Some(bundle) => Ok(bundle),
+        None => load(path, bundles),
+    }
+}
+
+/// The error and all its sources, as one message
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(e) = source {
+        let part = e.to_string();
+        // Some errors already include their source in their own message
+        if !message.ends_with(&part) {
+            message.push_str(": ");
+            message.push_str(&part);
+        }
+        source = e.source();
+    }
+    message
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Another server is listening on `{}`", path.display())]
+    InUse { path: PathBuf },
+    #[error("Could not listen on `{}`", path.display())]
+    CannotBind {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn lines_that_are_not_requests_close_the_connection() {
+        let (mut client, server) = UnixStream::pair().unwrap();
+        client
+            .write_all(b"POST / HTTP/1.1\n{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"nope\"}\n")
+            .unwrap();
+        client.shutdown(std::net::Shutdown::Write).unwrap();
+        handle_connection(server, &Bundles::default(), None).unwrap();
+
+        let mut responses = String::new();
+        client.read_to_string(&mut responses).unwrap();
+        let responses = responses.lines().collect::<Vec<_>>();
+        assert_eq!(responses.len(), 1);
+        assert!(responses[0].contains(&PARSE_ERROR.to_string()));
+    }
+
+    #[test]
+    fn output_stays_in_the_bundle_or_output_root() {
+        let bundle = tempfile::tempdir().unwrap();
+        let root = tempfile::tempdir().unwrap();
+        let canonical = bundle.path().canonicalize().unwrap();
+
+        assert_eq!(
+            confine_output(bundle.path(), Path::new("out"), None),
+            Ok(canonical.join("out"))
+        );
+        assert!(confine_output(bundle.path(), Path::new("../out"), None).is_err());
+        assert!(confine_output(bundle.path(), Path::new("/etc/xkb"), None).is_err());
+
+        let output = root.path().canonicalize().unwrap().join("xkb");
+        assert!(confine_output(bundle.path(), &output, None).is_err());
+        assert_eq!(
+            confine_output(bundle.path(), &output, Some(root.path())),
+            Ok(output)
+        );
+    }
+}
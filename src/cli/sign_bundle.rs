@@ -0,0 +1,110 @@
+//! Signing bundles for distribution, see `bundle::signature`
+
+use crate::{signature, SignatureError, SigningKey, VerifyingKey};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub project_path: PathBuf,
+    /// The secret key to sign with
+    pub key: PathBuf,
+    /// Generate the key, and its `.pub` public key, instead of reading it
+    pub generate_key: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    /// The key to verify the bundle with
+    pub public_key: VerifyingKey,
+    /// The number of files signed
+    pub files: usize,
+}
+
+/// Signs the bundle, writing its signature into it
+pub fn sign_bundle(options: &Options) -> Result<Report, Error> {
+    if !options.project_path.join("project.yaml").is_file() {
+        return Err(Error::NotABundle {
+            path: options.project_path.clone(),
+        });
+    }
+
+    let key = if options.generate_key {
+        if options.key.exists() {
+            return Err(Error::KeyExists {
+                path: options.key.clone(),
+            });
+        }
+        let key = SigningKey::generate();
+        key.write(&options.key)
+            .map_err(|source| Error::Signature { source })?;
+        log::info!(
+            "Wrote public key to `{}`",
+            signature::public_key_path(&options.key).display()
+        );
+        key
+    } else {
+        SigningKey::read(&options.key).map_err(|source| Error::Signature { source })?
+    };
+
+    key.sign_bundle(&options.project_path)
+        .map_err(|source| Error::Signature { source })?;
+    let files = signature::manifest(&options.project_path)
+        .map_err(|source| Error::Signature { source })?
+        .lines()
+        .count();
+    Ok(Report {
+        public_key: key.verifying_key(),
+        files,
+    })
+}
+
+/// Checks that the bundle is signed with the public key at `key`
+pub fn verify(project_path: &Path, key: &Path) -> Result<(), Error> {
+    VerifyingKey::read(key)
+        .and_then(|key| key.verify_bundle(project_path))
+        .map_err(|source| Error::Signature { source })
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("`{}` is not a kbdgen bundle", path.display())]
+    NotABundle { path: PathBuf },
+
+    #[error("`{}` already exists, and would be overwritten by a new key", path.display())]
+    KeyExists { path: PathBuf },
+
+    #[error("{}", source)]
+    Signature { source: SignatureError },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundles_are_signed_with_new_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = dir.path().join("test.kbdgen");
+        std::fs::create_dir_all(&bundle).unwrap();
+        std::fs::write(bundle.join("project.yaml"), "locales: {}\n").unwrap();
+
+        let options = Options {
+            project_path: bundle.clone(),
+            key: dir.path().join("signing.key"),
+            generate_key: true,
+        };
+        let report = sign_bundle(&options).unwrap();
+        assert_eq!(report.files, 1);
+        verify(&bundle, &dir.path().join("signing.key.pub")).unwrap();
+
+        assert!(matches!(
+            sign_bundle(&options),
+            Err(Error::KeyExists { .. })
+        ));
+        let options = Options {
+            generate_key: false,
+            ..options
+        };
+        assert_eq!(sign_bundle(&options).unwrap(), report);
+    }
+}
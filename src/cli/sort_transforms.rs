@@ -0,0 +1,171 @@
+//! Keeping a layout's transforms maintainable
+//!
+//! Transform blocks grow by hand and by import, and soon have no order at all.
+//! Rewriting them sorted by codepoint, without the entries that only differ
+//! in normalization, keeps them reviewable and their diffs small.
+
+use crate::{models::Layout, Load, TransformConflict};
+use serde_yaml::Value;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// The layout file, in the `layouts` directory of a bundle
+    pub layout: PathBuf,
+    /// Only report whether the file would change, without rewriting it
+    pub check: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    /// The number of duplicate transforms removed
+    pub removed: usize,
+    /// Whether the transforms were, or with `check` would be, rewritten
+    pub changed: bool,
+}
+
+/// Sorts the transforms of the layout by codepoint and removes duplicates,
+/// rewriting the file unless `check` is set. Nothing is written if
+/// equivalent transforms type different outputs.
+///
+/// Everything but the transforms keeps its order, but comments and
+/// formatting of the file are lost when it is rewritten.
+pub fn sort_transforms(options: &Options) -> Result<Report, Error> {
+    let path = &options.layout;
+    let mut layout = Layout::load(path).map_err(|source| Error::CannotLoad { source })?;
+    let removed = layout
+        .dedup_transforms()
+        .map_err(|conflicts| Error::Conflicts { conflicts })?;
+
+    let text = std::fs::read_to_string(path).map_err(|source| Error::CannotRead {
+        path: path.clone(),
+        source,
+    })?;
+    let mut document: Value =
+        serde_yaml::from_str(&text).map_err(|source| Error::CannotParse { source })?;
+    let mapping = match document.as_mapping_mut() {
+        Some(mapping) => mapping,
+        None => {
+            return Ok(Report {
+                removed,
+                changed: false,
+            })
+        }
+    };
+
+    let key = Value::String("transforms".into());
+    let transforms = match &layout.transforms {
+        Some(transforms) => {
+            serde_yaml::to_value(transforms).map_err(|source| Error::CannotSerialize { source })?
+        }
+        None => {
+            return Ok(Report {
+                removed,
+                changed: false,
+            })
+        }
+    };
+    // Mappings compare equal regardless of order, their YAML does not
+    let yaml = |value: &Value| {
+        serde_yaml::to_string(value).map_err(|source| Error::CannotSerialize { source })
+    };
+    let changed = match mapping.get(&key) {
+        Some(existing) => yaml(existing)? != yaml(&transforms)?,
+        None => true,
+    };
+    if !changed || options.check {
+        return Ok(Report { removed, changed });
+    }
+
+    mapping.insert(key, transforms);
+    let text = yaml(&document)?;
+    std::fs::write(path, text).map_err(|source| Error::CannotWrite {
+        path: path.clone(),
+        source,
+    })?;
+
+    Ok(Report { removed, changed })
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not load layout")]
+    CannotLoad { source: crate::LoadError },
+
+    #[error(
+        "Equivalent transforms type different outputs:\n{}",
+        conflicts.iter().map(|c| format!("  {}", c)).collect::<Vec<_>>().join("\n")
+    )]
+    Conflicts { conflicts: Vec<TransformConflict> },
+
+    #[error("Could not read `{}`", path.display())]
+    CannotRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Could not parse layout")]
+    CannotParse { source: serde_yaml::Error },
+
+    #[error("Could not serialize layout")]
+    CannotSerialize { source: serde_yaml::Error },
+
+    #[error("Could not write `{}`", path.display())]
+    CannotWrite {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAYOUT: &str = r#"displayNames:
+  en: Test
+modes:
+  desktop:
+    default: |
+      ´ 1 2
+        q w e r t y u i o p
+        a s d f g h j k l
+transforms:
+  ´:
+    o: ó
+    a: á
+    " ": ´
+"#;
+
+    #[test]
+    fn transforms_are_rewritten_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let layout = dir.path().join("test.yaml");
+        std::fs::write(&layout, LAYOUT).unwrap();
+
+        let mut options = Options {
+            layout: layout.clone(),
+            check: true,
+        };
+        let report = sort_transforms(&options).unwrap();
+        assert!(report.changed);
+        assert_eq!(std::fs::read_to_string(&layout).unwrap(), LAYOUT);
+
+        options.check = false;
+        sort_transforms(&options).unwrap();
+        let yaml = std::fs::read_to_string(&layout).unwrap();
+        let space = yaml.find("\" \"").unwrap();
+        let a = yaml.find("a: á").unwrap();
+        let o = yaml.find("o: ó").unwrap();
+        assert!(space < a && a < o);
+        assert!(yaml.find("displayNames").unwrap() < yaml.find("modes").unwrap());
+
+        let report = sort_transforms(&options).unwrap();
+        assert_eq!(
+            report,
+            Report {
+                removed: 0,
+                changed: false
+            }
+        );
+    }
+}
@@ -0,0 +1,79 @@
+//! Copying the templates of a bundle's targets into the bundle, see
+//! `crate::templates`
+
+use crate::{templates, Target};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub project_path: PathBuf,
+}
+
+/// Vendors the template of every target that has one, and rewrites the
+/// targets' files to use the copies. Returns the targets that were vendored.
+pub fn vendor(options: &Options) -> Result<Vec<Target>, Error> {
+    let project_path = &options.project_path;
+    let mut bundle =
+        super::load_bundle(project_path).map_err(|source| Error::CannotLoad { source })?;
+
+    let mut vendored = vec![];
+    for target in [Target::Android, Target::Ios] {
+        let template = match templates::template_for(&bundle, target) {
+            Some(template) => template.clone(),
+            None => continue,
+        };
+        let template = Some(
+            templates::vendor(&template, project_path, target)
+                .map_err(|source| Error::CannotVendor { target, source })?,
+        );
+
+        let targets = &mut bundle.targets;
+        match target {
+            Target::Android => {
+                let android = targets.android.as_mut().expect("has a template");
+                android.template = template;
+                write_target(project_path, "android", android)?;
+            }
+            Target::Ios => {
+                let ios = targets.i_os.as_mut().expect("has a template");
+                ios.template = template;
+                write_target(project_path, "ios", ios)?;
+            }
+            _ => unreachable!(),
+        }
+        vendored.push(target);
+    }
+
+    Ok(vendored)
+}
+
+fn write_target<T: Serialize>(project_path: &Path, name: &str, data: &T) -> Result<(), Error> {
+    let path = project_path.join("targets").join(format!("{}.yaml", name));
+    let yaml = serde_yaml::to_string(data).map_err(|source| Error::CannotSerialize {
+        path: path.clone(),
+        source,
+    })?;
+    std::fs::write(&path, yaml).map_err(|source| Error::CannotWrite { path, source })
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not load kbdgen bundle")]
+    CannotLoad { source: crate::LoadError },
+    #[error("Could not vendor the template for `{}`", target)]
+    CannotVendor {
+        target: Target,
+        source: templates::Error,
+    },
+    #[error("Could not serialize `{}`", path.display())]
+    CannotSerialize {
+        path: PathBuf,
+        source: serde_yaml::Error,
+    },
+    #[error("Could not write `{}`", path.display())]
+    CannotWrite {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
@@ -0,0 +1,205 @@
+//! Running the layout tests of a bundle, see `LayoutTests`
+//!
+//! With `xkb`, the cases typed on `x11` are also typed on the XKB symbols
+//! generated for the layout, compiled by xkbcommon, see `crate::xkb::harness`.
+//! This tests what Linux does with the symbols, where `Layout::simulate` only
+//! tests what they are meant to do.
+
+use crate::{
+    xkb::{harness::Harness, ConversionError, XkbFile},
+    KeyPress, KeyPressError, LayoutTests, LocaleFallbacks, Target, TestFailure,
+};
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub project_path: PathBuf,
+    /// Also type the `x11` cases with xkbcommon
+    pub xkb: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    /// The number of test files that were run
+    pub files: usize,
+    pub passed: usize,
+    /// The failed cases, with the file they are in
+    pub failures: Vec<(PathBuf, TestFailure)>,
+    /// The number of cases that typed what was expected with xkbcommon
+    pub xkb_passed: usize,
+    /// The cases that typed something else with xkbcommon, with the file they
+    /// are in
+    pub xkb_failures: Vec<(PathBuf, TestFailure)>,
+}
+
+/// The YAML files in the `tests` directory of the bundle, in order
+fn test_files(project_path: &Path) -> Result<Vec<PathBuf>, Error> {
+    let dir = project_path.join("tests");
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let entries = std::fs::read_dir(&dir).map_err(|source| Error::CannotRead {
+        path: dir.clone(),
+        source,
+    })?;
+    let mut files = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension() == Some(OsStr::new("yaml")))
+        .collect::<Vec<_>>();
+    files.sort();
+    Ok(files)
+}
+
+/// Runs every file in the `tests` directory of the bundle against its layout
+pub fn test(options: &Options) -> Result<Report, Error> {
+    let mut bundle =
+        super::load_bundle(&options.project_path).map_err(|source| Error::CannotLoad { source })?;
+    if options.xkb {
+        super::macros::expand_layout_macros(&mut bundle)
+            .map_err(|source| Error::CannotExpandMacros { source })?;
+    }
+    let fallbacks = LocaleFallbacks::of(&bundle.project);
+    let mut report = Report::default();
+
+    for path in test_files(&options.project_path)? {
+        let text = std::fs::read_to_string(&path).map_err(|source| Error::CannotRead {
+            path: path.clone(),
+            source,
+        })?;
+        let tests: LayoutTests =
+            serde_yaml::from_str(&text).map_err(|source| Error::CannotParse {
+                path: path.clone(),
+                source,
+            })?;
+
+        let name = match &tests.layout {
+            Some(name) => name.clone(),
+            None => path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        };
+        let layout = bundle.layouts.get(&name).ok_or_else(|| Error::NoLayout {
+            path: path.clone(),
+            layout: name.clone(),
+        })?;
+
+        let outcome = tests.run(layout).map_err(|source| Error::InvalidKeys {
+            path: path.clone(),
+            source,
+        })?;
+        report.files += 1;
+        report.passed += outcome.passed;
+        report.failures.extend(
+            outcome
+                .failures
+                .into_iter()
+                .map(|failure| (path.clone(), failure)),
+        );
+
+        if options.xkb && tests.cases_on(layout, Target::X11).next().is_some() {
+            let file =
+                XkbFile::from_layout(&name, layout.clone(), &fallbacks).map_err(|source| {
+                    Error::CannotConvert {
+                        layout: name.clone(),
+                        source,
+                    }
+                })?;
+            let harness = Harness::new(&name, &file).map_err(|source| Error::Xkbcommon {
+                layout: name.clone(),
+                source,
+            })?;
+            for case in tests.cases_on(layout, Target::X11) {
+                let presses =
+                    KeyPress::parse_sequence(&case.keys).map_err(|source| Error::InvalidKeys {
+                        path: path.clone(),
+                        source,
+                    })?;
+                let actual = harness
+                    .type_keys(&presses)
+                    .map_err(|source| Error::Xkbcommon {
+                        layout: name.clone(),
+                        source,
+                    })?;
+                if actual == case.expect {
+                    report.xkb_passed += 1;
+                } else {
+                    report.xkb_failures.push((
+                        path.clone(),
+                        TestFailure {
+                            name: case.name.clone(),
+                            keys: case.keys.clone(),
+                            target: Target::X11,
+                            expected: case.expect.clone(),
+                            actual,
+                        },
+                    ));
+                }
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not load kbdgen bundle")]
+    CannotLoad { source: crate::LoadError },
+
+    #[error("Could not read `{}`", path.display())]
+    CannotRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Could not parse `{}`", path.display())]
+    CannotParse {
+        path: PathBuf,
+        source: serde_yaml::Error,
+    },
+
+    #[error("`{}` tests the layout `{layout}`, which is not in the bundle", path.display())]
+    NoLayout { path: PathBuf, layout: String },
+
+    #[error("Invalid keys in `{}`", path.display())]
+    InvalidKeys {
+        path: PathBuf,
+        source: KeyPressError,
+    },
+
+    #[error("Could not expand macros")]
+    CannotExpandMacros { source: super::macros::Error },
+
+    #[error("Could not convert `{}` to XKB symbols", layout)]
+    CannotConvert {
+        layout: String,
+        source: ConversionError,
+    },
+
+    #[error("Could not type the tests of `{}` with xkbcommon", layout)]
+    Xkbcommon {
+        layout: String,
+        source: crate::xkb::harness::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn example_bundle_passes_its_tests() {
+        let options = Options {
+            project_path: "examples/sme.kbdgen".into(),
+            xkb: false,
+        };
+        let report = test(&options).unwrap();
+        assert_eq!(report.files, 1);
+        assert!(report.passed > 0);
+        assert_eq!(report.failures, vec![]);
+    }
+}
@@ -0,0 +1,489 @@
+//! Key character maps for physical keyboards attached to Android devices
+//!
+//! Android maps the keys of a hardware keyboard with `.kcm` files that apps
+//! can ship and declare in their manifest. One is generated per layout from
+//! its desktop modes, along with the `keyboard_layouts.xml` that lists them,
+//! for the Android generator to put into the app.
+//!
+//! See <https://source.android.com/devices/input/key-character-map-files>.
+
+use crate::{
+    bundle::keys::KeyValue,
+    identifier,
+    loss::{self, Loss},
+    models::{DesktopModes, IsoKey, Layout},
+    stamp::{self, Stamp},
+    xml_writer::XmlWriter,
+    Feature, LocaleFallbacks, Modifier, ModifierSet, Target, DEFAULT_LOCALE,
+};
+use log::{debug, log_enabled};
+use std::{
+    fmt::Write as _,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+use strum::IntoEnumIterator;
+
+/// Desktop modes to take the key maps from, in order of preference
+const KCM_SOURCE_TARGETS: &[Target] = &[Target::Desktop, Target::Win, Target::X11, Target::Chrome];
+
+/// The modes a key character map can express, with the names of their
+/// behaviours. Android has no equivalent of the other modifiers.
+const BEHAVIOURS: &[(&[Modifier], &str)] = &[
+    (&[], "base"),
+    (&[Modifier::Shift], "shift"),
+    (&[Modifier::Caps], "capslock"),
+    (&[Modifier::Caps, Modifier::Shift], "shift+capslock"),
+    (&[Modifier::Alt], "ralt"),
+    (&[Modifier::Alt, Modifier::Shift], "shift+ralt"),
+    (&[Modifier::Caps, Modifier::Alt], "capslock+ralt"),
+];
+
+/// The combining accents Android treats as dead keys, by the spacing
+/// character a layout uses for them
+pub(crate) const DEAD_KEY_ACCENTS: &[(char, char)] = &[
+    ('`', '\u{0300}'),
+    ('´', '\u{0301}'),
+    ('\'', '\u{0301}'),
+    ('^', '\u{0302}'),
+    ('ˆ', '\u{0302}'),
+    ('~', '\u{0303}'),
+    ('˜', '\u{0303}'),
+    ('¯', '\u{0304}'),
+    ('˘', '\u{0306}'),
+    ('˙', '\u{0307}'),
+    ('¨', '\u{0308}'),
+    ('"', '\u{0308}'),
+    ('˚', '\u{030A}'),
+    ('˝', '\u{030B}'),
+    ('ˇ', '\u{030C}'),
+    ('¸', '\u{0327}'),
+    ('˛', '\u{0328}'),
+];
+
+pub fn kbdgen_to_android_kcm(input: &Path, output: &Path) -> Result<(), Error> {
+    let mut bundle =
+        crate::cli::load_bundle(input).map_err(|source| Error::CannotLoad { source })?;
+    crate::cli::macros::expand_layout_macros(&mut bundle)
+        .map_err(|source| Error::CannotExpandMacros { source })?;
+    if log_enabled!(log::Level::Debug) {
+        debug!("Bundle `{}` loaded", input.display());
+    }
+
+    let res = output.join("kcm/res");
+    let mut layouts = bundle.layouts.iter().collect::<Vec<_>>();
+    layouts.sort_by_key(|(name, _)| name.as_str());
+
+    let stamp = Stamp::for_bundle(&bundle);
+    let fallbacks = LocaleFallbacks::of(&bundle.project);
+    let mut maps = vec![];
+    for (name, layout) in layouts {
+        let kcm = match KeyCharacterMap::from_layout(name, layout, &fallbacks) {
+            Some(kcm) => kcm,
+            None => {
+                debug!("Layout `{}` has no desktop modes, skipping", name);
+                continue;
+            }
+        };
+        let path = res
+            .join("raw")
+            .join(&kcm.resource_name)
+            .with_extension("kcm");
+        let mut header = stamp
+            .as_ref()
+            .map(|stamp| stamp.with_layout(name).comment("#"))
+            .unwrap_or_default();
+        header += &stamp::comment(&layout.license_notice(&bundle.project), "#");
+        write_file(&path, |w| {
+            w.write_all(header.as_bytes())?;
+            w.write_all(kcm.to_kcm().as_bytes())
+        })?;
+        maps.push(kcm);
+    }
+
+    if maps.is_empty() {
+        return Ok(());
+    }
+    let path = res.join("xml/keyboard_layouts.xml");
+    write_file(&path, |w| write_keyboard_layouts(&maps, stamp.as_ref(), w))
+}
+
+fn write_file(
+    path: &Path,
+    write: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+) -> Result<(), Error> {
+    std::fs::create_dir_all(path.parent().expect("has parent")).map_err(|source| {
+        Error::CannotCreateFile {
+            path: path.to_path_buf(),
+            source,
+        }
+    })?;
+    let file = File::create(path).map_err(|source| Error::CannotCreateFile {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let mut writer = BufWriter::new(file);
+    write(&mut writer)
+        .and_then(|_| writer.flush())
+        .map_err(|source| Error::CannotWriteFile {
+            path: path.to_path_buf(),
+            source,
+        })?;
+    log::info!("Wrote to file `{}`", path.display());
+    Ok(())
+}
+
+/// The `.kcm` of a layout
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyCharacterMap {
+    /// The name of the `raw` resource, like `keyboard_layout_se_se`
+    pub resource_name: String,
+    /// The name shown in Android's physical keyboard settings
+    pub label: String,
+    /// The behaviours of each key, by Android key code
+    pub keys: Vec<(&'static str, Vec<(&'static str, char)>)>,
+}
+
+impl KeyCharacterMap {
+    /// The key character map for the desktop modes of the layout, if it has
+    /// any
+    pub fn from_layout(
+        name: &str,
+        layout: &Layout,
+        fallbacks: &LocaleFallbacks<'_>,
+    ) -> Option<Self> {
+        let (target, modes) = KCM_SOURCE_TARGETS
+            .iter()
+            .find_map(|&target| Some((target, layout.modes.desktop_for(target)?)))?;
+        let no_caps_lock = layout.has_feature(Feature::NoCapsLock);
+
+        let keys = IsoKey::iter()
+            .map(|key| {
+                let mut behaviours = vec![];
+                for (modifiers, behaviour) in BEHAVIOURS {
+                    let mode = modifiers.iter().copied().collect::<ModifierSet>();
+                    if no_caps_lock && mode.contains(Modifier::Caps) {
+                        continue;
+                    }
+                    if let Some(c) = key_char(name, layout, target, modes, &mode, key) {
+                        behaviours.push((*behaviour, c));
+                    }
+                }
+                if !no_caps_lock {
+                    add_implied_caps_lock(&mut behaviours);
+                }
+                (key_code(key), behaviours)
+            })
+            .filter(|(_, behaviours)| !behaviours.is_empty())
+            .collect();
+
+        let resource_name = format!("keyboard_layout_{}", identifier(&name.to_lowercase()));
+        Some(KeyCharacterMap {
+            resource_name,
+            label: layout
+                .display_name(DEFAULT_LOCALE, fallbacks)
+                .unwrap_or_else(|| name.to_string()),
+            keys,
+        })
+    }
+
+    pub fn to_kcm(&self) -> String {
+        let mut kcm = String::from("type OVERLAY\n");
+        for (key_code, behaviours) in &self.keys {
+            let _ = write!(kcm, "\nkey {} {{\n", key_code);
+            if let Some(label) = label(behaviours) {
+                let _ = writeln!(kcm, "    {:<24}{}", "label:", char_literal(label));
+            }
+            for (behaviour, c) in behaviours {
+                let property = format!("{}:", behaviour);
+                let _ = writeln!(kcm, "    {:<24}{}", property, char_literal(*c));
+            }
+            kcm.push_str("}\n");
+        }
+        kcm
+    }
+}
+
+/// Layouts without a `caps` mode mostly expect caps lock to type capital
+/// letters, which Android only does when a key says so
+fn add_implied_caps_lock(behaviours: &mut Vec<(&'static str, char)>) {
+    let get = |name: &str, behaviours: &[(&str, char)]| {
+        behaviours
+            .iter()
+            .find(|(behaviour, _)| *behaviour == name)
+            .map(|(_, c)| *c)
+    };
+    if get("capslock", behaviours).is_some() {
+        return;
+    }
+    if let (Some(base), Some(shift)) = (get("base", behaviours), get("shift", behaviours)) {
+        if base.is_lowercase() && base.to_uppercase().eq(std::iter::once(shift)) {
+            behaviours.push(("capslock", shift));
+            behaviours.push(("shift+capslock", base));
+        }
+    }
+}
+
+/// The character a key types in a mode, as a combining accent for dead keys
+fn key_char(
+    name: &str,
+    layout: &Layout,
+    target: Target,
+    modes: &DesktopModes,
+    mode: &ModifierSet,
+    key: IsoKey,
+) -> Option<char> {
+    let value = match modes.get_mode(mode)?.0.get(&key)? {
+        KeyValue::Symbol(value) => value,
+        _ => return None,
+    };
+    let item = || format!("key `{}` in `{}`", key, mode);
+    let mut chars = value.chars();
+    let c = match (chars.next(), chars.next()) {
+        (Some(c), None) => c,
+        _ => {
+            loss::report(Loss::dropped("android", name, item()).because(format!(
+                "`{}` is more than one character, which a .kcm cannot type",
+                value
+            )));
+            return None;
+        }
+    };
+    // Characters outside the BMP cannot be written in a .kcm
+    if c as u32 > 0xFFFF {
+        loss::report(
+            Loss::dropped("android", name, item())
+                .because(format!("U+{:04X} cannot be written in a .kcm", c as u32)),
+        );
+        return None;
+    }
+
+    if is_dead_key(layout, target, mode, value) {
+        match DEAD_KEY_ACCENTS.iter().find(|(spacing, _)| *spacing == c) {
+            Some((_, accent)) => return Some(*accent),
+            None => loss::report(Loss::altered("android", name, item()).because(format!(
+                "Android has no dead key for `{}`, it will be typed as it is",
+                value
+            ))),
+        }
+    }
+    Some(c)
+}
+
+fn is_dead_key(layout: &Layout, target: Target, mode: &ModifierSet, value: &str) -> bool {
+    layout
+        .dead_keys
+        .as_ref()
+        .and_then(|dead_keys| dead_keys.get(&target))
+        .into_iter()
+        .flatten()
+        .filter(|(name, _)| name.parse::<ModifierSet>().ok().as_ref() == Some(mode))
+        .any(|(_, keys)| keys.iter().any(|key| key == value))
+}
+
+/// What is printed on the key: the capital letter for letters, the base
+/// character otherwise
+fn label(behaviours: &[(&str, char)]) -> Option<char> {
+    let (_, base) = behaviours
+        .iter()
+        .find(|(behaviour, _)| *behaviour == "base")?;
+    if is_combining(*base) {
+        return None;
+    }
+    let mut upper = base.to_uppercase();
+    match (upper.next(), upper.next()) {
+        (Some(upper), None) => Some(upper),
+        _ => Some(*base),
+    }
+}
+
+fn is_combining(c: char) -> bool {
+    matches!(c, '\u{0300}'..='\u{036F}')
+}
+
+fn char_literal(c: char) -> String {
+    match c {
+        '\\' => r"'\\'".to_string(),
+        '\'' => r"'\''".to_string(),
+        '"' => r#"'\"'"#.to_string(),
+        c if c.is_control() || c.is_whitespace() || is_combining(c) => {
+            format!("'\\u{:04x}'", c as u32)
+        }
+        c => format!("'{}'", c),
+    }
+}
+
+/// The Android key code at the position of the ISO key
+pub(crate) fn key_code(key: IsoKey) -> &'static str {
+    use IsoKey::*;
+
+    match key {
+        E00 => "GRAVE",
+        E01 => "1",
+        E02 => "2",
+        E03 => "3",
+        E04 => "4",
+        E05 => "5",
+        E06 => "6",
+        E07 => "7",
+        E08 => "8",
+        E09 => "9",
+        E10 => "0",
+        E11 => "MINUS",
+        E12 => "EQUALS",
+        D01 => "Q",
+        D02 => "W",
+        D03 => "E",
+        D04 => "R",
+        D05 => "T",
+        D06 => "Y",
+        D07 => "U",
+        D08 => "I",
+        D09 => "O",
+        D10 => "P",
+        D11 => "LEFT_BRACKET",
+        D12 => "RIGHT_BRACKET",
+        C01 => "A",
+        C02 => "S",
+        C03 => "D",
+        C04 => "F",
+        C05 => "G",
+        C06 => "H",
+        C07 => "J",
+        C08 => "K",
+        C09 => "L",
+        C10 => "SEMICOLON",
+        C11 => "APOSTROPHE",
+        C12 => "BACKSLASH",
+        // The key left of Z on ISO keyboards, called PLUS by Android
+        B00 => "PLUS",
+        B01 => "Z",
+        B02 => "X",
+        B03 => "C",
+        B04 => "V",
+        B05 => "B",
+        B06 => "N",
+        B07 => "M",
+        B08 => "COMMA",
+        B09 => "PERIOD",
+        B10 => "SLASH",
+    }
+}
+
+/// The `keyboard_layouts.xml` that declares the key character maps
+fn write_keyboard_layouts(
+    maps: &[KeyCharacterMap],
+    stamp: Option<&Stamp>,
+    w: &mut dyn Write,
+) -> io::Result<()> {
+    let mut w = XmlWriter::new(w);
+    w.declaration()?;
+    if let Some(stamp) = stamp {
+        stamp.write_xml(&mut w)?;
+    }
+    w.start(
+        "keyboard-layouts",
+        &[(
+            "xmlns:android",
+            "http://schemas.android.com/apk/res/android",
+        )],
+    )?;
+    for kcm in maps {
+        w.empty(
+            "keyboard-layout",
+            &[
+                ("android:name", &kcm.resource_name),
+                ("android:label", &kcm.label),
+                (
+                    "android:keyboardLayout",
+                    &format!("@raw/{}", kcm.resource_name),
+                ),
+            ],
+        )?;
+    }
+    w.end()?;
+    w.finish().map(drop)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not load kbdgen bundle")]
+    CannotLoad { source: crate::LoadError },
+    #[error("Could not expand layout macros")]
+    CannotExpandMacros { source: crate::cli::macros::Error },
+    #[error("Could not create file `{}`", path.display())]
+    CannotCreateFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Could not write file `{}`", path.display())]
+    CannotWriteFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAYOUT: &str = r#"
+displayNames:
+  en: Test
+modes:
+  win:
+    default: |
+      § 1 2 3 4 5 6 7 8 9 0 + ´
+        á w e r t y u i o p å ŋ
+        a s d f g h j k l ö ä '
+      < z x c v b n m , . -
+    shift: |
+      ½ ! " # ¤ % & / ( ) = ? `
+        Á W E R T Y U I O P Å Ŋ
+        A S D F G H J K L Ö Ä *
+      > Z X C V B N M ; : _
+    alt: |
+      \u{0} \u{0} @ £ $ \u{0} \u{0} { [ ] } \ \u{0}
+        \u{0} \u{0} € \u{0} ŧ \u{0} \u{0} \u{0} \u{0} \u{0} \u{0} ~
+        \u{0} \u{0} \u{0} \u{0} \u{0} \u{0} \u{0} \u{0} \u{0} \u{0} \u{0} \u{0}
+      | \u{0} \u{0} \u{0} \u{0} \u{0} \u{0} \u{0} \u{0} \u{0} \u{0}
+deadKeys:
+  win:
+    default: ["´"]
+    shift: ["`"]
+transforms:
+  ´:
+    a: á
+"#;
+
+    #[test]
+    fn desktop_modes_become_key_behaviours() {
+        let layout: Layout = serde_yaml::from_str(LAYOUT).unwrap();
+        let kcm =
+            KeyCharacterMap::from_layout("se-SE", &layout, &LocaleFallbacks::default()).unwrap();
+        assert_eq!(kcm.resource_name, "keyboard_layout_se_se");
+        assert_eq!(kcm.label, "Test");
+
+        let text = kcm.to_kcm();
+        assert!(text.starts_with("type OVERLAY\n"));
+        assert!(text.contains(
+            "key A {\n    label:                  'A'\n    base:                   'a'\n    shift:                  'A'\n    capslock:               'A'\n    shift+capslock:         'a'\n}\n"
+        ));
+        assert!(text.contains("key EQUALS {\n    base:                   '\\u0301'\n    shift:                  '\\u0300'\n}\n"));
+        assert!(text.contains("    ralt:                   '\\\\'\n"));
+        assert!(text.contains("key PLUS {\n    label:                  '<'\n"));
+        assert!(text.contains("    shift:                  '\\\"'\n"));
+    }
+
+    #[test]
+    fn layouts_without_desktop_modes_have_no_map() {
+        let layout: Layout = serde_yaml::from_str(
+            "displayNames:\n  en: Test\nmodes:\n  mobile:\n    default: |\n      a b c\n",
+        )
+        .unwrap();
+        assert!(
+            KeyCharacterMap::from_layout("test", &layout, &LocaleFallbacks::default()).is_none()
+        );
+    }
+}
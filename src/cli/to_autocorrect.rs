@@ -0,0 +1,84 @@
+//! Tap-error correction dictionaries for the mobile apps
+//!
+//! For each layout with a wordlist in `resources/wordlists/<layout>.txt` and
+//! mobile modes for the target, writes `autocorrect/<layout>.json` with the
+//! words as the keys tapped to type them and the keys' positions and
+//! neighbours, for the generated app projects to pick up. See
+//! `Layout::tap_dictionary`.
+
+use crate::{parse_wordlist, Target};
+use log::{debug, warn};
+use std::path::{Path, PathBuf};
+
+/// Where the wordlists of a bundle are, one per layout
+pub const WORDLISTS_DIR: &str = "resources/wordlists";
+
+pub fn kbdgen_to_autocorrect(input: &Path, output: &Path, target: Target) -> Result<(), Error> {
+    let bundle = crate::cli::load_bundle(input).map_err(|source| Error::CannotLoad { source })?;
+    let mut layouts = bundle.layouts.iter().collect::<Vec<_>>();
+    layouts.sort_by_key(|(name, _)| name.as_str());
+
+    for (name, layout) in layouts {
+        let wordlist = input.join(WORDLISTS_DIR).join(format!("{}.txt", name));
+        if !wordlist.exists() {
+            debug!("Layout `{}` has no wordlist, skipping", name);
+            continue;
+        }
+        let text = std::fs::read_to_string(&wordlist).map_err(|source| Error::CannotRead {
+            path: wordlist.clone(),
+            source,
+        })?;
+        let dictionary = match layout.tap_dictionary(target, parse_wordlist(&text)) {
+            Some(dictionary) => dictionary,
+            None => {
+                debug!(
+                    "Layout `{}` has no mobile modes for {}, skipping",
+                    name, target
+                );
+                continue;
+            }
+        };
+        if !dictionary.untypeable.is_empty() {
+            warn!(
+                "{} words of `{}` cannot be typed with layout `{}` on {}, like `{}`",
+                dictionary.untypeable.len(),
+                wordlist.display(),
+                name,
+                target,
+                dictionary.untypeable[0]
+            );
+        }
+
+        let path = output.join("autocorrect").join(format!("{}.json", name));
+        let json = serde_json::to_string_pretty(&dictionary).expect("dictionaries serialize");
+        std::fs::create_dir_all(path.parent().expect("has parent")).map_err(|source| {
+            Error::CannotWriteFile {
+                path: path.clone(),
+                source,
+            }
+        })?;
+        std::fs::write(&path, json).map_err(|source| Error::CannotWriteFile {
+            path: path.clone(),
+            source,
+        })?;
+        log::info!("Wrote to file `{}`", path.display());
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not load kbdgen bundle")]
+    CannotLoad { source: crate::LoadError },
+    #[error("Could not read wordlist `{}`", path.display())]
+    CannotRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Could not write to file `{}`", path.display())]
+    CannotWriteFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
@@ -1,214 +1,400 @@
-// use strum::IntoEnumIterator;
-// use crate::{
-//     cldr::{Keyboard, *},
-//     models::{DesktopModes, IsoKey, MobileModes},
-//     Load, ProjectBundle,
-// };
-// use log::{debug, log_enabled};
-// use snafu::{ResultExt, Snafu};
-// use snafu_cli_debug::SnafuCliDebug;
-// use std::{collections::BTreeMap, fs::File, io::BufWriter, path::PathBuf};
-// use structopt::StructOpt;
-
-// pub fn kbdgen_to_cldr() -> Result<(), Error> {
-//     // let _ = opts.verbose.setup_env_logger("kbdgen-cli");
-
-//     let bundle = ProjectBundle::load(&opts.input).context(CannotLoad)?;
-//     if log_enabled!(log::Level::Debug) {
-//         debug!("Bundle `{}` loaded", opts.input.display());
-//         let locales = bundle
-//             .project
-//             .locales
-//             .values()
-//             .map(|l| l.name.as_str())
-//             .collect::<Vec<_>>();
-//         debug!("Bundle contains these locales: {:?}", locales);
-//     }
-
-//     bundle
-//         .layouts
-//         .iter()
-//         .map(|(name, layout)| (name, layout_to_cldr(&name, layout, &bundle)))
-//         .try_for_each(|(name, keyboards)| {
-//             for (platform, keyboard) in keyboards? {
-//                 let path = &opts.output.join(name).join(platform).with_extension("xml");
-//                 std::fs::create_dir_all(path.parent().unwrap())
-//                     .context(CannotCreateFile { path })?;
-//                 let file = File::create(path).context(CannotCreateFile { path })?;
-//                 let mut writer = BufWriter::new(file);
-//                 keyboard
-//                     .write_xml(&mut writer)
-//                     .context(CannotSerializeXml)?;
-//             }
-//             Ok(())
-//         })
-//         .context(CannotBeSaved)?;
-
-//     Ok(())
-// }
-
-// fn layout_to_cldr(
-//     name: &str,
-//     layout: &crate::models::Layout,
-//     _project: &crate::ProjectBundle,
-// ) -> Result<Vec<(String, Keyboard)>, SavingError> {
-//     log::debug!("to cldr with you, {}!", name);
-
-//     let mut res = vec![];
-
-//     macro_rules! mode {
-//         (mobile: $name:ident) => {
-//             mode!(mobile_mode_to_keyboard -> $name)
-//         };
-//         (desktop: $name:ident) => {
-//             mode!(desktop_mode_to_keyboard -> $name)
-//         };
-//         ($fn:ident -> $name:ident) => {
-//             if let Some(a) = layout.modes.$name.as_ref() {
-//                 log::debug!("{}: check", stringify!($name));
-//                 res.push((
-//                     String::from(stringify!($name)),
-//                     $fn(name, stringify!($name), a, layout.longpress.as_ref(), layout),
-//                 ));
-//             }
-//         };
-//     }
-
-//     mode!(mobile: android);
-//     mode!(mobile: ios);
-//     mode!(mobile: mobile);
-//     mode!(desktop: win);
-//     mode!(desktop: mac);
-//     mode!(desktop: chrome);
-//     mode!(desktop: x11);
-//     mode!(desktop: desktop);
-
-//     Ok(res)
-// }
-
-// fn desktop_mode_to_keyboard(
-//     name: &str,
-//     target: &str,
-//     desktop: &DesktopModes,
-//     long_presses: Option<&BTreeMap<String, String>>,
-//     layout: &crate::models::Layout,
-// ) -> Keyboard {
-//     let mut key_maps = vec![];
-
-//     for (modifiers, mapping) in desktop {
-//         let keys = mapping
-//             .iter()
-//             .filter_map(|(iso, value)| {
-//                 if let Some(value) = value.as_ref() {
-//                     Some((iso, value))
-//                 } else {
-//                     None
-//                 }
-//             })
-//             .map(|(iso, value)| {
-//                 let long_press = long_presses.and_then(|l| l.get(value.as_str())).cloned();
-//                 Map {
-//                     iso: iso.to_string(),
-//                     to: value.to_string(),
-//                     transform: None,
-//                     long_press,
-//                 }
-//             })
-//             .collect();
-
-//         key_maps.push(KeyMap {
-//             keys,
-//             modifiers: Some(modifiers.to_string()),
-//         })
-//     }
-
-//     Keyboard {
-//         locale: format!("{}-t-k0-{}", name, target),
-//         names: vec![Names {
-//             values: vec![Name {
-//                 value: pick_name_from_display_names(&layout.display_names),
-//             }],
-//         }],
-//         key_maps,
-//         ..Keyboard::default()
-//     }
-// }
-
-// fn mobile_mode_to_keyboard(
-//     name: &str,
-//     target: &str,
-//     mobile: &MobileModes,
-//     long_presses: Option<&BTreeMap<String, String>>,
-//     layout: &crate::models::Layout,
-// ) -> Keyboard {
-//     let mut key_maps = vec![];
-
-//     for (modifiers, mapping) in mobile {
-//         use strum::IntoEnumIterator;
-//         let keys = IsoKey::iter()
-//             .zip(mapping.iter())
-//             .map(|(iso, value)| {
-//                 let long_press = long_presses.and_then(|l| l.get(value)).cloned();
-//                 Map {
-//                     iso: iso.to_string(),
-//                     to: value.to_string(),
-//                     transform: None,
-//                     long_press,
-//                 }
-//             })
-//             .collect();
-
-//         key_maps.push(KeyMap {
-//             keys,
-//             modifiers: Some(modifiers.to_string()),
-//         })
-//     }
-
-//     Keyboard {
-//         locale: format!("{}-t-k0-{}", name, target),
-//         names: vec![Names {
-//             values: vec![Name {
-//                 value: pick_name_from_display_names(&layout.display_names),
-//             }],
-//         }],
-//         key_maps,
-//         ..Keyboard::default()
-//     }
-// }
-
-// fn pick_name_from_display_names(names: &BTreeMap<String, String>) -> String {
-//     names
-//         .get("en")
-//         .or_else(|| names.values().next())
-//         .cloned()
-//         .unwrap_or_default()
-// }
-
-// #[derive(Snafu, SnafuCliDebug)]
-// pub enum Error {
-//     #[snafu(display("Could not load kbdgen bundle"))]
-//     CannotLoad {
-//         source: crate::LoadError,
-//         backtrace: backtrace::Backtrace,
-//     },
-//     #[snafu(display("Could not write CLDR file"))]
-//     CannotBeSaved {
-//         source: SavingError,
-//         backtrace: backtrace::Backtrace,
-//     },
-// }
-
-// #[derive(Snafu, Debug)]
-// pub enum SavingError {
-//     #[snafu(display("Could not create file `{}`", path.display()))]
-//     CannotCreateFile {
-//         path: PathBuf,
-//         source: std::io::Error,
-//         backtrace: backtrace::Backtrace,
-//     },
-//     #[snafu(display("Could transform to xml"))]
-//     CannotSerializeXml {
-//         source: std::io::Error,
-//         backtrace: backtrace::Backtrace,
-//     },
-// }
+//! Exporting layouts as CLDR keyboards
+//!
+//! Each layout becomes a keyboard for every CLDR platform it has modes for:
+//! Android, Windows, macOS and Chrome OS. With `for_submission`, the files
+//! are laid out as in the `keyboards` directory of the CLDR repository, with
+//! its copyright header, so that they can be copied into a checkout and sent
+//! upstream. Layouts imported from CLDR keep the file names they came from,
+//! see `Layout::cldr`.
+//!
+//! What CLDR keyboards cannot hold, like mobile symbol pages and special
+//! keys, is reported as losses.
+
+use crate::{
+    cldr::{
+        ir::parse_modifiers, KeyMap, Keyboard, Map, Name, Names, Settings, Transform, Transforms,
+        Version,
+    },
+    cli::from_cldr::platform_dir,
+    loss::{self, Loss},
+    models::{DesktopModes, Layout, MobileModes, Project, TransformPartial},
+    output_layout::{layout_path, LayoutFile},
+    KeyValue, Load, ProjectBundle, ResolvedModes, Target,
+};
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+/// The targets CLDR has keyboards for, by the platform they are exported as
+const TARGETS: &[Target] = &[Target::Android, Target::Win, Target::Mac, Target::Chrome];
+
+/// The loss target of exported keyboards
+const LOSS_TARGET: &str = "cldr";
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Write the files as they are laid out in the CLDR repository, with its
+    /// copyright header
+    pub for_submission: bool,
+    /// Only export this layout
+    pub layout: Option<String>,
+}
+
+/// Exports the layouts of the bundle at `input` into `output`, returning the
+/// paths of the written files
+pub fn kbdgen_to_cldr(
+    input: &Path,
+    output: &Path,
+    options: &Options,
+) -> Result<Vec<PathBuf>, Error> {
+    let bundle = ProjectBundle::load(input).map_err(|source| Error::CannotLoad { source })?;
+    let mut names = bundle.layouts.keys().collect::<Vec<_>>();
+    names.sort();
+    if let Some(only) = &options.layout {
+        names.retain(|name| *name == only);
+        if names.is_empty() {
+            return Err(Error::NoSuchLayout { name: only.clone() });
+        }
+    }
+
+    let mut written = vec![];
+    for name in names {
+        let layout = &bundle.layouts[name];
+        for (target, keyboard) in layout_to_cldr(name, layout)? {
+            let path = output.join(file_path(name, layout, target, options.for_submission));
+            let header = if options.for_submission {
+                copyright_header(layout, &bundle.project)
+            } else {
+                vec![]
+            };
+            write_keyboard(&path, &keyboard, &header)?;
+            written.push(path);
+        }
+    }
+    Ok(written)
+}
+
+fn write_keyboard(path: &Path, keyboard: &Keyboard, header: &[String]) -> Result<(), Error> {
+    let cannot_write = |source| Error::CannotWrite {
+        path: path.to_path_buf(),
+        source,
+    };
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(cannot_write)?;
+    }
+    let file = File::create(path).map_err(cannot_write)?;
+    keyboard
+        .write_xml_with_header(header, BufWriter::new(file))
+        .map_err(cannot_write)
+}
+
+/// Where the keyboard of a target is written, relative to the output
+/// directory
+///
+/// Submissions go where CLDR keeps keyboards, under the name they were
+/// imported from if there is one. Otherwise each layout gets a directory
+/// with a file per platform, unless the build sets an output layout.
+fn file_path(name: &str, layout: &Layout, target: Target, for_submission: bool) -> PathBuf {
+    let platform = platform_dir(target).unwrap_or_default();
+    if !for_submission {
+        let file = LayoutFile {
+            target: "cldr",
+            layout: name,
+            part: Some(platform),
+            extension: "xml",
+        };
+        return layout_path(&file, || Path::new(name).join(format!("{}.xml", platform)));
+    }
+    match imported_file(layout, target) {
+        Some(file) => PathBuf::from(file),
+        None => Path::new("keyboards")
+            .join(platform)
+            .join(format!("{}.xml", locale(name, target))),
+    }
+}
+
+/// The file in the CLDR repository the target's modes were imported from
+fn imported_file(layout: &Layout, target: Target) -> Option<&str> {
+    let sources = layout.cldr.as_ref()?;
+    target
+        .fallback_chain()
+        .find_map(|target| sources.get(&target))
+        .map(|source| source.file.as_str())
+}
+
+fn locale(name: &str, target: Target) -> String {
+    format!("{}-t-k0-{}", name, platform_dir(target).unwrap_or_default())
+}
+
+/// The comment before the root element of CLDR files, followed by the
+/// copyright and license of the layout
+fn copyright_header(layout: &Layout, project: &Project) -> Vec<String> {
+    let mut header = vec![
+        format!(
+            "Copyright © 1991-{} Unicode, Inc.",
+            chrono::Utc::now().format("%Y")
+        ),
+        "For terms of use, see http://www.unicode.org/copyright.html".to_string(),
+        "Unicode and the Unicode Logo are registered trademarks of Unicode, Inc. in the U.S. and other countries.".to_string(),
+        "CLDR data files are interpreted according to the LDML specification (http://unicode.org/reports/tr35/)".to_string(),
+    ];
+    let contributed = [&project.copyright, &project.organisation]
+        .iter()
+        .filter(|line| !line.is_empty())
+        .map(|line| line.to_string())
+        .chain(layout.license_notice(project))
+        .collect::<Vec<_>>();
+    if !contributed.is_empty() {
+        header.push(String::new());
+        header.extend(contributed);
+    }
+    header
+}
+
+/// The version CLDR's own keyboards give for each platform
+fn platform_version(target: Target) -> &'static str {
+    match target {
+        Target::Android => "4.4",
+        Target::Win => "10",
+        Target::Mac => "10.9",
+        _ => "0.17",
+    }
+}
+
+/// The keyboards of the layout, for every CLDR platform it has modes for
+pub fn layout_to_cldr(name: &str, layout: &Layout) -> Result<Vec<(Target, Keyboard)>, Error> {
+    let mut layout = layout.clone();
+    layout
+        .expand_transform_patterns()
+        .map_err(|source| Error::InvalidTransforms { source })?;
+
+    let mut keyboards = vec![];
+    for &target in TARGETS {
+        let key_maps = match layout.modes.resolve(target) {
+            Some(ResolvedModes::Desktop(_, modes)) => desktop_key_maps(name, target, modes),
+            Some(ResolvedModes::Mobile(_, modes)) => mobile_key_maps(name, &layout, modes),
+            None => continue,
+        };
+
+        let locale = imported_file(&layout, target)
+            .and_then(|file| Path::new(file).file_stem())
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| locale(name, target));
+        let settings = match layout.transform_partial {
+            Some(partial) if TransformPartial::is_configurable_for(target) => Some(Settings {
+                transform_partial: Some(partial.to_string()),
+                ..Settings::default()
+            }),
+            _ => None,
+        };
+        keyboards.push((
+            target,
+            Keyboard {
+                locale,
+                names: vec![Names {
+                    values: vec![Name {
+                        value: pick_name_from_display_names(&layout),
+                    }],
+                }],
+                version: Version {
+                    platform: platform_version(target).to_string(),
+                    number: "$Revision$".to_string(),
+                },
+                key_maps,
+                settings,
+                transforms: transforms(&layout, target),
+            },
+        ));
+    }
+    Ok(keyboards)
+}
+
+/// The `modifiers` of a key map for a mode, `None` for the default mode
+fn modifiers(name: &str, target: Target, mode: &str) -> Result<Option<String>, ()> {
+    if mode == "default" {
+        return Ok(None);
+    }
+    match parse_modifiers(Some(mode)) {
+        Ok(_) => Ok(Some(mode.to_string())),
+        Err(e) => {
+            loss::report(
+                Loss::dropped(LOSS_TARGET, name, format!("mode `{}` of {}", mode, target))
+                    .because(e.to_string()),
+            );
+            Err(())
+        }
+    }
+}
+
+fn desktop_key_maps(name: &str, target: Target, modes: &DesktopModes) -> Vec<KeyMap> {
+    modes
+        .0
+        .iter()
+        .filter_map(|(mode, key_map)| {
+            let modifiers = modifiers(name, target, mode).ok()?;
+            let keys = key_map
+                .0
+                .iter()
+                .filter_map(|(iso, value)| match value {
+                    KeyValue::Symbol(to) => Some(Map {
+                        iso: iso.to_string(),
+                        to: to.clone(),
+                        ..Map::default()
+                    }),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            if keys.is_empty() {
+                return None;
+            }
+            Some(KeyMap { keys, modifiers })
+        })
+        .collect()
+}
+
+/// Mobile rows are numbered from the top, as on Android in CLDR: `D`, `C`
+/// and `B` for three rows, starting from `E` when there are more
+fn mobile_key_maps(name: &str, layout: &Layout, modes: &MobileModes) -> Vec<KeyMap> {
+    const ROWS: [char; 5] = ['E', 'D', 'C', 'B', 'A'];
+
+    modes
+        .0
+        .iter()
+        .filter_map(|(mode, key_map)| {
+            let modifiers = modifiers(name, Target::Android, &mode.to_string()).ok()?;
+            let first = if key_map.0.len() > 3 { 0 } else { 1 };
+            if key_map.0.len() > ROWS.len() - first {
+                loss::report(
+                    Loss::dropped(LOSS_TARGET, name, format!("rows of mode `{}`", mode))
+                        .because(format!("CLDR has at most {} rows", ROWS.len())),
+                );
+            }
+            let keys = key_map
+                .0
+                .iter()
+                .zip(&ROWS[first..])
+                .flat_map(|(row, letter)| {
+                    row.iter()
+                        .filter_map(|key| match key {
+                            KeyValue::Symbol(to) => Some(to),
+                            _ => None,
+                        })
+                        .enumerate()
+                        .map(move |(n, to)| (format!("{}{:02}", letter, n + 1), to))
+                })
+                .map(|(iso, to)| Map {
+                    iso,
+                    to: to.clone(),
+                    long_press: layout
+                        .longpress
+                        .as_ref()
+                        .and_then(|longpress| longpress.get(to))
+                        .cloned(),
+                    ..Map::default()
+                })
+                .collect::<Vec<_>>();
+            if keys.is_empty() {
+                return None;
+            }
+            Some(KeyMap { keys, modifiers })
+        })
+        .collect()
+}
+
+/// The transforms of the target's dead keys, as CLDR's simple transforms
+///
+/// The dead keys are those of the target the modes come from, like for
+/// `Layout::is_dead_key`.
+fn transforms(layout: &Layout, target: Target) -> Option<Vec<Transforms>> {
+    let target = layout.modes.resolve(target)?.target();
+    let dead_keys = layout.dead_keys.as_ref()?.get(&target)?;
+    let transforms = layout.transforms.as_ref()?;
+
+    let mut keys = dead_keys.values().flatten().collect::<Vec<_>>();
+    keys.sort();
+    keys.dedup();
+    let values = keys
+        .into_iter()
+        .filter_map(|dead_key| Some((dead_key, transforms.get(dead_key)?)))
+        .flat_map(|(dead_key, transform)| {
+            transform.iter().map(move |(input, output)| Transform {
+                from: format!("{}{}", dead_key, input),
+                to: output.clone(),
+            })
+        })
+        .collect::<Vec<_>>();
+    if values.is_empty() {
+        return None;
+    }
+    Some(vec![Transforms {
+        type_: "simple".to_string(),
+        values,
+    }])
+}
+
+fn pick_name_from_display_names(layout: &Layout) -> String {
+    let names = &layout.display_names;
+    names
+        .get("en")
+        .or_else(|| names.values().next())
+        .cloned()
+        .unwrap_or_default()
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not load kbdgen bundle")]
+    CannotLoad { source: crate::LoadError },
+
+    #[error("The bundle has no layout `{}`", name)]
+    NoSuchLayout { name: String },
+
+    #[error("Could not expand the layout's transform patterns")]
+    InvalidTransforms { source: crate::TransformError },
+
+    #[error("Could not write `{}`", path.display())]
+    CannotWrite {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn submissions_are_laid_out_as_in_cldr() {
+        let bundle = ProjectBundle::load("examples/sme.kbdgen").unwrap();
+        let layout = &bundle.layouts["se-NO"];
+        let keyboards = layout_to_cldr("se-NO", layout).unwrap();
+        let (target, windows) = keyboards
+            .iter()
+            .find(|(target, _)| *target == Target::Win)
+            .unwrap();
+        assert_eq!(windows.locale, "se-NO-t-k0-windows");
+        assert_eq!(
+            file_path("se-NO", layout, *target, true),
+            Path::new("keyboards/windows/se-NO-t-k0-windows.xml")
+        );
+        assert_eq!(
+            file_path("se-NO", layout, *target, false),
+            Path::new("se-NO/windows.xml")
+        );
+
+        let mut xml = vec![];
+        let header = copyright_header(layout, &bundle.project);
+        windows.write_xml_with_header(&header, &mut xml).unwrap();
+        let xml = String::from_utf8(xml).unwrap();
+        assert!(xml.contains(r#"<!DOCTYPE keyboard SYSTEM "../dtd/ldmlKeyboard.dtd">"#));
+        assert!(xml.contains(&bundle.project.copyright));
+
+        let parsed: Keyboard = serde_xml_rs::from_str(&xml).unwrap();
+        assert_eq!(
+            parsed.to_desktop_modes().unwrap(),
+            windows.to_desktop_modes().unwrap()
+        );
+    }
+}
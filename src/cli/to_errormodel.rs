@@ -1,4 +1,4 @@
-use crate::{bundle::keys::KeyValue, Load, ProjectBundle};
+use crate::{bundle::keys::KeyValue, MobileMode, Target};
 use bigdecimal::ToPrimitive;
 use log::{debug, log_enabled};
 use std::{
@@ -34,7 +34,10 @@ impl KeyCoordinate {
 }
 
 pub fn kbdgen_to_errormodel(input: &Path, output: &Path, _options: &Options) -> Result<(), Error> {
-    let bundle = ProjectBundle::load(input).map_err(|source| Error::CannotLoad { source })?;
+    let mut bundle =
+        crate::cli::load_bundle(input).map_err(|source| Error::CannotLoad { source })?;
+    crate::cli::macros::expand_layout_macros(&mut bundle)
+        .map_err(|source| Error::CannotExpandMacros { source })?;
 
     if log_enabled!(log::Level::Debug) {
         debug!("Bundle `{}` loaded", input.display());
@@ -54,10 +57,18 @@ pub fn kbdgen_to_errormodel(input: &Path, output: &Path, _options: &Options) ->
             layout: _options.layout.to_owned(),
         })?;
 
-    let ios = layout.modes.ios.as_ref().unwrap();
-    let mode = ios.get("default").ok_or_else(|| Error::CouldNotFindMode {
-        mode: "default".into(),
-    })?;
+    let (_, ios) =
+        layout
+            .modes
+            .resolve_mobile(Target::Ios)
+            .ok_or_else(|| Error::NoMobileModes {
+                layout: _options.layout.to_owned(),
+            })?;
+    let mode = ios
+        .get(&MobileMode::Default)
+        .ok_or_else(|| Error::CouldNotFindMode {
+            mode: "default".into(),
+        })?;
 
     let longest = mode
         .0
@@ -139,8 +150,12 @@ pub struct Options {
 pub enum Error {
     #[error("Could not load kbdgen bundle")]
     CannotLoad { source: crate::LoadError },
+    #[error("Could not expand layout macros")]
+    CannotExpandMacros { source: crate::cli::macros::Error },
     #[error("Could not find layout {layout:?}")]
     CouldNotFindLayout { layout: String },
+    #[error("Layout {layout:?} has no `ios` or `mobile` modes")]
+    NoMobileModes { layout: String },
     #[error("Could not find mode {mode:?}")]
     CouldNotFindMode { mode: String },
     #[error("Could not create file {path:?}")]
@@ -1,17 +1,28 @@
-use crate::{bundle::keys::KeyValue, m17n_mim::*, models::DesktopModes, Load, ProjectBundle};
+use crate::{
+    bundle::keys::KeyValue,
+    loss::{self, Loss},
+    m17n_mim::*,
+    models::DesktopModes,
+    output_layout::{layout_path, LayoutFile},
+    stamp::{self, Stamp},
+    ProjectBundle, Target,
+};
 use log::{debug, log_enabled};
 use std::{
     collections::BTreeMap,
     convert::TryFrom,
     fs::File,
-    io::BufWriter,
+    io::{BufWriter, Write},
     path::{Path, PathBuf},
 };
 
 pub fn kbdgen_to_mim(input: &Path, output: &Path) -> Result<(), Error> {
     // let _ = opts.verbose.setup_env_logger("kbdgen-cli");
 
-    let bundle = ProjectBundle::load(input).map_err(|source| Error::CannotLoad { source })?;
+    let mut bundle =
+        crate::cli::load_bundle(input).map_err(|source| Error::CannotLoad { source })?;
+    crate::cli::macros::expand_layout_macros(&mut bundle)
+        .map_err(|source| Error::CannotExpandMacros { source })?;
     if log_enabled!(log::Level::Debug) {
         debug!("Bundle `{}` loaded", input.display());
         let locales = bundle
@@ -23,13 +34,28 @@ pub fn kbdgen_to_mim(input: &Path, output: &Path) -> Result<(), Error> {
         debug!("Bundle contains these locales: {:?}", locales);
     }
 
+    bundle_to_mim(&bundle, output)
+}
+
+/// Writes the input methods for the layouts of an already loaded bundle
+pub fn bundle_to_mim(bundle: &ProjectBundle, output: &Path) -> Result<(), Error> {
+    let stamp = Stamp::for_bundle(bundle);
     bundle
         .layouts
         .iter()
-        .map(|(name, layout)| (name, layout_to_mim(&name, layout, &bundle)))
-        .try_for_each(|(name, keyboards)| {
+        .map(|(name, layout)| (name, layout, layout_to_mim(name, layout, bundle)))
+        .try_for_each(|(name, layout, keyboards)| {
             for (platform, keyboard) in keyboards? {
-                let path = output.join(name).join(platform).with_extension("mim");
+                let part = platform.to_string();
+                let file = LayoutFile {
+                    target: "m17n",
+                    layout: name,
+                    part: Some(&part),
+                    extension: "mim",
+                };
+                let path = output.join(layout_path(&file, || {
+                    Path::new(name).join(format!("{}.mim", platform))
+                }));
                 std::fs::create_dir_all(path.parent().unwrap()).map_err(|source| {
                     SavingError::CannotCreateFile {
                         path: path.clone(),
@@ -42,6 +68,14 @@ pub fn kbdgen_to_mim(input: &Path, output: &Path) -> Result<(), Error> {
                 })?;
                 debug!("Created file `{}`", path.display());
                 let mut writer = BufWriter::new(file);
+                let mut header = stamp
+                    .as_ref()
+                    .map(|stamp| stamp.with_layout(name).comment(";;"))
+                    .unwrap_or_default();
+                header += &stamp::comment(&layout.license_notice(&bundle.project), ";;");
+                writer
+                    .write_all(header.as_bytes())
+                    .map_err(|source| SavingError::CannotSerializeMim { source })?;
                 keyboard
                     .write_mim(&mut writer)
                     .map_err(|source| SavingError::CannotSerializeMim { source })?;
@@ -54,6 +88,15 @@ pub fn kbdgen_to_mim(input: &Path, output: &Path) -> Result<(), Error> {
     Ok(())
 }
 
+/// Targets that get their own input method, each from their desktop modes
+const MIM_SOURCE_TARGETS: &[Target] = &[
+    Target::Win,
+    Target::Mac,
+    Target::Chrome,
+    Target::X11,
+    Target::Desktop,
+];
+
 fn layout_to_mim(
     name: &str,
     layout: &crate::models::Layout,
@@ -62,36 +105,32 @@ fn layout_to_mim(
     log::debug!("to mim with you, {}!", name);
     let mut res = vec![];
 
-    macro_rules! mode {
-        (desktop: $platform:ident) => {
-            mode!(desktop_mode_to_keyboard -> $platform)
-        };
-        ($fn:ident -> $platform:ident) => {
-            if let Some(a) = layout.modes.$platform.as_ref() {
-                log::debug!("{}: check", stringify!($platform));
-                let dead_key_rules = dead_key_transforms(&layout, stringify!($platform))
-                    .map_err(|source| SavingError::CannotCreateTransformMap {source})?;
-
-                res.push((
-                    String::from(stringify!($platform)),
-                    $fn(name, stringify!($platform), a, dead_key_rules, project)?,
-                ));
-            }
-        };
+    if layout.has_reordering() {
+        loss::report(
+            Loss::dropped("m17n", name, "reorders and backspaces")
+                .because("m17n cannot express them"),
+        );
     }
 
-    mode!(desktop: win);
-    mode!(desktop: mac);
-    mode!(desktop: chrome);
-    mode!(desktop: x11);
-    mode!(desktop: desktop);
+    for &target in MIM_SOURCE_TARGETS {
+        if let Some(modes) = layout.modes.desktop_for(target) {
+            log::debug!("{}: check", target);
+            let dead_key_rules = dead_key_transforms(layout, target)
+                .map_err(|source| SavingError::CannotCreateTransformMap { source })?;
+
+            res.push((
+                target.to_string(),
+                desktop_mode_to_keyboard(name, target, modes, dead_key_rules, project)?,
+            ));
+        }
+    }
 
     Ok(res)
 }
 
 fn desktop_mode_to_keyboard(
     name: &str,
-    target: &str,
+    target: Target,
     desktop: &DesktopModes,
     dead_key_transforms: Vec<Rule>,
     project: &crate::ProjectBundle,
@@ -198,7 +237,7 @@ fn desktop_mode_to_keyboard(
 /// Generates MIM rules in the style of `(("´" "a") "á")`.
 fn dead_key_transforms(
     layout: &crate::models::Layout,
-    platform: &str,
+    platform: Target,
 ) -> Result<Vec<Rule>, MimConversion> {
     let mut rules = vec![];
 
@@ -208,7 +247,7 @@ fn dead_key_transforms(
     let dead_key_map = layout
         .dead_keys
         .as_ref()
-        .and_then(|dead_keys| dead_keys.get(platform))
+        .and_then(|dead_keys| dead_keys.get(&platform))
         .unwrap_or_else(|| &empty_dead_keys);
     let transforms = layout
         .transforms
@@ -247,6 +286,8 @@ fn dead_key_transforms(
 pub enum Error {
     #[error("Could not load kbdgen bundle")]
     CannotLoad { source: crate::LoadError },
+    #[error("Could not expand layout macros")]
+    CannotExpandMacros { source: crate::cli::macros::Error },
     #[error("Could not write CLDR file")]
     CannotBeSaved { source: SavingError },
 }
@@ -0,0 +1,107 @@
+//! Per-layout icons for the macOS bundle
+//!
+//! The bundle itself is built by the Python generator, which copies the
+//! `.icns` input source icon written here for each layout into it.
+
+use crate::{
+    macos::{IconError, IconFamily},
+    Target,
+};
+use log::debug;
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+pub fn kbdgen_to_macos_resources(input: &Path, output: &Path) -> Result<(), Error> {
+    let bundle = crate::cli::load_bundle(input).map_err(|source| Error::CannotLoad { source })?;
+    let target = bundle.targets.mac_os.as_ref().ok_or(Error::NoMacTarget)?;
+
+    std::fs::create_dir_all(output).map_err(|source| Error::CannotCreateFile {
+        path: output.to_path_buf(),
+        source,
+    })?;
+
+    let mut names = bundle
+        .layouts
+        .iter()
+        .filter(|(_, layout)| layout.modes.resolve(Target::Mac).is_some())
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+    names.sort();
+
+    for name in names {
+        let layout_target = bundle.layouts[name]
+            .targets
+            .as_ref()
+            .and_then(|targets| targets.mac.as_ref());
+
+        let conventional_icon = input
+            .join("resources/mac")
+            .join(format!("icon.{}.png", name));
+        let icon = layout_target
+            .and_then(|t| t.icon.as_ref())
+            .map(|icon| input.join(icon))
+            .or_else(|| Some(conventional_icon).filter(|path| path.exists()))
+            .or_else(|| target.icon.as_ref().map(|icon| input.join(icon)));
+
+        if let Some(icon) = icon {
+            let path = output.join(format!("{}.icns", name));
+            write_icns(&icon, &path)?;
+            log::info!("Wrote to file `{}`", path.display());
+        } else {
+            debug!("No icon for layout `{}`", name);
+        }
+    }
+
+    Ok(())
+}
+
+fn write_icns(icon: &Path, path: &Path) -> Result<(), Error> {
+    let png = std::fs::read(icon).map_err(|source| Error::CannotReadIcon {
+        path: icon.to_path_buf(),
+        source,
+    })?;
+
+    let family = IconFamily::from_png(png).map_err(|source| Error::InvalidIcon {
+        path: icon.to_path_buf(),
+        source,
+    })?;
+
+    let file = File::create(path).map_err(|source| Error::CannotCreateFile {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    family
+        .write_icns(BufWriter::new(file))
+        .map_err(|source| Error::CannotWriteFile {
+            path: path.to_path_buf(),
+            source,
+        })
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not load kbdgen bundle")]
+    CannotLoad { source: crate::LoadError },
+    #[error("Bundle has no macOS target configuration")]
+    NoMacTarget,
+    #[error("Could not read icon `{}`", path.display())]
+    CannotReadIcon {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Icon `{}` cannot be used", path.display())]
+    InvalidIcon { path: PathBuf, source: IconError },
+    #[error("Could not create file `{}`", path.display())]
+    CannotCreateFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Could not write to file `{}`", path.display())]
+    CannotWriteFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
@@ -0,0 +1,186 @@
+//! Localised strings for the mobile apps
+//!
+//! Writes an Android `kbdgen_strings.xml` or iOS `Localizable.strings` per
+//! locale in `project.locales` into `strings`, laid out like the app project
+//! that the Python generators copy it into. Strings a locale does not define
+//! fall back to its `en` value.
+
+use crate::{models::ProjectDesc, stamp::Stamp, xml_writer::XmlWriter, Target};
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    path::{Path, PathBuf},
+};
+
+const DEFAULT_LOCALE: &str = "en";
+
+pub fn kbdgen_to_mobile_strings(input: &Path, output: &Path, target: Target) -> Result<(), Error> {
+    let bundle = crate::cli::load_bundle(input).map_err(|source| Error::CannotLoad { source })?;
+    let locales = &bundle.project.locales;
+    let fallback = locales.get(DEFAULT_LOCALE);
+    let stamp = Stamp::for_bundle(&bundle);
+
+    for (locale, desc) in locales {
+        let strings = collect_strings(desc, fallback);
+        let (path, content) = match target {
+            Target::Android => (
+                output
+                    .join("strings/app/src/main/res")
+                    .join(android_values_dir(locale))
+                    .join("kbdgen_strings.xml"),
+                android_strings_xml(&strings, stamp.as_ref()),
+            ),
+            Target::Ios => (
+                output
+                    .join("strings/HostingApp")
+                    .join(format!("{}.lproj", ios_lproj(locale)))
+                    .join("Localizable.strings"),
+                Ok(ios_localizable_strings(&strings, stamp.as_ref()).into_bytes()),
+            ),
+            _ => return Err(Error::UnsupportedTarget { target }),
+        };
+        let content = content.map_err(|source| Error::CannotWriteFile {
+            path: path.clone(),
+            source,
+        })?;
+
+        std::fs::create_dir_all(path.parent().expect("has parent")).map_err(|source| {
+            Error::CannotWriteFile {
+                path: path.clone(),
+                source,
+            }
+        })?;
+        std::fs::write(&path, content).map_err(|source| Error::CannotWriteFile {
+            path: path.clone(),
+            source,
+        })?;
+        log::info!("Wrote to file `{}`", path.display());
+    }
+
+    Ok(())
+}
+
+fn collect_strings<'a>(
+    desc: &'a ProjectDesc,
+    fallback: Option<&'a ProjectDesc>,
+) -> BTreeMap<&'static str, &'a str> {
+    let mut strings = BTreeMap::new();
+    let fallback_strings = fallback.and_then(|desc| desc.strings.as_ref());
+    let own_strings = desc.strings.as_ref();
+    for app_strings in fallback_strings.into_iter().chain(own_strings) {
+        strings.extend(app_strings.entries());
+    }
+    strings.insert("app_name", desc.name.as_str());
+    strings.insert("app_description", desc.description.as_str());
+    strings
+}
+
+/// `en` is the default resource set, everything else is qualified by its
+/// BCP 47 tag
+fn android_values_dir(locale: &str) -> String {
+    if locale == DEFAULT_LOCALE {
+        "values".to_string()
+    } else {
+        format!("values-b+{}", locale.replace('-', "+"))
+    }
+}
+
+/// `en` is the base localisation of the hosting app, like its `InfoPlist.strings`
+fn ios_lproj(locale: &str) -> &str {
+    if locale == DEFAULT_LOCALE {
+        "Base"
+    } else {
+        locale
+    }
+}
+
+fn android_strings_xml(
+    strings: &BTreeMap<&'static str, &str>,
+    stamp: Option<&Stamp>,
+) -> std::io::Result<Vec<u8>> {
+    let mut w = XmlWriter::new(vec![]);
+    w.declaration()?;
+    if let Some(stamp) = stamp {
+        stamp.write_xml(&mut w)?;
+    }
+    w.start("resources", &[])?;
+    for (name, value) in strings {
+        w.text_element("string", &[("name", name)], &escape_android(value))?;
+    }
+    w.end()?;
+    w.finish()
+}
+
+/// Android resource strings treat quotes and a leading `@` or `?` specially
+fn escape_android(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('\'', "\\'")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    if escaped.starts_with('@') || escaped.starts_with('?') {
+        format!("\\{}", escaped)
+    } else {
+        escaped
+    }
+}
+
+fn ios_localizable_strings(
+    strings: &BTreeMap<&'static str, &str>,
+    stamp: Option<&Stamp>,
+) -> String {
+    let mut res = stamp.map(|stamp| stamp.comment("//")).unwrap_or_default();
+    for (name, value) in strings {
+        let value = value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n");
+        let _ = writeln!(res, "\"{}\" = \"{}\";", name, value);
+    }
+    res
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not load kbdgen bundle")]
+    CannotLoad { source: crate::LoadError },
+    #[error("Strings can only be generated for Android and iOS, not {target}")]
+    UnsupportedTarget { target: Target },
+    #[error("Could not write to file `{}`", path.display())]
+    CannotWriteFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generate(target: Target) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        kbdgen_to_mobile_strings(Path::new("examples/sme.kbdgen"), dir.path(), target).unwrap();
+        dir
+    }
+
+    #[test]
+    fn android_strings_are_laid_out_like_the_app() {
+        let dir = generate(Target::Android);
+        let res = dir.path().join("strings/app/src/main/res");
+        let en = std::fs::read_to_string(res.join("values/kbdgen_strings.xml")).unwrap();
+        assert!(en.contains(r#"<string name="settings_title">Settings</string>"#));
+        let nb = std::fs::read_to_string(res.join("values-b+nb/kbdgen_strings.xml")).unwrap();
+        assert!(nb.contains(r#"<string name="app_name">Nordsamisk tastatur</string>"#));
+        assert!(nb.contains(r#"<string name="open_settings">Open Settings</string>"#));
+    }
+
+    #[test]
+    fn ios_strings_are_laid_out_like_the_app() {
+        let dir = generate(Target::Ios);
+        let app = dir.path().join("strings/HostingApp");
+        let base = std::fs::read_to_string(app.join("Base.lproj/Localizable.strings")).unwrap();
+        assert!(base.contains("\"open_settings\" = \"Open Settings\";\n"));
+        assert!(app.join("nb.lproj/Localizable.strings").is_file());
+        assert!(!app.join("en.lproj").exists());
+    }
+}
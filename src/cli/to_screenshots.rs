@@ -0,0 +1,409 @@
+//! Store listing screenshots of the mobile layers
+//!
+//! Each mobile mode is drawn onto a device frame at the resolutions Google
+//! Play and the App Store ask for, once per locale the layout has a display
+//! name in. Each screenshot is drawn as SVG at the exact pixel size of the
+//! device and rasterised to PNG, which is what the stores accept, with
+//! `rsvg-convert` from librsvg. The PNG is checked to have the size of the
+//! device, as the stores reject screenshots of any other size.
+
+use crate::{
+    bundle::keys::KeyValue,
+    models::{Layout, MobileModes},
+    output_layout::{layout_path, LayoutFile},
+    MobileKeyMap, Target,
+};
+use bigdecimal::ToPrimitive;
+use std::{
+    fmt::Write as _,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use xml::escape::escape_str_pcdata as escape;
+
+/// A screen size a store requires screenshots in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Device {
+    pub name: &'static str,
+    pub target: Target,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub const DEVICES: &[Device] = &[
+    Device {
+        name: "play-phone",
+        target: Target::Android,
+        width: 1080,
+        height: 1920,
+    },
+    Device {
+        name: "play-tablet-10",
+        target: Target::Android,
+        width: 1600,
+        height: 2560,
+    },
+    Device {
+        name: "appstore-iphone-6.5",
+        target: Target::Ios,
+        width: 1242,
+        height: 2688,
+    },
+    Device {
+        name: "appstore-iphone-5.5",
+        target: Target::Ios,
+        width: 1242,
+        height: 2208,
+    },
+    Device {
+        name: "appstore-ipad-12.9",
+        target: Target::Ios,
+        width: 2048,
+        height: 2732,
+    },
+];
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Only render this layout, instead of all layouts with mobile modes
+    pub layout: Option<String>,
+    /// Only write the SVG, without rasterising it to PNG
+    pub svg_only: bool,
+}
+
+/// The program the SVG is rasterised with
+const RSVG_CONVERT: &str = "rsvg-convert";
+
+pub fn kbdgen_to_screenshots(input: &Path, output: &Path, options: &Options) -> Result<(), Error> {
+    let mut bundle =
+        crate::cli::load_bundle(input).map_err(|source| Error::CannotLoad { source })?;
+    crate::cli::macros::expand_layout_macros(&mut bundle)
+        .map_err(|source| Error::CannotExpandMacros { source })?;
+
+    let layouts = match options.layout.as_ref() {
+        Some(name) => {
+            let layout = bundle
+                .layouts
+                .get(name)
+                .ok_or_else(|| Error::CouldNotFindLayout {
+                    layout: name.to_owned(),
+                })?;
+            vec![(name, layout)]
+        }
+        None => bundle.layouts.iter().collect(),
+    };
+
+    for (name, layout) in layouts {
+        for device in DEVICES {
+            let modes = match mobile_modes(layout, device.target) {
+                Some(modes) => modes,
+                None => {
+                    log::debug!("Layout `{}` has no modes for {}", name, device.target);
+                    continue;
+                }
+            };
+
+            for (locale, display_name) in &layout.display_names {
+                for (mode, key_map) in modes.iter() {
+                    let svg = render(device, display_name, layout, key_map);
+                    let part = format!("{}-{}-{}", locale, device.name, mode);
+                    let path = |extension| {
+                        let file = LayoutFile {
+                            target: "screenshots",
+                            layout: name,
+                            part: Some(&part),
+                            extension,
+                        };
+                        output.join(layout_path(&file, || {
+                            Path::new(name)
+                                .join(locale)
+                                .join(format!("{}-{}.{}", device.name, mode, extension))
+                        }))
+                    };
+                    let svg_path = path("svg");
+                    if let Some(dir) = svg_path.parent() {
+                        std::fs::create_dir_all(dir).map_err(|source| Error::CannotWriteFile {
+                            path: dir.to_path_buf(),
+                            source,
+                        })?;
+                    }
+                    std::fs::write(&svg_path, svg).map_err(|source| Error::CannotWriteFile {
+                        path: svg_path.clone(),
+                        source,
+                    })?;
+                    log::info!("Wrote to file `{}`", svg_path.display());
+
+                    if !options.svg_only {
+                        let png_path = path("png");
+                        rasterise(device, &svg_path, &png_path)?;
+                        log::info!("Wrote to file `{}`", png_path.display());
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Rasterises the SVG to a PNG at the size of the device
+fn rasterise(device: &Device, svg_path: &Path, png_path: &Path) -> Result<(), Error> {
+    let output = Command::new(RSVG_CONVERT)
+        .arg("--format=png")
+        .arg(format!("--width={}", device.width))
+        .arg(format!("--height={}", device.height))
+        .arg("--output")
+        .arg(png_path)
+        .arg(svg_path)
+        .output()
+        .map_err(|source| Error::CannotRunRsvgConvert { source })?;
+    if !output.status.success() {
+        return Err(Error::CannotRasterise {
+            path: svg_path.to_path_buf(),
+            message: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+
+    let png = std::fs::read(png_path).map_err(|source| Error::CannotReadFile {
+        path: png_path.to_path_buf(),
+        source,
+    })?;
+    match crate::png::size(&png) {
+        Some(size) if size == (device.width, device.height) => Ok(()),
+        size => Err(Error::WrongSize {
+            path: png_path.to_path_buf(),
+            expected: (device.width, device.height),
+            found: size,
+        }),
+    }
+}
+
+fn mobile_modes(layout: &Layout, target: Target) -> Option<&MobileModes> {
+    Some(layout.modes.resolve_mobile(target)?.1)
+}
+
+const FRAME: f32 = 0.04;
+const STATUS_HEIGHT: f32 = 0.06;
+const KEYBOARD_HEIGHT: f32 = 0.38;
+const KEY_GAP: f32 = 0.12;
+
+fn render(device: &Device, display_name: &str, layout: &Layout, key_map: &MobileKeyMap) -> String {
+    let (w, h) = (device.width as f32, device.height as f32);
+    let frame = w * FRAME;
+    let (screen_w, screen_h) = (w - 2.0 * frame, h - 2.0 * frame);
+
+    let mut rows: Vec<Vec<(String, f32)>> = key_map
+        .0
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|key| (label(layout, key), key_width(key)))
+                .collect()
+        })
+        .collect();
+    if let Some(last) = rows.last_mut() {
+        last.insert(0, ("⇧".to_string(), 1.5));
+        last.push(("⌫".to_string(), 1.5));
+    }
+    let strings = layout.strings.as_ref();
+    rows.push(vec![
+        ("123".to_string(), 1.5),
+        (
+            strings
+                .map(|s| s.space.clone())
+                .unwrap_or_else(|| display_name.to_string()),
+            5.0,
+        ),
+        (
+            strings
+                .map(|s| s.return_.clone())
+                .unwrap_or_else(|| "⏎".to_string()),
+            2.0,
+        ),
+    ]);
+
+    if layout.is_rtl() {
+        for row in &mut rows[..] {
+            row.reverse();
+        }
+    }
+
+    let columns = rows
+        .iter()
+        .map(|row| row.iter().map(|(_, width)| width).sum::<f32>())
+        .fold(0.0, f32::max);
+    let unit = screen_w / columns;
+    let row_height = screen_h * KEYBOARD_HEIGHT / rows.len() as f32;
+    let keyboard_top = frame + screen_h * (1.0 - KEYBOARD_HEIGHT);
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+        w = device.width,
+        h = device.height
+    );
+    let _ = writeln!(
+        svg,
+        r##"  <rect width="{}" height="{}" rx="{}" fill="#111"/>"##,
+        w,
+        h,
+        frame * 2.0
+    );
+    let _ = writeln!(
+        svg,
+        r##"  <rect x="{f}" y="{f}" width="{}" height="{}" rx="{}" fill="#f2f2f7"/>"##,
+        screen_w,
+        screen_h,
+        frame,
+        f = frame
+    );
+    let _ = writeln!(
+        svg,
+        r#"  <text x="{}" y="{}" font-family="sans-serif" font-size="{}" text-anchor="middle">{}</text>"#,
+        w / 2.0,
+        frame + screen_h * STATUS_HEIGHT * 0.7,
+        screen_h * STATUS_HEIGHT * 0.5,
+        escape(display_name)
+    );
+    let _ = writeln!(
+        svg,
+        r##"  <rect x="{}" y="{}" width="{}" height="{}" fill="#d1d3d9"/>"##,
+        frame,
+        keyboard_top,
+        screen_w,
+        screen_h * KEYBOARD_HEIGHT
+    );
+
+    for (y, row) in rows.iter().enumerate() {
+        let row_width = row.iter().map(|(_, width)| width).sum::<f32>();
+        let mut x = frame + (columns - row_width) * unit / 2.0;
+        let top = keyboard_top + y as f32 * row_height;
+        for (label, width) in row {
+            let gap = unit * KEY_GAP;
+            let key_w = width * unit - gap;
+            let key_h = row_height - gap;
+            let _ = writeln!(
+                svg,
+                r##"  <rect x="{}" y="{}" width="{}" height="{}" rx="{}" fill="#fff"/>"##,
+                x + gap / 2.0,
+                top + gap / 2.0,
+                key_w,
+                key_h,
+                gap
+            );
+            if !label.is_empty() {
+                let _ = writeln!(
+                    svg,
+                    r#"  <text x="{}" y="{}" font-family="sans-serif" font-size="{}" text-anchor="middle" dominant-baseline="central">{}</text>"#,
+                    x + width * unit / 2.0,
+                    top + row_height / 2.0,
+                    key_h.min(unit) * 0.45,
+                    escape(label)
+                );
+            }
+            x += width * unit;
+        }
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn label(layout: &Layout, key: &KeyValue) -> String {
+    match key {
+        KeyValue::Symbol(value) => layout.display_label(value).unwrap_or_else(|| value.clone()),
+        KeyValue::Special { id, .. } => match id.as_str() {
+            "shift" => "⇧".to_string(),
+            "backspace" => "⌫".to_string(),
+            "return" => "⏎".to_string(),
+            _ => String::new(),
+        },
+        KeyValue::None => String::new(),
+    }
+}
+
+fn key_width(key: &KeyValue) -> f32 {
+    match key {
+        KeyValue::Special { width, .. } => width.to_f32().unwrap_or(1.0),
+        _ => 1.0,
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not load kbdgen bundle")]
+    CannotLoad { source: crate::LoadError },
+    #[error("Could not expand layout macros")]
+    CannotExpandMacros { source: crate::cli::macros::Error },
+    #[error("Could not find layout {layout:?}")]
+    CouldNotFindLayout { layout: String },
+    #[error("Could not write to file `{}`", path.display())]
+    CannotWriteFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Could not read file `{}`", path.display())]
+    CannotReadFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error(
+        "Could not run `rsvg-convert`, is librsvg installed? Use `--svg-only` to skip the PNG"
+    )]
+    CannotRunRsvgConvert { source: std::io::Error },
+    #[error("Could not rasterise `{}`: {}", path.display(), message)]
+    CannotRasterise { path: PathBuf, message: String },
+    #[error(
+        "`{}` is {}, but the store requires {}x{}",
+        path.display(),
+        found.map(|(w, h)| format!("{}x{}", w, h)).unwrap_or_else(|| "not a PNG".into()),
+        expected.0,
+        expected.1
+    )]
+    WrongSize {
+        path: PathBuf,
+        expected: (u32, u32),
+        found: Option<(u32, u32)>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn screenshots_are_drawn_at_the_store_sizes() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = Options {
+            layout: Some("se".into()),
+            svg_only: true,
+        };
+        kbdgen_to_screenshots(Path::new("examples/sme.kbdgen"), dir.path(), &options).unwrap();
+
+        let rsvg_convert = Command::new(RSVG_CONVERT).arg("--version").output().is_ok();
+        for device in DEVICES {
+            let svg_path = dir
+                .path()
+                .join("se/en")
+                .join(format!("{}-default.svg", device.name));
+            let svg = std::fs::read_to_string(&svg_path).unwrap();
+            let size = format!(r#"width="{}" height="{}""#, device.width, device.height);
+            assert!(
+                svg.contains(&size),
+                "{} is not {}",
+                svg_path.display(),
+                size
+            );
+
+            // The rasterising itself needs librsvg, which is not installed
+            // everywhere the tests run
+            if rsvg_convert {
+                let png_path = svg_path.with_extension("png");
+                rasterise(device, &svg_path, &png_path).unwrap();
+                let png = std::fs::read(&png_path).unwrap();
+                assert_eq!(crate::png::size(&png), Some((device.width, device.height)));
+            }
+        }
+    }
+}
@@ -0,0 +1,415 @@
+//! A web app to try the layouts of a bundle in a browser
+//!
+//! Writes `web/`, a small installable web app (PWA) with an on-screen
+//! keyboard for each layout and a text area to type into, for user testing
+//! and demos. It works offline once loaded, and can be served from any
+//! static file host.
+//!
+//! The page does not interpret layouts itself. What each key types, which
+//! keys are dead keys, and what every dead key and the key after it produce
+//! are worked out here with `Layout::key`, `Layout::is_dead_key` and
+//! `Layout::transform`, the same as the generators and `kbdgen test` use,
+//! and written to `layouts.json` as plain tables for the page to look up.
+
+use crate::{
+    bundle::keys::KeyValue,
+    models::{IsoKey, Layout},
+    LocaleFallbacks, ModifierSet, Target, DEFAULT_LOCALE,
+};
+use bigdecimal::ToPrimitive;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+use strum::IntoEnumIterator;
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Only this layout, instead of every layout of the bundle
+    pub layout: Option<String>,
+}
+
+/// The static files of the app, by path in `web/`
+const FILES: &[(&str, &str)] = &[
+    ("index.html", include_str!("web/index.html")),
+    ("app.js", include_str!("web/app.js")),
+    ("style.css", include_str!("web/style.css")),
+    ("sw.js", include_str!("web/sw.js")),
+    ("icon.svg", include_str!("web/icon.svg")),
+];
+
+/// The targets whose modes the page shows, the first a layout has modes for.
+/// Desktop modes are shown if there are any, as they can also be typed with
+/// a physical keyboard.
+const TARGETS: &[Target] = &[Target::Chrome, Target::Android, Target::Ios];
+
+/// The modifiers the page has toggles for, in the order they are joined in
+/// layer names
+const MODIFIERS: &[&str] = &["caps", "alt", "shift"];
+
+/// Whether a layout is shown as a desktop or a mobile keyboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Desktop,
+    Mobile,
+}
+
+/// A key of the on-screen keyboard
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WebKey {
+    /// The ISO key, for desktop layouts, to match presses of a physical
+    /// keyboard
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<IsoKey>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    /// What to draw on the key instead of its value, see
+    /// `Layout::display_label`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// The id of a special key, like `shift` or `backspace`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub special: Option<String>,
+    /// In key widths
+    pub width: f32,
+}
+
+/// A layout as the page uses it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WebLayout {
+    pub name: String,
+    #[serde(rename = "displayName")]
+    pub display_name: String,
+    pub kind: Kind,
+    pub rtl: bool,
+    /// Rows of keys, by the modifiers held, like `default` or `alt+shift`
+    pub layers: BTreeMap<String, Vec<Vec<WebKey>>>,
+    /// The dead keys of each layer
+    #[serde(rename = "deadKeys")]
+    pub dead_keys: BTreeMap<String, BTreeSet<String>>,
+    /// What a pending dead key, or chain of dead keys, and the key typed
+    /// after it produce. Keys missing here produce the dead key's
+    /// terminator followed by what they type.
+    pub transforms: BTreeMap<String, BTreeMap<String, String>>,
+    /// Chains of dead keys that wait for another key, like `^´`
+    pub chains: BTreeSet<String>,
+    /// What each pending dead key types on its own
+    pub terminators: BTreeMap<String, String>,
+}
+
+impl WebLayout {
+    /// The layout as shown on the page, if it has modes for any of the
+    /// targets the page can show
+    pub fn of(name: &str, layout: &Layout, fallbacks: &LocaleFallbacks<'_>) -> Option<WebLayout> {
+        let target = TARGETS
+            .iter()
+            .copied()
+            .find(|&target| layout.modes.resolve(target).is_some())?;
+        let kind = if target.is_mobile() {
+            Kind::Mobile
+        } else {
+            Kind::Desktop
+        };
+
+        let mut layers = BTreeMap::new();
+        let mut dead_keys = BTreeMap::new();
+        for layer in layer_names() {
+            let mode = layer.parse::<ModifierSet>().expect("valid modifiers");
+            let rows = match kind {
+                Kind::Desktop => desktop_rows(layout, target, &mode),
+                Kind::Mobile => mobile_rows(layout, target, &mode),
+            };
+            let rows = match rows {
+                Some(rows) => rows,
+                None => continue,
+            };
+            let dead = rows
+                .iter()
+                .flatten()
+                .filter_map(|key| key.value.as_deref())
+                .filter(|value| layout.is_dead_key(target, &mode, value))
+                .map(str::to_string)
+                .collect::<BTreeSet<_>>();
+            if !dead.is_empty() {
+                dead_keys.insert(layer.clone(), dead);
+            }
+            layers.insert(layer, rows);
+        }
+
+        let values = layers
+            .values()
+            .flatten()
+            .flatten()
+            .filter_map(|key| key.value.clone())
+            .chain(std::iter::once(" ".to_string()))
+            .collect::<BTreeSet<_>>();
+        let all_dead = dead_keys
+            .values()
+            .flatten()
+            .cloned()
+            .collect::<BTreeSet<_>>();
+
+        let mut transforms = BTreeMap::new();
+        let mut chains = BTreeSet::new();
+        let mut terminators = BTreeMap::new();
+        let mut pending = all_dead.iter().cloned().collect::<Vec<_>>();
+        while let Some(dead_key) = pending.pop() {
+            if terminators.contains_key(&dead_key) {
+                continue;
+            }
+            terminators.insert(dead_key.clone(), layout.dead_key_terminator(&dead_key));
+            let mut table = BTreeMap::new();
+            for value in &values {
+                let chain = format!("{}{}", dead_key, value);
+                let is_chain = all_dead.contains(value)
+                    && matches!(&layout.transforms, Some(t) if t.contains_key(&chain));
+                if is_chain {
+                    chains.insert(chain.clone());
+                    pending.push(chain);
+                    continue;
+                }
+                match layout.transform(&dead_key, value) {
+                    Some(_) if value == " " => {
+                        table.insert(value.clone(), layout.dead_key_terminator(&dead_key));
+                    }
+                    Some(output) => {
+                        table.insert(value.clone(), output);
+                    }
+                    None => {}
+                }
+            }
+            transforms.insert(dead_key, table);
+        }
+
+        Some(WebLayout {
+            name: name.to_string(),
+            display_name: layout
+                .display_name(DEFAULT_LOCALE, fallbacks)
+                .unwrap_or_else(|| name.to_string()),
+            kind,
+            rtl: layout.is_rtl(),
+            layers,
+            dead_keys,
+            transforms,
+            chains,
+            terminators,
+        })
+    }
+}
+
+/// Every combination of `MODIFIERS`, named like the page names them
+fn layer_names() -> Vec<String> {
+    (0..1 << MODIFIERS.len())
+        .map(|bits: usize| {
+            let held = MODIFIERS
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| bits & (1 << i) != 0)
+                .map(|(_, name)| *name)
+                .collect::<Vec<_>>();
+            if held.is_empty() {
+                "default".to_string()
+            } else {
+                held.join("+")
+            }
+        })
+        .collect()
+}
+
+fn symbol(value: Option<&KeyValue>) -> Option<String> {
+    match value {
+        Some(KeyValue::Symbol(value)) => Some(value.clone()),
+        _ => None,
+    }
+}
+
+fn desktop_rows(layout: &Layout, target: Target, mode: &ModifierSet) -> Option<Vec<Vec<WebKey>>> {
+    let (_, modes) = layout.modes.resolve_desktop(target)?;
+    modes.get_mode(mode)?;
+
+    let mut rows: Vec<Vec<WebKey>> = vec![];
+    for key in IsoKey::iter() {
+        let (row, _) = key.grid_position();
+        if rows.len() <= row {
+            rows.resize_with(row + 1, Vec::new);
+        }
+        let value = symbol(layout.key(target, mode, key));
+        rows[row].push(WebKey {
+            key: Some(key),
+            label: value
+                .as_deref()
+                .and_then(|value| layout.display_label(value)),
+            value,
+            special: None,
+            width: 1.0,
+        });
+    }
+    Some(rows)
+}
+
+fn mobile_rows(layout: &Layout, target: Target, mode: &ModifierSet) -> Option<Vec<Vec<WebKey>>> {
+    let (_, modes) = layout.modes.resolve_mobile(target)?;
+    let key_map = modes.get_mode(mode)?;
+    let rows = key_map
+        .0
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|value| match value {
+                    KeyValue::Symbol(value) => WebKey {
+                        key: None,
+                        value: Some(value.clone()),
+                        label: layout.display_label(value),
+                        special: None,
+                        width: 1.0,
+                    },
+                    KeyValue::Special { id, width } => WebKey {
+                        key: None,
+                        value: None,
+                        label: None,
+                        special: Some(id.clone()),
+                        width: width.to_f32().unwrap_or(1.0),
+                    },
+                    KeyValue::None => WebKey {
+                        key: None,
+                        value: None,
+                        label: None,
+                        special: None,
+                        width: 1.0,
+                    },
+                })
+                .collect()
+        })
+        .collect();
+    Some(rows)
+}
+
+/// The web app manifest, named after the project
+fn manifest(name: &str) -> serde_json::Value {
+    serde_json::json!({
+        "name": name,
+        "short_name": name,
+        "start_url": ".",
+        "display": "standalone",
+        "background_color": "#ffffff",
+        "theme_color": "#2b5797",
+        "icons": [{ "src": "icon.svg", "sizes": "any", "type": "image/svg+xml" }],
+    })
+}
+
+pub fn kbdgen_to_web(input: &Path, output: &Path, options: &Options) -> Result<(), Error> {
+    let mut bundle =
+        crate::cli::load_bundle(input).map_err(|source| Error::CannotLoad { source })?;
+    crate::cli::macros::expand_layout_macros(&mut bundle)
+        .map_err(|source| Error::CannotExpandMacros { source })?;
+    let fallbacks = LocaleFallbacks::of(&bundle.project);
+
+    let mut names = match options.layout.as_ref() {
+        Some(name) if !bundle.layouts.contains_key(name) => {
+            return Err(Error::CouldNotFindLayout {
+                layout: name.to_owned(),
+            })
+        }
+        Some(name) => vec![name],
+        None => bundle.layouts.keys().collect(),
+    };
+    names.sort();
+
+    let mut layouts = vec![];
+    for name in names {
+        match WebLayout::of(name, &bundle.layouts[name], &fallbacks) {
+            Some(layout) => layouts.push(layout),
+            None => log::debug!("Layout `{}` has no modes the web app can show", name),
+        }
+    }
+    if layouts.is_empty() {
+        return Err(Error::NoLayouts);
+    }
+
+    let dir = output.join("web");
+    let write = |file: &str, contents: &[u8]| {
+        let path = dir.join(file);
+        std::fs::write(&path, contents).map_err(|source| Error::CannotWriteFile { path, source })
+    };
+    std::fs::create_dir_all(&dir).map_err(|source| Error::CannotWriteFile {
+        path: dir.clone(),
+        source,
+    })?;
+    for (file, contents) in FILES {
+        write(file, contents.as_bytes())?;
+    }
+
+    let name = bundle
+        .project
+        .locales
+        .get(DEFAULT_LOCALE)
+        .or_else(|| bundle.project.locales.values().next())
+        .map(|desc| desc.name.clone())
+        .unwrap_or_else(|| "kbdgen".to_string());
+    write("manifest.webmanifest", to_json(&manifest(&name)).as_bytes())?;
+    write("layouts.json", to_json(&layouts).as_bytes())?;
+    log::info!("Wrote the web app to `{}`", dir.display());
+    Ok(())
+}
+
+fn to_json<T: Serialize>(value: &T) -> String {
+    serde_json::to_string_pretty(value).expect("web layouts serialize")
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not load kbdgen bundle")]
+    CannotLoad { source: crate::LoadError },
+    #[error("Could not expand layout macros")]
+    CannotExpandMacros { source: crate::cli::macros::Error },
+    #[error("Could not find layout {layout:?}")]
+    CouldNotFindLayout { layout: String },
+    #[error("No layout has desktop or mobile modes to show")]
+    NoLayouts,
+    #[error("Could not write to file `{}`", path.display())]
+    CannotWriteFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dead_keys_are_resolved_into_tables() {
+        let layout: Layout = serde_yaml::from_str(
+            "displayNames:\n  en: Test\nmodes:\n  desktop:\n    default: |\n      ´ a e\n    shift: |\n      ` A E\ndeadKeys:\n  desktop:\n    default: [´]\ntransforms:\n  ´:\n    a: á\n    ' ': ´\n",
+        )
+        .unwrap();
+        let web = WebLayout::of("test", &layout, &LocaleFallbacks::default()).unwrap();
+        assert_eq!(web.kind, Kind::Desktop);
+        assert_eq!(web.display_name, "Test");
+
+        let first = |layer: &str| web.layers[layer][0][..3].to_vec();
+        let values = first("default")
+            .into_iter()
+            .map(|key| key.value)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            vec![Some("´".into()), Some("a".into()), Some("e".into())]
+        );
+        assert_eq!(first("shift")[1].value.as_deref(), Some("A"));
+        assert!(!web.layers.contains_key("alt"));
+
+        assert_eq!(
+            web.dead_keys["default"].iter().collect::<Vec<_>>(),
+            vec!["´"]
+        );
+        let transforms = &web.transforms["´"];
+        assert_eq!(transforms["a"], "á");
+        assert_eq!(transforms[" "], "´");
+        assert!(!transforms.contains_key("e"));
+        assert_eq!(web.terminators["´"], "´");
+        assert!(web.chains.is_empty());
+    }
+}
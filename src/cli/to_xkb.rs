@@ -1,13 +1,23 @@
-use crate::{utils::UnwrapOrUnknownExt, xkb::*, Load, ProjectBundle};
+use crate::{
+    loss::{self, Loss},
+    output_layout::{layout_path, LayoutFile},
+    stamp::{self, Stamp},
+    utils::UnwrapOrUnknownExt,
+    xkb::*,
+    LocaleFallbacks, ProjectBundle, Target, DEFAULT_LOCALE,
+};
 use log::{debug, log_enabled};
 use std::{
     fs::File,
-    io::BufWriter,
+    io::{BufWriter, Write},
     path::{Path, PathBuf},
 };
 
-pub fn kbdgen_to_xkb(input: &Path, output: &Path, _options: &Options) -> Result<(), Error> {
-    let bundle = ProjectBundle::load(input).map_err(|source| Error::CannotLoad { source })?;
+pub fn kbdgen_to_xkb(input: &Path, output: &Path, options: &Options) -> Result<(), Error> {
+    let mut bundle =
+        crate::cli::load_bundle(input).map_err(|source| Error::CannotLoad { source })?;
+    crate::cli::macros::expand_layout_macros(&mut bundle)
+        .map_err(|source| Error::CannotExpandMacros { source })?;
     if log_enabled!(log::Level::Debug) {
         debug!("Bundle `{}` loaded", input.display());
         let locales = bundle
@@ -19,29 +29,70 @@ pub fn kbdgen_to_xkb(input: &Path, output: &Path, _options: &Options) -> Result<
         debug!("Bundle contains these locales: {:?}", locales);
     }
 
+    bundle_to_xkb(&bundle, output, options)
+}
+
+/// Writes the XKB symbols for the layouts of an already loaded bundle
+pub fn bundle_to_xkb(
+    bundle: &ProjectBundle,
+    output: &Path,
+    options: &Options,
+) -> Result<(), Error> {
+    let stamp = Stamp::for_bundle(bundle);
+    let fallbacks = LocaleFallbacks::of(&bundle.project);
     bundle
         .layouts
         .iter()
         .filter(|(_, layout)| {
-            let can_be_converted = layout.modes.win.is_some() || layout.modes.x11.is_some();
+            let can_be_converted = layout.modes.resolve(Target::X11).is_some()
+                || XKB_SOURCE_TARGETS
+                    .iter()
+                    .any(|&target| layout.modes.has(target));
             if !can_be_converted {
                 log::info!(
                     "skipping {}, no modes that can be converted to xkb",
                     layout.name().unwrap_or_unknown()
                 );
-                log::trace!("modes found: {}", layout.modes.available_modes().join(", "));
+                log::trace!("modes found: {:?}", layout.modes.available_modes());
             }
             can_be_converted
         })
-        .map(|(name, layout)| (name, XkbFile::from_layout(name, layout.clone())))
-        .try_for_each(|(name, symbols)| {
-            let symbols = match symbols {
+        .inspect(|(name, layout)| {
+            if layout.targets_without_reordering().contains(&Target::X11) {
+                loss::report(
+                    Loss::dropped("x11", name, "reorders and backspaces")
+                        .because("XKB cannot express them"),
+                );
+            }
+        })
+        .inspect(|(name, layout)| {
+            if !layout.is_rtl() {
+                return;
+            }
+            for pair in layout.unmirrored_pairs() {
+                log::warn!(
+                    "{}: {} mode `{}` has brackets on {:?} and {:?} in left-to-right order, \
+                     but the layout is right-to-left",
+                    name,
+                    pair.target,
+                    pair.mode,
+                    pair.open,
+                    pair.close
+                );
+            }
+        })
+        .map(|(name, layout)| {
+            let symbols = XkbFile::from_layout(name, layout.clone(), &fallbacks);
+            (name, layout, symbols)
+        })
+        .try_for_each(|(name, layout, symbols)| {
+            let mut symbols = match symbols {
                 Ok(symbols) => symbols,
                 Err(ConversionError::NoXkbCompatibleModes {
                     available_modes, ..
                 }) => {
                     log::info!("skipping {}, no modes that can be converted to xkb", name);
-                    log::debug!("modes found: {}", available_modes.join(", "));
+                    log::debug!("modes found: {:?}", available_modes);
                     return Ok(());
                 }
                 Err(e) => Err(e).map_err(|source| SavingError::CannotConvertToXkb {
@@ -55,7 +106,12 @@ pub fn kbdgen_to_xkb(input: &Path, output: &Path, _options: &Options) -> Result<
                 })?,
             };
 
-            let path = output.join("linux").join(name).with_extension("xkb");
+            let second_group = second_group(bundle, name);
+            if let Some(group) = &second_group {
+                symbols.add_second_group(name, &group.include, &group.name, &group.toggle);
+            }
+
+            let path = output.join(xkb_path(name));
             std::fs::create_dir_all(path.parent().unwrap()).map_err(|source| {
                 SavingError::CannotCreateFile {
                     path: path.clone(),
@@ -68,10 +124,29 @@ pub fn kbdgen_to_xkb(input: &Path, output: &Path, _options: &Options) -> Result<
             })?;
             debug!("Created file `{}`", path.display());
             let mut writer = BufWriter::new(file);
+            let mut header = stamp
+                .as_ref()
+                .map(|stamp| stamp.with_layout(name).comment("//"))
+                .unwrap_or_default();
+            header += &stamp::comment(&layout.license_notice(&bundle.project), "//");
+            writer
+                .write_all(header.as_bytes())
+                .map_err(|source| SavingError::CannotSerializeXkb { source })?;
             symbols
                 .write_xkb(&mut writer)
                 .map_err(|source| SavingError::CannotSerializeXkb { source })?;
             log::info!("Wrote to file `{}`", path.display());
+
+            if options.verify {
+                let included = second_group
+                    .as_ref()
+                    .and_then(|group| group.layout)
+                    .and_then(|other| {
+                        let layout = bundle.layouts.get(other)?.clone();
+                        Some((other, XkbFile::from_layout(other, layout, &fallbacks).ok()?))
+                    });
+                verify_symbols(name, &symbols, included.as_ref())?;
+            }
             Ok(())
         })
         .map_err(|source| Error::CannotBeSaved { source })?;
@@ -79,15 +154,108 @@ pub fn kbdgen_to_xkb(input: &Path, output: &Path, _options: &Options) -> Result<
     Ok(())
 }
 
+/// The `secondGroup` of the x11 target, resolved for the layout `name`
+struct SecondGroup<'a> {
+    /// The symbols to include in the second group
+    include: String,
+    name: String,
+    toggle: String,
+    /// The layout of the bundle that the symbols are from, if any
+    layout: Option<&'a str>,
+}
+
+/// Where the symbols of a layout go, relative to the output directory:
+/// `linux/<layout>.xkb`, unless an output layout is set
+pub fn xkb_path(layout: &str) -> PathBuf {
+    let file = LayoutFile {
+        target: "x11",
+        layout,
+        part: None,
+        extension: "xkb",
+    };
+    layout_path(&file, || Path::new("linux").join(format!("{}.xkb", layout)))
+}
+
+fn second_group<'a>(bundle: &'a ProjectBundle, name: &str) -> Option<SecondGroup<'a>> {
+    let group = bundle.targets.x11.as_ref()?.second_group.as_ref()?;
+    if group.layout == name {
+        return None;
+    }
+    let toggle = group
+        .toggle
+        .clone()
+        .unwrap_or_else(|| "alt_shift_toggle".to_string());
+
+    match bundle.layouts.get_key_value(&group.layout) {
+        Some((other, layout)) => Some(SecondGroup {
+            include: format!("{}(basic)", other),
+            name: group
+                .name
+                .clone()
+                .or_else(|| {
+                    layout.display_name(DEFAULT_LOCALE, &LocaleFallbacks::of(&bundle.project))
+                })
+                .unwrap_or_unknown(),
+            toggle,
+            layout: Some(other.as_str()),
+        }),
+        None => Some(SecondGroup {
+            include: group.layout.clone(),
+            name: group.name.clone().unwrap_or_else(|| group.layout.clone()),
+            toggle,
+            layout: None,
+        }),
+    }
+}
+
+/// Compiles the symbols, logging every problem found. The symbols of another
+/// layout they include are compiled alongside.
+fn verify_symbols(
+    name: &str,
+    symbols: &XkbFile,
+    included: Option<&(&str, XkbFile)>,
+) -> Result<(), SavingError> {
+    let included = included
+        .map(|(other, file)| vec![(*other, file)])
+        .unwrap_or_default();
+    let problems = verify::verify_with(name, symbols, &included).map_err(|source| {
+        SavingError::CannotVerify {
+            layout: name.to_string(),
+            source,
+        }
+    })?;
+    for problem in &problems {
+        if problem.is_error() {
+            log::error!("{}: {}", name, problem);
+        } else {
+            log::warn!("{}: {}", name, problem);
+        }
+    }
+
+    let errors = problems.iter().filter(|p| p.is_error()).count();
+    if errors > 0 {
+        return Err(SavingError::FailedVerification {
+            layout: name.to_string(),
+            errors,
+        });
+    }
+    log::info!("Verified `{}` with xkbcomp", name);
+    Ok(())
+}
+
 #[derive(Debug, Clone)]
 pub struct Options {
     pub standalone: bool,
+    /// Whether to compile the written symbols with `xkbcomp`
+    pub verify: bool,
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("Could not load kbdgen bundle")]
     CannotLoad { source: crate::LoadError },
+    #[error("Could not expand layout macros")]
+    CannotExpandMacros { source: crate::cli::macros::Error },
     #[error("Could not write XKB file")]
     CannotBeSaved { source: SavingError },
 }
@@ -107,4 +275,11 @@ pub enum SavingError {
     },
     #[error("Could not transform to XKB")]
     CannotSerializeXkb { source: std::io::Error },
+    #[error("Could not verify XKB symbols of `{}`", layout)]
+    CannotVerify {
+        layout: String,
+        source: verify::Error,
+    },
+    #[error("xkbcomp found {} error(s) in the symbols of `{}`", errors, layout)]
+    FailedVerification { layout: String, errors: usize },
 }
@@ -0,0 +1,511 @@
+//! Checking that the layouts of a bundle are installed as they were built
+//!
+//! Compares the files a build of a desktop target wrote with the ones
+//! installed on this system by their SHA-256, and the version the installer
+//! recorded with the target's, to find layouts that were never installed,
+//! were only installed in part, or are left at an older build.
+//!
+//! The installed files are looked for where the installers put them:
+//! `Library/Keyboard Layouts` for macOS, `System32` and `SysWOW64` for
+//! Windows, and `/usr/share/X11/xkb/symbols` for X11.
+
+use crate::{cli::to_xkb::xkb_path, ProjectBundle, Target};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    ffi::OsStr,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+/// The targets whose installations can be verified
+pub const VERIFY_TARGETS: &[Target] = &[Target::Mac, Target::Win, Target::X11];
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub target: Target,
+    /// The `.kbdgen` bundle
+    pub project_path: PathBuf,
+    /// Where the target was built to
+    pub output_path: PathBuf,
+    /// Look for the installed files under this directory instead of the
+    /// system's install locations, like a mounted disk of another machine.
+    /// For Windows, it is the Windows directory.
+    pub root: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileState {
+    /// Installed, and the same as the build
+    Current,
+    /// Installed, but different from the build
+    Stale,
+    Missing,
+}
+
+/// A file of the build, and where it is installed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InstalledFile {
+    pub built: PathBuf,
+    /// Where the file is installed, or would be
+    pub installed: PathBuf,
+    pub state: FileState,
+}
+
+/// How the build of a target is installed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Installation {
+    pub target: Target,
+    pub files: Vec<InstalledFile>,
+    /// The version of the target in the bundle
+    pub expected_version: Option<String>,
+    /// The version the installer recorded, if there is one to check
+    pub installed_version: Option<String>,
+    /// X11 layouts whose symbols are installed but that are not listed in
+    /// the XKB rules, so that settings do not offer them
+    pub unlisted: Vec<String>,
+}
+
+/// Something wrong with an installation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// None of the files are installed
+    NotInstalled,
+    /// Some of the files are installed, but not this one
+    Missing {
+        path: PathBuf,
+    },
+    Stale {
+        path: PathBuf,
+    },
+    Version {
+        expected: String,
+        installed: String,
+    },
+    Unlisted {
+        layout: String,
+    },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::NotInstalled => write!(f, "the layouts are not installed"),
+            Problem::Missing { path } => {
+                write!(f, "partially installed, `{}` is missing", path.display())
+            }
+            Problem::Stale { path } => {
+                write!(f, "`{}` differs from the build", path.display())
+            }
+            Problem::Version {
+                expected,
+                installed,
+            } => write!(
+                f,
+                "version {} is installed, but the bundle is at {}",
+                installed, expected
+            ),
+            Problem::Unlisted { layout } => write!(
+                f,
+                "`{}` is installed but not listed in the XKB rules",
+                layout
+            ),
+        }
+    }
+}
+
+impl Installation {
+    pub fn problems(&self) -> Vec<Problem> {
+        if self.files.iter().all(|f| f.state == FileState::Missing) {
+            return vec![Problem::NotInstalled];
+        }
+        let mut problems = self
+            .files
+            .iter()
+            .filter_map(|file| {
+                let path = file.installed.clone();
+                match file.state {
+                    FileState::Current => None,
+                    FileState::Stale => Some(Problem::Stale { path }),
+                    FileState::Missing => Some(Problem::Missing { path }),
+                }
+            })
+            .collect::<Vec<_>>();
+        if let (Some(expected), Some(installed)) = (&self.expected_version, &self.installed_version)
+        {
+            if expected != installed {
+                problems.push(Problem::Version {
+                    expected: expected.clone(),
+                    installed: installed.clone(),
+                });
+            }
+        }
+        problems.extend(self.unlisted.iter().map(|layout| Problem::Unlisted {
+            layout: layout.clone(),
+        }));
+        problems
+    }
+}
+
+pub fn verify_install(options: &Options) -> Result<Installation, Error> {
+    if !VERIFY_TARGETS.contains(&options.target) {
+        return Err(Error::UnsupportedTarget {
+            target: options.target,
+        });
+    }
+    let bundle = crate::cli::load_bundle(&options.project_path)
+        .map_err(|source| Error::CannotLoad { source })?;
+    let root = options.root.as_deref();
+    let output = &options.output_path;
+
+    let mut installation = Installation {
+        target: options.target,
+        files: vec![],
+        expected_version: None,
+        installed_version: None,
+        unlisted: vec![],
+    };
+    let pairs = match options.target {
+        Target::Mac => {
+            let (pairs, bundle_dir) = mac_files(&bundle, output, root)?;
+            installation.expected_version =
+                bundle.targets.mac_os.as_ref().map(|t| t.version.clone());
+            installation.installed_version =
+                fs::read_to_string(bundle_dir.join("Contents/Info.plist"))
+                    .ok()
+                    .and_then(|plist| plist_string(&plist, "CFBundleShortVersionString"));
+            pairs
+        }
+        Target::Win => {
+            installation.expected_version =
+                bundle.targets.windows.as_ref().map(|t| t.version.clone());
+            if root.is_none() {
+                installation.installed_version = bundle
+                    .targets
+                    .windows
+                    .as_ref()
+                    .and_then(|t| win_installed_version(&t.uuid));
+            }
+            win_files(output, root)
+        }
+        _ => {
+            let pairs = x11_files(&bundle, output, root);
+            installation.unlisted = x11_unlisted(&pairs, root);
+            pairs
+        }
+    };
+    if pairs.is_empty() {
+        return Err(Error::NotBuilt {
+            target: options.target,
+            path: output.clone(),
+        });
+    }
+
+    for (built, installed) in pairs {
+        let state = file_state(&built, &installed)?;
+        installation.files.push(InstalledFile {
+            built,
+            installed,
+            state,
+        });
+    }
+    Ok(installation)
+}
+
+fn file_state(built: &Path, installed: &Path) -> Result<FileState, Error> {
+    let read = |path: &Path| {
+        fs::read(path).map_err(|source| Error::CannotRead {
+            path: path.to_path_buf(),
+            source,
+        })
+    };
+    if !installed.is_file() {
+        return Ok(FileState::Missing);
+    }
+    let (built, installed) = (
+        Sha256::digest(&read(built)?),
+        Sha256::digest(&read(installed)?),
+    );
+    Ok(if built == installed {
+        FileState::Current
+    } else {
+        FileState::Stale
+    })
+}
+
+/// The files in `dir` and its subdirectories, relative to it
+fn list_files(dir: &Path, relative: &Path, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+    let read_error = |source| Error::CannotRead {
+        path: dir.to_path_buf(),
+        source,
+    };
+    let mut entries = fs::read_dir(dir)
+        .map_err(read_error)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(read_error)?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = relative.join(entry.file_name());
+        if entry.path().is_dir() {
+            list_files(&entry.path(), &path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// The files of `dir` with the extension `extension`
+fn files_with_extension(dir: &Path, extension: &str) -> Vec<PathBuf> {
+    let mut files = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && path.extension() == Some(OsStr::new(extension)))
+        .collect::<Vec<_>>();
+    files.sort();
+    files
+}
+
+fn under(root: Option<&Path>, path: &str) -> PathBuf {
+    match root {
+        Some(root) => root.join(path.trim_start_matches('/')),
+        None => PathBuf::from(path),
+    }
+}
+
+/// The files of the built keyboard layout bundle and where they are
+/// installed, with the installed bundle. A bundle for this user is preferred
+/// to one for all users, as it is the one macOS uses.
+fn mac_files(
+    bundle: &ProjectBundle,
+    output: &Path,
+    root: Option<&Path>,
+) -> Result<(Vec<(PathBuf, PathBuf)>, PathBuf), Error> {
+    let target = bundle.targets.mac_os.as_ref().ok_or(Error::NoTarget {
+        target: Target::Mac,
+    })?;
+    // As the generator names it, for the Finder
+    let name = format!(
+        "{}.bundle",
+        target
+            .bundle_name
+            .replace(&['/', '\\', ':', '*', '?', '"', '<', '>', '|'][..], "")
+            .trim()
+    );
+    let built = output.join(&name);
+
+    let mut dirs = vec![under(root, "/Library/Keyboard Layouts")];
+    if root.is_none() {
+        if let Some(home) = std::env::var_os("HOME") {
+            dirs.insert(0, Path::new(&home).join("Library/Keyboard Layouts"));
+        }
+    }
+    let installed = dirs
+        .iter()
+        .map(|dir| dir.join(&name))
+        .find(|path| path.is_dir())
+        .unwrap_or_else(|| dirs[dirs.len() - 1].join(&name));
+
+    let mut files = vec![];
+    if built.is_dir() {
+        list_files(&built, Path::new(""), &mut files)?;
+    }
+    let pairs = files
+        .into_iter()
+        .map(|file| (built.join(&file), installed.join(&file)))
+        .collect();
+    Ok((pairs, installed))
+}
+
+/// The value of a `<key>` of a property list, if it is a `<string>`
+fn plist_string(plist: &str, key: &str) -> Option<String> {
+    let after = &plist[plist.find(&format!("<key>{}</key>", key))?..];
+    let start = after.find("<string>")? + "<string>".len();
+    let end = after.find("</string>")?;
+    Some(after.get(start..end)?.trim().to_string())
+}
+
+/// The built keyboard DLLs and where they are installed, by the directory
+/// the build puts them in for each architecture. 64-bit Windows, x64 or
+/// ARM64, has a `SysWOW64` for the 32-bit ones.
+fn win_files(output: &Path, root: Option<&Path>) -> Vec<(PathBuf, PathBuf)> {
+    let windows = match root {
+        Some(root) => root.to_path_buf(),
+        None => std::env::var_os("SystemRoot")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(r"C:\Windows")),
+    };
+    let is_arm64 = ["PROCESSOR_ARCHITEW6432", "PROCESSOR_ARCHITECTURE"]
+        .iter()
+        .any(|name| std::env::var(name).is_ok_and(|arch| arch.eq_ignore_ascii_case("ARM64")));
+    let dirs: &[(&str, &str)] = if windows.join("SysWOW64").is_dir() && is_arm64 {
+        &[("arm64", "System32"), ("wow64", "SysWOW64")]
+    } else if windows.join("SysWOW64").is_dir() {
+        &[("amd64", "System32"), ("wow64", "SysWOW64")]
+    } else {
+        &[("i386", "System32")]
+    };
+    let windows = &windows;
+    dirs.iter()
+        .flat_map(|(arch, system)| {
+            files_with_extension(&output.join(arch), "dll")
+                .into_iter()
+                .map(move |dll| {
+                    let installed = windows
+                        .join(system)
+                        .join(dll.file_name().expect("files have names"));
+                    (dll, installed)
+                })
+        })
+        .collect()
+}
+
+/// The version the installer recorded under `HKLM\SOFTWARE\kbdgen`
+fn win_installed_version(uuid: &str) -> Option<String> {
+    if !cfg!(windows) {
+        return None;
+    }
+    let key = format!(
+        r"HKLM\SOFTWARE\kbdgen\{}",
+        uuid.trim_matches(&['{', '}'][..]).to_uppercase()
+    );
+    let output = std::process::Command::new("reg")
+        .args(["query", &key, "/v", "Version"])
+        .output()
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| Some(line.split("REG_SZ").nth(1)?.trim().to_string()))
+}
+
+/// The built symbols and where they are installed, named after the layout,
+/// in order of the built file
+fn x11_files(
+    bundle: &ProjectBundle,
+    output: &Path,
+    root: Option<&Path>,
+) -> Vec<(PathBuf, PathBuf)> {
+    let symbols = under(root, "/usr/share/X11/xkb/symbols");
+    let mut pairs = bundle
+        .layouts
+        .keys()
+        .map(|name| (output.join(xkb_path(name)), symbols.join(name)))
+        .filter(|(built, _)| built.is_file())
+        .collect::<Vec<_>>();
+    pairs.sort();
+    pairs
+}
+
+/// The installed symbols whose layouts `evdev.xml` does not list
+fn x11_unlisted(pairs: &[(PathBuf, PathBuf)], root: Option<&Path>) -> Vec<String> {
+    let rules =
+        fs::read_to_string(under(root, "/usr/share/X11/xkb/rules/evdev.xml")).unwrap_or_default();
+    pairs
+        .iter()
+        .filter(|(_, installed)| installed.is_file())
+        .filter_map(|(_, installed)| Some(installed.file_name()?.to_string_lossy().to_string()))
+        .filter(|name| !rules.contains(&format!("<name>{}</name>", name)))
+        .collect()
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not load kbdgen bundle")]
+    CannotLoad { source: crate::LoadError },
+
+    #[error(
+        "Verifying installations of `{}` is not supported, only of mac, win and x11",
+        target
+    )]
+    UnsupportedTarget { target: Target },
+
+    #[error("The bundle has no `{}` target", target)]
+    NoTarget { target: Target },
+
+    #[error(
+        "Found nothing built for `{}` in `{}`, build it there first",
+        target,
+        path.display()
+    )]
+    NotBuilt { target: Target, path: PathBuf },
+
+    #[error("Could not read `{}`", path.display())]
+    CannotRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stale_and_missing_symbols_are_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("output");
+        let root = dir.path().join("root");
+        let symbols = root.join("usr/share/X11/xkb/symbols");
+        fs::create_dir_all(output.join("linux")).unwrap();
+        fs::create_dir_all(&symbols).unwrap();
+        fs::create_dir_all(root.join("usr/share/X11/xkb/rules")).unwrap();
+        for name in &["se", "se-FI", "se-NO"] {
+            fs::write(output.join("linux").join(name).with_extension("xkb"), name).unwrap();
+        }
+        fs::write(symbols.join("se"), "se").unwrap();
+        fs::write(symbols.join("se-FI"), "older").unwrap();
+        fs::write(
+            root.join("usr/share/X11/xkb/rules/evdev.xml"),
+            "<name>se</name>",
+        )
+        .unwrap();
+
+        let options = Options {
+            target: Target::X11,
+            project_path: "examples/sme.kbdgen".into(),
+            output_path: output,
+            root: Some(root),
+        };
+        let installation = verify_install(&options).unwrap();
+        // In order of file name: se-FI, se-NO and se
+        let states = installation
+            .files
+            .iter()
+            .map(|file| file.state)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            states,
+            vec![FileState::Stale, FileState::Missing, FileState::Current]
+        );
+        assert_eq!(
+            installation
+                .problems()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec![
+                format!(
+                    "`{}` differs from the build",
+                    symbols.join("se-FI").display()
+                ),
+                format!(
+                    "partially installed, `{}` is missing",
+                    symbols.join("se-NO").display()
+                ),
+                "`se-FI` is installed but not listed in the XKB rules".to_string(),
+            ]
+        );
+
+        assert_eq!(
+            plist_string(
+                "<key>CFBundleVersion</key>\n<string>3</string>\n<key>CFBundleShortVersionString</key>\n<string>1.2.0</string>",
+                "CFBundleShortVersionString"
+            ),
+            Some("1.2.0".to_string())
+        );
+    }
+}
@@ -0,0 +1,276 @@
+// The on-screen keyboard of `kbdgen build web`
+//
+// Everything a key types is looked up in `layouts.json`, which kbdgen writes
+// with the same code it generates the keyboards with. This only keeps track
+// of the modifiers held and the dead key pending.
+
+// The modifiers, in the order they are joined in layer names
+const MODIFIERS = ["caps", "alt", "shift"]
+
+// ISO keys by `KeyboardEvent.code`, for typing with a physical keyboard
+const ISO_KEYS = {
+  Backquote: "E00", Digit1: "E01", Digit2: "E02", Digit3: "E03", Digit4: "E04",
+  Digit5: "E05", Digit6: "E06", Digit7: "E07", Digit8: "E08", Digit9: "E09",
+  Digit0: "E10", Minus: "E11", Equal: "E12",
+  KeyQ: "D01", KeyW: "D02", KeyE: "D03", KeyR: "D04", KeyT: "D05", KeyY: "D06",
+  KeyU: "D07", KeyI: "D08", KeyO: "D09", KeyP: "D10", BracketLeft: "D11",
+  BracketRight: "D12",
+  KeyA: "C01", KeyS: "C02", KeyD: "C03", KeyF: "C04", KeyG: "C05", KeyH: "C06",
+  KeyJ: "C07", KeyK: "C08", KeyL: "C09", Semicolon: "C10", Quote: "C11",
+  Backslash: "C12",
+  IntlBackslash: "B00", KeyZ: "B01", KeyX: "B02", KeyC: "B03", KeyV: "B04",
+  KeyB: "B05", KeyN: "B06", KeyM: "B07", Comma: "B08", Period: "B09", Slash: "B10"
+}
+
+// Labels of the special keys of mobile layouts
+const SPECIAL_LABELS = {
+  backspace: "⌫",
+  return: "⏎",
+  shift: "⇧",
+  spacebar: " ",
+  space: " "
+}
+
+class Typist {
+  constructor(layout) {
+    this.layout = layout
+    this.held = new Set()
+    this.pending = null
+  }
+
+  // The layer for the modifiers held, dropping caps and then alt if the
+  // layout has no such layer
+  layerName(held = this.held) {
+    const names = MODIFIERS.filter((m) => held.has(m))
+    const candidates = [names, names.filter((m) => m !== "caps"), names.filter((m) => m === "shift"), []]
+    for (const candidate of candidates) {
+      const name = candidate.length > 0 ? candidate.join("+") : "default"
+      if (this.layout.layers[name] != null) {
+        return name
+      }
+    }
+    return "default"
+  }
+
+  isDeadKey(layerName, value) {
+    const deadKeys = this.layout.deadKeys[layerName]
+    return deadKeys != null && deadKeys.includes(value)
+  }
+
+  // The text that typing `value` in the layer produces, which is empty while
+  // a dead key is pending
+  type(value, layerName = this.layerName()) {
+    const pending = this.pending
+    if (pending != null) {
+      const chain = pending + value
+      if (this.isDeadKey(layerName, value) && this.layout.chains.includes(chain)) {
+        this.pending = chain
+        return ""
+      }
+      this.pending = null
+      const output = (this.layout.transforms[pending] || {})[value]
+      if (output != null) {
+        return output
+      }
+      return this.layout.terminators[pending] + value
+    }
+    if (this.isDeadKey(layerName, value)) {
+      this.pending = value
+      return ""
+    }
+    return value
+  }
+
+  // What a pending dead key types when the text area loses focus or the
+  // layout changes
+  flush() {
+    const pending = this.pending
+    this.pending = null
+    return pending == null ? "" : this.layout.terminators[pending]
+  }
+}
+
+const text = document.getElementById("text")
+const select = document.getElementById("layout")
+const keyboard = document.getElementById("keyboard")
+const modifiers = document.getElementById("modifiers")
+const pendingLabel = document.getElementById("pending")
+let typist = null
+
+function insert(output) {
+  if (output === "") {
+    return
+  }
+  const start = text.selectionStart
+  text.setRangeText(output, start, text.selectionEnd, "end")
+}
+
+function backspace() {
+  const start = text.selectionStart
+  if (start !== text.selectionEnd) {
+    text.setRangeText("", start, text.selectionEnd, "end")
+  } else if (start > 0) {
+    const before = Array.from(text.value.slice(0, start))
+    const last = before.pop() || ""
+    text.setRangeText("", start - last.length, start, "end")
+  }
+}
+
+function update() {
+  pendingLabel.textContent = typist.pending || ""
+  for (const button of modifiers.children) {
+    button.setAttribute("aria-pressed", typist.held.has(button.dataset.modifier))
+  }
+  render()
+}
+
+function pressKey(key) {
+  if (key.special != null) {
+    switch (key.special) {
+      case "backspace":
+        backspace()
+        break
+      case "return":
+        insert(typist.type("\n"))
+        break
+      case "shift":
+        toggle("shift")
+        return
+      case "spacebar":
+      case "space":
+        insert(typist.type(" "))
+        break
+    }
+  } else if (key.value != null) {
+    insert(typist.type(key.value))
+    // The on-screen shift only holds for one key, like on a phone
+    if (!typist.held.has("caps")) {
+      typist.held.delete("shift")
+    }
+  }
+  text.focus()
+  update()
+}
+
+function toggle(modifier) {
+  if (typist.held.has(modifier)) {
+    typist.held.delete(modifier)
+  } else {
+    typist.held.add(modifier)
+  }
+  update()
+}
+
+function render() {
+  const layerName = typist.layerName()
+  const rows = typist.layout.layers[layerName]
+  keyboard.replaceChildren()
+  keyboard.dir = typist.layout.rtl ? "rtl" : "ltr"
+  const spaceRow = typist.layout.kind === "desktop"
+    ? [[{ value: " ", width: 6 }]]
+    : []
+  for (const row of rows.concat(spaceRow)) {
+    const element = document.createElement("div")
+    element.className = "row"
+    for (const key of row) {
+      const button = document.createElement("button")
+      button.type = "button"
+      button.className = "key"
+      button.style.flexGrow = key.width
+      if (key.special != null) {
+        button.classList.add("special")
+        button.textContent = SPECIAL_LABELS[key.special] ?? key.special
+      } else if (key.value != null) {
+        button.textContent = key.label ?? key.value
+        if (typist.isDeadKey(layerName, key.value)) {
+          button.classList.add("dead")
+        }
+      }
+      button.addEventListener("mousedown", (event) => event.preventDefault())
+      button.addEventListener("click", () => pressKey(key))
+      element.appendChild(button)
+    }
+    keyboard.appendChild(element)
+  }
+}
+
+function renderModifiers() {
+  modifiers.replaceChildren()
+  const available = MODIFIERS.filter((m) =>
+    Object.keys(typist.layout.layers).some((name) => name.split("+").includes(m))
+  )
+  for (const modifier of available) {
+    const button = document.createElement("button")
+    button.type = "button"
+    button.dataset.modifier = modifier
+    button.textContent = modifier
+    button.addEventListener("mousedown", (event) => event.preventDefault())
+    button.addEventListener("click", () => toggle(modifier))
+    modifiers.appendChild(button)
+  }
+}
+
+// Keys of a physical keyboard type what the on-screen keyboard would, for
+// desktop layouts
+text.addEventListener("keydown", (event) => {
+  if (typist.layout.kind !== "desktop" || event.ctrlKey || event.metaKey) {
+    return
+  }
+  const held = new Set()
+  if (event.getModifierState("CapsLock")) held.add("caps")
+  if (event.getModifierState("AltGraph") || event.altKey) held.add("alt")
+  if (event.shiftKey) held.add("shift")
+  const layerName = typist.layerName(held)
+
+  let value = null
+  if (event.code === "Space") {
+    value = " "
+  } else if (ISO_KEYS[event.code] != null) {
+    const key = typist.layout.layers[layerName].flat()
+      .find((key) => key.key === ISO_KEYS[event.code])
+    value = key == null ? null : key.value
+  }
+  if (value == null) {
+    return
+  }
+  event.preventDefault()
+  insert(typist.type(value, layerName))
+  update()
+})
+
+text.addEventListener("blur", () => {
+  insert(typist.flush())
+  update()
+})
+
+function choose(layout) {
+  if (typist != null) {
+    insert(typist.flush())
+  }
+  typist = new Typist(layout)
+  text.dir = layout.rtl ? "rtl" : "ltr"
+  renderModifiers()
+  update()
+}
+
+fetch("layouts.json")
+  .then((response) => response.json())
+  .then((layouts) => {
+    for (const layout of layouts) {
+      const option = document.createElement("option")
+      option.value = layout.name
+      option.textContent = layout.displayName
+      select.appendChild(option)
+    }
+    select.addEventListener("change", () => {
+      choose(layouts.find((layout) => layout.name === select.value))
+    })
+    const requested = new URLSearchParams(location.search).get("layout")
+    const initial = layouts.find((layout) => layout.name === requested) || layouts[0]
+    select.value = initial.name
+    choose(initial)
+  })
+
+if ("serviceWorker" in navigator) {
+  navigator.serviceWorker.register("sw.js")
+}
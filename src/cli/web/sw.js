@@ -0,0 +1,32 @@
+// Serves the app from the cache once it has been loaded, so that it works
+// offline, and refreshes the cache in the background.
+const CACHE = "kbdgen-web"
+const FILES = [
+  ".",
+  "index.html",
+  "app.js",
+  "style.css",
+  "icon.svg",
+  "layouts.json",
+  "manifest.webmanifest"
+]
+
+self.addEventListener("install", (event) => {
+  event.waitUntil(caches.open(CACHE).then((cache) => cache.addAll(FILES)))
+})
+
+self.addEventListener("fetch", (event) => {
+  event.respondWith(
+    caches.open(CACHE).then((cache) =>
+      cache.match(event.request).then((cached) => {
+        const fetched = fetch(event.request).then((response) => {
+          if (response.ok) {
+            cache.put(event.request, response.clone())
+          }
+          return response
+        })
+        return cached || fetched
+      })
+    )
+  )
+})
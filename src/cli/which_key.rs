@@ -0,0 +1,98 @@
+//! Finding the keys that type a character, see `Layout::which_key`
+
+use crate::{LazyLayout, ModifierSet, Target, Way};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// The layout file, in the `layouts` directory of a bundle
+    pub layout: PathBuf,
+    pub target: Target,
+    /// Only use presses in these modes, or in any mode if empty
+    pub modes: Vec<ModifierSet>,
+    /// The character (or text) to type
+    pub output: String,
+}
+
+/// The ways of typing the output, shortest first
+pub fn which_key(options: &Options) -> Result<Vec<Way>, Error> {
+    // Only the modes of the target are needed
+    let layout = LazyLayout::load(&options.layout)
+        .and_then(|layout| layout.for_target(options.target))
+        .map_err(|source| Error::CannotLoad { source })?;
+    if layout.modes.resolve(options.target).is_none() {
+        return Err(Error::NoModes {
+            target: options.target,
+        });
+    }
+    Ok(layout.which_key(options.target, &options.output, &options.modes))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not load layout")]
+    CannotLoad { source: crate::LoadError },
+
+    #[error("Layout has no modes for `{}`", target)]
+    NoModes { target: Target },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn example_layout_types_t_stroke_with_a_key() {
+        let options = Options {
+            layout: "examples/sme.kbdgen/layouts/se-NO.yaml".into(),
+            target: Target::Win,
+            modes: vec![],
+            output: "ŧ".to_string(),
+        };
+        let ways = which_key(&options).unwrap();
+        assert_eq!(ways[0].to_string(), "alt+D05");
+    }
+
+    #[test]
+    fn placeholders_and_transform_patterns_are_expanded() {
+        let dir = tempfile::tempdir().unwrap();
+        let layout = dir.path().join("test.yaml");
+        std::fs::write(
+            &layout,
+            r#"
+displayNames:
+  en: Test
+modes:
+  win:
+    default: |
+      ´ 1 2 3 4 5 6 7 8 9 0 + {{zwj}}
+        q w e r t y u i o p å ¨
+        a s d f g h j k l ø æ '
+      < z x c v b n m , . -
+deadKeys:
+  win:
+    default: [´]
+transforms:
+  ´:
+    "[aeiou]": "$0\u0301"
+    " ": ´
+"#,
+        )
+        .unwrap();
+        let ways = |output: &str| {
+            which_key(&Options {
+                layout: layout.clone(),
+                target: Target::Win,
+                modes: vec![],
+                output: output.to_string(),
+            })
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ways("\u{200D}"), vec!["E12"]);
+        assert_eq!(ways("á"), vec!["E00 C01"]);
+    }
+}
@@ -0,0 +1,184 @@
+//! Pinning the keyboard ids of the Windows target in the layouts
+//!
+//! Without `targets.win.id`, a layout's keyboard DLL is named after the
+//! layout, so renaming the layout or adding one with a similar name changes
+//! which DLL users have installed. Allocating ids writes free ones into the
+//! layouts once, see `ProjectBundle::allocate_win_ids`.
+
+use crate::{cli::yaml_edit, layout_file, WinIdAllocation};
+use serde_yaml::Value;
+use std::path::{Path, PathBuf};
+
+/// The ids of the legacy layouts of a `compatMap`, by layout, for the Windows
+/// generator, see [`export_compat_ids`]
+pub const COMPAT_IDS_VAR: &str = "KBDGEN_WIN_COMPAT_IDS";
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub project_path: PathBuf,
+    /// Only report the ids that would be allocated, without writing them
+    pub check: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub allocation: WinIdAllocation,
+    /// The layout files the ids were written to
+    pub written: Vec<PathBuf>,
+}
+
+/// Allocates ids for the layouts of the bundle that have none, and writes
+/// them into the layout files unless `check` is set
+///
+/// Only the lines of `targets.win` are added to the layout files, so that
+/// their comments and anchors are kept. A `targets` or `targets.win` in flow
+/// style or behind an alias is an error, and its id needs to be added by hand.
+pub fn allocate_ids(options: &Options) -> Result<Report, Error> {
+    let bundle = crate::cli::load_bundle(&options.project_path)
+        .map_err(|source| Error::CannotLoad { source })?;
+    let allocation = bundle.allocate_win_ids();
+    if options.check {
+        return Ok(Report {
+            allocation,
+            written: vec![],
+        });
+    }
+
+    let mut written = vec![];
+    for (name, id) in &allocation.ids {
+        let path = layout_file(&options.project_path, name)
+            .map_err(|source| Error::InvalidLayoutName { source })?;
+        set_win_id(&path, name, id)?;
+        written.push(path);
+    }
+    Ok(Report {
+        allocation,
+        written,
+    })
+}
+
+/// Makes the ids of the legacy layouts of a `compatMap` available to the
+/// Windows generator, so that they are allocated like `kbdgen lint` checks
+/// them
+pub fn export_compat_ids(project_path: &Path) -> Result<(), Error> {
+    let bundle =
+        crate::cli::load_bundle(project_path).map_err(|source| Error::CannotLoad { source })?;
+    let json = serde_json::to_string(&bundle.compat_win_ids()).expect("ids serialize");
+    std::env::set_var(COMPAT_IDS_VAR, json);
+    Ok(())
+}
+
+/// Sets `targets.win.id` in the layout file, adding `targets.win` with the
+/// layout's name as its locale if there is none
+fn set_win_id(path: &Path, name: &str, id: &str) -> Result<(), Error> {
+    let text = std::fs::read_to_string(path).map_err(|source| Error::CannotRead {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let document: Value =
+        serde_yaml::from_str(&text).map_err(|source| Error::CannotParse { source })?;
+
+    // A merge key may bring in the locale, which is left to it
+    let win = &document["targets"]["win"];
+    let has_locale = ["locale", "<<"].iter().any(|key| win.get(key).is_some());
+    let edit = |text: &str, key: &str, value: &str| {
+        yaml_edit::set_scalar(text, &["targets", "win", key], value).map_err(|source| {
+            Error::CannotEdit {
+                path: path.to_path_buf(),
+                source,
+            }
+        })
+    };
+    let mut text = text;
+    if !has_locale {
+        text = edit(&text, "locale", name)?;
+    }
+    let text = edit(&text, "id", id)?;
+    std::fs::write(path, text).map_err(|source| Error::CannotWrite {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not load kbdgen bundle")]
+    CannotLoad { source: crate::LoadError },
+
+    #[error("Cannot write the id of this layout")]
+    InvalidLayoutName { source: crate::NameError },
+
+    #[error("Could not read `{}`", path.display())]
+    CannotRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Could not parse layout")]
+    CannotParse { source: serde_yaml::Error },
+
+    #[error("Could not add the id to `{}`", path.display())]
+    CannotEdit {
+        path: PathBuf,
+        source: yaml_edit::Error,
+    },
+
+    #[error("Could not write `{}`", path.display())]
+    CannotWrite {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{models::Layout, Load};
+
+    #[test]
+    fn ids_are_written_into_the_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("smn.yaml");
+        std::fs::write(
+            &path,
+            "displayNames:\n  en: Test\nmodes:\n  win:\n    default: a\n",
+        )
+        .unwrap();
+        set_win_id(&path, "smn", "smn").unwrap();
+        let layout = Layout::load(&path).unwrap();
+        let win = layout.targets.unwrap().win.unwrap();
+        assert_eq!(
+            (win.locale.as_str(), win.id.as_deref()),
+            ("smn", Some("smn"))
+        );
+
+        let path = dir.path().join("se-FI.yaml");
+        std::fs::copy("examples/sme.kbdgen/layouts/se-FI.yaml", &path).unwrap();
+        set_win_id(&path, "se-FI", "se-F2").unwrap();
+        let layout = Layout::load(&path).unwrap();
+        let win = layout.targets.unwrap().win.unwrap();
+        assert_eq!(
+            (win.locale.as_str(), win.id.as_deref()),
+            ("se-Latn-FI", Some("se-F2"))
+        );
+    }
+
+    #[test]
+    fn comments_and_anchors_are_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("smn.yaml");
+        let text = "# Inari Sami\ndisplayNames:\n  en: Test\nmodes:\n  win:\n    default: &keys a # letters\n  mac:\n    default: *keys\n";
+        std::fs::write(&path, text).unwrap();
+        set_win_id(&path, "smn", "smn").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            format!("{}targets:\n  win:\n    locale: smn\n    id: smn\n", text)
+        );
+
+        std::fs::write(&path, "targets: { win: { locale: smn } }\n").unwrap();
+        assert!(matches!(
+            set_win_id(&path, "smn", "smn"),
+            Err(Error::CannotEdit { .. })
+        ));
+    }
+}
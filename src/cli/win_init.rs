@@ -0,0 +1,165 @@
+//! Giving the Windows target of a bundle its installer GUID
+//!
+//! The `uuid` of the Windows target has to be generated once and then kept,
+//! see `crate::guid`. Only the target file is read, so that this works on a
+//! bundle that does not load because the `uuid` is missing.
+
+use crate::{cli::yaml_edit, is_guid, new_guid, windows_target_path};
+use serde_yaml::Value;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub project_path: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    /// The file of the Windows target
+    pub path: PathBuf,
+    pub uuid: String,
+    /// Whether the `uuid` was generated, rather than already set
+    pub generated: bool,
+}
+
+/// Generates a `uuid` for the Windows target and writes it into the target's
+/// file, unless it already has one. A `uuid` that is not a GUID is an error
+/// rather than replaced, as installed layouts are registered under it.
+///
+/// Only the `uuid` line of the file is written, so that its comments and
+/// anchors are kept.
+pub fn init(options: &Options) -> Result<Report, Error> {
+    let path = windows_target_path(&options.project_path.join("targets"));
+    if !path.is_file() {
+        return Err(Error::NoTarget { path });
+    }
+    let text = std::fs::read_to_string(&path).map_err(|source| Error::CannotRead {
+        path: path.clone(),
+        source,
+    })?;
+    let document: Value = serde_yaml::from_str(&text).map_err(|source| Error::CannotParse {
+        path: path.clone(),
+        source,
+    })?;
+    let mapping = match document.as_mapping() {
+        Some(mapping) => mapping,
+        None => return Err(Error::NoTarget { path }),
+    };
+
+    let key = Value::from("uuid");
+    match mapping.get(&key).and_then(Value::as_str) {
+        Some(uuid) if is_guid(uuid) => {
+            return Ok(Report {
+                path,
+                uuid: uuid.to_string(),
+                generated: false,
+            })
+        }
+        Some(uuid) if !uuid.is_empty() => {
+            return Err(Error::InvalidUuid {
+                path,
+                uuid: uuid.to_string(),
+            })
+        }
+        _ => {}
+    }
+
+    let uuid = new_guid();
+    let text =
+        yaml_edit::set_scalar(&text, &["uuid"], &uuid).map_err(|source| Error::CannotEdit {
+            path: path.clone(),
+            source,
+        })?;
+    std::fs::write(&path, text).map_err(|source| Error::CannotWrite {
+        path: path.clone(),
+        source,
+    })?;
+    Ok(Report {
+        path,
+        uuid,
+        generated: true,
+    })
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("There is no Windows target in `{}`", path.display())]
+    NoTarget { path: PathBuf },
+    #[error("`uuid` in `{}` is not a GUID: `{}`; fix or remove it", path.display(), uuid)]
+    InvalidUuid { path: PathBuf, uuid: String },
+    #[error("Could not read `{}`", path.display())]
+    CannotRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Could not parse `{}`", path.display())]
+    CannotParse {
+        path: PathBuf,
+        source: serde_yaml::Error,
+    },
+    #[error("Could not add the `uuid` to `{}`", path.display())]
+    CannotEdit {
+        path: PathBuf,
+        source: yaml_edit::Error,
+    },
+    #[error("Could not write `{}`", path.display())]
+    CannotWrite {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{models::TargetWindows, LoadError, ProjectBundle};
+
+    #[test]
+    fn uuids_are_generated_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let targets = dir.path().join("targets");
+        std::fs::create_dir_all(&targets).unwrap();
+        std::fs::create_dir_all(dir.path().join("layouts")).unwrap();
+        std::fs::copy(
+            "examples/sme.kbdgen/project.yaml",
+            dir.path().join("project.yaml"),
+        )
+        .unwrap();
+        let path = targets.join("win.yaml");
+        let text = "# Installer\nappName: &name Test\nversion: 1.0.0\nurl: ''\nuuid: ''\n";
+        std::fs::write(&path, text).unwrap();
+
+        let partial = ProjectBundle::load_partial(dir.path(), None).unwrap();
+        assert!(matches!(
+            partial.errors[..],
+            [LoadError::MissingUuid { .. }]
+        ));
+
+        let options = Options {
+            project_path: dir.path().to_path_buf(),
+        };
+        let report = init(&options).unwrap();
+        assert!(report.generated);
+        let target: TargetWindows =
+            serde_yaml::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(target.uuid, report.uuid);
+        assert_eq!(target.app_name, "Test");
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            format!(
+                "# Installer\nappName: &name Test\nversion: 1.0.0\nurl: ''\nuuid: {}\n",
+                report.uuid
+            )
+        );
+
+        let again = init(&options).unwrap();
+        assert_eq!((again.uuid, again.generated), (report.uuid, false));
+
+        std::fs::write(
+            &path,
+            "appName: Test\nversion: 1.0.0\nurl: ''\nuuid: nope\n",
+        )
+        .unwrap();
+        assert!(matches!(init(&options), Err(Error::InvalidUuid { .. })));
+    }
+}
@@ -0,0 +1,212 @@
+//! Building, validating and scoring every bundle of a workspace, see
+//! `crate::workspace`
+//!
+//! Each member is built by a `kbdgen build` of its own, as the generators
+//! keep state for the bundle they build, into a directory named after the
+//! member in the output directory. Its JSON envelope becomes the member's
+//! part of the combined report.
+
+use super::output::Envelope;
+use crate::{
+    lint::Warning,
+    workspace::{Member, Workspace},
+    LoadError, Score,
+};
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+/// What the linter found in a member, see `super::lint::Report`
+#[derive(Debug)]
+pub struct Validation {
+    pub member: Member,
+    /// The errors of files that could not be loaded
+    pub errors: Vec<LoadError>,
+    pub denied: Vec<Warning>,
+    pub warnings: Vec<Warning>,
+}
+
+impl Validation {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty() && self.denied.is_empty()
+    }
+}
+
+/// Loads the workspace, with its cache exported for the members
+pub fn load(path: &Path) -> Result<Workspace, Error> {
+    let workspace = Workspace::load(path).map_err(|source| Error::CannotLoad { source })?;
+    workspace.export();
+    Ok(workspace)
+}
+
+/// Lints every member, reporting the files that cannot be loaded rather than
+/// stopping at them
+pub fn validate(workspace: &Workspace) -> Result<Vec<Validation>, Error> {
+    workspace
+        .members
+        .iter()
+        .map(|member| {
+            let options = super::lint::Options {
+                project_path: member.path.clone(),
+                keep_going: true,
+                fonts: vec![],
+            };
+            let report = super::lint::lint(&options).map_err(|source| Error::CannotLint {
+                name: member.name.clone(),
+                source,
+            })?;
+            Ok(Validation {
+                member: member.clone(),
+                errors: report.errors,
+                denied: report.denied,
+                warnings: report.warnings,
+            })
+        })
+        .collect()
+}
+
+/// The scores of the layouts of a member, by layout name
+#[derive(Debug)]
+pub struct Stats {
+    pub member: Member,
+    pub scores: Vec<(String, Score)>,
+}
+
+/// The scores of the layouts of every member, see `super::score::score`
+pub fn stats(workspace: &Workspace) -> Result<Vec<Stats>, Error> {
+    workspace
+        .members
+        .iter()
+        .map(|member| {
+            let options = super::score::Options {
+                project_path: member.path.clone(),
+                badges: None,
+            };
+            let scores = super::score::score(&options).map_err(|source| Error::CannotScore {
+                name: member.name.clone(),
+                source,
+            })?;
+            Ok(Stats {
+                member: member.clone(),
+                scores,
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct BuildOptions {
+    pub output_path: PathBuf,
+    /// The arguments of `kbdgen build` up to the target and its options, like
+    /// `--report svg`
+    pub args: Vec<String>,
+    /// Build the remaining members after one fails, instead of stopping
+    pub keep_going: bool,
+}
+
+/// The output directory of a member
+pub fn member_output(output_path: &Path, member: &Member) -> PathBuf {
+    output_path.join(&member.name)
+}
+
+/// Builds the members in order, each into its own directory of the output
+/// directory, stopping at the first that fails unless `keep_going`
+pub fn build(
+    workspace: &Workspace,
+    options: &BuildOptions,
+) -> Result<Vec<(Member, Envelope)>, Error> {
+    let kbdgen = std::env::current_exe().map_err(|source| Error::CannotRun {
+        name: "kbdgen".into(),
+        source,
+    })?;
+
+    let mut built = vec![];
+    for member in &workspace.members {
+        log::info!("Building `{}`", member.name);
+        let output = Command::new(&kbdgen)
+            .arg("--json")
+            .arg("build")
+            .args(&options.args)
+            .arg("--output")
+            .arg(member_output(&options.output_path, member))
+            .arg(&member.path)
+            .stderr(Stdio::inherit())
+            .output()
+            .map_err(|source| Error::CannotRun {
+                name: member.name.clone(),
+                source,
+            })?;
+        let envelope: Envelope =
+            serde_json::from_slice(&output.stdout).map_err(|source| Error::CannotReadResult {
+                name: member.name.clone(),
+                source,
+            })?;
+        let failed = envelope.exit_code != 0;
+        built.push((member.clone(), envelope));
+        if failed && !options.keep_going {
+            break;
+        }
+    }
+    Ok(built)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not load workspace")]
+    CannotLoad { source: crate::workspace::Error },
+
+    #[error("Could not lint `{}`", name)]
+    CannotLint {
+        name: String,
+        source: super::lint::Error,
+    },
+
+    #[error("Could not score `{}`", name)]
+    CannotScore {
+        name: String,
+        source: super::score::Error,
+    },
+
+    #[error("Could not run the build of `{}`", name)]
+    CannotRun {
+        name: String,
+        source: std::io::Error,
+    },
+
+    #[error("Could not read the result of the build of `{}`", name)]
+    CannotReadResult {
+        name: String,
+        source: serde_json::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn members_are_validated_and_scored() {
+        let dir = tempfile::tempdir().unwrap();
+        let example = std::env::current_dir().unwrap().join("examples/sme.kbdgen");
+        std::fs::write(
+            dir.path().join(crate::workspace::WORKSPACE_FILE),
+            format!("bundles: ['{}']\n", example.display()),
+        )
+        .unwrap();
+        let workspace = Workspace::load(dir.path()).unwrap();
+
+        let validations = validate(&workspace).unwrap();
+        assert_eq!(validations.len(), 1);
+        assert_eq!(validations[0].member.name, "sme");
+        assert!(validations[0].is_valid(), "{:?}", validations[0]);
+
+        let stats = stats(&workspace).unwrap();
+        assert_eq!(stats.len(), 1);
+        assert!(!stats[0].scores.is_empty());
+        assert_eq!(
+            member_output(Path::new("out"), &stats[0].member),
+            Path::new("out/sme")
+        );
+    }
+}
@@ -0,0 +1,233 @@
+//! Setting values in the YAML files of a bundle without rewriting them
+//!
+//! Commands that write a value into a file, like `kbdgen target win init`,
+//! replace or add only the lines of that value, so that the comments, anchors
+//! and formatting of the rest of the file are kept. Only block mappings are
+//! edited; a value inside a flow mapping like `targets: { win: ... }` or
+//! behind an alias is an error, rather than the file being rewritten.
+
+/// Sets the scalar at `path`, like `["targets", "win", "id"]`, in `text`,
+/// adding the mappings on the way to it that are missing
+pub fn set_scalar(text: &str, path: &[&str], value: &str) -> Result<String, Error> {
+    let newline = if text.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut lines = text.lines().map(str::to_string).collect::<Vec<_>>();
+    let step = lines
+        .iter()
+        .filter_map(|line| indent(line))
+        .find(|&indent| indent > 0)
+        .unwrap_or(2);
+
+    let (mut start, mut end, mut parent) = (0, lines.len(), None);
+    for (depth, key) in path.iter().enumerate() {
+        let name = path[..=depth].join(".");
+        let first = (start..end).find_map(|i| Some((i, indent(&lines[i])?)));
+        let own = match first {
+            Some((i, own)) if lines[i][own..].starts_with(['{', '[']) => {
+                return Err(Error::FlowStyle {
+                    key: path[..depth].join("."),
+                })
+            }
+            Some((_, own)) => own,
+            None => parent.map_or(0, |parent| parent + step),
+        };
+        let found = (start..end).find_map(|i| {
+            if indent(&lines[i]) != Some(own) {
+                return None;
+            }
+            let (found_key, key_len, rest) = entry(&lines[i][own..])?;
+            (found_key == *key).then(|| (i, key_len, rest.to_string()))
+        });
+
+        let (i, key_len, rest) = match found {
+            Some(found) => found,
+            None => {
+                // After the last entry of the mapping, before any comments
+                // and blank lines that follow it, indented like the mapping
+                let step = parent.map_or(step, |parent| own - parent);
+                let at = (start..end)
+                    .rev()
+                    .find(|&i| indent(&lines[i]).is_some())
+                    .map_or(start, |i| i + 1);
+                let added = path[depth..]
+                    .iter()
+                    .enumerate()
+                    .map(|(offset, key)| {
+                        let pad = " ".repeat(own + offset * step);
+                        if depth + offset + 1 == path.len() {
+                            format!("{}{}: {}", pad, key, scalar(value))
+                        } else {
+                            format!("{}{}:", pad, key)
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                lines.splice(at..at, added);
+                break;
+            }
+        };
+
+        let block_end = (i + 1..end)
+            .find(|&j| matches!(indent(&lines[j]), Some(indent) if indent <= own))
+            .unwrap_or(end);
+        let rest = strip_comment(&rest);
+        if rest.starts_with('*') {
+            return Err(Error::Alias { key: name });
+        }
+        if depth + 1 == path.len() {
+            if rest.starts_with('&') {
+                return Err(Error::Anchor { key: name });
+            }
+            let value_end = (i + 1..block_end)
+                .rev()
+                .find(|&j| indent(&lines[j]).is_some())
+                .map_or(i + 1, |j| j + 1);
+            let line = format!("{}: {}", &lines[i][..own + key_len], scalar(value));
+            lines.splice(i..value_end, std::iter::once(line));
+            break;
+        }
+        if rest.starts_with(['{', '[']) {
+            return Err(Error::FlowStyle { key: name });
+        }
+        let after_anchor = match rest.strip_prefix('&') {
+            Some(anchored) => anchored
+                .split_once(' ')
+                .map_or("", |(_, value)| value.trim()),
+            None => rest,
+        };
+        if !after_anchor.is_empty() {
+            return Err(Error::NotAMapping { key: name });
+        }
+        start = i + 1;
+        end = block_end;
+        parent = Some(own);
+    }
+
+    let mut edited = lines.join(newline);
+    if text.is_empty() || text.ends_with('\n') {
+        edited.push_str(newline);
+    }
+    Ok(edited)
+}
+
+/// The indentation of a line with content, or none for blank lines, comments
+/// and document markers
+fn indent(line: &str) -> Option<usize> {
+    let content = line.trim_start_matches(' ');
+    if content.trim().is_empty() || content.starts_with('#') || line.starts_with("---") {
+        return None;
+    }
+    Some(line.len() - content.len())
+}
+
+/// The key of a mapping entry, unquoted, the length of the key as written
+/// and what follows the `:`
+fn entry(content: &str) -> Option<(String, usize, &str)> {
+    let (key, len) = match content.chars().next()? {
+        quote @ ('"' | '\'') => {
+            let close = content[1..].find(quote)? + 1;
+            (content[1..close].to_string(), close + 1)
+        }
+        _ => {
+            let len = content.find(": ").or_else(|| {
+                content
+                    .trim_end()
+                    .ends_with(':')
+                    .then(|| content.trim_end().len() - 1)
+            })?;
+            (content[..len].trim_end().to_string(), len)
+        }
+    };
+    let rest = content[len..].trim_start().strip_prefix(':')?;
+    if !rest.is_empty() && !rest.starts_with(' ') {
+        return None;
+    }
+    Some((key, len, rest))
+}
+
+fn strip_comment(value: &str) -> &str {
+    let value = value.trim();
+    if value.starts_with('#') {
+        return "";
+    }
+    value.split(" #").next().unwrap_or(value).trim()
+}
+
+/// `value` as a YAML scalar, quoted if it would otherwise be read as
+/// something else
+fn scalar(value: &str) -> String {
+    match serde_yaml::from_str::<serde_yaml::Value>(value) {
+        Ok(serde_yaml::Value::String(read)) if read == value && !value.contains(" #") => {
+            value.to_string()
+        }
+        _ => format!("'{}'", value.replace('\'', "''")),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum Error {
+    #[error(
+        "`{}` is written in flow style, like `{{ a: b }}`, which kbdgen does not edit; write it as a block mapping",
+        key
+    )]
+    FlowStyle { key: String },
+    #[error(
+        "`{}` is an alias, which kbdgen does not edit; write out its value",
+        key
+    )]
+    Alias { key: String },
+    #[error("`{}` has an anchor, which kbdgen does not replace", key)]
+    Anchor { key: String },
+    #[error("`{}` is not a mapping", key)]
+    NotAMapping { key: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_are_added_and_replaced_keeping_the_rest() {
+        let text = "# Northern Sami\ndisplayNames: &names\n  en: Test # English\n\ntargets:\n    mac:\n        icon: se.png\n\n# Mobile\nmodes: {}\n";
+        assert_eq!(
+            set_scalar(text, &["targets", "win", "id"], "sme").unwrap(),
+            "# Northern Sami\ndisplayNames: &names\n  en: Test # English\n\ntargets:\n    mac:\n        icon: se.png\n    win:\n        id: sme\n\n# Mobile\nmodes: {}\n"
+        );
+
+        let text = "appName: Test\nuuid: ''\nurl: x # the site\n";
+        assert_eq!(
+            set_scalar(text, &["uuid"], "{ABC}").unwrap(),
+            "appName: Test\nuuid: '{ABC}'\nurl: x # the site\n"
+        );
+        assert_eq!(
+            set_scalar("appName: Test\r\n", &["uuid"], "{ABC}").unwrap(),
+            "appName: Test\r\nuuid: '{ABC}'\r\n"
+        );
+        assert_eq!(
+            set_scalar("targets:\n", &["targets", "win", "locale"], "se").unwrap(),
+            "targets:\n  win:\n    locale: se\n"
+        );
+    }
+
+    #[test]
+    fn flow_mappings_and_aliases_are_refused() {
+        assert_eq!(
+            set_scalar(
+                "targets: { win: { locale: se } }\n",
+                &["targets", "win", "id"],
+                "se"
+            ),
+            Err(Error::FlowStyle {
+                key: "targets".into()
+            })
+        );
+        assert_eq!(
+            set_scalar("targets:\n  win: *win\n", &["targets", "win", "id"], "se"),
+            Err(Error::Alias {
+                key: "targets.win".into()
+            })
+        );
+        assert_eq!(
+            set_scalar("{ appName: Test }\n", &["uuid"], "{ABC}"),
+            Err(Error::FlowStyle { key: "".into() })
+        );
+    }
+}
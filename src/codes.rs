@@ -0,0 +1,266 @@
+//! Stable codes of the errors and warnings kbdgen reports
+//!
+//! Every diagnostic carries a code, like `E003` or `W014`, that stays the
+//! same from one version to the next, so that scripts and editors can tell
+//! diagnostics apart without matching their messages, and so that
+//! `kbdgen explain <code>` can say more about them than fits in a message.
+//! Errors have codes starting with `E` and warnings codes starting with `W`;
+//! a lint rule denied in `.kbdgen-lint.yaml` is reported as an error but keeps
+//! its `W` code.
+//!
+//! Codes are never reused or renumbered. A code that is no longer reported
+//! keeps its place in the table, and new ones are added at the end. The
+//! explanation of each code is in `src/codes/<code>.md`.
+
+use crate::{lint::Rule, LoadError};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{error::Error as StdError, fmt, str::FromStr};
+use strum_macros::EnumIter;
+
+macro_rules! codes {
+    ($($variant:ident = $id:literal, $name:literal, $summary:literal;)*) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, EnumIter)]
+        pub enum Code {
+            $($variant,)*
+        }
+
+        impl Code {
+            /// Like `E003`
+            pub fn id(self) -> &'static str {
+                match self {
+                    $(Code::$variant => $id,)*
+                }
+            }
+
+            /// Like `parse-file`, the name of the lint rule for warnings of
+            /// the linter
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(Code::$variant => $name,)*
+                }
+            }
+
+            /// One line on what the code is about
+            pub fn summary(self) -> &'static str {
+                match self {
+                    $(Code::$variant => $summary,)*
+                }
+            }
+
+            /// What the code means, why it is reported and how to fix it,
+            /// with examples
+            pub fn explanation(self) -> &'static str {
+                match self {
+                    $(Code::$variant => include_str!(concat!("codes/", $id, ".md")),)*
+                }
+            }
+        }
+    };
+}
+
+codes! {
+    ReadFile = "E001", "read-file", "A file or directory of the bundle could not be read";
+    MalformedFilename = "E002", "malformed-filename", "A layout file has no name before `.yaml`";
+    ParseFile = "E003", "parse-file", "A file of the bundle is not valid YAML for what it holds";
+    DuplicateLayout = "E004", "duplicate-layout", "Two layouts or variants have the same name";
+    InvalidLayoutName = "E005", "invalid-layout-name", "A layout name cannot be used as a file name";
+    InvalidModeName = "E006", "invalid-mode-name", "A mode name is unknown or not one the target has";
+    UnknownDeadKeyTerminator = "E007", "unknown-dead-key-terminator", "`deadKeyTerminators` has a key that is not a dead key";
+    InvalidTheme = "E008", "invalid-theme", "The `theme` of a mobile target has an invalid value";
+    MissingUuid = "E009", "missing-uuid", "The Windows target has no installer GUID";
+    InvalidUuid = "E010", "invalid-uuid", "The `uuid` of the Windows target is not a GUID";
+    Signature = "E011", "signature", "The bundle's signature does not match the required key";
+    UnsortedTransforms = "E012", "unsorted-transforms", "`sort-transforms --check` would change the layout";
+    SystemLayoutDiffers = "E013", "system-layout-differs", "The layout installed on the system differs from the bundle";
+    InstallProblem = "E014", "install-problem", "The installed files differ from the build";
+    MissingWinIds = "E015", "missing-win-ids", "`allocate-ids --check` found layouts without a Windows id";
+    LayoutTestFailed = "E016", "layout-test-failed", "A layout test typed something other than expected";
+    Untypable = "E017", "untypable", "`which-key` found no way to type the text";
+    NotSupported = "E018", "not-supported", "The command is not supported yet";
+    ConflictingTransforms = "E019", "conflicting-transforms", "Transforms that differ only in normalization type different outputs";
+    InvalidRender = "E020", "invalid-render", "The `render` section of the project has an invalid value";
+    InvalidTransformPattern = "E021", "invalid-transform-pattern", "A transform pattern is not a valid regular expression";
+    UnknownFlickKeys = "W001", "unknown-flick-keys", "`flicks` for a key that no mobile mode has";
+    UnsupportedReordering = "W002", "unsupported-reordering", "`reorders` or `backspaces` that desktop targets ignore";
+    UnsupportedDeadKeyChains = "W003", "unsupported-dead-key-chains", "Dead key chains that targets other than macOS ignore";
+    IgnoredTransformPartial = "W004", "ignored-transform-partial", "A `transformPartial` that a target cannot honour";
+    SymmetricTransformConflicts = "W005", "symmetric-transform-conflicts", "A swapped transform written out with a different output";
+    UnmirroredBrackets = "W006", "unmirrored-brackets", "Brackets in left-to-right order in a right-to-left layout";
+    InconsistentConfusables = "W007", "inconsistent-confusables", "Layouts use different lookalike characters for the same purpose";
+    UnusedFeatures = "W008", "unused-features", "`features` that no target of the layout honours";
+    IgnoredModifierRemaps = "W009", "ignored-modifier-remaps", "`modifierRemaps` that a target cannot do or ignores";
+    ShortcutConflicts = "W010", "shortcut-conflicts", "A `cmd` or `ctrl` mode moves or drops the letter of a shortcut";
+    MissingStoreUrls = "W011", "missing-store-urls", "Mobile targets without the URLs that app stores need";
+    UnreadableFonts = "W012", "unreadable-fonts", "`fonts` of a mobile target that cannot be read";
+    MissingGlyphs = "W013", "missing-glyphs", "Characters of a layout that its fonts have no glyphs for";
+    WinIdCollisions = "W014", "win-id-collisions", "Layouts built into the same Windows keyboard DLL";
+    Loss = "W015", "loss", "A key or rule the target cannot represent was dropped or altered";
+    BuildWarning = "W016", "build-warning", "A generator warned about something during a build";
+    SkippedLine = "W017", "skipped-line", "A line of an imported file could not be converted";
+    SharedWinId = "W018", "shared-win-id", "A layout with variants was given no Windows id";
+    DeadKeyNotInMode = "W019", "dead-key-not-in-mode", "A dead key that its mode does not type";
+    RawInvisibleCharacters = "W020", "raw-invisible-characters", "An invisible character written as it is in a layout file";
+    AndroidStyle = "W021", "android-style", "An Android `style`, which `theme` has replaced";
+}
+
+impl Code {
+    /// Whether the code is for a warning, which fails nothing on its own
+    pub fn is_warning(self) -> bool {
+        self.id().starts_with('W')
+    }
+
+    /// The code of the error that made loading a bundle fail, if any of
+    /// `error` and its sources is one
+    pub fn of_error(error: &(dyn StdError + 'static)) -> Option<Code> {
+        let mut error = Some(error);
+        while let Some(e) = error {
+            if let Some(e) = e.downcast_ref::<LoadError>() {
+                return Some(Code::from(e));
+            }
+            error = e.source();
+        }
+        None
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.id())
+    }
+}
+
+/// As the code's id
+impl Serialize for Code {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.id())
+    }
+}
+
+/// By the code's id or name, like `FromStr`
+impl<'de> Deserialize<'de> for Code {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Code, D::Error> {
+        let code = String::deserialize(deserializer)?;
+        code.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// A code by its id, in any case, or by its name
+impl FromStr for Code {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Code, Error> {
+        use strum::IntoEnumIterator;
+        Code::iter()
+            .find(|code| code.id().eq_ignore_ascii_case(s) || code.name() == s)
+            .ok_or_else(|| Error::UnknownCode {
+                code: s.to_string(),
+            })
+    }
+}
+
+impl From<Rule> for Code {
+    fn from(rule: Rule) -> Code {
+        match rule {
+            Rule::UnknownFlickKeys => Code::UnknownFlickKeys,
+            Rule::UnsupportedReordering => Code::UnsupportedReordering,
+            Rule::UnsupportedDeadKeyChains => Code::UnsupportedDeadKeyChains,
+            Rule::IgnoredTransformPartial => Code::IgnoredTransformPartial,
+            Rule::SymmetricTransformConflicts => Code::SymmetricTransformConflicts,
+            Rule::UnmirroredBrackets => Code::UnmirroredBrackets,
+            Rule::InconsistentConfusables => Code::InconsistentConfusables,
+            Rule::UnusedFeatures => Code::UnusedFeatures,
+            Rule::IgnoredModifierRemaps => Code::IgnoredModifierRemaps,
+            Rule::ShortcutConflicts => Code::ShortcutConflicts,
+            Rule::MissingStoreUrls => Code::MissingStoreUrls,
+            Rule::UnreadableFonts => Code::UnreadableFonts,
+            Rule::MissingGlyphs => Code::MissingGlyphs,
+            Rule::WinIdCollisions => Code::WinIdCollisions,
+            Rule::RawInvisibleCharacters => Code::RawInvisibleCharacters,
+            Rule::AndroidStyle => Code::AndroidStyle,
+        }
+    }
+}
+
+impl From<&LoadError> for Code {
+    fn from(error: &LoadError) -> Code {
+        match error {
+            LoadError::ReadFile { .. } => Code::ReadFile,
+            LoadError::MalformedFilename { .. } => Code::MalformedFilename,
+            LoadError::ParseFile { .. } => Code::ParseFile,
+            LoadError::DuplicateLayout { .. } => Code::DuplicateLayout,
+            LoadError::InvalidLayoutName { .. } => Code::InvalidLayoutName,
+            LoadError::InvalidModeName { .. } => Code::InvalidModeName,
+            LoadError::UnknownDeadKeyTerminator { .. } => Code::UnknownDeadKeyTerminator,
+            LoadError::InvalidTheme { .. } => Code::InvalidTheme,
+            LoadError::InvalidRender { .. } => Code::InvalidRender,
+            LoadError::InvalidTransformPattern { .. } => Code::InvalidTransformPattern,
+            LoadError::MissingUuid { .. } => Code::MissingUuid,
+            LoadError::InvalidUuid { .. } => Code::InvalidUuid,
+            LoadError::Signature { .. } => Code::Signature,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum Error {
+    #[error(
+        "`{}` is not a kbdgen error code, see `kbdgen explain` for all of them",
+        code
+    )]
+    UnknownCode { code: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use strum::IntoEnumIterator;
+
+    #[test]
+    fn codes_are_numbered_in_order_and_explained() {
+        let mut errors = 0;
+        let mut warnings = 0;
+        for code in Code::iter() {
+            let count = if code.is_warning() {
+                &mut warnings
+            } else {
+                &mut errors
+            };
+            *count += 1;
+            assert_eq!(&code.id()[1..], format!("{:03}", count), "{:?}", code);
+            assert!(
+                code.explanation()
+                    .starts_with(&format!("# {}: ", code.id())),
+                "{:?}",
+                code
+            );
+            assert_eq!(code.id().parse(), Ok(code));
+            assert_eq!(code.id().to_lowercase().parse(), Ok(code));
+            assert_eq!(code.name().parse(), Ok(code));
+        }
+        let names = Code::iter().map(Code::name).collect::<BTreeSet<_>>();
+        assert_eq!(names.len(), Code::iter().count());
+        assert!("E999".parse::<Code>().is_err());
+    }
+
+    #[test]
+    fn lint_rules_keep_their_names() {
+        for rule in Rule::iter() {
+            let code = Code::from(rule);
+            assert!(code.is_warning());
+            assert_eq!(code.name(), rule.to_string());
+        }
+    }
+
+    #[test]
+    fn load_errors_are_found_among_sources() {
+        let error = crate::cli::lint::Error::CannotLoad {
+            source: LoadError::MissingUuid {
+                path: "targets/win.yaml".into(),
+            },
+        };
+        assert_eq!(Code::of_error(&error), Some(Code::MissingUuid));
+        let error = "x".parse::<u8>().unwrap_err();
+        assert_eq!(Code::of_error(&error), None);
+    }
+}
@@ -0,0 +1,227 @@
+//! The user's configuration
+//!
+//! `config.toml` in the user's config directory, like
+//! `~/.config/kbdgen/config.toml`, or the file in `KBDGEN_CONFIG`, holds the
+//! defaults a user would otherwise pass to every command:
+//!
+//! ```toml
+//! output_dir = "~/kbdgen-output"
+//! output_layout = "per-locale"
+//! cache_dir = "/var/cache/kbdgen"
+//!
+//! [signing]
+//! bundle_key = "~/.config/kbdgen/signing.key"
+//! code_sign_id = "Developer ID Application: Example Corp"
+//!
+//! [templates.android]
+//! git = "https://github.com/example/keyboard-android"
+//! tag = "v1.2.0"
+//! ```
+//!
+//! Each setting has an environment variable that overrides it, and the
+//! configuration reaches commands and the Python generators through these
+//! variables, see [`Config::export`]. Settings of the bundle, like a target's
+//! `codeSignId` or `template`, take precedence over both.
+
+use crate::{
+    cli::repos,
+    models::Template,
+    output_layout::{OutputLayout, OUTPUT_LAYOUT_VAR},
+    Target,
+};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+/// The configuration file to use instead of the one in the config directory
+pub const CONFIG_VAR: &str = "KBDGEN_CONFIG";
+pub const OUTPUT_DIR_VAR: &str = "KBDGEN_OUTPUT_DIR";
+pub const CACHE_DIR_VAR: &str = "KBDGEN_CACHE_DIR";
+pub const SIGNING_KEY_VAR: &str = "KBDGEN_SIGNING_KEY";
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The output directory of builds that are not given one
+    /// (`KBDGEN_OUTPUT_DIR`)
+    pub output_dir: Option<PathBuf>,
+
+    /// Where the files generated for each layout go in the output directory
+    /// (`KBDGEN_OUTPUT_LAYOUT`), see `crate::output_layout`
+    pub output_layout: Option<OutputLayout>,
+
+    /// Where CLDR, XKB, templates and loaded bundles are cached
+    /// (`KBDGEN_CACHE_DIR`)
+    pub cache_dir: Option<PathBuf>,
+
+    pub signing: Signing,
+
+    /// The template of each target whose bundle does not set one, by target
+    pub templates: BTreeMap<String, Template>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Signing {
+    /// The key `kbdgen sign-bundle` signs with (`KBDGEN_SIGNING_KEY`)
+    pub bundle_key: Option<PathBuf>,
+
+    /// The public key bundles must be signed with
+    /// (`KBDGEN_REQUIRE_SIGNATURE`)
+    pub trusted_key: Option<PathBuf>,
+
+    /// The code signing identity for macOS and iOS (`CODE_SIGN_ID`)
+    pub code_sign_id: Option<String>,
+
+    /// The Apple developer team (`TEAM_ID` and `DEVELOPER_TEAM_ID`)
+    pub team_id: Option<String>,
+
+    /// The code signing certificate for Windows (`CODESIGN_PFX`)
+    pub codesign_pfx: Option<PathBuf>,
+}
+
+/// Expands a leading `~` to the user's home directory
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+impl Config {
+    /// The configuration file, which need not exist
+    pub fn path() -> PathBuf {
+        match std::env::var_os(CONFIG_VAR) {
+            Some(path) => PathBuf::from(path),
+            None => repos::config_dir().join("config.toml"),
+        }
+    }
+
+    /// Reads the configuration file, or the defaults if there is none
+    pub fn read(path: &Path) -> Result<Config, Error> {
+        if !path.is_file() {
+            return Ok(Config::default());
+        }
+        let text = std::fs::read_to_string(path).map_err(|source| Error::CannotRead {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        toml::from_str(&text).map_err(|source| Error::CannotParse {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    pub fn load() -> Result<Config, Error> {
+        Config::read(&Config::path())
+    }
+
+    /// The environment variables for the settings, by name
+    pub fn env_vars(&self) -> Vec<(&'static str, OsString)> {
+        let path = |path: &Option<PathBuf>| path.as_deref().map(|p| expand_home(p).into());
+        let text = |text: &Option<String>| text.as_ref().map(OsString::from);
+        let signing = &self.signing;
+        let vars = vec![
+            (OUTPUT_DIR_VAR, path(&self.output_dir)),
+            (
+                OUTPUT_LAYOUT_VAR,
+                self.output_layout.map(|layout| layout.to_string().into()),
+            ),
+            (CACHE_DIR_VAR, path(&self.cache_dir)),
+            (SIGNING_KEY_VAR, path(&signing.bundle_key)),
+            (
+                crate::cli::REQUIRE_SIGNATURE_VAR,
+                path(&signing.trusted_key),
+            ),
+            ("CODE_SIGN_ID", text(&signing.code_sign_id)),
+            ("TEAM_ID", text(&signing.team_id)),
+            ("DEVELOPER_TEAM_ID", text(&signing.team_id)),
+            ("CODESIGN_PFX", path(&signing.codesign_pfx)),
+        ];
+        vars.into_iter()
+            .filter_map(|(name, value)| Some((name, value?)))
+            .collect()
+    }
+
+    /// Sets the environment variables for the settings that are not set
+    /// already, so that the environment overrides the configuration
+    pub fn export(&self) {
+        for (name, value) in self.env_vars() {
+            if std::env::var_os(name).is_none() {
+                std::env::set_var(name, value);
+            }
+        }
+    }
+
+    /// The template for the target, unless the bundle sets one
+    pub fn template(&self, target: Target) -> Option<&Template> {
+        self.templates.get(&target.to_string())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not read `{}`", path.display())]
+    CannotRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Could not parse `{}`: {}", path.display(), source)]
+    CannotParse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_become_environment_variables() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        assert_eq!(Config::read(&path).unwrap(), Config::default());
+
+        std::fs::write(
+            &path,
+            r#"
+cache_dir = "/var/cache/kbdgen"
+output_layout = "flat"
+
+[signing]
+team_id = "ABCDE12345"
+
+[templates.android]
+git = "https://example.com/keyboard-android"
+tag = "v1.2.0"
+"#,
+        )
+        .unwrap();
+        let config = Config::read(&path).unwrap();
+        assert_eq!(
+            config.env_vars(),
+            vec![
+                (OUTPUT_LAYOUT_VAR, "flat".into()),
+                (CACHE_DIR_VAR, "/var/cache/kbdgen".into()),
+                ("TEAM_ID", "ABCDE12345".into()),
+                ("DEVELOPER_TEAM_ID", "ABCDE12345".into()),
+            ]
+        );
+        assert_eq!(
+            config.template(Target::Android).unwrap().tag.as_deref(),
+            Some("v1.2.0")
+        );
+        assert!(config.template(Target::Ios).is_none());
+
+        std::fs::write(&path, "output = \"out\"\n").unwrap();
+        assert!(matches!(
+            Config::read(&path),
+            Err(Error::CannotParse { .. })
+        ));
+    }
+}
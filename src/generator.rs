@@ -0,0 +1,298 @@
+//! What each backend writes to the output directory
+//!
+//! Generators declare the paths they own, so that commands like `kbdgen clean`
+//! can remove the output of one target without knowing how it is built, and
+//! without touching anything a generator did not write. Paths are relative to
+//! the output directory, and `*` in a path matches any part of a file name.
+
+use crate::{
+    cli::to_xkb::xkb_path,
+    output_layout::{layout_path, LayoutFile},
+    ProjectBundle, Target,
+};
+use std::path::{Path, PathBuf};
+
+pub trait Generator: Sync {
+    fn target(&self) -> Target;
+
+    /// The paths written for a single layout of the bundle
+    fn layout_outputs(&self, _bundle: &ProjectBundle, _layout: &str) -> Vec<String> {
+        vec![]
+    }
+
+    /// The paths written for the bundle as a whole, like installers and app
+    /// projects with every layout in them
+    fn bundle_outputs(&self) -> Vec<String> {
+        vec![]
+    }
+
+    /// Directories outside the output directory that only this generator uses
+    fn caches(&self, _bundle: &ProjectBundle) -> Vec<PathBuf> {
+        vec![]
+    }
+}
+
+struct Android;
+struct Ios;
+struct Mac;
+struct Win;
+struct Chrome;
+struct X11;
+
+impl Generator for Android {
+    fn target(&self) -> Target {
+        Target::Android
+    }
+
+    fn bundle_outputs(&self) -> Vec<String> {
+        vec![
+            "deps".into(),
+            "kcm".into(),
+            "strings/res".into(),
+            "template/android".into(),
+            "*.apk".into(),
+        ]
+    }
+
+    fn caches(&self, bundle: &ProjectBundle) -> Vec<PathBuf> {
+        template_caches(bundle, Target::Android)
+    }
+}
+
+impl Generator for Ios {
+    fn target(&self) -> Target {
+        Target::Ios
+    }
+
+    fn bundle_outputs(&self) -> Vec<String> {
+        vec![
+            "ios-build".into(),
+            "strings/*.lproj".into(),
+            "template/ios".into(),
+        ]
+    }
+
+    fn caches(&self, bundle: &ProjectBundle) -> Vec<PathBuf> {
+        template_caches(bundle, Target::Ios)
+    }
+}
+
+impl Generator for Mac {
+    fn target(&self) -> Target {
+        Target::Mac
+    }
+
+    fn layout_outputs(&self, _bundle: &ProjectBundle, layout: &str) -> Vec<String> {
+        vec![format!("{}.icns", layout)]
+    }
+
+    fn bundle_outputs(&self) -> Vec<String> {
+        vec!["*.bundle".into(), "*.pkg".into()]
+    }
+}
+
+impl Generator for Win {
+    fn target(&self) -> Target {
+        Target::Win
+    }
+
+    /// The `.klc` of each keyboard DLL of the layout
+    fn layout_outputs(&self, bundle: &ProjectBundle, layout: &str) -> Vec<String> {
+        bundle
+            .win_ids()
+            .into_iter()
+            .filter(|id| id.layout == layout)
+            .map(|id| pattern(&klc_path(layout, &format!("kbd{}", id.id))))
+            .collect()
+    }
+
+    fn bundle_outputs(&self) -> Vec<String> {
+        vec![
+            "i386".into(),
+            "amd64".into(),
+            "arm64".into(),
+            "wow64".into(),
+            "nlp".into(),
+            "kbdi.exe".into(),
+            "install.*.iss".into(),
+            "keyboard-*_windows*.exe".into(),
+        ]
+    }
+}
+
+impl Generator for Chrome {
+    fn target(&self) -> Target {
+        Target::Chrome
+    }
+
+    fn bundle_outputs(&self) -> Vec<String> {
+        vec!["chrome-build".into(), "*.zip".into(), "*.crx".into()]
+    }
+}
+
+impl Generator for X11 {
+    fn target(&self) -> Target {
+        Target::X11
+    }
+
+    fn layout_outputs(&self, _bundle: &ProjectBundle, layout: &str) -> Vec<String> {
+        vec![pattern(&xkb_path(layout))]
+    }
+
+    fn bundle_outputs(&self) -> Vec<String> {
+        vec!["*.xkb".into(), "*.xcompose".into()]
+    }
+}
+
+/// Where win.py writes the `.klc` of the keyboard DLL `dll` of a layout:
+/// named like the DLL, unless an output layout is set
+fn klc_path(layout: &str, dll: &str) -> PathBuf {
+    let file = LayoutFile {
+        target: "win",
+        layout,
+        part: Some(dll),
+        extension: "klc",
+    };
+    layout_path(&file, || PathBuf::from(format!("{}.klc", dll)))
+}
+
+/// `path` as a pattern for `expand`
+fn pattern(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+static GENERATORS: &[&dyn Generator] = &[&Android, &Ios, &Mac, &Win, &Chrome, &X11];
+
+/// Every generator, one per target that can be built
+pub fn generators() -> &'static [&'static dyn Generator] {
+    GENERATORS
+}
+
+/// The generator for the target, if it is one that can be built
+pub fn generator(target: Target) -> Option<&'static dyn Generator> {
+    GENERATORS.iter().copied().find(|g| g.target() == target)
+}
+
+fn template_caches(bundle: &ProjectBundle, target: Target) -> Vec<PathBuf> {
+    crate::templates::template_for(bundle, target)
+        .and_then(crate::templates::cache_dir)
+        .into_iter()
+        .collect()
+}
+
+/// The existing paths in `dir` that match the pattern
+pub fn expand(dir: &Path, pattern: &str) -> Vec<PathBuf> {
+    let mut paths = vec![dir.to_path_buf()];
+    for component in pattern.split('/') {
+        paths = paths
+            .into_iter()
+            .flat_map(|path| {
+                if !component.contains('*') {
+                    let path = path.join(component);
+                    return if path.exists() { vec![path] } else { vec![] };
+                }
+                let mut matches = std::fs::read_dir(&path)
+                    .into_iter()
+                    .flatten()
+                    .flatten()
+                    .filter(|entry| wildcard_match(component, &entry.file_name().to_string_lossy()))
+                    .map(|entry| entry.path())
+                    .collect::<Vec<_>>();
+                matches.sort();
+                matches
+            })
+            .collect();
+    }
+    paths
+}
+
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, rest)) => {
+            name.starts_with(prefix)
+                && (prefix.len()..=name.len())
+                    .filter(|&i| name.is_char_boundary(i))
+                    .any(|i| wildcard_match(rest, &name[i..]))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use strum::IntoEnumIterator;
+
+    #[test]
+    fn wildcards_match_parts_of_names() {
+        assert!(wildcard_match("*.apk", "sme-1.0_release.apk"));
+        assert!(wildcard_match(
+            "keyboard-*_windows*.exe",
+            "keyboard-sme_1.0_windows7.exe"
+        ));
+        assert!(wildcard_match("install.*.iss", "install.all.iss"));
+        assert!(!wildcard_match("*.apk", "sme.apk.txt"));
+        assert!(!wildcard_match("deps", "deps2"));
+    }
+
+    #[test]
+    fn only_abstract_targets_have_no_generator() {
+        for target in Target::iter() {
+            assert_eq!(
+                generator(target).is_none(),
+                matches!(target, Target::Desktop | Target::Mobile),
+                "{}",
+                target
+            );
+        }
+    }
+
+    #[test]
+    fn klc_files_are_named_like_the_windows_generator_names_them() {
+        let mut bundle: ProjectBundle = Default::default();
+        for (name, yaml) in [
+            ("se", "targets:\n  win:\n    locale: se\n    id: sme01\n"),
+            ("sma-NO", ""),
+        ] {
+            let layout = format!(
+                "displayNames:\n  en: Test\nmodes:\n  win:\n    default: a\n{}",
+                yaml
+            );
+            bundle
+                .layouts
+                .insert(name.to_string(), serde_yaml::from_str(&layout).unwrap());
+        }
+
+        let win = generator(Target::Win).unwrap();
+        // `_klc_get_name` in win.py: `kbd` and the id, or the first five
+        // letters, digits and hyphens of the layout name
+        assert_eq!(win.layout_outputs(&bundle, "se"), vec!["kbdsme01.klc"]);
+        assert_eq!(win.layout_outputs(&bundle, "sma-NO"), vec!["kbdsma-N.klc"]);
+    }
+
+    #[test]
+    fn patterns_are_expanded() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("linux")).unwrap();
+        std::fs::write(dir.path().join("linux/se.xkb"), "").unwrap();
+        std::fs::write(dir.path().join("linux/fi.xkb"), "").unwrap();
+        std::fs::write(dir.path().join("se.klc"), "").unwrap();
+
+        assert_eq!(
+            expand(dir.path(), "linux/*.xkb"),
+            vec![
+                dir.path().join("linux/fi.xkb"),
+                dir.path().join("linux/se.xkb")
+            ]
+        );
+        assert_eq!(
+            expand(dir.path(), "se.klc"),
+            vec![dir.path().join("se.klc")]
+        );
+        assert!(expand(dir.path(), "fi.klc").is_empty());
+        assert!(expand(dir.path(), "missing/*.xkb").is_empty());
+    }
+}
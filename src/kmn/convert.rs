@@ -0,0 +1,505 @@
+//! Converting the common subset of Keyman keyboards to kbdgen modes
+//!
+//! Rules without context become keys of the desktop modes. A rule that
+//! outputs a `deadkey()` puts a dead key on its key, and rules with that dead
+//! key as their context become its transforms. Keyman dead keys are
+//! invisible, so each gets the character typed by the dead key and space, or
+//! a spacing accent after its name. Everything else, like rules with other
+//! contexts, is reported as skipped. Keys without rules type what they type
+//! on the US layout, as they do in Keyman.
+
+use super::{Item, KmnFile, Rule};
+use crate::{
+    bundle::{models::DesktopModes, KeyValue},
+    models::IsoKey,
+    DesktopKeyMap, Modifier, ModifierSet,
+};
+use std::collections::BTreeMap;
+use strum::IntoEnumIterator;
+
+/// Keyman's names for the ISO keys, in the order of `IsoKey`
+const KEY_NAMES: [&str; 48] = [
+    "K_BKQUOTE",
+    "K_1",
+    "K_2",
+    "K_3",
+    "K_4",
+    "K_5",
+    "K_6",
+    "K_7",
+    "K_8",
+    "K_9",
+    "K_0",
+    "K_HYPHEN",
+    "K_EQUAL",
+    "K_Q",
+    "K_W",
+    "K_E",
+    "K_R",
+    "K_T",
+    "K_Y",
+    "K_U",
+    "K_I",
+    "K_O",
+    "K_P",
+    "K_LBRKT",
+    "K_RBRKT",
+    "K_A",
+    "K_S",
+    "K_D",
+    "K_F",
+    "K_G",
+    "K_H",
+    "K_J",
+    "K_K",
+    "K_L",
+    "K_COLON",
+    "K_QUOTE",
+    "K_BKSLASH",
+    "K_oE2",
+    "K_Z",
+    "K_X",
+    "K_C",
+    "K_V",
+    "K_B",
+    "K_N",
+    "K_M",
+    "K_COMMA",
+    "K_PERIOD",
+    "K_SLASH",
+];
+
+/// What the US layout types on the ISO keys, which quoted characters in the
+/// key of a rule stand for
+const US_DEFAULT: &str = r"`1234567890-=qwertyuiop[]asdfghjkl;'\\zxcvbnm,./";
+const US_SHIFT: &str = r#"~!@#$%^&*()_+QWERTYUIOP{}ASDFGHJKL:"||ZXCVBNM<>?"#;
+
+/// Spacing accents for common dead key names
+const ACCENTS: &[(&str, &str)] = &[
+    ("acute", "´"),
+    ("grave", "`"),
+    ("circumflex", "^"),
+    ("tilde", "~"),
+    ("diaeresis", "¨"),
+    ("umlaut", "¨"),
+    ("caron", "ˇ"),
+    ("cedilla", "¸"),
+    ("ring", "˚"),
+    ("macron", "¯"),
+    ("breve", "˘"),
+    ("ogonek", "˛"),
+    ("dot", "˙"),
+    ("doubleacute", "˝"),
+];
+
+/// A rule that was not imported, and why
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Skipped {
+    pub line: usize,
+    pub reason: String,
+}
+
+/// What a Keyman keyboard adds to a layout
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Conversion {
+    pub modes: DesktopModes,
+    /// Dead keys by mode name
+    pub dead_keys: BTreeMap<String, Vec<String>>,
+    pub transforms: BTreeMap<String, BTreeMap<String, String>>,
+    pub skipped: Vec<Skipped>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Press {
+    Key(ModifierSet, IsoKey),
+    Space,
+}
+
+fn mode_name(mode: &ModifierSet) -> String {
+    mode.to_string()
+}
+
+fn press(item: &Item) -> Result<Press, String> {
+    match item {
+        Item::Key { modifiers, key } => {
+            let mut mode = vec![];
+            for modifier in modifiers {
+                match modifier.as_str() {
+                    "SHIFT" => mode.push(Modifier::Shift),
+                    "CAPS" => mode.push(Modifier::Caps),
+                    // Rules without CAPS apply whether caps lock is on or not
+                    "NCAPS" => {}
+                    "ALT" | "LALT" | "RALT" | "ALTGR" => mode.push(Modifier::Alt),
+                    "CTRL" | "LCTRL" | "RCTRL" => mode.push(Modifier::Ctrl),
+                    other => return Err(format!("unsupported modifier `{}`", other)),
+                }
+            }
+            // Ctrl and Alt together are AltGr on Windows
+            if mode.contains(&Modifier::Alt) {
+                mode.retain(|&m| m != Modifier::Ctrl);
+            }
+
+            if key.eq_ignore_ascii_case("K_SPACE") && mode.is_empty() {
+                return Ok(Press::Space);
+            }
+            let index = KEY_NAMES
+                .iter()
+                .position(|name| name.eq_ignore_ascii_case(key))
+                .ok_or_else(|| format!("unsupported key `{}`", key))?;
+            let iso_key = IsoKey::iter().nth(index).expect("a name for every key");
+            Ok(Press::Key(mode.into_iter().collect(), iso_key))
+        }
+        Item::Text(text) if text == " " => Ok(Press::Space),
+        Item::Text(text) => {
+            let c = text.chars().next().filter(|_| text.chars().count() == 1);
+            let c = c.ok_or_else(|| format!("`{}` is not a single key", text))?;
+            for (layer, modifiers) in [(US_DEFAULT, vec![]), (US_SHIFT, vec![Modifier::Shift])] {
+                if let Some(index) = layer.chars().position(|x| x == c) {
+                    let iso_key = IsoKey::iter()
+                        .nth(index)
+                        .expect("a character for every key");
+                    return Ok(Press::Key(modifiers.into_iter().collect(), iso_key));
+                }
+            }
+            Err(format!("`{}` is not on the US layout", text))
+        }
+        other => Err(format!("unsupported key `{:?}`", other)),
+    }
+}
+
+enum Output {
+    Text(String),
+    DeadKey(String),
+}
+
+impl KmnFile {
+    /// The presses of a rule's key and, when the key is `any()`, the
+    /// position in the store each press was taken from
+    fn presses(&self, key: &Item) -> Result<Vec<(Press, Option<usize>)>, String> {
+        match key {
+            Item::Any(store) => {
+                let items = self
+                    .store_items(store)
+                    .ok_or_else(|| format!("unknown store `{}`", store))?;
+                items
+                    .iter()
+                    .enumerate()
+                    .map(|(index, item)| Ok((press(item)?, Some(index))))
+                    .collect()
+            }
+            key => Ok(vec![(press(key)?, None)]),
+        }
+    }
+
+    fn output(&self, items: &[Item], index: Option<usize>) -> Result<Output, String> {
+        if let [Item::DeadKey(name)] = items {
+            return Ok(Output::DeadKey(name.clone()));
+        }
+
+        let mut text = String::new();
+        for item in items {
+            match item {
+                Item::Text(value) => text.push_str(value),
+                Item::Outs(store) => {
+                    let items = self
+                        .store_items(store)
+                        .ok_or_else(|| format!("unknown store `{}`", store))?;
+                    for item in items {
+                        match item {
+                            Item::Text(value) => text.push_str(&value),
+                            _ => return Err(format!("store `{}` is not text", store)),
+                        }
+                    }
+                }
+                Item::Index { store, .. } => {
+                    let index = index.ok_or("`index()` without `any()`")?;
+                    let items = self
+                        .store_items(store)
+                        .ok_or_else(|| format!("unknown store `{}`", store))?;
+                    match items.get(index) {
+                        Some(Item::Text(value)) => text.push_str(value),
+                        _ => return Err(format!("store `{}` has no text at {}", store, index + 1)),
+                    }
+                }
+                Item::Beep | Item::Nul => {}
+                other => return Err(format!("unsupported output `{:?}`", other)),
+            }
+        }
+        Ok(Output::Text(text))
+    }
+
+    /// The rules of the groups that handle key presses, and the others as
+    /// skipped
+    fn key_rules(&self, skipped: &mut Vec<Skipped>) -> Vec<&Rule> {
+        let mut rules = vec![];
+        for group in &self.groups {
+            if group.using_keys {
+                rules.extend(&group.rules);
+            } else {
+                skipped.extend(group.rules.iter().map(|rule| Skipped {
+                    line: rule.line,
+                    reason: format!("group `{}` does not use keys", group.name),
+                }));
+            }
+        }
+        rules
+    }
+
+    pub fn convert(&self) -> Conversion {
+        let mut conversion = Conversion::default();
+        let mut skipped = vec![];
+        let rules = self.key_rules(&mut skipped);
+        let mut skip = |rule: &Rule, reason: String| {
+            skipped.push(Skipped {
+                line: rule.line,
+                reason,
+            })
+        };
+
+        let mut keys: BTreeMap<ModifierSet, BTreeMap<IsoKey, String>> = BTreeMap::new();
+        let mut dead_key_presses: Vec<(String, ModifierSet, IsoKey)> = vec![];
+        let mut dead_key_rules = vec![];
+
+        for &rule in &rules {
+            let key = match &rule.key {
+                Some(key) => key,
+                None => {
+                    skip(rule, "no key".to_string());
+                    continue;
+                }
+            };
+            match rule.context.as_slice() {
+                [] => {}
+                [Item::DeadKey(name)] => {
+                    dead_key_rules.push((name, rule));
+                    continue;
+                }
+                _ => {
+                    skip(rule, "only dead keys are supported as context".to_string());
+                    continue;
+                }
+            }
+
+            let presses = match self.presses(key) {
+                Ok(presses) => presses,
+                Err(reason) => {
+                    skip(rule, reason);
+                    continue;
+                }
+            };
+            for (press, index) in presses {
+                let (mode, iso_key) = match press {
+                    Press::Key(mode, iso_key) => (mode, iso_key),
+                    Press::Space => {
+                        skip(rule, "space is not part of the modes".to_string());
+                        continue;
+                    }
+                };
+                // Keyman uses the first rule that matches
+                let taken = matches!(keys.get(&mode), Some(keys) if keys.contains_key(&iso_key))
+                    || dead_key_presses
+                        .iter()
+                        .any(|(_, m, k)| *m == mode && *k == iso_key);
+                if taken {
+                    continue;
+                }
+                match self.output(&rule.output, index) {
+                    Ok(Output::Text(text)) if text.is_empty() => {}
+                    Ok(Output::Text(text)) => {
+                        keys.entry(mode).or_default().insert(iso_key, text);
+                    }
+                    Ok(Output::DeadKey(name)) => dead_key_presses.push((name, mode, iso_key)),
+                    Err(reason) => skip(rule, reason),
+                }
+            }
+        }
+
+        // Dead keys are shown as what they type before space, or an accent
+        // after their name
+        let mut symbols = BTreeMap::new();
+        for (name, rule) in &dead_key_rules {
+            if let Some(key) = &rule.key {
+                if matches!(press(key), Ok(Press::Space)) {
+                    if let Ok(Output::Text(text)) = self.output(&rule.output, None) {
+                        symbols.entry(name.as_str()).or_insert(text);
+                    }
+                }
+            }
+        }
+        for (name, _, _) in &dead_key_presses {
+            if symbols.contains_key(name.as_str()) {
+                continue;
+            }
+            let accent = ACCENTS
+                .iter()
+                .find(|(accent, _)| name.to_ascii_lowercase().contains(accent))
+                .map(|(_, symbol)| symbol.to_string())
+                .or_else(|| Some(name.clone()).filter(|name| name.chars().count() == 1));
+            if let Some(symbol) = accent {
+                symbols.insert(name.as_str(), symbol);
+            }
+        }
+
+        for (name, mode, iso_key) in &dead_key_presses {
+            match symbols.get(name.as_str()) {
+                Some(symbol) => {
+                    keys.entry(mode.clone())
+                        .or_default()
+                        .insert(*iso_key, symbol.clone());
+                    let dead_keys = conversion.dead_keys.entry(mode_name(mode)).or_default();
+                    if !dead_keys.contains(symbol) {
+                        dead_keys.push(symbol.clone());
+                    }
+                }
+                None => {
+                    let line = rules
+                        .iter()
+                        .find(|rule| rule.output == [Item::DeadKey(name.clone())])
+                        .map_or(0, |rule| rule.line);
+                    skipped.push(Skipped {
+                        line,
+                        reason: format!(
+                            "no character for dead key `{}`, which types nothing before space",
+                            name
+                        ),
+                    });
+                }
+            }
+        }
+
+        // Keys without rules type what they type on the US layout
+        for (layer, modifiers) in [(US_DEFAULT, vec![]), (US_SHIFT, vec![Modifier::Shift])] {
+            let mode_keys = keys.entry(modifiers.into_iter().collect()).or_default();
+            for (iso_key, c) in IsoKey::iter().zip(layer.chars()) {
+                mode_keys.entry(iso_key).or_insert_with(|| c.to_string());
+            }
+        }
+
+        for (name, rule) in dead_key_rules {
+            let symbol = match symbols.get(name.as_str()) {
+                Some(symbol) => symbol,
+                None => continue,
+            };
+            let key = rule.key.as_ref().expect("rules with a dead key have a key");
+            let presses = match self.presses(key) {
+                Ok(presses) => presses,
+                Err(reason) => {
+                    skipped.push(Skipped {
+                        line: rule.line,
+                        reason,
+                    });
+                    continue;
+                }
+            };
+            for (press, index) in presses {
+                let input = match press {
+                    Press::Space => Some(" ".to_string()),
+                    Press::Key(mode, iso_key) => {
+                        keys.get(&mode).and_then(|keys| keys.get(&iso_key)).cloned()
+                    }
+                };
+                let input = match input {
+                    Some(input) => input,
+                    None => {
+                        skipped.push(Skipped {
+                            line: rule.line,
+                            reason: "the key after the dead key types nothing".to_string(),
+                        });
+                        continue;
+                    }
+                };
+                match self.output(&rule.output, index) {
+                    Ok(Output::Text(text)) => {
+                        conversion
+                            .transforms
+                            .entry(symbol.clone())
+                            .or_default()
+                            .entry(input)
+                            .or_insert(text);
+                    }
+                    Ok(Output::DeadKey(_)) => skipped.push(Skipped {
+                        line: rule.line,
+                        reason: "chained dead keys are not supported".to_string(),
+                    }),
+                    Err(reason) => skipped.push(Skipped {
+                        line: rule.line,
+                        reason,
+                    }),
+                }
+            }
+        }
+
+        conversion.modes = DesktopModes(
+            keys.into_iter()
+                .map(|(mode, keys)| {
+                    let keys = keys
+                        .into_iter()
+                        .map(|(key, value)| (key, KeyValue::Symbol(value)))
+                        .collect();
+                    (mode_name(&mode), DesktopKeyMap(keys))
+                })
+                .collect(),
+        );
+        skipped.sort_by_key(|skipped| skipped.line);
+        conversion.skipped = skipped;
+        conversion
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_dead_keys_and_transforms_are_converted() {
+        let file: KmnFile = r#"
+store(&NAME) 'Test'
+store(vowels) 'aeAE'
+store(acuted) 'áéÁÉ'
+begin Unicode > use(main)
+
+group(main) using keys
++ 'q' > 'ŧ'
++ [SHIFT K_Q] > 'Ŧ'
++ [RALT K_E] > '€'
++ [K_EQUAL] > dk(acute)
+dk(acute) + any(vowels) > index(acuted, 2)
+dk(acute) + [K_SPACE] > U+00B4
++ [K_BKSLASH] > dk(nameless)
+'n' + '~' > 'ñ'
+"#
+        .parse()
+        .unwrap();
+        let conversion = file.convert();
+
+        let default = &conversion.modes.0["default"];
+        assert_eq!(default.get_string(IsoKey::D01).as_deref(), Some("ŧ"));
+        assert_eq!(default.get_string(IsoKey::E12).as_deref(), Some("´"));
+        assert_eq!(default.get_string(IsoKey::C01).as_deref(), Some("a"));
+        assert_eq!(
+            conversion.modes.0["shift"]
+                .get_string(IsoKey::D01)
+                .as_deref(),
+            Some("Ŧ")
+        );
+        assert_eq!(
+            conversion.modes.0["alt"].get_string(IsoKey::D03).as_deref(),
+            Some("€")
+        );
+        assert_eq!(conversion.dead_keys["default"], vec!["´".to_string()]);
+
+        let acute = &conversion.transforms["´"];
+        assert_eq!(acute["a"], "á");
+        assert_eq!(acute["E"], "É");
+        assert_eq!(acute[" "], "´");
+
+        assert_eq!(
+            conversion
+                .skipped
+                .iter()
+                .map(|skipped| skipped.line)
+                .collect::<Vec<_>>(),
+            vec![14, 15]
+        );
+    }
+}
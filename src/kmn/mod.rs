@@ -0,0 +1,416 @@
+//! Keyman keyboard source files (`.kmn`)
+//!
+//! Only the parts of the language needed to import a layout are read:
+//! stores, groups and their rules. Statements the importer has no use for,
+//! like `if()` or `platform()`, are kept as `Item::Other` so that the rules
+//! using them can be reported instead of silently dropped.
+
+use std::{collections::BTreeMap, str::FromStr};
+
+mod convert;
+pub use convert::{Conversion, Skipped};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Item {
+    /// Quoted strings and character codes like `U+0301`, `d65` or `x41`
+    Text(String),
+    /// A virtual key like `[SHIFT K_A]`, with its modifiers as written
+    Key {
+        modifiers: Vec<String>,
+        key: String,
+    },
+    Any(String),
+    /// `index(store, position)`, with the position counted from 1
+    Index {
+        store: String,
+        position: usize,
+    },
+    DeadKey(String),
+    Outs(String),
+    Context,
+    Nul,
+    Beep,
+    Use(String),
+    /// Anything else, as written
+    Other(String),
+}
+
+/// A `context + key > output` rule; rules of groups not `using keys` have no
+/// key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    /// The line the rule starts on, from 1
+    pub line: usize,
+    pub context: Vec<Item>,
+    pub key: Option<Item>,
+    pub output: Vec<Item>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Group {
+    pub name: String,
+    pub using_keys: bool,
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KmnFile {
+    /// Stores by name, system stores with their `&`, like `&NAME`
+    pub stores: BTreeMap<String, Vec<Item>>,
+    /// The group `begin Unicode` starts with
+    pub begin: Option<String>,
+    pub groups: Vec<Group>,
+}
+
+impl KmnFile {
+    /// The keyboard's name, from `store(&NAME)`
+    pub fn name(&self) -> Option<String> {
+        let items = self.stores.get("&NAME")?;
+        Some(
+            items
+                .iter()
+                .filter_map(|item| match item {
+                    Item::Text(text) => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect(),
+        )
+    }
+
+    /// The items of a store, with strings split into their characters as
+    /// `any()` and `index()` count them
+    pub fn store_items(&self, name: &str) -> Option<Vec<Item>> {
+        let items = self.stores.get(name)?;
+        Some(
+            items
+                .iter()
+                .flat_map(|item| match item {
+                    Item::Text(text) => text.chars().map(|c| Item::Text(c.to_string())).collect(),
+                    item => vec![item.clone()],
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Decodes a `.kmn` file, which is UTF-8 or, with a byte order mark, UTF-16
+pub fn decode(bytes: &[u8]) -> Result<String, Error> {
+    match bytes {
+        [0xff, 0xfe, rest @ ..] => {
+            let units = rest
+                .chunks(2)
+                .map(|pair| u16::from_le_bytes([pair[0], *pair.get(1).unwrap_or(&0)]))
+                .collect::<Vec<_>>();
+            String::from_utf16(&units).map_err(|_| Error::InvalidEncoding)
+        }
+        [0xef, 0xbb, 0xbf, rest @ ..] => {
+            String::from_utf8(rest.to_vec()).map_err(|_| Error::InvalidEncoding)
+        }
+        _ => String::from_utf8(bytes.to_vec()).map_err(|_| Error::InvalidEncoding),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Text(String),
+    Key(Vec<String>),
+    Call(String, String),
+    Word(String),
+    Plus,
+    Gt,
+}
+
+/// Logical lines with their line numbers, joining lines that end in `\`
+fn logical_lines(source: &str) -> Vec<(usize, String)> {
+    let mut lines = vec![];
+    let mut pending: Option<(usize, String)> = None;
+
+    for (index, line) in source.lines().enumerate() {
+        let (start, mut text) = pending.take().unwrap_or((index + 1, String::new()));
+        text.push_str(line);
+        let continued = text.trim_end().ends_with('\\');
+        if continued {
+            let end = text.trim_end().len() - 1;
+            text.truncate(end);
+            text.push(' ');
+            pending = Some((start, text));
+        } else {
+            lines.push((start, text));
+        }
+    }
+    lines.extend(pending);
+    lines
+}
+
+fn tokenize(line: &str, number: usize) -> Result<Vec<Token>, Error> {
+    let unterminated = || Error::Unterminated { line: number };
+    let mut tokens = vec![];
+    let mut rest = line.trim_start_matches('\u{feff}');
+
+    loop {
+        rest = rest.trim_start();
+        let c = match rest.chars().next() {
+            Some(c) => c,
+            None => break,
+        };
+
+        match c {
+            '\'' | '"' => {
+                let end = rest[1..].find(c).ok_or_else(unterminated)? + 1;
+                tokens.push(Token::Text(rest[1..end].to_string()));
+                rest = &rest[end + 1..];
+            }
+            '[' => {
+                let end = rest.find(']').ok_or_else(unterminated)?;
+                let parts = rest[1..end].split_whitespace().map(str::to_string);
+                tokens.push(Token::Key(parts.collect()));
+                rest = &rest[end + 1..];
+            }
+            '+' => {
+                tokens.push(Token::Plus);
+                rest = &rest[1..];
+            }
+            '>' => {
+                tokens.push(Token::Gt);
+                rest = &rest[1..];
+            }
+            _ => {
+                // `+` ends a word, except in character codes like `U+0301`
+                let start = if rest.starts_with("U+") || rest.starts_with("u+") {
+                    2
+                } else {
+                    0
+                };
+                let end = rest[start..]
+                    .find(|c: char| c.is_whitespace() || "'\"[+>(".contains(c))
+                    .map_or(rest.len(), |end| start + end);
+                let word = &rest[..end];
+                rest = &rest[end..];
+
+                // A comment runs to the end of the line
+                if word.eq_ignore_ascii_case("c") {
+                    break;
+                }
+
+                let after = rest.trim_start();
+                if after.starts_with('(') {
+                    let close = after.find(')').ok_or_else(unterminated)?;
+                    tokens.push(Token::Call(
+                        word.to_ascii_lowercase(),
+                        after[1..close].trim().to_string(),
+                    ));
+                    rest = &after[close + 1..];
+                } else if word.is_empty() {
+                    // A stray `(`
+                    rest = &rest[1..];
+                } else {
+                    tokens.push(Token::Word(word.to_string()));
+                }
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// The character of a code like `U+0301`, `d769` or `x301`
+fn character_code(word: &str) -> Option<char> {
+    let (digits, radix) = if let Some(hex) = word.strip_prefix("U+").or(word.strip_prefix("u+")) {
+        (hex, 16)
+    } else if let Some(decimal) = word.strip_prefix('d').or(word.strip_prefix('D')) {
+        (decimal, 10)
+    } else if let Some(hex) = word.strip_prefix('x').or(word.strip_prefix('X')) {
+        (hex, 16)
+    } else {
+        return None;
+    };
+    if digits.is_empty() {
+        return None;
+    }
+    std::char::from_u32(u32::from_str_radix(digits, radix).ok()?)
+}
+
+fn item(token: Token) -> Item {
+    match token {
+        Token::Text(text) => Item::Text(text),
+        Token::Key(mut parts) => {
+            let key = parts.pop().unwrap_or_default();
+            Item::Key {
+                modifiers: parts.iter().map(|m| m.to_ascii_uppercase()).collect(),
+                key,
+            }
+        }
+        Token::Call(name, args) => match name.as_str() {
+            "any" => Item::Any(args),
+            "deadkey" | "dk" => Item::DeadKey(args),
+            "outs" => Item::Outs(args),
+            "use" => Item::Use(args),
+            "index" => {
+                let mut args = args.split(',').map(str::trim);
+                let store = args.next().unwrap_or_default().to_string();
+                match args.next().and_then(|position| position.parse().ok()) {
+                    Some(position) => Item::Index { store, position },
+                    None => Item::Other(format!("index({})", store)),
+                }
+            }
+            _ => Item::Other(format!("{}({})", name, args)),
+        },
+        Token::Word(word) => match word.to_ascii_lowercase().as_str() {
+            "context" => Item::Context,
+            "nul" => Item::Nul,
+            "beep" => Item::Beep,
+            _ => match character_code(&word) {
+                Some(c) => Item::Text(c.to_string()),
+                None => Item::Other(word),
+            },
+        },
+        Token::Plus => Item::Other("+".to_string()),
+        Token::Gt => Item::Other(">".to_string()),
+    }
+}
+
+impl FromStr for KmnFile {
+    type Err = Error;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let mut file = KmnFile::default();
+
+        for (number, line) in logical_lines(source) {
+            let mut tokens = tokenize(&line, number)?;
+            if tokens.is_empty() {
+                continue;
+            }
+
+            match tokens.first().cloned() {
+                Some(Token::Call(name, args)) if name == "store" => {
+                    let items = tokens.drain(1..).map(item).collect();
+                    file.stores.insert(args, items);
+                }
+                Some(Token::Call(name, args)) if name == "group" => {
+                    let using_keys = tokens[1..]
+                        .iter()
+                        .any(|t| matches!(t, Token::Word(w) if w.eq_ignore_ascii_case("keys")));
+                    file.groups.push(Group {
+                        name: args,
+                        using_keys,
+                        rules: vec![],
+                    });
+                }
+                Some(Token::Word(word)) if word.eq_ignore_ascii_case("begin") => {
+                    let unicode = tokens
+                        .iter()
+                        .any(|t| matches!(t, Token::Word(w) if w.eq_ignore_ascii_case("unicode")));
+                    let group = tokens.iter().find_map(|t| match t {
+                        Token::Call(name, args) if name == "use" => Some(args.clone()),
+                        _ => None,
+                    });
+                    if unicode || file.begin.is_none() {
+                        file.begin = group;
+                    }
+                }
+                _ => {
+                    let gt = match tokens.iter().position(|t| *t == Token::Gt) {
+                        Some(gt) => gt,
+                        // Header lines of old keyboards, like `NAME "..."`
+                        None => continue,
+                    };
+                    let output = tokens.split_off(gt + 1).into_iter().map(item).collect();
+                    tokens.pop();
+                    let key = match tokens.iter().position(|t| *t == Token::Plus) {
+                        Some(plus) => {
+                            let mut key = tokens.split_off(plus);
+                            key.remove(0);
+                            if key.len() != 1 {
+                                return Err(Error::InvalidRule { line: number });
+                            }
+                            key.pop().map(item)
+                        }
+                        None => None,
+                    };
+                    let group = file
+                        .groups
+                        .last_mut()
+                        .ok_or(Error::RuleOutsideGroup { line: number })?;
+                    group.rules.push(Rule {
+                        line: number,
+                        context: tokens.into_iter().map(item).collect(),
+                        key,
+                        output,
+                    });
+                }
+            }
+        }
+
+        Ok(file)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("The file is neither UTF-8 nor UTF-16 with a byte order mark")]
+    InvalidEncoding,
+
+    #[error("Unterminated string, key or parenthesis on line {line}")]
+    Unterminated { line: usize },
+
+    #[error("Expected a single key after `+` on line {line}")]
+    InvalidRule { line: usize },
+
+    #[error("Rule on line {line} is not in a group")]
+    RuleOutsideGroup { line: usize },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_and_stores_are_read() {
+        let file: KmnFile = r#"
+c A comment
+store(&NAME) 'Test ' "Keyboard"
+store(vowels) 'ae' U+0131
+begin Unicode > use(main)
+
+group(main) using keys
++ [SHIFT K_A] > 'A' c typed with shift
+dk(acute) + any(vowels) > \
+    index(accented, 2)
+'a' + "'" > context beep
+"#
+        .parse()
+        .unwrap();
+
+        assert_eq!(file.name().as_deref(), Some("Test Keyboard"));
+        assert_eq!(file.begin.as_deref(), Some("main"));
+        assert_eq!(
+            file.store_items("vowels").unwrap(),
+            vec![
+                Item::Text("a".into()),
+                Item::Text("e".into()),
+                Item::Text("ı".into())
+            ]
+        );
+
+        let rules = &file.groups[0].rules;
+        assert!(file.groups[0].using_keys);
+        assert_eq!(
+            rules[0].key,
+            Some(Item::Key {
+                modifiers: vec!["SHIFT".into()],
+                key: "K_A".into()
+            })
+        );
+        assert_eq!(rules[0].output, vec![Item::Text("A".into())]);
+        assert_eq!(rules[1].line, 9);
+        assert_eq!(rules[1].context, vec![Item::DeadKey("acute".into())]);
+        assert_eq!(
+            rules[1].output,
+            vec![Item::Index {
+                store: "accented".into(),
+                position: 2
+            }]
+        );
+        assert_eq!(rules[2].output, vec![Item::Context, Item::Beep]);
+    }
+}
@@ -1,8 +1,24 @@
+pub mod build_report;
 pub mod bundle;
+pub mod chrome;
 pub mod cldr;
 pub mod cli;
+pub mod codes;
+pub mod config;
+pub mod generator;
+pub mod kmn;
+pub mod loss;
+pub mod lsp;
 pub mod m17n_mim;
+pub mod macos;
+pub mod output_layout;
+pub mod png;
+pub mod staging;
+pub mod stamp;
+pub mod templates;
+pub mod workspace;
 pub mod xkb;
+pub mod xml_writer;
 
 pub use bundle::*;
 
@@ -0,0 +1,161 @@
+//! What generators could not represent exactly
+//!
+//! Every target has things it cannot express: Windows only takes four code
+//! points per key, XKB has no reordering, Android cannot write characters
+//! outside the BMP to a `.kcm`. Instead of a free-form warning, a generator
+//! reports each key or rule it drops or changes as a [`Loss`] with [`report`].
+//!
+//! A loss is logged as a warning on the `kbdgen::loss` target, with the loss
+//! as JSON for its message, so that the Python generators can report losses
+//! through their logger too (see `report_loss` in `kbdgen.gen.base`).
+//! [`crate::build_report::WarningLog`] turns them back into losses, which end
+//! up in the build report and the `--json` output of `kbdgen build`.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The log target losses are reported on
+pub const LOG_TARGET: &str = "kbdgen::loss";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LossKind {
+    /// Left out of the output
+    Dropped,
+    /// In the output, but not as the layout has it
+    Altered,
+}
+
+/// A key or rule that a generator could not represent exactly
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Loss {
+    /// The generator, like `win` or `m17n`
+    pub target: String,
+    pub layout: String,
+    /// What was lost, like ``transform `^a` `` or ``key `E00` in `shift` ``
+    pub item: String,
+    pub kind: LossKind,
+    pub reason: String,
+}
+
+impl Loss {
+    pub fn dropped(target: &str, layout: &str, item: impl Into<String>) -> Loss {
+        Loss {
+            target: target.to_string(),
+            layout: layout.to_string(),
+            item: item.into(),
+            kind: LossKind::Dropped,
+            reason: String::new(),
+        }
+    }
+
+    pub fn altered(target: &str, layout: &str, item: impl Into<String>) -> Loss {
+        Loss {
+            kind: LossKind::Altered,
+            ..Loss::dropped(target, layout, item)
+        }
+    }
+
+    pub fn because(self, reason: impl Into<String>) -> Loss {
+        Loss {
+            reason: reason.into(),
+            ..self
+        }
+    }
+
+    /// The loss in a log message from [`report`], if it is one
+    pub fn from_message(message: &str) -> Option<Loss> {
+        serde_json::from_str(message).ok()
+    }
+}
+
+/// Like `se: transform `^a` dropped for win: …`
+impl fmt::Display for Loss {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            LossKind::Dropped => "dropped",
+            LossKind::Altered => "altered",
+        };
+        write!(
+            f,
+            "{}: {} {} for {}",
+            self.layout, self.item, kind, self.target
+        )?;
+        if !self.reason.is_empty() {
+            write!(f, ": {}", self.reason)?;
+        }
+        Ok(())
+    }
+}
+
+/// Logs the loss, for the build report to pick up
+pub fn report(loss: Loss) {
+    let message = serde_json::to_string(&loss).expect("losses serialize");
+    log::warn!(target: LOG_TARGET, "{}", message);
+}
+
+/// The losses of a build, in the order they were reported
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct LossReport(pub Vec<Loss>);
+
+impl LossReport {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Loss> {
+        self.0.iter()
+    }
+
+    pub fn count(&self, kind: LossKind) -> usize {
+        self.0.iter().filter(|loss| loss.kind == kind).count()
+    }
+
+    /// Like `3 keys or rules in 2 layouts could not be represented: 2
+    /// dropped, 1 altered`
+    pub fn summary(&self) -> String {
+        let mut layouts = self.0.iter().map(|loss| &loss.layout).collect::<Vec<_>>();
+        layouts.sort();
+        layouts.dedup();
+        let s = |n: usize| if n == 1 { "" } else { "s" };
+        format!(
+            "{} key{} or rule{} in {} layout{} could not be represented: {} dropped, {} altered",
+            self.0.len(),
+            s(self.0.len()),
+            s(self.0.len()),
+            layouts.len(),
+            s(layouts.len()),
+            self.count(LossKind::Dropped),
+            self.count(LossKind::Altered),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn losses_survive_the_log_message() {
+        let loss = Loss::dropped("win", "se", "transform `^q`")
+            .because("Windows dead keys only type a single character");
+        let message = serde_json::to_string(&loss).unwrap();
+        assert_eq!(Loss::from_message(&message), Some(loss.clone()));
+        assert_eq!(Loss::from_message("reorders are ignored"), None);
+        assert_eq!(
+            loss.to_string(),
+            "se: transform `^q` dropped for win: Windows dead keys only type a single character"
+        );
+
+        let report = LossReport(vec![
+            loss,
+            Loss::altered("x11", "se", "key `E00`"),
+            Loss::dropped("x11", "smj", "reorders"),
+        ]);
+        assert_eq!(
+            report.summary(),
+            "3 keys or rules in 2 layouts could not be represented: 2 dropped, 1 altered"
+        );
+    }
+}
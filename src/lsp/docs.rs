@@ -0,0 +1,244 @@
+//! Hover documentation, read from the reference that `CollectDocs` generates
+//! into `docs/generated`
+
+use std::collections::BTreeMap;
+
+const REFERENCE: &[&str] = &[
+    include_str!("../../docs/generated/DeriveOptions.adoc"),
+    include_str!("../../docs/generated/DesktopKeyMap.adoc"),
+    include_str!("../../docs/generated/DesktopModes.adoc"),
+    include_str!("../../docs/generated/IsoKey.adoc"),
+    include_str!("../../docs/generated/KeyValue.adoc"),
+    include_str!("../../docs/generated/Layout.adoc"),
+    include_str!("../../docs/generated/LayoutStrings.adoc"),
+    include_str!("../../docs/generated/LayoutTarget.adoc"),
+    include_str!("../../docs/generated/LayoutTargetAndroid.adoc"),
+    include_str!("../../docs/generated/LayoutTargetIOS.adoc"),
+    include_str!("../../docs/generated/LayoutTargetWindows.adoc"),
+    include_str!("../../docs/generated/MobileKeyMap.adoc"),
+    include_str!("../../docs/generated/MobileModes.adoc"),
+    include_str!("../../docs/generated/Modes.adoc"),
+    include_str!("../../docs/generated/Project.adoc"),
+    include_str!("../../docs/generated/ProjectBundle.adoc"),
+    include_str!("../../docs/generated/ProjectDesc.adoc"),
+    include_str!("../../docs/generated/TargetAndroid.adoc"),
+    include_str!("../../docs/generated/TargetChrome.adoc"),
+    include_str!("../../docs/generated/TargetIOS.adoc"),
+    include_str!("../../docs/generated/TargetMacOS.adoc"),
+    include_str!("../../docs/generated/TargetMim.adoc"),
+    include_str!("../../docs/generated/TargetWindows.adoc"),
+    include_str!("../../docs/generated/TargetX11.adoc"),
+    include_str!("../../docs/generated/Targets.adoc"),
+    include_str!("../../docs/generated/YamlValue.adoc"),
+];
+
+/// Newtypes are documented with a single field of this name
+const INNER_FIELD: &str = "unnamed internal field #0";
+
+#[derive(Debug, Clone, Default)]
+pub struct Field {
+    pub name: String,
+    pub required: bool,
+    /// The type as written in the reference, e.g. `Map<String, <<Modes>>>`
+    pub ty: String,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TypeDocs {
+    pub name: String,
+    pub description: String,
+    pub fields: Vec<Field>,
+}
+
+pub struct Reference {
+    types: BTreeMap<String, TypeDocs>,
+}
+
+impl Reference {
+    pub fn load() -> Reference {
+        let types = REFERENCE
+            .iter()
+            .map(|doc| parse(doc))
+            .map(|ty| (ty.name.clone(), ty))
+            .collect();
+        Reference { types }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&TypeDocs> {
+        self.types.get(name)
+    }
+
+    /// The documented fields of the mapping at `path` below a value of type
+    /// `root`
+    pub fn fields_at(&self, root: &str, path: &[String]) -> Vec<&Field> {
+        let mut ty = match self.types.get(root) {
+            Some(ty) => ty,
+            None => return vec![],
+        };
+        let mut expr: Option<&str> = None;
+
+        for key in path {
+            let current = match expr.take() {
+                Some(expr) => expr,
+                None => match ty.fields.iter().find(|f| &f.name == key) {
+                    Some(field) => field.ty.as_str(),
+                    None => match ty.fields.iter().find(|f| f.name == INNER_FIELD) {
+                        Some(inner) => map_value(&inner.ty).unwrap_or(""),
+                        None => return vec![],
+                    },
+                },
+            };
+            match named_type(current).and_then(|name| self.types.get(name)) {
+                Some(next) => ty = next,
+                None => match map_value(current) {
+                    Some(value) => expr = Some(value),
+                    None => return vec![],
+                },
+            }
+        }
+
+        if expr.is_some() {
+            return vec![];
+        }
+        ty.fields.iter().filter(|f| f.name != INNER_FIELD).collect()
+    }
+
+    /// Hover text for the key at the end of `path`
+    pub fn describe(&self, root: &str, path: &[String]) -> Option<String> {
+        let (key, parent) = path.split_last()?;
+        let field = self
+            .fields_at(root, parent)
+            .into_iter()
+            .find(|f| &f.name == key)?;
+
+        let mut text = format!("**{}**: `{}`", field.name, field.ty);
+        if !field.required {
+            text.push_str(" _(optional)_");
+        }
+        if !field.description.is_empty() {
+            text.push_str("\n\n");
+            text.push_str(&field.description);
+        }
+        if let Some(ty) = named_type(&field.ty).and_then(|name| self.types.get(name)) {
+            if !ty.description.is_empty() {
+                text.push_str("\n\n---\n\n");
+                text.push_str(&ty.description);
+            }
+        }
+        Some(text)
+    }
+}
+
+/// `T` of `<<T>>`
+fn named_type(expr: &str) -> Option<&str> {
+    expr.strip_prefix("<<")?.strip_suffix(">>")
+}
+
+/// `V` of `Map<String, V>`
+fn map_value(expr: &str) -> Option<&str> {
+    expr.strip_prefix("Map<String, ")?.strip_suffix('>')
+}
+
+/// Reads one generated AsciiDoc page
+fn parse(doc: &str) -> TypeDocs {
+    let mut ty = TypeDocs::default();
+    let mut lines = doc.lines().filter(|l| !l.starts_with("//")).peekable();
+
+    // Title and the type's own description, up to the example or fields
+    let mut description = vec![];
+    for line in &mut lines {
+        if let Some(name) = line.strip_prefix("= ") {
+            ty.name = name.trim().to_string();
+        } else if line.starts_with(".Example") || line.starts_with(".Fields") {
+            break;
+        } else {
+            description.push(line);
+        }
+    }
+    ty.description = description.join("\n").trim().to_string();
+
+    let mut field: Option<Field> = None;
+    let mut in_example = false;
+    let mut in_listing = false;
+    for line in lines {
+        if line == "----" {
+            in_listing = !in_listing;
+            continue;
+        }
+        if in_listing {
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("* `") {
+            if let Some(field) = field.take() {
+                ty.fields.push(field);
+            }
+            let (name, rest) = rest.split_at(rest.find('`').unwrap_or(rest.len()));
+            field = Some(Field {
+                name: name.to_string(),
+                required: rest.contains("(required)"),
+                ..Field::default()
+            });
+            in_example = false;
+            continue;
+        }
+
+        let field = match field.as_mut() {
+            Some(field) => field,
+            None => continue,
+        };
+        if let Some(ty) = line.strip_prefix("Type: `") {
+            field.ty = ty.trim_end_matches('`').to_string();
+        } else if line.starts_with(".Example") {
+            in_example = true;
+        } else if !in_example && !line.starts_with("[source") {
+            let line = if line == "+" { "" } else { line };
+            if !(field.description.is_empty() && line.is_empty()) {
+                field.description.push_str(line);
+                field.description.push('\n');
+            }
+        }
+    }
+    if let Some(field) = field.take() {
+        ty.fields.push(field);
+    }
+    for field in &mut ty.fields {
+        field.description = field.description.trim().to_string();
+    }
+
+    ty
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(keys: &[&str]) -> Vec<String> {
+        keys.iter().map(|k| k.to_string()).collect()
+    }
+
+    #[test]
+    fn fields_follow_nested_types() {
+        let reference = Reference::load();
+        let names = |keys: &[&str]| {
+            reference
+                .fields_at("Layout", &path(keys))
+                .iter()
+                .map(|f| f.name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert!(names(&[]).contains(&"deadKeys".to_string()));
+        assert!(names(&["modes"]).contains(&"mac".to_string()));
+        assert!(names(&["modes", "mac"]).is_empty());
+        assert!(names(&["strings"]).contains(&"space".to_string()));
+    }
+
+    #[test]
+    fn describe_a_field() {
+        let reference = Reference::load();
+        let text = reference.describe("Layout", &path(&["decimal"])).unwrap();
+        assert!(text.starts_with("**decimal**: `String` _(optional)_"));
+        assert!(text.contains("decimal separator"));
+        assert!(!text.contains("----"));
+    }
+}
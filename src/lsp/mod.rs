@@ -0,0 +1,514 @@
+//! A language server for the YAML files of a bundle
+//!
+//! Speaks the Language Server Protocol on stdin and stdout with full document
+//! sync. Layout files (`layouts/*.yaml`) and `project.yaml` get:
+//!
+//! - diagnostics as they are typed: YAML and schema errors from the loader,
+//!   unknown modifiers in mode names, and dead keys that are in no mode
+//! - hover docs for keys, from the generated reference
+//! - completion of field names, targets and mode names
+//! - go to definition from a character in a mode string to its `deadKeys`
+//!   entry or transform, and from a `deadKeys` entry to the mode string
+
+pub mod docs;
+pub mod outline;
+
+use crate::{codes::Code, models::Layout, models::Project, ModeName, ModifierSet, Target};
+use docs::Reference;
+use outline::{byte_offset, utf16_column, Outline};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    io::{BufRead, Write},
+    str::FromStr,
+};
+use strum::IntoEnumIterator;
+
+/// Keys under `modes` and friends that take mode names
+const TARGET_MAPS: &[&str] = &["modes", "deadKeys", "space"];
+
+/// Modes offered for completion, besides those the layout already uses
+const COMMON_MODES: &[&str] = &[
+    "default",
+    "shift",
+    "caps",
+    "caps+shift",
+    "alt",
+    "alt+shift",
+    "caps+alt",
+    "ctrl",
+    "cmd",
+    "cmd+shift",
+    "cmd+alt",
+    "cmd+alt+shift",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileKind {
+    Layout,
+    Project,
+}
+
+impl FileKind {
+    fn of(uri: &str) -> Option<FileKind> {
+        if uri.ends_with("/project.yaml") {
+            Some(FileKind::Project)
+        } else if uri.contains("/layouts/") && uri.ends_with(".yaml") {
+            Some(FileKind::Layout)
+        } else {
+            None
+        }
+    }
+
+    fn root_type(self) -> &'static str {
+        match self {
+            FileKind::Layout => "Layout",
+            FileKind::Project => "Project",
+        }
+    }
+}
+
+struct Document {
+    kind: FileKind,
+    text: String,
+    outline: Outline,
+}
+
+impl Document {
+    fn new(kind: FileKind, text: String) -> Document {
+        let outline = Outline::parse(&text);
+        Document {
+            kind,
+            text,
+            outline,
+        }
+    }
+
+    fn line(&self, line: usize) -> &str {
+        self.text.lines().nth(line).unwrap_or("")
+    }
+
+    fn range(&self, line: usize, bytes: std::ops::Range<usize>) -> Value {
+        let text = self.line(line);
+        json!({
+            "start": { "line": line, "character": utf16_column(text, bytes.start) },
+            "end": { "line": line, "character": utf16_column(text, bytes.end) },
+        })
+    }
+}
+
+pub struct Server {
+    documents: HashMap<String, Document>,
+    reference: Reference,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Server {
+            documents: HashMap::new(),
+            reference: Reference::load(),
+        }
+    }
+}
+
+/// Serves requests from `input` until the client sends `exit`
+pub fn run<R: BufRead, W: Write>(mut input: R, mut output: W) -> Result<(), Error> {
+    let mut server = Server::default();
+
+    while let Some(message) = read_message(&mut input)? {
+        let method = message["method"].as_str().unwrap_or_default().to_string();
+        if method == "exit" {
+            break;
+        }
+
+        let params = &message["params"];
+        let mut outgoing = vec![];
+        let result = server.handle(&method, params, &mut outgoing);
+
+        if let Some(id) = message.get("id") {
+            outgoing.push(match result {
+                Some(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                None => json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": { "code": -32601, "message": format!("No method `{}`", method) },
+                }),
+            });
+        }
+
+        for message in outgoing {
+            write_message(&mut output, &message)?;
+        }
+    }
+
+    Ok(())
+}
+
+impl Server {
+    /// Handles one message; `None` if the method is not supported
+    fn handle(&mut self, method: &str, params: &Value, outgoing: &mut Vec<Value>) -> Option<Value> {
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+
+        match method {
+            "initialize" => Some(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "completionProvider": { "triggerCharacters": ["+"] },
+                    "definitionProvider": true,
+                },
+                "serverInfo": { "name": "kbdgen", "version": env!("CARGO_PKG_VERSION") },
+            })),
+            "shutdown" => Some(Value::Null),
+            "initialized" | "$/cancelRequest" | "textDocument/didSave" => Some(Value::Null),
+            "textDocument/didOpen" | "textDocument/didChange" => {
+                let text = if method == "textDocument/didOpen" {
+                    params["textDocument"]["text"].as_str()
+                } else {
+                    params["contentChanges"]
+                        .as_array()
+                        .and_then(|changes| changes.last())
+                        .and_then(|change| change["text"].as_str())
+                };
+                if let (Some(kind), Some(text)) = (FileKind::of(&uri), text) {
+                    let document = Document::new(kind, text.to_string());
+                    outgoing.push(publish_diagnostics(&uri, diagnostics(&document)));
+                    self.documents.insert(uri, document);
+                }
+                Some(Value::Null)
+            }
+            "textDocument/didClose" => {
+                if self.documents.remove(&uri).is_some() {
+                    outgoing.push(publish_diagnostics(&uri, vec![]));
+                }
+                Some(Value::Null)
+            }
+            "textDocument/hover" => Some(
+                self.position(&uri, params)
+                    .and_then(|(doc, line, byte)| self.hover(doc, line, byte))
+                    .unwrap_or(Value::Null),
+            ),
+            "textDocument/completion" => Some(
+                self.position(&uri, params)
+                    .map(|(doc, line, byte)| self.completion(doc, line, byte))
+                    .unwrap_or_else(|| json!([])),
+            ),
+            "textDocument/definition" => Some(
+                self.position(&uri, params)
+                    .and_then(|(doc, line, byte)| definition(doc, line, byte))
+                    .map(|(line, range)| {
+                        let doc = &self.documents[&uri];
+                        json!({ "uri": uri, "range": doc.range(line, range) })
+                    })
+                    .unwrap_or(Value::Null),
+            ),
+            _ if method.starts_with("$/") => Some(Value::Null),
+            _ => None,
+        }
+    }
+
+    /// The document and byte position a request is about
+    fn position(&self, uri: &str, params: &Value) -> Option<(&Document, usize, usize)> {
+        let document = self.documents.get(uri)?;
+        let line = params["position"]["line"].as_u64()? as usize;
+        let column = params["position"]["character"].as_u64()? as usize;
+        Some((document, line, byte_offset(document.line(line), column)))
+    }
+
+    fn hover(&self, document: &Document, line: usize, byte: usize) -> Option<Value> {
+        let node = document.outline.node_at(line, byte)?;
+        let mut text = self
+            .reference
+            .describe(document.kind.root_type(), &node.path);
+
+        // Mode names are map keys, so they have no docs of their own
+        if let [map, _, mode] = node.path.as_slice() {
+            if document.kind == FileKind::Layout && TARGET_MAPS.contains(&map.as_str()) {
+                let list = |set: &ModifierSet| {
+                    set.iter()
+                        .map(|m| format!("`{}`", m))
+                        .collect::<Vec<_>>()
+                        .join(" + ")
+                };
+                text = Some(match ModeName::from_str(mode) {
+                    Ok(name) if name.required.is_default() && name.is_exact() => {
+                        "Mode without modifiers".to_string()
+                    }
+                    Ok(name) if name.is_exact() => {
+                        format!("Mode with modifiers {}", list(&name.required))
+                    }
+                    Ok(name) => format!(
+                        "Mode with modifiers {}, also used with {} unless those modes are defined",
+                        if name.required.is_default() {
+                            "none".to_string()
+                        } else {
+                            list(&name.required)
+                        },
+                        list(&name.optional)
+                    ),
+                    Err(e) => e.to_string(),
+                });
+            }
+        }
+
+        Some(json!({
+            "contents": { "kind": "markdown", "value": text? },
+            "range": document.range(line, node.key.clone()),
+        }))
+    }
+
+    fn completion(&self, document: &Document, line: usize, byte: usize) -> Value {
+        if document.outline.is_block_line(line) {
+            return json!([]);
+        }
+        let text = document.line(line);
+        let indent = text.len() - text.trim_start_matches(' ').len();
+        if byte < indent || text[indent..byte.min(text.len())].contains(':') {
+            return json!([]);
+        }
+        let parent = document.outline.parent_path(line, indent);
+        let existing = |path: &[String]| {
+            document
+                .outline
+                .nodes
+                .iter()
+                .filter(|n| n.path.len() == path.len() + 1 && n.path.starts_with(path))
+                .map(|n| n.path.last().cloned().unwrap_or_default())
+                .collect::<Vec<_>>()
+        };
+        let siblings = existing(&parent);
+
+        let items: Vec<Value> = match (document.kind, parent.as_slice()) {
+            (FileKind::Layout, [map])
+                if TARGET_MAPS.contains(&map.as_str()) || map == "targets" =>
+            {
+                Target::iter()
+                    .map(|t| t.to_string())
+                    .filter(|t| !siblings.contains(t))
+                    .map(|t| json!({ "label": t, "kind": 20 }))
+                    .collect()
+            }
+            (FileKind::Layout, [map, target]) if TARGET_MAPS.contains(&map.as_str()) => {
+                let mut modes = existing(&["modes".to_string(), target.clone()]);
+                if map == "modes" {
+                    modes.clear();
+                }
+                let is_mobile = Target::from_str(target)
+                    .map(Target::is_mobile)
+                    .unwrap_or(false);
+                let common: &[&str] = if is_mobile {
+                    &COMMON_MODES[..2]
+                } else {
+                    COMMON_MODES
+                };
+                modes.extend(common.iter().map(|m| m.to_string()));
+                let mut seen = std::collections::BTreeSet::new();
+                modes
+                    .into_iter()
+                    .filter(|m| !siblings.contains(m) && seen.insert(m.clone()))
+                    .map(|m| json!({ "label": m, "kind": 12 }))
+                    .collect()
+            }
+            (kind, path) => self
+                .reference
+                .fields_at(kind.root_type(), path)
+                .into_iter()
+                .filter(|f| !siblings.contains(&f.name))
+                .map(|f| {
+                    json!({
+                        "label": f.name,
+                        "kind": 10,
+                        "detail": f.ty,
+                        "documentation": { "kind": "markdown", "value": f.description },
+                    })
+                })
+                .collect(),
+        };
+
+        Value::Array(items)
+    }
+}
+
+/// The mode string that `deadKeys` entries for `target` and `mode` are typed
+/// on, following the target fallbacks
+fn modes_for(outline: &Outline, target: &str, mode: &str) -> Option<usize> {
+    let chain: Vec<String> = match Target::from_str(target) {
+        Ok(target) => target.fallback_chain().map(|t| t.to_string()).collect(),
+        Err(_) => vec![target.to_string()],
+    };
+    chain
+        .iter()
+        .find_map(|t| outline.node(&["modes", t, mode]).map(|(i, _)| i))
+}
+
+fn definition(
+    document: &Document,
+    line: usize,
+    byte: usize,
+) -> Option<(usize, std::ops::Range<usize>)> {
+    let outline = &document.outline;
+    let value = outline.value_at(line, byte)?;
+    let node = &outline.nodes[value.node];
+
+    match node.path.as_slice() {
+        [map, target, mode] if map == "modes" => {
+            if let Some((dead_keys, _)) = outline.node(&["deadKeys", target, mode]) {
+                if let Some(entry) = outline.values_of(dead_keys).find(|v| v.text == value.text) {
+                    return Some((entry.line, entry.range.clone()));
+                }
+            }
+            let (_, transform) = outline.node(&["transforms", &value.text])?;
+            Some((transform.line, transform.key.clone()))
+        }
+        [map, target, mode] if map == "deadKeys" => {
+            let modes = modes_for(outline, target, mode)?;
+            let found = outline.values_of(modes).find(|v| v.text == value.text)?;
+            Some((found.line, found.range.clone()))
+        }
+        _ => None,
+    }
+}
+
+fn diagnostics(document: &Document) -> Vec<Value> {
+    let mut diagnostics = vec![];
+    let outline = &document.outline;
+
+    let parsed = match document.kind {
+        FileKind::Layout => serde_yaml::from_str::<Layout>(&document.text).map(|_| ()),
+        FileKind::Project => serde_yaml::from_str::<Project>(&document.text).map(|_| ()),
+    };
+    if let Err(e) = parsed {
+        let (line, column) = e
+            .location()
+            .map(|l| (l.line().saturating_sub(1), l.column().saturating_sub(1)))
+            .unwrap_or((0, 0));
+        let text = document.line(line);
+        let start = byte_offset(text, column).min(text.len());
+        diagnostics.push(diagnostic(
+            document.range(line, start..text.len().max(start)),
+            1,
+            Code::ParseFile,
+            e.to_string(),
+        ));
+    }
+
+    if document.kind != FileKind::Layout {
+        return diagnostics;
+    }
+
+    for (index, node) in outline.nodes.iter().enumerate() {
+        match node.path.as_slice() {
+            [map, target, mode] if TARGET_MAPS.contains(&map.as_str()) => {
+                let checked = match Target::from_str(target) {
+                    Ok(target) => crate::parse_mode_name(target, mode)
+                        .map(drop)
+                        .map_err(|e| e.to_string()),
+                    Err(_) => ModeName::from_str(mode)
+                        .map(drop)
+                        .map_err(|e| e.to_string()),
+                };
+                if let Err(e) = checked {
+                    diagnostics.push(diagnostic(
+                        document.range(node.line, node.key.clone()),
+                        1,
+                        Code::InvalidModeName,
+                        e,
+                    ));
+                }
+
+                if map != "deadKeys" {
+                    continue;
+                }
+                let modes = modes_for(outline, target, mode);
+                for value in outline.values_of(index) {
+                    let found = modes
+                        .map(|m| outline.values_of(m).any(|v| v.text == value.text))
+                        .unwrap_or(false);
+                    if !found {
+                        diagnostics.push(diagnostic(
+                            document.range(value.line, value.range.clone()),
+                            2,
+                            Code::DeadKeyNotInMode,
+                            format!(
+                                "Dead key `{}` is not in the `{}` mode for {}",
+                                value.text, mode, target
+                            ),
+                        ));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    diagnostics
+}
+
+fn diagnostic(range: Value, severity: u8, code: Code, message: String) -> Value {
+    json!({
+        "range": range,
+        "severity": severity,
+        "code": code,
+        "source": "kbdgen",
+        "message": message,
+    })
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    })
+}
+
+fn read_message<R: BufRead>(input: &mut R) -> Result<Option<Value>, Error> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input
+            .read_line(&mut header)
+            .map_err(|source| Error::CannotRead { source })?
+            == 0
+        {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let mut body = vec![0; length.ok_or(Error::MissingContentLength)?];
+    input
+        .read_exact(&mut body)
+        .map_err(|source| Error::CannotRead { source })?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|source| Error::InvalidMessage { source })
+}
+
+fn write_message<W: Write>(output: &mut W, message: &Value) -> Result<(), Error> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)
+        .and_then(|_| output.flush())
+        .map_err(|source| Error::CannotWrite { source })
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not read from the client")]
+    CannotRead { source: std::io::Error },
+    #[error("Could not write to the client")]
+    CannotWrite { source: std::io::Error },
+    #[error("Message from the client has no Content-Length header")]
+    MissingContentLength,
+    #[error("Message from the client is not valid JSON")]
+    InvalidMessage { source: serde_json::Error },
+}
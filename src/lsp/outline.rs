@@ -0,0 +1,380 @@
+//! Positions of keys and values in a bundle YAML file
+//!
+//! serde_yaml throws positions away once a document is parsed, so this is a
+//! line-based scan of the subset of YAML that bundle files are written in:
+//! block mappings, block and flow sequences of scalars, and `|` block scalars
+//! for the mode strings. Anything it does not understand is skipped, which
+//! only means the editor features have nothing to offer there.
+
+use std::ops::Range;
+
+/// A mapping key, with the keys of the mappings it is nested in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    pub path: Vec<String>,
+    pub line: usize,
+    /// Byte range of the key, without quotes
+    pub key: Range<usize>,
+    pub indent: usize,
+    /// Whether the value is a `|` or `>` block scalar on the following lines
+    pub block: bool,
+}
+
+/// A scalar in a sequence or a block scalar, belonging to the node at `node`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Value {
+    pub node: usize,
+    pub line: usize,
+    /// Byte range of the scalar, without quotes
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Outline {
+    pub nodes: Vec<Node>,
+    pub values: Vec<Value>,
+    /// For each line, the node whose block scalar it is part of
+    block_lines: Vec<Option<usize>>,
+}
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn is_blank_or_comment(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.is_empty() || trimmed.starts_with('#')
+}
+
+/// Splits a key off the start of `rest`, returning the unquoted key range
+/// (relative to `rest`) and the offset of what follows the `:`
+fn split_key(rest: &str) -> Option<(Range<usize>, usize)> {
+    let quote = rest.chars().next().filter(|c| *c == '\'' || *c == '"');
+    let key = match quote {
+        Some(q) => {
+            let mut end = None;
+            let mut chars = rest.char_indices().skip(1).peekable();
+            while let Some((i, c)) = chars.next() {
+                if c == q {
+                    // `''` is an escaped quote in single-quoted scalars
+                    if q == '\'' && chars.peek().map(|(_, c)| *c) == Some('\'') {
+                        chars.next();
+                        continue;
+                    }
+                    end = Some(i);
+                    break;
+                }
+            }
+            let end = end?;
+            if !rest[end + 1..].starts_with(':') {
+                return None;
+            }
+            (1..end, end + 2)
+        }
+        None => {
+            let colon = rest
+                .match_indices(':')
+                .map(|(i, _)| i)
+                .find(|&i| rest[i + 1..].is_empty() || rest[i + 1..].starts_with(' '))?;
+            (0..colon, colon + 1)
+        }
+    };
+    Some(key)
+}
+
+/// Ranges of the scalars in `text` (a flow sequence like `[a, 'b']` or a
+/// single scalar), relative to `text`
+fn scalars(text: &str) -> Vec<Range<usize>> {
+    let trimmed = text.trim_end();
+    let (inner, offset) = if trimmed.starts_with('[') && trimmed.ends_with(']') {
+        (&trimmed[1..trimmed.len() - 1], 1)
+    } else {
+        (trimmed, 0)
+    };
+
+    let mut ranges = vec![];
+    let mut start = 0;
+    let items: Vec<&str> = if offset == 1 {
+        inner.split(',').collect()
+    } else {
+        vec![inner]
+    };
+    for item in items {
+        let lead = item.len() - item.trim_start().len();
+        let value = item.trim();
+        let mut range = start + lead..start + lead + value.len();
+        if value.len() >= 2
+            && ((value.starts_with('\'') && value.ends_with('\''))
+                || (value.starts_with('"') && value.ends_with('"')))
+        {
+            range = range.start + 1..range.end - 1;
+        }
+        if !value.is_empty() {
+            ranges.push(range.start + offset..range.end + offset);
+        }
+        start += item.len() + 1;
+    }
+    ranges
+}
+
+fn unquote(text: &str) -> String {
+    text.replace("''", "'")
+}
+
+impl Outline {
+    pub fn parse(text: &str) -> Outline {
+        let mut outline = Outline::default();
+        let mut stack: Vec<(usize, usize)> = vec![]; // (indent, node)
+        let mut block: Option<(usize, usize)> = None; // (indent of key, node)
+
+        for (line_no, line) in text.lines().enumerate() {
+            outline.block_lines.push(None);
+
+            if let Some((key_indent, node)) = block {
+                if line.trim().is_empty() || indent_of(line) > key_indent {
+                    outline.block_lines[line_no] = Some(node);
+                    for (start, token) in tokens(line) {
+                        outline.values.push(Value {
+                            node,
+                            line: line_no,
+                            range: start..start + token.len(),
+                            text: token.to_string(),
+                        });
+                    }
+                    continue;
+                }
+                block = None;
+            }
+
+            if is_blank_or_comment(line) {
+                continue;
+            }
+
+            let indent = indent_of(line);
+            while stack.last().map(|(i, _)| *i >= indent).unwrap_or(false) {
+                stack.pop();
+            }
+
+            let rest = &line[indent..];
+            if let Some(item) = rest.strip_prefix("- ") {
+                if let Some(&(_, node)) = stack.last() {
+                    let start = indent + 2;
+                    for range in scalars(item) {
+                        let range = range.start + start..range.end + start;
+                        outline.values.push(Value {
+                            node,
+                            line: line_no,
+                            text: unquote(&line[range.clone()]),
+                            range,
+                        });
+                    }
+                }
+                continue;
+            }
+
+            let (key, after) = match split_key(rest) {
+                Some(split) => split,
+                None => continue,
+            };
+            let key = key.start + indent..key.end + indent;
+            let value = line[indent + after..].trim();
+            let mut path: Vec<String> = stack
+                .iter()
+                .map(|(_, node)| {
+                    outline.nodes[*node]
+                        .path
+                        .last()
+                        .cloned()
+                        .unwrap_or_default()
+                })
+                .collect();
+            path.push(unquote(&line[key.clone()]));
+
+            let node = outline.nodes.len();
+            let is_block = value.starts_with('|') || value.starts_with('>');
+            outline.nodes.push(Node {
+                path,
+                line: line_no,
+                key,
+                indent,
+                block: is_block,
+            });
+
+            if is_block {
+                block = Some((indent, node));
+            } else if !value.is_empty() && !value.starts_with('#') {
+                let start = line.len() - line[indent + after..].trim_start().len();
+                let value_text = line[start..].trim_end();
+                if value_text.starts_with('[') {
+                    for range in scalars(value_text) {
+                        let range = range.start + start..range.end + start;
+                        outline.values.push(Value {
+                            node,
+                            line: line_no,
+                            text: unquote(&line[range.clone()]),
+                            range,
+                        });
+                    }
+                }
+            }
+            stack.push((indent, node));
+        }
+
+        outline
+    }
+
+    /// The node whose key is at the position
+    pub fn node_at(&self, line: usize, byte: usize) -> Option<&Node> {
+        self.nodes
+            .iter()
+            .find(|n| n.line == line && n.key.start <= byte && byte <= n.key.end)
+    }
+
+    /// The value at the position
+    pub fn value_at(&self, line: usize, byte: usize) -> Option<&Value> {
+        self.values
+            .iter()
+            .find(|v| v.line == line && v.range.start <= byte && byte <= v.range.end)
+    }
+
+    pub fn node(&self, path: &[&str]) -> Option<(usize, &Node)> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .find(|(_, n)| n.path.iter().map(String::as_str).eq(path.iter().copied()))
+    }
+
+    pub fn values_of(&self, node: usize) -> impl Iterator<Item = &Value> {
+        self.values.iter().filter(move |v| v.node == node)
+    }
+
+    /// Whether the line is part of a block scalar
+    pub fn is_block_line(&self, line: usize) -> bool {
+        self.block_lines.get(line).copied().flatten().is_some()
+    }
+
+    /// The path of the mapping that a key at `indent` on `line` would be
+    /// added to
+    pub fn parent_path(&self, line: usize, indent: usize) -> Vec<String> {
+        self.nodes
+            .iter()
+            .filter(|n| n.line < line)
+            .fold(vec![], |mut parents: Vec<&Node>, node| {
+                while parents
+                    .last()
+                    .map(|p| p.indent >= node.indent)
+                    .unwrap_or(false)
+                {
+                    parents.pop();
+                }
+                parents.push(node);
+                parents
+            })
+            .into_iter()
+            .rev()
+            .find(|node| node.indent < indent)
+            .map(|node| node.path.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// Whitespace separated tokens and their byte offsets
+pub fn tokens(line: &str) -> impl Iterator<Item = (usize, &str)> {
+    line.split(' ')
+        .scan(0, |offset, token| {
+            let start = *offset;
+            *offset += token.len() + 1;
+            Some((start, token))
+        })
+        .filter(|(_, token)| !token.is_empty())
+}
+
+/// The UTF-16 column of a byte offset, as LSP positions count
+pub fn utf16_column(line: &str, byte: usize) -> usize {
+    line[..byte.min(line.len())].encode_utf16().count()
+}
+
+/// The byte offset of a UTF-16 column
+pub fn byte_offset(line: &str, column: usize) -> usize {
+    let mut units = 0;
+    for (i, c) in line.char_indices() {
+        if units >= column {
+            return i;
+        }
+        units += c.len_utf16();
+    }
+    line.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAYOUT: &str = r#"displayNames:
+  en: Test
+modes:
+  mac:
+    default: |
+      ' 1 2 #
+        á š
+    shift: |
+      ` !
+deadKeys:
+  mac:
+    default: [´, '`']
+    shift:
+      - '`'
+transforms:
+  ´:
+    a: á
+"#;
+
+    #[test]
+    fn nested_keys_have_paths() {
+        let outline = Outline::parse(LAYOUT);
+        let (_, node) = outline.node(&["modes", "mac", "shift"]).unwrap();
+        assert_eq!(node.line, 7);
+        assert!(node.block);
+        assert_eq!(
+            outline.node_at(15, 3).unwrap().path,
+            vec!["transforms", "´"]
+        );
+    }
+
+    #[test]
+    fn values_of_sequences_and_block_scalars() {
+        let outline = Outline::parse(LAYOUT);
+        let texts = |path: &[&str]| {
+            let (node, _) = outline.node(path).unwrap();
+            outline
+                .values_of(node)
+                .map(|v| v.text.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            texts(&["modes", "mac", "default"]),
+            ["'", "1", "2", "#", "á", "š"]
+        );
+        assert_eq!(texts(&["deadKeys", "mac", "default"]), ["´", "`"]);
+        assert_eq!(texts(&["deadKeys", "mac", "shift"]), ["`"]);
+
+        let value = outline.value_at(11, 19).unwrap();
+        assert_eq!((value.text.as_str(), value.range.clone()), ("`", 19..20));
+    }
+
+    #[test]
+    fn parent_path_of_a_new_line() {
+        let outline = Outline::parse(LAYOUT);
+        assert_eq!(outline.parent_path(9, 4), vec!["modes", "mac"]);
+        assert_eq!(outline.parent_path(9, 2), vec!["modes"]);
+        assert!(outline.parent_path(9, 0).is_empty());
+        assert!(outline.is_block_line(6));
+    }
+
+    #[test]
+    fn utf16_columns() {
+        assert_eq!(utf16_column("a𝒜b", 5), 3);
+        assert_eq!(byte_offset("a𝒜b", 3), 5);
+    }
+}
@@ -0,0 +1,177 @@
+use std::io::{self, Write};
+
+use crate::png;
+
+/// The icon types that hold PNG data, by pixel size
+const PNG_ICON_TYPES: &[(u32, &[u8; 4])] = &[
+    (16, b"icp4"),
+    (32, b"icp5"),
+    (64, b"icp6"),
+    (128, b"ic07"),
+    (256, b"ic08"),
+    (512, b"ic09"),
+    (1024, b"ic10"),
+];
+
+/// An `.icns` icon family built from square PNG images
+///
+/// Modern macOS reads PNG data from the icon family directly, so the images
+/// are embedded as PNG instead of being converted to the legacy bitmap
+/// types.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IconFamily {
+    icons: Vec<(&'static [u8; 4], Vec<u8>)>,
+}
+
+impl IconFamily {
+    /// Builds the family from a square PNG image of any size, scaled down to
+    /// each standard size below its own, and up to 16 and 32 pixels if it is
+    /// smaller. An image of a standard size is embedded as it is.
+    pub fn from_png(png: Vec<u8>) -> Result<Self, Error> {
+        let (width, height) = png::size(&png).ok_or(Error::NotPng)?;
+        if width != height {
+            return Err(Error::NotSquare { width, height });
+        }
+        let image = png::decode(&png)?;
+
+        let mut family = IconFamily::default();
+        for &(size, _) in PNG_ICON_TYPES {
+            if size == width {
+                family.add_png(png.clone())?;
+            } else if size < width || size <= 32 {
+                family.add_png(png::encode(&image.resize(size, size)))?;
+            }
+        }
+        Ok(family)
+    }
+
+    /// Adds a PNG image, picking the icon type from its size
+    pub fn add_png(&mut self, png: Vec<u8>) -> Result<(), Error> {
+        let (width, height) = png::size(&png).ok_or(Error::NotPng)?;
+        if width != height {
+            return Err(Error::NotSquare { width, height });
+        }
+
+        let kind = PNG_ICON_TYPES
+            .iter()
+            .find(|(size, _)| *size == width)
+            .map(|(_, kind)| *kind)
+            .ok_or(Error::UnsupportedSize { size: width })?;
+
+        self.icons.retain(|(existing, _)| *existing != kind);
+        self.icons.push((kind, png));
+        Ok(())
+    }
+
+    pub fn write_icns(&self, mut w: impl Write) -> io::Result<()> {
+        let len = 8 + self
+            .icons
+            .iter()
+            .map(|(_, data)| 8 + data.len())
+            .sum::<usize>();
+
+        w.write_all(b"icns")?;
+        w.write_all(&(len as u32).to_be_bytes())?;
+        for (kind, data) in &self.icons {
+            w.write_all(*kind)?;
+            w.write_all(&(8 + data.len() as u32).to_be_bytes())?;
+            w.write_all(data)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Icon is not a PNG image")]
+    NotPng,
+    #[error("Icon cannot be decoded")]
+    CannotDecode(#[from] png::Error),
+    #[error("Icon must be square, but is {width}x{height}")]
+    NotSquare { width: u32, height: u32 },
+    #[error("Icon size {size}x{size} is not supported, use 16, 32, 64, 128, 256, 512 or 1024")]
+    UnsupportedSize { size: u32 },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png_header(width: u32, height: u32) -> Vec<u8> {
+        let mut png = png::SIGNATURE.to_vec();
+        png.extend_from_slice(&13u32.to_be_bytes());
+        png.extend_from_slice(b"IHDR");
+        png.extend_from_slice(&width.to_be_bytes());
+        png.extend_from_slice(&height.to_be_bytes());
+        png
+    }
+
+    #[test]
+    fn embeds_png_by_size() {
+        let mut family = IconFamily::default();
+        family.add_png(png_header(32, 32)).unwrap();
+
+        let mut icns = vec![];
+        family.write_icns(&mut icns).unwrap();
+
+        assert_eq!(&icns[0..4], b"icns");
+        assert_eq!(
+            u32::from_be_bytes([icns[4], icns[5], icns[6], icns[7]]) as usize,
+            icns.len()
+        );
+        assert_eq!(&icns[8..12], b"icp5");
+        assert_eq!(&icns[16..], &png_header(32, 32)[..]);
+    }
+
+    #[test]
+    fn scales_images_to_the_standard_sizes() {
+        let image = |size: u32| png::Image {
+            width: size,
+            height: size,
+            pixels: vec![200; (size * size * 4) as usize],
+        };
+        let kinds = |family: &IconFamily| {
+            family
+                .icons
+                .iter()
+                .map(|(kind, data)| (**kind, png::size(data).unwrap().0))
+                .collect::<Vec<_>>()
+        };
+
+        let family = IconFamily::from_png(png::encode(&image(20))).unwrap();
+        assert_eq!(kinds(&family), [(*b"icp4", 16), (*b"icp5", 32)]);
+
+        let original = png::encode(&image(64));
+        let family = IconFamily::from_png(original.clone()).unwrap();
+        assert_eq!(
+            kinds(&family),
+            [(*b"icp4", 16), (*b"icp5", 32), (*b"icp6", 64)]
+        );
+        assert_eq!(family.icons[2].1, original);
+        let scaled = png::decode(&family.icons[0].1).unwrap();
+        assert_eq!(scaled, image(16));
+
+        assert!(matches!(
+            IconFamily::from_png(png::encode(&png::Image {
+                width: 20,
+                height: 10,
+                pixels: vec![0; 800],
+            })),
+            Err(Error::NotSquare { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_odd_sizes() {
+        let mut family = IconFamily::default();
+        assert!(matches!(
+            family.add_png(png_header(20, 20)),
+            Err(Error::UnsupportedSize { size: 20 })
+        ));
+        assert!(matches!(
+            family.add_png(png_header(32, 16)),
+            Err(Error::NotSquare { .. })
+        ));
+        assert!(matches!(family.add_png(vec![0; 32]), Err(Error::NotPng)));
+    }
+}
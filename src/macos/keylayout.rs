@@ -0,0 +1,446 @@
+//! Reading `.keylayout` files, as installed on a Mac
+//!
+//! Only what is needed to compare an installed layout with a bundle is read:
+//! the output of each alphanumeric key per modifier combination, which keys
+//! are dead keys, and what the dead keys combine into.
+
+use crate::{models::IsoKey, Modifier, ModifierSet};
+use std::{collections::BTreeMap, io::Read};
+use xml::reader::{EventReader, XmlEvent};
+
+/// Virtual key codes of the ISO keys, as the generator writes them
+pub const KEY_CODES: &[(IsoKey, u32)] = &[
+    (IsoKey::E00, 10),
+    (IsoKey::E01, 18),
+    (IsoKey::E02, 19),
+    (IsoKey::E03, 20),
+    (IsoKey::E04, 21),
+    (IsoKey::E05, 23),
+    (IsoKey::E06, 22),
+    (IsoKey::E07, 26),
+    (IsoKey::E08, 28),
+    (IsoKey::E09, 25),
+    (IsoKey::E10, 29),
+    (IsoKey::E11, 27),
+    (IsoKey::E12, 24),
+    (IsoKey::D01, 12),
+    (IsoKey::D02, 13),
+    (IsoKey::D03, 14),
+    (IsoKey::D04, 15),
+    (IsoKey::D05, 17),
+    (IsoKey::D06, 16),
+    (IsoKey::D07, 32),
+    (IsoKey::D08, 34),
+    (IsoKey::D09, 31),
+    (IsoKey::D10, 35),
+    (IsoKey::D11, 33),
+    (IsoKey::D12, 30),
+    (IsoKey::C01, 0),
+    (IsoKey::C02, 1),
+    (IsoKey::C03, 2),
+    (IsoKey::C04, 3),
+    (IsoKey::C05, 5),
+    (IsoKey::C06, 4),
+    (IsoKey::C07, 38),
+    (IsoKey::C08, 40),
+    (IsoKey::C09, 37),
+    (IsoKey::C10, 41),
+    (IsoKey::C11, 39),
+    (IsoKey::C12, 42),
+    (IsoKey::B00, 50),
+    (IsoKey::B01, 6),
+    (IsoKey::B02, 7),
+    (IsoKey::B03, 8),
+    (IsoKey::B04, 9),
+    (IsoKey::B05, 11),
+    (IsoKey::B06, 45),
+    (IsoKey::B07, 46),
+    (IsoKey::B08, 43),
+    (IsoKey::B09, 47),
+    (IsoKey::B10, 44),
+];
+
+/// What pressing a key does, without any dead key pending
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyOutput {
+    Output(String),
+    /// A dead key, with the output of pressing it twice or before a key it
+    /// does not combine with
+    Dead(String),
+}
+
+#[derive(Debug, Clone)]
+enum Binding {
+    Output(String),
+    Action(String),
+}
+
+#[derive(Debug, Clone, Default)]
+struct When {
+    state: String,
+    output: Option<String>,
+    next: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct KeyMap {
+    keys: BTreeMap<u32, Binding>,
+    /// `(baseMapSet, baseIndex)` to look up keys this map does not define
+    base: Option<(String, usize)>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct ModifierMap {
+    default_index: usize,
+    /// `(mapIndex, keys)` of each `keyMapSelect`, in order
+    selects: Vec<(usize, Vec<String>)>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct KeyLayout {
+    pub name: String,
+    /// `(mapSet, modifiers)` of the first `layout` element
+    layout: Option<(String, String)>,
+    modifier_maps: BTreeMap<String, ModifierMap>,
+    key_maps: BTreeMap<(String, usize), KeyMap>,
+    actions: BTreeMap<String, Vec<When>>,
+    terminators: BTreeMap<String, String>,
+}
+
+fn attribute(attributes: &[xml::attribute::OwnedAttribute], name: &str) -> Option<String> {
+    attributes
+        .iter()
+        .find(|a| a.name.local_name == name)
+        .map(|a| a.value.clone())
+}
+
+fn index(attributes: &[xml::attribute::OwnedAttribute], name: &str) -> Result<usize, Error> {
+    let value = attribute(attributes, name).ok_or_else(|| Error::MissingAttribute {
+        attribute: name.to_string(),
+    })?;
+    value.parse().map_err(|_| Error::InvalidNumber { value })
+}
+
+/// Whether a `modifier keys="..."` expression matches the held modifiers
+///
+/// Every modifier without a `?` must be held, and every held modifier must be
+/// mentioned. Left, right and `any` variants are not told apart.
+fn matches(expression: &str, held: &ModifierSet) -> bool {
+    let mut required = vec![];
+    let mut allowed = vec![];
+    for token in expression.split_whitespace() {
+        let (name, optional) = match token.strip_suffix('?') {
+            Some(name) => (name, true),
+            None => (token, false),
+        };
+        let name = name.to_lowercase();
+        let name = name
+            .trim_start_matches("any")
+            .trim_start_matches("right")
+            .trim_start_matches("left");
+        let modifier = match name {
+            "command" => Modifier::Cmd,
+            "control" => Modifier::Ctrl,
+            "caps" => Modifier::Caps,
+            "option" => Modifier::Alt,
+            "shift" => Modifier::Shift,
+            _ => continue,
+        };
+        if !optional {
+            required.push(modifier);
+        }
+        allowed.push(modifier);
+    }
+    required.iter().all(|m| held.contains(*m)) && held.iter().all(|m| allowed.contains(&m))
+}
+
+impl KeyLayout {
+    pub fn parse(reader: impl Read) -> Result<KeyLayout, Error> {
+        let mut layout = KeyLayout::default();
+        let mut modifier_map: Option<String> = None;
+        let mut map_select: Option<usize> = None;
+        let mut key_map_set: Option<String> = None;
+        let mut key_map: Option<(String, usize)> = None;
+        let mut action: Option<String> = None;
+
+        for event in EventReader::new(reader) {
+            let (name, attributes) = match event.map_err(|source| Error::InvalidXml { source })? {
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } => (name.local_name, attributes),
+                _ => continue,
+            };
+
+            match name.as_str() {
+                "keyboard" => layout.name = attribute(&attributes, "name").unwrap_or_default(),
+                "layout" if layout.layout.is_none() => {
+                    layout.layout =
+                        attribute(&attributes, "mapSet").zip(attribute(&attributes, "modifiers"));
+                }
+                "modifierMap" => {
+                    let id = attribute(&attributes, "id").unwrap_or_default();
+                    let default_index = index(&attributes, "defaultIndex")?;
+                    layout.modifier_maps.insert(
+                        id.clone(),
+                        ModifierMap {
+                            default_index,
+                            selects: vec![],
+                        },
+                    );
+                    modifier_map = Some(id);
+                }
+                "keyMapSelect" => map_select = Some(index(&attributes, "mapIndex")?),
+                "modifier" => {
+                    if let (Some(id), Some(map_index)) = (&modifier_map, map_select) {
+                        let keys = attribute(&attributes, "keys").unwrap_or_default();
+                        let selects = &mut layout.modifier_maps.get_mut(id).expect("added").selects;
+                        match selects.iter_mut().find(|(i, _)| *i == map_index) {
+                            Some((_, expressions)) => expressions.push(keys),
+                            None => selects.push((map_index, vec![keys])),
+                        }
+                    }
+                }
+                "keyMapSet" => key_map_set = attribute(&attributes, "id"),
+                "keyMap" => {
+                    let set = key_map_set.clone().unwrap_or_default();
+                    let id = (set, index(&attributes, "index")?);
+                    let base = match attribute(&attributes, "baseMapSet") {
+                        Some(base_set) => Some((base_set, index(&attributes, "baseIndex")?)),
+                        None => None,
+                    };
+                    layout.key_maps.insert(
+                        id.clone(),
+                        KeyMap {
+                            keys: BTreeMap::new(),
+                            base,
+                        },
+                    );
+                    key_map = Some(id);
+                }
+                "key" => {
+                    if let Some(id) = &key_map {
+                        let code = index(&attributes, "code")? as u32;
+                        let binding = match (
+                            attribute(&attributes, "output"),
+                            attribute(&attributes, "action"),
+                        ) {
+                            (Some(output), _) => Binding::Output(output),
+                            (None, Some(action)) => Binding::Action(action),
+                            (None, None) => continue,
+                        };
+                        layout
+                            .key_maps
+                            .get_mut(id)
+                            .expect("added")
+                            .keys
+                            .insert(code, binding);
+                    }
+                }
+                "action" => {
+                    let id = attribute(&attributes, "id").unwrap_or_default();
+                    layout.actions.insert(id.clone(), vec![]);
+                    action = Some(id);
+                }
+                "when" => {
+                    let when = When {
+                        state: attribute(&attributes, "state").unwrap_or_default(),
+                        output: attribute(&attributes, "output"),
+                        next: attribute(&attributes, "next"),
+                    };
+                    match &action {
+                        Some(id) => layout.actions.get_mut(id).expect("added").push(when),
+                        // Outside of `actions`, this is a terminator
+                        None => {
+                            if let Some(output) = when.output {
+                                layout.terminators.insert(when.state, output);
+                            }
+                        }
+                    }
+                }
+                "terminators" => action = None,
+                _ => {}
+            }
+        }
+
+        Ok(layout)
+    }
+
+    fn selected_index(&self, mode: &ModifierSet) -> Option<Option<usize>> {
+        let (_, modifiers) = self.layout.as_ref()?;
+        let map = self.modifier_maps.get(modifiers)?;
+        Some(
+            map.selects
+                .iter()
+                .find(|(_, expressions)| expressions.iter().any(|e| matches(e, mode)))
+                .map(|(index, _)| *index),
+        )
+    }
+
+    /// Whether a modifier map entry matches `mode`, rather than it falling
+    /// back to the default key map
+    pub fn selects(&self, mode: &ModifierSet) -> bool {
+        matches!(self.selected_index(mode), Some(Some(_)))
+    }
+
+    /// The index of the key map used while `mode` is held
+    pub fn key_map_index(&self, mode: &ModifierSet) -> Option<usize> {
+        let (_, modifiers) = self.layout.as_ref()?;
+        let map = self.modifier_maps.get(modifiers)?;
+        Some(self.selected_index(mode)?.unwrap_or(map.default_index))
+    }
+
+    fn binding(&self, set: &str, index: usize, code: u32) -> Option<&Binding> {
+        let map = self.key_maps.get(&(set.to_string(), index))?;
+        map.keys.get(&code).or_else(|| {
+            let (base_set, base_index) = map.base.as_ref()?;
+            self.binding(base_set, *base_index, code)
+        })
+    }
+
+    /// What `key` does while `mode` is held
+    pub fn key(&self, mode: &ModifierSet, key: IsoKey) -> Option<KeyOutput> {
+        let (set, _) = self.layout.as_ref()?;
+        let code = KEY_CODES.iter().find(|(k, _)| *k == key)?.1;
+        match self.binding(set, self.key_map_index(mode)?, code)? {
+            Binding::Output(output) => Some(KeyOutput::Output(output.clone())),
+            Binding::Action(id) => {
+                let when = self.actions.get(id)?.iter().find(|w| w.state == "none")?;
+                match (&when.output, &when.next) {
+                    (Some(output), _) => Some(KeyOutput::Output(output.clone())),
+                    (None, Some(state)) => Some(KeyOutput::Dead(
+                        self.terminators.get(state).cloned().unwrap_or_default(),
+                    )),
+                    (None, None) => None,
+                }
+            }
+        }
+    }
+
+    /// What each dead key, by its terminator, combines the following output
+    /// into
+    pub fn dead_key_combinations(&self) -> BTreeMap<String, BTreeMap<String, String>> {
+        let mut combinations: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+        for whens in self.actions.values() {
+            let base = match whens
+                .iter()
+                .find(|w| w.state == "none")
+                .and_then(|w| w.output.as_ref())
+            {
+                Some(base) => base,
+                None => continue,
+            };
+            for when in whens.iter().filter(|w| w.state != "none") {
+                if let (Some(output), Some(terminator)) =
+                    (&when.output, self.terminators.get(&when.state))
+                {
+                    combinations
+                        .entry(terminator.clone())
+                        .or_default()
+                        .insert(base.clone(), output.clone());
+                }
+            }
+        }
+        combinations
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not read keylayout XML")]
+    InvalidXml { source: xml::reader::Error },
+    #[error("Missing `{}` attribute", attribute)]
+    MissingAttribute { attribute: String },
+    #[error("`{}` is not a number", value)]
+    InvalidNumber { value: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEYLAYOUT: &str = r#"<?xml version="1.1" encoding="UTF-8"?>
+<!DOCTYPE keyboard PUBLIC "" "file://localhost/System/Library/DTDs/KeyboardLayout.dtd">
+<keyboard group="126" id="-1234" name="Test">
+  <layouts>
+    <layout first="0" last="17" mapSet="default" modifiers="modifiers"/>
+  </layouts>
+  <modifierMap id="modifiers" defaultIndex="0">
+    <keyMapSelect mapIndex="0">
+      <modifier keys="command?"/>
+    </keyMapSelect>
+    <keyMapSelect mapIndex="1">
+      <modifier keys="anyShift caps? command?"/>
+    </keyMapSelect>
+    <keyMapSelect mapIndex="2">
+      <modifier keys="anyOption command?"/>
+    </keyMapSelect>
+  </modifierMap>
+  <keyMapSet id="default">
+    <keyMap index="0">
+      <key code="0" action="a000"/>
+      <key code="24" action="a001"/>
+      <key code="12" output="q"/>
+    </keyMap>
+    <keyMap index="1">
+      <key code="0" output="A"/>
+    </keyMap>
+    <keyMap index="2" baseMapSet="default" baseIndex="0"/>
+  </keyMapSet>
+  <actions>
+    <action id="a000">
+      <when state="none" output="a"/>
+      <when state="s000" output="á"/>
+    </action>
+    <action id="a001">
+      <when state="none" next="s000"/>
+      <when state="s000" output="´"/>
+    </action>
+  </actions>
+  <terminators>
+    <when state="s000" output="´"/>
+  </terminators>
+</keyboard>
+"#;
+
+    fn mode(name: &str) -> ModifierSet {
+        name.parse().unwrap()
+    }
+
+    #[test]
+    fn modifiers_select_key_maps() {
+        let layout = KeyLayout::parse(KEYLAYOUT.as_bytes()).unwrap();
+        assert_eq!(layout.name, "Test");
+        assert_eq!(layout.key_map_index(&mode("default")), Some(0));
+        assert_eq!(layout.key_map_index(&mode("caps+shift")), Some(1));
+        assert_eq!(layout.key_map_index(&mode("cmd+alt")), Some(2));
+        // Nothing matches, so the default index is used
+        assert!(!layout.selects(&mode("ctrl")));
+        assert_eq!(layout.key_map_index(&mode("ctrl")), Some(0));
+    }
+
+    #[test]
+    fn keys_resolve_through_actions_and_base_maps() {
+        let layout = KeyLayout::parse(KEYLAYOUT.as_bytes()).unwrap();
+        let output = |m: &str, key| layout.key(&mode(m), key);
+        assert_eq!(
+            output("default", IsoKey::C01),
+            Some(KeyOutput::Output("a".into()))
+        );
+        assert_eq!(
+            output("shift", IsoKey::C01),
+            Some(KeyOutput::Output("A".into()))
+        );
+        assert_eq!(
+            output("alt", IsoKey::D01),
+            Some(KeyOutput::Output("q".into()))
+        );
+        assert_eq!(
+            output("default", IsoKey::E12),
+            Some(KeyOutput::Dead("´".into()))
+        );
+        assert_eq!(output("shift", IsoKey::D01), None);
+
+        let combinations = layout.dead_key_combinations();
+        assert_eq!(combinations["´"]["a"], "á");
+    }
+}
@@ -0,0 +1,6 @@
+//! Resources for macOS keyboard layout bundles, and reading installed layouts
+
+pub mod icns;
+pub mod keylayout;
+pub use icns::{Error as IconError, IconFamily};
+pub use keylayout::{KeyLayout, KeyOutput};
@@ -1,3 +1,5 @@
+use kbdgen::cli::output::{Diagnostic, Output, Status};
+use kbdgen::codes::Code;
 use pyembed::{ExtensionModule, MainPythonInterpreter, OxidizedPythonInterpreterConfig};
 use std::path::PathBuf;
 use structopt::{clap::AppSettings::*, StructOpt};
@@ -105,6 +107,10 @@ enum BuildCommands {
 
         #[structopt(long = "standalone")]
         standalone: bool,
+
+        /// Compiles the generated symbols with `xkbcomp` and reports errors
+        #[structopt(long = "verify")]
+        verify: bool,
     },
 
     #[structopt(about = "Generates m17n output")]
@@ -144,11 +150,39 @@ enum BuildCommands {
         #[structopt(short, long = "layout")]
         layout: String,
     },
+
+    #[structopt(about = "Renders the mobile layers as screenshots for store listings")]
+    Screenshots {
+        #[structopt(flatten)]
+        in_out: InOutPaths,
+
+        #[structopt(short, long = "layout")]
+        layout: Option<String>,
+
+        /// Only write the SVG, without rasterising it to PNG with `rsvg-convert`
+        #[structopt(long)]
+        svg_only: bool,
+    },
+
+    #[structopt(about = "Generates a web app with an on-screen keyboard for trying the layouts")]
+    Web {
+        #[structopt(flatten)]
+        in_out: InOutPaths,
+
+        #[structopt(short, long = "layout")]
+        layout: Option<String>,
+    },
 }
 
 #[derive(Debug, StructOpt)]
 struct InOutPaths {
-    #[structopt(short, long = "output", default_value = ".", parse(from_os_str))]
+    #[structopt(
+        short,
+        long = "output",
+        env = "KBDGEN_OUTPUT_DIR",
+        default_value = ".",
+        parse(from_os_str)
+    )]
     output_path: PathBuf,
 
     #[structopt(parse(from_os_str))]
@@ -164,6 +198,10 @@ struct BuildMode {
     /// Continuous integration build
     #[structopt(long = "ci")]
     ci: bool,
+
+    /// Language that language pickers are sorted for (default: en)
+    #[structopt(long = "ui-locale")]
+    ui_locale: Option<String>,
 }
 
 #[derive(Debug, StructOpt)]
@@ -188,6 +226,145 @@ enum MetaCommands {
     Fetch { target: PathBuf },
 }
 
+#[derive(Debug, StructOpt)]
+enum TemplatesCommands {
+    #[structopt(about = "Copy the targets' templates into the bundle for offline builds")]
+    Vendor {
+        /// The `.kbdgen` bundle
+        project_path: PathBuf,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum CldrCommands {
+    #[structopt(about = "Import CLDR keyboard files into a layout of an existing bundle")]
+    Import {
+        /// The `.kbdgen` bundle to import into
+        #[structopt(long, parse(from_os_str))]
+        into: PathBuf,
+
+        /// The layout to import into, instead of the one named after the
+        /// keyboards' locale
+        #[structopt(long)]
+        layout: Option<String>,
+
+        /// Replace the modes of targets the layout already has
+        #[structopt(long)]
+        overwrite: bool,
+
+        /// The CLDR keyboard XML files
+        #[structopt(required = true, parse(from_os_str))]
+        files: Vec<PathBuf>,
+    },
+    #[structopt(about = "Compare a layout imported from CLDR with CLDR as it is now")]
+    DiffUpstream {
+        /// Use the CLDR checkout as it is, without updating it
+        #[structopt(long)]
+        offline: bool,
+
+        /// The layout's YAML file
+        #[structopt(parse(from_os_str))]
+        layout: PathBuf,
+    },
+    #[structopt(about = "Export the layouts of a bundle as CLDR keyboard files")]
+    Export {
+        /// Lay the files out as in the CLDR repository, with its copyright
+        /// header, ready to be submitted upstream
+        #[structopt(long)]
+        for_submission: bool,
+
+        /// Only export this layout
+        #[structopt(long)]
+        layout: Option<String>,
+
+        #[structopt(flatten)]
+        in_out: InOutPaths,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum LayoutCommands {
+    #[structopt(about = "Sort a layout's transforms by codepoint and remove duplicates")]
+    SortTransforms {
+        /// Exit with an error if the transforms are not sorted, instead of
+        /// rewriting the file
+        #[structopt(long)]
+        check: bool,
+
+        /// The layout's YAML file
+        layout: PathBuf,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum WinCommands {
+    #[structopt(about = "Generate the installer GUID of the target if it has none")]
+    Init {
+        /// The `.kbdgen` bundle
+        project_path: PathBuf,
+    },
+    #[structopt(about = "Write free keyboard ids into the layouts that have none")]
+    AllocateIds {
+        /// Exit with an error if any layout has no id, instead of writing
+        /// the ids
+        #[structopt(long)]
+        check: bool,
+
+        /// The `.kbdgen` bundle
+        project_path: PathBuf,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum WorkspaceCommands {
+    #[structopt(about = "Build every bundle of the workspace for a target")]
+    Build {
+        /// Build the remaining bundles after one fails, instead of stopping
+        #[structopt(long = "keep-going")]
+        keep_going: bool,
+
+        /// Each bundle is built into a directory of its own in here
+        #[structopt(
+            short,
+            long = "output",
+            env = "KBDGEN_OUTPUT_DIR",
+            default_value = ".",
+            parse(from_os_str)
+        )]
+        output_path: PathBuf,
+
+        /// The workspace, a directory with a `workspace.yaml`
+        #[structopt(parse(from_os_str))]
+        workspace_path: PathBuf,
+
+        /// The target and its options, as given to `kbdgen build`, like
+        /// `-- --report android --release`
+        #[structopt(last = true, required = true)]
+        args: Vec<String>,
+    },
+    #[structopt(about = "Lint every bundle of the workspace")]
+    Validate {
+        /// The workspace, a directory with a `workspace.yaml`
+        #[structopt(parse(from_os_str))]
+        workspace_path: PathBuf,
+    },
+    #[structopt(about = "Score the layouts of every bundle of the workspace")]
+    Stats {
+        /// The workspace, a directory with a `workspace.yaml`
+        #[structopt(parse(from_os_str))]
+        workspace_path: PathBuf,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum TargetCommands {
+    #[structopt(about = "Maintain the Windows target", setting(DisableHelpSubcommand))]
+    Win {
+        #[structopt(subcommand)]
+        command: WinCommands,
+    },
+}
+
 #[derive(Debug, StructOpt)]
 enum Commands {
     #[structopt(
@@ -201,6 +378,36 @@ enum Commands {
         #[structopt(long = "github-token")]
         github_token: Option<String>,
 
+        /// Leave the kbdgen version, bundle commit and build date out of the
+        /// generated files, so that builds are reproducible
+        #[structopt(long = "no-stamp")]
+        no_stamp: bool,
+
+        /// Refuse to build unless the bundle is signed with this public key,
+        /// see `kbdgen sign-bundle`
+        #[structopt(
+            long = "require-signature",
+            env = "KBDGEN_REQUIRE_SIGNATURE",
+            parse(from_os_str)
+        )]
+        require_signature: Option<PathBuf>,
+
+        /// Write what was generated, the warnings and how long it took to
+        /// `kbdgen-report.json` in the output directory
+        #[structopt(long)]
+        report: bool,
+
+        /// Keep what a failed build wrote, in the staging directory next to
+        /// the output directory, instead of removing it
+        #[structopt(long = "keep-failed")]
+        keep_failed: bool,
+
+        /// Where the files generated for each layout go in the output
+        /// directory, `per-target`, `per-locale` or `flat`, instead of each
+        /// target's own convention
+        #[structopt(long = "output-layout", env = "KBDGEN_OUTPUT_LAYOUT")]
+        output_layout: Option<kbdgen::output_layout::OutputLayout>,
+
         #[structopt(subcommand)]
         command: BuildCommands,
     },
@@ -217,6 +424,272 @@ enum Commands {
         #[structopt(subcommand)]
         command: MetaCommands,
     },
+    #[structopt(about = "Manage target project templates", setting(DisableHelpSubcommand))]
+    Templates {
+        #[structopt(subcommand)]
+        command: TemplatesCommands,
+    },
+    #[structopt(about = "Work with CLDR keyboard files", setting(DisableHelpSubcommand))]
+    Cldr {
+        #[structopt(subcommand)]
+        command: CldrCommands,
+    },
+    #[structopt(about = "Import a Keyman .kmn keyboard into a layout of a bundle")]
+    FromKmn {
+        /// The `.kbdgen` bundle to import into
+        #[structopt(long, parse(from_os_str))]
+        into: PathBuf,
+
+        /// The layout to import into, instead of the one named after the file
+        #[structopt(long)]
+        layout: Option<String>,
+
+        /// Replace the desktop modes if the layout already has them
+        #[structopt(long)]
+        overwrite: bool,
+
+        /// The Keyman keyboard source file
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+    },
+    #[structopt(about = "Import Android .kcm files and AOSP keyboard XML into a layout of a bundle")]
+    FromAndroid {
+        /// The `.kbdgen` bundle to import into
+        #[structopt(long, parse(from_os_str))]
+        into: PathBuf,
+
+        /// The layout to import into, instead of the one named after the
+        /// first file
+        #[structopt(long)]
+        layout: Option<String>,
+
+        /// Replace the modes of targets the layout already has
+        #[structopt(long)]
+        overwrite: bool,
+
+        /// `.kcm` files for the desktop modes and AOSP `.xml` files for the
+        /// android modes
+        #[structopt(required = true, parse(from_os_str))]
+        files: Vec<PathBuf>,
+    },
+    #[structopt(about = "Maintain layout files", setting(DisableHelpSubcommand))]
+    Layout {
+        #[structopt(subcommand)]
+        command: LayoutCommands,
+    },
+    #[structopt(about = "Maintain the settings of targets", setting(DisableHelpSubcommand))]
+    Target {
+        #[structopt(subcommand)]
+        command: TargetCommands,
+    },
+    #[structopt(
+        about = "Build, validate or score every bundle of a workspace",
+        setting(DisableHelpSubcommand)
+    )]
+    Workspace {
+        #[structopt(subcommand)]
+        command: WorkspaceCommands,
+    },
+    #[cfg(unix)]
+    #[structopt(about = "Answer JSON-RPC requests from editors and previews on a local socket")]
+    Serve {
+        /// The Unix domain socket to listen on, by default `kbdgen.sock` in
+        /// `XDG_RUNTIME_DIR`
+        #[structopt(long)]
+        socket: Option<PathBuf>,
+        /// A directory `generate` may write to besides the bundle
+        #[structopt(long)]
+        output_root: Option<PathBuf>,
+    },
+    #[structopt(about = "Run a language server for bundle files on stdin and stdout")]
+    Lsp,
+    #[structopt(about = "Compare a layout with the one installed on this system")]
+    CompareSystem {
+        #[structopt(long)]
+        target: kbdgen::Target,
+
+        /// An installed or exported layout file to compare with, instead of
+        /// looking it up by the layout's name
+        #[structopt(long = "system-file")]
+        system_file: Option<PathBuf>,
+
+        /// The layout's YAML file
+        layout: PathBuf,
+    },
+    #[structopt(about = "List every output of a layout with its codepoints and character names")]
+    Annotate {
+        /// Only list the modes for this target
+        #[structopt(long)]
+        target: Option<kbdgen::Target>,
+
+        /// The layout's YAML file
+        layout: PathBuf,
+    },
+    #[structopt(about = "Write a copy of a layout with its keys moved onto Dvorak, Colemak or Workman")]
+    Remap {
+        /// The arrangement to move the keys onto: dvorak, colemak or workman
+        #[structopt(long)]
+        base: kbdgen::ErgonomicBase,
+
+        /// The file to write, by default `<layout>-<base>.yaml` next to the
+        /// layout
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+
+        /// The layout's YAML file
+        layout: PathBuf,
+    },
+    #[structopt(about = "Explain an error or warning code, like E003 or W014")]
+    Explain {
+        /// The code, or the name of a lint rule; lists every code if left out
+        code: Option<String>,
+    },
+    #[structopt(about = "Warn about likely mistakes in a bundle")]
+    Lint {
+        /// Report every file that cannot be loaded and lint the rest, instead
+        /// of stopping at the first error
+        #[structopt(long = "keep-going")]
+        keep_going: bool,
+
+        /// Also warn about every character of the layouts that this font has
+        /// no glyph for, by mode. Can be given more than once, for fonts
+        /// used as fallbacks of each other.
+        #[structopt(long = "font", parse(from_os_str))]
+        fonts: Vec<PathBuf>,
+
+        /// The `.kbdgen` bundle
+        project_path: PathBuf,
+    },
+    #[structopt(about = "Check that layouts type what the bundle's `tests` directory expects")]
+    Test {
+        /// Also type the `x11` cases on the generated XKB symbols with
+        /// xkbcommon, as Linux would
+        #[structopt(long)]
+        xkb: bool,
+
+        /// The `.kbdgen` bundle
+        project_path: PathBuf,
+    },
+    #[structopt(about = "Score how complete the layouts of a bundle are")]
+    Score {
+        /// Write a shields.io JSON and an SVG badge for each layout to this
+        /// directory
+        #[structopt(long, parse(from_os_str))]
+        badges: Option<PathBuf>,
+
+        /// The `.kbdgen` bundle
+        project_path: PathBuf,
+    },
+    #[structopt(about = "Sign a bundle for distribution to build machines")]
+    SignBundle {
+        /// The secret key to sign with
+        #[structopt(long, env = "KBDGEN_SIGNING_KEY", parse(from_os_str))]
+        key: PathBuf,
+
+        /// Generate the key, and its public key next to it as `<key>.pub`
+        #[structopt(long = "generate-key")]
+        generate_key: bool,
+
+        /// The `.kbdgen` bundle
+        project_path: PathBuf,
+    },
+    #[structopt(about = "Show which keys type a character on a target")]
+    WhichKey {
+        #[structopt(long)]
+        target: kbdgen::Target,
+
+        /// Only use presses in this mode, like `alt+shift`; can be given more
+        /// than once
+        #[structopt(long = "mode")]
+        modes: Vec<kbdgen::ModifierSet>,
+
+        /// The layout's YAML file
+        layout: PathBuf,
+
+        /// The character to type
+        output: String,
+    },
+    #[structopt(about = "Show which keys of a mobile layer border which, as JSON")]
+    Adjacency {
+        #[structopt(long)]
+        target: kbdgen::Target,
+
+        /// The mode to take the keys from, like `shift`
+        #[structopt(long, default_value = "default")]
+        mode: kbdgen::MobileMode,
+
+        /// Write the graph to this file instead of showing it
+        #[structopt(short, long = "output", parse(from_os_str))]
+        output_path: Option<PathBuf>,
+
+        /// The layout's YAML file
+        layout: PathBuf,
+    },
+    #[structopt(about = "Check that the built layouts of a target are installed on this system")]
+    VerifyInstall {
+        /// One of mac, win or x11
+        #[structopt(long)]
+        target: kbdgen::Target,
+
+        /// Where the target was built to
+        #[structopt(
+            short,
+            long = "output",
+            env = "KBDGEN_OUTPUT_DIR",
+            default_value = ".",
+            parse(from_os_str)
+        )]
+        output_path: PathBuf,
+
+        /// Look for the installed files under this directory instead, like
+        /// a mounted disk; for Windows, the Windows directory
+        #[structopt(long, parse(from_os_str))]
+        root: Option<PathBuf>,
+
+        /// The `.kbdgen` bundle
+        project_path: PathBuf,
+    },
+    #[structopt(about = "Remove generated outputs and caches of targets")]
+    Clean {
+        #[structopt(
+            short,
+            long = "output",
+            env = "KBDGEN_OUTPUT_DIR",
+            default_value = ".",
+            parse(from_os_str)
+        )]
+        output_path: PathBuf,
+
+        /// Only clean this target, may be given more than once
+        #[structopt(long = "target")]
+        targets: Vec<kbdgen::Target>,
+
+        /// Only clean the outputs of this layout, may be given more than once
+        #[structopt(long = "layout")]
+        layouts: Vec<String>,
+
+        /// Also remove the targets' caches, like downloaded templates
+        #[structopt(long)]
+        caches: bool,
+
+        /// List what would be removed without removing it
+        #[structopt(short = "D", long = "dry-run")]
+        dry_run: bool,
+
+        /// The `.kbdgen` bundle
+        project_path: PathBuf,
+    },
+    #[structopt(about = "Print completions of kbdgen's commands for a shell")]
+    Completions {
+        /// One of bash, fish, zsh, powershell or elvish
+        #[structopt(possible_values = &structopt::clap::Shell::variants(), case_insensitive = true)]
+        shell: structopt::clap::Shell,
+    },
+    #[structopt(about = "Write manual pages of kbdgen's commands to a directory")]
+    Manpages {
+        #[structopt(parse(from_os_str))]
+        dir: PathBuf,
+    },
     #[structopt(setting(Hidden))]
     Repl,
 }
@@ -231,11 +704,75 @@ struct Opts {
     #[structopt(long = "logging", default_value = "info")]
     logging: String,
 
+    /// Print the result as one JSON object, see the documentation for its
+    /// fields and the exit codes
+    #[structopt(long, global = true)]
+    json: bool,
+
     #[structopt(subcommand)]
     command: Commands,
 }
 
 impl BuildCommands {
+    fn name(&self) -> &'static str {
+        use BuildCommands::*;
+
+        match self {
+            Svg { .. } => "svg",
+            Android { .. } => "android",
+            Win { .. } => "win",
+            X11 { .. } => "x11",
+            M17n { .. } => "m17n",
+            Chrome { .. } => "chrome",
+            Qr { .. } => "qr",
+            ErrorModel { .. } => "error-model",
+            Screenshots { .. } => "screenshots",
+            Web { .. } => "web",
+            #[cfg(target_os = "macos")]
+            IOS { .. } => "ios",
+            #[cfg(target_os = "macos")]
+            Mac { .. } => "mac",
+        }
+    }
+
+    fn in_out(&self) -> &InOutPaths {
+        use BuildCommands::*;
+
+        match self {
+            Svg { in_out, .. }
+            | Android { in_out, .. }
+            | Win { in_out, .. }
+            | X11 { in_out, .. }
+            | M17n { in_out, .. }
+            | Chrome { in_out, .. }
+            | Qr { in_out, .. }
+            | ErrorModel { in_out, .. }
+            | Screenshots { in_out, .. }
+            | Web { in_out, .. } => in_out,
+            #[cfg(target_os = "macos")]
+            IOS { in_out, .. } | Mac { in_out, .. } => in_out,
+        }
+    }
+
+    fn in_out_mut(&mut self) -> &mut InOutPaths {
+        use BuildCommands::*;
+
+        match self {
+            Svg { in_out, .. }
+            | Android { in_out, .. }
+            | Win { in_out, .. }
+            | X11 { in_out, .. }
+            | M17n { in_out, .. }
+            | Chrome { in_out, .. }
+            | Qr { in_out, .. }
+            | ErrorModel { in_out, .. }
+            | Screenshots { in_out, .. }
+            | Web { in_out, .. } => in_out,
+            #[cfg(target_os = "macos")]
+            IOS { in_out, .. } | Mac { in_out, .. } => in_out,
+        }
+    }
+
     async fn to_py_args<'a>(
         &'a self,
         github_username: Option<&'a str>,
@@ -244,6 +781,10 @@ impl BuildCommands {
     ) -> Result<Vec<&'a str>, Box<dyn std::error::Error>> {
         use BuildCommands::*;
 
+        // The Python generators expand macros themselves, with the values
+        // from CLDR looked up here
+        kbdgen::cli::export_macro_values(&self.in_out().project_path)?;
+
         let mut args = match self {
             Svg {
                 in_out:
@@ -270,8 +811,28 @@ impl BuildCommands {
                     },
                 dry_run,
                 local,
-                build_mode: BuildMode { release, ci },
+                build_mode: BuildMode { release, ci, ui_locale },
             } => {
+                if !*dry_run {
+                    kbdgen::cli::to_mobile_strings::kbdgen_to_mobile_strings(
+                        project_path,
+                        output_path,
+                        kbdgen::Target::Android,
+                    )?;
+                    kbdgen::cli::to_android_kcm::kbdgen_to_android_kcm(project_path, output_path)?;
+                    kbdgen::cli::to_autocorrect::kbdgen_to_autocorrect(
+                        project_path,
+                        output_path,
+                        kbdgen::Target::Android,
+                    )?;
+                }
+
+                if let Some(dir) =
+                    kbdgen::templates::prepare(project_path, output_path, kbdgen::Target::Android)?
+                {
+                    std::env::set_var("KBDGEN_TEMPLATE_DIR", dir);
+                }
+
                 let mut args = vec![
                     "-t",
                     "android",
@@ -299,6 +860,11 @@ impl BuildCommands {
                     args.push("--ci");
                 }
 
+                if let Some(ui_locale) = ui_locale {
+                    args.push("--ui-locale");
+                    args.push(ui_locale);
+                }
+
                 if *local {
                     args.push("--local");
                 }
@@ -318,8 +884,27 @@ impl BuildCommands {
                         project_path,
                     },
                 dry_run,
-                build_mode: BuildMode { release, ci },
+                build_mode: BuildMode { release, ci, ui_locale },
             } => {
+                if !*dry_run {
+                    kbdgen::cli::to_mobile_strings::kbdgen_to_mobile_strings(
+                        project_path,
+                        output_path,
+                        kbdgen::Target::Ios,
+                    )?;
+                    kbdgen::cli::to_autocorrect::kbdgen_to_autocorrect(
+                        project_path,
+                        output_path,
+                        kbdgen::Target::Ios,
+                    )?;
+                }
+
+                if let Some(dir) =
+                    kbdgen::templates::prepare(project_path, output_path, kbdgen::Target::Ios)?
+                {
+                    std::env::set_var("KBDGEN_TEMPLATE_DIR", dir);
+                }
+
                 let mut args = vec![
                     "-t",
                     "ios",
@@ -343,6 +928,11 @@ impl BuildCommands {
                     args.push("--ci");
                 }
 
+                if let Some(ui_locale) = ui_locale {
+                    args.push("--ui-locale");
+                    args.push(ui_locale);
+                }
+
                 match command {
                     Some(IOSCommands::Init) => {
                         args.push("--command");
@@ -364,7 +954,7 @@ impl BuildCommands {
                         output_path,
                         project_path,
                     },
-                build_mode: BuildMode { release, ci },
+                build_mode: BuildMode { release, ci, ui_locale },
             } => {
                 let mut args = vec!["-t", "chrome", "-o", &*output_path.to_str().unwrap()];
 
@@ -376,6 +966,11 @@ impl BuildCommands {
                     args.push("--ci");
                 }
 
+                if let Some(ui_locale) = ui_locale {
+                    args.push("--ui-locale");
+                    args.push(ui_locale);
+                }
+
                 args.push(&*project_path.to_str().unwrap());
                 args
             }
@@ -386,7 +981,7 @@ impl BuildCommands {
                         project_path,
                     },
                 dry_run,
-                build_mode: BuildMode { release, ci },
+                build_mode: BuildMode { release, ci, .. },
                 build_legacy,
             } => {
                 kbdgen::install_kbdi().await;
@@ -406,6 +1001,7 @@ impl BuildCommands {
                 }
                 std::env::set_var("KBDI", kbdi_pkg_path);
                 std::env::set_var("KBDI_LEGACY", kbdi_legacy_pkg_path);
+                kbdgen::cli::win_ids::export_compat_ids(project_path)?;
 
                 let mut args = vec!["-t", "win", "-o", &*output_path.to_str().unwrap()];
 
@@ -436,8 +1032,15 @@ impl BuildCommands {
                         project_path,
                     },
                 dry_run,
-                build_mode: BuildMode { release, ci },
+                build_mode: BuildMode { release, ci, .. },
             } => {
+                if !*dry_run {
+                    kbdgen::cli::to_macos_resources::kbdgen_to_macos_resources(
+                        project_path,
+                        output_path,
+                    )?;
+                }
+
                 let mut args = vec!["-t", "mac", "-o", &*output_path.to_str().unwrap()];
 
                 if *release {
@@ -471,7 +1074,7 @@ impl BuildCommands {
                 layout,
                 &*project_path.to_str().unwrap(),
             ],
-            ErrorModel { .. } | M17n { .. } | X11 { .. } => {
+            ErrorModel { .. } | M17n { .. } | X11 { .. } | Screenshots { .. } | Web { .. } => {
                 unreachable!("covered in previous match")
             }
         };
@@ -527,6 +1130,49 @@ fn python_config<'a>(args: &[&str]) -> OxidizedPythonInterpreterConfig<'a> {
     config
 }
 
+/// Uploads the web store zip of a release build, with the OAuth credentials
+/// that the Chrome generator checks are set
+async fn upload_chrome_package(
+    package: &kbdgen::cli::package_chrome::Package,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let var = |name: &str| std::env::var(name).map_err(|_| format!("`{}` is not set", name));
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post("https://www.googleapis.com/oauth2/v4/token")
+        .form(&[
+            ("grant_type", "refresh_token".to_string()),
+            ("client_id", var("CHROME_CLIENT_ID")?),
+            ("client_secret", var("CHROME_CLIENT_SECRET")?),
+            ("refresh_token", var("CHROME_REFRESH_TOKEN")?),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let response: serde_json::Value = serde_json::from_str(&response)?;
+    let access_token = response["access_token"]
+        .as_str()
+        .ok_or("The Chrome Web Store gave no access token")?;
+
+    log::info!("Uploading `{}`…", package.zip_path.display());
+    let response = client
+        .put(format!(
+            "https://www.googleapis.com/upload/chromewebstore/v1.1/items/{}",
+            package.app_id
+        ))
+        .bearer_auth(access_token)
+        .header("x-goog-api-version", "2")
+        .body(std::fs::read(&package.zip_path)?)
+        .send()
+        .await?
+        .error_for_status()?;
+    log::debug!("{:?}", response);
+
+    Ok(())
+}
+
 fn launch_py_kbdgen(args: &[&str]) -> i32 {
     // Load the default Python configuration as derived by the PyOxidizer config
     // file used at build time.
@@ -562,6 +1208,16 @@ fn launch_repl() -> i32 {
 
 #[tokio::main]
 async fn main() {
+    // The user's configuration sets the defaults of flags through the
+    // environment, so it is exported before the flags are parsed
+    match kbdgen::config::Config::load() {
+        Ok(config) => config.export(),
+        Err(e) => {
+            eprintln!("ERROR: {:?}", e);
+            std::process::exit(Status::Error.exit_code())
+        }
+    }
+
     let opt = Opts::from_args();
 
     let logging = match &*opt.logging {
@@ -572,112 +1228,1177 @@ async fn main() {
         "error" => log::Level::Error,
         x => {
             eprintln!("Invalid logging level: {}", x);
-            std::process::exit(1);
+            std::process::exit(Status::Error.exit_code());
         }
     };
 
-    env_logger::Builder::from_default_env()
+    let logger = env_logger::Builder::from_default_env()
         .filter(Some("kbdgen"), logging.to_level_filter())
         .target(env_logger::Target::Stderr)
-        .init();
+        .build();
+    log::set_max_level(logger.filter().max(log::LevelFilter::Warn));
+    log::set_boxed_logger(Box::new(kbdgen::build_report::WarningLog::new(logger)))
+        .expect("the logger is only set once");
 
     // info!("logging mode {}", logging);
 
     std::env::set_var("RUST_LOG", logging.to_string());
 
+    let json = opt.json;
+
+    if let Commands::Build {
+        no_stamp,
+        require_signature,
+        output_layout,
+        command,
+        ..
+    } = &opt.command
+    {
+        // The generators, the Python ones included, read it from the
+        // environment
+        if let Some(layout) = output_layout {
+            std::env::set_var(kbdgen::output_layout::OUTPUT_LAYOUT_VAR, layout.to_string());
+        }
+        // With `--json`, stdout is only for the JSON, so the Python
+        // generators and the tools they run write to stderr instead
+        if json {
+            std::env::set_var("KBDGEN_STDOUT_TO_STDERR", "1");
+        }
+        if let Some(key) = require_signature {
+            let project_path = &command.in_out().project_path;
+            if let Err(e) = kbdgen::cli::sign_bundle::verify(project_path, key) {
+                Output::new(&format!("build {}", command.name()), json).error(&e)
+            }
+            std::env::set_var(kbdgen::cli::REQUIRE_SIGNATURE_VAR, key);
+        }
+        if *no_stamp {
+            kbdgen::stamp::Stamp::disable();
+        } else if let Some(stamp) = kbdgen::stamp::Stamp::new(&command.in_out().project_path) {
+            stamp.export();
+        }
+    }
+
     match opt.command {
         Commands::Build {
             github_username,
             github_token,
-            command,
-        } => match command {
-            BuildCommands::X11 {
-                in_out:
-                    InOutPaths {
-                        output_path,
-                        project_path,
-                    },
-                build_mode: BuildMode { .. },
-                standalone,
-            } => kbdgen::cli::to_xkb::kbdgen_to_xkb(
-                &project_path,
-                &output_path,
-                &kbdgen::cli::to_xkb::Options { standalone },
-            )
-            .unwrap(),
-            BuildCommands::M17n {
-                in_out:
-                    InOutPaths {
-                        output_path,
-                        project_path,
-                    },
-                build_mode: BuildMode { .. },
-            } => kbdgen::cli::to_m17n_mim::kbdgen_to_mim(&project_path, &output_path).unwrap(),
-            BuildCommands::ErrorModel {
-                in_out:
-                    InOutPaths {
-                        output_path,
-                        project_path,
-                    },
-                layout,
-            } => kbdgen::cli::to_errormodel::kbdgen_to_errormodel(
-                &project_path,
-                &output_path,
-                &kbdgen::cli::to_errormodel::Options { layout },
-            )
-            .unwrap(),
-            command => match command
-                .to_py_args(
-                    github_username.as_ref().map(|x| &**x),
-                    github_token.as_ref().map(|x| &**x),
-                    &opt.logging,
+            report,
+            keep_failed,
+            mut command,
+            ..
+        } => {
+            let in_out = command.in_out();
+            let output_path = in_out.output_path.clone();
+            let out = Output::new(&format!("build {}", command.name()), json);
+            // Builds are always recorded, for the summary of what the target
+            // could not represent
+            let recorder = kbdgen::build_report::Recorder::start(
+                command.name(),
+                &in_out.project_path,
+                &in_out.output_path,
+            );
+            let recorder = Some(recorder.write_report(report));
+
+            // The generator writes into the staging directory, which is only
+            // moved into the output directory if the build succeeds
+            let staging =
+                match kbdgen::staging::Staging::start(command.name(), &output_path, keep_failed) {
+                    Ok(staging) => staging,
+                    Err(e) => out.error(&e),
+                };
+            command.in_out_mut().output_path = staging.path().to_path_buf();
+
+            let result: Result<(), Box<dyn std::error::Error>> = match command {
+                BuildCommands::X11 {
+                    in_out:
+                        InOutPaths {
+                            output_path,
+                            project_path,
+                        },
+                    build_mode: BuildMode { .. },
+                    standalone,
+                    verify,
+                } => kbdgen::cli::to_xkb::kbdgen_to_xkb(
+                    &project_path,
+                    &output_path,
+                    &kbdgen::cli::to_xkb::Options { standalone, verify },
                 )
-                .await
-            {
-                Ok(args) => {
-                    let args = args.iter().map(|x| x.to_string()).collect::<Vec<String>>();
-                    let exit_code = std::thread::spawn(move || {
-                        let args = args.iter().map(|x| &**x).collect::<Vec<_>>();
-                        launch_py_kbdgen(&args)
-                    })
-                        .join()
-                        .unwrap();
-                    std::process::exit(exit_code)
-                }
-                Err(e) => {
-                    eprintln!("{:?}", e);
-                    std::process::exit(1);
-                }
-            },
-        },
+                .map_err(Into::into),
+                BuildCommands::M17n {
+                    in_out:
+                        InOutPaths {
+                            output_path,
+                            project_path,
+                        },
+                    build_mode: BuildMode { .. },
+                } => kbdgen::cli::to_m17n_mim::kbdgen_to_mim(&project_path, &output_path)
+                    .map_err(Into::into),
+                BuildCommands::ErrorModel {
+                    in_out:
+                        InOutPaths {
+                            output_path,
+                            project_path,
+                        },
+                    layout,
+                } => kbdgen::cli::to_errormodel::kbdgen_to_errormodel(
+                    &project_path,
+                    &output_path,
+                    &kbdgen::cli::to_errormodel::Options { layout },
+                )
+                .map_err(Into::into),
+                BuildCommands::Screenshots {
+                    in_out:
+                        InOutPaths {
+                            output_path,
+                            project_path,
+                        },
+                    layout,
+                    svg_only,
+                } => kbdgen::cli::to_screenshots::kbdgen_to_screenshots(
+                    &project_path,
+                    &output_path,
+                    &kbdgen::cli::to_screenshots::Options { layout, svg_only },
+                )
+                .map_err(Into::into),
+                BuildCommands::Web {
+                    in_out:
+                        InOutPaths {
+                            output_path,
+                            project_path,
+                        },
+                    layout,
+                } => kbdgen::cli::to_web::kbdgen_to_web(
+                    &project_path,
+                    &output_path,
+                    &kbdgen::cli::to_web::Options { layout },
+                )
+                .map_err(Into::into),
+                command => match command
+                    .to_py_args(
+                        github_username.as_ref().map(|x| &**x),
+                        github_token.as_ref().map(|x| &**x),
+                        &opt.logging,
+                    )
+                    .await
+                {
+                    Ok(args) => {
+                        let args = args.iter().map(|x| x.to_string()).collect::<Vec<String>>();
+                        let exit_code = std::thread::spawn(move || {
+                            let args = args.iter().map(|x| &**x).collect::<Vec<_>>();
+                            launch_py_kbdgen(&args)
+                        })
+                            .join()
+                            .unwrap();
+
+                        match (exit_code, &command) {
+                            (0, BuildCommands::Chrome { in_out, build_mode }) => {
+                                match kbdgen::cli::package_chrome::package_chrome(
+                                    &in_out.project_path,
+                                    &in_out.output_path,
+                                ) {
+                                    Ok(package) if build_mode.release => {
+                                        upload_chrome_package(&package).await
+                                    }
+                                    Ok(_) => Ok(()),
+                                    Err(e) => Err(e.into()),
+                                }
+                            }
+                            (0, _) => Ok(()),
+                            (exit_code, command) => Err(format!(
+                                "The {} generator exited with code {}",
+                                command.name(),
+                                exit_code
+                            )
+                            .into()),
+                        }
+                    }
+                    Err(e) => Err(e),
+                },
+            };
+            let result = result.and_then(|()| staging.commit().map_err(Into::into));
+
+            out.finish_build(recorder, &output_path, result)
+        }
 
         Commands::New { command } => match command {
             NewCommands::Bundle {
                 bundle_name,
                 output_path,
-            } => match kbdgen::cli::from_cldr::cldr_to_kbdgen(&*output_path, &*bundle_name) {
-                Ok(_) => std::process::exit(0),
-                Err(e) => {
-                    eprintln!("{:?}", e);
-                    std::process::exit(1);
+            } => {
+                let mut out = Output::new("new bundle", json);
+                match kbdgen::cli::from_cldr::cldr_to_kbdgen(&*output_path, &*bundle_name) {
+                    Ok(_) => {
+                        out.artifact(output_path.join(&bundle_name));
+                        out.finish(Status::Ok)
+                    }
+                    Err(e) => out.error(&e),
                 }
-            },
+            }
             NewCommands::Layout { .. } => {
-                eprintln!("Not yet supported.");
-                std::process::exit(1)
+                let mut out = Output::new("new layout", json);
+                out.diagnostic(Diagnostic::error("Not yet supported.").with_code(Code::NotSupported));
+                out.finish(Status::Error)
             }
         },
 
         Commands::Meta { command } => match command {
-            MetaCommands::Fetch { target } => match meta::fetch(target).await {
-                Ok(_) => {}
-                Err(e) => {
-                    eprintln!("ERROR: {:?}", e);
-                    std::process::exit(1)
+            MetaCommands::Fetch { target } => {
+                let out = Output::new("meta fetch", json);
+                match meta::fetch(target).await {
+                    Ok(_) => out.finish(Status::Ok),
+                    Err(e) => out.error(e.as_ref()),
                 }
-            },
+            }
         },
 
+        Commands::Templates { command } => match command {
+            TemplatesCommands::Vendor { project_path } => {
+                let mut out = Output::new("templates vendor", json);
+                let options = kbdgen::cli::templates::Options { project_path };
+                match kbdgen::cli::templates::vendor(&options) {
+                    Ok(targets) if targets.is_empty() => {
+                        out.message("No targets have a template.");
+                        out.finish(Status::Ok)
+                    }
+                    Ok(targets) => {
+                        for target in &targets {
+                            out.artifact(
+                                options.project_path.join("templates").join(target.to_string()),
+                            );
+                            out.message(format!("Vendored the template for {}.", target));
+                        }
+                        out.data(&targets);
+                        out.finish(Status::Ok)
+                    }
+                    Err(e) => out.error(&e),
+                }
+            }
+        },
+
+        Commands::Cldr { command } => match command {
+            CldrCommands::Import {
+                into,
+                layout,
+                overwrite,
+                files,
+            } => {
+                let mut out = Output::new("cldr import", json);
+                let options = kbdgen::cli::from_cldr::ImportOptions {
+                    project_path: into,
+                    files,
+                    layout,
+                    overwrite,
+                };
+                match kbdgen::cli::from_cldr::import(&options) {
+                    Ok(report) => {
+                        let verb = if report.created { "Created" } else { "Updated" };
+                        for target in &report.imported {
+                            out.message(format!("Imported {} modes.", target));
+                        }
+                        for target in &report.skipped {
+                            out.message(format!(
+                                "Kept the existing {} modes, pass --overwrite to replace them.",
+                                target
+                            ));
+                        }
+                        if !report.imported.is_empty() {
+                            out.artifact(layout_path(&options.project_path, &report.layout));
+                            out.message(format!("{} layout `{}`.", verb, report.layout));
+                        }
+                        out.data(serde_json::json!({
+                            "layout": report.layout,
+                            "created": report.created,
+                            "imported": report.imported,
+                            "skipped": report.skipped,
+                        }));
+                        out.finish(Status::Ok)
+                    }
+                    Err(e) => out.error(&e),
+                }
+            }
+            CldrCommands::DiffUpstream { offline, layout } => {
+                let mut out = Output::new("cldr diff-upstream", json);
+                let options = kbdgen::cli::cldr_diff::Options { layout, offline };
+                match kbdgen::cli::cldr_diff::diff_upstream(&options) {
+                    Ok(differences) => {
+                        for difference in &differences {
+                            out.message(difference.to_string());
+                        }
+                        if differences.is_empty() {
+                            out.message("The layout matches CLDR.");
+                        }
+                        out.data(&differences);
+                        out.finish(Status::failed_if(!differences.is_empty()))
+                    }
+                    Err(e) => out.error(&e),
+                }
+            }
+            CldrCommands::Export {
+                for_submission,
+                layout,
+                in_out,
+            } => {
+                let mut out = Output::new("cldr export", json);
+                let options = kbdgen::cli::to_cldr::Options {
+                    for_submission,
+                    layout,
+                };
+                match kbdgen::cli::to_cldr::kbdgen_to_cldr(
+                    &in_out.project_path,
+                    &in_out.output_path,
+                    &options,
+                ) {
+                    Ok(paths) => {
+                        for path in &paths {
+                            out.artifact(path);
+                        }
+                        out.message(format!("Wrote {} CLDR keyboard file(s).", paths.len()));
+                        out.finish(Status::Ok)
+                    }
+                    Err(e) => out.error(&e),
+                }
+            }
+        },
+
+        Commands::FromKmn {
+            into,
+            layout,
+            overwrite,
+            file,
+        } => {
+            let mut out = Output::new("from-kmn", json);
+            let options = kbdgen::cli::from_kmn::Options {
+                project_path: into,
+                file,
+                layout,
+                overwrite,
+            };
+            match kbdgen::cli::from_kmn::import(&options) {
+                Ok(report) if report.kept => {
+                    out.message(format!(
+                        "Kept the existing desktop modes of `{}`, pass --overwrite to replace them.",
+                        report.layout
+                    ));
+                    out.finish(Status::Ok)
+                }
+                Ok(report) => {
+                    for skipped in &report.skipped {
+                        out.diagnostic(
+                            Diagnostic::warning(format!(
+                                "Skipped line {}: {}",
+                                skipped.line, skipped.reason
+                            ))
+                            .with_code(Code::SkippedLine)
+                            .with_path(&options.file),
+                        );
+                    }
+                    let verb = if report.created { "Created" } else { "Updated" };
+                    out.artifact(layout_path(&options.project_path, &report.layout));
+                    out.message(format!(
+                        "{} layout `{}` with {} transforms.",
+                        verb, report.layout, report.transforms
+                    ));
+                    out.data(serde_json::json!({
+                        "layout": report.layout,
+                        "created": report.created,
+                        "transforms": report.transforms,
+                    }));
+                    out.finish(Status::Ok)
+                }
+                Err(e) => out.error(&e),
+            }
+        }
+
+        Commands::FromAndroid {
+            into,
+            layout,
+            overwrite,
+            files,
+        } => {
+            let mut out = Output::new("from-android", json);
+            let options = kbdgen::cli::from_android::ImportOptions {
+                project_path: into,
+                files,
+                layout,
+                overwrite,
+            };
+            match kbdgen::cli::from_android::import(&options) {
+                Ok(report) => {
+                    let verb = if report.created { "Created" } else { "Updated" };
+                    for target in &report.imported {
+                        out.message(format!("Imported {} modes.", target));
+                    }
+                    for target in &report.skipped {
+                        out.message(format!(
+                            "Kept the existing {} modes, pass --overwrite to replace them.",
+                            target
+                        ));
+                    }
+                    if !report.imported.is_empty() {
+                        out.artifact(layout_path(&options.project_path, &report.layout));
+                        out.message(format!("{} layout `{}`.", verb, report.layout));
+                    }
+                    out.data(serde_json::json!({
+                        "layout": report.layout,
+                        "created": report.created,
+                        "imported": report.imported,
+                        "skipped": report.skipped,
+                    }));
+                    out.finish(Status::Ok)
+                }
+                Err(e) => out.error(&e),
+            }
+        }
+
+        Commands::Layout { command } => match command {
+            LayoutCommands::SortTransforms { check, layout } => {
+                let mut out = Output::new("layout sort-transforms", json);
+                let options = kbdgen::cli::sort_transforms::Options { layout, check };
+                match kbdgen::cli::sort_transforms::sort_transforms(&options) {
+                    Ok(report) => {
+                        out.data(serde_json::json!({
+                            "changed": report.changed,
+                            "removed": report.removed,
+                        }));
+                        if !report.changed {
+                            out.message("Transforms are sorted.");
+                            out.finish(Status::Ok)
+                        } else if check {
+                            out.diagnostic(
+                                Diagnostic::error(format!(
+                                    "Transforms are not sorted, {} duplicates.",
+                                    report.removed
+                                ))
+                                .with_code(Code::UnsortedTransforms)
+                                .with_path(&options.layout),
+                            );
+                            out.finish(Status::Failed)
+                        } else {
+                            out.artifact(&options.layout);
+                            out.message(format!(
+                                "Sorted transforms, removed {} duplicates.",
+                                report.removed
+                            ));
+                            out.finish(Status::Ok)
+                        }
+                    }
+                    Err(e @ kbdgen::cli::sort_transforms::Error::Conflicts { .. }) => {
+                        out.diagnostic(
+                            Diagnostic::error(e.to_string())
+                                .with_code(Code::ConflictingTransforms)
+                                .with_path(&options.layout),
+                        );
+                        out.finish(Status::Failed)
+                    }
+                    Err(e) => out.error(&e),
+                }
+            }
+        },
+
+        #[cfg(unix)]
+        Commands::Serve {
+            socket,
+            output_root,
+        } => {
+            let options = kbdgen::cli::serve::Options {
+                socket: socket.unwrap_or_else(kbdgen::cli::serve::default_socket),
+                output_root,
+            };
+            if let Err(e) = kbdgen::cli::serve::serve(&options) {
+                Output::new("serve", json).error(&e)
+            }
+        }
+
+        Commands::Lsp => {
+            let stdin = std::io::stdin();
+            if let Err(e) = kbdgen::lsp::run(stdin.lock(), std::io::stdout()) {
+                Output::new("lsp", json).error(&e)
+            }
+        }
+
+        Commands::CompareSystem {
+            target,
+            system_file,
+            layout,
+        } => {
+            let mut out = Output::new("compare-system", json);
+            let options = kbdgen::cli::compare_system::Options {
+                target,
+                layout,
+                system_file,
+            };
+            match kbdgen::cli::compare_system::compare_system(&options) {
+                Ok(differences) if differences.is_empty() => {
+                    out.message("The installed layout matches.");
+                    out.finish(Status::Ok)
+                }
+                Ok(differences) => {
+                    for difference in &differences {
+                        out.diagnostic(
+                            Diagnostic::error(difference.to_string())
+                                .with_code(Code::SystemLayoutDiffers),
+                        );
+                    }
+                    out.finish(Status::Failed)
+                }
+                Err(e) => out.error(&e),
+            }
+        }
+
+        Commands::VerifyInstall {
+            target,
+            output_path,
+            root,
+            project_path,
+        } => {
+            let mut out = Output::new("verify-install", json);
+            let options = kbdgen::cli::verify_install::Options {
+                target,
+                project_path,
+                output_path,
+                root,
+            };
+            match kbdgen::cli::verify_install::verify_install(&options) {
+                Ok(installation) => {
+                    let problems = installation.problems();
+                    if problems.is_empty() {
+                        out.message(format!(
+                            "The {} layouts are installed as built.",
+                            installation.files.len()
+                        ));
+                    }
+                    for problem in &problems {
+                        out.diagnostic(
+                            Diagnostic::error(problem.to_string()).with_code(Code::InstallProblem),
+                        );
+                    }
+                    out.data(installation);
+                    out.finish(Status::failed_if(!problems.is_empty()))
+                }
+                Err(e) => out.error(&e),
+            }
+        }
+
+        Commands::Target {
+            command:
+                TargetCommands::Win {
+                    command: WinCommands::Init { project_path },
+                },
+        } => {
+            let mut out = Output::new("target win init", json);
+            let options = kbdgen::cli::win_init::Options { project_path };
+            match kbdgen::cli::win_init::init(&options) {
+                Ok(report) => {
+                    out.data(serde_json::json!({
+                        "uuid": report.uuid,
+                        "generated": report.generated,
+                    }));
+                    if report.generated {
+                        out.message(format!(
+                            "Wrote uuid {} to `{}`.",
+                            report.uuid,
+                            report.path.display()
+                        ));
+                        out.artifact(report.path);
+                    } else {
+                        out.message(format!("The target already has uuid {}.", report.uuid));
+                    }
+                    out.finish(Status::Ok)
+                }
+                Err(e) => out.error(&e),
+            }
+        }
+
+        Commands::Target {
+            command:
+                TargetCommands::Win {
+                    command: WinCommands::AllocateIds { check, project_path },
+                },
+        } => {
+            let mut out = Output::new("target win allocate-ids", json);
+            let options = kbdgen::cli::win_ids::Options {
+                project_path,
+                check,
+            };
+            match kbdgen::cli::win_ids::allocate_ids(&options) {
+                Ok(report) => {
+                    let allocation = &report.allocation;
+                    out.data(serde_json::json!({
+                        "ids": allocation.ids,
+                        "skipped": allocation.skipped,
+                    }));
+                    for layout in &allocation.skipped {
+                        out.diagnostic(
+                            Diagnostic::warning(format!(
+                                "`{}` has variants, which would share its id; set `targets.win.id` by hand.",
+                                layout
+                            ))
+                            .with_code(Code::SharedWinId),
+                        );
+                    }
+                    for (layout, id) in &allocation.ids {
+                        out.message(format!("{}: kbd{}.dll", layout, id));
+                    }
+                    for path in report.written {
+                        out.artifact(path);
+                    }
+                    if allocation.ids.is_empty() {
+                        out.message("Every layout has an id.");
+                        out.finish(Status::Ok)
+                    } else if check {
+                        out.diagnostic(
+                            Diagnostic::error(format!(
+                                "{} layouts have no id.",
+                                allocation.ids.len()
+                            ))
+                            .with_code(Code::MissingWinIds),
+                        );
+                        out.finish(Status::Failed)
+                    } else {
+                        out.finish(Status::Ok)
+                    }
+                }
+                Err(e) => out.error(&e),
+            }
+        }
+
+        Commands::Workspace {
+            command:
+                WorkspaceCommands::Build {
+                    keep_going,
+                    output_path,
+                    workspace_path,
+                    args,
+                },
+        } => {
+            let mut out = Output::new("workspace build", json);
+            let workspace = match kbdgen::cli::workspace::load(&workspace_path) {
+                Ok(workspace) => workspace,
+                Err(e) => out.error(&e),
+            };
+            let options = kbdgen::cli::workspace::BuildOptions {
+                output_path,
+                args,
+                keep_going,
+            };
+            match kbdgen::cli::workspace::build(&workspace, &options) {
+                Ok(built) => {
+                    let mut results = std::collections::BTreeMap::new();
+                    for (member, envelope) in built {
+                        for diagnostic in envelope.diagnostics.iter().cloned() {
+                            out.diagnostic(Diagnostic {
+                                message: format!("{}: {}", member.name, diagnostic.message),
+                                ..diagnostic
+                            });
+                        }
+                        for artifact in envelope.artifacts.iter().cloned() {
+                            out.artifact(artifact);
+                        }
+                        out.message(match envelope.status {
+                            Status::Ok => format!("Built `{}`.", member.name),
+                            _ => format!("Could not build `{}`.", member.name),
+                        });
+                        results.insert(member.name, envelope);
+                    }
+                    let failed = results.values().any(|envelope| envelope.exit_code != 0);
+                    let skipped = workspace.members.len() - results.len();
+                    if skipped > 0 {
+                        out.message(format!(
+                            "Skipped {} bundles, pass --keep-going to build them as well.",
+                            skipped
+                        ));
+                    }
+                    out.data(results);
+                    out.finish(Status::failed_if(failed))
+                }
+                Err(e) => out.error(&e),
+            }
+        }
+
+        Commands::Workspace {
+            command: WorkspaceCommands::Validate { workspace_path },
+        } => {
+            let mut out = Output::new("workspace validate", json);
+            let validations = kbdgen::cli::workspace::load(&workspace_path)
+                .and_then(|workspace| kbdgen::cli::workspace::validate(&workspace));
+            match validations {
+                Ok(validations) => {
+                    let mut results = std::collections::BTreeMap::new();
+                    for validation in &validations {
+                        let name = &validation.member.name;
+                        for error in &validation.errors {
+                            out.diagnostic(
+                                Diagnostic::error(format!("{}: {}", name, error)).with_code(error),
+                            );
+                        }
+                        for warning in &validation.denied {
+                            out.diagnostic(
+                                Diagnostic::error(format!("{}: {}", name, warning))
+                                    .with_code(warning.rule),
+                            );
+                        }
+                        for warning in &validation.warnings {
+                            out.diagnostic(
+                                Diagnostic::warning(format!("{}: {}", name, warning))
+                                    .with_code(warning.rule),
+                            );
+                        }
+                        results.insert(
+                            name.clone(),
+                            serde_json::json!({
+                                "valid": validation.is_valid(),
+                                "errors": validation.errors.len() + validation.denied.len(),
+                                "warnings": validation.warnings.len(),
+                            }),
+                        );
+                    }
+                    let invalid = validations.iter().filter(|v| !v.is_valid()).count();
+                    out.message(format!(
+                        "{} of {} bundles are valid.",
+                        validations.len() - invalid,
+                        validations.len()
+                    ));
+                    out.data(results);
+                    out.finish(Status::failed_if(invalid > 0))
+                }
+                Err(e) => out.error(&e),
+            }
+        }
+
+        Commands::Workspace {
+            command: WorkspaceCommands::Stats { workspace_path },
+        } => {
+            let mut out = Output::new("workspace stats", json);
+            let stats = kbdgen::cli::workspace::load(&workspace_path)
+                .and_then(|workspace| kbdgen::cli::workspace::stats(&workspace));
+            match stats {
+                Ok(stats) => {
+                    let percent = |part: Option<f64>| match part {
+                        Some(part) => format!("{:.0}%", part * 100.0),
+                        None => "-".to_string(),
+                    };
+                    out.message("bundle\tlayout\ttotal\torthography\ttargets\tcaps\tlongpress");
+                    let mut results = std::collections::BTreeMap::new();
+                    for stats in stats {
+                        for (name, score) in &stats.scores {
+                            out.message(format!(
+                                "{}\t{}\t{}%\t{}\t{}\t{}\t{}",
+                                stats.member.name,
+                                name,
+                                score.percent(),
+                                percent(score.orthography),
+                                percent(Some(score.targets)),
+                                percent(score.caps),
+                                percent(score.longpress)
+                            ));
+                        }
+                        results.insert(
+                            stats.member.name,
+                            stats.scores.into_iter().collect::<std::collections::BTreeMap<_, _>>(),
+                        );
+                    }
+                    let layouts = results.values().map(|scores| scores.len()).sum::<usize>();
+                    out.message(format!("{} layouts in {} bundles.", layouts, results.len()));
+                    out.data(results);
+                    out.finish(Status::Ok)
+                }
+                Err(e) => out.error(&e),
+            }
+        }
+
+        Commands::Remap {
+            base,
+            output,
+            layout,
+        } => {
+            let mut out = Output::new("remap", json);
+            let options = kbdgen::cli::remap::Options {
+                layout,
+                base,
+                output,
+            };
+            match kbdgen::cli::remap::remap(&options) {
+                Ok(path) => {
+                    out.message(format!("Wrote `{}`.", path.display()));
+                    out.artifact(path);
+                    out.finish(Status::Ok)
+                }
+                Err(e) => out.error(&e),
+            }
+        }
+
+        Commands::Annotate { target, layout } => {
+            let mut out = Output::new("annotate", json);
+            let options = kbdgen::cli::annotate::Options { layout, target };
+            match kbdgen::cli::annotate::annotate(&options) {
+                Ok(annotations) => {
+                    if !json {
+                        print!("{}", kbdgen::cli::annotate::format_table(&annotations));
+                    }
+                    let data: Vec<_> = annotations
+                        .iter()
+                        .map(|annotation| {
+                            serde_json::json!({
+                                "location": annotation.location,
+                                "output": annotation.output,
+                                "codepoints": annotation.codepoints(),
+                                "names": annotation.names(),
+                            })
+                        })
+                        .collect();
+                    out.data(data);
+                    out.finish(Status::Ok)
+                }
+                Err(e) => out.error(&e),
+            }
+        }
+
+        Commands::Explain { code } => {
+            let mut out = Output::new("explain", json);
+            match code.as_deref().map(str::parse::<Code>) {
+                Some(Ok(code)) => {
+                    out.message(code.explanation().trim_end());
+                    out.data(serde_json::json!({
+                        "code": code,
+                        "name": code.name(),
+                        "summary": code.summary(),
+                        "explanation": code.explanation(),
+                    }));
+                    out.finish(Status::Ok)
+                }
+                Some(Err(e)) => out.error(&e),
+                None => {
+                    use strum::IntoEnumIterator;
+                    let codes = Code::iter()
+                        .map(|code| {
+                            out.message(format!(
+                                "{}  {:<30} {}",
+                                code,
+                                code.name(),
+                                code.summary()
+                            ));
+                            serde_json::json!({
+                                "code": code,
+                                "name": code.name(),
+                                "summary": code.summary(),
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    out.data(codes);
+                    out.finish(Status::Ok)
+                }
+            }
+        }
+
+        Commands::Lint {
+            keep_going,
+            fonts,
+            project_path,
+        } => {
+            let mut out = Output::new("lint", json);
+            let options = kbdgen::cli::lint::Options {
+                project_path,
+                keep_going,
+                fonts,
+            };
+            match kbdgen::cli::lint::lint(&options) {
+                Ok(report)
+                    if report.errors.is_empty()
+                        && report.denied.is_empty()
+                        && report.warnings.is_empty() =>
+                {
+                    out.message("No warnings.");
+                    out.finish(Status::Ok)
+                }
+                Ok(report) => {
+                    for error in &report.errors {
+                        out.diagnostic(Diagnostic::error(error.to_string()).with_code(error));
+                    }
+                    for warning in &report.denied {
+                        out.diagnostic(
+                            Diagnostic::error(warning.to_string()).with_code(warning.rule),
+                        );
+                    }
+                    for warning in &report.warnings {
+                        out.diagnostic(
+                            Diagnostic::warning(warning.to_string()).with_code(warning.rule),
+                        );
+                    }
+                    out.finish(Status::failed_if(
+                        !report.errors.is_empty() || !report.denied.is_empty(),
+                    ))
+                }
+                Err(e) => out.error(&e),
+            }
+        }
+
+        Commands::Test { xkb, project_path } => {
+            let mut out = Output::new("test", json);
+            let options = kbdgen::cli::test::Options { project_path, xkb };
+            match kbdgen::cli::test::test(&options) {
+                Ok(report) => {
+                    let failures = report
+                        .failures
+                        .iter()
+                        .map(|failure| (failure, ""))
+                        .chain(
+                            report
+                                .xkb_failures
+                                .iter()
+                                .map(|failure| (failure, " with xkbcommon")),
+                        );
+                    for ((path, failure), engine) in failures {
+                        let name = failure
+                            .name
+                            .as_ref()
+                            .map(|name| format!(" ({})", name))
+                            .unwrap_or_default();
+                        out.diagnostic(
+                            Diagnostic::error(format!(
+                                "`{}`{} on {}{} typed {:?}, expected {:?}",
+                                failure.keys,
+                                name,
+                                failure.target,
+                                engine,
+                                failure.actual,
+                                failure.expected
+                            ))
+                            .with_code(Code::LayoutTestFailed)
+                            .with_path(path),
+                        );
+                    }
+                    out.message(format!(
+                        "{} passed, {} failed in {} files.",
+                        report.passed,
+                        report.failures.len(),
+                        report.files
+                    ));
+                    if xkb {
+                        out.message(format!(
+                            "With xkbcommon: {} passed, {} failed.",
+                            report.xkb_passed,
+                            report.xkb_failures.len()
+                        ));
+                    }
+                    out.data(serde_json::json!({
+                        "files": report.files,
+                        "passed": report.passed,
+                        "failed": report.failures.len(),
+                        "xkb": if xkb {
+                            serde_json::json!({
+                                "passed": report.xkb_passed,
+                                "failed": report.xkb_failures.len(),
+                            })
+                        } else {
+                            serde_json::Value::Null
+                        },
+                    }));
+                    out.finish(Status::failed_if(
+                        !report.failures.is_empty() || !report.xkb_failures.is_empty(),
+                    ))
+                }
+                Err(e) => out.error(&e),
+            }
+        }
+
+        Commands::Score {
+            badges,
+            project_path,
+        } => {
+            let mut out = Output::new("score", json);
+            let options = kbdgen::cli::score::Options {
+                project_path,
+                badges,
+            };
+            match kbdgen::cli::score::score(&options) {
+                Ok(scores) => {
+                    let percent = |part: Option<f64>| match part {
+                        Some(part) => format!("{:.0}%", part * 100.0),
+                        None => "-".to_string(),
+                    };
+                    out.message("layout\ttotal\torthography\ttargets\tcaps\tlongpress");
+                    for (name, score) in &scores {
+                        out.message(format!(
+                            "{}\t{}%\t{}\t{}\t{}\t{}",
+                            name,
+                            score.percent(),
+                            percent(score.orthography),
+                            percent(Some(score.targets)),
+                            percent(score.caps),
+                            percent(score.longpress)
+                        ));
+                        if let Some(dir) = &options.badges {
+                            out.artifact(dir.join(format!("{}.json", name)));
+                            out.artifact(dir.join(format!("{}.svg", name)));
+                        }
+                    }
+                    out.data(scores.into_iter().collect::<std::collections::BTreeMap<_, _>>());
+                    out.finish(Status::Ok)
+                }
+                Err(e) => out.error(&e),
+            }
+        }
+
+        Commands::SignBundle {
+            key,
+            generate_key,
+            project_path,
+        } => {
+            let mut out = Output::new("sign-bundle", json);
+            let options = kbdgen::cli::sign_bundle::Options {
+                project_path,
+                key,
+                generate_key,
+            };
+            match kbdgen::cli::sign_bundle::sign_bundle(&options) {
+                Ok(report) => {
+                    out.message(format!(
+                        "Signed {} files with {}",
+                        report.files, report.public_key
+                    ));
+                    out.artifact(
+                        options
+                            .project_path
+                            .join(kbdgen::signature::SIGNATURE_FILE),
+                    );
+                    if options.generate_key {
+                        out.artifact(&options.key);
+                        out.artifact(kbdgen::signature::public_key_path(&options.key));
+                    }
+                    out.data(serde_json::json!({
+                        "files": report.files,
+                        "publicKey": report.public_key.to_string(),
+                    }));
+                    out.finish(Status::Ok)
+                }
+                Err(e) => out.error(&e),
+            }
+        }
+
+        Commands::WhichKey {
+            target,
+            modes,
+            layout,
+            output,
+        } => {
+            let mut out = Output::new("which-key", json);
+            let options = kbdgen::cli::which_key::Options {
+                layout,
+                target,
+                modes,
+                output,
+            };
+            match kbdgen::cli::which_key::which_key(&options) {
+                Ok(ways) if ways.is_empty() => {
+                    out.diagnostic(
+                        Diagnostic::error(format!(
+                            "`{}` cannot be typed on {}.",
+                            options.output, target
+                        ))
+                        .with_code(Code::Untypable),
+                    );
+                    out.finish(Status::Failed)
+                }
+                Ok(ways) => {
+                    let ways: Vec<_> = ways.iter().map(ToString::to_string).collect();
+                    for way in &ways {
+                        out.message(way.clone());
+                    }
+                    out.data(ways);
+                    out.finish(Status::Ok)
+                }
+                Err(e) => out.error(&e),
+            }
+        }
+
+        Commands::Adjacency {
+            target,
+            mode,
+            output_path,
+            layout,
+        } => {
+            let mut out = Output::new("adjacency", json);
+            let options = kbdgen::cli::adjacency::Options {
+                layout,
+                target,
+                mode,
+            };
+            match kbdgen::cli::adjacency::adjacency(&options) {
+                Ok(graph) => {
+                    let text = serde_json::to_string_pretty(&graph).expect("graphs serialize");
+                    match output_path {
+                        Some(path) => {
+                            if let Err(e) = std::fs::write(&path, text) {
+                                out.error(&e)
+                            }
+                            out.artifact(path);
+                        }
+                        // The graph is the envelope's data with `--json`
+                        None if !json => println!("{}", text),
+                        None => {}
+                    }
+                    out.data(graph);
+                    out.finish(Status::Ok)
+                }
+                Err(e) => out.error(&e),
+            }
+        }
+
+        Commands::Clean {
+            output_path,
+            targets,
+            layouts,
+            caches,
+            dry_run,
+            project_path,
+        } => {
+            let mut out = Output::new("clean", json);
+            let options = kbdgen::cli::clean::Options {
+                project_path,
+                output_path,
+                targets,
+                layouts,
+                caches,
+                dry_run,
+            };
+            match kbdgen::cli::clean::clean(&options) {
+                Ok(paths) if paths.is_empty() => {
+                    out.message("Nothing to remove.");
+                    out.finish(Status::Ok)
+                }
+                Ok(paths) => {
+                    for path in &paths {
+                        if dry_run {
+                            out.message(format!("Would remove {}", path.display()));
+                        } else {
+                            out.message(format!("Removed {}", path.display()));
+                        }
+                    }
+                    out.data(paths);
+                    out.finish(Status::Ok)
+                }
+                Err(e) => out.error(&e),
+            }
+        }
+
+        Commands::Completions { shell } => {
+            Opts::clap().gen_completions_to("kbdgen", shell, &mut std::io::stdout());
+        }
+
+        Commands::Manpages { dir } => {
+            let mut out = Output::new("manpages", json);
+            match kbdgen::cli::manpages::write_manpages(&Opts::clap(), &dir) {
+                Ok(paths) => {
+                    for path in paths {
+                        out.message(format!("Wrote {}", path.display()));
+                        out.artifact(path);
+                    }
+                    out.finish(Status::Ok)
+                }
+                Err(e) => out.error(&e),
+            }
+        }
+
         Commands::Repl => {
             let exit_code = std::thread::spawn(|| launch_repl()).join().unwrap();
             std::process::exit(exit_code)
@@ -685,6 +2406,13 @@ async fn main() {
     }
 }
 
+/// The file of a layout in a bundle
+fn layout_path(project_path: &std::path::Path, layout: &str) -> PathBuf {
+    project_path
+        .join("layouts")
+        .join(format!("{}.yaml", layout))
+}
+
 pub(crate) mod meta {
     use super::*;
     use serde::{Deserialize, Serialize};
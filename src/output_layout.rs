@@ -0,0 +1,133 @@
+//! Where the files generated for each layout go in the output directory
+//!
+//! Targets that write a file per layout, like `x11` with `linux/se.xkb` and
+//! `m17n` with `se/linux.mim`, each have a convention of their own. A build
+//! can lay them all out the same way instead, with `--output-layout`,
+//! `KBDGEN_OUTPUT_LAYOUT` or `output_layout` in `config.toml`:
+//!
+//! - `per-target`: a directory per target, like `x11/se.xkb` and
+//!   `m17n/se-linux.mim`
+//! - `per-locale`: a directory per layout, like `se/x11.xkb` and
+//!   `se/m17n-linux.mim`
+//! - `flat`: every file in the output directory, like `se.xkb` and
+//!   `se-linux.mim`
+//!
+//! Targets that build an app or installer, like `android` and `win`, lay out
+//! their projects as the platform expects and are not affected, other than
+//! the `.klc` `win` writes for each keyboard DLL, like `win/se-kbdsme.klc`.
+
+use serde::Deserialize;
+use std::path::PathBuf;
+use strum_macros::{Display, EnumIter, EnumString};
+
+/// The layout for the generators, which `kbdgen build --output-layout` sets
+pub const OUTPUT_LAYOUT_VAR: &str = "KBDGEN_OUTPUT_LAYOUT";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Display, EnumString, EnumIter, Deserialize)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputLayout {
+    PerTarget,
+    PerLocale,
+    Flat,
+}
+
+/// A file generated for a layout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LayoutFile<'a> {
+    /// Like `x11`, which names the directory of `per-target`
+    pub target: &'a str,
+    pub layout: &'a str,
+    /// What tells the files of the layout for the target apart, if there is
+    /// more than one, like the platform of an m17n input method
+    pub part: Option<&'a str>,
+    pub extension: &'a str,
+}
+
+impl OutputLayout {
+    /// The layout set in `KBDGEN_OUTPUT_LAYOUT`, if it is set to one
+    pub fn from_env() -> Option<OutputLayout> {
+        let value = std::env::var(OUTPUT_LAYOUT_VAR).ok()?;
+        match value.parse() {
+            Ok(layout) => Some(layout),
+            Err(_) => {
+                log::warn!(
+                    "Ignoring unknown {} `{}`, expected per-target, per-locale or flat",
+                    OUTPUT_LAYOUT_VAR,
+                    value
+                );
+                None
+            }
+        }
+    }
+
+    /// Where `file` goes, relative to the output directory
+    pub fn path(self, file: &LayoutFile) -> PathBuf {
+        let name = |first: &str| match file.part {
+            Some(part) => format!("{}-{}.{}", first, part, file.extension),
+            None => format!("{}.{}", first, file.extension),
+        };
+        match self {
+            OutputLayout::PerTarget => PathBuf::from(file.target).join(name(file.layout)),
+            OutputLayout::PerLocale => PathBuf::from(file.layout).join(name(file.target)),
+            OutputLayout::Flat => PathBuf::from(name(file.layout)),
+        }
+    }
+}
+
+/// Where `file` goes, relative to the output directory, under the layout in
+/// `KBDGEN_OUTPUT_LAYOUT`, or else `default`, the target's own convention
+pub fn layout_path(file: &LayoutFile, default: impl FnOnce() -> PathBuf) -> PathBuf {
+    match OutputLayout::from_env() {
+        Some(layout) => layout.path(file),
+        None => default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use strum::IntoEnumIterator;
+
+    #[test]
+    fn files_are_laid_out_by_target_locale_or_not_at_all() {
+        let xkb = LayoutFile {
+            target: "x11",
+            layout: "se",
+            part: None,
+            extension: "xkb",
+        };
+        let mim = LayoutFile {
+            target: "m17n",
+            part: Some("linux"),
+            extension: "mim",
+            ..xkb
+        };
+        let paths = |file| {
+            OutputLayout::iter()
+                .map(|layout| layout.path(file))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            paths(&xkb),
+            vec![
+                Path::new("x11/se.xkb"),
+                Path::new("se/x11.xkb"),
+                Path::new("se.xkb")
+            ]
+        );
+        assert_eq!(
+            paths(&mim),
+            vec![
+                Path::new("m17n/se-linux.mim"),
+                Path::new("se/m17n-linux.mim"),
+                Path::new("se-linux.mim")
+            ]
+        );
+        assert_eq!("per-locale".parse(), Ok(OutputLayout::PerLocale));
+        assert!("nested".parse::<OutputLayout>().is_err());
+    }
+}
@@ -0,0 +1,534 @@
+//! Reading and writing PNG images, shared by the icons of the macOS bundles
+//! and the screenshots of the mobile layouts
+//!
+//! Only what kbdgen needs is supported: all standard PNG images can be
+//! decoded to 8-bit RGBA pixels, and images are written as 8-bit RGBA.
+
+use std::io::{Read, Write};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+
+pub const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// The width and height in the `IHDR` chunk of a PNG, which always comes
+/// first
+pub fn size(png: &[u8]) -> Option<(u32, u32)> {
+    let header = png.strip_prefix(SIGNATURE)?.get(4..16)?;
+    if &header[..4] != b"IHDR" {
+        return None;
+    }
+    Some((be_u32(&header[4..8]), be_u32(&header[8..12])))
+}
+
+/// An image of 8-bit RGBA pixels, row by row, with straight alpha
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    /// Scales the image to `width` × `height`, giving each pixel the
+    /// average of the pixels it covers, weighted by their alpha so that
+    /// transparent pixels do not darken the edges
+    pub fn resize(&self, width: u32, height: u32) -> Image {
+        let columns = spans(self.width, width);
+        let rows = spans(self.height, height);
+
+        let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+        for row in &rows {
+            for column in &columns {
+                let (mut sum, mut alpha, mut weight) = ([0.0f32; 3], 0.0f32, 0.0f32);
+                for &(y, wy) in row {
+                    for &(x, wx) in column {
+                        let at = (y * self.width as usize + x) * 4;
+                        let pixel = &self.pixels[at..at + 4];
+                        let w = wx * wy;
+                        let a = pixel[3] as f32 * w;
+                        for (sum, &channel) in sum.iter_mut().zip(pixel) {
+                            *sum += channel as f32 * a;
+                        }
+                        alpha += a;
+                        weight += w;
+                    }
+                }
+                for sum in &sum {
+                    let value = if alpha > 0.0 { sum / alpha } else { 0.0 };
+                    pixels.push(channel(value));
+                }
+                pixels.push(channel(alpha / weight));
+            }
+        }
+
+        Image {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+/// The pixels of a row or column of `from` pixels that each of `to` pixels
+/// covers, with how much of them it covers
+fn spans(from: u32, to: u32) -> Vec<Vec<(usize, f32)>> {
+    let scale = from as f32 / to as f32;
+    (0..to)
+        .map(|i| {
+            let (start, end) = (i as f32 * scale, (i + 1) as f32 * scale);
+            (start.floor() as usize..(end.ceil() as usize).min(from as usize))
+                .map(|pixel| {
+                    let covered = end.min(pixel as f32 + 1.0) - start.max(pixel as f32);
+                    (pixel, covered)
+                })
+                .filter(|&(_, covered)| covered > 0.0)
+                .collect()
+        })
+        .collect()
+}
+
+fn channel(value: f32) -> u8 {
+    (value + 0.5).clamp(0.0, 255.0) as u8
+}
+
+/// Decodes a PNG image of any color type, bit depth and interlacing
+pub fn decode(png: &[u8]) -> Result<Image, Error> {
+    let mut rest = png.strip_prefix(SIGNATURE).ok_or(Error::NotPng)?;
+    let (mut header, mut palette, mut transparency) = (None, &[][..], &[][..]);
+    let mut data = vec![];
+    while !rest.is_empty() {
+        let len = be_u32(rest.get(..4).ok_or(Error::Damaged)?) as usize;
+        let kind = rest.get(4..8).ok_or(Error::Damaged)?;
+        let body = rest.get(8..8 + len).ok_or(Error::Damaged)?;
+        rest = rest.get(12 + len..).ok_or(Error::Damaged)?;
+        match kind {
+            b"IHDR" => header = Some(Header::read(body)?),
+            b"PLTE" => palette = body,
+            b"tRNS" => transparency = body,
+            b"IDAT" => data.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+    let header = header.ok_or(Error::Damaged)?;
+
+    let mut raw = vec![];
+    ZlibDecoder::new(&data[..])
+        .read_to_end(&mut raw)
+        .map_err(|_| Error::Damaged)?;
+
+    let (width, height) = (header.width as usize, header.height as usize);
+    let bits = header.bits_per_pixel();
+    // The filters work on whole bytes, so on one byte for pixels of less
+    let filter_bytes = (bits / 8).max(1);
+    let passes = if header.interlaced {
+        ADAM7
+    } else {
+        &[(0, 0, 1, 1)]
+    };
+
+    let mut pixels = vec![0; width * height * 4];
+    let mut offset = 0;
+    for &(x0, y0, dx, dy) in passes {
+        let pass_width = (width + dx - x0 - 1) / dx;
+        let pass_height = (height + dy - y0 - 1) / dy;
+        if pass_width == 0 || pass_height == 0 {
+            continue;
+        }
+        let stride = (pass_width * bits).div_ceil(8);
+        let mut previous = vec![0; stride];
+        for row in 0..pass_height {
+            let filter = *raw.get(offset).ok_or(Error::Damaged)?;
+            let mut line = raw
+                .get(offset + 1..offset + 1 + stride)
+                .ok_or(Error::Damaged)?
+                .to_vec();
+            unfilter(filter, &mut line, &previous, filter_bytes)?;
+
+            for column in 0..pass_width {
+                let pixel = header.pixel(&line, column, palette, transparency)?;
+                let at = ((y0 + row * dy) * width + x0 + column * dx) * 4;
+                pixels[at..at + 4].copy_from_slice(&pixel);
+            }
+            previous = line;
+            offset += 1 + stride;
+        }
+    }
+
+    Ok(Image {
+        width: header.width,
+        height: header.height,
+        pixels,
+    })
+}
+
+/// Encodes an image as an 8-bit RGBA PNG
+pub fn encode(image: &Image) -> Vec<u8> {
+    let mut raw = Vec::with_capacity(image.pixels.len() + image.height as usize);
+    for row in image.pixels.chunks(image.width as usize * 4) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+    let mut encoder = ZlibEncoder::new(vec![], Compression::best());
+    encoder
+        .write_all(&raw)
+        .expect("compressing into memory cannot fail");
+    let data = encoder
+        .finish()
+        .expect("compressing into memory cannot fail");
+
+    let mut header = image.width.to_be_bytes().to_vec();
+    header.extend_from_slice(&image.height.to_be_bytes());
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    let mut png = SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &data);
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    png.extend_from_slice(&crc.finalize().to_be_bytes());
+}
+
+/// The first column and row of each pass of an interlaced image, and the
+/// distance between the columns and rows in it
+const ADAM7: &[(usize, usize, usize, usize)] = &[
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+struct Header {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    color_type: u8,
+    interlaced: bool,
+}
+
+impl Header {
+    fn read(body: &[u8]) -> Result<Header, Error> {
+        if body.len() != 13 {
+            return Err(Error::Damaged);
+        }
+        let header = Header {
+            width: be_u32(&body[0..4]),
+            height: be_u32(&body[4..8]),
+            bit_depth: body[8],
+            color_type: body[9],
+            interlaced: body[12] == 1,
+        };
+
+        let depths: &[u8] = match header.color_type {
+            0 => &[1, 2, 4, 8, 16],
+            3 => &[1, 2, 4, 8],
+            2 | 4 | 6 => &[8, 16],
+            _ => &[],
+        };
+        if !depths.contains(&header.bit_depth) {
+            return Err(Error::Unsupported {
+                color_type: header.color_type,
+                bit_depth: header.bit_depth,
+            });
+        }
+        if header.width == 0 || header.height == 0 || body[10] != 0 || body[11] != 0 || body[12] > 1
+        {
+            return Err(Error::Damaged);
+        }
+        Ok(header)
+    }
+
+    fn channels(&self) -> usize {
+        match self.color_type {
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 1,
+        }
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.channels() * self.bit_depth as usize
+    }
+
+    /// The RGBA value of the pixel in `column` of an unfiltered line
+    fn pixel(
+        &self,
+        line: &[u8],
+        column: usize,
+        palette: &[u8],
+        transparency: &[u8],
+    ) -> Result<[u8; 4], Error> {
+        let channels = self.channels();
+        let depth = self.bit_depth as usize;
+        let sample = |channel: usize| -> u16 {
+            let i = column * channels + channel;
+            match depth {
+                16 => u16::from_be_bytes([line[2 * i], line[2 * i + 1]]),
+                8 => line[i] as u16,
+                _ => {
+                    let bit = i * depth;
+                    ((line[bit / 8] >> (8 - depth - bit % 8)) & ((1 << depth) - 1)) as u16
+                }
+            }
+        };
+        let scale = |value: u16| -> u8 {
+            match depth {
+                16 => (value >> 8) as u8,
+                _ => (value as u32 * 255 / ((1 << depth) - 1)) as u8,
+            }
+        };
+        // The color that is transparent in images without an alpha channel
+        let transparent = |samples: &[u16]| {
+            transparency.len() == 2 * samples.len()
+                && samples.iter().enumerate().all(|(i, &s)| {
+                    s == u16::from_be_bytes([transparency[2 * i], transparency[2 * i + 1]])
+                })
+        };
+        let opaque = |transparent: bool| if transparent { 0 } else { 255 };
+
+        Ok(match self.color_type {
+            0 => {
+                let gray = sample(0);
+                let g = scale(gray);
+                [g, g, g, opaque(transparent(&[gray]))]
+            }
+            2 => {
+                let rgb = [sample(0), sample(1), sample(2)];
+                [
+                    scale(rgb[0]),
+                    scale(rgb[1]),
+                    scale(rgb[2]),
+                    opaque(transparent(&rgb)),
+                ]
+            }
+            3 => {
+                let index = sample(0) as usize;
+                let color = palette
+                    .get(3 * index..3 * index + 3)
+                    .ok_or(Error::Damaged)?;
+                let alpha = transparency.get(index).copied().unwrap_or(255);
+                [color[0], color[1], color[2], alpha]
+            }
+            4 => {
+                let g = scale(sample(0));
+                [g, g, g, scale(sample(1))]
+            }
+            _ => [
+                scale(sample(0)),
+                scale(sample(1)),
+                scale(sample(2)),
+                scale(sample(3)),
+            ],
+        })
+    }
+}
+
+/// Reverses the filter of a line, given the unfiltered line before it
+fn unfilter(filter: u8, line: &mut [u8], previous: &[u8], bytes: usize) -> Result<(), Error> {
+    for i in 0..line.len() {
+        let left = if i >= bytes { line[i - bytes] } else { 0 };
+        let up = previous[i];
+        let up_left = if i >= bytes { previous[i - bytes] } else { 0 };
+        let predicted = match filter {
+            0 => 0,
+            1 => left,
+            2 => up,
+            3 => ((left as u16 + up as u16) / 2) as u8,
+            4 => paeth(left, up, up_left),
+            _ => return Err(Error::Damaged),
+        };
+        line[i] = line[i].wrapping_add(predicted);
+    }
+    Ok(())
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let p = left as i16 + up as i16 - up_left as i16;
+    let (pa, pb, pc) = (
+        (p - left as i16).abs(),
+        (p - up as i16).abs(),
+        (p - up_left as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        left
+    } else if pb <= pc {
+        up
+    } else {
+        up_left
+    }
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Image is not a PNG image")]
+    NotPng,
+    #[error("PNG image is damaged")]
+    Damaged,
+    #[error("PNG images of color type {color_type} with a bit depth of {bit_depth} do not exist")]
+    Unsupported { color_type: u8, bit_depth: u8 },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend(width.to_be_bytes());
+        png.extend(height.to_be_bytes());
+        png.extend([8, 6, 0, 0, 0]);
+        png
+    }
+
+    /// A PNG with the given header fields and lines, each line starting with
+    /// its filter
+    fn png_with(
+        width: u32,
+        height: u32,
+        header: [u8; 5],
+        chunks: &[(&[u8; 4], &[u8])],
+        raw: &[u8],
+    ) -> Vec<u8> {
+        let mut body = width.to_be_bytes().to_vec();
+        body.extend(height.to_be_bytes());
+        body.extend(header);
+        let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+        encoder.write_all(raw).unwrap();
+
+        let mut png = SIGNATURE.to_vec();
+        write_chunk(&mut png, b"IHDR", &body);
+        for (kind, data) in chunks {
+            write_chunk(&mut png, kind, data);
+        }
+        write_chunk(&mut png, b"IDAT", &encoder.finish().unwrap());
+        write_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    #[test]
+    fn sizes_are_read_from_the_header() {
+        assert_eq!(size(&png(1242, 2688)), Some((1242, 2688)));
+        assert_eq!(size(b"<svg/>"), None);
+        assert_eq!(size(&png(1242, 2688)[..20]), None);
+    }
+
+    #[test]
+    fn encoded_images_decode_to_the_same_pixels() {
+        let image = Image {
+            width: 3,
+            height: 2,
+            pixels: (0..24).map(|i| i * 10).collect(),
+        };
+        let png = encode(&image);
+        assert_eq!(size(&png), Some((3, 2)));
+        assert_eq!(decode(&png).unwrap(), image);
+    }
+
+    #[test]
+    fn palettes_transparency_and_filters_are_decoded() {
+        // Two bits per pixel, the second line predicted from the first
+        let png = png_with(
+            3,
+            2,
+            [2, 3, 0, 0, 0],
+            &[(b"PLTE", &[0, 0, 0, 255, 0, 0, 0, 0, 255]), (b"tRNS", &[0])],
+            &[0, 0b00_01_10_00, 2, 0],
+        );
+        assert_eq!(
+            decode(&png).unwrap().pixels,
+            [
+                [0, 0, 0, 0],
+                [255, 0, 0, 255],
+                [0, 0, 255, 255],
+                [0, 0, 0, 0],
+                [255, 0, 0, 255],
+                [0, 0, 255, 255]
+            ]
+            .concat()
+        );
+
+        // 16-bit gray with one gray level transparent
+        let png = png_with(
+            2,
+            1,
+            [16, 0, 0, 0, 0],
+            &[(b"tRNS", &[0x12, 0x34])],
+            &[1, 0x12, 0x34, 0xed, 0xcb],
+        );
+        assert_eq!(
+            decode(&png).unwrap().pixels,
+            [0x12, 0x12, 0x12, 0, 0xff, 0xff, 0xff, 255]
+        );
+    }
+
+    #[test]
+    fn interlaced_images_are_decoded() {
+        // 8-bit gray, where only passes 1, 6 and 7 have pixels
+        let png = png_with(2, 2, [8, 0, 0, 0, 1], &[], &[0, 10, 0, 20, 0, 30, 40]);
+        let pixels = decode(&png).unwrap().pixels;
+        let grays = pixels.chunks(4).map(|p| p[0]).collect::<Vec<_>>();
+        assert_eq!(grays, [10, 20, 30, 40]);
+    }
+
+    #[test]
+    fn damaged_and_impossible_images_are_refused() {
+        assert!(matches!(decode(b"GIF89a"), Err(Error::NotPng)));
+        assert!(matches!(decode(&png(16, 16)), Err(Error::Damaged)));
+        let png = png_with(1, 1, [4, 2, 0, 0, 0], &[], &[0, 0]);
+        assert!(matches!(
+            decode(&png),
+            Err(Error::Unsupported {
+                color_type: 2,
+                bit_depth: 4
+            })
+        ));
+    }
+
+    #[test]
+    fn resizing_averages_the_pixels_by_alpha() {
+        let image = Image {
+            width: 2,
+            height: 2,
+            pixels: [
+                [255, 0, 0, 255],
+                [0, 0, 0, 0],
+                [255, 0, 0, 255],
+                [0, 0, 255, 0],
+            ]
+            .concat(),
+        };
+        assert_eq!(image.resize(1, 1).pixels, [255, 0, 0, 128]);
+
+        let scaled = image.resize(4, 4);
+        assert_eq!(scaled.pixels.len(), 4 * 4 * 4);
+        assert_eq!(&scaled.pixels[..8], &[255, 0, 0, 255, 255, 0, 0, 255]);
+
+        let image = Image {
+            width: 3,
+            height: 1,
+            pixels: [[0, 0, 0, 255], [90, 90, 90, 255], [180, 180, 180, 255]].concat(),
+        };
+        assert_eq!(
+            image.resize(2, 1).pixels,
+            [30, 30, 30, 255, 150, 150, 150, 255]
+        );
+    }
+}
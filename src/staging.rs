@@ -0,0 +1,273 @@
+//! Building a target into a staging directory and swapping it into place
+//!
+//! Generators write their files as they go, so a build that fails halfway
+//! would leave a half-written output tree. `kbdgen build` instead points the
+//! generator at a staging directory next to the output directory, and only
+//! moves what it wrote into the output directory once the build succeeded.
+//!
+//! Directories the target declares as bundle outputs, like `deps` or
+//! `ios-build`, replace the old ones whole; other directories are merged, as
+//! targets share some of them. Whatever is replaced is moved aside first, and
+//! moved back if any part of the swap fails. A failed build's staging
+//! directory is removed, or kept for debugging with `--keep-failed`.
+
+use crate::{generator, Target};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// A directory a target is built into before it is moved into the output
+/// directory
+#[derive(Debug)]
+pub struct Staging {
+    output_path: PathBuf,
+    dir: Option<tempfile::TempDir>,
+    /// The bundle outputs of the target, relative to the output directory
+    bundle_outputs: Vec<String>,
+    keep_failed: bool,
+}
+
+/// A path that was moved during a swap, to be moved back on failure
+struct Move {
+    from: PathBuf,
+    to: PathBuf,
+}
+
+impl Staging {
+    /// Creates the staging directory for building `target`, a name like
+    /// `android` or `svg`, into `output_path`
+    pub fn start(target: &str, output_path: &Path, keep_failed: bool) -> Result<Staging, Error> {
+        let create_error = |source| Error::CannotCreate {
+            path: output_path.to_path_buf(),
+            source,
+        };
+        fs::create_dir_all(output_path).map_err(create_error)?;
+        let output_path = fs::canonicalize(output_path).map_err(create_error)?;
+        // Next to the output directory, so that moving out of it is a rename
+        // on the same file system, and so that it is not in the build report
+        let parent = output_path.parent().unwrap_or(&output_path);
+        let name = output_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let dir = tempfile::Builder::new()
+            .prefix(&format!(".{}.{}.", name, target))
+            .suffix(".staging")
+            .tempdir_in(parent)
+            .map_err(|source| Error::CannotCreate {
+                path: parent.to_path_buf(),
+                source,
+            })?;
+        log::debug!("Staging the {} build in `{}`", target, dir.path().display());
+
+        let bundle_outputs = target
+            .parse::<Target>()
+            .ok()
+            .and_then(generator::generator)
+            .map(|generator| generator.bundle_outputs())
+            .unwrap_or_default();
+        Ok(Staging {
+            output_path,
+            dir: Some(dir),
+            bundle_outputs,
+            keep_failed,
+        })
+    }
+
+    /// Where the target is to be built
+    pub fn path(&self) -> &Path {
+        self.dir.as_ref().expect("not yet finished").path()
+    }
+
+    /// Moves what was built into the output directory. If any of it cannot
+    /// be moved, the output directory is left as it was.
+    pub fn commit(mut self) -> Result<(), Error> {
+        let dir = self.dir.take().expect("not yet finished");
+        let backup = tempfile::Builder::new()
+            .prefix(".kbdgen-replaced.")
+            .tempdir_in(dir.path())
+            .map_err(|source| Error::CannotCreate {
+                path: dir.path().to_path_buf(),
+                source,
+            })?;
+        let replaced = self.replaced_outputs(dir.path());
+
+        let mut moves = vec![];
+        let result = merge(
+            dir.path(),
+            &self.output_path,
+            Path::new(""),
+            &Swap {
+                backup: backup.path(),
+                replaced: &replaced,
+            },
+            &mut moves,
+        );
+        if let Err(e) = result {
+            for done in moves.iter().rev() {
+                if let Err(e) = fs::rename(&done.to, &done.from) {
+                    log::error!(
+                        "Could not move `{}` back to `{}`: {}",
+                        done.to.display(),
+                        done.from.display(),
+                        e
+                    );
+                }
+            }
+            self.dir = Some(dir);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// The declared bundle outputs that were built, relative to the output
+    /// directory
+    fn replaced_outputs(&self, dir: &Path) -> Vec<PathBuf> {
+        self.bundle_outputs
+            .iter()
+            .flat_map(|pattern| generator::expand(dir, pattern))
+            .filter_map(|path| Some(path.strip_prefix(dir).ok()?.to_path_buf()))
+            .collect()
+    }
+}
+
+impl Drop for Staging {
+    fn drop(&mut self) {
+        let dir = match self.dir.take() {
+            Some(dir) => dir,
+            None => return,
+        };
+        if self.keep_failed {
+            let path = dir.into_path();
+            log::warn!(
+                "Kept the output of the failed build in `{}`",
+                path.display()
+            );
+        }
+    }
+}
+
+struct Swap<'a> {
+    /// Where replaced paths are moved, in the staging directory
+    backup: &'a Path,
+    /// Paths that replace what is in the output directory whole
+    replaced: &'a [PathBuf],
+}
+
+/// Moves the contents of `from` into `to`, recording every move in `moves`
+fn merge(
+    from: &Path,
+    to: &Path,
+    relative: &Path,
+    swap: &Swap<'_>,
+    moves: &mut Vec<Move>,
+) -> Result<(), Error> {
+    let read_error = |source| Error::CannotMove {
+        path: from.to_path_buf(),
+        source,
+    };
+    let mut entries = fs::read_dir(from)
+        .map_err(read_error)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(read_error)?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let source = entry.path();
+        if source == swap.backup {
+            continue;
+        }
+        let relative = relative.join(entry.file_name());
+        let target = to.join(entry.file_name());
+        let both_dirs = source.is_dir() && target.is_dir();
+        if both_dirs && !swap.replaced.contains(&relative) {
+            merge(&source, &target, &relative, swap, moves)?;
+            continue;
+        }
+        if target.exists() {
+            let backup = swap.backup.join(&relative);
+            create_parent(&backup)?;
+            rename(&target, &backup, moves)?;
+        }
+        rename(&source, &target, moves)?;
+    }
+    Ok(())
+}
+
+fn create_parent(path: &Path) -> Result<(), Error> {
+    let parent = path.parent().expect("has parent");
+    fs::create_dir_all(parent).map_err(|source| Error::CannotCreate {
+        path: parent.to_path_buf(),
+        source,
+    })
+}
+
+fn rename(from: &Path, to: &Path, moves: &mut Vec<Move>) -> Result<(), Error> {
+    fs::rename(from, to).map_err(|source| Error::CannotMove {
+        path: from.to_path_buf(),
+        source,
+    })?;
+    moves.push(Move {
+        from: from.to_path_buf(),
+        to: to.to_path_buf(),
+    });
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not create `{}`", path.display())]
+    CannotCreate { path: PathBuf, source: io::Error },
+
+    #[error("Could not move `{}` into the output directory", path.display())]
+    CannotMove { path: PathBuf, source: io::Error },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_replace_their_outputs_on_commit_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("output");
+        fs::create_dir_all(output.join("deps/old")).unwrap();
+        fs::create_dir_all(output.join("strings/en.lproj")).unwrap();
+        fs::write(output.join("deps/old/file"), "old").unwrap();
+
+        let staging = Staging::start("android", &output, false).unwrap();
+        fs::create_dir_all(staging.path().join("deps/new")).unwrap();
+        fs::create_dir_all(staging.path().join("strings/res")).unwrap();
+        fs::write(staging.path().join("deps/new/file"), "new").unwrap();
+        fs::write(staging.path().join("app.apk"), "apk").unwrap();
+        assert!(!output.join("app.apk").exists());
+        staging.commit().unwrap();
+
+        // `deps` is replaced whole, `strings` is shared with iOS
+        assert!(!output.join("deps/old").exists());
+        assert_eq!(
+            fs::read_to_string(output.join("deps/new/file")).unwrap(),
+            "new"
+        );
+        assert!(output.join("strings/en.lproj").is_dir());
+        assert!(output.join("strings/res").is_dir());
+        assert!(output.join("app.apk").is_file());
+
+        let staging = Staging::start("android", &output, false).unwrap();
+        let path = staging.path().to_path_buf();
+        fs::write(path.join("half.apk"), "").unwrap();
+        drop(staging);
+        assert!(!path.exists());
+        assert!(!output.join("half.apk").exists());
+
+        let staging = Staging::start("svg", &output, true).unwrap();
+        let path = staging.path().to_path_buf();
+        drop(staging);
+        assert!(path.exists());
+        fs::remove_dir_all(path).unwrap();
+
+        let siblings = fs::read_dir(dir.path()).unwrap().count();
+        assert_eq!(siblings, 1);
+    }
+}
@@ -0,0 +1,136 @@
+//! Provenance of generated files
+//!
+//! Every generated file says which kbdgen, bundle commit and layout it came
+//! from, so that a file found in the wild can be traced back to its source.
+//! Setting `KBDGEN_NO_STAMP` (`--no-stamp`) leaves the stamp out, for output
+//! that is the same on every build.
+//!
+//! The Python generators get the stamp through `KBDGEN_STAMP`, see
+//! [`Stamp::export`].
+
+use crate::{xml_writer::XmlWriter, ProjectBundle};
+use serde::{Deserialize, Serialize};
+use std::{io, path::Path, process::Command};
+
+const NO_STAMP_VAR: &str = "KBDGEN_NO_STAMP";
+const STAMP_VAR: &str = "KBDGEN_STAMP";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stamp {
+    pub version: String,
+    /// The commit of the bundle's git repository, if it is in one
+    pub commit: Option<String>,
+    /// The day of the build, as `YYYY-MM-DD`
+    pub date: String,
+    pub layout: Option<String>,
+}
+
+impl Stamp {
+    /// The stamp for files generated from the bundle at `project_path`, or
+    /// `None` if stamping is turned off
+    pub fn new(project_path: &Path) -> Option<Stamp> {
+        if std::env::var_os(NO_STAMP_VAR).is_some() {
+            return None;
+        }
+        Some(Stamp {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            commit: git_commit(project_path),
+            date: chrono::Utc::now().format("%Y-%m-%d").to_string(),
+            layout: None,
+        })
+    }
+
+    /// Like `new`, for a bundle that was loaded from disk
+    pub fn for_bundle(bundle: &ProjectBundle) -> Option<Stamp> {
+        Stamp::new(bundle.path.as_deref().unwrap_or_else(|| Path::new(".")))
+    }
+
+    /// Turns stamping off for this process and the generators it runs
+    pub fn disable() {
+        std::env::set_var(NO_STAMP_VAR, "1");
+    }
+
+    /// Makes the stamp available to the Python generators
+    pub fn export(&self) {
+        if let Ok(json) = serde_json::to_string(self) {
+            std::env::set_var(STAMP_VAR, json);
+        }
+    }
+
+    pub fn with_layout(&self, layout: &str) -> Stamp {
+        Stamp {
+            layout: Some(layout.to_string()),
+            ..self.clone()
+        }
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![format!("Generated by kbdgen {}", self.version)];
+        if let Some(commit) = &self.commit {
+            lines.push(format!("Bundle commit: {}", commit));
+        }
+        lines.push(format!("Build date: {}", self.date));
+        if let Some(layout) = &self.layout {
+            lines.push(format!("Layout: {}", layout));
+        }
+        lines
+    }
+
+    /// The stamp as line comments starting with `prefix`, like `//` or `#`
+    pub fn comment(&self, prefix: &str) -> String {
+        comment(&self.lines(), prefix)
+    }
+
+    /// The stamp as XML comments, one per line
+    pub fn write_xml<W: io::Write>(&self, w: &mut XmlWriter<W>) -> io::Result<()> {
+        for line in self.lines() {
+            w.comment(&line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Each line as a line comment starting with `prefix`
+pub fn comment(lines: &[String], prefix: &str) -> String {
+    lines
+        .iter()
+        .map(|line| format!("{} {}\n", prefix, line))
+        .collect()
+}
+
+fn git_commit(project_path: &Path) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(project_path)
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let commit = String::from_utf8(output.stdout).ok()?;
+    Some(commit.trim().to_string()).filter(|commit| !commit.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comments_have_one_line_per_field() {
+        let stamp = Stamp {
+            version: "2.0.0".into(),
+            commit: Some("abc1234".into()),
+            date: "2021-05-01".into(),
+            layout: None,
+        }
+        .with_layout("se");
+        assert_eq!(
+            stamp.comment("//"),
+            "// Generated by kbdgen 2.0.0\n\
+             // Bundle commit: abc1234\n\
+             // Build date: 2021-05-01\n\
+             // Layout: se\n"
+        );
+    }
+}
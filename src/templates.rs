@@ -0,0 +1,528 @@
+//! App projects that mobile keyboards are built in
+//!
+//! A target's `template` names a directory in the bundle or a git repository.
+//! Git templates are fetched into the user's cache, one checkout per tag, so
+//! a template update only needs a new tag in `targets/*.yaml`, not a new
+//! kbdgen release. Templates are copied to the output directory with their
+//! `{{ variable }}` placeholders replaced by values from the bundle, except in
+//! binary files like images and fonts, and with their permissions, so that
+//! scripts like `gradlew` stay executable.
+//!
+//! The checksum of each tagged checkout is recorded when it is downloaded, and
+//! a checkout that no longer matches is downloaded again. A template with a
+//! `sha256` must match it wherever it comes from, and `vendor` copies a
+//! template into the bundle pinned to its checksum for offline builds.
+//!
+//! Targets without a template in the bundle use the one in the user's
+//! configuration, if any, see `config::Config`.
+
+use crate::{cli::repos, config::Config, models::Template, LoadError, ProjectBundle, Target};
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Extensions of files that are copied as they are, without substituting
+/// placeholders, even if they happen to be valid UTF-8
+const BINARY_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "gif", "webp", "ico", "icns", "ttf", "otf", "woff", "woff2", "jar",
+    "aar", "so", "a", "dylib", "keystore", "jks", "zip", "bhfst", "zhfst", "pdf", "car", "mp3",
+    "ogg", "wav",
+];
+
+fn is_binary(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| BINARY_EXTENSIONS.contains(&ext.as_str()))
+}
+
+/// The template configured for the target, if any
+pub fn template_for(bundle: &ProjectBundle, target: Target) -> Option<&Template> {
+    match target {
+        Target::Android => bundle.targets.android.as_ref()?.template.as_ref(),
+        Target::Ios => bundle.targets.i_os.as_ref()?.template.as_ref(),
+        _ => None,
+    }
+}
+
+/// The values templates for the target can use, by name
+pub fn variables(bundle: &ProjectBundle, target: Target) -> BTreeMap<String, String> {
+    let mut variables = BTreeMap::new();
+    let mut set = |name: &str, value: &str| {
+        variables.insert(name.to_string(), value.to_string());
+    };
+
+    let project = &bundle.project;
+    set("author", &project.author);
+    set("email", &project.email);
+    set("copyright", &project.copyright);
+    set("organisation", &project.organisation);
+    let desc = project
+        .locales
+        .get("en")
+        .or_else(|| project.locales.values().next());
+    if let Some(desc) = desc {
+        set("name", &desc.name);
+        set("description", &desc.description);
+    }
+
+    match target {
+        Target::Android => {
+            if let Some(android) = &bundle.targets.android {
+                set("version", &android.version);
+                set("build", &android.build.to_string());
+                set("packageId", &android.package_id);
+            }
+        }
+        Target::Ios => {
+            if let Some(ios) = &bundle.targets.i_os {
+                set("version", &ios.version);
+                set("build", &ios.build.to_string());
+                set("packageId", &ios.package_id);
+                set("bundleName", &ios.bundle_name);
+                if let Some(team_id) = &ios.team_id {
+                    set("teamId", team_id);
+                }
+            }
+        }
+        _ => {}
+    }
+
+    variables
+}
+
+/// Replaces `{{ name }}` with the value of the variable, leaving placeholders
+/// for unknown variables as they are
+pub fn substitute(text: &str, variables: &BTreeMap<String, String>) -> String {
+    lazy_static! {
+        static ref PLACEHOLDER: Regex =
+            Regex::new(r"\{\{\s*([A-Za-z][A-Za-z0-9_]*)\s*\}\}").unwrap();
+    }
+
+    PLACEHOLDER
+        .replace_all(text, |captures: &Captures| {
+            match variables.get(&captures[1]) {
+                Some(value) => value.clone(),
+                None => captures[0].to_string(),
+            }
+        })
+        .into_owned()
+}
+
+/// The directory a git template is checked out to
+fn checkout_dir(url: &str, tag: Option<&str>) -> PathBuf {
+    let name = url
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    repos::templates_dir()
+        .join(name)
+        .join(tag.unwrap_or("default"))
+}
+
+/// The directory in the user's cache with every downloaded tag of a git
+/// template
+pub fn cache_dir(template: &Template) -> Option<PathBuf> {
+    let url = template.git.as_ref()?;
+    checkout_dir(url, None).parent().map(Path::to_path_buf)
+}
+
+/// The directory the template is in, fetching it first if it is a git
+/// repository
+pub fn fetch(template: &Template, bundle_path: &Path) -> Result<PathBuf, Error> {
+    let dir = match (&template.path, &template.git) {
+        (Some(path), None) => {
+            let path = bundle_path.join(path);
+            if !path.is_dir() {
+                return Err(Error::MissingTemplate { path });
+            }
+            path
+        }
+        (None, Some(url)) => {
+            let dir = checkout_dir(url, template.tag.as_deref());
+            match &template.tag {
+                Some(tag) => fetch_tag(url, tag, &dir)?,
+                None => repos::update_repo("template", &dir, url)
+                    .map_err(|source| Error::CannotUpdate { source })?,
+            }
+            dir
+        }
+        _ => return Err(Error::AmbiguousSource),
+    };
+
+    if let Some(expected) = &template.sha256 {
+        let actual = checksum(&dir)?;
+        if !actual.eq_ignore_ascii_case(expected) {
+            return Err(Error::ChecksumMismatch {
+                path: dir,
+                expected: expected.clone(),
+                actual,
+            });
+        }
+    }
+    Ok(dir)
+}
+
+/// Clones the tag into `dir`, unless it already holds a checkout that matches
+/// the checksum recorded when it was cloned
+fn fetch_tag(url: &str, tag: &str, dir: &Path) -> Result<(), Error> {
+    let mut record = OsString::from(dir.as_os_str());
+    record.push(".sha256");
+    let record = PathBuf::from(record);
+
+    if dir.exists() {
+        let recorded = fs::read_to_string(&record).unwrap_or_default();
+        if recorded.trim() == checksum(dir)? {
+            return Ok(());
+        }
+        log::warn!(
+            "Template `{}` has changed since it was downloaded, downloading it again",
+            dir.display()
+        );
+        fs::remove_dir_all(dir).map_err(|source| Error::CannotWrite {
+            path: dir.to_path_buf(),
+            source,
+        })?;
+    }
+
+    log::info!("Downloading template {} {}…", url, tag);
+    let status = Command::new("git")
+        .args(["clone", "--depth", "1", "--branch", tag, url])
+        .arg(dir)
+        .status()
+        .map_err(|source| Error::CannotRunGit { source })?;
+    if !status.success() {
+        return Err(Error::CannotClone {
+            url: url.to_string(),
+            tag: tag.to_string(),
+        });
+    }
+
+    fs::write(&record, checksum(dir)?).map_err(|source| Error::CannotWrite {
+        path: record.clone(),
+        source,
+    })
+}
+
+/// A SHA-256 of the paths and contents of the files in the template, in hex
+///
+/// `.git` is left out, so a checkout and a copy of it have the same checksum.
+pub fn checksum(dir: &Path) -> Result<String, Error> {
+    let mut files = vec![];
+    list_files(dir, "", &mut files)?;
+    files.sort();
+
+    let mut hasher = Sha256::new();
+    for file in files {
+        let path = dir.join(&file);
+        let contents = fs::read(&path).map_err(|source| Error::CannotRead { path, source })?;
+        hasher.update(file.as_bytes());
+        hasher.update([0]);
+        hasher.update((contents.len() as u64).to_le_bytes());
+        hasher.update(&contents);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+/// The files under `dir`, as paths relative to it separated by `/`
+fn list_files(dir: &Path, prefix: &str, files: &mut Vec<String>) -> Result<(), Error> {
+    let read_error = |source| Error::CannotRead {
+        path: dir.to_path_buf(),
+        source,
+    };
+    for entry in fs::read_dir(dir).map_err(read_error)? {
+        let entry = entry.map_err(read_error)?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name == ".git" {
+            continue;
+        }
+        let relative = format!("{}{}", prefix, name);
+        if entry.path().is_dir() {
+            list_files(&entry.path(), &format!("{}/", relative), files)?;
+        } else {
+            files.push(relative);
+        }
+    }
+    Ok(())
+}
+
+/// Copies the template for the target into the bundle's `templates`
+/// directory, returning the configuration that uses the copy, pinned to its
+/// checksum
+pub fn vendor(template: &Template, bundle_path: &Path, target: Target) -> Result<Template, Error> {
+    let dir = fetch(template, bundle_path)?;
+    let path = format!("templates/{}", target);
+    let output = bundle_path.join(&path);
+
+    let already_vendored = match (fs::canonicalize(&dir), fs::canonicalize(&output)) {
+        (Ok(dir), Ok(output)) => dir == output,
+        _ => false,
+    };
+    if !already_vendored {
+        log::info!("Copying template `{}` into the bundle…", dir.display());
+        // Without variables the files are copied as they are
+        instantiate(&dir, &output, &BTreeMap::new())?;
+    }
+
+    Ok(Template {
+        path: Some(path),
+        git: None,
+        tag: None,
+        sha256: Some(checksum(&output)?),
+    })
+}
+
+/// Copies the template to `output`, replacing placeholders in the names and
+/// contents of its files
+///
+/// Files that are not UTF-8 text are copied as they are. Anything already in
+/// `output` is removed first.
+pub fn instantiate(
+    template: &Path,
+    output: &Path,
+    variables: &BTreeMap<String, String>,
+) -> Result<(), Error> {
+    if output.exists() {
+        fs::remove_dir_all(output).map_err(|source| Error::CannotWrite {
+            path: output.to_path_buf(),
+            source,
+        })?;
+    }
+    copy_dir(template, output, variables)
+}
+
+fn copy_dir(from: &Path, to: &Path, variables: &BTreeMap<String, String>) -> Result<(), Error> {
+    let read_error = |source| Error::CannotRead {
+        path: from.to_path_buf(),
+        source,
+    };
+    fs::create_dir_all(to).map_err(|source| Error::CannotWrite {
+        path: to.to_path_buf(),
+        source,
+    })?;
+
+    for entry in fs::read_dir(from).map_err(read_error)? {
+        let entry = entry.map_err(read_error)?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name == ".git" {
+            continue;
+        }
+        let source = entry.path();
+        let target = to.join(substitute(&name, variables));
+
+        if source.is_dir() {
+            copy_dir(&source, &target, variables)?;
+            continue;
+        }
+
+        let bytes = fs::read(&source).map_err(|e| Error::CannotRead {
+            path: source.clone(),
+            source: e,
+        })?;
+        let bytes = match String::from_utf8(bytes) {
+            Ok(text) if !is_binary(&source) => substitute(&text, variables).into_bytes(),
+            Ok(text) => text.into_bytes(),
+            Err(e) => e.into_bytes(),
+        };
+        let permissions = entry.metadata().map_err(read_error)?.permissions();
+        fs::write(&target, bytes)
+            .and_then(|()| fs::set_permissions(&target, permissions))
+            .map_err(|source| Error::CannotWrite {
+                path: target.clone(),
+                source,
+            })?;
+    }
+
+    Ok(())
+}
+
+/// Fetches and instantiates the template for the target into the output
+/// directory, returning where it was put, or nothing if the bundle does not
+/// configure a template
+pub fn prepare(
+    project_path: &Path,
+    output_path: &Path,
+    target: Target,
+) -> Result<Option<PathBuf>, Error> {
+    let bundle =
+        crate::cli::load_bundle(project_path).map_err(|source| Error::CannotLoad { source })?;
+    let config = Config::load().map_err(|source| Error::CannotLoadConfig { source })?;
+    let template = match template_for(&bundle, target).or_else(|| config.template(target)) {
+        Some(template) => template,
+        None => return Ok(None),
+    };
+
+    let dir = fetch(template, project_path)?;
+    let output = output_path.join("template").join(target.to_string());
+    log::info!("Instantiating template `{}`…", dir.display());
+    instantiate(&dir, &output, &variables(&bundle, target))?;
+    Ok(Some(output))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not load kbdgen bundle")]
+    CannotLoad { source: LoadError },
+    #[error("Could not load the user's configuration")]
+    CannotLoadConfig { source: crate::config::Error },
+    #[error("A template needs either a `path` or a `git` repository")]
+    AmbiguousSource,
+    #[error("Template directory `{}` does not exist", path.display())]
+    MissingTemplate { path: PathBuf },
+    #[error("Could not run `git`, is it installed?")]
+    CannotRunGit { source: std::io::Error },
+    #[error("Could not clone `{}` at `{}`", url, tag)]
+    CannotClone { url: String, tag: String },
+    #[error("Could not update template")]
+    CannotUpdate { source: repos::Error },
+    #[error("Template `{}` has checksum {}, but {} is expected", path.display(), actual, expected)]
+    ChecksumMismatch {
+        path: PathBuf,
+        expected: String,
+        actual: String,
+    },
+    #[error("Could not read `{}`", path.display())]
+    CannotRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Could not write `{}`", path.display())]
+    CannotWrite {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables() -> BTreeMap<String, String> {
+        let mut variables = BTreeMap::new();
+        variables.insert("packageId".to_string(), "com.example.kbd".to_string());
+        variables.insert("version".to_string(), "1.2.0".to_string());
+        variables
+    }
+
+    #[test]
+    fn placeholders_are_replaced() {
+        assert_eq!(
+            substitute(
+                "id = \"{{packageId}}\" v{{ version }} {{ unknown }}",
+                &variables()
+            ),
+            "id = \"com.example.kbd\" v1.2.0 {{ unknown }}"
+        );
+    }
+
+    #[test]
+    fn templates_are_copied_with_substitutions() {
+        let template = tempfile::tempdir().unwrap();
+        let output = tempfile::tempdir().unwrap();
+        let app = template.path().join("app");
+        fs::create_dir_all(template.path().join(".git")).unwrap();
+        fs::create_dir_all(&app).unwrap();
+        fs::write(app.join("{{packageId}}.txt"), "{{ version }}").unwrap();
+        fs::write(app.join("icon.png"), [0x89, 0x50, 0xff, 0x7b, 0x7b]).unwrap();
+
+        let out = output.path().join("android");
+        fs::create_dir_all(&out).unwrap();
+        fs::write(out.join("stale.txt"), "").unwrap();
+        instantiate(template.path(), &out, &variables()).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(out.join("app/com.example.kbd.txt")).unwrap(),
+            "1.2.0"
+        );
+        assert_eq!(
+            fs::read(out.join("app/icon.png")).unwrap(),
+            vec![0x89, 0x50, 0xff, 0x7b, 0x7b]
+        );
+        assert!(!out.join(".git").exists());
+        assert!(!out.join("stale.txt").exists());
+    }
+
+    #[test]
+    fn binary_files_and_permissions_are_kept() {
+        let template = tempfile::tempdir().unwrap();
+        let output = tempfile::tempdir().unwrap();
+        fs::write(template.path().join("gradlew"), "echo {{ version }}\n").unwrap();
+        fs::write(template.path().join("font.TTF"), "{{ version }}").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(
+                template.path().join("gradlew"),
+                fs::Permissions::from_mode(0o755),
+            )
+            .unwrap();
+        }
+
+        let out = output.path().join("android");
+        instantiate(template.path(), &out, &variables()).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(out.join("gradlew")).unwrap(),
+            "echo 1.2.0\n"
+        );
+        assert_eq!(
+            fs::read_to_string(out.join("font.TTF")).unwrap(),
+            "{{ version }}"
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(out.join("gradlew"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o755);
+        }
+    }
+
+    #[test]
+    fn vendored_templates_are_pinned_to_their_checksum() {
+        let bundle = tempfile::tempdir().unwrap();
+        let source = bundle.path().join("app");
+        fs::create_dir_all(source.join("src/.git")).unwrap();
+        fs::write(source.join("src/main.kt"), "{{ packageId }}").unwrap();
+        fs::write(source.join("src/.git/HEAD"), "ref").unwrap();
+
+        let template = Template {
+            path: Some("app".to_string()),
+            git: None,
+            tag: None,
+            sha256: None,
+        };
+        let vendored = vendor(&template, bundle.path(), Target::Android).unwrap();
+        assert_eq!(vendored.path.as_deref(), Some("templates/android"));
+        assert_eq!(
+            fs::read_to_string(bundle.path().join("templates/android/src/main.kt")).unwrap(),
+            "{{ packageId }}"
+        );
+        // The copy matches the original, which has a `.git`
+        assert_eq!(vendored.sha256, Some(checksum(&source).unwrap()));
+        assert!(fetch(&vendored, bundle.path()).is_ok());
+        // Vendoring again keeps the copy
+        assert_eq!(
+            vendor(&vendored, bundle.path(), Target::Android).unwrap(),
+            vendored
+        );
+
+        fs::write(bundle.path().join("templates/android/extra"), "").unwrap();
+        assert!(matches!(
+            fetch(&vendored, bundle.path()),
+            Err(Error::ChecksumMismatch { .. })
+        ));
+    }
+}
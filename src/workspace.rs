@@ -0,0 +1,252 @@
+//! Several bundles built, validated and scored together
+//!
+//! Organisations maintaining keyboards for many languages keep a bundle per
+//! language. A workspace is a directory holding such bundles, with a
+//! `workspace.yaml`:
+//!
+//! ```yaml
+//! bundles:
+//!   - sme.kbdgen
+//!   - smj.kbdgen
+//!   - vendor/sma.kbdgen
+//! cacheDir: .kbdgen-cache
+//! ```
+//!
+//! Without `bundles`, every `.kbdgen` directory directly in the workspace is
+//! a member. `cacheDir`, relative to the workspace, is the cache shared by the
+//! members, see [`Workspace::export`]; without it, the user's cache is used.
+//! `kbdgen workspace build`, `validate` and `stats` go through every member
+//! and report on all of them at once.
+
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+pub const WORKSPACE_FILE: &str = "workspace.yaml";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub struct Config {
+    /// The member bundles, relative to the workspace
+    pub bundles: Option<Vec<PathBuf>>,
+    /// The cache shared by the members, relative to the workspace
+    pub cache_dir: Option<PathBuf>,
+}
+
+/// A bundle of a workspace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Member {
+    /// The name of the bundle's directory without `.kbdgen`, which names its
+    /// output directory and its part of reports
+    pub name: String,
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Workspace {
+    pub path: PathBuf,
+    pub config: Config,
+    /// Sorted by name
+    pub members: Vec<Member>,
+}
+
+impl Workspace {
+    /// Whether `path` is a workspace rather than a bundle
+    pub fn is_workspace(path: &Path) -> bool {
+        path.join(WORKSPACE_FILE).is_file()
+    }
+
+    pub fn load(path: &Path) -> Result<Workspace, Error> {
+        let config_path = path.join(WORKSPACE_FILE);
+        let text = std::fs::read_to_string(&config_path).map_err(|source| Error::CannotRead {
+            path: config_path.clone(),
+            source,
+        })?;
+        let config: Config = if text.trim().is_empty() {
+            Config::default()
+        } else {
+            serde_yaml::from_str(&text).map_err(|source| Error::CannotParse {
+                path: config_path.clone(),
+                source,
+            })?
+        };
+
+        let paths = match &config.bundles {
+            Some(bundles) => bundles.iter().map(|bundle| path.join(bundle)).collect(),
+            None => find_bundles(path)?,
+        };
+        let mut members: Vec<Member> = vec![];
+        for path in paths {
+            if !path.join("project.yaml").is_file() {
+                return Err(Error::NotABundle { path });
+            }
+            let name = bundle_name(&path);
+            if let Some(other) = members.iter().find(|member| member.name == name) {
+                return Err(Error::DuplicateName {
+                    name,
+                    paths: vec![other.path.clone(), path],
+                });
+            }
+            members.push(Member { name, path });
+        }
+        if members.is_empty() {
+            return Err(Error::NoBundles {
+                path: path.to_path_buf(),
+            });
+        }
+        members.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(Workspace {
+            path: path.to_path_buf(),
+            config,
+            members,
+        })
+    }
+
+    /// The cache shared by the members, if the workspace has one
+    pub fn cache_dir(&self) -> Option<PathBuf> {
+        self.config
+            .cache_dir
+            .as_ref()
+            .map(|dir| self.path.join(dir))
+    }
+
+    /// Sets `KBDGEN_CACHE_DIR` to the workspace's cache, if it has one, so
+    /// that loading the members and the builds they start share it
+    pub fn export(&self) {
+        if let Some(dir) = self.cache_dir() {
+            std::env::set_var(crate::config::CACHE_DIR_VAR, dir);
+        }
+    }
+}
+
+/// `sme` for `sme.kbdgen`
+fn bundle_name(path: &Path) -> String {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    match name.strip_suffix(".kbdgen") {
+        Some(stem) => stem.to_string(),
+        None => name,
+    }
+}
+
+/// The `.kbdgen` directories directly in `path`
+fn find_bundles(path: &Path) -> Result<Vec<PathBuf>, Error> {
+    let entries = std::fs::read_dir(path).map_err(|source| Error::CannotRead {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    Ok(entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir() && path.extension().is_some_and(|e| e == "kbdgen"))
+        .collect())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not read `{}`", path.display())]
+    CannotRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Could not parse `{}`", path.display())]
+    CannotParse {
+        path: PathBuf,
+        source: serde_yaml::Error,
+    },
+
+    #[error("`{}` is not a kbdgen bundle, it has no project.yaml", path.display())]
+    NotABundle { path: PathBuf },
+
+    #[error(
+        "Bundles `{}` are both named `{}` in the workspace",
+        paths.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join("` and `"),
+        name
+    )]
+    DuplicateName { name: String, paths: Vec<PathBuf> },
+
+    #[error("The workspace `{}` has no bundles", path.display())]
+    NoBundles { path: PathBuf },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bundle(root: &Path, path: &str) {
+        let path = root.join(path);
+        std::fs::create_dir_all(&path).unwrap();
+        std::fs::write(path.join("project.yaml"), "").unwrap();
+    }
+
+    #[test]
+    fn members_are_found_or_listed() {
+        let dir = tempfile::tempdir().unwrap();
+        bundle(dir.path(), "smj.kbdgen");
+        bundle(dir.path(), "sme.kbdgen");
+        bundle(dir.path(), "vendor/sma.kbdgen");
+        std::fs::create_dir(dir.path().join("docs")).unwrap();
+        std::fs::write(dir.path().join(WORKSPACE_FILE), "").unwrap();
+
+        let workspace = Workspace::load(dir.path()).unwrap();
+        let names = workspace
+            .members
+            .iter()
+            .map(|m| m.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["sme", "smj"]);
+        assert_eq!(workspace.cache_dir(), None);
+
+        std::fs::write(
+            dir.path().join(WORKSPACE_FILE),
+            "bundles: [sme.kbdgen, vendor/sma.kbdgen]\ncacheDir: .cache\n",
+        )
+        .unwrap();
+        let workspace = Workspace::load(dir.path()).unwrap();
+        assert_eq!(
+            workspace.members,
+            vec![
+                Member {
+                    name: "sma".into(),
+                    path: dir.path().join("vendor/sma.kbdgen"),
+                },
+                Member {
+                    name: "sme".into(),
+                    path: dir.path().join("sme.kbdgen"),
+                },
+            ]
+        );
+        assert_eq!(workspace.cache_dir(), Some(dir.path().join(".cache")));
+    }
+
+    #[test]
+    fn members_must_be_bundles_with_distinct_names() {
+        let dir = tempfile::tempdir().unwrap();
+        bundle(dir.path(), "sme.kbdgen");
+        bundle(dir.path(), "vendor/sme.kbdgen");
+        std::fs::create_dir(dir.path().join("docs")).unwrap();
+
+        std::fs::write(dir.path().join(WORKSPACE_FILE), "bundles: [docs]").unwrap();
+        assert!(matches!(
+            Workspace::load(dir.path()),
+            Err(Error::NotABundle { .. })
+        ));
+        std::fs::write(
+            dir.path().join(WORKSPACE_FILE),
+            "bundles: [sme.kbdgen, vendor/sme.kbdgen]",
+        )
+        .unwrap();
+        assert!(matches!(
+            Workspace::load(dir.path()),
+            Err(Error::DuplicateName { .. })
+        ));
+        std::fs::write(dir.path().join(WORKSPACE_FILE), "bundles: []").unwrap();
+        assert!(matches!(
+            Workspace::load(dir.path()),
+            Err(Error::NoBundles { .. })
+        ));
+    }
+}
@@ -1,31 +1,70 @@
 use super::{Key, Symbols, XkbFile, XkbKeySym};
 use crate::{
-    bundle::keys::KeyValue,
+    bundle::{keys::KeyValue, modifier_remaps},
+    loss::{self, Loss},
     models::{DesktopModes, Layout},
     utils::UnwrapOrUnknownExt,
+    DesktopKeyMap, Feature, LocaleFallbacks, ModeName, ModifierKey, Target, DEFAULT_LOCALE,
 };
 use std::collections::BTreeMap;
 
+/// Targets whose modes can be converted to XKB symbols, in order of
+/// preference
+pub const XKB_SOURCE_TARGETS: &[Target] = &[Target::X11, Target::Win, Target::Mac, Target::Chrome];
+
+/// The id of the variant with a second group, see `XkbFile::add_second_group`
+pub const SECOND_GROUP_VARIANT: &str = "bilingual";
+
 impl XkbFile {
-    pub fn from_layout(name: &str, layout: Layout) -> Result<Self, Error> {
-        let mut modes = vec![
-            layout.modes.x11.clone().map(|x| ("x11", x)),
-            layout.modes.win.clone().map(|x| ("win", x)),
-            layout.modes.mac.clone().map(|x| ("mac", x)),
-            layout.modes.chrome.clone().map(|x| ("chrome", x)),
-        ]
-        .into_iter()
-        .flatten();
+    pub fn from_layout(
+        name: &str,
+        layout: Layout,
+        fallbacks: &LocaleFallbacks<'_>,
+    ) -> Result<Self, Error> {
+        let display_name = layout
+            .display_name(DEFAULT_LOCALE, fallbacks)
+            .unwrap_or_unknown();
+        // The default symbols are those of `x11` or what it falls back to,
+        // and the other targets with modes of their own become variants
+        let sources = layout
+            .modes
+            .resolve_desktop(Target::X11)
+            .into_iter()
+            .chain(
+                XKB_SOURCE_TARGETS
+                    .iter()
+                    .filter(|&&target| target != Target::X11)
+                    .filter_map(|&target| Some((target, layout.modes.desktop_for(target)?))),
+            )
+            .map(|(target, modes)| (target, modes.clone()))
+            .collect::<Vec<(Target, DesktopModes)>>();
+        // The id of the symbols of each target, to include in its dead keys
+        let symbols_id = |target: Target| {
+            let index = sources.iter().position(|(t, _)| *t == target)?;
+            Some(if index == 0 {
+                "basic".to_string()
+            } else {
+                target.to_string()
+            })
+        };
+        let mut modes = sources.iter().cloned();
 
         let default = modes
             .next()
             .map(|(target, mode)| {
                 Ok(Symbols {
                     id: "basic".to_string(),
-                    name: format!("{} ({})", layout.name().unwrap_or_unknown(), target),
-                    leading_includes: vec!["latin".to_string()],
+                    name: format!("{} ({})", display_name, target),
+                    second_group_name: None,
+                    // Keys left out of a right-to-left layout should not
+                    // fall back to Latin letters
+                    leading_includes: if layout.is_rtl() {
+                        vec![]
+                    } else {
+                        vec!["latin".to_string()]
+                    },
                     keys: collect_keys(&mode, None)?,
-                    trailing_includes: vec!["level3(ralt_switch)".to_string()],
+                    trailing_includes: modifier_includes(name, &layout, true),
                 })
             })
             .ok_or_else(|| Error::NoXkbCompatibleModes {
@@ -36,50 +75,35 @@ impl XkbFile {
             .map(|(target, mode)| {
                 Ok(Symbols {
                     id: target.to_string(),
-                    name: format!("{} ({})", layout.name().unwrap_or_unknown(), target),
+                    name: format!("{} ({})", display_name, target),
+                    second_group_name: None,
                     leading_includes: vec![format!("{}(basic)", name)],
                     keys: collect_keys(&mode, Some(&default))?,
-                    trailing_includes: vec!["level3(ralt_switch)".to_string()],
+                    trailing_includes: modifier_includes(name, &layout, false),
                 })
             })
             .collect::<Result<Vec<Symbols>, Error>>()?;
 
         if let Some(dead_keys) = layout.dead_keys.as_ref() {
-            for (target, mode_keys) in dead_keys {
-                let parent =
-                    match target.as_str() {
-                        "x11" => layout.modes.x11.as_ref().ok_or(
-                            Error::DeadKeysForUnconfiguredTarget {
-                                target: target.clone(),
-                            },
-                        )?,
-                        "win" => layout.modes.win.as_ref().ok_or(
-                            Error::DeadKeysForUnconfiguredTarget {
-                                target: target.clone(),
-                            },
-                        )?,
-                        "mac" => layout.modes.mac.as_ref().ok_or(
-                            Error::DeadKeysForUnconfiguredTarget {
-                                target: target.clone(),
-                            },
-                        )?,
-                        "chrome" => layout.modes.chrome.as_ref().ok_or(
-                            Error::DeadKeysForUnconfiguredTarget {
-                                target: target.clone(),
-                            },
-                        )?,
-                        _ => continue,
-                    };
+            for (&target, mode_keys) in dead_keys {
+                // The dead keys of `desktop` only apply if its modes are used
+                let is_source = XKB_SOURCE_TARGETS.contains(&target)
+                    || (target == Target::Desktop && symbols_id(target).is_some());
+                if !is_source {
+                    continue;
+                }
+                let parent = layout
+                    .modes
+                    .desktop_for(target)
+                    .ok_or(Error::DeadKeysForUnconfiguredTarget { target })?;
+                let include = symbols_id(target).expect("targets with modes have symbols");
 
                 others.push(Symbols {
-                    id: format!("{}_deadkeys", target.to_string()),
-                    name: format!(
-                        "{} ({}) (dead keys)",
-                        layout.name().unwrap_or_unknown(),
-                        target
-                    ),
-                    leading_includes: vec![format!("{}({})", name, target)],
-                    keys: collect_dead_keys(&mode_keys, parent)?,
+                    id: format!("{}_deadkeys", target),
+                    name: format!("{} ({}) (dead keys)", display_name, target),
+                    second_group_name: None,
+                    leading_includes: vec![format!("{}({})", name, include)],
+                    keys: collect_dead_keys(name, mode_keys, parent)?,
                     trailing_includes: vec![],
                 });
             }
@@ -87,16 +111,68 @@ impl XkbFile {
 
         Ok(XkbFile { default, others })
     }
+
+    /// Adds a variant of the layout `name` with the symbols `include`, like
+    /// `no` or `fi(classic)`, in the second group, switched to with `toggle`
+    /// from the `group` symbols
+    pub fn add_second_group(&mut self, name: &str, include: &str, group_name: &str, toggle: &str) {
+        self.others.push(Symbols {
+            id: SECOND_GROUP_VARIANT.to_string(),
+            name: self.default.name.clone(),
+            second_group_name: Some(group_name.to_string()),
+            leading_includes: vec![format!("{}(basic)", name), format!("{}:2", include)],
+            keys: vec![],
+            trailing_includes: vec![format!("group({})", toggle)],
+        });
+    }
+}
+
+/// The symbols of the options that give the modifier keys their roles
+///
+/// Right Alt is AltGr unless the layout remaps it. Variants include the
+/// default symbols, so only the default symbols turn caps lock off for the
+/// `no-caps-lock` feature.
+fn modifier_includes(name: &str, layout: &Layout, is_default: bool) -> Vec<String> {
+    let remaps = layout.modifier_remaps(Target::X11);
+    let mut includes = vec![];
+    if !remaps.contains_key(&ModifierKey::RightAlt) {
+        includes.push("level3(ralt_switch)".to_string());
+    }
+    if is_default
+        && layout.has_feature(Feature::NoCapsLock)
+        && !remaps.contains_key(&ModifierKey::Caps)
+    {
+        includes.push("capslock(none)".to_string());
+    }
+    for (key, role) in remaps {
+        match modifier_remaps::xkb_option(key, role) {
+            Some((option, include)) => {
+                if is_default {
+                    log::debug!("{}: including the symbols of XKB option `{}`", name, option);
+                }
+                includes.push(include.to_string())
+            }
+            None if is_default => loss::report(
+                Loss::dropped("x11", name, format!("modifier remap of `{}`", key))
+                    .because(format!("XKB has no option to make it {}", role)),
+            ),
+            None => {}
+        }
+    }
+    includes
+}
+
+/// The key map of a mode, including those derived from optional modifiers
+fn mode(modes: &DesktopModes, name: &str) -> Option<DesktopKeyMap> {
+    let mode = name.parse().expect("valid mode name");
+    modes.get_mode(&mode).cloned()
 }
 
 fn collect_keys(key_map: &DesktopModes, _default: Option<&Symbols>) -> Result<Vec<Key>, Error> {
-    let default = key_map
-        .get("default")
-        .cloned()
-        .ok_or(Error::NoDefaultKeyMap)?;
-    let shift = key_map.get("shift").cloned().unwrap_or_default();
-    let alt = key_map.get("alt").cloned().unwrap_or_default();
-    let alt_shift = key_map.get("alt+shift").cloned().unwrap_or_default();
+    let default = mode(key_map, "default").ok_or(Error::NoDefaultKeyMap)?;
+    let shift = mode(key_map, "shift").unwrap_or_default();
+    let alt = mode(key_map, "alt").unwrap_or_default();
+    let alt_shift = mode(key_map, "alt+shift").unwrap_or_default();
 
     let mut res = Vec::new();
 
@@ -118,6 +194,7 @@ fn collect_keys(key_map: &DesktopModes, _default: Option<&Symbols>) -> Result<Ve
 }
 
 fn collect_dead_keys(
+    name: &str,
     key_map: &BTreeMap<String, Vec<String>>,
     parent: &DesktopModes,
 ) -> Result<Vec<Key>, Error> {
@@ -133,19 +210,20 @@ fn collect_dead_keys(
         }
     }
 
-    let default = parent
-        .get("default")
-        .cloned()
-        .ok_or(Error::NoDefaultKeyMap)?;
-    let shift = parent.get("shift").cloned().unwrap_or_default();
-    let alt = parent.get("alt").cloned().unwrap_or_default();
-    let alt_shift = parent.get("alt_shift").cloned().unwrap_or_default();
+    let default = mode(parent, "default").ok_or(Error::NoDefaultKeyMap)?;
+    let shift = mode(parent, "shift").unwrap_or_default();
+    let alt = mode(parent, "alt").unwrap_or_default();
+    let alt_shift = mode(parent, "alt+shift").unwrap_or_default();
 
     let mut overwritten_keys = BTreeMap::new();
 
-    for (mode, map) in key_map {
+    for (mode_name, map) in key_map {
+        let level = match mode_name.parse::<ModeName>() {
+            Ok(mode_name) => mode_name.required,
+            Err(_) => continue,
+        };
         let parent_key = parent
-            .get(mode)
+            .get_mode(&level)
             .expect("dead keys only defined for know modes");
         for key in map {
             let (iso_code, value) = parent_key
@@ -168,14 +246,14 @@ fn collect_dead_keys(
             let dead = if let Some(x) = dead {
                 x
             } else {
-                log::warn!(
-                    "while generating XKB dead key block: `{}` cannot be turned into a dead key",
-                    value
+                loss::report(
+                    Loss::altered("x11", name, format!("dead key `{}` in `{}`", value, level))
+                        .because("XKB has no dead key for it, it will be typed as it is"),
                 );
                 continue;
             };
 
-            match mode.as_str() {
+            match level.to_string().as_str() {
                 "default" => key.default = Some(dead),
                 "shift" => key.shift = Some(dead),
                 "alt" => key.alt = Some(dead),
@@ -195,8 +273,94 @@ fn collect_dead_keys(
 pub enum Error {
     #[error("No `default` keymap")]
     NoDefaultKeyMap,
-    #[error("No XKB compatible modes, found: {}", available_modes.join(", "))]
-    NoXkbCompatibleModes { available_modes: Vec<String> },
+    #[error("No XKB compatible modes, found: {}", join_targets(available_modes))]
+    NoXkbCompatibleModes { available_modes: Vec<Target> },
     #[error("Cannot set dead keys for unconfigured target `{}`", target)]
-    DeadKeysForUnconfiguredTarget { target: String },
+    DeadKeysForUnconfiguredTarget { target: Target },
+}
+
+fn join_targets(targets: &[Target]) -> String {
+    targets
+        .iter()
+        .map(|t| t.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xkb::ToXkb;
+
+    #[test]
+    fn second_group_is_a_variant_of_its_own() {
+        let layout: Layout = serde_yaml::from_str(
+            r#"
+displayNames:
+  se: Davvisámegiella
+modes:
+  x11:
+    default: |
+      § 1 2 3 4 5 6 7 8 9 0 + ´
+        á š e r t y u i o p å ŋ
+        a s d f g h j k l ö æ đ
+      ž z č c v b n m , . -
+"#,
+        )
+        .unwrap();
+        let mut file = XkbFile::from_layout("se", layout, &LocaleFallbacks::default()).unwrap();
+        file.add_second_group("se", "no", "Norwegian", "alt_shift_toggle");
+
+        let mut symbols = vec![];
+        file.others.last().unwrap().write_xkb(&mut symbols).unwrap();
+        assert_eq!(
+            String::from_utf8(symbols).unwrap(),
+            "partial alphanumeric_keys\n\
+             xkb_symbols \"bilingual\" {\n    \
+             name[Group1] = \"Davvisámegiella (x11)\";\n    \
+             name[Group2] = \"Norwegian\";\n    \n    \
+             include \"se(basic)\"\n    \
+             include \"no:2\"\n\n    \
+             include \"group(alt_shift_toggle)\"\n\
+             };\n\n"
+        );
+    }
+
+    #[test]
+    fn modifier_remaps_include_xkb_options() {
+        let layout: Layout = serde_yaml::from_str(
+            r#"
+displayNames:
+  se: Davvisámegiella
+modes:
+  x11:
+    default: |
+      § 1 2 3 4 5 6 7 8 9 0 + ´
+        á š e r t y u i o p å ŋ
+        a s d f g h j k l ö æ đ
+      ž z č c v b n m , . -
+  win:
+    default: |
+      § 1 2 3 4 5 6 7 8 9 0 + ´
+        á š e r t y u i o p å ŋ
+        a s d f g h j k l ö æ đ
+      ž z č c v b n m , . -
+features: [no-caps-lock]
+modifierRemaps:
+  x11:
+    caps: level3
+    right-alt: alt
+"#,
+        )
+        .unwrap();
+        let file = XkbFile::from_layout("se", layout, &LocaleFallbacks::default()).unwrap();
+        assert_eq!(
+            file.default.trailing_includes,
+            vec!["level3(caps_switch)", "level3(ralt_alt)"]
+        );
+        assert_eq!(
+            file.others[0].trailing_includes,
+            file.default.trailing_includes
+        );
+    }
 }
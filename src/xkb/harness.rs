@@ -0,0 +1,444 @@
+//! Typing on generated symbols with xkbcommon
+//!
+//! The symbols of a layout are compiled by libxkbcommon into a keymap with the
+//! usual evdev keycodes, types and compat sections, looking them up in a
+//! temporary directory ahead of the system's, so nothing has to be installed.
+//! Key presses then go through an `xkb_state` as evdev key events, with the
+//! modifiers held down around them, and the keysyms they produce through the
+//! `en_US.UTF-8` compose table, which is what gives dead keys their meaning on
+//! Linux. What comes out is what an application using xkbcommon would get.
+//!
+//! libxkbcommon is loaded when a harness is made rather than linked, so that
+//! kbdgen runs without it everywhere else.
+
+use super::{ToXkb, XkbFile};
+use crate::{models::IsoKey, KeyPress, Modifier};
+use std::{
+    ffi::{CStr, CString},
+    fs,
+    os::raw::{c_char, c_int, c_void},
+    path::PathBuf,
+};
+
+/// The compose table that dead keys go through
+const COMPOSE_LOCALE: &str = "en_US.UTF-8";
+
+type Ptr = *mut c_void;
+
+const XKB_CONTEXT_NO_DEFAULT_INCLUDES: c_int = 1;
+const XKB_CONTEXT_NO_ENVIRONMENT_NAMES: c_int = 2;
+const XKB_KEYMAP_FORMAT_TEXT_V1: c_int = 1;
+const XKB_KEY_UP: c_int = 0;
+const XKB_KEY_DOWN: c_int = 1;
+const XKB_COMPOSE_COMPOSING: c_int = 1;
+const XKB_COMPOSE_COMPOSED: c_int = 2;
+const XKB_COMPOSE_CANCELLED: c_int = 3;
+
+/// Declares the functions of libxkbcommon that are used, and `Api::load`
+/// to look them up in the loaded library
+macro_rules! api {
+    ($($name:ident: fn($($arg:ty),*) $(-> $ret:ty)?;)*) => {
+        struct Api {
+            $($name: unsafe extern "C" fn($($arg),*) $(-> $ret)?,)*
+        }
+
+        impl Api {
+            /// Safety: `handle` must be a library loaded with `dlopen`
+            #[cfg(unix)]
+            unsafe fn load(handle: Ptr) -> Option<Api> {
+                Some(Api {
+                    $($name: {
+                        let name = concat!(stringify!($name), "\0");
+                        let symbol = dl::dlsym(handle, name.as_ptr() as *const c_char);
+                        if symbol.is_null() {
+                            return None;
+                        }
+                        std::mem::transmute::<Ptr, unsafe extern "C" fn($($arg),*) $(-> $ret)?>(
+                            symbol,
+                        )
+                    },)*
+                })
+            }
+        }
+    };
+}
+
+api! {
+    xkb_context_new: fn(c_int) -> Ptr;
+    xkb_context_include_path_append: fn(Ptr, *const c_char) -> c_int;
+    xkb_context_include_path_append_default: fn(Ptr) -> c_int;
+    xkb_context_unref: fn(Ptr);
+    xkb_keymap_new_from_string: fn(Ptr, *const c_char, c_int, c_int) -> Ptr;
+    xkb_keymap_unref: fn(Ptr);
+    xkb_state_new: fn(Ptr) -> Ptr;
+    xkb_state_unref: fn(Ptr);
+    xkb_state_update_key: fn(Ptr, u32, c_int) -> c_int;
+    xkb_state_key_get_one_sym: fn(Ptr, u32) -> u32;
+    xkb_state_key_get_utf8: fn(Ptr, u32, *mut c_char, usize) -> c_int;
+    xkb_compose_table_new_from_locale: fn(Ptr, *const c_char, c_int) -> Ptr;
+    xkb_compose_table_unref: fn(Ptr);
+    xkb_compose_state_new: fn(Ptr, c_int) -> Ptr;
+    xkb_compose_state_unref: fn(Ptr);
+    xkb_compose_state_feed: fn(Ptr, u32) -> c_int;
+    xkb_compose_state_get_status: fn(Ptr) -> c_int;
+    xkb_compose_state_get_utf8: fn(Ptr, *mut c_char, usize) -> c_int;
+}
+
+#[cfg(unix)]
+mod dl {
+    use std::os::raw::{c_char, c_int, c_void};
+
+    pub const RTLD_NOW: c_int = 2;
+
+    extern "C" {
+        pub fn dlopen(filename: *const c_char, flags: c_int) -> *mut c_void;
+        pub fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    }
+}
+
+#[cfg(unix)]
+fn load_api() -> Option<Api> {
+    const NAMES: &[&str] = &[
+        "libxkbcommon.so.0\0",
+        "libxkbcommon.so\0",
+        "libxkbcommon.0.dylib\0",
+    ];
+    NAMES.iter().find_map(|name| unsafe {
+        let handle = dl::dlopen(name.as_ptr() as *const c_char, dl::RTLD_NOW);
+        if handle.is_null() {
+            None
+        } else {
+            Api::load(handle)
+        }
+    })
+}
+
+#[cfg(not(unix))]
+fn load_api() -> Option<Api> {
+    None
+}
+
+lazy_static::lazy_static! {
+    static ref XKBCOMMON: Option<Api> = load_api();
+}
+
+/// The evdev key code of an ISO key, as xkbcommon counts them, 8 more than
+/// the kernel
+fn keycode(key: IsoKey) -> u32 {
+    use IsoKey::*;
+    let evdev = match key {
+        E00 => 41,
+        E01 | E02 | E03 | E04 | E05 | E06 | E07 | E08 | E09 | E10 | E11 | E12 => {
+            2 + (key as u32 - E01 as u32)
+        }
+        D01 | D02 | D03 | D04 | D05 | D06 | D07 | D08 | D09 | D10 | D11 | D12 => {
+            16 + (key as u32 - D01 as u32)
+        }
+        C01 | C02 | C03 | C04 | C05 | C06 | C07 | C08 | C09 | C10 | C11 => {
+            30 + (key as u32 - C01 as u32)
+        }
+        C12 => 43,
+        B00 => 86,
+        B01 | B02 | B03 | B04 | B05 | B06 | B07 | B08 | B09 | B10 => 44 + (key as u32 - B01 as u32),
+    };
+    evdev + 8
+}
+
+/// The key held down for a modifier. `alt` is AltGr, the third level of the
+/// symbols.
+fn modifier_keycode(modifier: Modifier) -> u32 {
+    let evdev = match modifier {
+        Modifier::Shift => 42,
+        Modifier::Alt => 100,
+        Modifier::Ctrl => 29,
+        Modifier::Caps => 58,
+        Modifier::Cmd => 125,
+    };
+    evdev + 8
+}
+
+/// The id of the variant the harness types on: the one with the dead keys of
+/// the default symbols, if there is one
+fn variant(name: &str, file: &XkbFile) -> String {
+    let basic = format!("{}(basic)", name);
+    file.others
+        .iter()
+        .find(|symbols| {
+            symbols.id.ends_with("_deadkeys") && symbols.leading_includes == [basic.clone()]
+        })
+        .map(|symbols| symbols.id.clone())
+        .unwrap_or_else(|| file.default.id.clone())
+}
+
+/// A keymap compiled from the symbols of a layout, to type on
+pub struct Harness {
+    api: &'static Api,
+    context: Ptr,
+    keymap: Ptr,
+    compose_table: Ptr,
+    pub variant: String,
+}
+
+impl Harness {
+    /// Compiles the symbols `file` of the layout `name`
+    pub fn new(name: &str, file: &XkbFile) -> Result<Harness, Error> {
+        let api = XKBCOMMON.as_ref().ok_or(Error::CannotLoad)?;
+
+        let mut symbols = vec![];
+        file.write_xkb(&mut symbols)
+            .map_err(|source| Error::CannotSerialize { source })?;
+        let dir = tempfile::tempdir().map_err(|source| Error::CannotWrite {
+            path: std::env::temp_dir(),
+            source,
+        })?;
+        let symbols_path = dir.path().join("symbols").join(name);
+        fs::create_dir_all(dir.path().join("symbols"))
+            .and_then(|_| fs::write(&symbols_path, &symbols))
+            .map_err(|source| Error::CannotWrite {
+                path: symbols_path.clone(),
+                source,
+            })?;
+
+        let variant = variant(name, file);
+        let include = cstring(&dir.path().to_string_lossy());
+        let keymap = cstring(&super::verify::keymap(name, &variant));
+        let locale = cstring(COMPOSE_LOCALE);
+
+        let mut harness = Harness {
+            api,
+            context: std::ptr::null_mut(),
+            keymap: std::ptr::null_mut(),
+            compose_table: std::ptr::null_mut(),
+            variant,
+        };
+        // Safety: the pointers passed are those xkbcommon returned, checked
+        // for null, and they are released in `drop`
+        unsafe {
+            harness.context = (api.xkb_context_new)(
+                XKB_CONTEXT_NO_DEFAULT_INCLUDES | XKB_CONTEXT_NO_ENVIRONMENT_NAMES,
+            );
+            if harness.context.is_null() {
+                return Err(Error::CannotCreateContext);
+            }
+            (api.xkb_context_include_path_append)(harness.context, include.as_ptr());
+            (api.xkb_context_include_path_append_default)(harness.context);
+
+            harness.keymap = (api.xkb_keymap_new_from_string)(
+                harness.context,
+                keymap.as_ptr(),
+                XKB_KEYMAP_FORMAT_TEXT_V1,
+                0,
+            );
+            if harness.keymap.is_null() {
+                return Err(Error::CannotCompile {
+                    variant: harness.variant.clone(),
+                });
+            }
+
+            harness.compose_table =
+                (api.xkb_compose_table_new_from_locale)(harness.context, locale.as_ptr(), 0);
+            if harness.compose_table.is_null() {
+                return Err(Error::NoComposeTable {
+                    locale: COMPOSE_LOCALE.to_string(),
+                });
+            }
+        }
+
+        Ok(harness)
+    }
+
+    /// What `presses` type, from a state with no modifiers or dead keys
+    /// pending. A dead key left pending at the end types nothing, as on
+    /// Linux.
+    pub fn type_keys(&self, presses: &[KeyPress]) -> Result<String, Error> {
+        let api = self.api;
+        let mut output = String::new();
+
+        // Safety: see `new`; the states are released before returning
+        unsafe {
+            let state = (api.xkb_state_new)(self.keymap);
+            if state.is_null() {
+                return Err(Error::CannotCreateState);
+            }
+            let compose = (api.xkb_compose_state_new)(self.compose_table, 0);
+            if compose.is_null() {
+                (api.xkb_state_unref)(state);
+                return Err(Error::CannotCreateState);
+            }
+
+            let tap = |keycode: u32| {
+                (api.xkb_state_update_key)(state, keycode, XKB_KEY_DOWN);
+                (api.xkb_state_update_key)(state, keycode, XKB_KEY_UP);
+            };
+            for press in presses {
+                // Caps lock is a lock, so it is tapped on and off again
+                for modifier in press.mode.iter() {
+                    match modifier {
+                        Modifier::Caps => tap(modifier_keycode(modifier)),
+                        _ => {
+                            (api.xkb_state_update_key)(
+                                state,
+                                modifier_keycode(modifier),
+                                XKB_KEY_DOWN,
+                            );
+                        }
+                    }
+                }
+
+                let keycode = keycode(press.key);
+                let keysym = (api.xkb_state_key_get_one_sym)(state, keycode);
+                (api.xkb_compose_state_feed)(compose, keysym);
+                match (api.xkb_compose_state_get_status)(compose) {
+                    XKB_COMPOSE_COMPOSING | XKB_COMPOSE_CANCELLED => {}
+                    XKB_COMPOSE_COMPOSED => output.push_str(&read_utf8(|buffer, size| {
+                        (api.xkb_compose_state_get_utf8)(compose, buffer, size)
+                    })),
+                    _ => output.push_str(&read_utf8(|buffer, size| {
+                        (api.xkb_state_key_get_utf8)(state, keycode, buffer, size)
+                    })),
+                }
+                tap(keycode);
+
+                for modifier in press.mode.iter() {
+                    match modifier {
+                        Modifier::Caps => tap(modifier_keycode(modifier)),
+                        _ => {
+                            (api.xkb_state_update_key)(
+                                state,
+                                modifier_keycode(modifier),
+                                XKB_KEY_UP,
+                            );
+                        }
+                    }
+                }
+            }
+
+            (api.xkb_compose_state_unref)(compose);
+            (api.xkb_state_unref)(state);
+        }
+
+        Ok(output)
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        let api = self.api;
+        // Safety: the pointers are from xkbcommon, or null if making the
+        // harness stopped before them
+        unsafe {
+            if !self.compose_table.is_null() {
+                (api.xkb_compose_table_unref)(self.compose_table);
+            }
+            if !self.keymap.is_null() {
+                (api.xkb_keymap_unref)(self.keymap);
+            }
+            if !self.context.is_null() {
+                (api.xkb_context_unref)(self.context);
+            }
+        }
+    }
+}
+
+fn cstring(s: &str) -> CString {
+    CString::new(s).expect("no NUL in paths and keymaps")
+}
+
+/// The text an xkbcommon function that writes UTF-8 to a buffer writes
+fn read_utf8(write: impl Fn(*mut c_char, usize) -> c_int) -> String {
+    let mut buffer = [0 as c_char; 64];
+    let written = write(buffer.as_mut_ptr(), buffer.len());
+    if written <= 0 {
+        return String::new();
+    }
+    // Safety: xkbcommon NUL terminates what it writes, truncating it to fit
+    unsafe { CStr::from_ptr(buffer.as_ptr()) }
+        .to_string_lossy()
+        .to_string()
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not load libxkbcommon, is it installed?")]
+    CannotLoad,
+    #[error("Could not serialize XKB symbols")]
+    CannotSerialize { source: std::io::Error },
+    #[error("Could not write `{}`", path.display())]
+    CannotWrite {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Could not create an xkbcommon context")]
+    CannotCreateContext,
+    #[error(
+        "xkbcommon could not compile the variant `{}`, `kbdgen build x11 --verify` shows why",
+        variant
+    )]
+    CannotCompile { variant: String },
+    #[error("xkbcommon has no compose table for `{}`", locale)]
+    NoComposeTable { locale: String },
+    #[error("Could not create an xkbcommon state")]
+    CannotCreateState,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{models::Layout, LocaleFallbacks};
+    use strum::IntoEnumIterator;
+
+    #[test]
+    fn iso_keys_have_evdev_keycodes() {
+        let keycodes = IsoKey::iter().map(keycode).collect::<Vec<_>>();
+        let mut unique = keycodes.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        assert_eq!(unique.len(), keycodes.len());
+        assert_eq!(keycode(IsoKey::E01), 10);
+        assert_eq!(keycode(IsoKey::D01), 24);
+        assert_eq!(keycode(IsoKey::C11), 48);
+        assert_eq!(keycode(IsoKey::B10), 61);
+    }
+
+    #[test]
+    fn keys_are_typed_on_the_compiled_symbols() {
+        let layout: Layout = serde_yaml::from_str(
+            r#"
+displayNames:
+  en: Test
+modes:
+  x11:
+    default: |
+      § 1 2 3 4 5 6 7 8 9 0 + ´
+        á š e r t y u i o p å ŋ
+        a s d f g h j k l ö æ đ
+      ž z č c v b n m , . -
+    shift: |
+      ½ ! " # ¤ % & / ( ) = ? `
+        Á Š E R T Y U I O P Å Ŋ
+        A S D F G H J K L Ö Æ Đ
+      Ž Z Č C V B N M ; : _
+deadKeys:
+  x11:
+    default: ["´"]
+"#,
+        )
+        .unwrap();
+        let file = XkbFile::from_layout("kbdgentest", layout, &LocaleFallbacks::default()).unwrap();
+        assert_eq!(variant("kbdgentest", &file), "x11_deadkeys");
+
+        // Without libxkbcommon there is nothing to type on
+        let harness = match Harness::new("kbdgentest", &file) {
+            Err(Error::CannotLoad) => return,
+            harness => harness.unwrap(),
+        };
+        let typed = |keys: &str| {
+            harness
+                .type_keys(&KeyPress::parse_sequence(keys).unwrap())
+                .unwrap()
+        };
+        assert_eq!(typed("D01 shift+D02 shift+E05"), "áŠ%");
+        assert_eq!(typed("E12 C01"), "á");
+        assert_eq!(typed("caps+D02 D02"), "Šš");
+    }
+}
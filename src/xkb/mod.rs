@@ -1,8 +1,10 @@
 use std::fmt;
 
 mod convert;
+pub mod harness;
 mod ser;
-pub use convert::Error as ConversionError;
+pub mod verify;
+pub use convert::{Error as ConversionError, SECOND_GROUP_VARIANT, XKB_SOURCE_TARGETS};
 pub use ser::ToXkb;
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
@@ -15,6 +17,8 @@ pub struct XkbFile {
 pub struct Symbols {
     pub id: String,
     pub name: String,
+    /// The name of the second group, if the symbols include one
+    pub second_group_name: Option<String>,
     pub leading_includes: Vec<String>,
     pub keys: Vec<Key>,
     pub trailing_includes: Vec<String>,
@@ -38,7 +42,19 @@ impl fmt::Display for XkbKeySym {
         if let Some(sym) = x11_keysymdef::lookup_by_codepoint(c).and_then(|r| r.names.get(0)) {
             write!(f, "{}", sym)
         } else {
-            write!(f, "U{}", c as u32)
+            write!(f, "U{:04X}", c as u32)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code_point_keysyms_are_hex() {
+        // xkbcommon reads `U49` as U+0049 `I`, not `1`
+        assert_eq!(XkbKeySym("1".into()).to_string(), "U0031");
+        assert_eq!(XkbKeySym("€".into()).to_string(), "U20AC");
+    }
+}
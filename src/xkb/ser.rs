@@ -27,6 +27,9 @@ impl ToXkb for Symbols {
         let mut inner = PadAdapter::wrap(&mut w);
         {
             writeln!(inner, r#"name[Group1] = "{}";"#, self.name)?;
+            if let Some(name) = &self.second_group_name {
+                writeln!(inner, r#"name[Group2] = "{}";"#, name)?;
+            }
             writeln!(inner)?;
 
             let mut inner = PadAdapter::wrap(&mut w);
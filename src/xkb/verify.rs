@@ -0,0 +1,307 @@
+//! Checking generated symbols with `xkbcomp`
+//!
+//! A symbols file cannot be compiled on its own, so each variant is included
+//! into a keymap with the usual evdev keycodes, types and compat sections.
+//! Diagnostics name either a line of the symbols file or a key, and both are
+//! mapped back to the ISO key of the layout the line was generated from.
+
+use super::{ToXkb, XkbFile};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// A diagnostic from compiling one variant of the symbols
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub severity: Severity,
+    pub variant: String,
+    /// ISO key code, like `E01`
+    pub key: Option<String>,
+    pub message: String,
+}
+
+impl Problem {
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.variant)?;
+        if let Some(key) = &self.key {
+            write!(f, ", key {}", key)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// A keymap of the variant `variant` of the symbols `name`
+pub(super) fn keymap(name: &str, variant: &str) -> String {
+    format!(
+        r#"xkb_keymap {{
+    xkb_keycodes {{ include "evdev+aliases(qwerty)" }};
+    xkb_types {{ include "complete" }};
+    xkb_compat {{ include "complete" }};
+    xkb_symbols {{ include "pc+{}({})" }};
+}};
+"#,
+        name, variant
+    )
+}
+
+/// The ISO key each line of the symbols defines, by 1-based line number
+fn line_keys(symbols: &str) -> BTreeMap<usize, String> {
+    symbols
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let rest = line.trim_start().strip_prefix("key <A")?;
+            let end = rest.find('>')?;
+            Some((i + 1, rest[..end].to_string()))
+        })
+        .collect()
+}
+
+/// Splits compiler output into problems. Messages start at the beginning of a
+/// line, continuation lines are indented, except for the symbol a syntax
+/// error was found at.
+fn parse_output(
+    output: &str,
+    variant: &str,
+    file_name: &str,
+    line_keys: &BTreeMap<usize, String>,
+) -> Vec<Problem> {
+    lazy_static! {
+        static ref KEY: Regex = Regex::new(r"<A([A-E]\d\d)>").unwrap();
+        // xkbcomp: `syntax error: line 5 of symbols/se`
+        static ref XKBCOMP_LINE: Regex = Regex::new(r"line (\d+) of (\S+)").unwrap();
+        // xkbcommon: `symbols/se:5:10: syntax error`
+        static ref XKBCOMMON_LINE: Regex = Regex::new(r"(\S+):(\d+):\d+:").unwrap();
+    }
+
+    let mut messages: Vec<String> = vec![];
+    for line in output.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match messages.last_mut() {
+            Some(message)
+                if line.starts_with(char::is_whitespace)
+                    || line.starts_with("last scanned symbol") =>
+            {
+                message.push(' ');
+                message.push_str(line.trim());
+            }
+            _ => messages.push(line.trim().to_string()),
+        }
+    }
+
+    let is_ours = |path: &str| Path::new(path).file_name() == Some(file_name.as_ref());
+    messages
+        .into_iter()
+        .filter(|message| {
+            !message.starts_with("Exiting") && !message.starts_with("Errors encountered")
+        })
+        .filter_map(|message| {
+            let line = XKBCOMP_LINE
+                .captures(&message)
+                .filter(|c| is_ours(&c[2]))
+                .map(|c| c[1].to_string())
+                .or_else(|| {
+                    XKBCOMMON_LINE
+                        .captures(&message)
+                        .filter(|c| is_ours(&c[1]))
+                        .map(|c| c[2].to_string())
+                })
+                .and_then(|line| line.parse::<usize>().ok());
+            let key = line
+                .and_then(|line| line_keys.get(&line).cloned())
+                .or_else(|| KEY.captures(&message).map(|c| c[1].to_string()));
+
+            let lower = message.to_lowercase();
+            let severity = if lower.starts_with("warning") || lower.contains(": warning:") {
+                // Warnings about the system files are not ours to fix
+                if key.is_none() && line.is_none() {
+                    return None;
+                }
+                Severity::Warning
+            } else {
+                Severity::Error
+            };
+
+            Some(Problem {
+                severity,
+                variant: variant.to_string(),
+                key,
+                message,
+            })
+        })
+        .collect()
+}
+
+/// Compiles every variant of the symbols file for the layout `name`
+pub fn verify(name: &str, file: &XkbFile) -> Result<Vec<Problem>, Error> {
+    verify_with(name, file, &[])
+}
+
+/// Like `verify`, with the symbols files of other layouts that `file`
+/// includes, like the one of a second group
+pub fn verify_with(
+    name: &str,
+    file: &XkbFile,
+    included: &[(&str, &XkbFile)],
+) -> Result<Vec<Problem>, Error> {
+    let mut symbols = vec![];
+    file.write_xkb(&mut symbols)
+        .map_err(|source| Error::CannotSerialize { source })?;
+    let symbols = String::from_utf8(symbols).expect("symbols are written as UTF-8");
+    let line_keys = line_keys(&symbols);
+
+    let dir = tempfile::tempdir().map_err(|source| Error::CannotWrite {
+        path: std::env::temp_dir(),
+        source,
+    })?;
+    let symbols_path = dir.path().join("symbols").join(name);
+    fs::create_dir_all(dir.path().join("symbols"))
+        .and_then(|_| fs::write(&symbols_path, &symbols))
+        .map_err(|source| Error::CannotWrite {
+            path: symbols_path.clone(),
+            source,
+        })?;
+    for (other, other_file) in included {
+        let mut other_symbols = vec![];
+        other_file
+            .write_xkb(&mut other_symbols)
+            .map_err(|source| Error::CannotSerialize { source })?;
+        let path = dir.path().join("symbols").join(other);
+        fs::write(&path, other_symbols).map_err(|source| Error::CannotWrite { path, source })?;
+    }
+
+    let mut problems = vec![];
+    for variant in std::iter::once(&file.default).chain(&file.others) {
+        let keymap_path = dir.path().join("keymap.xkb");
+        fs::write(&keymap_path, keymap(name, &variant.id)).map_err(|source| {
+            Error::CannotWrite {
+                path: keymap_path.clone(),
+                source,
+            }
+        })?;
+
+        let output = Command::new("xkbcomp")
+            .arg(format!("-I{}", dir.path().display()))
+            .arg(&keymap_path)
+            .arg(dir.path().join("out.xkb"))
+            .output()
+            .map_err(|source| Error::CannotRunXkbcomp { source })?;
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let mut found = parse_output(&stderr, &variant.id, name, &line_keys);
+        if !output.status.success() && !found.iter().any(Problem::is_error) {
+            found.push(Problem {
+                severity: Severity::Error,
+                variant: variant.id.clone(),
+                key: None,
+                message: format!("xkbcomp exited with {}", output.status),
+            });
+        }
+        problems.extend(found);
+    }
+
+    Ok(problems)
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not serialize XKB symbols")]
+    CannotSerialize { source: std::io::Error },
+    #[error("Could not write `{}`", path.display())]
+    CannotWrite {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Could not run `xkbcomp`, is it installed?")]
+    CannotRunXkbcomp { source: std::io::Error },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SYMBOLS: &str = r#"default partial alphanumeric_keys
+xkb_symbols "basic" {
+    name[Group1] = "Test (x11)";
+
+    include "latin"
+
+    key <AE01> { [ 1, exclam ] };
+    key <AD01> { [ q, Q ] };
+};
+"#;
+
+    #[test]
+    fn lines_map_to_keys() {
+        let keys = line_keys(SYMBOLS);
+        let problems = parse_output(
+            "syntax error: line 8 of /tmp/x/symbols/test\n\
+             last scanned symbol is: Q\n\
+             Errors encountered in /tmp/x/keymap.xkb; not compiled.\n",
+            "basic",
+            "test",
+            &keys,
+        );
+        assert_eq!(
+            problems,
+            vec![Problem {
+                severity: Severity::Error,
+                variant: "basic".to_string(),
+                key: Some("D01".to_string()),
+                message: "syntax error: line 8 of /tmp/x/symbols/test \
+                          last scanned symbol is: Q"
+                    .to_string(),
+            }]
+        );
+        assert_eq!(
+            problems[0].to_string(),
+            format!("basic, key D01: {}", problems[0].message)
+        );
+    }
+
+    #[test]
+    fn key_names_and_continuation_lines() {
+        let problems = parse_output(
+            "Warning:          Symbol map for key <AE01> redefined\n\
+             \x20                 Using last definition for conflicting fields\n\
+             Warning:          Could not resolve keysym XF86FullScreen\n\
+             xkbcommon: ERROR: test:7:22: unrecognized keysym \"exclamm\"\n",
+            "x11",
+            "test",
+            &line_keys(SYMBOLS),
+        );
+        let summary = problems
+            .iter()
+            .map(|p| (p.severity, p.key.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                (Severity::Warning, Some("E01")),
+                (Severity::Error, Some("E01"))
+            ]
+        );
+        assert!(problems[0]
+            .message
+            .ends_with("Using last definition for conflicting fields"));
+    }
+}
@@ -0,0 +1,239 @@
+//! Streaming XML output shared by the generators
+//!
+//! A thin layer over quick-xml's writer that does its own escaping. Besides
+//! the markup characters, whitespace other than a space, C1 controls and
+//! private use codepoints are written as character references: the former so
+//! they survive attribute value normalisation, the latter so they can be seen
+//! when reading the file. Characters XML 1.0 cannot represent at all, like
+//! most C0 controls, are an error instead of a broken document.
+
+use quick_xml::events::{attributes::Attribute, BytesDecl, BytesEnd, BytesStart, BytesText, Event};
+use std::{
+    borrow::Cow,
+    fmt::Write as _,
+    io::{self, Write},
+};
+
+/// Indentation of nested elements, as `PadAdapter` does for the other formats
+const INDENT: usize = 4;
+
+pub struct XmlWriter<W: Write> {
+    inner: quick_xml::Writer<W>,
+    open: Vec<String>,
+}
+
+fn io_error(error: quick_xml::Error) -> io::Error {
+    match error {
+        quick_xml::Error::Io(e) => e,
+        e => io::Error::new(io::ErrorKind::InvalidData, e.to_string()),
+    }
+}
+
+fn is_private_use(c: char) -> bool {
+    matches!(c, '\u{E000}'..='\u{F8FF}' | '\u{F0000}'..='\u{FFFFD}' | '\u{100000}'..='\u{10FFFD}')
+}
+
+/// Whether XML 1.0 can contain the character, raw or as a reference
+fn is_xml_char(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\r' | '\u{20}'..='\u{D7FF}' | '\u{E000}'..='\u{FFFD}' | '\u{10000}'..='\u{10FFFF}')
+}
+
+/// Escapes a value for use in both attributes and text
+pub fn escape(value: &str) -> io::Result<Cow<'_, str>> {
+    let needs_escape = |c: char| {
+        matches!(c, '&' | '<' | '>' | '"' | '\'' | '\t' | '\n' | '\r')
+            || c.is_control()
+            || is_private_use(c)
+            || !is_xml_char(c)
+    };
+    if !value.chars().any(needs_escape) {
+        return Ok(Cow::Borrowed(value));
+    }
+
+    let mut escaped = String::with_capacity(value.len() + 8);
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c if !is_xml_char(c) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("U+{:04X} cannot be written to XML", c as u32),
+                ))
+            }
+            c if c.is_control() || is_private_use(c) => {
+                let _ = write!(escaped, "&#x{:X};", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    Ok(Cow::Owned(escaped))
+}
+
+impl<W: Write> XmlWriter<W> {
+    pub fn new(w: W) -> Self {
+        XmlWriter {
+            inner: quick_xml::Writer::new_with_indent(w, b' ', INDENT),
+            open: vec![],
+        }
+    }
+
+    fn write(&mut self, event: Event<'_>) -> io::Result<()> {
+        self.inner.write_event(event).map_err(io_error)
+    }
+
+    fn element(name: &str, attributes: &[(&str, &str)]) -> io::Result<BytesStart<'static>> {
+        let mut start = BytesStart::owned_name(name.as_bytes().to_vec());
+        for (key, value) in attributes {
+            start.push_attribute(Attribute {
+                key: key.as_bytes(),
+                value: Cow::Owned(escape(value)?.into_owned().into_bytes()),
+            });
+        }
+        Ok(start)
+    }
+
+    /// `<?xml version="1.0" encoding="UTF-8"?>`
+    pub fn declaration(&mut self) -> io::Result<()> {
+        self.write(Event::Decl(BytesDecl::new(b"1.0", Some(b"UTF-8"), None)))
+    }
+
+    /// A `<!DOCTYPE ...>`, written as given
+    pub fn doctype(&mut self, doctype: &str) -> io::Result<()> {
+        self.write(Event::DocType(BytesText::from_escaped_str(format!(
+            " {}",
+            doctype
+        ))))
+    }
+
+    /// A `<!-- ... -->`, with any `--` in the text broken up
+    pub fn comment(&mut self, text: &str) -> io::Result<()> {
+        let text = text.replace("--", "- -");
+        self.write(Event::Comment(BytesText::from_escaped_str(format!(
+            " {} ",
+            text
+        ))))
+    }
+
+    pub fn start(&mut self, name: &str, attributes: &[(&str, &str)]) -> io::Result<()> {
+        let start = Self::element(name, attributes)?;
+        self.write(Event::Start(start))?;
+        self.open.push(name.to_string());
+        Ok(())
+    }
+
+    pub fn empty(&mut self, name: &str, attributes: &[(&str, &str)]) -> io::Result<()> {
+        let element = Self::element(name, attributes)?;
+        self.write(Event::Empty(element))
+    }
+
+    /// Closes the innermost open element
+    pub fn end(&mut self) -> io::Result<()> {
+        let name = self.open.pop().expect("no open element to end");
+        self.write(Event::End(BytesEnd::owned(name.into_bytes())))
+    }
+
+    /// An element containing only text, on a single line
+    pub fn text_element(
+        &mut self,
+        name: &str,
+        attributes: &[(&str, &str)],
+        text: &str,
+    ) -> io::Result<()> {
+        self.start(name, attributes)?;
+        let text = escape(text)?.into_owned();
+        self.write(Event::Text(BytesText::from_escaped_str(text)))?;
+        self.end()
+    }
+
+    /// Ends the document with a newline and returns the writer
+    pub fn finish(mut self) -> io::Result<W> {
+        debug_assert!(self.open.is_empty(), "unclosed elements: {:?}", self.open);
+        self.inner.inner().write_all(b"\n")?;
+        let mut w = self.inner.into_inner();
+        w.flush()?;
+        Ok(w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use xml::reader::{EventReader, XmlEvent};
+
+    /// The attribute value and text of `<a v="...">...</a>` as read back
+    fn round_trip(value: &str) -> (String, String) {
+        let mut w = XmlWriter::new(vec![]);
+        w.text_element("a", &[("v", value)], value).unwrap();
+        let xml = w.finish().unwrap();
+
+        let mut attribute = None;
+        let mut text = String::new();
+        for event in EventReader::new(&xml[..]) {
+            match event.unwrap() {
+                XmlEvent::StartElement { attributes, .. } => {
+                    attribute = Some(attributes[0].value.clone())
+                }
+                XmlEvent::Characters(t) | XmlEvent::Whitespace(t) => text.push_str(&t),
+                _ => {}
+            }
+        }
+        (attribute.unwrap(), text)
+    }
+
+    #[test]
+    fn nested_elements_are_indented() {
+        let mut w = XmlWriter::new(vec![]);
+        w.declaration().unwrap();
+        w.start("keyboard", &[("locale", "se")]).unwrap();
+        w.empty("map", &[("iso", "E00"), ("to", "<")]).unwrap();
+        w.text_element("name", &[], "a & b").unwrap();
+        w.end().unwrap();
+        let xml = String::from_utf8(w.finish().unwrap()).unwrap();
+
+        assert_eq!(
+            xml,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<keyboard locale="se">
+    <map iso="E00" to="&lt;"/>
+    <name>a &amp; b</name>
+</keyboard>
+"#
+        );
+    }
+
+    #[test]
+    fn invisible_characters_are_references() {
+        assert_eq!(escape("\u{E000}\t\u{85}a").unwrap(), "&#xE000;&#x9;&#x85;a");
+        assert!(escape("\u{1}").is_err());
+        assert!(escape("\u{FFFF}").is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn escaped_values_read_back_unchanged(value in "\\PC*") {
+            let (attribute, text) = round_trip(&value);
+            prop_assert_eq!(&attribute, &value);
+            prop_assert_eq!(&text, &value);
+        }
+
+        #[test]
+        fn escaping_never_leaves_markup_or_controls(value in any::<String>()) {
+            if let Ok(escaped) = escape(&value) {
+                let left_raw = escaped.chars().any(|c| {
+                    matches!(c, '<' | '>' | '"' | '\'') || c.is_control() || is_private_use(c)
+                });
+                prop_assert!(!left_raw);
+                let (attribute, text) = round_trip(&value);
+                prop_assert_eq!(&attribute, &value);
+                prop_assert_eq!(&text, &value);
+            } else {
+                prop_assert!(value.chars().any(|c| !is_xml_char(c)));
+            }
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! Generator output compared with known-good files in `tests/golden`
+//!
+//! The files are what the generators make of the `se-NO` layout of the
+//! example bundle. Stamps are left out of the output and ignored in the
+//! files. When a change to a generator is meant to change its output, run
+//! the tests with `KBDGEN_UPDATE_GOLDEN=1` to rewrite the files, and review
+//! their diff.
+//!
+//! The `.klc` and `.keylayout` files come from the Python generators, which
+//! only run in a binary with Python embedded, so their tests here are
+//! ignored; `pysrc/tests/test_golden.py` compares the same files with the
+//! output of the generators themselves.
+
+use assert_cmd::Command;
+use std::path::{Path, PathBuf};
+use tempfile::tempdir;
+
+const BUNDLE: &str = "examples/sme.kbdgen";
+
+fn golden_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(name)
+}
+
+/// The text of a generated file, which for `.klc` files is UTF-16
+fn decode(bytes: &[u8]) -> String {
+    match bytes {
+        [0xff, 0xfe, rest @ ..] => {
+            let units = rest
+                .chunks(2)
+                .map(|pair| u16::from_le_bytes([pair[0], *pair.get(1).unwrap_or(&0)]))
+                .collect::<Vec<_>>();
+            String::from_utf16(&units).expect("valid UTF-16")
+        }
+        _ => String::from_utf8(bytes.to_vec()).expect("valid UTF-8"),
+    }
+}
+
+/// The text without the comment lines of a stamp, see `kbdgen::stamp`
+fn without_stamp(text: &str) -> String {
+    const STAMP_LINES: [&str; 4] = [
+        "Generated by kbdgen ",
+        "Bundle commit: ",
+        "Build date: ",
+        "Layout: ",
+    ];
+    text.split_inclusive('\n')
+        .filter(|line| {
+            let line = line.trim_start();
+            let comment = ["//", ";", "#", "<!--"]
+                .iter()
+                .find_map(|prefix| line.strip_prefix(prefix));
+            match comment {
+                Some(comment) => !STAMP_LINES
+                    .iter()
+                    .any(|stamp| comment.trim_start().starts_with(stamp)),
+                None => true,
+            }
+        })
+        .collect()
+}
+
+/// Compares a generated file with the golden file of the same name, byte for
+/// byte apart from stamps
+fn assert_golden(generated: &Path) {
+    let name = generated.file_name().unwrap().to_string_lossy().to_string();
+    let golden = golden_path(&name);
+    let actual = std::fs::read(generated).unwrap();
+
+    if std::env::var_os("KBDGEN_UPDATE_GOLDEN").is_some() {
+        std::fs::write(&golden, &actual).unwrap();
+        return;
+    }
+
+    let expected = std::fs::read(&golden).unwrap_or_else(|e| {
+        panic!(
+            "Could not read `{}` ({}), run with KBDGEN_UPDATE_GOLDEN=1 to create it",
+            golden.display(),
+            e
+        )
+    });
+    assert_eq!(
+        actual[..2.min(actual.len())],
+        expected[..2.min(expected.len())],
+        "`{}` has a different encoding than its golden file",
+        name
+    );
+
+    let actual = without_stamp(&decode(&actual));
+    let expected = without_stamp(&decode(&expected));
+    let first_difference = actual
+        .lines()
+        .zip(expected.lines())
+        .enumerate()
+        .find(|(_, (actual, expected))| actual != expected);
+    if let Some((number, (actual, expected))) = first_difference {
+        panic!(
+            "`{}` differs from its golden file on line {}:\n  expected: {}\n  actual:   {}\n\
+             Run with KBDGEN_UPDATE_GOLDEN=1 to update it if that is intended",
+            name,
+            number + 1,
+            expected,
+            actual
+        );
+    }
+    assert!(
+        actual == expected,
+        "`{}` differs from its golden file in its length or line endings",
+        name
+    );
+}
+
+#[test]
+fn xkb_matches_golden() {
+    let tmp = tempdir().unwrap();
+    kbdgen::stamp::Stamp::disable();
+    let options = kbdgen::cli::to_xkb::Options {
+        standalone: false,
+        verify: false,
+    };
+    kbdgen::cli::to_xkb::kbdgen_to_xkb(Path::new(BUNDLE), tmp.path(), &options).unwrap();
+
+    assert_golden(&tmp.path().join("linux").join("se-NO.xkb"));
+}
+
+#[test]
+#[ignore = "downloads kbdi and needs the Python generators embedded in the binary"]
+fn klc_matches_golden() {
+    let tmp = tempdir().unwrap();
+    Command::cargo_bin("kbdgen")
+        .unwrap()
+        .env("KBDGEN_NO_STAMP", "1")
+        .arg("build")
+        .arg("win")
+        .arg(BUNDLE)
+        .arg("--output")
+        .arg(tmp.path())
+        .assert()
+        .success();
+
+    assert_golden(&tmp.path().join("kbdse-NO.klc"));
+}
+
+#[cfg(target_os = "macos")]
+#[test]
+#[ignore = "needs the Python generators embedded in the binary"]
+fn keylayout_matches_golden() {
+    let tmp = tempdir().unwrap();
+    Command::cargo_bin("kbdgen")
+        .unwrap()
+        .env("KBDGEN_NO_STAMP", "1")
+        .arg("build")
+        .arg("mac")
+        .arg(BUNDLE)
+        .arg("--output")
+        .arg(tmp.path())
+        .assert()
+        .success();
+
+    assert_golden(
+        &tmp.path()
+            .join("North Sami Keyboard.bundle")
+            .join("Contents")
+            .join("Resources")
+            .join("seNO.keylayout"),
+    );
+}
@@ -1,7 +1,543 @@
-use kbdgen::{Load, ProjectBundle};
+use kbdgen::{models::IsoKey, KeyPress, KeyValue, Load, ProjectBundle, Target};
 
 #[test]
 fn load_fixtures() {
     let bundle = ProjectBundle::load("examples/sme.kbdgen").unwrap();
     eprintln!("{:?}", bundle);
 }
+
+#[test]
+fn key_lookup_falls_back_to_generic_modes() {
+    let bundle = ProjectBundle::load("examples/sme.kbdgen").unwrap();
+    let shift = "shift".parse().unwrap();
+
+    let mobile = &bundle.layouts["se"];
+    let a_acute = KeyValue::Symbol("Á".into());
+    assert_eq!(mobile.key(Target::Ios, &shift, IsoKey::D01), Some(&a_acute));
+    assert_eq!(
+        mobile.key(Target::Android, &shift, IsoKey::D01),
+        Some(&a_acute)
+    );
+    assert_eq!(mobile.key(Target::Win, &shift, IsoKey::D01), None);
+
+    let desktop = &bundle.layouts["se-NO"];
+    let default = "default".parse().unwrap();
+    let a_acute = KeyValue::Symbol("á".into());
+    assert_eq!(
+        desktop.key(Target::Mac, &default, IsoKey::D01),
+        Some(&a_acute)
+    );
+    assert_eq!(desktop.key(Target::X11, &default, IsoKey::D01), None);
+}
+
+#[test]
+fn simulate_combines_dead_keys_through_transforms() {
+    let bundle = ProjectBundle::load("examples/sme.kbdgen").unwrap();
+    let layout = &bundle.layouts["se-NO"];
+    let press = |key, mode: &str| KeyPress {
+        key,
+        mode: mode.parse().unwrap(),
+    };
+
+    let presses = [
+        press(IsoKey::E12, "default"),
+        press(IsoKey::C01, "default"),
+        press(IsoKey::E12, "default"),
+        press(IsoKey::C01, "shift"),
+        press(IsoKey::C02, "default"),
+        press(IsoKey::E12, "default"),
+    ];
+    assert_eq!(layout.simulate(Target::Mac, &presses), "áÁs´");
+}
+
+#[test]
+fn optional_modifiers_derive_unspecified_modes() {
+    let layout: kbdgen::models::Layout = serde_yaml::from_str(
+        r#"
+displayNames:
+  en: Test
+modes:
+  win:
+    default: |
+      § 1 2 3 4 5 6 7 8 9 0 + ´
+        q w e r t y u i o p å ¨
+        a s d f g h j k l ö ä '
+      < z x c v b n m , . -
+    shift+caps?: |
+      ½ ! " # ¤ % & / ( ) = ? `
+        Q W E R T Y U I O P Å ^
+        A S D F G H J K L Ö Ä *
+      > Z X C V B N M ; : _
+    caps+shift: |
+      ½ ! " # ¤ % & / ( ) = ? `
+        q w e r t y u i o p å ^
+        a s d f g h j k l ö ä *
+      > z x c v b n m ; : _
+    alt+caps?: |
+      | @ £ $ € ‰ { [ ] } \ ± ´
+        œ ∑ é ® † µ ü ı ø π ˙ ~
+        æ ß ∂ ƒ ¸ ˛ √ ª ﬁ ö ä @
+      ≤ ÷ ≈ ç ‹ › ‘ ’ ‚ … –
+"#,
+    )
+    .unwrap();
+    assert!(layout.mode_name_errors().is_empty());
+
+    let key = |mode: &str| {
+        layout
+            .key(Target::Win, &mode.parse().unwrap(), IsoKey::D01)
+            .cloned()
+    };
+    let symbol = |s: &str| Some(KeyValue::Symbol(s.into()));
+    assert_eq!(key("shift"), symbol("Q"));
+    // Defined explicitly, so not derived from `shift+caps?`
+    assert_eq!(key("caps+shift"), symbol("q"));
+    assert_eq!(key("alt"), symbol("œ"));
+    assert_eq!(key("caps+alt"), symbol("œ"));
+    assert_eq!(key("caps"), None);
+}
+
+#[test]
+fn reordering_is_reported_for_desktop_targets() {
+    let layout: kbdgen::models::Layout = serde_yaml::from_str(
+        r#"
+displayNames:
+  en: Test
+modes:
+  mobile:
+    default: |
+      ក ខ គ េ ្
+  desktop:
+    default: |
+      § 1 2 3 4 5 6 7 8 9 0 + ´
+  mac:
+    default: |
+      § 1 2 3 4 5 6 7 8 9 0 + ´
+reorders:
+  - from: "េ"
+    order: 3
+  - from: "្"
+    before: "ក"
+    tertiaryBase: true
+backspaces:
+  "្ក": ""
+"#,
+    )
+    .unwrap();
+
+    let reorders = layout.reorders.as_ref().unwrap();
+    assert_eq!(reorders[0].from, "\u{17C1}");
+    assert_eq!(reorders[1].tertiary_base, Some(true));
+    assert_eq!(
+        layout.targets_without_reordering(),
+        vec![Target::Win, Target::Mac, Target::Chrome, Target::X11]
+    );
+
+    let mut mobile_only = layout;
+    mobile_only.modes.desktop = None;
+    mobile_only.modes.mac = None;
+    assert!(mobile_only.targets_without_reordering().is_empty());
+}
+
+#[test]
+fn flicks_are_checked_against_mobile_keys() {
+    let layout: kbdgen::models::Layout = serde_yaml::from_str(
+        r#"
+displayNames:
+  en: Test
+modes:
+  mobile:
+    default: |
+      a s d
+  ios:
+    default: |
+      a o d
+flicks:
+  a:
+    up: á
+    left: à
+  o:
+    right: ø
+  x:
+    down: y
+"#,
+    )
+    .unwrap();
+
+    let flicks = layout.flicks.as_ref().unwrap();
+    assert_eq!(flicks["a"].up.as_deref(), Some("á"));
+    assert_eq!(flicks["a"].down, None);
+    assert_eq!(layout.unknown_flick_keys(), vec!["x"]);
+}
+
+#[test]
+fn partial_loading_skips_broken_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let bundle_path = dir.path().join("test.kbdgen");
+    let layouts = bundle_path.join("layouts");
+    let targets = bundle_path.join("targets");
+    std::fs::create_dir_all(&layouts).unwrap();
+    std::fs::create_dir_all(&targets).unwrap();
+
+    let example = std::path::Path::new("examples/sme.kbdgen");
+    std::fs::copy(
+        example.join("project.yaml"),
+        bundle_path.join("project.yaml"),
+    )
+    .unwrap();
+    std::fs::copy(
+        example.join("layouts/se-SE.yaml"),
+        layouts.join("se-SE.yaml"),
+    )
+    .unwrap();
+    std::fs::write(layouts.join("broken.yaml"), "displayNames: [").unwrap();
+    std::fs::write(
+        layouts.join("typo.yaml"),
+        "displayNames:\n  en: Typo\nmodes:\n  win:\n    shfit: |\n      a\n",
+    )
+    .unwrap();
+    std::fs::write(targets.join("android.yaml"), "version: [").unwrap();
+
+    assert!(ProjectBundle::load(&bundle_path).is_err());
+
+    let partial = ProjectBundle::load_partial(&bundle_path, None).unwrap();
+    assert_eq!(
+        partial.bundle.layouts.keys().collect::<Vec<_>>(),
+        vec!["se-SE"]
+    );
+    assert!(partial.bundle.targets.android.is_none());
+
+    let mut errors = partial
+        .errors
+        .iter()
+        .map(|e| match e {
+            kbdgen::LoadError::ParseFile { path, .. }
+            | kbdgen::LoadError::InvalidModeName { path, .. } => {
+                path.file_name().unwrap().to_string_lossy().to_string()
+            }
+            e => panic!("unexpected error: {}", e),
+        })
+        .collect::<Vec<_>>();
+    errors.sort();
+    assert_eq!(errors, vec!["android.yaml", "broken.yaml", "typo.yaml"]);
+}
+
+#[test]
+fn dead_key_terminators_replace_the_space_transform() {
+    let layout: kbdgen::models::Layout = serde_yaml::from_str(
+        r#"
+displayNames:
+  en: Test
+modes:
+  mac:
+    default: |
+      ´ a s
+deadKeys:
+  mac:
+    default: ["´"]
+transforms:
+  ´:
+    ' ': ´
+    a: á
+deadKeyTerminators:
+  ´: "'"
+"#,
+    )
+    .unwrap();
+    let press = |key| KeyPress {
+        key,
+        mode: "default".parse().unwrap(),
+    };
+
+    assert_eq!(layout.dead_key_terminator("´"), "'");
+    assert!(layout.unknown_dead_key_terminators().is_empty());
+    let presses = [
+        press(IsoKey::E00),
+        press(IsoKey::E01),
+        press(IsoKey::E00),
+        press(IsoKey::E02),
+        press(IsoKey::E00),
+    ];
+    assert_eq!(layout.simulate(Target::Mac, &presses), "á's'");
+
+    let mut layout = layout;
+    layout.dead_key_terminators = Some(
+        vec![("¨".to_string(), "¨".to_string())]
+            .into_iter()
+            .collect(),
+    );
+    assert_eq!(layout.unknown_dead_key_terminators(), vec!["¨"]);
+}
+
+#[test]
+fn dead_key_chains_wait_for_the_next_key() {
+    let layout: kbdgen::models::Layout = serde_yaml::from_str(
+        r#"
+displayNames:
+  en: Test
+modes:
+  desktop:
+    default: |
+      ^ ´ a s
+deadKeys:
+  desktop:
+    default: ["^", "´"]
+transforms:
+  ^:
+    ' ': ^
+    a: â
+  ´:
+    ' ': ´
+    a: á
+  ^´:
+    ' ': ^´
+    a: ấ
+"#,
+    )
+    .unwrap();
+    let press = |key| KeyPress {
+        key,
+        mode: "default".parse().unwrap(),
+    };
+
+    assert_eq!(layout.dead_key_chain("^´"), Some(vec!["^", "´"]));
+    assert_eq!(layout.dead_key_chain("^"), None);
+    assert_eq!(layout.dead_key_chains(), vec!["^´"]);
+    assert_eq!(
+        layout.targets_without_dead_key_chains(),
+        vec![Target::Win, Target::Chrome, Target::X11]
+    );
+
+    let presses = [
+        press(IsoKey::E00),
+        press(IsoKey::E01),
+        press(IsoKey::E02),
+        press(IsoKey::E01),
+        press(IsoKey::E00),
+        press(IsoKey::E03),
+        press(IsoKey::E00),
+        press(IsoKey::E01),
+    ];
+    assert_eq!(layout.simulate(Target::Mac, &presses), "ấ´^s^´");
+}
+
+#[test]
+fn accessibility_labels_default_to_character_names() {
+    let layout: kbdgen::models::Layout = serde_yaml::from_str(
+        r#"
+displayNames:
+  en: Test
+modes:
+  mobile:
+    default: |
+      a ŋ á
+accessibilityLabels:
+  ŋ: eng
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(layout.accessibility_label("ŋ").as_deref(), Some("eng"));
+    assert_eq!(
+        layout.accessibility_label("a").as_deref(),
+        Some("latin small letter a")
+    );
+    assert_eq!(
+        layout.accessibility_label("a\u{301}").as_deref(),
+        Some("latin small letter a combining acute accent")
+    );
+    assert_eq!(layout.accessibility_label(""), None);
+}
+
+#[test]
+fn mode_errors_in_aliases_name_the_alias() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.yaml");
+    std::fs::write(
+        &path,
+        r#"displayNames:
+  en: Test
+modes:
+  desktop: &rows
+    default: |
+      1 2 3
+    shfit: |
+      ! " #
+  mac: *rows
+"#,
+    )
+    .unwrap();
+
+    match kbdgen::models::Layout::load(&path) {
+        Err(e @ kbdgen::LoadError::InvalidModeName { .. }) => {
+            assert!(e
+                .to_string()
+                .ends_with("expanded from alias `*rows` on line 9"));
+        }
+        x => panic!("unexpected {:?}", x),
+    }
+}
+
+#[test]
+fn layout_license_falls_back_to_the_project() {
+    let mut project: kbdgen::models::Project = serde_yaml::from_str(
+        r#"
+locales: {}
+author: Example Person
+email: person@place.example
+organisation: Example Corp
+copyright: Copyright © 2017 Example Corp
+license: CC-BY-4.0
+attributions:
+  - Based on the keyboards by Example Corp
+"#,
+    )
+    .unwrap();
+    let mut layout = kbdgen::models::Layout {
+        attributions: Some(vec![
+            "Dead keys by Someone Else".into(),
+            "Based on the keyboards by Example Corp".into(),
+        ]),
+        ..Default::default()
+    };
+
+    assert_eq!(layout.license(&project), Some("CC-BY-4.0"));
+    assert_eq!(
+        layout.license_notice(&project),
+        vec![
+            "License: CC-BY-4.0",
+            "Based on the keyboards by Example Corp",
+            "Dead keys by Someone Else",
+        ]
+    );
+
+    layout.license = Some("MIT".into());
+    layout.attributions = None;
+    project.attributions = None;
+    assert_eq!(layout.license_notice(&project), vec!["License: MIT"]);
+}
+
+#[test]
+fn spacebar_label_follows_target_options_and_features() {
+    use kbdgen::models::{Layout, LayoutStrings, Spacebar, SpacebarLabel};
+    use kbdgen::Feature;
+
+    let mut layout = Layout {
+        display_names: std::iter::once(("se".to_string(), "Davvisámegiella".to_string())).collect(),
+        strings: Some(LayoutStrings {
+            space: "gasska".into(),
+            return_: "linnjamålssom".into(),
+        }),
+        ..Default::default()
+    };
+    assert_eq!(layout.spacebar_label("se", None), Some("gasska".into()));
+
+    layout.features = Some(vec![Feature::SpacebarLanguageLabel]);
+    assert_eq!(
+        layout.spacebar_label("se", None),
+        Some("Davvisámegiella".into())
+    );
+
+    let spacebar = Spacebar {
+        label: Some(SpacebarLabel::None),
+        ..Default::default()
+    };
+    assert_eq!(layout.spacebar_label("se", Some(&spacebar)), None);
+
+    let spacebar: Spacebar = serde_yaml::from_str("label: space").unwrap();
+    assert_eq!(
+        layout.spacebar_label("se", Some(&spacebar)),
+        Some("gasska".into())
+    );
+}
+
+#[test]
+fn app_stores_need_support_and_privacy_policy_urls() {
+    let mut project: kbdgen::models::Project = serde_yaml::from_str(
+        r#"
+locales: {}
+author: Example Person
+email: person@place.example
+organisation: Example Corp
+copyright: Copyright © 2017 Example Corp
+homepage: https://example.com/keyboards
+supportUrl: https://example.com/keyboards/support
+"#,
+    )
+    .unwrap();
+    assert_eq!(
+        project.homepage.as_deref(),
+        Some("https://example.com/keyboards")
+    );
+    assert_eq!(project.missing_store_urls(), vec!["privacyPolicyUrl"]);
+
+    project.privacy_policy_url = Some("https://example.com/privacy".into());
+    assert!(project.missing_store_urls().is_empty());
+}
+
+#[test]
+fn layout_errors_are_reported_in_order_of_path() {
+    let dir = tempfile::tempdir().unwrap();
+    let bundle_path = dir.path().join("test.kbdgen");
+    let layouts = bundle_path.join("layouts");
+    std::fs::create_dir_all(&layouts).unwrap();
+    std::fs::copy(
+        "examples/sme.kbdgen/project.yaml",
+        bundle_path.join("project.yaml"),
+    )
+    .unwrap();
+    for name in &["d", "b", "a", "c"] {
+        std::fs::write(layouts.join(format!("{}.yaml", name)), "modes: [").unwrap();
+    }
+
+    let partial = ProjectBundle::load_partial(&bundle_path, None).unwrap();
+    let errors = partial
+        .errors
+        .iter()
+        .map(|e| match e {
+            kbdgen::LoadError::ParseFile { path, .. } => {
+                path.file_name().unwrap().to_string_lossy().to_string()
+            }
+            e => panic!("unexpected error: {}", e),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(errors, vec!["a.yaml", "b.yaml", "c.yaml", "d.yaml"]);
+}
+
+#[test]
+fn transform_patterns_are_expanded_when_loading() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.yaml");
+    std::fs::write(
+        &path,
+        r#"displayNames:
+  en: Test
+modes:
+  desktop:
+    default: |
+      a e x
+deadKeys:
+  desktop:
+    default: [x]
+transforms:
+  x:
+    "[ae]": "$0́"
+    e: ê
+    " ": x
+"#,
+    )
+    .unwrap();
+
+    let layout = kbdgen::models::Layout::load(&path).unwrap();
+    let transforms = &layout.transforms.as_ref().unwrap()["x"];
+    assert_eq!(transforms.get("a").map(String::as_str), Some("á"));
+    assert_eq!(transforms.get("e").map(String::as_str), Some("ê"));
+    assert!(!transforms.contains_key("[ae]"));
+
+    let text = std::fs::read_to_string(&path).unwrap();
+    std::fs::write(&path, text.replace("[ae]", "[\\\\p{Nope}]")).unwrap();
+    match kbdgen::models::Layout::load(&path) {
+        Err(kbdgen::LoadError::InvalidTransformPattern { .. }) => {}
+        x => panic!("unexpected {:?}", x),
+    }
+}